@@ -7,7 +7,7 @@
 // #[derive(Parser)]: this is the main macro from clap. It instructs clap to generate all the command-line parsing logic based on the fields of the struct.
 // Doc Comments (///) : the triple slash comments are special. clap uses them to automatically generate the help messages for your application. What you write here is what the user will see when they run rdd --help.
 // #[]command(...)] : this attribute provides top-level information about your application, like the author and a longer description.
-// Subcommands : The Cli and Command enums create a subcommand structure (e.g., rdd copy...). This is a modern CLI pattern that makes the tool extensible. For now, we only have the copy subcommand.
+// Subcommands : The Cli and Command enums create a subcommand structure (e.g., rdd copy...). This is a modern CLI pattern that makes the tool extensible. We have the copy subcommand, verify to compare an existing copy with its source, wipe to overwrite a device, scrub to re-check archived images, list to show the attached drives, benchmark to measure a device's throughput at different block sizes, extract to read part of a seekable archive, wizard to set up a copy step by step, and top to watch every running copy.
 // #[arg(...)] : This attribute configures each command-line argument.
     // long : Defines the long name (e.g., --input)
     // short : Defines the optional short name(e.g., -i)
@@ -19,10 +19,11 @@
 
 // src/cli.rs
 
+use crate::utils::passphrase::PassphraseArgs;
 use clap::{Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
 
 /// rdd: A  modern, safe, and fast replacement for GNU dd.
-
 #[derive(Parser, Debug)]
 #[command(
     author,
@@ -30,67 +31,661 @@ use clap::{Parser, Subcommand, ValueEnum};
     about,
     long_about = "rdd is a utility for copying and converting data. It replicates the core functionality of dd while adding modern features like rich progress bars, multithreading, and on-the-fly hash verification."
     )]
-
 pub struct Cli{
     #[command(subcommand) ]
     pub command: Command,
+
+    /// Disable colored error and warning messages (also honours the NO_COLOR environment variable).
+    #[arg(long, global = true)]
+    pub no_color: bool,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// The core disk/file copy operation, mirroring dd's functionality.
-    Copy(CopyArgs),
-    // Future subcommands like 'verify' or 'partition' would be added here.
+    Copy(Box<CopyArgs>),
+    /// Compare two files or devices by hashing both, without copying anything.
+    Verify(VerifyArgs),
+    /// Overwrite a file or device with zeros, ones or random data, in one or more passes.
+    Wipe(WipeArgs),
+    /// Check archived images against the digests in their manifests, to find the ones that have decayed or gone missing.
+    Scrub(ScrubArgs),
+    /// Show the attached drives with their size, model and whether they're removable or hold the running system, to pick the right one to copy to.
+    List(ListArgs),
+    /// Measure how fast a file or device reads and writes sequentially at a range of block sizes, and recommend the --bs to copy with. The write test overwrites the start of the target.
+    Benchmark(BenchmarkArgs),
+    /// Write part or all of the image in a seekable zstd (--compress zstd --seekable) or qcow2 archive to a file or drive, reading only the frames or clusters it needs: a byte range, one partition, or the whole image to restore it.
+    Extract(ExtractArgs),
+    /// Set up a copy step by step: pick the source, the destination drive, verification and compression, review the equivalent 'rdd copy' command line, and run it.
+    Wizard,
+    /// Show every copy running on this machine in a full-screen dashboard: progress, throughput, drive temperatures and latest messages.
+    Top(TopArgs),
+    // Future subcommands like 'partition' would be added here.
+}
+
+/// Arguments for the 'verify' command
+#[derive(Parser, Debug)]
+pub struct VerifyArgs {
+    /// The original file or device (e.g., image.iso).
+    #[arg(value_name = "SOURCE")]
+    pub source: String,
+
+    /// The copy to check against it (e.g., /dev/sdb).
+    #[arg(value_name = "TARGET")]
+    pub target: String,
+
+    /// Size of each read. Supports suffixes: k, M, G (e.g., 4k, 128M, 2G). Defaults to 512k.
+    #[arg(long, value_name = "SIZE")]
+    pub bs: Option<String>,
+
+    /// Hashing algorithm used for both files.
+    #[arg(long, value_enum, default_value_t = HashAlgorithm::Sha256)]
+    pub algorithm: HashAlgorithm,
+
+    /// Only compare the first SIZE bytes of each. Useful when the target is a device larger than the source image.
+    #[arg(long, value_name = "SIZE")]
+    pub bytes: Option<String>,
+
+    /// Don't show a progress bar.
+    #[arg(long)]
+    pub no_progress: bool,
+}
+
+/// Arguments for the 'wipe' command
+#[derive(Parser, Debug)]
+pub struct WipeArgs {
+    /// The file or device to overwrite (e.g., /dev/sdb). Everything on it is destroyed.
+    #[arg(value_name = "TARGET")]
+    pub target: String,
+
+    /// What each pass writes, in order (e.g., zeros,random,zeros).
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "zeros")]
+    pub passes: Vec<WipePattern>,
+
+    /// Size of each write. Supports suffixes: k, M, G (e.g., 4k, 128M, 2G). Defaults to 512k.
+    #[arg(long, value_name = "SIZE")]
+    pub bs: Option<String>,
+
+    /// Only overwrite the first SIZE bytes.
+    #[arg(long, value_name = "SIZE")]
+    pub bytes: Option<String>,
+
+    /// Read the target back after the last pass and check that it holds what the pass wrote.
+    #[arg(long)]
+    pub verify: bool,
+
+    /// Don't show a progress bar.
+    #[arg(long)]
+    pub no_progress: bool,
+
+    /// Wipe the target even if it's a drive that holds the running system or has mounted filesystems.
+    #[arg(long)]
+    pub force: bool,
+
+    /// Don't ask for confirmation before wiping a drive.
+    #[arg(long)]
+    pub yes: bool,
+
+    /// Warn when a removable drive has had more than LIMIT written to it by rdd (e.g. 10T, 500x or off). Defaults to 1000x.
+    #[arg(long, value_name = "LIMIT")]
+    pub wear_limit: Option<String>,
+
+    /// The file where rdd records what it writes to each removable drive.
+    #[arg(long, value_name = "FILE")]
+    pub wear_history: Option<String>,
+}
+
+/// Arguments for the 'scrub' command
+#[derive(Parser, Debug)]
+pub struct ScrubArgs {
+    /// Directories to search for manifests written by --manifest-out (searched recursively), or manifest files.
+    #[arg(value_name = "PATH", required = true)]
+    pub paths: Vec<String>,
+
+    /// Read at most SIZE bytes per second (e.g., 50M), so a scrub can run alongside other work on the archive. Same suffixes as --bs.
+    #[arg(long, value_name = "SIZE")]
+    pub rate_limit: Option<String>,
+
+    /// Size of each read. Supports suffixes: k, M, G (e.g., 4k, 128M, 2G). Defaults to 512k.
+    #[arg(long, value_name = "SIZE")]
+    pub bs: Option<String>,
+
+    /// How the report is printed: a line per image, or a single JSON object for scripts.
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = StatsFormat::Text)]
+    pub report_format: StatsFormat,
+
+    /// Don't show a progress bar.
+    #[arg(long)]
+    pub no_progress: bool,
+}
+
+/// Arguments for the 'list' command
+#[derive(Parser, Debug)]
+pub struct ListArgs {
+    /// Only show removable drives (USB sticks, SD cards, external disks).
+    #[arg(long)]
+    pub removable: bool,
+
+    /// How the list is printed: a table, or a JSON array for scripts.
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = StatsFormat::Text)]
+    pub report_format: StatsFormat,
+}
+
+/// Arguments for the 'benchmark' command
+#[derive(Parser, Debug)]
+pub struct BenchmarkArgs {
+    /// The file or device to measure (e.g., /dev/sdb). The write test overwrites its first --bytes; a file that doesn't exist is created and removed again.
+    #[arg(value_name = "TARGET")]
+    pub target: String,
+
+    /// The block sizes to try, in order. Same suffixes as --bs. Defaults to 4k,16k,64k,256k,1M,4M,16M.
+    #[arg(long, value_name = "SIZES", value_delimiter = ',')]
+    pub sizes: Vec<String>,
+
+    /// How much to read and write at each block size (e.g., 256M). More gives steadier numbers; it's capped at the size of a device. Defaults to 64M.
+    #[arg(long, value_name = "SIZE")]
+    pub bytes: Option<String>,
+
+    /// Only measure reads, leaving the target as it is.
+    #[arg(long)]
+    pub read_only: bool,
+
+    /// How the results are printed: a table, or a JSON object for scripts.
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = StatsFormat::Text)]
+    pub report_format: StatsFormat,
+
+    /// Don't show a progress bar.
+    #[arg(long)]
+    pub no_progress: bool,
+
+    /// Write to the target even if it's a drive that holds the running system or has mounted filesystems.
+    #[arg(long)]
+    pub force: bool,
+
+    /// Don't ask for confirmation before writing to a drive.
+    #[arg(long)]
+    pub yes: bool,
+
+    /// [Unix-only] Bypass the page cache with O_DIRECT, to measure the device rather than the cache. The block sizes must be multiples of its sector size.
+    #[cfg(unix)]
+    #[arg(long)]
+    pub direct: bool,
+}
+
+/// Arguments for the 'extract' command
+#[derive(Parser, Debug)]
+pub struct ExtractArgs {
+    /// The archive to read: seekable zstd or qcow2.
+    #[arg(value_name = "ARCHIVE")]
+    pub archive: String,
+
+    /// The file or device to write to, or '-' for stdout.
+    #[arg(short, long, value_name = "FILE")]
+    pub output: String,
+
+    /// Only write LENGTH bytes of the image starting at OFFSET, e.g. 1G:512M. Same suffixes as --bs.
+    #[arg(long, value_name = "OFFSET:LENGTH", conflicts_with = "partition")]
+    pub range: Option<String>,
+
+    /// Only write partition N of the image's MBR or GPT, numbered from 1 like /dev/sda1.
+    #[arg(long, value_name = "N")]
+    pub partition: Option<usize>,
+
+    /// Don't show a progress bar.
+    #[arg(long)]
+    pub no_progress: bool,
+
+    /// Write to the output even if it's a drive that holds the running system or has mounted filesystems.
+    #[arg(long)]
+    pub force: bool,
+
+    /// Don't ask for confirmation before writing to a drive.
+    #[arg(long)]
+    pub yes: bool,
+}
+
+/// Arguments for the 'top' command
+#[derive(Parser, Debug)]
+pub struct TopArgs {
+    /// Print the running copies once and exit, instead of drawing them until Ctrl-C.
+    #[arg(long)]
+    pub once: bool,
 }
 
 /// Arguments for the 'copy' command
 #[derive(Parser, Debug)]
 pub struct CopyArgs {
-    /// Input file or device (e.g., /dev/sda, image.iso).
-    #[arg(long, short, value_name = "FILE", required = true)]
-    pub input: String,
+    /// Input file or device (e.g., /dev/sda, image.iso), '-' for stdin, tcp://HOST:PORT to receive it over the network (tcp://:PORT waits for the sender to connect), ssh://[USER@]HOST/PATH to read it on another machine with ssh, or an http:// or https:// URL to download it (with curl), resuming if the download breaks off.
+    #[arg(long, short, value_name = "FILE", required_unless_present = "from_manifest")]
+    pub input: Option<String>,
 
-    /// Output file or device.
-    #[arg(long, short, value_name = "FILE", required = true)]
-    pub output: String,
+    /// Output file or device, '-' for stdout, tcp://HOST:PORT to send it over the network (tcp://:PORT waits for the receiver to connect), or ssh://[USER@]HOST/PATH to write it on another machine with ssh.
+    #[arg(long, short, value_name = "FILE", required_unless_present = "from_manifest")]
+    pub output: Option<String>,
+
+    /// Block size in bytes. Supports suffixes: k, M, G (e.g., 4k, 128M, 2G). Defaults to 512k, or to the block size of the chosen --target-profile. 'auto' starts there and tries bigger and smaller block sizes during the first seconds of the copy, then keeps the fastest (single-threaded copy only; give --count, --skip and --seek in bytes then).
+    #[arg(long, short = 'b', value_name = "SIZE")]
+    pub bs: Option<String>,
+
+    /// [Enhancement] Largest single read or write issued to the OS. Blocks bigger than this are transferred in several chunks, so any --bs works without a matching allocation. Same suffixes as --bs. Defaults to 64M.
+    #[arg(long, value_name = "SIZE")]
+    pub max_io: Option<String>,
+
+    /// [Enhancement] Gather consecutive writes into writes of up to SIZE bytes (e.g. 1M), for devices that are slow with many small writes, while --bs keeps its meaning for count, skip and seek. Single-threaded copy only. Same suffixes as --bs.
+    #[arg(long, value_name = "SIZE")]
+    pub coalesce: Option<String>,
 
-    /// Block size in bytes. Supports suffixes: k, M, G (e.g., 4k, 128M, 2G).
-    #[arg(long, short = 'b', value_name = "SIZE", default_value = "512k")]
-    pub bs: String,
+    /// [Enhancement] Copy at most SIZE bytes per second (e.g., 50M), to leave bandwidth for others on a shared disk or network. Same suffixes as --bs.
+    #[arg(long, value_name = "SIZE")]
+    pub rate_limit: Option<String>,
 
-    /// Number of blocks to copy (if 0, copies until end of input).
+    /// Number of blocks to copy (if 0, copies until end of input), or of lines with --records.
     #[arg(long, short, value_name = "N", default_value_t = 0)]
     pub count: u64,
 
-    /// Skip N blocks of 'bs size at the start of the input.
+    /// Skip N blocks of 'bs size at the start of the input, or N lines with --records.
     #[arg(long, default_value_t = 0)]
     pub skip: u64,
     
-    /// Seek N blocks of 'bs' size at the start of the output.
+    /// Seek N blocks of 'bs' size at the start of the output. Like dd, the rest of the output is kept (see --no-truncate).
     #[arg(long, default_value_t=0)]
     pub seek: u64,
 
-    /// [Enhancement] Hashing algorithm to verify data integrity during the copy.
+    /// [Enhancement] Copy only SIZE bytes (e.g., 446, 1M or 0x1BE). Takes precedence over --count.
+    #[arg(long, value_name = "SIZE")]
+    pub count_bytes: Option<String>,
+
+    /// [Enhancement] Start reading SIZE bytes into the input (e.g., 0x1BE). Takes precedence over --skip.
+    #[arg(long, value_name = "SIZE")]
+    pub skip_bytes: Option<String>,
+
+    /// [Enhancement] Start writing SIZE bytes into the output (e.g., 0x1BE). Takes precedence over --seek, and keeps the rest of the output like it.
+    #[arg(long, value_name = "SIZE")]
+    pub seek_bytes: Option<String>,
+
+    /// [Enhancement] Count --skip and --count in records (lines ending in a newline) of the input instead of blocks, to cut a slice out of a log or CSV file, e.g. --records --skip 1000 --count 50. The input is read as a stream then, like stdin.
+    #[arg(long, conflicts_with_all = ["skip_bytes", "count_bytes"])]
+    pub records: bool,
+
+    /// [Enhancement] Don't truncate the output when it's opened, like dd's conv=notrunc: what the copy doesn't overwrite stays. Implied by --seek and --seek-bytes.
+    #[arg(long)]
+    pub no_truncate: bool,
+
+    /// [Enhancement] Add the data at the end of the output instead of overwriting it from the start, like dd's oflag=append.
+    #[arg(long)]
+    pub append: bool,
+
+    /// [Enhancement] Copy only the byte ranges listed in FILE, to the same offsets in the output. Accepts "offset length" lines or a GNU ddrescue map file (every block not marked '+' is copied). The output is not truncated.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["skip", "seek", "count"])]
+    pub ranges_file: Option<String>,
+
+    /// [Enhancement] Copy everything except the byte ranges listed in FILE (same formats as --ranges-file). Excluded ranges are never read, and are zeroed or left as holes in the output.
+    #[arg(long, value_name = "FILE", conflicts_with = "ranges_file")]
+    pub exclude_ranges_file: Option<String>,
+
+    /// [Enhancement] Copy these regions of the input before the rest, for a failing drive that may not last the whole copy: "auto" finds the partition table and the metadata of the filesystems on it, or FILE lists them (same formats as --ranges-file). The whole input is still copied, to the same offsets.
+    #[arg(long, value_name = "auto|FILE", conflicts_with_all = ["ranges_file", "exclude_ranges_file", "skip", "seek", "count"])]
+    pub priority_ranges: Option<String>,
+
+    /// [Enhancement] Hash the data while copying, then read the output back and check that it hashes the same. Give it more than once (--verify sha256 --verify blake3) to hash with several algorithms in the same pass.
     #[arg(long, value_enum)]
-    pub verify: Option<HashAlgorithm>,
+    pub verify: Vec<HashAlgorithm>,
 
     /// Show a rich progress bar and live statistics (enabled by default).
     #[arg(long, default_value_t=true, action = clap::ArgAction::SetTrue)]
     pub progress: bool,
 
-    /// [Enhancement] Number of threads for I/O (1=single=threaded, >1 = multithreaded).
+    /// [Enhancement] Show a full-screen dashboard instead of the progress bar: progress, a throughput graph, drive temperatures (with --monitor-health) and the latest messages. 'rdd top' shows every running copy the same way.
+    #[arg(long)]
+    pub tui: bool,
+
+    /// [Enhancement] Don't write chunks that are all zeros; seek over them instead, leaving holes that take no disk space (like dd's conv=sparse). Only applies when the output is a regular file; devices still get the zeros written.
+    #[arg(long)]
+    pub sparse: bool,
+
+    /// [Enhancement] Deallocate the regions of the output where the data is all zeros instead of writing them, e.g. when restoring an image to an SSD or a thin-provisioned volume. Only where the output guarantees they read back as zeros; elsewhere the zeros are written. Linux only.
+    #[arg(long, conflicts_with = "sparse")]
+    pub discard_zeros: bool,
+
+    /// Convert the data between reading and writing it, like dd's conv=: swab (swap the bytes of every pair), ucase or lcase (the case of ASCII letters), ascii (from EBCDIC) or ebcdic (from ASCII). Separate several with commas, e.g. --conv ascii,lcase.
+    #[arg(long, value_enum, value_name = "CONVERSIONS", value_delimiter = ',')]
+    pub conv: Vec<Conversion>,
+
+    /// Keep going after read errors, like dd's conv=noerror,sync: the unreadable chunk is logged, filled with --error-fill bytes in the output, and skipped in the input. The bad regions are listed at the end.
+    #[arg(long)]
+    pub noerror: bool,
+
+    /// [Enhancement] The byte that stands in for unreadable data with --noerror, in decimal or hex (e.g., 0xFF). Defaults to 0.
+    #[arg(long, value_name = "BYTE", requires = "noerror")]
+    pub error_fill: Option<String>,
+
+    /// [Enhancement] Give up once more than N reads have failed with --noerror: a drive that keeps producing errors is deteriorating fast and needs a different strategy. The map of what was read is saved first (see --error-map), so a later run can go back for the rest with --ranges-file.
+    #[arg(long, value_name = "N", requires = "noerror")]
+    pub max_errors: Option<u64>,
+
+    /// [Enhancement] Where --max-errors saves the map of the copy when it gives up, in GNU ddrescue's format. Defaults to the output's file name plus ".map", in the current directory.
+    #[arg(long, value_name = "FILE", requires = "max_errors")]
+    pub error_map: Option<String>,
+
+    /// [Enhancement] Read each output block before writing it and skip the write when it already matches the input. Saves erase cycles when re-flashing nearly-identical images. The output is not truncated in this mode.
+    #[arg(long)]
+    pub write_if_changed: bool,
+
+    /// [Enhancement] Check the input's size, modification time and inode before and after the copy, and warn or fail if it changed while being read.
+    #[arg(long, value_enum, value_name = "POLICY")]
+    pub check_input_stable: Option<InputGuard>,
+
+    /// [Enhancement] Flush the output every SIZE bytes (e.g., 256M) and compare the target's stable write rate to the rate writes are acknowledged at. Warns when a device caches writes and the progress would be misleading.
+    #[arg(long, value_name = "SIZE")]
+    pub flush_probe: Option<String>,
+
+    /// [Enhancement] Follow an input file that is still being written, like `tail -f`: at its end, wait for it to grow and copy what's added, until --until-size, --until-idle or Ctrl-C, which ends the copy normally.
+    #[arg(long)]
+    pub follow: bool,
+
+    /// [Enhancement] With --follow, stop once the input has reached SIZE bytes (e.g., 4G) and all of it is copied.
+    #[arg(long, value_name = "SIZE", requires = "follow")]
+    pub until_size: Option<String>,
+
+    /// [Enhancement] With --follow, stop once the input hasn't grown for SECS seconds.
+    #[arg(long, value_name = "SECS", requires = "follow", value_parser = clap::value_parser!(u64).range(1..))]
+    pub until_idle: Option<u64>,
+
+    /// [Enhancement] After the copy, check that the output looks bootable (MBR signature, EFI system partition, ISO hybrid markers) and report a verdict.
+    #[arg(long)]
+    pub check_bootable: bool,
+
+    /// [Enhancement] After the copy, read the FAT and exFAT filesystems back from the output and check their structures: FAT copies, FSINFO, boot region checksums, and every file's cluster chain. Catches SD cards that reorder or drop writes, which a byte comparison through the cache misses. Fails the copy on problems the input doesn't have.
+    #[arg(long)]
+    pub check_fat: bool,
+
+    /// [Enhancement] Turn off the target drive's volatile write cache during the copy and restore it afterwards, so acknowledged writes are really on the medium. Linux only; needs root and a SATA/SCSI or NVMe output device.
+    #[arg(long)]
+    pub disable_write_cache: bool,
+
+    /// [Enhancement] Record progress in a state file (OUTPUT.rdd-state, or FILE) while copying. If the copy is interrupted, run the same command again to continue where it stopped.
+    #[arg(long, value_name = "FILE", num_args = 0..=1, require_equals = true)]
+    pub resume: Option<Option<String>>,
+
+    /// [Enhancement] Show the temperature and I/O error count of the input and output drives in the progress bar, and warn when a drive reports new errors. Linux only; reads the kernel's hwmon sensors and error counters.
+    #[arg(long)]
+    pub monitor_health: bool,
+
+    /// [Enhancement] When to sync the output: none (never; fastest, but a crash can lose everything still cached), end (once after the copy, the default, like conv=fsync), interval:SIZE (also every SIZE bytes, e.g. interval:256M, so a crash or a pulled drive loses at most that much) or per-block (after every block, like oflag=dsync; slow).
+    #[arg(long, value_name = "MODE")]
+    pub sync_mode: Option<String>,
+
+    /// [Enhancement] Skip syncing the output's directory after the final sync. That sync makes sure a newly created output file survives a power loss, but it can be slow on some network filesystems.
+    #[arg(long)]
+    pub no_sync_dir: bool,
+
+    /// [Enhancement] Write to the output even if it's a drive that holds the running system or has mounted filesystems. rdd refuses to otherwise.
+    #[arg(long)]
+    pub force: bool,
+
+    /// [Enhancement] Don't ask for confirmation before overwriting a drive. rdd only asks when it runs in a terminal.
+    #[arg(long)]
+    pub yes: bool,
+
+    /// [Enhancement] Warn when a removable output drive has had more than LIMIT written to it by rdd: a size (e.g. 10T), a number of full writes of the drive (e.g. 500x), or 'off'. Defaults to 1000x.
+    #[arg(long, value_name = "LIMIT")]
+    pub wear_limit: Option<String>,
+
+    /// [Enhancement] The file where rdd records what it writes to each removable drive. Defaults to write-history in rdd's state directory (e.g. ~/.local/state/rdd).
+    #[arg(long, value_name = "FILE")]
+    pub wear_history: Option<String>,
+
+    /// [Enhancement] Pause the copy while a drive is hotter than CELSIUS degrees, and resume once it has cooled down by 5 degrees. Implies --monitor-health.
+    #[arg(long, value_name = "CELSIUS", value_parser = clap::value_parser!(u8).range(1..))]
+    pub max_temp: Option<u8>,
+
+    /// [Enhancement] When running on battery, wait for the charger before writing, and pause whenever it's unplugged during the copy. Without it, rdd only warns. Linux only.
+    #[arg(long)]
+    pub pause_on_battery: bool,
+
+    /// [Enhancement] Try a read that failed with an I/O error again, up to N times, before failing the copy (or, with --noerror, filling it in). Helps with flaky USB readers and cables. The retries of each offset are listed in the summary.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub retries: u32,
+
+    /// [Enhancement] Wait MS milliseconds before the first retry; the wait doubles before each further one, up to 30 seconds. Defaults to 100.
+    #[arg(long, value_name = "MS", requires = "retries", value_parser = clap::value_parser!(u64).range(1..))]
+    pub retry_delay: Option<u64>,
+
+    /// [Enhancement] Retry failed writes the same way as reads. Can't be used when the output is a stream (stdout, --compress, --split-size).
+    #[arg(long, requires = "retries")]
+    pub retry_writes: bool,
+
+    /// [Enhancement] Give up with an error if opening the input or output takes longer than SECS seconds, e.g. on a failing device that hangs.
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub open_timeout: Option<u64>,
+
+    /// [Enhancement] With a tcp:// input or output, send the data in length-prefixed frames with an end marker, so the receiver can tell a finished transfer from a dropped connection. Both ends must use it; leave it off to talk to netcat.
+    #[arg(long)]
+    pub tcp_framing: bool,
+
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+
+    /// [Enhancement] Number of threads for I/O (1=single=threaded, >1 = multithreaded). With N threads, one thread reads and N-1 threads write, so reads and writes overlap.
     #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
     pub threads: u8,
 
+    /// [Enhancement] How the data is moved. io-uring (Linux only) keeps a queue of reads and writes in flight, which helps fast drives like NVMe SSDs; it copies files and devices front to back, and falls back to the standard engine if the kernel doesn't offer io_uring.
+    #[arg(long, value_enum, value_name = "ENGINE", default_value_t = Engine::Standard)]
+    pub engine: Engine,
+
+    /// [Enhancement] The most data the multithreaded and io_uring engines read ahead of the output, e.g. 64M. When the output is slower than the input, the reader waits for the writers and then reads at their pace instead of filling memory with data they can't take yet. Same suffixes as --bs. Defaults to 256M.
+    #[arg(long, value_name = "SIZE")]
+    pub max_in_flight: Option<String>,
+
+    /// [Enhancement] Run in a small, fixed amount of memory, for 32-bit boards and initramfs environments: reads and writes are capped at 64k (like --max-io 64k), the multithreaded copy keeps one buffer per writer, the compressors run single-threaded, and --block-hashes and --compare-manifest (which keep a hash per block in memory) and more than one --verify algorithm are refused.
+    #[arg(long)]
+    pub low_memory: bool,
+
+    /// [Enhancement] Always copy through rdd's own read/write loop. Without it, a copy between two regular files is left to the kernel (copy_file_range, or a reflink on btrfs and XFS) when no option needs to see the data, falling back to the loop where the kernel can't.
+    #[arg(long)]
+    pub no_fast_path: bool,
+
+    /// [Enhancement] Tune block size and caching hints for a class of target device in one go. An explicit --bs always wins over the profile.
+    #[arg(long, value_enum, value_name = "PROFILE")]
+    pub target_profile: Option<TargetProfile>,
+
+    /// [Enhancement] How the results are reported once the copy is done: as text, or as a single JSON object for scripts (bytes read and written, full and partial blocks, elapsed time, throughput, digests, bad blocks). Defaults to text, or to json with --stats-file.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub stats_format: Option<StatsFormat>,
+
+    /// [Enhancement] Write the JSON results to FILE instead of stdout.
+    #[arg(long, value_name = "FILE")]
+    pub stats_file: Option<String>,
+
+    /// [Enhancement] Write a line to the CSV file FILE every second with the time, the bytes read and written so far, and the read and write rates, to graph how the devices behaved over a long copy (e.g. a drive slowing down as it heats up, or once its write cache is full).
+    #[arg(long, value_name = "FILE")]
+    pub bandwidth_log: Option<String>,
+
+    /// [Enhancement] Append time-stamped start and finish entries for this copy to FILE, a hash-chained log for chain-of-custody records: each entry includes the hash of the one before it, so later edits are evident. The data's SHA-256 (or --verify digest) is logged too.
+    #[arg(long, value_name = "FILE")]
+    pub custody_log: Option<String>,
+
+    /// [Enhancement] When the copy is done, write a reproducibility manifest to FILE: every effective option, the rdd version and platform, the copy engine, and a digest of the data read. Hashes the data with SHA-256 (or the --verify algorithm) as it goes.
+    #[arg(long, value_name = "FILE")]
+    pub manifest_out: Option<String>,
+
+    /// [Enhancement] When the copy is done, write the digest of the data to FILE as a `<digest>  <output>` line, which `sha256sum -c` or `b3sum -c` can check later. Uses the --verify algorithms, or SHA-256; with several, each goes to its own file, FILE.sha256, FILE.blake3. The check only passes while the output holds exactly the data copied (a file, not a larger device).
+    #[arg(long, value_name = "FILE")]
+    pub hash_output: Option<String>,
+
+    /// [Enhancement] Record the hash of every block read in FILE, so a later read of the same input can be checked block by block with --compare-manifest. Uses SHA-256, or the --verify algorithm.
+    #[arg(long, value_name = "FILE")]
+    pub block_hashes: Option<String>,
+
+    /// [Enhancement] Hash the data in windows of SIZE bytes (e.g. 1M) as it's read, like dcfldd's hashwindow, and write the offset, length and hash of every window to the --hash-log FILE. Uses SHA-256, or the --verify algorithm. Same suffixes as --bs.
+    #[arg(long, value_name = "SIZE", requires = "hash_log")]
+    pub hash_window: Option<String>,
+
+    /// [Enhancement] Where --hash-window writes the hash of every window. It's in the format of --block-hashes, so a later --compare-manifest run can check the input against it.
+    #[arg(long, value_name = "FILE", requires = "hash_window")]
+    pub hash_log: Option<String>,
+
+    /// [Enhancement] Check every block read against the hashes recorded by an earlier --block-hashes run, list the blocks that differ (e.g. bit rot on a drive kept in storage), and fail if any do. Uses the block size recorded in FILE unless --bs is given.
+    #[arg(long, value_name = "FILE")]
+    pub compare_manifest: Option<String>,
+
+    /// [Enhancement] Read the input as if it came from the device described in MODEL, to try out rescue and tuning options without failing hardware: every read takes the model's latency and bandwidth, and reads of its bad or flaky regions fail. See core/simulate.rs for the format, e.g. "latency 2ms", "bandwidth 40M", "bad 0x100000 4096", "flaky 0x200000 512 2", "time virtual".
+    #[arg(long, value_name = "MODEL")]
+    pub simulate_device: Option<String>,
+
+    /// [Enhancement] Compress the output as it's written (e.g., zstd for a disk image). Runs the gzip, zstd or xz program, which must be installed. The output is a stream then, like stdout, so options that read it back or seek in it can't be used.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub compress: Option<Compression>,
+
+    /// [Enhancement] Compression level for --compress: 1-9 for gzip, 1-19 for zstd, 0-9 for xz. Defaults to the program's own default.
+    #[arg(long, value_name = "LEVEL", requires = "compress")]
+    pub compress_level: Option<u32>,
+
+    /// [Enhancement] With --compress zstd, write the zstd seekable format: the data is compressed in independent frames of FRAME_SIZE (4M unless given, as --seekable=16M), followed by a table of where each frame starts, so part of the image can later be decompressed without the rest. Any zstd still decompresses the whole file.
+    #[arg(long, value_name = "FRAME_SIZE", num_args = 0..=1, require_equals = true, requires = "compress")]
+    pub seekable: Option<Option<String>>,
+
+    /// [Enhancement] Decompress the input before copying it. A regular file or a download that starts with a gzip, zstd or xz header is decompressed without this; use it for devices and stdin.
+    #[arg(long, conflicts_with = "no_decompress")]
+    pub decompress: bool,
+
+    /// [Enhancement] Copy a compressed input file as it is, instead of decompressing it.
+    #[arg(long)]
+    pub no_decompress: bool,
+
+    /// [Enhancement] Write the output in chunks of at most SIZE (e.g., 4000M for FAT32, or an upload limit): OUTPUT.000, OUTPUT.001, and so on. An input named like the first chunk (image.img.000) is read as the whole set when a second chunk (image.img.001) is next to it. The output is a stream then, like stdout.
+    #[arg(long, value_name = "SIZE")]
+    pub split_size: Option<String>,
+
+    /// [Enhancement] Re-run the copy recorded in a manifest written by --manifest-out, with exactly the same settings, and fail if the data read no longer has the recorded digest. Can't be combined with options that change the copy.
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = [
+            "input", "output", "bs", "max_io", "coalesce", "rate_limit", "count", "skip", "seek", "count_bytes", "skip_bytes", "seek_bytes", "records", "no_truncate", "append",
+            "ranges_file", "exclude_ranges_file", "priority_ranges", "verify", "sparse", "discard_zeros", "conv", "noerror", "error_fill", "max_errors",
+            "write_if_changed", "check_input_stable", "flush_probe", "check_bootable", "check_fat",
+            "disable_write_cache", "follow", "until_size", "until_idle", "resume", "monitor_health", "no_sync_dir", "sync_mode", "max_temp",
+            "pause_on_battery", "open_timeout", "tcp_framing", "retries", "retry_delay", "retry_writes", "threads", "engine", "max_in_flight", "simulate_device", "low_memory", "no_fast_path",
+            "target_profile", "compress",
+            "compress_level", "seekable", "decompress", "no_decompress", "split_size",
+        ]
+    )]
+    pub from_manifest: Option<String>,
+
     /// [Unix-only] Use O_DIRECT to bypass the OS page cache for I/O. This can improve performance for large transfers on fast devices but may degrade it in other cases. Requires block size to be aligned to the filesystem's logical block size.
     #[cfg(unix)]
-    #[arg(long)]
+    #[arg(long, conflicts_with = "from_manifest")]
     pub direct: bool,
 }
 
-/// Supported hashing algorithms for the --verfiy flag.
-#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
+/// Supported hashing algorithms for the --verify flag.
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum HashAlgorithm {
     Sha256,
+    #[cfg(feature = "blake3")]
     Blake3
 }
+
+/// What one pass of `rdd wipe` writes.
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WipePattern {
+    /// Every byte 0x00.
+    Zeros,
+    /// Every byte 0xFF.
+    Ones,
+    /// Pseudo-random data, different on every run.
+    Random,
+}
+
+/// Compressed formats for --compress, each handled by the program of the same name.
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Compression {
+    /// .gz, widely available but slow.
+    Gzip,
+    /// .zst, fast, with a good ratio.
+    Zstd,
+    /// .xz, the best ratio, and the slowest.
+    Xz,
+}
+
+/// The data conversions of --conv, after dd's conv= operand.
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Conversion {
+    /// Swap the bytes of every pair.
+    Swab,
+    /// Upper-case ASCII letters.
+    Ucase,
+    /// Lower-case ASCII letters.
+    Lcase,
+    /// Translate EBCDIC to ASCII.
+    Ascii,
+    /// Translate ASCII to EBCDIC.
+    Ebcdic,
+}
+
+/// When the input of a copy is decompressed (--decompress, --no-decompress).
+#[derive(ValueEnum, Clone, Debug, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Decompression {
+    /// Regular files that start with a known header.
+    #[default]
+    Auto,
+    /// Always; the input must be in a known format.
+    Always,
+    /// Never; compressed files are copied as they are.
+    Never,
+}
+
+/// How the copy moves its data (--engine).
+#[derive(ValueEnum, Clone, Debug, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Engine {
+    /// One read or write at a time, or reader and writer threads with --threads.
+    #[default]
+    Standard,
+    /// A queue of reads and writes in flight through Linux's io_uring.
+    IoUring,
+}
+
+/// Built-in tuning profiles for the --target-profile flag.
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TargetProfile {
+    /// Spinning disks in USB enclosures.
+    UsbHdd,
+    /// USB sticks and SD cards.
+    UsbFlash,
+    /// Local NVMe SSDs.
+    Nvme,
+    /// NFS/SMB and other network filesystems.
+    NetworkFs,
+    /// SD cards cloned for embedded products: like usb-flash, plus --check-fat.
+    SdClone,
+}
+
+/// How the results of a copy are reported (--stats-format).
+#[derive(ValueEnum, Clone, Debug, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StatsFormat {
+    /// A few lines of text for people.
+    #[default]
+    Text,
+    /// One JSON object for scripts.
+    Json,
+}
+
+/// What to do when the input changes during a copy (--check-input-stable).
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InputGuard {
+    /// Print a warning and keep the output.
+    Warn,
+    /// Fail the copy with an error.
+    Fail,
+}
@@ -3,70 +3,2463 @@
 // Explanation of this file:
 // CopyConfig Struct: This is our clean, internal representation of the job to be done. Notice that block_size is a usize, the correct type for memory allocations and buffer size in Rust.
 // from_args Function: This acts as a bridge between the cli module and our application logic. It takes the CopyArgs struct (which contains strings) and produces a validated CopyConfig struct (which contains correctly typed data).
+// CopyConfigBuilder: The typed way to create a CopyConfig when rdd is used as a library. from_args only parses strings and then hands the typed values to the builder, so the CLI and library users share exactly the same defaults and validation in build().
 // parse_size Function: This is the workhorse of the module
     // It's robust : It handles whitespace, is case-insensitive, and provides clear error messages for invalid numbers or suffixes.
     // It's safe : It uses checked_mul to prevent integer overflows if a user specifies an enormous number (e.g., 1000000T)
     // It's architecture-aware: It uses usize::try_from to ensure the final size fits into the memory space of the target machine (a u64 can be larger than a usize on a 32-bit system.)
-// Validation: We added a check to ensure block_size is not zero, which would cause an infinite loop or a panic in the copy logic. This is the kind of validation this module is reponsible for.
+// Features : --compress, --decompress, --engine io-uring and --tui are rejected with FeatureDisabled when rdd was built without the cargo feature they need (see Cargo.toml). BLAKE3 is simply missing from HashAlgorithm then, so the parser rejects it.
+// Threads : On platforms without threads (WebAssembly without the threads proposal, see utils/threads.rs), build() rejects --threads, --split-size, --compress and --open-timeout, which all need a second thread.
+// Engines : --engine io-uring (see core/engine.rs) has the restrictions of the multithreaded copy, and also needs files it can read and write at offsets, so build() rejects streams and --noerror with it.
+// Low memory : --low-memory caps max_io, and with it every copy buffer, at LOW_MEMORY_MAX_IO. It refuses block hash files, which hold a hash per block, and more than one --verify algorithm, and tells the engines to keep their queues short (see parallel.rs and compress.rs).
+// Coalescing : --coalesce is capped at max_io, and only the single-threaded copy gathers writes (see core/coalesce.rs), so build() rejects it with --threads, --engine io-uring and flush probes.
+// Back-pressure : --max-in-flight caps the data the multithreaded and io_uring engines read ahead of the output, in bytes (see core/scheduler.rs). It's only a limit: a single chunk may still be bigger than it.
+// Retries : --retries and --retry-delay are passed on to the copy engines (see core/retry.rs). Retried writes continue at the offset where they failed, so build() rejects --retry-writes for outputs that are streams, and the io_uring engine rejects retries altogether.
+// Digests : --verify can be given more than once; the first algorithm is verification_algo and the others go to verify_also, and digest_algorithms() lists them all, so the data is hashed with every one of them in the same pass. --hash-output needs the digest of the whole output file, so build() rejects it with ranges, --seek, --no-truncate and --append, as well as for streams.
+// Byte offsets : --skip-bytes, --seek-bytes and --count-bytes take sizes like --bs, or hexadecimal numbers (0x1BE), and win over --skip, --seek and --count. build() resolves both kinds into skip_bytes and seek_bytes, the absolute offsets the engines start at, and count_bytes, a byte limit that leaves count at 0. Only count stays a block count, since dd counts partial blocks from short reads as blocks too.
+// Records : With --records, --skip and --count count lines of the input instead (see core/records.rs). build() moves them into records and leaves skip and count at 0, so the engines copy what the record slicer hands them, from start to end.
+// Truncating : The output is truncated when it's opened, unless --no-truncate or --append says not to. Like dd, --seek implies --no-truncate, so patching a region inside an existing image keeps the rest of it; build() folds all of that into truncate. --append writes wherever the output ends, so only the single-threaded copy can do it, and not together with positioning in the output.
+// Fast paths : Between two regular files, the single-threaded copy lets the kernel move the data (see core/fastpath.rs) unless an option needs to see it. --no-fast-path turns that off.
+// Block size tuning : --bs auto sets auto_block_size, and block_size is then where the tuning starts (the default, or the target profile's). The block size changes during the copy, so build() rejects it with --count, --skip and --seek in blocks (the byte options work), with options that record or compare blocks, and with the engines that don't tune.
+// Target profiles: If a target profile is given, its tuned defaults (see profile.rs) fill in anything the user didn't set explicitly. An explicit block size always takes precedence.
+// stdin and stdout: An input or output of "-" means stdin or stdout, like in most Unix tools. build() rejects the options that need to stat, read back or seek around in a named file, since stdin and stdout are often pipes.
+// Network : An input or output of tcp://HOST:PORT (or tcp://:PORT to listen) is received or sent over a connection (see core/network.rs), one of ssh://[USER@]HOST/PATH is read or written by ssh (see core/remote.rs), and an http:// or https:// input is downloaded (see core/http.rs). It's a stream like stdin and stdout, so build() rejects the same options for it, and --tcp-framing needs one.
+// Splitting : --split-size makes the output a pipe to the splitter (see core/split.rs), with the same restrictions as stdout. An input named like the first chunk of a set is a pipe from the joiner; that's only known once it's opened.
+// Compression : --compress turns the output into a pipe to the compressor, and a decompressed input is a pipe from the decompressor (see core/compress.rs), so the same options are rejected for them. Whether a regular file is compressed is only known once it's read, so with the default Decompression::Auto those checks happen when it's opened. --seekable only works with zstd, and its frame sizes must fit the seek table (see core/seekable.rs).
+// VerifyConfig : The settings of the 'verify' command. It only has a handful of options, so it's built directly from its arguments without a builder; programs start from VerifyConfig::new and set the public fields they need. WipeConfig, ScrubConfig, ListConfig, BenchmarkConfig, ExtractConfig and TopConfig do the same for the 'wipe', 'scrub', 'list', 'benchmark', 'extract' and 'top' commands.
+// Manifests : --manifest-out records the effective settings of a copy in a manifest file, and --from-manifest reads them back (see core/manifest.rs). A manifest is turned into a builder rather than a finished CopyConfig, so a replayed copy goes through the same validation as a typed-in one.
+// Hash windows : --hash-window and --hash-log go together, into hash_window and hash_log. Like block hashes, they are a record of this run, so --from-manifest takes them from the command line. The windows are counted from where the data starts in the input, so build() rejects them with a ranges list.
+// Simulation : A --simulate-device model is loaded here too, so a malformed one is reported before the copy starts. The io_uring engine reads in the kernel, where the model can't intervene, so build() rejects it (see core/simulate.rs).
+// Block hashes : A --compare-manifest file is loaded here too, and its block size becomes the copy's unless one is set explicitly; a different one is refused, since blocks can only be compared with hashes of the same blocks.
+// Ranges files: If --ranges-file or --exclude-ranges-file is given, the file is loaded and parsed here, so a malformed file is reported before any copying starts.
+// Priority ranges : --priority-ranges auto or FILE becomes a PriorityRanges, a file loaded here like a ranges file. They're only turned into the copy's ranges list once the input is open (see core/priority.rs), so build() rejects with them whatever it rejects with a ranges list, and everything else that can't jump around in the whole input.
+// Sync mode : --sync-mode is parsed by SyncMode::parse (see core/syncmode.rs). Syncing along the way needs an output rdd writes itself, so build() rejects interval and per-block for stdout and the compressor's output; the splitter's chunks are fine.
+// Following : --follow, --until-size and --until-idle become a Follow (see core/follow.rs). Only the single-threaded copy follows an input, and it reads it front to back, so build() rejects threads, io_uring, stdin, ranges and --resume with it, as well as --check-input-stable, since a followed input changes on purpose.
+// Wear tracking : --wear-limit and --wear-history are parsed into a WearTracking for copies and wipes (see core/wear.rs). The CLI always keeps the history; the builder leaves it off unless wear() is called.
+// Reporter : Where the core sends its messages, warnings and results (see core/reporter.rs). The builder defaults to the terminal; library users pass their own with reporter().
+// Serialization : A CopyConfig carries the version of its serialized format (CONFIG_VERSION). Deserializing one turns it back into a builder and runs build() on it, so a stored job description is validated exactly like a typed-in one; a document from a newer format is refused.
+// Validation: build() checks that block_size is not zero, which would cause an infinite loop or a panic in the copy logic. This is the kind of validation this module is reponsible for. It also rejects options the multithreaded copy (--threads 2 or more) can't honour.
 
 // src/config.rs
 
-use crate::cli::{CopyArgs, HashAlgorithm};
+use crate::cli::{
+    BenchmarkArgs, Compression, Conversion, CopyArgs, Decompression, Engine, ExtractArgs, HashAlgorithm, InputGuard, ListArgs, ScrubArgs, StatsFormat, TargetProfile, TopArgs,
+    VerifyArgs, WipeArgs, WipePattern,
+};
+use crate::core::blockhash::BlockHashes;
+use crate::core::checkpoint::default_state_file;
+use crate::core::compress::{check_level, check_stream_input, DECOMPRESS_ADVICE};
+use crate::core::follow::Follow;
+use crate::core::hash::DataDigest;
+use crate::core::http;
+use crate::core::manifest;
+use crate::core::mapfile::parse_offset;
+use crate::core::network::{self, Endpoint};
+use crate::core::reporter::{default_reporter, Reporter, TerminalReporter};
+use crate::core::priority::PriorityRanges;
+use crate::core::ranges::{load_ranges_file, normalize, ByteRange};
+use crate::core::records::Records;
+use crate::core::remote::{self, Remote};
+use crate::core::seekable;
+use crate::core::simulate::DeviceModel;
+use crate::core::syncmode::SyncMode;
+use crate::core::wear::{default_history, WearLimit, WearTracking};
 use crate::error::{RddError, RddResult};
+use crate::utils::threads;
+use crate::profile::FadviseHints;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The block size used when neither --bs nor a target profile sets one.
+const DEFAULT_BLOCK_SIZE: usize = 512 * 1024;
+
+/// The default upper limit for a single read or write syscall.
+const DEFAULT_MAX_IO: usize = 64 * 1024 * 1024;
+
+/// The largest single read or write with --low-memory.
+const LOW_MEMORY_MAX_IO: usize = 64 * 1024;
+
+/// The default limit on data read ahead of the output by the multithreaded
+/// and io_uring engines.
+const DEFAULT_MAX_IN_FLIGHT: usize = 256 * 1024 * 1024;
+
+/// The wait before the first retry of a failed read or write.
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// The path that stands for stdin (as input) or stdout (as output).
+pub const STDIO_PATH: &str = "-";
+
+/// The version of the serialized CopyConfig format. Raise it whenever a
+/// field changes meaning; only adding a field that defaults doesn't need it.
+pub const CONFIG_VERSION: u32 = 1;
 
 /// A validated and processed configuration for a copy operation.
 ///
 /// This struct holds all the necessary parameters for the core copy logic,
 /// with data types that are ready for immediate use (e.g., `block_size` is a
 /// `usize`, not a `String`).
-#[derive(Debug)]
+///
+/// It can be serialized with serde, so job descriptions can be stored and
+/// replayed. Fields added in later versions default when missing, keeping
+/// older documents readable. A deserialized config goes through
+/// [`CopyConfigBuilder::build`], so it's checked like any other.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct CopyConfig {
+    /// The format version the config was serialized with. Documents from
+    /// before it was recorded read as 0.
+    #[serde(default)]
+    pub version: u32,
     pub input_file: String,
     pub output_file: String,
     pub block_size: usize,
+    /// Let the copy tune its block size, starting from `block_size` (see
+    /// core/autotune.rs).
+    #[serde(default)]
+    pub auto_block_size: bool,
+    /// Largest single read or write; bigger blocks are split into chunks.
+    #[serde(default = "default_max_io")]
+    pub max_io: usize,
+    /// Largest write --coalesce gathers small writes into.
+    #[serde(default)]
+    pub coalesce: Option<usize>,
+    /// Most bytes read ahead of the output at once (see core/scheduler.rs).
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+    /// Bytes per second the copy may not exceed.
+    #[serde(default)]
+    pub rate_limit: Option<u64>,
+    /// Blocks to copy, or 0. Left at 0 when count_bytes is given.
     pub count: u64,
     pub skip: u64,
     pub seek: u64,
+    /// Where the copy starts in the input: `skip` blocks, or --skip-bytes.
+    #[serde(default)]
+    pub skip_bytes: u64,
+    /// Where the copy starts in the output: `seek` blocks, or --seek-bytes.
+    #[serde(default)]
+    pub seek_bytes: u64,
+    /// Bytes to copy, with --count-bytes.
+    #[serde(default)]
+    pub count_bytes: Option<u64>,
+    /// With --records, the lines to skip and copy, which then replace
+    /// `skip` and `count` (see core/records.rs).
+    #[serde(default)]
+    pub records: Option<Records>,
+    /// Truncate the output when it's opened. Off with --no-truncate, and
+    /// whenever the copy seeks into the output or appends to it.
+    #[serde(default = "default_truncate")]
+    pub truncate: bool,
+    /// Add the data at the end of the output instead of its start.
+    #[serde(default)]
+    pub append: bool,
     pub show_progress: bool,
+    /// How the results are reported at the end.
+    #[serde(default)]
+    pub stats_format: StatsFormat,
+    /// Where JSON results go instead of stdout.
+    #[serde(default)]
+    pub stats_file: Option<String>,
+    /// Log the bytes read and written, and the rates, to this CSV file
+    /// every second (see core/bandwidth.rs).
+    #[serde(default)]
+    pub bandwidth_log: Option<String>,
+    #[serde(default)]
+    pub write_if_changed: bool,
+    /// Leave all-zero chunks as holes in the output instead of writing them.
+    #[serde(default)]
+    pub sparse: bool,
+    /// Deallocate all-zero chunks in the output instead of writing them.
+    #[serde(default)]
+    pub discard_zeros: bool,
+    /// How the data is converted between reading and writing (see --conv).
+    #[serde(default)]
+    pub conversions: Vec<Conversion>,
+    /// Fill unreadable chunks and carry on instead of failing.
+    #[serde(default)]
+    pub noerror: bool,
+    /// How often a failed read (and with `retry_writes`, write) is tried again.
+    #[serde(default)]
+    pub retries: u32,
+    /// The wait before the first retry; it doubles before every further one.
+    #[serde(default = "default_retry_delay")]
+    pub retry_delay: Duration,
+    /// Retry failed writes as well as reads.
+    #[serde(default)]
+    pub retry_writes: bool,
+    /// The byte unreadable chunks are filled with.
+    #[serde(default)]
+    pub error_fill: u8,
+    /// Give up once more reads than this have failed (with `noerror`).
+    #[serde(default)]
+    pub max_errors: Option<u64>,
+    /// Where the map of the copy is saved when `max_errors` gives up.
+    #[serde(default)]
+    pub error_map: Option<String>,
+    /// If set, only these ranges of the input are copied (see --ranges-file).
+    #[serde(default)]
+    pub ranges: Option<Vec<ByteRange>>,
+    /// Input ranges that must never be read (see --exclude-ranges-file).
+    #[serde(default)]
+    pub excluded: Vec<ByteRange>,
+    /// Regions of the input copied before the rest (see --priority-ranges).
+    #[serde(default)]
+    pub priority_ranges: Option<PriorityRanges>,
+    #[serde(default)]
+    pub fadvise: FadviseHints,
+    /// Check that the input didn't change while it was copied.
+    #[serde(default)]
+    pub input_guard: Option<InputGuard>,
+    /// Keep reading an input that is still growing (see core/follow.rs).
+    #[serde(default)]
+    pub follow: Option<Follow>,
+    /// Flush the output every this many bytes to measure its stable write rate.
+    #[serde(default)]
+    pub flush_probe: Option<u64>,
+    /// How long to wait for the input or output to open.
+    #[serde(default)]
+    pub open_timeout: Option<Duration>,
+    /// Frame the data of a tcp:// input or output (see core/network.rs).
+    #[serde(default)]
+    pub tcp_framing: bool,
+    /// The file ssh's password is read from (see utils/passphrase.rs).
+    #[serde(default)]
+    pub passphrase_file: Option<String>,
+    /// Inspect the output for boot markers once the copy is done.
+    #[serde(default)]
+    pub check_bootable: bool,
+    /// Check the FAT and exFAT filesystems read back from the output.
+    #[serde(default)]
+    pub check_fat: bool,
+    /// Turn off the target drive's volatile write cache during the copy.
+    #[serde(default)]
+    pub disable_write_cache: bool,
+    /// Sync the output's directory after the output itself.
+    #[serde(default = "default_sync_dir")]
+    pub sync_dir: bool,
+    /// When the output is synced: never, at the end, or also along the way.
+    #[serde(default)]
+    pub sync_mode: SyncMode,
+    /// Write to the output even if it's a drive that holds the running
+    /// system or has mounted filesystems (see core/safety.rs). Never saved in
+    /// a manifest; every run has to ask for it again.
+    #[serde(skip)]
+    pub force: bool,
+    /// Where to record what's written to removable drives, and when to warn
+    /// that one is worn (see core/wear.rs). Like --force, it's this run's
+    /// choice and isn't saved in a manifest.
+    #[serde(skip)]
+    pub wear: Option<WearTracking>,
+    /// The state file that records progress, so an interrupted copy can resume.
+    #[serde(default)]
+    pub resume: Option<String>,
+    /// Watch the drives' temperature and error counters during the copy.
+    #[serde(default)]
+    pub monitor_health: bool,
+    /// Pause while a drive is hotter than this many degrees Celsius.
+    #[serde(default)]
+    pub max_temp: Option<u8>,
+    /// Wait for AC power instead of writing on battery.
+    #[serde(default)]
+    pub pause_on_battery: bool,
     pub verification_algo: Option<HashAlgorithm>,
+    /// Further algorithms the data is hashed and verified with in the same
+    /// pass (--verify given more than once).
+    #[serde(default)]
+    pub verify_also: Vec<HashAlgorithm>,
+    /// Write the digests of the data to this file, for sha256sum -c or b3sum -c.
+    #[serde(default)]
+    pub hash_output: Option<String>,
+    /// Size of the windows the data is hashed in (--hash-window).
+    #[serde(default)]
+    pub hash_window: Option<u64>,
+    /// Where the hash of every window is written.
+    #[serde(default)]
+    pub hash_log: Option<String>,
+    /// Write a reproducibility manifest of the copy to this file.
+    #[serde(default)]
+    pub manifest_out: Option<String>,
+    /// The digest the copied data must have, from a manifest.
+    #[serde(default)]
+    pub expected_digest: Option<DataDigest>,
+    /// Append hash-chained start and finish entries to this log.
+    #[serde(default)]
+    pub custody_log: Option<String>,
+    /// Record the hash of every block read in this file.
+    #[serde(default)]
+    pub block_hashes: Option<String>,
+    /// The block hash file the blocks read are compared with.
+    #[serde(default)]
+    pub compare_manifest: Option<String>,
+    /// The hashes loaded from `compare_manifest`.
+    #[serde(default)]
+    pub expected_blocks: Option<BlockHashes>,
+    /// The device model the input is read through (--simulate-device).
+    #[serde(default)]
+    pub simulate_device: Option<String>,
+    /// The model loaded from `simulate_device`.
+    #[serde(default)]
+    pub device_model: Option<DeviceModel>,
+    /// Compress the output in this format.
+    #[serde(default)]
+    pub compress: Option<Compression>,
+    /// The compression level; the compressor's default when unset.
+    #[serde(default)]
+    pub compress_level: Option<u32>,
+    /// Write --compress zstd output as seekable zstd, in frames of this
+    /// many bytes of input.
+    #[serde(default)]
+    pub seekable_frame_size: Option<u64>,
+    /// When the input is decompressed before it's copied.
+    #[serde(default)]
+    pub decompress: Decompression,
+    /// The largest chunk the output is split into.
+    #[serde(default)]
+    pub split_size: Option<u64>,
     pub threads: u8,
+    /// How the data is moved.
+    #[serde(default)]
+    pub engine: Engine,
+    /// Keep memory use small and fixed (--low-memory).
+    #[serde(default)]
+    pub low_memory: bool,
+    /// Let the kernel move the data between regular files where it can.
+    #[serde(default = "default_fast_path")]
+    pub fast_path: bool,
     #[cfg(unix)]
+    #[serde(default)]
     pub use_direct_io: bool,
+    /// Receives the messages, warnings and results of the copy.
+    #[serde(skip, default = "default_reporter")]
+    pub reporter: Arc<dyn Reporter>,
 }
 
 impl CopyConfig {
+    /// Starts building a `CopyConfig` for copying `input_file` to `output_file`.
+    ///
+    /// This is the entry point for configuring a copy programmatically, without
+    /// going through the command-line parser.
+    pub fn builder(input_file: impl Into<String>, output_file: impl Into<String>) -> CopyConfigBuilder {
+        CopyConfigBuilder::new(input_file.into(), output_file.into())
+    }
+
+    /// Whether --direct was requested. Always false where it's unavailable.
+    pub fn direct_io(&self) -> bool {
+        #[cfg(unix)]
+        return self.use_direct_io;
+        #[cfg(not(unix))]
+        return false;
+    }
+
+    /// The algorithm the copied data is hashed with, if anything needs its
+    /// digest: --verify, a manifest to check against or one to write, the
+    /// custody log, a hash file or hash windows.
+    pub fn digest_algorithm(&self) -> Option<HashAlgorithm> {
+        let recorded = self.manifest_out.is_some()
+            || self.custody_log.is_some()
+            || self.hash_output.is_some()
+            || self.hash_window.is_some();
+        self.verification_algo
+            .or(self.expected_digest.as_ref().map(|digest| digest.algorithm))
+            .or(recorded.then_some(HashAlgorithm::Sha256))
+    }
+
+    /// Every algorithm the copied data is hashed with: digest_algorithm()
+    /// first, then the further --verify algorithms.
+    pub fn digest_algorithms(&self) -> Vec<HashAlgorithm> {
+        let mut algorithms: Vec<_> = self.digest_algorithm().into_iter().collect();
+        for &algo in &self.verify_also {
+            if !algorithms.contains(&algo) {
+                algorithms.push(algo);
+            }
+        }
+        algorithms
+    }
+
+    /// Whether the input is read from stdin.
+    pub fn input_is_stdin(&self) -> bool {
+        self.input_file == STDIO_PATH
+    }
+
+    /// Whether the output is written to stdout.
+    pub fn output_is_stdout(&self) -> bool {
+        self.output_file == STDIO_PATH
+    }
+
+    /// Whether the input is received over the network (a tcp://, ssh:// or
+    /// http(s):// address).
+    pub fn input_is_network(&self) -> bool {
+        network::is_tcp(&self.input_file) || remote::is_ssh(&self.input_file) || http::is_http(&self.input_file)
+    }
+
+    /// Whether the output is sent over the network (a tcp:// or ssh://
+    /// address).
+    pub fn output_is_network(&self) -> bool {
+        network::is_tcp(&self.output_file) || remote::is_ssh(&self.output_file)
+    }
+
+    /// Whether the copy writes to a stream that can't be sought in or read
+    /// back: stdout, a connection, or the pipe to the compressor or the
+    /// splitter.
+    pub fn output_is_stream(&self) -> bool {
+        self.output_is_stdout() || self.output_is_network() || self.compress.is_some() || self.split_size.is_some()
+    }
+
     /// Creates a new `CopyConfig` from the raw command-line arguments.
     ///
-    /// This function is responsible for parsing and validating the arguments
-    /// provided by the user.
+    /// This function is responsible for parsing the string arguments provided
+    /// by the user. The parsed values are then validated by the builder.
     pub fn from_args(args: CopyArgs) -> RddResult<Self> {
-        let block_size = parse_size(&args.bs)?;
+        // The dashboard can be left out of the build (see Cargo.toml).
+        if args.tui && !cfg!(feature = "tui") {
+            return Err(feature_disabled("--tui", "tui"));
+        }
+        // A manifest brings all of its settings along; only the display,
+        // the records kept of this run and --force are taken from the
+        // command line.
+        if let Some(path) = &args.from_manifest {
+            let mut builder = manifest::load(path, &TerminalReporter)?
+                .progress(args.progress)
+                .force(args.force)
+                .wear(wear_tracking(args.wear_limit.as_deref(), args.wear_history.clone())?);
+            if let Some(out) = args.manifest_out {
+                builder = builder.manifest_out(out);
+            }
+            if let Some(path) = args.hash_output {
+                builder = builder.hash_output(path);
+            }
+            if let (Some(size), Some(log)) = (&args.hash_window, args.hash_log) {
+                builder = builder.hash_window(parse_size(size)? as u64, log);
+            }
+            if let Some(log) = args.custody_log {
+                builder = builder.custody_log(log);
+            }
+            if let Some(path) = args.passphrase.passphrase_file {
+                builder = builder.passphrase_file(path);
+            }
+            if let Some(path) = args.block_hashes {
+                builder = builder.block_hashes(path);
+            }
+            if let Some(path) = args.bandwidth_log {
+                builder = builder.bandwidth_log(path);
+            }
+            if let Some(path) = args.compare_manifest {
+                let hashes = BlockHashes::load(&path)?;
+                builder = builder.compare_manifest(path, hashes);
+            }
+            return stats_options(builder, args.stats_format, args.stats_file).build();
+        }
+        let (Some(input), Some(output)) = (args.input, args.output) else {
+            return Err(RddError::Config(
+                "--input and --output are required unless --from-manifest is given.".to_string(),
+            ));
+        };
+
+        let state_file = args
+            .resume
+            .map(|path| path.unwrap_or_else(|| default_state_file(&output)));
+        let mut builder = CopyConfig::builder(input, output)
+            .count(args.count)
+            .skip(args.skip)
+            .seek(args.seek)
+            .records(args.records)
+            .truncate(!args.no_truncate)
+            .append(args.append)
+            .progress(args.progress)
+            .write_if_changed(args.write_if_changed)
+            .sparse(args.sparse)
+            .discard_zeros(args.discard_zeros)
+            .conversions(args.conv.clone())
+            .noerror(args.noerror)
+            .retries(args.retries)
+            .retry_writes(args.retry_writes)
+            .check_bootable(args.check_bootable)
+            .check_fat(args.check_fat)
+            .disable_write_cache(args.disable_write_cache)
+            .sync_dir(!args.no_sync_dir)
+            .force(args.force)
+            .wear(wear_tracking(args.wear_limit.as_deref(), args.wear_history.clone())?)
+            .monitor_health(args.monitor_health)
+            .pause_on_battery(args.pause_on_battery)
+            .threads(args.threads)
+            .engine(args.engine)
+            .low_memory(args.low_memory)
+            .fast_path(!args.no_fast_path);
+
+        match &args.bs {
+            Some(bs) if bs.trim().eq_ignore_ascii_case("auto") => builder = builder.auto_block_size(true),
+            Some(bs) => builder = builder.block_size(parse_size(bs)?),
+            None => {}
+        }
+        if let Some(max_io) = &args.max_io {
+            builder = builder.max_io(parse_size(max_io)?);
+        }
+        if let Some(size) = &args.coalesce {
+            builder = builder.coalesce(parse_size(size)?);
+        }
+        if let Some(max_in_flight) = &args.max_in_flight {
+            builder = builder.max_in_flight(parse_size(max_in_flight)?);
+        }
+        if let Some(rate) = &args.rate_limit {
+            builder = builder.rate_limit(parse_size(rate)? as u64);
+        }
+        if let Some(bytes) = &args.count_bytes {
+            builder = builder.count_bytes(parse_byte_offset(bytes)?);
+        }
+        if let Some(bytes) = &args.skip_bytes {
+            builder = builder.skip_bytes(parse_byte_offset(bytes)?);
+        }
+        if let Some(bytes) = &args.seek_bytes {
+            builder = builder.seek_bytes(parse_byte_offset(bytes)?);
+        }
+        if let Some(interval) = &args.flush_probe {
+            builder = builder.flush_probe(parse_size(interval)? as u64);
+        }
+        if let Some(mode) = &args.sync_mode {
+            builder = builder.sync_mode(SyncMode::parse(mode)?);
+        }
+        if let Some(fill) = &args.error_fill {
+            let byte = parse_offset(fill)
+                .and_then(|value| u8::try_from(value).ok())
+                .ok_or_else(|| RddError::Config(format!("Invalid --error-fill byte: '{}'. Use a value from 0 to 255 (or 0x00 to 0xFF).", fill)))?;
+            builder = builder.error_fill(byte);
+        }
+        if let Some(errors) = args.max_errors {
+            builder = builder.max_errors(errors);
+        }
+        if let Some(path) = args.error_map {
+            builder = builder.error_map(path);
+        }
+        if let Some(path) = state_file {
+            builder = builder.resume(path);
+        }
+        if let Some(celsius) = args.max_temp {
+            builder = builder.max_temp(celsius);
+        }
+        if let Some(ms) = args.retry_delay {
+            builder = builder.retry_delay(Duration::from_millis(ms));
+        }
+        if let Some(secs) = args.open_timeout {
+            builder = builder.open_timeout(Duration::from_secs(secs));
+        }
+        if args.tcp_framing {
+            builder = builder.tcp_framing(true);
+        }
+        if let Some(path) = args.passphrase.passphrase_file {
+            builder = builder.passphrase_file(path);
+        }
+        if let Some(profile) = args.target_profile {
+            builder = builder.target_profile(profile);
+        }
+        if let Some(path) = &args.ranges_file {
+            builder = builder.ranges(load_ranges_file(path)?);
+        }
+        if let Some(path) = &args.exclude_ranges_file {
+            builder = builder.exclude_ranges(load_ranges_file(path)?);
+        }
+        if let Some(value) = &args.priority_ranges {
+            builder = builder.priority_ranges(match value.as_str() {
+                "auto" => PriorityRanges::Auto,
+                path => PriorityRanges::List(load_ranges_file(path)?),
+            });
+        }
+        if let Some(policy) = args.check_input_stable {
+            builder = builder.input_guard(policy);
+        }
+        if args.follow {
+            let until_size = match &args.until_size {
+                Some(size) => Some(parse_size(size)? as u64),
+                None => None,
+            };
+            builder = builder.follow(Follow {
+                until_size,
+                until_idle: args.until_idle.map(Duration::from_secs),
+            });
+        }
+        for algo in args.verify {
+            builder = builder.verify(algo);
+        }
+        if let Some(path) = args.hash_output {
+            builder = builder.hash_output(path);
+        }
+        if let (Some(size), Some(log)) = (&args.hash_window, args.hash_log) {
+            builder = builder.hash_window(parse_size(size)? as u64, log);
+        }
+        if let Some(path) = args.manifest_out {
+            builder = builder.manifest_out(path);
+        }
+        if let Some(path) = args.block_hashes {
+            builder = builder.block_hashes(path);
+        }
+        if let Some(path) = args.compare_manifest {
+            let hashes = BlockHashes::load(&path)?;
+            builder = builder.compare_manifest(path, hashes);
+        }
+        if let Some(path) = args.simulate_device {
+            let model = DeviceModel::load(&path)?;
+            builder = builder.simulate_device(path, model);
+        }
+        if let Some(path) = args.custody_log {
+            builder = builder.custody_log(path);
+        }
+        if let Some(path) = args.bandwidth_log {
+            builder = builder.bandwidth_log(path);
+        }
+        if let Some(format) = args.compress {
+            builder = builder.compress(format, args.compress_level);
+        }
+        if let Some(frame_size) = &args.seekable {
+            let frame_size = match frame_size {
+                Some(size) => parse_size(size)? as u64,
+                None => seekable::FRAME_SIZE,
+            };
+            builder = builder.seekable(frame_size);
+        }
+        if args.decompress {
+            builder = builder.decompress(Decompression::Always);
+        } else if args.no_decompress {
+            builder = builder.decompress(Decompression::Never);
+        }
+        if let Some(size) = args.split_size {
+            builder = builder.split_size(parse_size(&size)? as u64);
+        }
+        #[cfg(unix)]
+        {
+            builder = builder.direct(args.direct);
+        }
+
+        stats_options(builder, args.stats_format, args.stats_file).build()
+    }
+}
+
+/// Turns --wear-limit and --wear-history into the wear tracking of a copy or
+/// wipe. The limit is a size (e.g. 10T), a number of full writes of the
+/// drive (e.g. 500x), or "off" to keep the history without warning.
+fn wear_tracking(limit: Option<&str>, history: Option<String>) -> RddResult<WearTracking> {
+    let limit = match limit.map(str::trim) {
+        None => Some(WearTracking::DEFAULT_LIMIT),
+        Some("off") => None,
+        Some(limit) => match limit.strip_suffix(['x', 'X']) {
+            Some(writes) => match writes.parse::<u64>() {
+                Ok(writes) if writes > 0 => Some(WearLimit::Writes(writes)),
+                _ => {
+                    return Err(RddError::Config(format!(
+                        "Invalid wear limit '{}': expected a size (e.g. 10T), a number of full writes (e.g. 500x) or 'off'.",
+                        limit
+                    )));
+                }
+            },
+            None => Some(WearLimit::Bytes(parse_size(limit)? as u64)),
+        },
+    };
+    Ok(WearTracking {
+        history: history.map(PathBuf::from).unwrap_or_else(default_history),
+        limit,
+    })
+}
+
+/// Applies --stats-format and --stats-file. A stats file holds JSON, so
+/// giving one switches the format to JSON unless another was asked for.
+fn stats_options(
+    mut builder: CopyConfigBuilder,
+    format: Option<StatsFormat>,
+    file: Option<String>,
+) -> CopyConfigBuilder {
+    if let Some(path) = file {
+        builder = builder.stats_file(path).stats_format(StatsFormat::Json);
+    }
+    if let Some(format) = format {
+        builder = builder.stats_format(format);
+    }
+    builder
+}
+
+/// A builder for [`CopyConfig`].
+///
+/// Every setting starts at the same default the CLI uses, except that the
+/// progress bar is off. Nothing is checked until [`build`](Self::build) is
+/// called, which returns the same errors the CLI would report.
+#[derive(Debug, Clone)]
+pub struct CopyConfigBuilder {
+    input_file: String,
+    output_file: String,
+    block_size: Option<usize>,
+    auto_block_size: bool,
+    max_io: usize,
+    coalesce: Option<usize>,
+    max_in_flight: usize,
+    rate_limit: Option<u64>,
+    target_profile: Option<TargetProfile>,
+    fadvise: Option<FadviseHints>,
+    count: u64,
+    skip: u64,
+    seek: u64,
+    count_bytes: Option<u64>,
+    records: bool,
+    skip_bytes: Option<u64>,
+    seek_bytes: Option<u64>,
+    truncate: bool,
+    append: bool,
+    show_progress: bool,
+    stats_format: StatsFormat,
+    stats_file: Option<String>,
+    bandwidth_log: Option<String>,
+    write_if_changed: bool,
+    sparse: bool,
+    discard_zeros: bool,
+    conversions: Vec<Conversion>,
+    noerror: bool,
+    retries: u32,
+    retry_delay: Duration,
+    retry_writes: bool,
+    error_fill: u8,
+    max_errors: Option<u64>,
+    error_map: Option<String>,
+    ranges: Option<Vec<ByteRange>>,
+    excluded: Vec<ByteRange>,
+    priority_ranges: Option<PriorityRanges>,
+    input_guard: Option<InputGuard>,
+    follow: Option<Follow>,
+    flush_probe: Option<u64>,
+    open_timeout: Option<Duration>,
+    tcp_framing: bool,
+    passphrase_file: Option<String>,
+    check_bootable: bool,
+    check_fat: bool,
+    disable_write_cache: bool,
+    sync_dir: bool,
+    sync_mode: SyncMode,
+    force: bool,
+    wear: Option<WearTracking>,
+    resume: Option<String>,
+    monitor_health: bool,
+    max_temp: Option<u8>,
+    pause_on_battery: bool,
+    verification_algo: Option<HashAlgorithm>,
+    verify_also: Vec<HashAlgorithm>,
+    hash_output: Option<String>,
+    hash_window: Option<(u64, String)>,
+    manifest_out: Option<String>,
+    expected_digest: Option<DataDigest>,
+    custody_log: Option<String>,
+    block_hashes: Option<String>,
+    compare_manifest: Option<(String, BlockHashes)>,
+    simulate_device: Option<(String, DeviceModel)>,
+    compress: Option<Compression>,
+    compress_level: Option<u32>,
+    seekable_frame_size: Option<u64>,
+    decompress: Decompression,
+    split_size: Option<u64>,
+    threads: u8,
+    engine: Engine,
+    low_memory: bool,
+    fast_path: bool,
+    #[cfg(unix)]
+    use_direct_io: bool,
+    reporter: Arc<dyn Reporter>,
+}
+
+impl CopyConfigBuilder {
+    fn new(input_file: String, output_file: String) -> Self {
+        Self {
+            input_file,
+            output_file,
+            block_size: None,
+            auto_block_size: false,
+            max_io: DEFAULT_MAX_IO,
+            coalesce: None,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            rate_limit: None,
+            target_profile: None,
+            fadvise: None,
+            count: 0,
+            count_bytes: None,
+            records: false,
+            skip_bytes: None,
+            seek_bytes: None,
+            skip: 0,
+            seek: 0,
+            truncate: true,
+            append: false,
+            show_progress: false,
+            stats_format: StatsFormat::Text,
+            stats_file: None,
+            bandwidth_log: None,
+            write_if_changed: false,
+            sparse: false,
+            discard_zeros: false,
+            conversions: Vec::new(),
+            noerror: false,
+            retries: 0,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            retry_writes: false,
+            error_fill: 0,
+            max_errors: None,
+            error_map: None,
+            ranges: None,
+            excluded: Vec::new(),
+            priority_ranges: None,
+            input_guard: None,
+            follow: None,
+            flush_probe: None,
+            open_timeout: None,
+            tcp_framing: false,
+            passphrase_file: None,
+            check_bootable: false,
+            check_fat: false,
+            disable_write_cache: false,
+            sync_dir: true,
+            sync_mode: SyncMode::End,
+            force: false,
+            wear: None,
+            resume: None,
+            monitor_health: false,
+            max_temp: None,
+            pause_on_battery: false,
+            verification_algo: None,
+            verify_also: Vec::new(),
+            hash_output: None,
+            hash_window: None,
+            manifest_out: None,
+            expected_digest: None,
+            custody_log: None,
+            block_hashes: None,
+            compare_manifest: None,
+            simulate_device: None,
+            compress: None,
+            compress_level: None,
+            seekable_frame_size: None,
+            decompress: Decompression::Auto,
+            split_size: None,
+            threads: 1,
+            engine: Engine::Standard,
+            low_memory: false,
+            fast_path: true,
+            #[cfg(unix)]
+            use_direct_io: false,
+            reporter: default_reporter(),
+        }
+    }
+
+    /// Block size in bytes. Overrides the target profile's block size.
+    pub fn block_size(mut self, bytes: usize) -> Self {
+        self.block_size = Some(bytes);
+        self
+    }
+
+    /// Let the copy find the fastest block size while it runs, starting
+    /// from the one it would use otherwise.
+    pub fn auto_block_size(mut self, enabled: bool) -> Self {
+        self.auto_block_size = enabled;
+        self
+    }
+
+    /// Largest single read or write issued to the OS. Larger blocks are
+    /// transferred in several chunks.
+    pub fn max_io(mut self, bytes: usize) -> Self {
+        self.max_io = bytes;
+        self
+    }
+
+    /// Gathers consecutive writes into writes of up to `bytes` bytes, for
+    /// devices that are slow with small ones.
+    pub fn coalesce(mut self, bytes: usize) -> Self {
+        self.coalesce = Some(bytes);
+        self
+    }
+
+    /// Most bytes the multithreaded and io_uring engines read ahead of the
+    /// output. At least one chunk is always in flight, however small this is.
+    pub fn max_in_flight(mut self, bytes: usize) -> Self {
+        self.max_in_flight = bytes;
+        self
+    }
+
+    /// Limits the copy to `bytes_per_sec` bytes per second.
+    pub fn rate_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.rate_limit = Some(bytes_per_sec);
+        self
+    }
+
+    /// Applies a target profile's tuned defaults (see `profile.rs`).
+    pub fn target_profile(mut self, profile: TargetProfile) -> Self {
+        self.target_profile = Some(profile);
+        self
+    }
+
+    /// Page cache hints. Overrides the target profile's hints.
+    pub fn fadvise(mut self, hints: FadviseHints) -> Self {
+        self.fadvise = Some(hints);
+        self
+    }
+
+    /// Number of blocks to copy (0 copies until end of input).
+    pub fn count(mut self, blocks: u64) -> Self {
+        self.count = blocks;
+        self
+    }
+
+    /// Number of blocks to skip at the start of the input.
+    pub fn skip(mut self, blocks: u64) -> Self {
+        self.skip = blocks;
+        self
+    }
+
+    /// Number of blocks to seek over at the start of the output.
+    pub fn seek(mut self, blocks: u64) -> Self {
+        self.seek = blocks;
+        self
+    }
+
+    /// Number of bytes to copy. Takes precedence over count().
+    pub fn count_bytes(mut self, bytes: u64) -> Self {
+        self.count_bytes = Some(bytes);
+        self
+    }
+
+    /// Count `skip` and `count` in newline-delimited records of the input
+    /// instead of blocks.
+    pub fn records(mut self, enabled: bool) -> Self {
+        self.records = enabled;
+        self
+    }
+
+    /// Number of bytes to skip at the start of the input. Takes precedence
+    /// over skip().
+    pub fn skip_bytes(mut self, bytes: u64) -> Self {
+        self.skip_bytes = Some(bytes);
+        self
+    }
+
+    /// Number of bytes to seek over at the start of the output. Takes
+    /// precedence over seek().
+    pub fn seek_bytes(mut self, bytes: u64) -> Self {
+        self.seek_bytes = Some(bytes);
+        self
+    }
+
+    /// Whether to truncate the output when it's opened, like dd does
+    /// without conv=notrunc. On by default, but a copy that seeks into the
+    /// output or appends to it never truncates it.
+    pub fn truncate(mut self, enabled: bool) -> Self {
+        self.truncate = enabled;
+        self
+    }
+
+    /// Add the data at the end of the output, like dd's oflag=append.
+    pub fn append(mut self, enabled: bool) -> Self {
+        self.append = enabled;
+        self
+    }
+
+    /// Whether to draw a progress bar on stderr.
+    pub fn progress(mut self, enabled: bool) -> Self {
+        self.show_progress = enabled;
+        self
+    }
+
+    /// How the results are reported once the copy is done.
+    pub fn stats_format(mut self, format: StatsFormat) -> Self {
+        self.stats_format = format;
+        self
+    }
+
+    /// Write the JSON results to `path` instead of stdout. Needs the JSON
+    /// stats format.
+    pub fn stats_file(mut self, path: impl Into<String>) -> Self {
+        self.stats_file = Some(path.into());
+        self
+    }
+
+    /// Log the bytes read and written, and the rates, to the CSV file at
+    /// `path` every second.
+    pub fn bandwidth_log(mut self, path: impl Into<String>) -> Self {
+        self.bandwidth_log = Some(path.into());
+        self
+    }
+
+    /// Skip writing output blocks that already match the input.
+    pub fn write_if_changed(mut self, enabled: bool) -> Self {
+        self.write_if_changed = enabled;
+        self
+    }
+
+    /// Seek over all-zero chunks instead of writing them, leaving holes in
+    /// the output.
+    pub fn sparse(mut self, enabled: bool) -> Self {
+        self.sparse = enabled;
+        self
+    }
+
+    /// Deallocate all-zero chunks in the output (where it guarantees they
+    /// read back as zeros) instead of writing them.
+    pub fn discard_zeros(mut self, enabled: bool) -> Self {
+        self.discard_zeros = enabled;
+        self
+    }
+
+    /// Convert the data like dd's conv= does (swab, case, ASCII/EBCDIC).
+    pub fn conversions(mut self, conversions: Vec<Conversion>) -> Self {
+        self.conversions = conversions;
+        self
+    }
+
+    /// Fill chunks that can't be read and keep going, instead of failing.
+    pub fn noerror(mut self, enabled: bool) -> Self {
+        self.noerror = enabled;
+        self
+    }
+
+    /// Try a failed read again up to `retries` times before giving up on it
+    /// (or, with `noerror`, filling it).
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// How long to wait before the first retry. The wait doubles before
+    /// every further one.
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Retry failed writes too, not just reads.
+    pub fn retry_writes(mut self, enabled: bool) -> Self {
+        self.retry_writes = enabled;
+        self
+    }
+
+    /// The byte written in place of unreadable data (with `noerror`).
+    pub fn error_fill(mut self, byte: u8) -> Self {
+        self.error_fill = byte;
+        self
+    }
+
+    /// Give up once more than `errors` reads have failed (with `noerror`),
+    /// after saving the map of the copy.
+    pub fn max_errors(mut self, errors: u64) -> Self {
+        self.max_errors = Some(errors);
+        self
+    }
+
+    /// Where `max_errors` saves the map of the copy, instead of the
+    /// output's file name plus ".map".
+    pub fn error_map(mut self, path: impl Into<String>) -> Self {
+        self.error_map = Some(path.into());
+        self
+    }
+
+    /// Copy only these ranges of the input, to the same output offsets.
+    pub fn ranges(mut self, ranges: Vec<ByteRange>) -> Self {
+        self.ranges = Some(ranges);
+        self
+    }
+
+    /// Never read these ranges of the input; zero them in the output.
+    pub fn exclude_ranges(mut self, ranges: Vec<ByteRange>) -> Self {
+        self.excluded = ranges;
+        self
+    }
+
+    /// Copy these regions of the input first, then the rest.
+    pub fn priority_ranges(mut self, priority: PriorityRanges) -> Self {
+        self.priority_ranges = Some(priority);
+        self
+    }
+
+    /// Warn or fail if the input changes while it is being copied.
+    pub fn input_guard(mut self, policy: InputGuard) -> Self {
+        self.input_guard = Some(policy);
+        self
+    }
+
+    /// Keep reading the input at its end while it grows, until one of the
+    /// conditions of `follow` is met or Ctrl-C.
+    pub fn follow(mut self, follow: Follow) -> Self {
+        self.follow = Some(follow);
+        self
+    }
+
+    /// Flush the output every `interval` bytes and compare its stable write
+    /// rate with the apparent one.
+    pub fn flush_probe(mut self, interval: u64) -> Self {
+        self.flush_probe = Some(interval);
+        self
+    }
+
+    /// Fail with a timeout error if opening the input or output takes longer
+    /// than `timeout`.
+    pub fn open_timeout(mut self, timeout: Duration) -> Self {
+        self.open_timeout = Some(timeout);
+        self
+    }
+
+    /// Send and receive the data of a tcp:// input or output in
+    /// length-prefixed frames with an end marker.
+    pub fn tcp_framing(mut self, enabled: bool) -> Self {
+        self.tcp_framing = enabled;
+        self
+    }
+
+    /// Give ssh the password or key passphrase in the first line of `path`,
+    /// for an ssh:// input or output.
+    pub fn passphrase_file(mut self, path: impl Into<String>) -> Self {
+        self.passphrase_file = Some(path.into());
+        self
+    }
+
+    /// Check whether the output looks bootable once the copy is done.
+    pub fn check_bootable(mut self, enabled: bool) -> Self {
+        self.check_bootable = enabled;
+        self
+    }
+
+    /// Read the FAT and exFAT filesystems back from the output once the
+    /// copy is done, and fail if they're inconsistent where the input's
+    /// aren't. A target profile can turn it on too.
+    pub fn check_fat(mut self, enabled: bool) -> Self {
+        self.check_fat = enabled;
+        self
+    }
+
+    /// Turn off the output drive's volatile write cache while copying, and
+    /// restore it afterwards.
+    pub fn disable_write_cache(mut self, enabled: bool) -> Self {
+        self.disable_write_cache = enabled;
+        self
+    }
+
+    /// Whether to sync the output's directory after the final sync, so a
+    /// newly created output file survives a power loss. On by default.
+    pub fn sync_dir(mut self, enabled: bool) -> Self {
+        self.sync_dir = enabled;
+        self
+    }
+
+    /// When to sync the output (see core/syncmode.rs). Once at the end by
+    /// default.
+    pub fn sync_mode(mut self, mode: SyncMode) -> Self {
+        self.sync_mode = mode;
+        self
+    }
+
+    /// Whether to write to a drive that holds the running system or has
+    /// mounted filesystems, which is refused otherwise. Off by default.
+    pub fn force(mut self, enabled: bool) -> Self {
+        self.force = enabled;
+        self
+    }
+
+    /// Record what's written to a removable output drive in a write
+    /// history, and warn when it's worn. Off by default.
+    pub fn wear(mut self, tracking: WearTracking) -> Self {
+        self.wear = Some(tracking);
+        self
+    }
+
+    /// Record progress in `state_file`, and continue from it if it's left
+    /// over from an interrupted run of the same copy.
+    pub fn resume(mut self, state_file: impl Into<String>) -> Self {
+        self.resume = Some(state_file.into());
+        self
+    }
+
+    /// Show the drives' temperature and error counters while copying.
+    pub fn monitor_health(mut self, enabled: bool) -> Self {
+        self.monitor_health = enabled;
+        self
+    }
+
+    /// Pause the copy while a drive is hotter than `celsius` degrees.
+    /// Implies health monitoring.
+    pub fn max_temp(mut self, celsius: u8) -> Self {
+        self.max_temp = Some(celsius);
+        self
+    }
+
+    /// Wait for AC power before and during the copy when on battery.
+    pub fn pause_on_battery(mut self, enabled: bool) -> Self {
+        self.pause_on_battery = enabled;
+        self
+    }
+
+    /// Hashing algorithm used to verify the copy. Called again with another
+    /// algorithm, the data is hashed and verified with both in one pass; the
+    /// first is the one the summary, manifests and block hashes use.
+    pub fn verify(mut self, algo: HashAlgorithm) -> Self {
+        match self.verification_algo {
+            None => self.verification_algo = Some(algo),
+            Some(first) if first != algo && !self.verify_also.contains(&algo) => self.verify_also.push(algo),
+            Some(_) => {}
+        }
+        self
+    }
+
+    /// Write the digests of the data to `path` once the copy is done, in the
+    /// format of sha256sum and b3sum.
+    pub fn hash_output(mut self, path: impl Into<String>) -> Self {
+        self.hash_output = Some(path.into());
+        self
+    }
+
+    /// Hash the data in windows of `size` bytes and write the hash of every
+    /// window to `log` once the copy is done.
+    pub fn hash_window(mut self, size: u64, log: impl Into<String>) -> Self {
+        self.hash_window = Some((size, log.into()));
+        self
+    }
+
+    /// Write a reproducibility manifest of the copy to `path` once it's done.
+    pub fn manifest_out(mut self, path: impl Into<String>) -> Self {
+        self.manifest_out = Some(path.into());
+        self
+    }
+
+    /// Fail the copy if the data read doesn't have this digest.
+    pub fn expect_digest(mut self, digest: DataDigest) -> Self {
+        self.expected_digest = Some(digest);
+        self
+    }
+
+    /// Append hash-chained entries about the copy to the custody log at `path`.
+    pub fn custody_log(mut self, path: impl Into<String>) -> Self {
+        self.custody_log = Some(path.into());
+        self
+    }
+
+    /// Record the hash of every block read in `path`.
+    pub fn block_hashes(mut self, path: impl Into<String>) -> Self {
+        self.block_hashes = Some(path.into());
+        self
+    }
+
+    /// Compare every block read with `hashes`, loaded from `path`. The copy
+    /// uses their block size unless one is set explicitly.
+    pub fn compare_manifest(mut self, path: impl Into<String>, hashes: BlockHashes) -> Self {
+        self.compare_manifest = Some((path.into(), hashes));
+        self
+    }
+
+    /// Read the input as if it came from the device described by `model`,
+    /// loaded from `path`: slowly, and with the model's read errors.
+    pub fn simulate_device(mut self, path: impl Into<String>, model: DeviceModel) -> Self {
+        self.simulate_device = Some((path.into(), model));
+        self
+    }
+
+    /// Compress the output in `format`, at `level` or the compressor's
+    /// default level.
+    pub fn compress(mut self, format: Compression, level: Option<u32>) -> Self {
+        self.compress = Some(format);
+        self.compress_level = level;
+        self
+    }
+
+    /// Write the compressed output in the zstd seekable format, with
+    /// `frame_size` bytes of input in each frame. Needs zstd compression.
+    pub fn seekable(mut self, frame_size: u64) -> Self {
+        self.seekable_frame_size = Some(frame_size);
+        self
+    }
+
+    /// When to decompress the input. Defaults to Auto: regular files with
+    /// the header of a known format.
+    pub fn decompress(mut self, mode: Decompression) -> Self {
+        self.decompress = mode;
+        self
+    }
+
+    /// Write the output in chunks of at most `bytes`, named after it with
+    /// ".000", ".001", ... appended.
+    pub fn split_size(mut self, bytes: u64) -> Self {
+        self.split_size = Some(bytes);
+        self
+    }
+
+    /// Where the copy's messages, warnings and results go. Defaults to the
+    /// terminal.
+    pub fn reporter(mut self, reporter: Arc<dyn Reporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Number of threads used for I/O.
+    pub fn threads(mut self, threads: u8) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// How the data is moved. Defaults to the standard engine.
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Keep memory use small and fixed: smaller reads and writes, a shorter
+    /// queue for the multithreaded copy, and no per-block hashes.
+    pub fn low_memory(mut self, enabled: bool) -> Self {
+        self.low_memory = enabled;
+        self
+    }
+
+    /// Let the kernel copy between regular files (copy_file_range, or a
+    /// reflink) when nothing needs to see the data. On by default.
+    pub fn fast_path(mut self, enabled: bool) -> Self {
+        self.fast_path = enabled;
+        self
+    }
+
+    /// Use O_DIRECT to bypass the page cache.
+    #[cfg(unix)]
+    pub fn direct(mut self, enabled: bool) -> Self {
+        self.use_direct_io = enabled;
+        self
+    }
+
+    /// Validates the settings and produces the final `CopyConfig`.
+    pub fn build(mut self) -> RddResult<CopyConfig> {
+        let tuning = self.target_profile.map(|profile| profile.tuning());
+        let check_fat = self.check_fat || tuning.is_some_and(|t| t.check_fat);
+
+        // A tcp:// address must say where to connect or listen (see
+        // core/network.rs), and an ssh:// one which host and path (see
+        // core/remote.rs). An http(s):// URL can only be downloaded (see
+        // core/http.rs).
+        let (tcp_input, tcp_output) = (network::is_tcp(&self.input_file), network::is_tcp(&self.output_file));
+        let (ssh_input, ssh_output) = (remote::is_ssh(&self.input_file), remote::is_ssh(&self.output_file));
+        let network_input = tcp_input || ssh_input || http::is_http(&self.input_file);
+        let network_output = tcp_output || ssh_output;
+        for path in [&self.input_file, &self.output_file] {
+            if network::is_tcp(path) {
+                Endpoint::parse(path)?;
+            }
+            if remote::is_ssh(path) {
+                Remote::parse(path)?;
+            }
+        }
+        if http::is_http(&self.output_file) {
+            return Err(RddError::Config(format!(
+                "'{}' can only be downloaded from; rdd doesn't upload. Write to a file, device or ssh:// path instead.",
+                self.output_file
+            )));
+        }
+        if self.tcp_framing && !tcp_input && !tcp_output {
+            return Err(RddError::Config("--tcp-framing needs a tcp:// input or output.".to_string()));
+        }
+        if self.passphrase_file.is_some() && !ssh_input && !ssh_output {
+            return Err(RddError::Config("--passphrase-file needs an ssh:// input or output.".to_string()));
+        }
+
+        // --records counts lines, which only the record slicer finds (see
+        // core/records.rs), so skip and count leave the blocks alone. The
+        // copy reads the slicer's pipe, a stream like stdin.
+        let records = match self.records {
+            true => {
+                let unsupported = [
+                    (self.skip_bytes.is_some_and(|bytes| bytes > 0), "--skip-bytes"),
+                    (self.count_bytes.is_some(), "--count-bytes"),
+                    (self.ranges.is_some(), "a ranges list"),
+                    (!self.excluded.is_empty(), "excluded ranges"),
+                    (self.priority_ranges.is_some(), "--priority-ranges"),
+                    (self.input_guard.is_some(), "--check-input-stable"),
+                    (self.resume.is_some(), "--resume"),
+                    (self.noerror, "--noerror"),
+                    (self.follow.is_some(), "--follow"),
+                    (self.engine == Engine::IoUring, "--engine io-uring"),
+                ];
+                if let Some((_, what)) = unsupported.iter().find(|(set, _)| *set) {
+                    return Err(RddError::Config(format!("--records cannot be combined with {}.", what)));
+                }
+                let records = Records {
+                    skip: self.skip,
+                    count: (self.count > 0).then_some(self.count),
+                };
+                (self.skip, self.count) = (0, 0);
+                Some(records)
+            }
+            false => None,
+        };
+
+        // --bs auto changes the block size as the copy goes (see
+        // core/autotune.rs), so nothing may count in blocks, and only the
+        // single-threaded copy tunes it.
+        if self.auto_block_size {
+            let unsupported = [
+                (self.threads > 1, "--threads"),
+                (self.engine == Engine::IoUring, "--engine io-uring"),
+                (self.count > 0 && self.count_bytes.is_none(), "--count (use --count-bytes)"),
+                (self.skip > 0 && self.skip_bytes.is_none(), "--skip (use --skip-bytes)"),
+                (self.seek > 0 && self.seek_bytes.is_none(), "--seek (use --seek-bytes)"),
+                (self.resume.is_some(), "--resume"),
+                (self.block_hashes.is_some(), "--block-hashes"),
+                (self.compare_manifest.is_some(), "--compare-manifest"),
+            ];
+            if let Some((_, what)) = unsupported.iter().find(|(set, _)| *set) {
+                return Err(RddError::Config(format!("--bs auto cannot be combined with {}.", what)));
+            }
+        }
+
+        let recorded_block_size = self.compare_manifest.as_ref().map(|(_, hashes)| hashes.block_size);
+        let block_size = self
+            .block_size
+            .or(recorded_block_size)
+            .or(tuning.map(|t| t.block_size))
+            .unwrap_or(DEFAULT_BLOCK_SIZE);
+
+        // Blocks can only be compared with hashes of the same blocks.
+        if let Some((path, hashes)) = &self.compare_manifest
+            && hashes.block_size != block_size
+        {
+            return Err(RddError::Config(format!(
+                "The block hashes in '{}' were recorded with a block size of {} bytes; compare with the same block size, not {}.",
+                path, hashes.block_size, block_size
+            )));
+        }
 
         // The block size must not be zero.
         if block_size == 0 {
             return Err(RddError::Config("Block size cannot be zero.".to_string()));
         }
 
-        Ok(Self {
-            input_file: args.input,
-            output_file: args.output,
+        // Everything is resolved to bytes; the byte options win over the
+        // block counts.
+        let bs = block_size as u64;
+        let blocks = |count: u64, option: &str| {
+            count
+                .checked_mul(bs)
+                .ok_or_else(|| RddError::Config(format!("{} {} of {}-byte blocks is too large.", option, count, bs)))
+        };
+        let skip_bytes = match self.skip_bytes {
+            Some(bytes) => bytes,
+            None => blocks(self.skip, "--skip")?,
+        };
+        let seek_bytes = match self.seek_bytes {
+            Some(bytes) => bytes,
+            None => blocks(self.seek, "--seek")?,
+        };
+        let count = match self.count_bytes {
+            Some(0) => return Err(RddError::Config("--count-bytes cannot be zero.".to_string())),
+            Some(_) => 0,
+            None => self.count,
+        };
+        blocks(count, "--count")?;
+
+        if self.max_io == 0 {
+            return Err(RddError::Config("Maximum I/O size cannot be zero.".to_string()));
+        }
+        if self.max_in_flight == 0 {
+            return Err(RddError::Config("--max-in-flight cannot be zero.".to_string()));
+        }
+        if self.coalesce == Some(0) {
+            return Err(RddError::Config("--coalesce cannot be zero.".to_string()));
+        }
+        // Every buffer of the copy is at most max_io bytes (see copy.rs).
+        let max_io = match self.low_memory {
+            true => self.max_io.min(LOW_MEMORY_MAX_IO),
+            false => self.max_io,
+        };
+        // A block hash file keeps a hash per block in memory, which grows
+        // with the size of the input.
+        if self.low_memory
+            && (self.block_hashes.is_some() || self.compare_manifest.is_some() || self.hash_window.is_some())
+        {
+            return Err(RddError::Config(
+                "--low-memory cannot be combined with --block-hashes, --compare-manifest or --hash-window, which keep a hash of every block or window in memory.".to_string(),
+            ));
+        }
+        // Hashing with several algorithms keeps a hasher state for each.
+        if self.low_memory && !self.verify_also.is_empty() {
+            return Err(RddError::Config(
+                "--low-memory cannot be combined with more than one --verify algorithm.".to_string(),
+            ));
+        }
+
+        if self.retry_writes && self.retries == 0 {
+            return Err(RddError::Config("--retry-writes needs --retries.".to_string()));
+        }
+
+        if self.max_errors.is_some() && !self.noerror {
+            return Err(RddError::Config("--max-errors needs --noerror.".to_string()));
+        }
+        if self.error_map.is_some() && self.max_errors.is_none() {
+            return Err(RddError::Config("--error-map needs --max-errors.".to_string()));
+        }
+
+        if self.rate_limit == Some(0) {
+            return Err(RddError::Config("Rate limit cannot be zero.".to_string()));
+        }
+
+        if self.flush_probe == Some(0) {
+            return Err(RddError::Config("Flush probe interval cannot be zero.".to_string()));
+        }
+
+        if self.stats_file.is_some() && self.stats_format != StatsFormat::Json {
+            return Err(RddError::Config(
+                "A stats file holds JSON; it cannot be combined with --stats-format text.".to_string(),
+            ));
+        }
+
+        if self.input_file.is_empty() || self.output_file.is_empty() {
+            return Err(RddError::Config(
+                "Input and output paths cannot be empty.".to_string(),
+            ));
+        }
+
+        if self.threads == 0 {
+            return Err(RddError::Config(
+                "Thread count must be at least 1.".to_string(),
+            ));
+        }
+
+        // The parallel copy only streams the input front to back. Everything
+        // that needs to look at the output first, or jump around in the
+        // files, is left to the single-threaded copy.
+        if self.threads > 1 {
+            let unsupported = [
+                (self.write_if_changed, "--write-if-changed"),
+                (self.ranges.is_some(), "a ranges list"),
+                (!self.excluded.is_empty(), "excluded ranges"),
+                (self.flush_probe.is_some(), "flush probes"),
+                (self.sparse, "--sparse"),
+                (self.discard_zeros, "--discard-zeros"),
+                (self.resume.is_some(), "--resume"),
+                (self.block_hashes.is_some(), "--block-hashes"),
+                (self.compare_manifest.is_some(), "--compare-manifest"),
+                (self.coalesce.is_some(), "--coalesce"),
+                (self.follow.is_some(), "--follow"),
+            ];
+            if let Some((_, what)) = unsupported.iter().find(|(set, _)| *set) {
+                return Err(RddError::Config(format!(
+                    "More than one thread cannot be combined with {}.",
+                    what
+                )));
+            }
+        }
+
+        // Without threads (see utils/threads.rs), only the single-threaded
+        // copy runs.
+        if !threads::AVAILABLE {
+            let unsupported = [
+                (self.threads > 1, "--threads"),
+                (self.split_size.is_some(), "--split-size"),
+                (self.compress.is_some(), "--compress"),
+                (self.open_timeout.is_some(), "--open-timeout"),
+                (self.bandwidth_log.is_some(), "--bandwidth-log"),
+                (network_input || network_output, "a network input or output"),
+            ];
+            if let Some((_, what)) = unsupported.iter().find(|(set, _)| *set) {
+                return Err(RddError::Config(format!(
+                    "{} needs threads, which this platform doesn't have.",
+                    what
+                )));
+            }
+        }
+
+        // The io_uring engine streams the input front to back too, and needs
+        // files it can read and write at offsets.
+        if self.engine == Engine::IoUring {
+            if !cfg!(feature = "io-uring") {
+                return Err(feature_disabled("--engine io-uring", "io-uring"));
+            }
+            if !cfg!(target_os = "linux") {
+                return Err(RddError::Config("--engine io-uring is only available on Linux.".to_string()));
+            }
+            let unsupported = [
+                (self.threads > 1, "--threads"),
+                (self.input_file == STDIO_PATH, "reading from stdin"),
+                (self.output_file == STDIO_PATH, "writing to stdout"),
+                (network_input || network_output, "a network input or output"),
+                (self.compress.is_some(), "--compress"),
+                (self.decompress == Decompression::Always, "--decompress"),
+                (self.split_size.is_some(), "--split-size"),
+                (self.write_if_changed, "--write-if-changed"),
+                (self.ranges.is_some(), "a ranges list"),
+                (!self.excluded.is_empty(), "excluded ranges"),
+                (self.flush_probe.is_some(), "flush probes"),
+                (self.sparse, "--sparse"),
+                (self.discard_zeros, "--discard-zeros"),
+                (self.noerror, "--noerror"),
+                (self.retries > 0, "--retries"),
+                (self.resume.is_some(), "--resume"),
+                (self.block_hashes.is_some(), "--block-hashes"),
+                (self.compare_manifest.is_some(), "--compare-manifest"),
+                (self.coalesce.is_some(), "--coalesce"),
+                (self.simulate_device.is_some(), "--simulate-device"),
+                (self.follow.is_some(), "--follow"),
+            ];
+            if let Some((_, what)) = unsupported.iter().find(|(set, _)| *set) {
+                return Err(RddError::Config(format!(
+                    "--engine io-uring cannot be combined with {}.",
+                    what
+                )));
+            }
+        }
+
+        // stdin and stdout may be pipes: they can't be stat'ed by path, read
+        // back or moved around in. Neither can a connection.
+        if self.input_file == STDIO_PATH || network_input {
+            let unsupported = [
+                (self.input_guard.is_some(), "--check-input-stable"),
+                (self.ranges.is_some(), "a ranges list"),
+                (!self.excluded.is_empty(), "excluded ranges"),
+                (self.resume.is_some(), "--resume"),
+                (self.noerror, "--noerror"),
+                (self.follow.is_some(), "--follow"),
+            ];
+            if let Some((_, what)) = unsupported.iter().find(|(set, _)| *set) {
+                let reading = match network_input {
+                    true => "Receiving over the network",
+                    false => "Reading from stdin",
+                };
+                return Err(RddError::Config(format!("{} cannot be combined with {}.", reading, what)));
+            }
+        }
+        // A followed input is read front to back as it grows, and changes
+        // on purpose.
+        if let Some(follow) = self.follow {
+            if follow.until_size == Some(0) {
+                return Err(RddError::Config("--until-size cannot be zero.".to_string()));
+            }
+            let unsupported = [
+                (self.ranges.is_some(), "a ranges list"),
+                (!self.excluded.is_empty(), "excluded ranges"),
+                (self.resume.is_some(), "--resume"),
+                (self.input_guard.is_some(), "--check-input-stable"),
+            ];
+            if let Some((_, what)) = unsupported.iter().find(|(set, _)| *set) {
+                return Err(RddError::Config(format!("--follow cannot be combined with {}.", what)));
+            }
+        }
+        // So are the pipes to the compressor and the splitter.
+        if self.output_file == STDIO_PATH || network_output || self.compress.is_some() || self.split_size.is_some() {
+            let unsupported = [
+                (self.write_if_changed, "--write-if-changed"),
+                (self.ranges.is_some(), "a ranges list"),
+                (self.verification_algo.is_some(), "--verify"),
+                (self.hash_output.is_some(), "--hash-output"),
+                (self.flush_probe.is_some(), "flush probes"),
+                (self.check_bootable, "--check-bootable"),
+                (check_fat, "--check-fat"),
+                (self.disable_write_cache, "--disable-write-cache"),
+                (self.sparse, "--sparse"),
+                (self.discard_zeros, "--discard-zeros"),
+                (self.resume.is_some(), "--resume"),
+                (self.retry_writes, "--retry-writes"),
+            ];
+            if let Some((_, what)) = unsupported.iter().find(|(set, _)| *set) {
+                let writing = match (self.compress, self.split_size, network_output) {
+                    (Some(_), _, _) => "Compressing the output",
+                    (None, Some(_), _) => "Splitting the output",
+                    (None, None, true) => "Sending over the network",
+                    (None, None, false) => "Writing to stdout",
+                };
+                return Err(RddError::Config(format!("{} cannot be combined with {}.", writing, what)));
+            }
+        }
+        // The splitter syncs its chunks as it writes them, but stdout and
+        // the compressor's output aren't written by us.
+        if self.sync_mode.periodic() && self.split_size.is_none() {
+            let writing = match (self.compress, self.output_file == STDIO_PATH, network_output) {
+                (Some(_), _, _) => Some("Compressing the output"),
+                (None, true, _) => Some("Writing to stdout"),
+                (None, false, true) => Some("Sending over the network"),
+                (None, false, false) => None,
+            };
+            if let Some(writing) = writing {
+                return Err(RddError::Config(format!(
+                    "{} cannot be combined with --sync-mode {}.",
+                    writing, self.sync_mode
+                )));
+            }
+        }
+        if let Some(format) = self.compress {
+            check_level(format, self.compress_level)?;
+        }
+        if let Some(frame_size) = self.seekable_frame_size {
+            if self.compress != Some(Compression::Zstd) {
+                return Err(RddError::Config("--seekable needs --compress zstd.".to_string()));
+            }
+            if frame_size == 0 || frame_size > seekable::MAX_FRAME_SIZE {
+                return Err(RddError::Config(
+                    "The --seekable frame size must be greater than zero and at most 1G.".to_string(),
+                ));
+            }
+        }
+        // The codecs can be left out of the build (see Cargo.toml).
+        if !cfg!(feature = "compress") {
+            if self.compress.is_some() {
+                return Err(feature_disabled("--compress", "compress"));
+            }
+            if self.decompress == Decompression::Always {
+                return Err(feature_disabled("--decompress", "compress"));
+            }
+        }
+        if let Some(size) = self.split_size {
+            if size == 0 {
+                return Err(RddError::Config("--split-size must be greater than zero.".to_string()));
+            }
+            if self.output_file == STDIO_PATH || network_output {
+                return Err(RddError::Config(
+                    "--split-size needs an output file to name the chunks after, not stdout or a connection.".to_string(),
+                ));
+            }
+        }
+
+        // Direct I/O needs every offset to be sector-aligned, which arbitrary
+        // ranges are not.
+        #[cfg(unix)]
+        if self.use_direct_io && (self.ranges.is_some() || !self.excluded.is_empty() || self.priority_ranges.is_some()) {
+            return Err(RddError::Config(
+                "--direct cannot be combined with a ranges list, excluded ranges or --priority-ranges.".to_string(),
+            ));
+        }
+        #[cfg(unix)]
+        if self.use_direct_io && self.compress.is_some() {
+            return Err(RddError::Config("--direct cannot be combined with --compress.".to_string()));
+        }
+        #[cfg(unix)]
+        if self.use_direct_io && self.split_size.is_some() {
+            return Err(RddError::Config("--direct cannot be combined with --split-size.".to_string()));
+        }
+        #[cfg(unix)]
+        if self.use_direct_io && records.is_some() {
+            return Err(RddError::Config("--direct cannot be combined with --records.".to_string()));
+        }
+
+        if self.discard_zeros {
+            if !cfg!(target_os = "linux") {
+                return Err(RddError::Config("--discard-zeros is only available on Linux.".to_string()));
+            }
+            if self.sparse || self.write_if_changed {
+                return Err(RddError::Config(
+                    "--discard-zeros cannot be combined with --sparse or --write-if-changed.".to_string(),
+                ));
+            }
+        }
+
+        // Each pair undoes the other.
+        let converts = |conversion| self.conversions.contains(&conversion);
+        if converts(Conversion::Ascii) && converts(Conversion::Ebcdic) {
+            return Err(RddError::Config("--conv cannot include both ascii and ebcdic.".to_string()));
+        }
+        if converts(Conversion::Ucase) && converts(Conversion::Lcase) {
+            return Err(RddError::Config("--conv cannot include both ucase and lcase.".to_string()));
+        }
+
+        // Holes only read back as zeros in an output we truncated ourselves.
+        // Skipping over zeros in an output updated in place would leave its
+        // old data behind.
+        if self.sparse && (self.write_if_changed || self.ranges.is_some()) {
+            return Err(RddError::Config(
+                "--sparse cannot be combined with --write-if-changed or a ranges list.".to_string(),
+            ));
+        }
+
+        // The blocks an earlier run copied aren't read again, so they can't
+        // be hashed.
+        if self.resume.is_some() && (self.block_hashes.is_some() || self.compare_manifest.is_some()) {
+            return Err(RddError::Config(
+                "--resume cannot be combined with --block-hashes or --compare-manifest.".to_string(),
+            ));
+        }
+
+        // A resumed copy picks up at a single offset, which only describes
+        // how far a straight front-to-back copy got.
+        if self.resume.is_some() && (self.ranges.is_some() || !self.excluded.is_empty()) {
+            return Err(RddError::Config(
+                "--resume cannot be combined with a ranges list or excluded ranges.".to_string(),
+            ));
+        }
+
+        // Flush probes time single writes, which coalescing merges.
+        if self.coalesce.is_some() && self.flush_probe.is_some() {
+            return Err(RddError::Config(
+                "--coalesce cannot be combined with flush probes.".to_string(),
+            ));
+        }
+
+        // Windows are counted from where the data starts in the input, so
+        // the data must come from one stretch of it.
+        if let Some((size, _)) = &self.hash_window {
+            if *size == 0 {
+                return Err(RddError::Config("--hash-window cannot be zero.".to_string()));
+            }
+            if self.ranges.is_some() {
+                return Err(RddError::Config(
+                    "--hash-window cannot be combined with a ranges list.".to_string(),
+                ));
+            }
+        }
+
+        // A hash file names the output, so the output must hold just the
+        // data that was hashed.
+        if self.hash_output.is_some()
+            && (self.ranges.is_some() || seek_bytes > 0 || !self.truncate || self.append)
+        {
+            return Err(RddError::Config(
+                "--hash-output cannot be combined with seek, --no-truncate, --append or a ranges list, which leave other data in the output.".to_string(),
+            ));
+        }
+
+        // Appended data goes wherever the output ends, which the engines
+        // that write at offsets don't know, and which has nothing to compare
+        // with or resume from.
+        if self.append {
+            let unsupported = [
+                (seek_bytes > 0, "--seek"),
+                (self.ranges.is_some(), "a ranges list"),
+                (self.write_if_changed, "--write-if-changed"),
+                (self.resume.is_some(), "--resume"),
+                (self.threads > 1, "--threads"),
+                (self.engine == Engine::IoUring, "--engine io-uring"),
+                (self.output_file == STDIO_PATH, "writing to stdout"),
+                (network_output, "a network output"),
+                (self.split_size.is_some(), "--split-size"),
+            ];
+            if let Some((_, what)) = unsupported.iter().find(|(set, _)| *set) {
+                return Err(RddError::Config(format!("--append cannot be combined with {}.", what)));
+            }
+        }
+
+        // A ranges list decides where to read and write on its own, so it
+        // can't be combined with the block-based positioning options.
+        if self.ranges.is_some() && (count > 0 || self.count_bytes.is_some() || skip_bytes > 0 || seek_bytes > 0) {
+            return Err(RddError::Config(
+                "A ranges list cannot be combined with count, skip or seek.".to_string(),
+            ));
+        }
+        if self.ranges.is_some() && !self.excluded.is_empty() {
+            return Err(RddError::Config(
+                "A ranges list cannot be combined with excluded ranges.".to_string(),
+            ));
+        }
+
+        // Priority ranges become a ranges list over the whole input once it's
+        // open (see core/priority.rs), so they go wherever a ranges list does.
+        if self.priority_ranges.is_some() {
+            let unsupported = [
+                (self.ranges.is_some(), "a ranges list"),
+                (!self.excluded.is_empty(), "excluded ranges"),
+                (count > 0 || self.count_bytes.is_some() || skip_bytes > 0 || seek_bytes > 0, "count, skip or seek"),
+                (self.append, "--append"),
+                (self.threads > 1, "more than one thread"),
+                (self.engine == Engine::IoUring, "--engine io-uring"),
+                (self.input_file == STDIO_PATH, "reading from stdin"),
+                (self.output_file == STDIO_PATH, "writing to stdout"),
+                (network_input || network_output, "a network input or output"),
+                (self.compress.is_some(), "--compress"),
+                (self.decompress == Decompression::Always, "--decompress"),
+                (self.split_size.is_some(), "--split-size"),
+                (self.follow.is_some(), "--follow"),
+                (self.sparse, "--sparse"),
+                (self.resume.is_some(), "--resume"),
+                (self.hash_window.is_some(), "--hash-window"),
+                (self.hash_output.is_some(), "--hash-output"),
+            ];
+            if let Some((_, what)) = unsupported.iter().find(|(set, _)| *set) {
+                return Err(RddError::Config(format!(
+                    "--priority-ranges cannot be combined with {}.",
+                    what
+                )));
+            }
+        }
+
+        let (compare_path, expected_blocks) = self.compare_manifest.unzip();
+        let (simulate_device, device_model) = self.simulate_device.unzip();
+        let (hash_window_size, hash_log) = self.hash_window.unzip();
+        let config = CopyConfig {
+            version: CONFIG_VERSION,
+            input_file: self.input_file,
+            output_file: self.output_file,
             block_size,
-            count: args.count,
-            skip: args.skip,
-            seek: args.seek,
-            show_progress: args.progress,
-            verification_algo: args.verify,
-            threads: args.threads,
+            auto_block_size: self.auto_block_size,
+            max_io,
+            // Like every other transfer, a coalesced write is at most max_io.
+            coalesce: self.coalesce.map(|size| size.min(max_io)),
+            max_in_flight: self.max_in_flight,
+            rate_limit: self.rate_limit,
+            count,
+            skip: self.skip,
+            seek: self.seek,
+            skip_bytes,
+            seek_bytes,
+            count_bytes: self.count_bytes,
+            records,
+            // Like dd, a copy that seeks into the output keeps what's there.
+            truncate: self.truncate && !self.append && seek_bytes == 0,
+            append: self.append,
+            show_progress: self.show_progress,
+            stats_format: self.stats_format,
+            stats_file: self.stats_file,
+            bandwidth_log: self.bandwidth_log,
+            write_if_changed: self.write_if_changed,
+            sparse: self.sparse,
+            discard_zeros: self.discard_zeros,
+            conversions: self.conversions,
+            noerror: self.noerror,
+            retries: self.retries,
+            retry_delay: self.retry_delay,
+            retry_writes: self.retry_writes,
+            error_fill: self.error_fill,
+            max_errors: self.max_errors,
+            error_map: self.error_map,
+            ranges: self.ranges.map(normalize),
+            excluded: normalize(self.excluded),
+            priority_ranges: self.priority_ranges,
+            fadvise: self
+                .fadvise
+                .or(tuning.map(|t| t.fadvise))
+                .unwrap_or_default(),
+            input_guard: self.input_guard,
+            follow: self.follow,
+            flush_probe: self.flush_probe,
+            open_timeout: self.open_timeout,
+            tcp_framing: self.tcp_framing,
+            passphrase_file: self.passphrase_file,
+            check_bootable: self.check_bootable,
+            check_fat,
+            disable_write_cache: self.disable_write_cache,
+            sync_dir: self.sync_dir,
+            sync_mode: self.sync_mode,
+            force: self.force,
+            wear: self.wear,
+            resume: self.resume,
+            monitor_health: self.monitor_health || self.max_temp.is_some(),
+            max_temp: self.max_temp,
+            pause_on_battery: self.pause_on_battery,
+            verification_algo: self.verification_algo,
+            verify_also: self.verify_also,
+            hash_output: self.hash_output,
+            hash_window: hash_window_size,
+            hash_log,
+            manifest_out: self.manifest_out,
+            expected_digest: self.expected_digest,
+            custody_log: self.custody_log,
+            block_hashes: self.block_hashes,
+            compare_manifest: compare_path,
+            expected_blocks,
+            simulate_device,
+            device_model,
+            compress: self.compress,
+            compress_level: self.compress_level,
+            seekable_frame_size: self.seekable_frame_size,
+            decompress: self.decompress,
+            split_size: self.split_size,
+            threads: self.threads,
+            engine: self.engine,
+            low_memory: self.low_memory,
+            fast_path: self.fast_path,
             #[cfg(unix)]
-            use_direct_io: args.direct,
+            use_direct_io: self.use_direct_io,
+            reporter: self.reporter,
+        };
+
+        // A decompressed input is a pipe too. Regular files are only found
+        // to be compressed once they're opened, so Auto is checked then.
+        if config.decompress == Decompression::Always {
+            check_stream_input(&config, "Decompressing the input", DECOMPRESS_ADVICE)?;
+        }
+        Ok(config)
+    }
+}
+
+impl Serialize for CopyConfig {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CopyConfig::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CopyConfig {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let config = CopyConfig::deserialize(deserializer)?;
+        config.validated().map_err(serde::de::Error::custom)
+    }
+}
+
+impl CopyConfig {
+    /// Runs a deserialized config through the builder, so it's held to the
+    /// same rules as one that was built.
+    fn validated(self) -> RddResult<Self> {
+        if self.version > CONFIG_VERSION {
+            return Err(RddError::Config(format!(
+                "This copy configuration has format version {}, but this rdd only reads up to version {}.",
+                self.version, CONFIG_VERSION
+            )));
+        }
+        let paired = |first: bool, second: bool, what: &str| match first == second {
+            true => Ok(()),
+            false => Err(RddError::Config(format!("This copy configuration has only one of {}.", what))),
+        };
+        paired(self.hash_window.is_some(), self.hash_log.is_some(), "hash_window and hash_log")?;
+        paired(self.compare_manifest.is_some(), self.expected_blocks.is_some(), "compare_manifest and expected_blocks")?;
+        paired(self.simulate_device.is_some(), self.device_model.is_some(), "simulate_device and device_model")?;
+
+        // build() takes --records' lines from skip and count.
+        let (records, skip, count) = match self.records {
+            Some(records) => (true, records.skip, records.count.unwrap_or(0)),
+            None => (false, self.skip, self.count),
+        };
+        CopyConfigBuilder {
+            input_file: self.input_file,
+            output_file: self.output_file,
+            block_size: Some(self.block_size),
+            auto_block_size: self.auto_block_size,
+            max_io: self.max_io,
+            coalesce: self.coalesce,
+            max_in_flight: self.max_in_flight,
+            rate_limit: self.rate_limit,
+            target_profile: None,
+            fadvise: Some(self.fadvise),
+            count,
+            skip,
+            seek: self.seek,
+            count_bytes: self.count_bytes,
+            records,
+            skip_bytes: Some(self.skip_bytes),
+            seek_bytes: Some(self.seek_bytes),
+            truncate: self.truncate,
+            append: self.append,
+            show_progress: self.show_progress,
+            stats_format: self.stats_format,
+            stats_file: self.stats_file,
+            bandwidth_log: self.bandwidth_log,
+            write_if_changed: self.write_if_changed,
+            sparse: self.sparse,
+            discard_zeros: self.discard_zeros,
+            conversions: self.conversions,
+            noerror: self.noerror,
+            retries: self.retries,
+            retry_delay: self.retry_delay,
+            retry_writes: self.retry_writes,
+            error_fill: self.error_fill,
+            max_errors: self.max_errors,
+            error_map: self.error_map,
+            ranges: self.ranges,
+            excluded: self.excluded,
+            priority_ranges: self.priority_ranges,
+            input_guard: self.input_guard,
+            follow: self.follow,
+            flush_probe: self.flush_probe,
+            open_timeout: self.open_timeout,
+            tcp_framing: self.tcp_framing,
+            passphrase_file: self.passphrase_file,
+            check_bootable: self.check_bootable,
+            check_fat: self.check_fat,
+            disable_write_cache: self.disable_write_cache,
+            sync_dir: self.sync_dir,
+            sync_mode: self.sync_mode,
+            force: self.force,
+            wear: self.wear,
+            resume: self.resume,
+            monitor_health: self.monitor_health,
+            max_temp: self.max_temp,
+            pause_on_battery: self.pause_on_battery,
+            verification_algo: self.verification_algo,
+            verify_also: self.verify_also,
+            hash_output: self.hash_output,
+            hash_window: self.hash_window.zip(self.hash_log),
+            manifest_out: self.manifest_out,
+            expected_digest: self.expected_digest,
+            custody_log: self.custody_log,
+            block_hashes: self.block_hashes,
+            compare_manifest: self.compare_manifest.zip(self.expected_blocks),
+            simulate_device: self.simulate_device.zip(self.device_model),
+            compress: self.compress,
+            compress_level: self.compress_level,
+            seekable_frame_size: self.seekable_frame_size,
+            decompress: self.decompress,
+            split_size: self.split_size,
+            threads: self.threads,
+            engine: self.engine,
+            low_memory: self.low_memory,
+            fast_path: self.fast_path,
+            #[cfg(unix)]
+            use_direct_io: self.use_direct_io,
+            reporter: self.reporter,
+        }
+        .build()
+    }
+}
+
+/// A validated configuration for the 'verify' command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyConfig {
+    pub source: String,
+    pub target: String,
+    pub block_size: usize,
+    pub algorithm: HashAlgorithm,
+    /// Compare only this many bytes from the start of each file.
+    pub limit: Option<u64>,
+    pub show_progress: bool,
+    /// Receives the digests and the verdict.
+    #[serde(skip, default = "default_reporter")]
+    pub reporter: Arc<dyn Reporter>,
+}
+
+impl VerifyConfig {
+    /// Compares all of `source` and `target` with `algorithm`, reading the
+    /// default block size and showing no progress. The fields are public,
+    /// so a program can change the rest before running it.
+    pub fn new(source: impl Into<String>, target: impl Into<String>, algorithm: HashAlgorithm) -> Self {
+        VerifyConfig {
+            source: source.into(),
+            target: target.into(),
+            block_size: DEFAULT_BLOCK_SIZE,
+            algorithm,
+            limit: None,
+            show_progress: false,
+            reporter: default_reporter(),
+        }
+    }
+
+    /// Creates a `VerifyConfig` from the raw command-line arguments.
+    pub fn from_args(args: VerifyArgs) -> RddResult<Self> {
+        let block_size = match &args.bs {
+            Some(bs) => parse_size(bs)?,
+            None => DEFAULT_BLOCK_SIZE,
+        };
+        if block_size == 0 {
+            return Err(RddError::Config("Block size cannot be zero.".to_string()));
+        }
+        let limit = match &args.bytes {
+            Some(bytes) => Some(parse_size(bytes)? as u64),
+            None => None,
+        };
+
+        Ok(VerifyConfig {
+            source: args.source,
+            target: args.target,
+            block_size,
+            algorithm: args.algorithm,
+            limit,
+            show_progress: !args.no_progress,
+            reporter: default_reporter(),
+        })
+    }
+}
+
+/// A validated configuration for the 'wipe' command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WipeConfig {
+    pub target: String,
+    pub block_size: usize,
+    /// What each pass writes, in order.
+    pub passes: Vec<WipePattern>,
+    /// Overwrite only this many bytes from the start of the target.
+    pub limit: Option<u64>,
+    /// Read the target back after the last pass.
+    pub verify: bool,
+    pub show_progress: bool,
+    /// Wipe a drive that holds the running system or has mounted
+    /// filesystems (see core/safety.rs).
+    pub force: bool,
+    /// Where to record what's written to a removable drive (see core/wear.rs).
+    #[serde(skip)]
+    pub wear: Option<WearTracking>,
+    /// Receives the progress of the passes and the result.
+    #[serde(skip, default = "default_reporter")]
+    pub reporter: Arc<dyn Reporter>,
+}
+
+impl WipeConfig {
+    /// Creates a `WipeConfig` from the raw command-line arguments.
+    pub fn from_args(args: WipeArgs) -> RddResult<Self> {
+        let block_size = match &args.bs {
+            Some(bs) => parse_size(bs)?,
+            None => DEFAULT_BLOCK_SIZE,
+        };
+        if block_size == 0 {
+            return Err(RddError::Config("Block size cannot be zero.".to_string()));
+        }
+        if args.target == STDIO_PATH {
+            return Err(RddError::Config("Only a file or a device can be wiped, not stdout.".to_string()));
+        }
+        let limit = match &args.bytes {
+            Some(bytes) => Some(parse_size(bytes)? as u64),
+            None => None,
+        };
+
+        Ok(WipeConfig {
+            target: args.target,
+            block_size,
+            passes: args.passes,
+            limit,
+            verify: args.verify,
+            show_progress: !args.no_progress,
+            force: args.force,
+            wear: Some(wear_tracking(args.wear_limit.as_deref(), args.wear_history)?),
+            reporter: default_reporter(),
+        })
+    }
+}
+
+/// A validated configuration for the 'scrub' command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrubConfig {
+    /// Directories to search for manifests, and manifest files.
+    pub paths: Vec<String>,
+    pub block_size: usize,
+    /// Bytes per second the scrub may not exceed.
+    pub rate_limit: Option<u64>,
+    pub report_format: StatsFormat,
+    pub show_progress: bool,
+    /// Receives the report.
+    #[serde(skip, default = "default_reporter")]
+    pub reporter: Arc<dyn Reporter>,
+}
+
+impl ScrubConfig {
+    /// Creates a `ScrubConfig` from the raw command-line arguments.
+    pub fn from_args(args: ScrubArgs) -> RddResult<Self> {
+        let block_size = match &args.bs {
+            Some(bs) => parse_size(bs)?,
+            None => DEFAULT_BLOCK_SIZE,
+        };
+        if block_size == 0 {
+            return Err(RddError::Config("Block size cannot be zero.".to_string()));
+        }
+        let rate_limit = match &args.rate_limit {
+            Some(rate) => Some(parse_size(rate)? as u64),
+            None => None,
+        };
+        if rate_limit == Some(0) {
+            return Err(RddError::Config("Rate limit cannot be zero.".to_string()));
+        }
+
+        Ok(ScrubConfig {
+            paths: args.paths,
+            block_size,
+            rate_limit,
+            report_format: args.report_format,
+            show_progress: !args.no_progress,
+            reporter: default_reporter(),
+        })
+    }
+}
+
+/// A configuration for the 'list' command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListConfig {
+    /// Only list removable drives.
+    pub removable_only: bool,
+    pub report_format: StatsFormat,
+    /// Receives the list.
+    #[serde(skip, default = "default_reporter")]
+    pub reporter: Arc<dyn Reporter>,
+}
+
+impl ListConfig {
+    /// Creates a `ListConfig` from the raw command-line arguments.
+    pub fn from_args(args: ListArgs) -> Self {
+        ListConfig {
+            removable_only: args.removable,
+            report_format: args.report_format,
+            reporter: default_reporter(),
+        }
+    }
+}
+
+/// The block sizes 'benchmark' tries unless --sizes says otherwise.
+pub const DEFAULT_BENCHMARK_SIZES: [usize; 7] = [4 << 10, 16 << 10, 64 << 10, 256 << 10, 1 << 20, 4 << 20, 16 << 20];
+
+/// How much 'benchmark' reads and writes at each block size by default.
+pub const DEFAULT_BENCHMARK_BYTES: u64 = 64 << 20;
+
+/// A validated configuration for the 'benchmark' command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkConfig {
+    pub target: String,
+    /// The block sizes to try, in order.
+    pub block_sizes: Vec<usize>,
+    /// Bytes read and written at each block size.
+    pub bytes: u64,
+    /// Leave out the write test.
+    pub read_only: bool,
+    /// Bypass the page cache. Always false where it's unavailable.
+    pub direct: bool,
+    pub report_format: StatsFormat,
+    pub show_progress: bool,
+    /// Write to a drive that holds the running system or has mounted
+    /// filesystems (see core/safety.rs).
+    pub force: bool,
+    /// Receives the results.
+    #[serde(skip, default = "default_reporter")]
+    pub reporter: Arc<dyn Reporter>,
+}
+
+impl BenchmarkConfig {
+    /// Creates a `BenchmarkConfig` from the raw command-line arguments.
+    pub fn from_args(args: BenchmarkArgs) -> RddResult<Self> {
+        let block_sizes = match args.sizes.is_empty() {
+            true => DEFAULT_BENCHMARK_SIZES.to_vec(),
+            false => args.sizes.iter().map(|size| parse_size(size)).collect::<RddResult<_>>()?,
+        };
+        if block_sizes.contains(&0) {
+            return Err(RddError::Config("Block size cannot be zero.".to_string()));
+        }
+        if args.target == STDIO_PATH {
+            return Err(RddError::Config("Only a file or a device can be benchmarked, not stdin or stdout.".to_string()));
+        }
+        let bytes = match &args.bytes {
+            Some(bytes) => parse_size(bytes)? as u64,
+            None => DEFAULT_BENCHMARK_BYTES,
+        };
+        if bytes == 0 {
+            return Err(RddError::Config("--bytes cannot be zero.".to_string()));
+        }
+        #[cfg(unix)]
+        let direct = args.direct;
+        #[cfg(not(unix))]
+        let direct = false;
+
+        Ok(BenchmarkConfig {
+            target: args.target,
+            block_sizes,
+            bytes,
+            read_only: args.read_only,
+            direct,
+            report_format: args.report_format,
+            show_progress: !args.no_progress,
+            force: args.force,
+            reporter: default_reporter(),
+        })
+    }
+}
+
+/// What 'extract' writes out of the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExtractWhat {
+    /// The whole image.
+    All,
+    /// These bytes of it (--range).
+    Range(ByteRange),
+    /// Its partition with this number, from 1 (--partition).
+    Partition(usize),
+}
+
+/// A validated configuration for the 'extract' command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractConfig {
+    pub archive: String,
+    pub output: String,
+    pub what: ExtractWhat,
+    pub show_progress: bool,
+    /// Write to a drive that holds the running system or has mounted
+    /// filesystems (see core/safety.rs).
+    pub force: bool,
+    /// Receives the messages.
+    #[serde(skip, default = "default_reporter")]
+    pub reporter: Arc<dyn Reporter>,
+}
+
+impl ExtractConfig {
+    /// Creates an `ExtractConfig` from the raw command-line arguments.
+    pub fn from_args(args: ExtractArgs) -> RddResult<Self> {
+        let what = match (&args.range, args.partition) {
+            (Some(range), _) => {
+                let parsed = range
+                    .split_once(':')
+                    .map(|(offset, length)| Ok::<_, RddError>((parse_size(offset)? as u64, parse_size(length)? as u64)));
+                match parsed {
+                    Some(Ok((offset, length))) if length > 0 => ExtractWhat::Range(ByteRange { offset, length }),
+                    Some(Ok(_)) => return Err(RddError::Config("The --range length cannot be zero.".to_string())),
+                    Some(Err(e)) => return Err(e),
+                    None => {
+                        return Err(RddError::Config(format!(
+                            "Invalid --range '{}': use OFFSET:LENGTH, e.g. 1G:512M.",
+                            range
+                        )));
+                    }
+                }
+            }
+            (None, Some(0)) => return Err(RddError::Config("Partitions are numbered from 1.".to_string())),
+            (None, Some(number)) => ExtractWhat::Partition(number),
+            (None, None) => ExtractWhat::All,
+        };
+        if args.archive == STDIO_PATH {
+            return Err(RddError::Config("extract reads the archive at random, so it can't be stdin.".to_string()));
+        }
+        Ok(ExtractConfig {
+            archive: args.archive,
+            output: args.output,
+            what,
+            show_progress: !args.no_progress,
+            force: args.force,
+            reporter: default_reporter(),
         })
     }
 }
 
+/// A configuration for the 'top' command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopConfig {
+    /// Print the running copies once instead of drawing them until Ctrl-C.
+    pub once: bool,
+    /// Receives the list with --once.
+    #[serde(skip, default = "default_reporter")]
+    pub reporter: Arc<dyn Reporter>,
+}
+
+impl TopConfig {
+    /// Creates a `TopConfig` from the raw command-line arguments.
+    pub fn from_args(args: TopArgs) -> Self {
+        TopConfig {
+            once: args.once,
+            reporter: default_reporter(),
+        }
+    }
+}
+
+/// The error for an option whose cargo `feature` this build was made without.
+fn feature_disabled(what: &str, feature: &str) -> RddError {
+    RddError::FeatureDisabled {
+        what: what.to_string(),
+        feature: feature.to_string(),
+    }
+}
+
+fn default_max_io() -> usize {
+    DEFAULT_MAX_IO
+}
+
+fn default_max_in_flight() -> usize {
+    DEFAULT_MAX_IN_FLIGHT
+}
+
+/// Parses a byte offset or length: a size like --bs takes (e.g. 446 or
+/// 1M), or a hexadecimal number (e.g. 0x1BE).
+fn parse_byte_offset(s: &str) -> RddResult<u64> {
+    let s = s.trim();
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16)
+            .map_err(|_| RddError::Config(format!("Invalid hexadecimal value: '{}'", s))),
+        None => Ok(parse_size(s)? as u64),
+    }
+}
+
+fn default_sync_dir() -> bool {
+    true
+}
+
+fn default_truncate() -> bool {
+    true
+}
+
+fn default_fast_path() -> bool {
+    true
+}
+
+fn default_retry_delay() -> Duration {
+    DEFAULT_RETRY_DELAY
+}
+
 /// Parses a size string (e.g., "512k", "1M", "2G") into a number of bytes.
 ///
 /// This function is case-insensitive and supports standard suffixes.
-fn parse_size(s: &str) -> RddResult<usize> {
+pub(crate) fn parse_size(s: &str) -> RddResult<usize> {
     let s_trimmed = s.trim();
     if s_trimmed.is_empty() {
         return Err(RddError::Config("Size string cannot be empty.".to_string()));
@@ -112,3 +2505,45 @@ fn parse_size(s: &str) -> RddResult<usize> {
         ))
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn serialized() -> serde_json::Value {
+        let config = CopyConfig::builder("in.img", "out.img").block_size(4096).build().unwrap();
+        serde_json::to_value(&config).unwrap()
+    }
+
+    #[test]
+    fn a_serialized_config_reads_back() {
+        let config: CopyConfig = serde_json::from_value(serialized()).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.block_size, 4096);
+        assert_eq!(config.input_file, "in.img");
+    }
+
+    #[test]
+    fn a_deserialized_config_is_validated() {
+        for (field, value) in [("block_size", 0), ("max_io", 0), ("threads", 0)] {
+            let mut document = serialized();
+            document[field] = value.into();
+            assert!(serde_json::from_value::<CopyConfig>(document).is_err(), "{} of {}", field, value);
+        }
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn low_memory_hashes_with_one_algorithm() {
+        let builder = CopyConfig::builder("in.img", "out.img").low_memory(true).verify(HashAlgorithm::Sha256);
+        assert!(builder.clone().build().is_ok());
+        assert!(builder.verify(HashAlgorithm::Blake3).build().is_err());
+    }
+
+    #[test]
+    fn a_newer_format_is_refused() {
+        let mut document = serialized();
+        document["version"] = (CONFIG_VERSION + 1).into();
+        assert!(serde_json::from_value::<CopyConfig>(document).is_err());
+    }
+}
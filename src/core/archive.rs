@@ -0,0 +1,443 @@
+// This file reads disk images stored in containers that can be read at random: seekable zstd (written by --compress zstd --seekable, see seekable.rs) and qcow2, the format of QEMU and most virtual machine tools. Getting one partition or a few megabytes out of a plain compressed image means decompressing everything in front of them; these containers say where each piece of the image is stored, so only the pieces a request covers are read.
+
+// Explanation of this file:
+// Detecting : Archive::open recognizes a qcow2 image by the "QFI\xfb" magic at its start and seekable zstd by the seek table's magic at its end (see SeekTable::read). Anything else isn't an archive and gives None.
+// Seekable zstd : The seek table gives every frame's compressed and decompressed size, so the frame holding an offset is found by adding them up. A frame is decompressed whole, by the zstd program, and the last one is kept so reads that follow each other don't decompress it again.
+// qcow2 : The image is cut into clusters. A two-level table (L1, then L2) maps each cluster of the disk to where it's stored in the file; a cluster that isn't stored, or is marked as reading as zeros, is zeros. The last L2 table used is kept. An L1 table bigger than the disk needs, or than the file, is refused as damaged before anything is allocated for it. Compressed clusters (qemu-img convert -c), encryption, backing files and external data files would need more than this and are refused with an error that says so.
+// Units : Frames and clusters are both "units": read_at never reads past the end of the unit an offset is in, and units_read counts the different units a request touched, which 'rdd extract' reports against the total.
+// Partitions : partitions reads the MBR, or the GPT behind a protective MBR, through the archive, and returns the byte range of every partition, numbered like Linux numbers them (sda1, sda2, ...). The GPT's sector numbers come straight from the image, so they're multiplied with overflow checks; an entry that doesn't fit is left out like an empty slot.
+
+// src/core/archive.rs
+
+use crate::core::ranges::ByteRange;
+use crate::core::seekable::{decompress_frame, SeekTable};
+use crate::error::{IoResultExt, RddError, RddResult};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+/// The magic bytes a qcow2 image starts with.
+const QCOW2_MAGIC: [u8; 4] = *b"QFI\xfb";
+
+/// The offset bits of qcow2's L1 and L2 entries.
+const QCOW2_OFFSET_MASK: u64 = 0x00FF_FFFF_FFFF_FE00;
+
+/// An L2 entry's flag for a compressed cluster.
+const QCOW2_COMPRESSED: u64 = 1 << 62;
+
+/// An L2 entry's flag for a cluster that reads as zeros (qcow2 version 3).
+const QCOW2_ZERO: u64 = 1;
+
+/// The qcow2 features this reader can't handle: a corrupt image, an
+/// external data file and extended L2 entries.
+const QCOW2_UNSUPPORTED_FEATURES: u64 = (1 << 1) | (1 << 2) | (1 << 4);
+
+/// The sector size partition tables are read with.
+const SECTOR: u64 = 512;
+
+/// The GPT entries we look at.
+const MAX_GPT_ENTRIES: u64 = 128;
+
+/// A disk image in a container that can be read at random.
+pub struct Archive {
+    file: File,
+    path: String,
+    kind: Kind,
+    /// The unit the last read was in, to count the different ones.
+    last_unit: Option<u64>,
+    units_read: u64,
+}
+
+enum Kind {
+    SeekableZstd {
+        table: SeekTable,
+        /// Where each frame starts in the file and in the image.
+        starts: Vec<(u64, u64)>,
+        /// The last frame decompressed, by index.
+        cached: Option<(usize, Vec<u8>)>,
+    },
+    Qcow2 {
+        cluster_bits: u32,
+        size: u64,
+        l1: Vec<u64>,
+        /// The last L2 table read, by its offset in the file.
+        cached: Option<(u64, Vec<u64>)>,
+    },
+}
+
+impl Archive {
+    /// Opens `path` as an archive. None if it's neither seekable zstd nor
+    /// qcow2.
+    pub fn open(path: &str) -> RddResult<Option<Self>> {
+        let mut file = File::open(path).with_path(path)?;
+        let mut magic = [0u8; 4];
+        let is_qcow2 = file.read_exact(&mut magic).is_ok() && magic == QCOW2_MAGIC;
+        let kind = match is_qcow2 {
+            true => open_qcow2(&mut file, path)?,
+            false => match SeekTable::read(&mut file, path)? {
+                Some(table) => {
+                    let mut starts = Vec::with_capacity(table.frames.len());
+                    let (mut stored, mut image) = (0, 0);
+                    for frame in &table.frames {
+                        starts.push((stored, image));
+                        stored += u64::from(frame.compressed);
+                        image += u64::from(frame.decompressed);
+                    }
+                    Kind::SeekableZstd {
+                        table,
+                        starts,
+                        cached: None,
+                    }
+                }
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(Archive {
+            file,
+            path: path.to_string(),
+            kind,
+            last_unit: None,
+            units_read: 0,
+        }))
+    }
+
+    /// The name of the container format.
+    pub fn format(&self) -> &'static str {
+        match self.kind {
+            Kind::SeekableZstd { .. } => "seekable zstd",
+            Kind::Qcow2 { .. } => "qcow2",
+        }
+    }
+
+    /// What the image is stored in units of: "frames" or "clusters".
+    pub fn unit_name(&self) -> &'static str {
+        match self.kind {
+            Kind::SeekableZstd { .. } => "frames",
+            Kind::Qcow2 { .. } => "clusters",
+        }
+    }
+
+    /// The size of the image in the archive.
+    pub fn len(&self) -> u64 {
+        match &self.kind {
+            Kind::SeekableZstd { table, .. } => table.decompressed_len(),
+            Kind::Qcow2 { size, .. } => *size,
+        }
+    }
+
+    /// Whether the image is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How many units the image is stored in.
+    pub fn units(&self) -> u64 {
+        match &self.kind {
+            Kind::SeekableZstd { table, .. } => table.frames.len() as u64,
+            Kind::Qcow2 { cluster_bits, size, .. } => size.div_ceil(1 << cluster_bits),
+        }
+    }
+
+    /// How many different units the reads so far touched.
+    pub fn units_read(&self) -> u64 {
+        self.units_read
+    }
+
+    /// Reads the image at `offset` into `buf`, up to the end of the unit
+    /// `offset` is in. Returns how much was read, 0 at the end of the image.
+    pub fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> RddResult<usize> {
+        if offset >= self.len() || buf.is_empty() {
+            return Ok(0);
+        }
+        let path = self.path.as_str();
+        let (unit, n) = match &mut self.kind {
+            Kind::SeekableZstd { table, starts, cached } => {
+                // The last frame that starts at or before the offset.
+                let index = starts.partition_point(|&(_, image)| image <= offset) - 1;
+                let (stored, image) = starts[index];
+                if cached.as_ref().is_none_or(|(cached, _)| *cached != index) {
+                    let mut data = vec![0u8; table.frames[index].compressed as usize];
+                    self.file.seek(SeekFrom::Start(stored)).with_path(path)?;
+                    self.file.read_exact(&mut data).at_offset(path, stored)?;
+                    let frame = decompress_frame(data)?;
+                    if frame.len() != table.frames[index].decompressed as usize {
+                        return Err(RddError::Config(format!(
+                            "Frame {} of '{}' decompressed to {} bytes, but the seek table says {}.",
+                            index,
+                            path,
+                            frame.len(),
+                            table.frames[index].decompressed
+                        )));
+                    }
+                    *cached = Some((index, frame));
+                }
+                let frame = cached.as_ref().map_or(&[][..], |(_, frame)| frame.as_slice());
+                let from = (offset - image) as usize;
+                let n = buf.len().min(frame.len() - from);
+                buf[..n].copy_from_slice(&frame[from..from + n]);
+                (index as u64, n)
+            }
+            Kind::Qcow2 {
+                cluster_bits,
+                size,
+                l1,
+                cached,
+            } => {
+                let cluster_size = 1u64 << *cluster_bits;
+                let l2_bits = *cluster_bits - 3;
+                let cluster = offset >> *cluster_bits;
+                let within = offset & (cluster_size - 1);
+                let n = (buf.len() as u64).min(cluster_size - within).min(*size - offset) as usize;
+
+                let l1_entry = l1.get((cluster >> l2_bits) as usize).copied().unwrap_or(0);
+                let l2_offset = l1_entry & QCOW2_OFFSET_MASK;
+                let entry = match l2_offset {
+                    0 => 0,
+                    _ => {
+                        if cached.as_ref().is_none_or(|(cached, _)| *cached != l2_offset) {
+                            let mut table = vec![0u8; cluster_size as usize];
+                            self.file.seek(SeekFrom::Start(l2_offset)).with_path(path)?;
+                            self.file.read_exact(&mut table).at_offset(path, l2_offset)?;
+                            let entries = table.chunks_exact(8).map(|e| u64::from_be_bytes(e.try_into().unwrap())).collect();
+                            *cached = Some((l2_offset, entries));
+                        }
+                        let index = (cluster & ((1 << l2_bits) - 1)) as usize;
+                        cached.as_ref().map_or(0, |(_, entries)| entries[index])
+                    }
+                };
+                if entry & QCOW2_COMPRESSED != 0 {
+                    return Err(RddError::Config(format!(
+                        "'{}' has compressed clusters, which rdd can't read. Convert it with 'qemu-img convert -O qcow2' (without -c) first.",
+                        path
+                    )));
+                }
+                let stored = entry & QCOW2_OFFSET_MASK;
+                if stored == 0 || entry & QCOW2_ZERO != 0 {
+                    buf[..n].fill(0);
+                } else {
+                    let at = stored + within;
+                    self.file.seek(SeekFrom::Start(at)).with_path(path)?;
+                    self.file.read_exact(&mut buf[..n]).at_offset(path, at)?;
+                }
+                (cluster, n)
+            }
+        };
+        if self.last_unit != Some(unit) {
+            self.last_unit = Some(unit);
+            self.units_read += 1;
+        }
+        Ok(n)
+    }
+
+    /// Reads `len` bytes of the image at `offset` (fewer at its end).
+    fn read_exact_at(&mut self, mut offset: u64, len: usize) -> RddResult<Vec<u8>> {
+        let mut out = vec![0u8; len];
+        let mut filled = 0;
+        while filled < len {
+            match self.read_at(offset, &mut out[filled..])? {
+                0 => break,
+                n => {
+                    filled += n;
+                    offset += n as u64;
+                }
+            }
+        }
+        out.truncate(filled);
+        Ok(out)
+    }
+
+    /// The partitions of the image, numbered from 1 in the order of its
+    /// partition table: the MBR's four slots, or the GPT's entries.
+    /// Empty slots keep their number. Empty if there's no partition table.
+    pub fn partitions(&mut self) -> RddResult<Vec<Option<ByteRange>>> {
+        let mbr = self.read_exact_at(0, SECTOR as usize)?;
+        if mbr.len() < SECTOR as usize || u16::from_le_bytes([mbr[510], mbr[511]]) != 0xAA55 {
+            return Ok(Vec::new());
+        }
+        let u32_at = |data: &[u8], off: usize| u32::from_le_bytes(data[off..off + 4].try_into().unwrap());
+        let u64_at = |data: &[u8], off: usize| u64::from_le_bytes(data[off..off + 8].try_into().unwrap());
+
+        if mbr[446..510].chunks_exact(16).any(|entry| entry[4] == 0xEE) {
+            let header = self.read_exact_at(SECTOR, SECTOR as usize)?;
+            if header.len() < 92 || &header[0..8] != b"EFI PART" {
+                return Ok(Vec::new());
+            }
+            let entries = u64_at(&header, 72);
+            let count = u64::from(u32_at(&header, 80)).min(MAX_GPT_ENTRIES);
+            let size = u64::from(u32_at(&header, 84));
+            if !(48..=4096).contains(&size) {
+                return Ok(Vec::new());
+            }
+            let Some(at) = entries.checked_mul(SECTOR) else {
+                return Ok(Vec::new());
+            };
+            let table = self.read_exact_at(at, (count * size) as usize)?;
+            let mut partitions: Vec<_> = table
+                .chunks_exact(size as usize)
+                .map(|entry| {
+                    let (first, last) = (u64_at(entry, 32), u64_at(entry, 40));
+                    if entry[..16].iter().all(|&b| b == 0) || last < first {
+                        return None;
+                    }
+                    // An entry whose sectors are past what a byte offset
+                    // can hold is skipped like an empty one.
+                    let offset = first.checked_mul(SECTOR)?;
+                    let length = (last - first).checked_add(1)?.checked_mul(SECTOR)?;
+                    offset.checked_add(length)?;
+                    Some(ByteRange { offset, length })
+                })
+                .collect();
+            while partitions.last().is_some_and(Option::is_none) {
+                partitions.pop();
+            }
+            return Ok(partitions);
+        }
+
+        Ok(mbr[446..510]
+            .chunks_exact(16)
+            .map(|entry| {
+                let (start, sectors) = (u64::from(u32_at(entry, 8)), u64::from(u32_at(entry, 12)));
+                (entry[4] != 0 && sectors > 0).then(|| ByteRange {
+                    offset: start * SECTOR,
+                    length: sectors * SECTOR,
+                })
+            })
+            .collect())
+    }
+}
+
+/// Reads the header and L1 table of the qcow2 image in `file`.
+fn open_qcow2(file: &mut File, path: &str) -> RddResult<Kind> {
+    let mut header = [0u8; 104];
+    file.seek(SeekFrom::Start(0)).with_path(path)?;
+    let read = file.read(&mut header).with_path(path)?;
+    let u32_at = |off: usize| u32::from_be_bytes(header[off..off + 4].try_into().unwrap());
+    let u64_at = |off: usize| u64::from_be_bytes(header[off..off + 8].try_into().unwrap());
+    let unsupported = |why: &str| RddError::Config(format!("'{}' is a qcow2 image {}, which rdd can't read.", path, why));
+
+    let version = u32_at(4);
+    if read < 72 || !(2..=3).contains(&version) {
+        return Err(unsupported(&format!("of version {}", version)));
+    }
+    if u64_at(8) != 0 {
+        return Err(unsupported("with a backing file"));
+    }
+    if u32_at(32) != 0 {
+        return Err(unsupported("that is encrypted"));
+    }
+    if version == 3 && read >= 80 && u64_at(72) & QCOW2_UNSUPPORTED_FEATURES != 0 {
+        return Err(unsupported("marked corrupt or with features like an external data file"));
+    }
+    let cluster_bits = u32_at(20);
+    if !(9..=21).contains(&cluster_bits) {
+        return Err(unsupported(&format!("with clusters of 2^{} bytes", cluster_bits)));
+    }
+
+    // Every L1 entry maps one L2 table's worth of clusters. The table is
+    // read whole, so one that's longer than the disk needs, or runs past
+    // the end of the file, is a damaged header rather than a reason to
+    // allocate gigabytes.
+    let (size, l1_size, l1_offset) = (u64_at(24), u64::from(u32_at(36)), u64_at(40));
+    let l2_bits = cluster_bits - 3;
+    let needed = size.div_ceil(1 << (cluster_bits + l2_bits));
+    let file_len = file.metadata().with_path(path)?.len();
+    let l1_end = l1_size.checked_mul(8).and_then(|len| len.checked_add(l1_offset));
+    if l1_size > needed || l1_end.is_none_or(|end| end > file_len) {
+        return Err(RddError::Config(format!(
+            "'{}' is a damaged qcow2 image: its L1 table of {} entries at offset {} doesn't fit a {}-byte disk in a {}-byte file.",
+            path, l1_size, l1_offset, size, file_len
+        )));
+    }
+    let mut table = vec![0u8; (l1_size * 8) as usize];
+    file.seek(SeekFrom::Start(l1_offset)).with_path(path)?;
+    file.read_exact(&mut table).at_offset(path, l1_offset)?;
+    let l1 = table.chunks_exact(8).map(|e| u64::from_be_bytes(e.try_into().unwrap())).collect();
+    Ok(Kind::Qcow2 {
+        cluster_bits,
+        size,
+        l1,
+        cached: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A qcow2 image with 64 KiB clusters of a `size`-byte disk, whose L1
+    /// table of `l1_size` entries is at 64 KiB and maps the first cluster
+    /// to `data`.
+    fn qcow2(name: &str, size: u64, l1_size: u32, data: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("rdd-archive-test-{}-{}", std::process::id(), name));
+        let mut image = vec![0u8; 4 << 16];
+        image[0..4].copy_from_slice(&QCOW2_MAGIC);
+        image[4..8].copy_from_slice(&3u32.to_be_bytes());
+        image[20..24].copy_from_slice(&16u32.to_be_bytes());
+        image[24..32].copy_from_slice(&size.to_be_bytes());
+        image[36..40].copy_from_slice(&l1_size.to_be_bytes());
+        image[40..48].copy_from_slice(&(1u64 << 16).to_be_bytes());
+        image[1 << 16..(1 << 16) + 8].copy_from_slice(&(2u64 << 16).to_be_bytes());
+        image[2 << 16..(2 << 16) + 8].copy_from_slice(&(3u64 << 16).to_be_bytes());
+        image[3 << 16..(3 << 16) + data.len()].copy_from_slice(data);
+        std::fs::write(&path, image).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    /// A GPT disk whose entries start at LBA `entries` and span the
+    /// sectors in `spans`.
+    fn gpt(entries: u64, spans: &[(u64, u64)]) -> Vec<u8> {
+        let mut disk = vec![0u8; 4096];
+        disk[446 + 4] = 0xEE;
+        disk[510..512].copy_from_slice(&[0x55, 0xAA]);
+        disk[512..520].copy_from_slice(b"EFI PART");
+        disk[512 + 72..512 + 80].copy_from_slice(&entries.to_le_bytes());
+        disk[512 + 80..512 + 84].copy_from_slice(&(spans.len() as u32).to_le_bytes());
+        disk[512 + 84..512 + 88].copy_from_slice(&128u32.to_le_bytes());
+        for (i, &(first, last)) in spans.iter().enumerate() {
+            let entry = 1024 + i * 128;
+            disk[entry] = 1;
+            disk[entry + 32..entry + 40].copy_from_slice(&first.to_le_bytes());
+            disk[entry + 40..entry + 48].copy_from_slice(&last.to_le_bytes());
+        }
+        disk
+    }
+
+    #[test]
+    fn an_l1_table_bigger_than_the_disk_or_the_file_is_refused() {
+        let cases = [("disk", 1 << 20, 2), ("file", u64::MAX, u32::MAX), ("fits", 1 << 20, 1)];
+        let opened: Vec<_> = cases
+            .iter()
+            .map(|&(name, size, l1_size)| {
+                let path = qcow2(name, size, l1_size, &[]);
+                let opened = Archive::open(&path);
+                std::fs::remove_file(&path).unwrap();
+                opened
+            })
+            .collect();
+
+        assert!(matches!(opened[0], Err(RddError::Config(_))));
+        assert!(matches!(opened[1], Err(RddError::Config(_))));
+        assert!(matches!(opened[2], Ok(Some(_))));
+    }
+
+    #[test]
+    fn gpt_entries_that_overflow_are_skipped() {
+        let spans = [(2048, 4095), (u64::MAX / 256, u64::MAX / 256), (0, u64::MAX), (4096, 8191)];
+        let path = qcow2("gpt", 1 << 20, 1, &gpt(2, &spans));
+        let partitions = Archive::open(&path).unwrap().unwrap().partitions().unwrap();
+        let far = qcow2("far", 1 << 20, 1, &gpt(u64::MAX / 256, &spans));
+        let unreadable = Archive::open(&far).unwrap().unwrap().partitions().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&far).unwrap();
+
+        assert_eq!(
+            partitions,
+            [
+                Some(ByteRange { offset: 2048 * 512, length: 2048 * 512 }),
+                None,
+                None,
+                Some(ByteRange { offset: 4096 * 512, length: 4096 * 512 }),
+            ]
+        );
+        assert!(unreadable.is_empty());
+    }
+}
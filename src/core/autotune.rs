@@ -0,0 +1,199 @@
+// This file implements --bs auto, which lets the copy pick its own block size. The best block size depends on both devices and everything between them, and a poor one can make a copy several times slower than it has to be; 'rdd benchmark' measures it up front, but only for one device at a time and at the cost of a separate run. With --bs auto, the single-threaded copy starts with the usual block size, tries bigger and smaller ones during its first seconds while it copies anyway, and settles on the fastest.
+
+// Explanation of this file:
+// Trials : Each block size gets a trial of at least TRIAL_TIME and TRIAL_BLOCKS blocks, and its rate is the bytes copied in it over the time it took. The first trial is the starting size (the default, or the block size of a --target-profile).
+// Climbing : From the starting size, the tuner tries the doubled size, and keeps doubling as long as each step is faster by more than MARGIN; if the first step up isn't, it tries halving instead, the same way. When neither direction helps any more, the fastest size measured is locked in. The sizes stay between MIN_BLOCK_SIZE and MAX_BLOCK_SIZE, and within --max-io, so the buffers are allocated once, at the largest size the tuner may pick (largest_block_size).
+// Time limit : Tuning stops after TUNING_TIME even if it hasn't settled, with the fastest size so far; a copy that ends earlier keeps what it had. Either way the copy carries on at that size to the end.
+// Changing sizes : The copy asks the tuner at the end of every block, so a block is never split between two sizes. Only the lengths of blocks change; where the data goes doesn't, which is why build() (see config.rs) refuses --bs auto together with options that count in blocks.
+// The result : TuningStats holds the chosen size and the rate of every size tried, which the summary reports.
+
+// src/core/autotune.rs
+
+use crate::config::CopyConfig;
+use indicatif::BinaryBytes;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// The smallest block size the tuner tries.
+pub const MIN_BLOCK_SIZE: usize = 64 << 10;
+
+/// The largest block size the tuner tries.
+pub const MAX_BLOCK_SIZE: usize = 16 << 20;
+
+/// How long each block size is measured, at least.
+const TRIAL_TIME: Duration = Duration::from_millis(500);
+
+/// How many blocks each block size is measured over, at least.
+const TRIAL_BLOCKS: u64 = 4;
+
+/// How much faster a step has to be to count as faster.
+const MARGIN: f64 = 0.05;
+
+/// How long the tuner may take before it settles on the best size so far.
+const TUNING_TIME: Duration = Duration::from_secs(10);
+
+/// The largest block size --bs auto may pick for `config`, which the copy
+/// allocates its buffers for.
+pub fn largest_block_size(config: &CopyConfig) -> usize {
+    MAX_BLOCK_SIZE.min(config.max_io).max(config.block_size)
+}
+
+/// Which way the tuner is going from the starting size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Up,
+    Down,
+}
+
+/// What --bs auto chose, and the rates it measured on the way.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TuningStats {
+    /// The block size the copy settled on.
+    pub block_size: usize,
+    /// Every block size tried, in order, with its rate in bytes per second.
+    pub trials: Vec<(usize, f64)>,
+    /// Whether the tuner settled before the copy ended.
+    pub settled: bool,
+}
+
+impl TuningStats {
+    /// One line for the summary, e.g. "--bs auto chose 4.00 MiB (...)".
+    pub fn summary(&self) -> String {
+        let trials: Vec<_> = self
+            .trials
+            .iter()
+            .map(|&(size, rate)| format!("{}: {}/s", BinaryBytes(size as u64), BinaryBytes(rate as u64)))
+            .collect();
+        let tried = match trials.is_empty() {
+            true => "the copy ended before a block size could be measured".to_string(),
+            false => format!("measured {}", trials.join(", ")),
+        };
+        let unsettled = match self.settled || self.trials.is_empty() {
+            true => "",
+            false => "; the copy ended before tuning did",
+        };
+        format!(
+            "--bs auto chose a block size of {} ({}{})",
+            BinaryBytes(self.block_size as u64),
+            tried,
+            unsettled
+        )
+    }
+}
+
+/// Picks the block size of a copy with --bs auto while it runs.
+#[derive(Debug)]
+pub struct BlockSizeTuner {
+    start: usize,
+    min: usize,
+    max: usize,
+    current: usize,
+    direction: Direction,
+    started: Instant,
+    trial_started: Instant,
+    trial_bytes: u64,
+    trial_blocks: u64,
+    stats: TuningStats,
+}
+
+impl BlockSizeTuner {
+    /// A tuner that starts at the configured block size.
+    pub fn new(config: &CopyConfig) -> Self {
+        let max = largest_block_size(config);
+        let start = config.block_size;
+        let now = Instant::now();
+        BlockSizeTuner {
+            start,
+            min: MIN_BLOCK_SIZE.min(start),
+            max,
+            current: start,
+            direction: Direction::Up,
+            started: now,
+            trial_started: now,
+            trial_bytes: 0,
+            trial_blocks: 0,
+            stats: TuningStats {
+                block_size: start,
+                ..TuningStats::default()
+            },
+        }
+    }
+
+    /// The block size to copy the next block with.
+    pub fn block_size(&self) -> usize {
+        self.current
+    }
+
+    /// Counts a block of `bytes` that was just copied. Returns the block size
+    /// to copy the next block with, if it changes.
+    pub fn block_done(&mut self, bytes: u64) -> Option<usize> {
+        if self.stats.settled {
+            return None;
+        }
+        self.trial_bytes += bytes;
+        self.trial_blocks += 1;
+        let elapsed = self.trial_started.elapsed();
+        if elapsed < TRIAL_TIME || self.trial_blocks < TRIAL_BLOCKS {
+            return None;
+        }
+
+        let rate = self.trial_bytes as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        let best_before = self.best().map(|(_, rate)| rate);
+        self.stats.trials.push((self.current, rate));
+        let faster = best_before.is_none_or(|best| rate > best * (1.0 + MARGIN));
+
+        let before = self.current;
+        let next = match self.started.elapsed() >= TUNING_TIME {
+            true => None,
+            false if faster => self.step(self.direction).or_else(|| self.turn()),
+            false => self.turn(),
+        };
+        match next {
+            Some(size) => self.current = size,
+            None => {
+                self.stats.settled = true;
+                self.current = self.best().map_or(self.current, |(size, _)| size);
+            }
+        }
+        self.stats.block_size = self.current;
+        self.trial_started = Instant::now();
+        self.trial_bytes = 0;
+        self.trial_blocks = 0;
+        (self.current != before).then_some(self.current)
+    }
+
+    /// Going up from the starting size didn't help: goes down from it
+    /// instead. None if that was done already, or going up did help.
+    fn turn(&mut self) -> Option<usize> {
+        if self.direction == Direction::Down || self.best().is_some_and(|(size, _)| size != self.start) {
+            return None;
+        }
+        self.direction = Direction::Down;
+        self.current = self.start;
+        self.step(Direction::Down)
+    }
+
+    /// The size one step from the current one, if it's within the limits
+    /// and hasn't been tried.
+    fn step(&self, direction: Direction) -> Option<usize> {
+        let size = match direction {
+            Direction::Up => self.current.checked_mul(2)?,
+            Direction::Down => self.current / 2,
+        };
+        let tried = self.stats.trials.iter().any(|&(tried, _)| tried == size);
+        (size >= self.min && size <= self.max && !tried).then_some(size)
+    }
+
+    /// The fastest size measured so far, with its rate.
+    fn best(&self) -> Option<(usize, f64)> {
+        self.stats.trials.iter().copied().reduce(|a, b| if b.1 > a.1 { b } else { a })
+    }
+
+    /// What the tuner chose: the fastest size so far if it hadn't settled.
+    pub fn finish(mut self) -> TuningStats {
+        if !self.stats.settled {
+            self.stats.block_size = self.best().map_or(self.current, |(size, _)| size);
+        }
+        self.stats
+    }
+}
@@ -0,0 +1,152 @@
+// This file writes the bandwidth log (--bandwidth-log), a CSV file with one line per second of the copy: when it was taken, the bytes read and written so far, and the read and write rates over the second before. The summary only gives the average rate of the whole copy, which hides how a device behaved along the way: a drive that slows down once it gets hot (thermal throttling), or a flash drive that writes fast until its cache is full and then crawls. Graphed, the log shows both at a glance.
+
+// Explanation of this file:
+// Meter : Counts the bytes read from the input and written to the output. Every engine keeps one and counts each chunk in it (the parallel writers share the one of their copy); it's only two atomic counters, so it's kept whether there's a log or not.
+// BandwidthLog : Started by each engine before the transfer. With --bandwidth-log it creates the file and starts a thread that samples the Meter every LOG_INTERVAL and appends a line. finish() (or dropping it, when the copy fails) stops the thread after one last line, so the log covers the copy up to its end; the final sync and the verification that follow aren't part of it.
+// The columns : unix_time (seconds, with milliseconds), bytes_read, bytes_written, read_rate and write_rate (bytes per second over the interval before). Reads run ahead of writes by what's in flight in the parallel and io_uring engines, and writes the coalescer gathers count when they're handed to it. Each line is flushed as it's written, so the log can be watched while the copy runs.
+// Errors : A log that can't be created fails the copy before it starts. A line that can't be written later ends the log with a warning, but not the copy.
+
+// src/core/bandwidth.rs
+
+use crate::config::CopyConfig;
+use crate::error::{IoResultExt, RddResult};
+use crate::utils::diagnostics::Diagnostic;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How often a line is added to the log.
+pub const LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The first line of the log.
+const HEADER: &str = "unix_time,bytes_read,bytes_written,read_rate,write_rate\n";
+
+/// The bytes a copy has read and written so far.
+#[derive(Debug, Default)]
+pub struct Meter {
+    read: AtomicU64,
+    written: AtomicU64,
+}
+
+impl Meter {
+    /// Counts `bytes` read from the input.
+    pub fn read(&self, bytes: u64) {
+        self.read.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Counts `bytes` written to the output.
+    pub fn wrote(&self, bytes: u64) {
+        self.written.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn totals(&self) -> (u64, u64) {
+        (self.read.load(Ordering::Relaxed), self.written.load(Ordering::Relaxed))
+    }
+}
+
+/// The bandwidth log of a copy, and the Meter it samples.
+pub struct BandwidthLog {
+    meter: Arc<Meter>,
+    /// Tells the thread to stop, by being sent to or dropped.
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BandwidthLog {
+    /// Starts the log --bandwidth-log asks for, if any. The Meter is there
+    /// either way.
+    pub fn start(config: &CopyConfig) -> RddResult<Self> {
+        let mut log = BandwidthLog {
+            meter: Arc::new(Meter::default()),
+            stop: None,
+            thread: None,
+        };
+        let Some(path) = &config.bandwidth_log else {
+            return Ok(log);
+        };
+
+        let mut file = File::create(path).map(BufWriter::new).with_path(path)?;
+        file.write_all(HEADER.as_bytes()).and_then(|_| file.flush()).with_path(path)?;
+
+        let (stop, stopped) = mpsc::channel();
+        let (meter, reporter, name) = (log.meter.clone(), config.reporter.clone(), path.clone());
+        let thread = thread::Builder::new()
+            .name("rdd-bandwidth".to_string())
+            .spawn(move || {
+                if let Err(e) = sample(&meter, &mut file, &stopped) {
+                    reporter.diagnostic(&Diagnostic::warning(format!(
+                        "the bandwidth log '{}' ends here, since it can't be written: {}",
+                        name, e
+                    )));
+                }
+            })
+            .with_path(path)?;
+        log.stop = Some(stop);
+        log.thread = Some(thread);
+        Ok(log)
+    }
+
+    /// The Meter the engine counts its reads and writes in.
+    pub fn meter(&self) -> Arc<Meter> {
+        self.meter.clone()
+    }
+
+    /// Adds the last line and closes the log.
+    pub fn finish(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for BandwidthLog {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Appends a line to `file` every LOG_INTERVAL, and one more once `stop`
+/// is sent to or dropped.
+fn sample(meter: &Meter, file: &mut impl Write, stop: &Receiver<()>) -> io::Result<()> {
+    let mut last = (meter.totals(), Instant::now());
+    let mut next = Instant::now() + LOG_INTERVAL;
+    loop {
+        let waited = stop.recv_timeout(next.saturating_duration_since(Instant::now()));
+        let done = !matches!(waited, Err(RecvTimeoutError::Timeout));
+        let now = Instant::now();
+        let (read, written) = meter.totals();
+        let ((last_read, last_written), last_at) = last;
+        let seconds = (now - last_at).as_secs_f64().max(f64::EPSILON);
+        let unix_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        writeln!(
+            file,
+            "{:.3},{},{},{:.0},{:.0}",
+            unix_time.as_secs_f64(),
+            read,
+            written,
+            read.saturating_sub(last_read) as f64 / seconds,
+            written.saturating_sub(last_written) as f64 / seconds
+        )?;
+        file.flush()?;
+        if done {
+            return Ok(());
+        }
+        last = ((read, written), now);
+        // Lines stay a second apart, even if writing one took a while.
+        next += LOG_INTERVAL;
+        if next < now {
+            next = now + LOG_INTERVAL;
+        }
+    }
+}
@@ -0,0 +1,319 @@
+// This file implements the 'benchmark' command, which measures how fast a file or device reads and writes sequentially at a range of block sizes. The best --bs differs a lot between devices: a USB stick may want 4 MiB writes where an NVMe drive is already at full speed with 128 KiB, and too small a block size can make a copy several times slower than it has to be. Measuring the actual device takes the guessing out of it, and the command ends by recommending the block size to copy with.
+
+// Explanation of this file:
+// The tests : For every block size, in the order given, the first --bytes of the target are written with random data and synced, then read back. Random data keeps drives that compress or deduplicate from looking faster than they are with real data. Each test is timed from its first block to its last (for writes, to the end of the sync), so the rate is what a copy would see.
+// The page cache : Without --direct, the written data is dropped from the page cache before it's read back (see utils/fadvise.rs), so the reads come from the device. With --direct, the cache is bypassed altogether; the block sizes must then be multiples of the device's sector size, which is checked before anything is written.
+// The target : A device is measured over at most its size, and --read-only over at most what a file holds; block sizes bigger than that are left out with a warning. The write test overwrites the start of the target, so it's checked like the output of a copy (see safety.rs) and main.rs asks before writing to a drive. A file that didn't exist is removed again at the end.
+// The recommendation : A copy runs at the speed of the slower of its reads and writes, so each block size is scored by the harmonic mean of its two rates (with --read-only, by the read rate alone). The smallest block size within RECOMMEND_WITHIN of the best score is recommended, since larger blocks only cost memory once the device is at full speed.
+// The report : A table with a line per block size and the recommendation under it, or one JSON object with --report-format json.
+// Signals : Like a copy, the benchmark checks for SIGUSR1 and Ctrl-C between blocks (see utils/signals.rs).
+
+// src/core/benchmark.rs
+
+use crate::cli::StatsFormat;
+use crate::config::BenchmarkConfig;
+use crate::core::capacity::device_size;
+use crate::core::prng::Prng;
+use crate::core::reporter::progress_bar;
+use crate::core::safety::check_target;
+use crate::core::verify::read_full;
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::diagnostics::Diagnostic;
+use crate::utils::direct::{self, AlignedBuffer};
+use crate::utils::fadvise::advise_dont_need;
+use crate::utils::json::Json;
+use crate::utils::rawdevice;
+use crate::utils::signals;
+use indicatif::{BinaryBytes, ProgressBar};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How close to the best score a smaller block size has to come to be
+/// recommended instead.
+const RECOMMEND_WITHIN: f64 = 0.95;
+
+/// The rates measured at one block size, in bytes per second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockSizeResult {
+    pub block_size: usize,
+    /// Bytes read and written at this block size.
+    pub bytes: u64,
+    pub read_rate: f64,
+    /// None with --read-only.
+    pub write_rate: Option<f64>,
+}
+
+impl BlockSizeResult {
+    /// How fast a copy at this block size would go.
+    fn score(&self) -> f64 {
+        match self.write_rate {
+            Some(write) if write > 0.0 && self.read_rate > 0.0 => 2.0 / (1.0 / self.read_rate + 1.0 / write),
+            Some(_) => 0.0,
+            None => self.read_rate,
+        }
+    }
+}
+
+/// What a finished benchmark measured.
+#[derive(Debug, Clone)]
+pub struct BenchmarkReport {
+    pub target: String,
+    pub direct: bool,
+    pub results: Vec<BlockSizeResult>,
+    /// The block size to copy with.
+    pub recommended: usize,
+}
+
+/// Measures `config.target` at every block size in `config.block_sizes`,
+/// prints the results and returns them.
+pub fn run_benchmark(config: &BenchmarkConfig) -> RddResult<BenchmarkReport> {
+    let path = config.target.as_str();
+    let existed = Path::new(path).exists();
+    if config.read_only && !existed {
+        return Err(RddError::Config(format!("'{}' doesn't exist, so there's nothing to read.", path)));
+    }
+    if !config.read_only {
+        check_target(path, config.force, config.reporter.as_ref())?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.read(true).write(!config.read_only).create(!config.read_only);
+    if config.direct {
+        direct::enable(&mut options);
+    }
+    let raw = rawdevice::raw_device(path);
+    if raw.is_some() {
+        rawdevice::share(&mut options);
+        options.create(false);
+    }
+    let mut file = options.open(path).with_path(path)?;
+    let result = measure_all(config, &mut file, raw);
+    drop(file);
+    if !existed {
+        fs::remove_file(path).with_path(path)?;
+    }
+    let results = result?;
+
+    let Some(best) = results.iter().map(BlockSizeResult::score).reduce(f64::max) else {
+        return Err(RddError::Config(format!(
+            "None of the block sizes fit in '{}'; try smaller ones with --sizes.",
+            path
+        )));
+    };
+    let recommended = results
+        .iter()
+        .filter(|result| result.score() >= best * RECOMMEND_WITHIN)
+        .map(|result| result.block_size)
+        .min()
+        .unwrap_or(results[0].block_size);
+    let report = BenchmarkReport {
+        target: path.to_string(),
+        direct: config.direct,
+        results,
+        recommended,
+    };
+
+    match config.report_format {
+        StatsFormat::Text => config.reporter.data(&table(&report))?,
+        StatsFormat::Json => config.reporter.data(&format!("{:#}\n", report_json(&report)))?,
+    }
+    Ok(report)
+}
+
+/// Runs the tests of every block size that fits in the target.
+fn measure_all(
+    config: &BenchmarkConfig,
+    file: &mut File,
+    raw: Option<rawdevice::RawDevice>,
+) -> RddResult<Vec<BlockSizeResult>> {
+    let path = config.target.as_str();
+    if config.direct {
+        direct::after_open(file).with_path(path)?;
+        let sector = direct::logical_sector_size(file).with_path(path)?;
+        if let Some(size) = config.block_sizes.iter().find(|&&size| size % sector != 0) {
+            return Err(RddError::Config(format!(
+                "With --direct, every block size must be a multiple of the sector size of '{}' ({} bytes), which {} isn't.",
+                path, sector, size
+            )));
+        }
+    }
+    if let Some(device) = raw
+        && !config.read_only
+    {
+        rawdevice::lock_for_writing(file, device).with_path(path)?;
+    }
+
+    // A device can't grow, and reads can't go past the end of a file.
+    let capacity = match device_size(file, path) {
+        Some(size) => Some(size),
+        None if config.read_only => Some(file.metadata().with_path(path)?.len()),
+        None => None,
+    };
+    let limit = capacity.map_or(config.bytes, |capacity| capacity.min(config.bytes));
+
+    // Every test length is a whole number of blocks, at least one.
+    let mut plan = Vec::new();
+    for &block_size in &config.block_sizes {
+        let blocks = (limit / block_size as u64).max(1);
+        let bytes = blocks * block_size as u64;
+        if capacity.is_some_and(|capacity| bytes > capacity) {
+            config.reporter.diagnostic(&Diagnostic::warning(format!(
+                "left out block size {}: '{}' only holds {}.",
+                BinaryBytes(block_size as u64),
+                path,
+                BinaryBytes(capacity.unwrap_or(0))
+            )));
+            continue;
+        }
+        plan.push((block_size, bytes));
+    }
+
+    let passes = if config.read_only { 1 } else { 2 };
+    let total = plan.iter().map(|&(_, bytes)| bytes * passes).sum();
+    let progress = progress_bar(&*config.reporter, Some(total), config.show_progress);
+    let largest = plan.iter().map(|&(block_size, _)| block_size).max().unwrap_or(0);
+    let mut buffer = AlignedBuffer::new(largest);
+    Prng::from_seed(Prng::entropy_seed()).fill(&mut buffer);
+
+    let mut results = Vec::new();
+    for (block_size, bytes) in plan {
+        progress.set_message(format!("bs {}", BinaryBytes(block_size as u64)));
+        let write_rate = match config.read_only {
+            true => None,
+            false => Some(rate(bytes, write_test(config, file, &buffer[..block_size], bytes, &progress)?)),
+        };
+        let read_rate = rate(bytes, read_test(config, file, &mut buffer[..block_size], bytes, &progress)?);
+        results.push(BlockSizeResult {
+            block_size,
+            bytes,
+            read_rate,
+            write_rate,
+        });
+    }
+    progress.finish_and_clear();
+    Ok(results)
+}
+
+/// Writes `block` over the first `bytes` of the target and syncs it.
+/// Returns how long that took.
+fn write_test(config: &BenchmarkConfig, file: &mut File, block: &[u8], bytes: u64, progress: &ProgressBar) -> RddResult<Duration> {
+    let path = config.target.as_str();
+    file.seek(SeekFrom::Start(0)).with_path(path)?;
+    let started = Instant::now();
+    let mut pos = 0;
+    while pos < bytes {
+        signals::check(&*config.reporter, progress)?;
+        file.write_all(block).at_offset(path, pos)?;
+        pos += block.len() as u64;
+        progress.inc(block.len() as u64);
+    }
+    file.sync_all().with_path(path)?;
+    Ok(started.elapsed())
+}
+
+/// Reads the first `bytes` of the target into `block`, a block at a time.
+/// Returns how long that took.
+fn read_test(config: &BenchmarkConfig, file: &mut File, block: &mut [u8], bytes: u64, progress: &ProgressBar) -> RddResult<Duration> {
+    let path = config.target.as_str();
+    file.seek(SeekFrom::Start(0)).with_path(path)?;
+    // Don't let the page cache answer for the device.
+    if !config.direct {
+        advise_dont_need(file, 0, bytes);
+    }
+    let started = Instant::now();
+    let mut pos = 0;
+    while pos < bytes {
+        signals::check(&*config.reporter, progress)?;
+        let got = read_full(file, block).at_offset(path, pos)?;
+        if got == 0 {
+            break;
+        }
+        pos += got as u64;
+        progress.inc(got as u64);
+    }
+    Ok(started.elapsed())
+}
+
+/// Bytes per second.
+fn rate(bytes: u64, took: Duration) -> f64 {
+    bytes as f64 / took.as_secs_f64().max(f64::EPSILON)
+}
+
+/// A block size as --bs takes it, e.g. "4k" or "16M".
+fn bs_arg(block_size: usize) -> String {
+    match block_size {
+        size if size % (1 << 20) == 0 => format!("{}M", size >> 20),
+        size if size % (1 << 10) == 0 => format!("{}k", size >> 10),
+        size => size.to_string(),
+    }
+}
+
+/// The results as a table, with the recommendation under it.
+fn table(report: &BenchmarkReport) -> String {
+    let per_second = |rate: f64| format!("{}/s", BinaryBytes(rate as u64));
+    let rows: Vec<[String; 4]> = report
+        .results
+        .iter()
+        .map(|result| {
+            [
+                bs_arg(result.block_size),
+                per_second(result.read_rate),
+                result.write_rate.map_or_else(|| "-".to_string(), per_second),
+                BinaryBytes(result.bytes).to_string(),
+            ]
+        })
+        .collect();
+    let header = ["BLOCK SIZE", "READ", "WRITE", "TESTED"].map(str::to_string);
+    let mut widths = header.clone().map(|title| title.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out.push_str(&format!(
+        "\nRecommended: --bs {}{} for '{}'.\n",
+        bs_arg(report.recommended),
+        if report.direct { " --direct" } else { "" },
+        report.target
+    ));
+    out
+}
+
+/// The results as JSON.
+fn report_json(report: &BenchmarkReport) -> Json {
+    Json::object([
+        ("target", Json::str(&report.target)),
+        ("direct", Json::Bool(report.direct)),
+        (
+            "results",
+            Json::Array(
+                report
+                    .results
+                    .iter()
+                    .map(|result| {
+                        Json::object([
+                            ("block_size", Json::Int(result.block_size as u64)),
+                            ("bytes", Json::Int(result.bytes)),
+                            ("read_bytes_per_second", Json::Float(result.read_rate)),
+                            ("write_bytes_per_second", Json::or_null(result.write_rate, Json::Float)),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ),
+        ("recommended_block_size", Json::Int(report.recommended as u64)),
+    ])
+}
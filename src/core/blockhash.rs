@@ -0,0 +1,289 @@
+// This file implements --block-hashes and --compare-manifest. A single digest of a whole drive says that something changed, but not where. With --block-hashes FILE, a copy records the hash of every block it reads. Reading the same drive again later with --compare-manifest FILE hashes the blocks again and lists every block whose content no longer matches, which makes rdd a bit-rot scrub for drives that sit in a drawer between checks.
+
+// Explanation of this file:
+// The format : A text file in the style of our map files (see mapfile.rs). '#' starts a comment; "algorithm" and "block_size" lines say how the hashes were made; every other line is "offset length hash" for one block, with the offset in the input written by format_offset, so an offset from the copy summary can be found with a text search.
+// Blocks : Blocks are the logical blocks of the copy, keyed by where they start in the input. A block cut short by an excluded range or a short read is hashed as it is, and excluded ranges aren't hashed at all.
+// Comparing : The comparison is done with the algorithm and block size that are in the file; the copy adopts that block size when --bs isn't given, and refuses a different one. Blocks that differ, in content or length, are collected as ranges of the input and listed in the summary, and the copy then fails with RddError::BlockHashMismatch, after the output has been completely written. Blocks the file has no hash for (e.g. the input grew) are only counted.
+// Both at once : --block-hashes and --compare-manifest can be given together, to check a drive against the last scrub and record today's hashes for the next one.
+// Hash windows : --hash-window SIZE hashes the data in windows of a fixed size instead, like dcfldd's hashwindow, so a forensic image comes with a hash of every N megabytes. WindowHasher is fed by the copy's verifier (see hash.rs), which sees the data in input order in every engine, and writes the same format to --hash-log, with the window size as the block size. A later --compare-manifest run can therefore check the input against the log window by window.
+
+// src/core/blockhash.rs
+
+use crate::cli::HashAlgorithm;
+use crate::core::hash::{algorithm_flag, StreamHasher};
+use crate::core::mapfile::{format_offset, parse_offset};
+use crate::core::ranges::ByteRange;
+use crate::error::{IoResultExt, RddError, RddResult};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+/// The hash of one block of the input.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHash {
+    pub offset: u64,
+    pub length: u64,
+    pub hex: String,
+}
+
+/// The contents of a block hash file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHashes {
+    pub algorithm: HashAlgorithm,
+    pub block_size: usize,
+    pub blocks: Vec<BlockHash>,
+}
+
+impl BlockHashes {
+    /// Reads and parses the block hash file at `path`.
+    pub fn load(path: &str) -> RddResult<Self> {
+        let contents = fs::read_to_string(path).with_path(path)?;
+        Self::parse(path, &contents)
+    }
+
+    /// Parses the contents of a block hash file. `path` is only used in
+    /// error messages.
+    pub fn parse(path: &str, contents: &str) -> RddResult<Self> {
+        let invalid = |line: usize, reason: &str| {
+            RddError::Config(format!("{}:{}: invalid block hash line: {}", path, line, reason))
+        };
+
+        let (mut algorithm, mut block_size) = (None, None);
+        let mut blocks: Vec<BlockHash> = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line_no = i + 1;
+            let fields: Vec<_> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [] => {}
+                [first, ..] if first.starts_with('#') => {}
+                ["algorithm", name] => {
+                    algorithm = Some(
+                        HashAlgorithm::from_str(name, true).map_err(|_| invalid(line_no, "unknown algorithm"))?,
+                    );
+                }
+                ["block_size", size] => {
+                    block_size = Some(
+                        parse_offset(size)
+                            .and_then(|s| usize::try_from(s).ok())
+                            .filter(|&s| s > 0)
+                            .ok_or_else(|| invalid(line_no, "invalid block size"))?,
+                    );
+                }
+                [offset, length, hex] => {
+                    let block = BlockHash {
+                        offset: parse_offset(offset).ok_or_else(|| invalid(line_no, "invalid offset"))?,
+                        length: parse_offset(length).ok_or_else(|| invalid(line_no, "invalid length"))?,
+                        hex: hex.to_ascii_lowercase(),
+                    };
+                    if blocks.last().is_some_and(|prev| block.offset < prev.offset + prev.length) {
+                        return Err(invalid(line_no, "block overlaps the previous one"));
+                    }
+                    blocks.push(block);
+                }
+                _ => return Err(invalid(line_no, "expected 'offset length hash'")),
+            }
+        }
+
+        let (Some(algorithm), Some(block_size)) = (algorithm, block_size) else {
+            return Err(RddError::Config(format!(
+                "{}: not a block hash file (the 'algorithm' or 'block_size' line is missing)",
+                path
+            )));
+        };
+        Ok(Self {
+            algorithm,
+            block_size,
+            blocks,
+        })
+    }
+
+    /// Renders the file; `input` is recorded in the header comment.
+    pub fn render(&self, input: &str) -> String {
+        let mut out = format!(
+            "# Block hashes. Created by rdd version {}\n# Input: {}\n# Check the input against them with: rdd copy ... --compare-manifest <this file>\n",
+            env!("CARGO_PKG_VERSION"),
+            input
+        );
+        out.push_str(&format!("algorithm {}\n", algorithm_flag(self.algorithm)));
+        out.push_str(&format!("block_size {}\n", self.block_size));
+        out.push_str("#   offset      length  hash\n");
+        for block in &self.blocks {
+            out.push_str(&format!(
+                "{}  {}  {}\n",
+                format_offset(block.offset),
+                format_offset(block.length),
+                block.hex
+            ));
+        }
+        out
+    }
+
+    /// Writes the file to `path`, through a temporary file like map files.
+    pub fn write(&self, path: &str, input: &str) -> RddResult<()> {
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, self.render(input)).with_path(&tmp)?;
+        fs::rename(&tmp, path).with_path(path)
+    }
+}
+
+/// How the blocks read compare with a block hash file (--compare-manifest).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlockCompareStats {
+    /// Blocks that had a recorded hash to compare with.
+    pub compared: u64,
+    /// Blocks whose content or length differs, merged into ranges of the input.
+    pub differing: Vec<ByteRange>,
+    /// Number of blocks in `differing`.
+    pub differing_blocks: u64,
+    /// Blocks the file has no hash for.
+    pub unrecorded: u64,
+}
+
+/// Hashes the data of a copy in windows of a fixed size (--hash-window),
+/// whatever the block size and however the data is split into chunks.
+pub struct WindowHasher {
+    size: u64,
+    /// Where the current window starts in the input, and how much it holds.
+    offset: u64,
+    filled: u64,
+    hasher: StreamHasher,
+    hashes: BlockHashes,
+}
+
+impl WindowHasher {
+    /// A hasher for windows of `size` bytes, the first starting at input
+    /// offset `start`.
+    pub fn new(algorithm: HashAlgorithm, size: u64, start: u64) -> Self {
+        Self {
+            size,
+            offset: start,
+            filled: 0,
+            hasher: StreamHasher::new(algorithm),
+            hashes: BlockHashes {
+                algorithm,
+                block_size: size as usize,
+                blocks: Vec::new(),
+            },
+        }
+    }
+
+    /// Adds the next `data` of the input, ending windows as they fill up.
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let take = (self.size - self.filled).min(data.len() as u64) as usize;
+            self.hasher.update(&data[..take]);
+            self.filled += take as u64;
+            data = &data[take..];
+            if self.filled == self.size {
+                self.end_window();
+            }
+        }
+    }
+
+    fn end_window(&mut self) {
+        if self.filled == 0 {
+            return;
+        }
+        let hasher = std::mem::replace(&mut self.hasher, StreamHasher::new(self.hashes.algorithm));
+        self.hashes.blocks.push(BlockHash {
+            offset: self.offset,
+            length: self.filled,
+            hex: hasher.finalize(),
+        });
+        self.offset += self.filled;
+        self.filled = 0;
+    }
+
+    /// The hashes of all windows, the last one possibly short.
+    pub fn finish(mut self) -> BlockHashes {
+        self.end_window();
+        self.hashes
+    }
+}
+
+/// Hashes every block of a copy, to record the hashes, compare them with
+/// earlier ones, or both.
+pub struct BlockHasher {
+    algorithm: HashAlgorithm,
+    /// The block being hashed: where it starts in the input, and its hash so far.
+    current: Option<(u64, u64, StreamHasher)>,
+    recorded: Option<BlockHashes>,
+    /// Recorded hashes by offset, with their lengths.
+    expected: Option<HashMap<u64, (u64, String)>>,
+    compare: BlockCompareStats,
+}
+
+impl BlockHasher {
+    /// A hasher that records with `record` and compares with `expected`.
+    /// Hashes are made with the algorithm of `expected` when there is one.
+    pub fn new(
+        record: bool,
+        expected: Option<&BlockHashes>,
+        algorithm: HashAlgorithm,
+        block_size: usize,
+    ) -> Self {
+        let algorithm = expected.map_or(algorithm, |e| e.algorithm);
+        Self {
+            algorithm,
+            current: None,
+            recorded: record.then(|| BlockHashes {
+                algorithm,
+                block_size,
+                blocks: Vec::new(),
+            }),
+            expected: expected.map(|e| {
+                e.blocks
+                    .iter()
+                    .map(|b| (b.offset, (b.length, b.hex.clone())))
+                    .collect()
+            }),
+            compare: BlockCompareStats::default(),
+        }
+    }
+
+    /// Adds `data`, read at input offset `pos`, to the current block.
+    pub fn update(&mut self, pos: u64, data: &[u8]) {
+        let algorithm = self.algorithm;
+        let (_, length, hasher) = self
+            .current
+            .get_or_insert_with(|| (pos, 0, StreamHasher::new(algorithm)));
+        hasher.update(data);
+        *length += data.len() as u64;
+    }
+
+    /// Finishes the current block, if anything was read into it.
+    pub fn end_block(&mut self) {
+        let Some((offset, length, hasher)) = self.current.take() else {
+            return;
+        };
+        let hex = hasher.finalize();
+
+        if let Some(expected) = &self.expected {
+            match expected.get(&offset) {
+                None => self.compare.unrecorded += 1,
+                Some((expected_length, expected_hex)) => {
+                    self.compare.compared += 1;
+                    if *expected_length != length || *expected_hex != hex {
+                        self.compare.differing_blocks += 1;
+                        match self.compare.differing.last_mut() {
+                            Some(last) if last.end() == offset => last.length += length,
+                            _ => self.compare.differing.push(ByteRange { offset, length }),
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(recorded) = self.recorded.as_mut() {
+            recorded.blocks.push(BlockHash { offset, length, hex });
+        }
+    }
+
+    /// The recorded hashes (with --block-hashes) and the comparison (with
+    /// --compare-manifest).
+    pub fn finish(mut self) -> (Option<BlockHashes>, Option<BlockCompareStats>) {
+        self.end_block();
+        let compare = self.expected.is_some().then_some(self.compare);
+        (self.recorded, compare)
+    }
+}
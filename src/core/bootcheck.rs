@@ -0,0 +1,202 @@
+// This file inspects a freshly written image or device and judges whether it looks bootable. It can't prove a machine will boot from it, but it catches the most common flashing mistakes, like writing a plain ISO that has no boot sector, or copying a partition instead of the whole disk.
+
+// Explanation of this file:
+// BootReport struct: What we found, one flag per check, plus the overall verdict from likely_bootable().
+// The checks (all done on the first 64 KiB of the output):
+    // MBR signature : Bytes 510-511 of the first sector must be 0x55 0xAA. BIOS firmware refuses to boot a disk without it.
+    // Active MBR partition / EFI partition : One of the four MBR partition entries is marked active (0x80), or has type 0xEF (EFI System Partition).
+    // GPT : The "EFI PART" header sits at LBA 1. We try both 512- and 4096-byte sectors. If present, we walk its partition entries looking for the EFI System Partition type GUID.
+    // ISO 9660 / El Torito : An ISO image has the "CD001" volume descriptor at 32 KiB, and a bootable one also has an El Torito boot record right after it. An ISO that also carries an MBR signature is an "isohybrid" image, which is what makes it bootable from a USB stick.
+// The verdict: Bootable from USB means UEFI (an ESP in the GPT or MBR) or BIOS (an MBR signature plus an active partition or an isohybrid image).
+
+// src/core/bootcheck.rs
+
+use crate::error::{IoResultExt, RddResult};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+
+/// How much of the start of the output we read for the checks.
+const INSPECT_LEN: usize = 64 * 1024;
+
+/// Offset of the first ISO 9660 volume descriptor (sector 16 of 2048 bytes).
+const ISO_PVD_OFFSET: usize = 16 * 2048;
+
+/// Offset of the El Torito boot record volume descriptor (sector 17).
+const ISO_BOOT_RECORD_OFFSET: usize = 17 * 2048;
+
+/// The GPT partition type GUID of an EFI System Partition
+/// (C12A7328-F81F-11D2-BA4B-00A0C93EC93B), in its on-disk mixed-endian form.
+const ESP_TYPE_GUID: [u8; 16] = [
+    0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11, 0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e, 0xc9, 0x3b,
+];
+
+/// The results of the bootability checks.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BootReport {
+    pub mbr_signature: bool,
+    pub mbr_active_partition: bool,
+    pub gpt: bool,
+    pub efi_system_partition: bool,
+    pub iso9660: bool,
+    pub el_torito: bool,
+}
+
+impl BootReport {
+    /// An ISO image that also carries an MBR, so it boots from USB media.
+    pub fn isohybrid(&self) -> bool {
+        self.iso9660 && self.mbr_signature
+    }
+
+    /// Our overall verdict: does this look like it can boot?
+    pub fn likely_bootable(&self) -> bool {
+        let uefi = self.efi_system_partition;
+        let bios = self.mbr_signature && (self.mbr_active_partition || self.isohybrid());
+        uefi || bios
+    }
+
+    /// A one-line summary for the end of a copy.
+    pub fn summary(&self) -> String {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        format!(
+            "Boot check: MBR signature {}, GPT {}, EFI system partition {}, ISO 9660 {} (El Torito {}, isohybrid {}); likely bootable: {}",
+            yes_no(self.mbr_signature),
+            yes_no(self.gpt),
+            yes_no(self.efi_system_partition),
+            yes_no(self.iso9660),
+            yes_no(self.el_torito),
+            yes_no(self.isohybrid()),
+            yes_no(self.likely_bootable()),
+        )
+    }
+}
+
+/// Reads the start of `path` and runs the bootability checks on it.
+pub fn inspect(path: &str) -> RddResult<BootReport> {
+    let mut file = File::open(path).with_path(path)?;
+
+    let mut head = Vec::with_capacity(INSPECT_LEN);
+    file.by_ref()
+        .take(INSPECT_LEN as u64)
+        .read_to_end(&mut head)
+        .with_path(path)?;
+
+    Ok(inspect_bytes(&head))
+}
+
+/// Runs the bootability checks on the first bytes of an image.
+pub fn inspect_bytes(head: &[u8]) -> BootReport {
+    let mut report = BootReport::default();
+
+    // --- MBR ---
+    if head.len() >= 512 {
+        report.mbr_signature = head[510] == 0x55 && head[511] == 0xAA;
+
+        if report.mbr_signature {
+            // Four 16-byte partition entries start at offset 446.
+            for entry in head[446..510].chunks_exact(16) {
+                let status = entry[0];
+                let partition_type = entry[4];
+                if status == 0x80 {
+                    report.mbr_active_partition = true;
+                }
+                if partition_type == 0xEF {
+                    report.efi_system_partition = true;
+                }
+            }
+        }
+    }
+
+    // --- GPT ---
+    for sector_size in [512usize, 4096] {
+        if has_gpt_esp(head, sector_size, &mut report) {
+            break;
+        }
+    }
+
+    // --- ISO 9660 / El Torito ---
+    report.iso9660 = head
+        .get(ISO_PVD_OFFSET + 1..ISO_PVD_OFFSET + 6)
+        .is_some_and(|id| id == b"CD001");
+    report.el_torito = report.iso9660
+        && head
+            .get(ISO_BOOT_RECORD_OFFSET..ISO_BOOT_RECORD_OFFSET + 30)
+            .is_some_and(|vd| vd[0] == 0 && &vd[1..6] == b"CD001" && vd[7..].starts_with(b"EL TORITO SPECIFICATION"));
+
+    report
+}
+
+/// Looks for a GPT header at LBA 1 for the given sector size, recording what
+/// it finds in `report`. Returns true if a GPT header was found.
+fn has_gpt_esp(head: &[u8], sector_size: usize, report: &mut BootReport) -> bool {
+    let Some(header) = head.get(sector_size..sector_size + 92) else {
+        return false;
+    };
+    if &header[0..8] != b"EFI PART" {
+        return false;
+    }
+    report.gpt = true;
+
+    let u32_at = |off: usize| u32::from_le_bytes(header[off..off + 4].try_into().unwrap());
+    let entries_lba = u64::from_le_bytes(header[72..80].try_into().unwrap()) as usize;
+    let entry_count = u32_at(80) as usize;
+    let entry_size = u32_at(84) as usize;
+    if entry_size < 16 {
+        return true;
+    }
+
+    // Only the entries that fall inside the bytes we read can be checked;
+    // the ESP is normally one of the first few. The header comes from the
+    // image, so its offsets may be anything.
+    let start = entries_lba.saturating_mul(sector_size);
+    for i in 0..entry_count {
+        let Some(offset) = i.checked_mul(entry_size).and_then(|o| start.checked_add(o)) else {
+            break;
+        };
+        let Some(end) = offset.checked_add(16) else {
+            break;
+        };
+        match head.get(offset..end) {
+            Some(type_guid) if type_guid == ESP_TYPE_GUID => {
+                report.efi_system_partition = true;
+                break;
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A GPT header at LBA 1 of 512-byte sectors, with its entries at
+    /// `entries_lba`.
+    fn gpt_head(entries_lba: u64) -> Vec<u8> {
+        let mut head = vec![0u8; 4096];
+        head[512..520].copy_from_slice(b"EFI PART");
+        head[512 + 72..512 + 80].copy_from_slice(&entries_lba.to_le_bytes());
+        head[512 + 80..512 + 84].copy_from_slice(&128u32.to_le_bytes());
+        head[512 + 84..512 + 88].copy_from_slice(&128u32.to_le_bytes());
+        head
+    }
+
+    #[test]
+    fn finds_the_esp_among_the_entries() {
+        let mut head = gpt_head(2);
+        head[1024 + 128..1024 + 144].copy_from_slice(&ESP_TYPE_GUID);
+        let mut report = BootReport::default();
+        assert!(has_gpt_esp(&head, 512, &mut report));
+        assert!(report.efi_system_partition);
+    }
+
+    #[test]
+    fn entries_past_the_end_of_memory_are_not_a_panic() {
+        let head = gpt_head(u64::MAX);
+        let mut report = BootReport::default();
+        assert!(has_gpt_esp(&head, 512, &mut report));
+        assert!(!report.efi_system_partition);
+    }
+}
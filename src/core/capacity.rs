@@ -0,0 +1,93 @@
+// This file checks, before a copy writes anything, that the output device can hold what's going to be written to it. A device has a fixed size, and dd only finds out it's too small when a write fails at its end: a 64 GB image written to a 16 GB stick runs for half an hour, fails, and leaves the stick with the first quarter of an image on it. Both sizes are known before the copy starts, so we compare them up front and refuse with a clear error instead.
+
+// Explanation of this file:
+// device_size : The size of a block device, found by seeking to its end and back to where it was (the file's own length is 0 for a device node). On Windows, raw devices (see utils/rawdevice.rs) are asked with IOCTL_DISK_GET_LENGTH_INFO instead. Anything else, a regular file or a pipe, has no fixed size and gives None.
+// input_size : The size of the input as far as it's known up front: a regular file's length, or a device's size. expected_bytes (see copy.rs) uses it, so a copy from a device gets a progress bar with an end too.
+// check_capacity : Called by open_files (see copy.rs) for every engine, once both files are open. The copy needs the output up to where its last byte lands: --seek plus what it's expected to transfer (for a download, from the size the server gave), or the end of its last range with --ranges-file. If that's beyond the device's end, the copy fails with RddError::TargetTooSmall before it writes anything.
+// Unknown sizes : An output that isn't a device can grow, and an input whose size isn't known up front (a pipe, a decompressed image, a followed file without --until-size) can't be compared; both pass without a check.
+
+// src/core/capacity.rs
+
+use crate::config::CopyConfig;
+use crate::core::compress::Filters;
+use crate::core::ranges::ByteRange;
+use crate::error::{RddError, RddResult};
+use std::fs::File;
+
+/// The size of the device `file` (opened from `path`) is, if it's one.
+pub fn device_size(file: &File, path: &str) -> Option<u64> {
+    platform::device_size(file, path)
+}
+
+/// The size of the input `file`, if it's a regular file or a device.
+pub fn input_size(file: &File, path: &str) -> Option<u64> {
+    let metadata = file.metadata().ok()?;
+    if metadata.is_file() {
+        return Some(metadata.len());
+    }
+    device_size(file, path)
+}
+
+/// Refuses a copy whose output is a device too small for what the copy
+/// writes to it. `filters` are what the input was opened through.
+pub(crate) fn check_capacity(config: &CopyConfig, input: &File, output: &File, filters: &Filters) -> RddResult<()> {
+    let Some(capacity) = device_size(output, &config.output_file) else {
+        return Ok(());
+    };
+    let needed = match &config.ranges {
+        // Ranges land at their own offsets in the output, in any order with
+        // --priority-ranges. The input's end cuts the last one short.
+        Some(ranges) => {
+            let Some(end) = ranges.iter().map(ByteRange::end).max() else {
+                return Ok(());
+            };
+            match input_size(input, &config.input_file) {
+                Some(size) => end.min(size),
+                None => end,
+            }
+        }
+        None => {
+            let Some(bytes) = filters.expected_bytes(config, input) else {
+                return Ok(());
+            };
+            config.seek_bytes.saturating_add(bytes)
+        }
+    };
+    if needed > capacity {
+        return Err(RddError::TargetTooSmall {
+            path: config.output_file.clone(),
+            needed,
+            capacity,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::fs::File;
+    use std::io::{Seek, SeekFrom};
+    use std::os::unix::fs::FileTypeExt;
+
+    pub fn device_size(file: &File, _path: &str) -> Option<u64> {
+        if !file.metadata().ok()?.file_type().is_block_device() {
+            return None;
+        }
+        let mut file = file;
+        let position = file.stream_position().ok()?;
+        let size = file.seek(SeekFrom::End(0)).ok();
+        file.seek(SeekFrom::Start(position)).ok()?;
+        size
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    use crate::utils::rawdevice;
+    use std::fs::File;
+
+    pub fn device_size(file: &File, path: &str) -> Option<u64> {
+        rawdevice::raw_device(path)?;
+        rawdevice::device_length(file).ok()
+    }
+}
@@ -0,0 +1,214 @@
+// This file implements --resume. Imaging a 2 TB drive that fails at 80% shouldn't mean starting over from zero. While copying, we regularly write down how far we've safely got in a small state file next to the output. If the copy is interrupted (a crash, a pulled cable, Ctrl-C) and run again with the same settings, it continues from the last recorded position instead of from the start.
+
+// Explanation of this file:
+// The state file: By default it's the output path plus ".rdd-state"; --resume=FILE puts it elsewhere, which is useful for device outputs, since /dev doesn't survive a reboot. It's a short text file of "key value" lines: the fingerprint, the input offset we reached (relative to skip) and the number of blocks copied up to there.
+// Committing : Every CHECKPOINT_INTERVAL, at the end of a block, the output is synced first and only then is the new offset recorded. So the offset in the state file never runs ahead of data that is really on the output. A copy stopped with Ctrl-C commits once more on its way out, at the last complete block (see utils/signals.rs). The file is replaced atomically (write a temporary file, then rename), so an interruption never leaves a half-written state behind.
+// Fingerprint : A SHA-256 over everything that decides which bytes end up where: the paths, bs, skip, seek, count, the modes that change what we write, and the input's size, modification time and inode (see guard.rs). Resuming with a different command line, or after the input changed, fails instead of stitching together two different copies.
+// Resuming : The output is opened without truncating it, then cut back to the recorded offset, so anything written after the last commit is redone. A fresh start (no state file yet) cuts it back to nothing, like the normal truncating open. An output that isn't truncated (--seek, --no-truncate) is never cut: what was written after the last commit is simply overwritten again, and the data around the copy stays.
+// Hash state : The hash libraries can't save a half-finished hash. With --verify, a resumed copy re-reads the part of the input that was already copied and hashes it again before it carries on.
+// Finishing : Once the copy has been synced and verified, the state file is deleted. A state file left behind therefore always means an unfinished copy.
+
+// src/core/checkpoint.rs
+
+use crate::cli::HashAlgorithm;
+use crate::config::CopyConfig;
+use crate::core::guard::InputSnapshot;
+use crate::core::hash::StreamHasher;
+use crate::core::mapfile::{format_offset, parse_offset};
+use crate::error::{IoResultExt, RddError, RddResult};
+use std::fs::{self, File};
+use std::io;
+use std::time::{Duration, Instant};
+
+/// How often the copy position is committed to the state file.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The state file used when --resume is given without a path.
+pub fn default_state_file(output: &str) -> String {
+    format!("{}.rdd-state", output)
+}
+
+/// The contents of a state file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub fingerprint: String,
+    /// Input bytes copied so far, counted from the skip position.
+    pub offset: u64,
+    /// Blocks copied up to `offset`.
+    pub blocks: u64,
+}
+
+impl Checkpoint {
+    /// Reads the state file at `path`, or returns `None` if there is none.
+    pub fn load(path: &str) -> RddResult<Option<Self>> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_path(path),
+        };
+        Self::parse(path, &contents).map(Some)
+    }
+
+    /// Parses the contents of a state file. `path` is only used in error messages.
+    pub fn parse(path: &str, contents: &str) -> RddResult<Self> {
+        let (mut fingerprint, mut offset, mut blocks) = (None, None, None);
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(' ') {
+                Some(("fingerprint", value)) => fingerprint = Some(value.trim().to_string()),
+                Some(("offset", value)) => offset = parse_offset(value.trim()),
+                Some(("blocks", value)) => blocks = value.trim().parse().ok(),
+                _ => {}
+            }
+        }
+        match (fingerprint, offset, blocks) {
+            (Some(fingerprint), Some(offset), Some(blocks)) => Ok(Checkpoint {
+                fingerprint,
+                offset,
+                blocks,
+            }),
+            _ => Err(RddError::Config(format!(
+                "{}: not a valid rdd state file. Delete it to start the copy over.",
+                path
+            ))),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        format!(
+            "# rdd state file, created by rdd version {}\n# Delete it to start the copy over.\nfingerprint {}\noffset {}\nblocks {}\n",
+            env!("CARGO_PKG_VERSION"),
+            self.fingerprint,
+            format_offset(self.offset),
+            self.blocks
+        )
+    }
+
+    /// Writes the state to `path` through a temporary file, so an
+    /// interrupted write never leaves a truncated state file behind.
+    pub fn write(&self, path: &str) -> RddResult<()> {
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, self.render()).with_path(&tmp)?;
+        fs::rename(&tmp, path).with_path(path)
+    }
+}
+
+/// Computes the fingerprint of a copy: everything that decides which bytes
+/// end up where in the output, plus the identity of the input.
+pub fn fingerprint(config: &CopyConfig) -> RddResult<String> {
+    let input = InputSnapshot::take(&config.input_file)?;
+    let description = format!(
+        "input={:?} output={:?} bs={} skip={} seek={} count={} count_bytes={:?} write_if_changed={} sparse={} input_len={} input_modified={:?} input_identity={:?}",
+        config.input_file,
+        config.output_file,
+        config.block_size,
+        config.skip_bytes,
+        config.seek_bytes,
+        config.count,
+        config.count_bytes,
+        config.write_if_changed,
+        config.sparse,
+        input.len,
+        input.modified,
+        input.identity
+    );
+    let mut hasher = StreamHasher::new(HashAlgorithm::Sha256);
+    hasher.update(description.as_bytes());
+    Ok(hasher.finalize())
+}
+
+/// Keeps the state file of a running copy up to date.
+pub struct Checkpointer {
+    path: String,
+    fingerprint: String,
+    /// Where this run started: zero, or the checkpoint we resumed from.
+    resumed: Checkpoint,
+    last_commit: Instant,
+}
+
+impl Checkpointer {
+    /// Loads the state file at `path`, checks that it belongs to this copy
+    /// and cuts the output back to its offset. Without a state file the copy
+    /// starts from the beginning.
+    pub fn open(config: &CopyConfig, path: &str, output: &File) -> RddResult<Self> {
+        let fingerprint = fingerprint(config)?;
+        let resumed = match Checkpoint::load(path)? {
+            Some(checkpoint) if checkpoint.fingerprint != fingerprint => {
+                return Err(RddError::Config(format!(
+                    "The state file '{}' belongs to a different copy (other options, or the input has changed since). Delete it to start the copy over.",
+                    path
+                )));
+            }
+            Some(checkpoint) => checkpoint,
+            None => Checkpoint {
+                fingerprint: fingerprint.clone(),
+                offset: 0,
+                blocks: 0,
+            },
+        };
+
+        // Drop whatever was written after the last commit; it's copied again.
+        // Only an output that's truncated anyway is cut: --seek and
+        // --no-truncate keep what's around the copy (see config.rs), and
+        // write-if-changed compares against the existing output.
+        if config.truncate && !config.write_if_changed && output.metadata()?.is_file() {
+            output.set_len(resumed.offset).with_path(&config.output_file)?;
+        }
+
+        Ok(Self {
+            path: path.to_string(),
+            fingerprint,
+            resumed,
+            last_commit: Instant::now(),
+        })
+    }
+
+    /// The checkpoint this run resumed from (offset 0 for a fresh start).
+    pub fn resumed(&self) -> &Checkpoint {
+        &self.resumed
+    }
+
+    /// Records that the copy has reached `offset` bytes and `blocks` blocks
+    /// in this run, if the last commit was long enough ago. The output is
+    /// synced first, so the state file never claims more than is stored.
+    pub fn maybe_commit(&mut self, config: &CopyConfig, output: &File, offset: u64, blocks: u64) -> RddResult<()> {
+        if !self.due() {
+            return Ok(());
+        }
+        self.commit(config, output, offset, blocks)
+    }
+
+    /// Whether the last commit was long enough ago for the next one.
+    pub fn due(&self) -> bool {
+        self.last_commit.elapsed() >= CHECKPOINT_INTERVAL
+    }
+
+    /// Like `maybe_commit`, but commits right away, e.g. when the copy is
+    /// interrupted.
+    pub fn commit(&mut self, config: &CopyConfig, output: &File, offset: u64, blocks: u64) -> RddResult<()> {
+        output.sync_data().with_path(&config.output_file)?;
+        Checkpoint {
+            fingerprint: self.fingerprint.clone(),
+            offset: self.resumed.offset + offset,
+            blocks: self.resumed.blocks + blocks,
+        }
+        .write(&self.path)?;
+        self.last_commit = Instant::now();
+        Ok(())
+    }
+
+    /// The path of the state file.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Deletes the state file once the copy has completed.
+    pub fn finish(self) -> RddResult<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e).with_path(&self.path),
+            _ => Ok(()),
+        }
+    }
+}
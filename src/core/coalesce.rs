@@ -0,0 +1,119 @@
+// This file implements --coalesce. Some copies have to keep a small block size: --count, --skip and --seek are counted in blocks, and a forensic procedure or a script may prescribe bs=512. Every block is then written with a write call of its own, and many devices (USB sticks, SD cards, network shares) are much slower with many small writes than with a few large ones. --coalesce SIZE gathers the consecutive writes of the copy in a buffer and writes it out in pieces of up to SIZE bytes, while the blocks keep their meaning everywhere else.
+
+// Explanation of this file:
+// Buffer : Coalescer holds the data that's due at the output's current position but not written yet. Data is appended to it as the copy goes, and written out whenever the buffer is full. Since the output's position only advances when the buffer is written, each byte still lands at the offset it would have without --coalesce.
+// Flushing : Anything that looks at or moves the output position writes the buffer out first: excluded ranges, holes for --sparse, --write-if-changed, a --resume checkpoint, Ctrl-C, and the end of every span, which comes before the final sync and the read-back for --verify (see BlockCopier in copy.rs).
+// Where it's used : Only the single-threaded copy coalesces. The multithreaded and io_uring engines write every chunk at its own offset, and build() rejects --coalesce with them, as well as with flush probes, which time single writes. The size is capped at --max-io like every other transfer, and a copy whose chunks are already that big doesn't coalesce at all.
+// Summary : How many writes were gathered into how many is reported once the copy is done, and in the JSON stats.
+
+// src/core/coalesce.rs
+
+use crate::config::CopyConfig;
+use crate::core::copy::relax_direct_for;
+use crate::core::retry::{self, RetriedIo};
+use crate::error::{IoResultExt, RddResult};
+use crate::utils::direct::AlignedBuffer;
+use crate::utils::fadvise::advise_dont_need;
+use indicatif::{BinaryBytes, ProgressBar};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Seek;
+
+/// How the writes of a copy were gathered.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CoalesceStats {
+    /// Writes the copy asked for.
+    pub writes: u64,
+    /// Writes that were actually issued.
+    pub physical_writes: u64,
+    /// The most one of them wrote.
+    pub size: usize,
+}
+
+impl CoalesceStats {
+    /// A one-line summary for the end of a copy.
+    pub fn summary(&self) -> String {
+        format!(
+            "Coalesced {} writes into {} of up to {}",
+            self.writes,
+            self.physical_writes,
+            BinaryBytes(self.size as u64)
+        )
+    }
+}
+
+/// Gathers consecutive writes to the output into larger ones.
+pub struct Coalescer {
+    buffer: AlignedBuffer,
+    /// How much of the buffer is waiting to be written.
+    pending: usize,
+    stats: CoalesceStats,
+}
+
+impl Coalescer {
+    /// A coalescer for writes of up to `size` bytes.
+    pub fn new(size: usize) -> Self {
+        Self {
+            buffer: AlignedBuffer::new(size),
+            pending: 0,
+            stats: CoalesceStats {
+                size,
+                ..CoalesceStats::default()
+            },
+        }
+    }
+
+    /// Adds `data`, due right after what's pending, and writes out the
+    /// buffer each time it fills up.
+    pub fn write(
+        &mut self,
+        config: &CopyConfig,
+        output: &mut File,
+        mut data: &[u8],
+        direct_align: Option<usize>,
+        progress: &ProgressBar,
+        retried: &mut Vec<RetriedIo>,
+    ) -> RddResult<()> {
+        self.stats.writes += 1;
+        while !data.is_empty() {
+            let take = (self.buffer.len() - self.pending).min(data.len());
+            self.buffer[self.pending..self.pending + take].copy_from_slice(&data[..take]);
+            self.pending += take;
+            data = &data[take..];
+            if self.pending == self.buffer.len() {
+                self.flush(config, output, direct_align, progress, retried)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes out whatever is pending, at the output's position.
+    pub fn flush(
+        &mut self,
+        config: &CopyConfig,
+        output: &mut File,
+        direct_align: Option<usize>,
+        progress: &ProgressBar,
+        retried: &mut Vec<RetriedIo>,
+    ) -> RddResult<()> {
+        if self.pending == 0 {
+            return Ok(());
+        }
+        relax_direct_for(config, output, direct_align, self.pending)?;
+        retry::write_all(config, output, &self.buffer[..self.pending], progress, retried)
+            .with_path(&config.output_file)?;
+        if config.fadvise.drop_output_cache
+            && let Ok(end) = output.stream_position()
+        {
+            advise_dont_need(output, end - self.pending as u64, self.pending as u64);
+        }
+        self.stats.physical_writes += 1;
+        self.pending = 0;
+        Ok(())
+    }
+
+    /// How the writes were gathered. Anything still pending is not counted.
+    pub fn stats(&self) -> CoalesceStats {
+        self.stats.clone()
+    }
+}
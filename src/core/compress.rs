@@ -0,0 +1,521 @@
+// This file implements --compress and the decompression of compressed inputs. Disk images are mostly empty space and compress well, and imaging a drive used to mean piping dd into zstd. With --compress, rdd does the piping itself: the copy writes into a compressor, which writes the output file. The other way round, copying a compressed image to a drive decompresses it on the way.
+
+// Explanation of this file:
+// External programs : Compression runs the gzip, zstd or xz program as a child process, the way the shell pipeline did; these are fast, well-tested and installed almost everywhere. CodecMissing says which one to install when it isn't.
+// Pipes as files : The copy engines read and write Files. The pipe to the compressor's stdin (or from the decompressor's stdout) is turned into a File, so the engines treat it like stdin or stdout: a stream that can't be sought in or read back. That's why build() rejects the same options for it (see config.rs).
+// Detecting : A regular input file, or a download, that starts with the magic bytes of a known format is decompressed unless --no-decompress is given. Devices and stdin are only decompressed with --decompress, since a drive must be copied bit for bit. With --decompress, the format is still taken from the header; stdin's header is read first and fed to the decompressor ahead of the rest by a helper thread.
+// finish : Once the transfer is done, the pipes are closed and the programs waited for; a program that failed fails the copy with CodecFailed. A decompressor stopped early by --count is killed by SIGPIPE, which is expected. finish returns the real output file, which the copy then syncs like any other output.
+// Chunks : Filters also starts and finishes the splitter and joiner of split.rs, so a compressed image can be split (and a split one decompressed) in one go.
+// Network : A tcp:// input is received, and a tcp:// output sent, by the connections of network.rs, and ssh:// ones by the ssh of remote.rs, which sit where a file would: the decompressor reads from the receiving one, and the compressor writes into the sending one. An http(s):// input is downloaded by the curl of http.rs, which knows the size of a download read as it is.
+// Records : With --records, the record slicer of records.rs reads whatever the input turned out to be (a file, stdin, a decompressor, a connection) and the copy reads the slicer.
+// Seekable : With --seekable, the compressor is the SeekableWriter of seekable.rs, which runs zstd once per frame instead of once for the whole stream.
+// Feature : Without the compress cargo feature, config.rs rejects --compress and --decompress, compressed inputs are copied as they are, and spawn refuses to start a codec (so scrub reports a compressed image as unreadable rather than corrupted).
+// Bytes : The copy's counters, hashes and manifest digest are all about the uncompressed data, so an image made with --compress verifies against the drive it came from.
+
+// src/core/compress.rs
+
+use crate::cli::{Compression, Decompression};
+use crate::config::CopyConfig;
+use crate::core::copy::{expected_bytes, expected_bytes_of};
+use crate::core::http::{is_http, Download};
+use crate::core::network::Connection;
+use crate::core::open::{open_stdin, open_stdout, open_with_timeout, pipe_file};
+use crate::core::records::RecordSlicer;
+use crate::core::remote::{is_ssh, RemoteFile};
+use crate::core::seekable::SeekableWriter;
+use crate::core::split::{chunk_set, Joiner, Splitter, BUFFER_SIZE};
+use crate::core::verify::read_full;
+use crate::error::{IoResultExt, RddError, RddResult};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Cursor, Read};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+
+/// The bytes every stream of each format starts with.
+const MAGIC: &[(Compression, &[u8])] = &[
+    (Compression::Gzip, &[0x1F, 0x8B]),
+    (Compression::Zstd, &[0x28, 0xB5, 0x2F, 0xFD]),
+    (Compression::Xz, &[0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00]),
+];
+
+/// Enough of the input to recognize any format in MAGIC.
+const HEADER_LEN: usize = 6;
+
+/// The program that handles `format`.
+pub fn tool(format: Compression) -> &'static str {
+    match format {
+        Compression::Gzip => "gzip",
+        Compression::Zstd => "zstd",
+        Compression::Xz => "xz",
+    }
+}
+
+/// The compression levels the program for `format` accepts.
+pub fn levels(format: Compression) -> RangeInclusive<u32> {
+    match format {
+        Compression::Gzip => 1..=9,
+        // Levels 20 and up need zstd's --ultra and a lot of memory.
+        Compression::Zstd => 1..=19,
+        Compression::Xz => 0..=9,
+    }
+}
+
+/// Checks that `level` is a level the program for `format` accepts.
+pub fn check_level(format: Compression, level: Option<u32>) -> RddResult<()> {
+    let levels = levels(format);
+    match level {
+        Some(level) if !levels.contains(&level) => Err(RddError::Config(format!(
+            "Invalid --compress-level {} for {}: use {} to {}.",
+            level,
+            tool(format),
+            levels.start(),
+            levels.end()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// The format whose magic bytes `header` starts with, if any.
+pub fn detect(header: &[u8]) -> Option<Compression> {
+    MAGIC
+        .iter()
+        .find(|(_, magic)| header.starts_with(magic))
+        .map(|&(format, _)| format)
+}
+
+/// What to do when a compressed input can't be decompressed on the fly.
+pub const DECOMPRESS_ADVICE: &str = "Decompress it first, or copy it as it is with --no-decompress.";
+
+/// Checks that nothing in `config` needs to seek in the input, which a
+/// decompressed input or a chunk set doesn't allow. `reading` says how the
+/// input is read and `advice` what to do instead, for the error.
+pub fn check_stream_input(config: &CopyConfig, reading: &str, advice: &str) -> RddResult<()> {
+    let unsupported = [
+        (config.ranges.is_some(), "a ranges list"),
+        (!config.excluded.is_empty(), "excluded ranges"),
+        (config.resume.is_some(), "--resume"),
+        (config.noerror, "--noerror"),
+        (config.direct_io(), "--direct"),
+    ];
+    match unsupported.iter().find(|(set, _)| *set) {
+        Some((_, what)) => Err(RddError::Config(format!(
+            "{} cannot be combined with {}. {}",
+            reading, what, advice
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// A compressor or decompressor running for a copy.
+struct Process {
+    child: Child,
+    tool: &'static str,
+}
+
+/// What the data of a copy passes through on its way: the compressor and
+/// decompressor, the splitter and joiner of split.rs, the connections of
+/// network.rs, the ssh of remote.rs, the download of http.rs and the record
+/// slicer of records.rs.
+#[derive(Default)]
+pub struct Filters {
+    decompressor: Option<Process>,
+    /// The compressor, and the file it writes to.
+    compressor: Option<(Process, File)>,
+    /// The writer of --seekable output, which runs a compressor per frame.
+    seekable: Option<SeekableWriter>,
+    splitter: Option<Splitter>,
+    joiner: Option<Joiner>,
+    /// The connection a tcp:// input arrives on (see network.rs).
+    receiver: Option<Connection>,
+    /// The connection a tcp:// output goes out on.
+    sender: Option<Connection>,
+    /// The ssh an ssh:// input is read with (see remote.rs).
+    remote_input: Option<RemoteFile>,
+    /// The ssh an ssh:// output is written with.
+    remote_output: Option<RemoteFile>,
+    /// The download an http(s):// input arrives from (see http.rs).
+    download: Option<Download>,
+    /// The record slicer of --records (see records.rs).
+    slicer: Option<RecordSlicer>,
+}
+
+impl Filters {
+    /// Opens the input when it takes more than a plain open: a tcp://,
+    /// ssh:// or http(s):// input is received, a chunk set is joined, and a
+    /// compressed input is decompressed. Returns the stream to read, or None if the input is
+    /// read as it is.
+    pub fn open_input(&mut self, config: &CopyConfig) -> RddResult<Option<File>> {
+        let path = config.input_file.as_str();
+        let stdin = config.input_is_stdin();
+        let network = config.input_is_network();
+        let chunks = if stdin || network { None } else { chunk_set(path) };
+        if let Some(chunks) = &chunks {
+            check_stream_input(config, "Reading a chunk set", "Join the chunks into one file first.")?;
+            config.reporter.message(&format!(
+                "'{}' is the first of {} chunks; reading them as one input.",
+                path,
+                chunks.len()
+            ));
+        }
+
+        // The format is told by the first bytes, which stdin only has once.
+        let detect_format = match config.decompress {
+            Decompression::Never => false,
+            // A build without the codecs copies compressed files as they are.
+            Decompression::Auto => cfg!(feature = "compress") && !stdin && (Path::new(path).is_file() || is_http(path)),
+            Decompression::Always => true,
+        };
+        let mut header = [0u8; HEADER_LEN];
+        let mut read = 0;
+        let mut stdin_source = None;
+        if detect_format {
+            let mut first = match (&chunks, stdin) {
+                _ if network => self.receive(config)?,
+                (_, true) => open_stdin()?,
+                (Some(chunks), _) => File::open(&chunks[0]).with_path(&chunks[0])?,
+                (None, _) => open_with_timeout(OpenOptions::new().read(true), path, config.open_timeout)?,
+            };
+            // A download shows its first bytes without giving them up.
+            read = match &self.download {
+                Some(download) => download.peek(&mut header),
+                None => read_full(&mut first, &mut header).with_path(path)?,
+            };
+            if stdin || network {
+                stdin_source = Some(first);
+            }
+        }
+
+        let Some(format) = detect(&header[..read]) else {
+            if config.decompress == Decompression::Always {
+                return Err(RddError::Config(format!(
+                    "'{}' isn't compressed in a format rdd knows (gzip, zstd or xz); copy it without --decompress.",
+                    path
+                )));
+            }
+            if network {
+                return stdin_source.map_or_else(|| self.receive(config), Ok).map(Some);
+            }
+            return chunks.map(|chunks| self.join(chunks)).transpose();
+        };
+        if config.decompress == Decompression::Auto {
+            check_stream_input(config, "Decompressing the input", DECOMPRESS_ADVICE)?;
+            config.reporter.message(&format!(
+                "'{}' is {}-compressed; copying its decompressed contents (--no-decompress copies it as it is).",
+                path,
+                tool(format)
+            ));
+        }
+
+        // Stdin can't be read again, so the header we took from it goes to
+        // the decompressor first.
+        let (source, prefix) = match (chunks, stdin_source) {
+            (_, Some(download)) if self.download.is_some() => (download, &[][..]),
+            (_, Some(stdin)) => (stdin, &header[..read]),
+            (Some(chunks), None) => (self.join(chunks)?, &[][..]),
+            (None, None) => (
+                open_with_timeout(OpenOptions::new().read(true), path, config.open_timeout)?,
+                &[][..],
+            ),
+        };
+        self.start_decompressor(path, format, source, prefix).map(Some)
+    }
+
+    /// Opens the image at `path` for reading: the whole set if it's the first
+    /// chunk of one, and through a decompressor if it starts with the header
+    /// of a known format.
+    pub fn open_decompressed(&mut self, path: &str) -> RddResult<File> {
+        let chunks = chunk_set(path);
+        let first = chunks.as_ref().map_or(path, |chunks| chunks[0].as_str());
+        let mut header = [0u8; HEADER_LEN];
+        let read = read_full(&mut File::open(first).with_path(first)?, &mut header).with_path(first)?;
+
+        let source = match chunks {
+            Some(chunks) => self.join(chunks)?,
+            None => File::open(path).with_path(path)?,
+        };
+        match detect(&header[..read]) {
+            Some(format) => self.start_decompressor(path, format, source, &[]),
+            None => Ok(source),
+        }
+    }
+
+    /// Waits for the sender of a tcp:// input, or starts reading an ssh://
+    /// one or downloading an http(s):// one, and returns the stream its data
+    /// arrives on.
+    fn receive(&mut self, config: &CopyConfig) -> RddResult<File> {
+        if is_http(&config.input_file) {
+            let (download, stream) = Download::start(config)?;
+            self.download = Some(download);
+            return Ok(stream);
+        }
+        if is_ssh(&config.input_file) {
+            let (remote, stream) = RemoteFile::read(config)?;
+            self.remote_input = Some(remote);
+            return Ok(stream);
+        }
+        let (receiver, pipe) = Connection::receive(config)?;
+        self.receiver = Some(receiver);
+        Ok(pipe)
+    }
+
+    /// How many bytes the copy is expected to transfer (see
+    /// expected_bytes), which for a download read as it is comes from the
+    /// size the server gave.
+    pub fn expected_bytes(&self, config: &CopyConfig, input: &File) -> Option<u64> {
+        match &self.download {
+            Some(download) if self.decompressor.is_none() && self.slicer.is_none() => {
+                expected_bytes_of(config, download.size()?)
+            }
+            _ => expected_bytes(config, input),
+        }
+    }
+
+    /// With --records, starts cutting the records the copy takes out of
+    /// `input`, and returns the stream to read them from instead.
+    pub fn slice_records(&mut self, config: &CopyConfig, input: File) -> RddResult<File> {
+        let Some(records) = config.records else {
+            return Ok(input);
+        };
+        let (slicer, pipe) = RecordSlicer::start(input, &config.input_file, records, config.reporter.clone())?;
+        self.slicer = Some(slicer);
+        Ok(pipe)
+    }
+
+    /// Starts reading `chunks` as one stream.
+    fn join(&mut self, chunks: Vec<String>) -> RddResult<File> {
+        let (joiner, pipe) = Joiner::start(chunks)?;
+        self.joiner = Some(joiner);
+        Ok(pipe)
+    }
+
+    /// Starts the decompressor for `format` on `source`, and returns the
+    /// stream it writes to. `prefix` is what was already read from `source`.
+    fn start_decompressor(&mut self, path: &str, format: Compression, source: File, prefix: &[u8]) -> RddResult<File> {
+        let (stdin, unread) = match prefix.is_empty() {
+            true => (Stdio::from(source), None),
+            false => (Stdio::piped(), Some(source)),
+        };
+        let mut command = Command::new(tool(format));
+        command.args(decompress_args(format)).stdin(stdin).stdout(Stdio::piped());
+        let mut child = spawn(&mut command, format)?;
+        if let (Some(source), Some(mut pipe)) = (unread, child.stdin.take()) {
+            let mut rest = Cursor::new(prefix.to_vec()).chain(source);
+            // Write errors only mean the decompressor is gone, which finish
+            // reports.
+            thread::spawn(move || io::copy(&mut rest, &mut pipe));
+        }
+        let stream = child.stdout.take().map(pipe_file).transpose().with_path(path)?;
+        self.decompressor = Some(Process {
+            child,
+            tool: tool(format),
+        });
+        stream.ok_or_else(|| RddError::Channel("the decompressor has no output pipe".to_string()))
+    }
+
+    /// Opens the output when it takes more than a plain open: --split-size
+    /// writes into the splitter, a tcp:// or ssh:// output into a connection
+    /// or ssh, and
+    /// --compress into a compressor (which writes into the splitter or the
+    /// connection). Returns the stream to write, or None if the output is
+    /// written as it is.
+    pub fn open_output(&mut self, config: &CopyConfig, options: &OpenOptions) -> RddResult<Option<File>> {
+        let path = config.output_file.as_str();
+        let split = match config.split_size {
+            Some(size) => {
+                let (splitter, pipe) = Splitter::start(
+                    path,
+                    size,
+                    BUFFER_SIZE.min(config.max_io),
+                    options,
+                    config.sync_mode,
+                    config.reporter.clone(),
+                )?;
+                self.splitter = Some(splitter);
+                Some(pipe)
+            }
+            None if is_ssh(&config.output_file) => {
+                let (remote, pipe) = RemoteFile::write(config)?;
+                self.remote_output = Some(remote);
+                Some(pipe)
+            }
+            None if config.output_is_network() => {
+                let (sender, pipe) = Connection::send(config)?;
+                self.sender = Some(sender);
+                Some(pipe)
+            }
+            None => None,
+        };
+        let Some(format) = config.compress else {
+            return Ok(split);
+        };
+        let output = match split {
+            Some(pipe) => pipe,
+            None if config.output_is_stdout() => open_stdout()?,
+            None => open_with_timeout(options, path, config.open_timeout)?,
+        };
+        if let Some(frame_size) = config.seekable_frame_size {
+            let (writer, pipe) = SeekableWriter::start(
+                output,
+                path,
+                frame_size,
+                config.compress_level,
+                config.low_memory,
+                config.reporter.clone(),
+            )?;
+            self.seekable = Some(writer);
+            return Ok(Some(pipe));
+        }
+
+        let mut command = Command::new(tool(format));
+        command.args(compress_args(format, config.low_memory));
+        if let Some(level) = config.compress_level {
+            command.arg(format!("-{}", level));
+        }
+        let writes_to = output.try_clone().with_path(path)?;
+        let mut child = spawn(command.stdin(Stdio::piped()).stdout(Stdio::from(writes_to)), format)?;
+        let pipe = child.stdin.take().map(pipe_file).transpose().with_path(path)?;
+        self.compressor = Some((
+            Process {
+                child,
+                tool: tool(format),
+            },
+            output,
+        ));
+        Ok(pipe)
+    }
+
+    /// Waits for everything the data went through once the copy has read
+    /// `input` and written `output`, and returns the file the output really
+    /// went to.
+    pub fn finish(mut self, input: File, output: File) -> RddResult<File> {
+        // Closing our ends tells a decompressor nobody reads any more, and
+        // the compressor and splitter that their input is complete.
+        self.finish_input(input)?;
+        let mut output = output;
+        if let Some((mut compressor, file)) = self.compressor.take() {
+            drop(output);
+            let status = compressor.child.wait().with_path(compressor.tool)?;
+            if !status.success() {
+                return Err(failed(compressor.tool, "compressing", status));
+            }
+            output = file;
+        }
+        if let Some(writer) = self.seekable.take() {
+            drop(output);
+            output = writer.finish()?;
+        }
+        if let Some(splitter) = self.splitter.take() {
+            drop(output);
+            output = splitter.finish()?;
+        }
+        if let Some(sender) = self.sender.take() {
+            drop(output);
+            // The sender hands back its end of the pipe, which stands for
+            // the output from here on.
+            output = sender
+                .finish()?
+                .ok_or_else(|| RddError::Channel("the sender has no pipe".to_string()))?;
+        }
+        if let Some(remote) = self.remote_output.take() {
+            drop(output);
+            output = remote
+                .finish()?
+                .ok_or_else(|| RddError::Channel("the ssh feeder has no pipe".to_string()))?;
+        }
+        Ok(output)
+    }
+
+    /// Waits for the record slicer, the decompressor, the joiner and what
+    /// received the input, if there are any, once `input` has been read.
+    pub fn finish_input(&mut self, input: File) -> RddResult<()> {
+        drop(input);
+        // The slicer reads from the others, and stops reading once it has
+        // the records.
+        if let Some(slicer) = self.slicer.take() {
+            slicer.finish()?;
+        }
+        if let Some(mut decompressor) = self.decompressor.take() {
+            let status = decompressor.child.wait().with_path(decompressor.tool)?;
+            if !status.success() && !stopped_by_us(status) {
+                return Err(failed(decompressor.tool, "decompressing", status));
+            }
+        }
+        if let Some(joiner) = self.joiner.take() {
+            joiner.finish()?;
+        }
+        if let Some(receiver) = self.receiver.take() {
+            receiver.finish()?;
+        }
+        if let Some(remote) = self.remote_input.take() {
+            remote.finish()?;
+        }
+        if let Some(download) = self.download.take() {
+            download.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// The compressor's arguments. It uses all cores, unless `low_memory`
+/// asks for a single thread, which also keeps its buffers to one set.
+fn compress_args(format: Compression, low_memory: bool) -> &'static [&'static str] {
+    match (format, low_memory) {
+        (Compression::Gzip, _) => &["-c"],
+        (Compression::Zstd, false) => &["-c", "-q", "-T0"],
+        (Compression::Zstd, true) => &["-c", "-q", "-T1"],
+        (Compression::Xz, false) => &["-c", "-T0"],
+        (Compression::Xz, true) => &["-c", "-T1"],
+    }
+}
+
+fn decompress_args(format: Compression) -> &'static [&'static str] {
+    match format {
+        Compression::Gzip => &["-d", "-c"],
+        Compression::Zstd => &["-d", "-c", "-q"],
+        Compression::Xz => &["-d", "-c"],
+    }
+}
+
+/// Starts the program for `format`, telling the user which one to install
+/// if it's missing.
+pub(crate) fn spawn(command: &mut Command, format: Compression) -> RddResult<Child> {
+    if !cfg!(feature = "compress") {
+        return Err(RddError::FeatureDisabled {
+            what: format!("{}-compressed data", tool(format)),
+            feature: "compress".to_string(),
+        });
+    }
+    command.spawn().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => RddError::CodecMissing {
+            tool: tool(format).to_string(),
+        },
+        _ => RddError::IoAt {
+            path: tool(format).to_string(),
+            offset: None,
+            source: e,
+        },
+    })
+}
+
+pub(crate) fn failed(tool: &str, what: &str, status: ExitStatus) -> RddError {
+    RddError::CodecFailed {
+        tool: tool.to_string(),
+        what: what.to_string(),
+        status: status.to_string(),
+    }
+}
+
+/// Whether a decompressor died of SIGPIPE because the copy stopped reading
+/// it early (e.g. with --count).
+#[cfg(unix)]
+pub(crate) fn stopped_by_us(status: ExitStatus) -> bool {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal() == Some(nix::libc::SIGPIPE)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn stopped_by_us(_status: ExitStatus) -> bool {
+    false
+}
@@ -3,91 +3,1894 @@
 // Explanation of this file:
 // Function signature: It takes a reference to our CopyConfig struct, which contains all the necesary parameters. It returns our RddResult<()>, so it can signal success (Ok(())) or failure (Err(RddError)).
 // File handling: It uses std::fs::File to open the input and std::fs::OpenOptions to gain more control over how the output file is opened (write, create, truncate).
-// skip and seek: It uses the seek method on the file handles to move the read/ write cursors to the correct starting position before the loop begins. This is a direct implementation of dd's skip and seek operands.
+// skip and seek: It uses the seek method on the file handles to move the read/ write cursors to the correct starting position before the loop begins. This is a direct implementation of dd's skip and seek operands. An input that can't seek (a pipe) skips by reading and discarding instead.
+// stdin and stdout : An input or output of "-" is stdin or stdout (see open.rs). The terminal reporter prints the summary and every other message on stderr (see reporter.rs), so stdout is free for the data.
 // The Buffer: let mut buffer = vec![0;config.block_size]; creates a block of memory on the heap that we will reuse for every read/write cycle. This is efficient.
 // The Loop:
     // It first checks the count condition.
     // input_file.read(&mut buffer)? attempts to fill the entire buffer from the input file. It returns the number of bytes actually read.
     // if bytes_read == 0: this is the standard way to detect the end of a file (EOF) when reading.
     // output_file.write_all(&buffer [..bytes_read])?: This is the most critical line. We write only the bytes that were read. If we wrote the whole buffer, we would write garbage data on the last, partial block. 
+    // write_if_changed: When enabled, we read the current contents of each output block before writing. If it already matches the input block, we simply leave it alone and move on. If it differs, we seek back over the bytes we just read and write the new block in its place.
+    // Truncating : The output is truncated when it's opened, unless --no-truncate, --seek or --append keep it (see config.rs); then what the copy doesn't overwrite stays. Holes (excluded ranges, --sparse) are only left in a truncated output, since elsewhere they would keep old data. With --append, the output's position starts at its end.
+    // Ranges mode : With --ranges-file, instead of one contiguous copy we seek both files to the start of each listed range and copy just that range (see ranges.rs). The output isn't truncated, so the ranges are patched into an existing image.
+    // Priority ranges : With --priority-ranges, run_copy replaces the config with one whose ranges list holds the priority ranges first and then the rest of the input (see priority.rs), so it's ranges mode that copies them.
+    // Chunked I/O : A logical block larger than --max-io is read and written in several chunks of at most max_io bytes. The buffers are only chunk-sized, so a huge --bs doesn't need a huge allocation, and no single read or write exceeds what the OS accepts in one syscall. A short read still ends the block early, exactly like it does for a single read.
+    // Excluded ranges : With --exclude-ranges-file, the listed regions of the input are never read. The matching part of the output is zeroed, or simply skipped over as a hole when the output is a freshly truncated regular file. A read that would run into an excluded range is shortened to stop right before it.
+    // Error budget : With --max-errors, skip_unreadable counts the reads it fills in, and once there are more than the budget allows the copy gives up: a drive that keeps failing is deteriorating, and every further pass over it may cost more of what's still readable. Before the error is returned, a map in GNU ddrescue's format is saved (see of_copy in mapfile.rs), with what was read, what failed and what wasn't tried yet, so a later run with it as --ranges-file (or ddrescue itself) can go back for just the rest.
+    // Retries : With --retries, a read that fails is tried again a few times with a growing wait in between before it counts as failed (see retry.rs), and with --retry-writes so is a write. The retried offsets are listed in the summary.
+    // Read errors : By default a failed read ends the copy. With --noerror, the chunk that couldn't be read is filled with the --error-fill byte instead, the input is moved past it, and the copy carries on (skip_unreadable). Every bad region is logged as it happens and listed again in the summary, with offsets in the input.
+    // Sparse output : With --sparse, a chunk that is all zeros is not written. We seek over it instead, which leaves a hole in the same freshly truncated regular files that excluded ranges may leave holes in. At the end the file is extended to its full length, in case it ends with a hole.
+    // Discarding zeros : With --discard-zeros, a chunk that is all zeros is deallocated in the output instead (see discard.rs), which works for devices and for outputs updated in place too. It still counts as written, since the output reads back as zeros.
+    // Fast paths : Between two regular files, when no option needs to see the data, the blocks are moved by the kernel instead (copy_file_range, sendfile, or a reflink of the whole file; see fastpath.rs). copy_span_fast keeps the same block counters, checkpoints and checks as the normal loop. If the kernel can't copy between the two files, the normal loop runs after all.
+    // BlockCopier : The read/write loop lives in this struct together with its buffers and counters, so it can be run once for a normal copy or once per range.
+    // Page cache hints : If a --target-profile asks for it, we advise the kernel that the input is read sequentially, and that each output block can be dropped from the cache once written (see utils/fadvise.rs).
+    // CopyStats : The counters of the copy are collected in this struct and returned to the caller, so library users get the numbers without having to parse our output. It can be serialized with serde.
+    // Input guard : With --check-input-stable, the input is stat'ed before it's opened and again after the copy (see guard.rs). If it changed in between, we warn or fail.
+    // Flush probes : With --flush-probe, every N written bytes the output is flushed with sync_data and timed (see flushprobe.rs). If writes are acknowledged far faster than the device stores them, we warn that the progress is misleading.
+    // Following : With --follow, the end of the input isn't the end of the copy: copy_span waits there for the input to grow (see follow.rs), until --until-size, --until-idle or Ctrl-C. A short read doesn't end a block then, since it only means we caught up with whoever writes the input. Only this loop follows; the fast path is left out.
+    // Wear : run_copy adds what it wrote to a removable drive to the drive's write history, and warns when the drive has absorbed more than --wear-limit (see wear.rs).
+    // Capacity : Once both files are open, an output device is checked against what the copy will write to it, and one that's too small fails the copy before anything is written (see capacity.rs). The size of an input device is read the same way, for the progress bar.
+    // System drives : Before anything is opened, the output is checked with check_target (see safety.rs). The drive the running system lives on, or one with mounted filesystems, is refused without --force.
+    // Raw devices : On Windows, \\.\PhysicalDriveN and \\.\X: are opened shared and never truncated, blocks must be whole sectors of them, and the volumes on an output device are locked and dismounted before anything is written (see utils/rawdevice.rs).
+    // Direct I/O : With --direct, both files are opened with O_DIRECT (F_NOCACHE on macOS) and the buffers are page-aligned. Blocks must be whole sectors, which is checked right after opening. A final partial block is written through the page cache instead, since a direct write of it would fail.
+    // Open timeout : With --open-timeout, both files are opened through open_with_timeout (see open.rs), so a device node that hangs in the kernel produces an error instead of a frozen rdd.
+    // Resume : With --resume, the position of the copy is committed to a state file every few seconds, and a copy that was interrupted continues from there (see checkpoint.rs). The output is then opened without truncating it.
+    // Drive health : With --monitor-health or --max-temp, the drives' temperature and error counters are read every few seconds at the end of a block and shown in the progress bar (see health.rs). A drive that is too hot pauses the copy right there, between two blocks.
+    // Power : Before the copy we check whether a laptop runs on battery, and during it whether the system was suspended (see power.rs). A read that fails right after a suspend is retried once, after the devices had a moment to wake up.
+    // Signals : Before every chunk, the loop checks whether SIGUSR1 asked for the status or Ctrl-C asked it to stop (see utils/signals.rs). A stopped copy syncs the output and, with --resume, saves its checkpoint at the last complete block before it returns the error.
+    // Bandwidth log : With --bandwidth-log, every chunk read and written is counted in a Meter, which a thread samples into a CSV file every second while the transfer runs (see bandwidth.rs). The parallel and io_uring engines count theirs the same way.
+    // Block size tuning : With --bs auto, the loop's block size is its own (BlockCopier::block_size) rather than config.block_size, and end_block asks the tuner in autotune.rs whether the next block gets another one. The buffers are allocated for the largest size it may pick. The chosen size is reported with the summary.
+    // Conversions : With --conv, every chunk is converted right after it's read (see transform.rs), so everything after that sees the converted data, as it's written.
+    // Rate limit : With --rate-limit, every chunk read passes through a token bucket (see throttle.rs), which sleeps whenever the copy gets ahead of the limit.
+    // Write cache : With --disable-write-cache, the target drive's volatile write cache is turned off right after the files are opened and restored when the copy returns (see writecache.rs).
+    // Verification : With --verify, every byte we write is hashed as we go and the output regions we wrote are recorded (see hash.rs). After the final sync those regions are read back and hashed again, and a mismatch fails the copy.
+    // Hash files : With --hash-output, the digests of the data are written after the final sync in the format of sha256sum and b3sum, naming the output (see hash.rs).
+    // Manifests : With --manifest-out, the data is hashed as it goes by even without --verify, and a manifest of the copy's settings and digest is written at the end (see manifest.rs). A copy replayed with --from-manifest fails if its digest differs from the recorded one.
+    // Boot check : With --check-bootable, the start of the output is inspected after the final sync for MBR, GPT and ISO boot markers (see bootcheck.rs), and the verdict is added to the summary.
+    // FAT check : With --check-fat (or --target-profile sd-clone), the FAT and exFAT filesystems are read back from the output and their structures checked (see fatcheck.rs). Problems the input doesn't have fail the copy.
+    // Resource limits : Before it picks an engine, run_copy checks the files, threads and buffer memory the copy needs against the system's limits (see limits.rs). It may raise the open-files limit or lower --max-in-flight, and otherwise fails before anything is written.
+    // run_copy : The entry point for the binary and for library users alike. It picks the io_uring engine (engine.rs) for --engine io-uring, and otherwise this single-threaded copy or the parallel one in parallel.rs from config.threads.
+    // Shared steps : Opening the files (open_files) and everything after the transfer (finish_copy) are also used by the parallel copy in parallel.rs, so both behave the same apart from the transfer itself.
+    // Reporting : Nothing here prints directly. Notes, warnings, the summary and the JSON results all go to config.reporter (see reporter.rs), so a program embedding rdd decides where they end up.
+    // Summary : Once the copy is done, we print the elapsed time and throughput, plus the CPU time the copy consumed (see utils/cpu.rs). The CPU snapshot is taken after the files are opened, so it measures the copy itself.
+    // Custody log : With --custody-log, the whole copy runs inside custody::logged, which appends a hash-chained entry before it starts and one with the outcome after it ends (see custody.rs).
+    // Block hashes : With --block-hashes or --compare-manifest, every logical block is also hashed on its own as it's read (see blockhash.rs). The hashes are written, or the differing blocks listed, with the summary.
+    // JSON stats : With --stats-format json, the summary lines are left out and a single JSON object with the same numbers is printed instead, or written to --stats-file (see statsjson.rs).
     // output_file.sync_all()?: this is crucial for data integrity. It tells the operating system to flush all its internal write caches to the physical disk. This ensures that when rdd exits, the data is safely stored. IT's the equivalent of dd's conv=fsync.
+    // Directory sync : Syncing a file doesn't sync the directory entry that names it. For a regular-file output we also sync its parent directory (on Unix), so a newly created file doesn't vanish after a power loss. --no-sync-dir turns this off.
+    // Sync mode : --sync-mode none leaves out the final sync and the directory sync, and interval and per-block also sync along the way: every write is counted by a Syncer (see syncmode.rs), in the block loop and the fast path alike, and the coalescer is flushed before a sync so it covers everything written so far.
 
 // src/core/copy.rs
 
 // src/core/copy.rs
 
+use crate::cli::{Engine, HashAlgorithm, StatsFormat};
 use crate::config::CopyConfig;
-use crate::error::RddResult;
+use crate::core::autotune::{largest_block_size, BlockSizeTuner, TuningStats};
+use crate::core::bandwidth::{BandwidthLog, Meter};
+use crate::core::blockhash::{BlockCompareStats, BlockHasher, BlockHashes};
+use crate::core::bootcheck::{self, BootReport};
+use crate::core::capacity::{check_capacity, input_size};
+use crate::core::fatcheck::{self, FilesystemCheck};
+use crate::core::checkpoint::Checkpointer;
+use crate::core::coalesce::{CoalesceStats, Coalescer};
+use crate::core::compress::Filters;
+use crate::core::custody;
+use crate::core::discard::Discarder;
+use crate::core::engine::run_io_uring_copy;
+use crate::core::fastpath::{self, FastPath};
+use crate::core::flushprobe::{FlushProbe, FlushProbeStats};
+use crate::core::follow::{FollowStats, Follower};
+use crate::core::guard::{check_input_stable, InputSnapshot};
+use crate::core::safety::check_target;
+use crate::core::wear::WearTracker;
+use crate::core::hash::{write_hash_files, CopyVerifier, DataDigest, VerificationReport};
+use crate::core::manifest;
+use crate::core::limits;
+use crate::core::mapfile::{format_offset, MapFile};
+use crate::core::health::{HealthMonitor, HealthStats};
+use crate::core::open::{open_stdin, open_stdout, open_with_timeout};
+use crate::core::parallel::run_parallel_copy;
+use crate::core::power::{PowerMonitor, PowerStats};
+use crate::core::priority;
+use crate::core::ranges::ByteRange;
+use crate::core::reporter::progress_bar;
+use crate::core::retry::{self, IoOperation, RetriedIo};
+use crate::core::scheduler::BackpressureStats;
+use crate::core::simulate::{SimulatedDevice, SimulationStats};
+use crate::core::statsjson;
+use crate::core::syncmode::{self, SyncMode, Syncer};
+use crate::core::throttle::Throttle;
+use crate::core::transform::Transform;
+use crate::core::writecache::WriteCacheGuard;
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::cpu::CpuUsage;
+use crate::utils::diagnostics::Diagnostic;
+use crate::utils::direct::{self, AlignedBuffer};
+use crate::utils::fadvise::{advise_dont_need, advise_sequential};
+use crate::utils::rawdevice;
+use crate::utils::signals;
+use indicatif::{BinaryBytes, ProgressBar};
+use serde::{Deserialize, Serialize};
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Runs the copy described by `config`: in the engine it asks for, with
+/// separate reader and writer threads when it asks for two or more, in
+/// this thread otherwise.
+///
+/// This is the entry point for running a copy from a program embedding rdd.
+pub fn run_copy(config: &CopyConfig) -> RddResult<CopyStats> {
+    config.reporter.message(&format!(
+        "Starting copy from '{}' to '{}' with block size {} bytes{}.",
+        config.input_file,
+        config.output_file,
+        config.block_size,
+        if config.auto_block_size { " (tuned as the copy goes, --bs auto)" } else { "" }
+    ));
+    // Make sure the system lets the copy have its files, threads and
+    // buffers before anything is written (see limits.rs).
+    let fitted = limits::fit(config)?;
+    // With --priority-ranges, the copy follows a ranges list that starts
+    // with them (see priority.rs).
+    let config = &*priority::plan(&fitted)?;
+    // A removable output drive's write history (see wear.rs).
+    let wear = WearTracker::start(config.wear.as_ref(), &config.output_file, config.reporter.as_ref());
+    let stats = match config.engine {
+        Engine::IoUring => run_io_uring_copy(config),
+        Engine::Standard if config.threads > 1 => run_parallel_copy(config),
+        Engine::Standard => run_singlethreaded_copy(config),
+    }?;
+    if let Some(wear) = wear {
+        wear.record(stats.bytes_written);
+    }
+    Ok(stats)
+}
 
 /// Runs the core copy operation in a single thread.
 ///
 /// This function orchestrates the entire copy process: opening files, seeking to
 /// the correct positions, and executing the main read/write loop.
-pub fn run_singlethreaded_copy(config: &CopyConfig) -> RddResult<()> {
-    // Open the input file for reading.
-    let mut input_file = File::open(&config.input_file)?;
+pub fn run_singlethreaded_copy(config: &CopyConfig) -> RddResult<CopyStats> {
+    custody::logged(config, || copy_singlethreaded(config))
+}
+
+fn copy_singlethreaded(config: &CopyConfig) -> RddResult<CopyStats> {
+    // Remember what the input looked like before we start reading it.
+    let input_snapshot = take_input_snapshot(config)?;
+
+    let (mut input_file, mut output_file, filters) = open_files(config)?;
+    if config.follow.is_some() && !input_file.metadata().with_path(&config.input_file)?.is_file() {
+        return Err(RddError::Config(format!(
+            "--follow needs an input file that grows; '{}' isn't a regular file (or is read through a decompressor).",
+            config.input_file
+        )));
+    }
+
+    // Keep the target's write cache off until we return, synced or not.
+    let _write_cache = match config.disable_write_cache {
+        true => Some(WriteCacheGuard::disable(config)?),
+        false => None,
+    };
+
+    // With --resume, pick up the state of an interrupted run, if there is one.
+    let checkpoint = match &config.resume {
+        Some(path) => Some(Checkpointer::open(config, path, &output_file)?),
+        None => None,
+    };
+
+    let timer = CopyTimer::start();
+
+    // Excluded regions (and zero chunks, with --sparse) can be left as holes
+    // only in a freshly truncated regular file; anything else needs real
+    // zeros written over them.
+    let in_place = config.write_if_changed || config.ranges.is_some();
+    let holes_allowed = config.truncate && !in_place && output_file.metadata()?.is_file();
+
+    let progress = progress_bar(&*config.reporter, filters.expected_bytes(config, &input_file), config.show_progress);
+    let direct_align = direct_alignment(config, &output_file)?;
+    let bandwidth = BandwidthLog::start(config)?;
+    let discarder = config.discard_zeros.then(|| Discarder::new(config, &output_file));
+    let mut copier = BlockCopier::new(
+        config,
+        progress,
+        holes_allowed,
+        direct_align,
+        checkpoint,
+        discarder,
+        bandwidth.meter(),
+    );
+
+    match &config.ranges {
+        // --- Ranges mode ---
+        // Copy each listed range to the same offset in the output.
+        Some(ranges) => {
+            for range in ranges {
+                input_file.seek(SeekFrom::Start(range.offset))?;
+                output_file.seek(SeekFrom::Start(range.offset))?;
+                copier.copy_span(&mut input_file, &mut output_file, None, Some(range.length))?;
+            }
+        }
+        None => {
+            // --- Handle seek/skip options ---
+            skip_input(config, &mut input_file)?;
+            let (resumed_bytes, resumed_blocks) = copier.skip_resumed(&mut input_file)?;
+
+            // Move the cursor in the output file if `seek` is specified, and
+            // past what an interrupted run already copied.
+            if config.seek_bytes > 0 || resumed_bytes > 0 {
+                let seek_bytes = config.seek_bytes + resumed_bytes;
+                output_file
+                    .seek(SeekFrom::Start(seek_bytes))
+                    .at_offset(&config.output_file, seek_bytes)?;
+            }
+
+            // Following the input, blocks are only cut short at the very end,
+            // so `count` and --until-size are byte limits alike.
+            if let Some(follow) = config.follow {
+                let bs = config.block_size as u64;
+                let limits = [
+                    (config.count > 0).then(|| config.count * bs),
+                    config.count_bytes,
+                    follow.until_size.map(|size| size.saturating_sub(config.skip_bytes)),
+                ];
+                let max_bytes = limits.into_iter().flatten().min();
+                copier.copy_span(&mut input_file, &mut output_file, None, max_bytes)?;
+            // With exclusions, a block may be split around an excluded range,
+            // so `count` is applied as a byte limit instead of a block limit.
+            } else if config.count > 0 && !config.excluded.is_empty() {
+                let max_bytes = config.count * config.block_size as u64;
+                copier.copy_span(&mut input_file, &mut output_file, None, Some(max_bytes))?;
+            } else {
+                let max_blocks = (config.count > 0).then_some(config.count.saturating_sub(resumed_blocks));
+                let max_bytes = config.count_bytes.map(|bytes| bytes.saturating_sub(resumed_bytes));
+                // Let the kernel move the data if it can; otherwise (or if it
+                // turns out it can't) use the read/write loop.
+                let fast = match fastpath::eligible(config) {
+                    true => FastPath::new(&input_file, &output_file),
+                    false => None,
+                };
+                let copied = match fast {
+                    Some(fast) => copier.copy_span_fast(fast, &mut input_file, &mut output_file, max_blocks, max_bytes)?,
+                    None => false,
+                };
+                if copied {
+                    // The kernel moved the data, so there was no block size to tune.
+                    copier.tuner = None;
+                } else {
+                    copier.copy_span(&mut input_file, &mut output_file, max_blocks, max_bytes)?;
+                }
+            }
 
-    // Open the output file for writing, creating it if it doesn't exist.
-    // We truncate it by default, mimicking dd's behavior.
-    let mut output_file = OpenOptions::new()
+            // If the copy ended inside an excluded range or a zero chunk that
+            // we left as a hole, extend the file so it still has the right length.
+            if holes_allowed {
+                let end = output_file.stream_position()?;
+                if output_file.metadata()?.len() < end {
+                    output_file.set_len(end)?;
+                }
+            }
+
+            // In write-if-changed mode the output was not truncated, so a regular
+            // file may still have stale bytes past the end of what we copied.
+            if config.write_if_changed && output_file.metadata()?.is_file() {
+                let end = output_file.stream_position()?;
+                output_file.set_len(end)?;
+            }
+        }
+    }
+
+    bandwidth.finish();
+    let mut stats = copier.stats;
+    stats.flush_probe = copier.flush_probe.map(|probe| probe.totals);
+    stats.follow = copier.follower.map(|follower| follower.totals);
+    stats.health = copier.health.map(|health| health.totals);
+    stats.power = Some(copier.power.totals);
+    stats.simulation = copier.device.as_ref().map(SimulatedDevice::stats);
+    stats.block_size_tuning = copier.tuner.map(BlockSizeTuner::finish);
+    // A copy the kernel did on a fast path had nothing to gather.
+    stats.coalesced = copier
+        .coalescer
+        .as_ref()
+        .map(Coalescer::stats)
+        .filter(|coalesced| coalesced.writes > 0);
+    if let Some(block_hasher) = copier.block_hasher {
+        (stats.block_hashes, stats.block_compare) = block_hasher.finish();
+    }
+
+    // Wait for the compressor to write out the rest; what gets synced is
+    // the file it wrote.
+    let output_file = filters.finish(input_file, output_file)?;
+    let stats = finish_copy(
+        config,
+        &output_file,
+        &copier.progress,
+        input_snapshot.as_ref(),
+        copier.verifier,
+        stats,
+        timer,
+    )?;
+
+    // The copy is complete (and verified), so there's nothing left to resume.
+    if let Some(checkpoint) = copier.checkpoint {
+        checkpoint.finish()?;
+    }
+    Ok(stats)
+}
+
+/// Stats the input before the copy, if --check-input-stable asks for it.
+pub(crate) fn take_input_snapshot(config: &CopyConfig) -> RddResult<Option<InputSnapshot>> {
+    match config.input_guard {
+        Some(_) => Ok(Some(InputSnapshot::take(&config.input_file)?)),
+        None => Ok(None),
+    }
+}
+
+/// Opens the input for reading and the output for writing, through the
+/// decompressor and compressor the copy needs, if any.
+pub(crate) fn open_files(config: &CopyConfig) -> RddResult<(File, File, Filters)> {
+    // Open the input file for reading, and the output file for writing,
+    // creating it if it doesn't exist. Opens are bounded by --open-timeout,
+    // since a dying device can hang inside the kernel.
+    // We truncate the output by default, mimicking dd's behavior, unless
+    // --no-truncate, --seek or --append keep it (config.truncate). Write-if-changed
+    // and ranges mode both update an existing output in place, so they leave it
+    // untouched. Write-if-changed also needs to read the existing contents.
+    // With --resume, the output is kept too, and cut back to the last
+    // checkpoint once it's open (see checkpoint.rs).
+    let in_place = config.write_if_changed || config.ranges.is_some();
+    let mut input_options = OpenOptions::new();
+    input_options.read(true);
+    let mut output_options = OpenOptions::new();
+    output_options
+        .read(config.write_if_changed)
         .write(true)
+        .append(config.append)
         .create(true)
-        .truncate(true)
-        .open(&config.output_file)?;
+        .truncate(config.truncate && !in_place && config.resume.is_none());
+    // With --direct, both files bypass the page cache (see utils/direct.rs).
+    if config.direct_io() {
+        direct::enable(&mut input_options);
+        direct::enable(&mut output_options);
+    }
+    // Raw devices on Windows must be opened shared, and a device can't be
+    // truncated or created (see utils/rawdevice.rs).
+    let raw_input = rawdevice::raw_device(&config.input_file);
+    let raw_output = rawdevice::raw_device(&config.output_file);
+    if raw_input.is_some() {
+        rawdevice::share(&mut input_options);
+    }
+    if raw_output.is_some() {
+        rawdevice::share(&mut output_options);
+        output_options.create(false).truncate(false);
+    }
+
+    // Nothing is opened if the output is the system drive or a drive with
+    // mounted filesystems, unless --force says so (see safety.rs).
+    if !config.output_is_stdout() && !config.output_is_network() {
+        check_target(&config.output_file, config.force, config.reporter.as_ref())?;
+    }
+
+    // A compressed input is read from a decompressor, and --compress writes
+    // into a compressor (see compress.rs); chunk sets and --split-size go
+    // through the joiner and the splitter (see split.rs).
+    let mut filters = Filters::default();
+    let input_file = match filters.open_input(config)? {
+        Some(stream) => stream,
+        None if config.input_is_stdin() => open_stdin()?,
+        None => open_with_timeout(&input_options, &config.input_file, config.open_timeout)?,
+    };
+    // --records reads only some lines of it (see records.rs).
+    let input_file = filters.slice_records(config, input_file)?;
+
+    let output_file = match filters.open_output(config, &output_options)? {
+        Some(pipe) => pipe,
+        None if config.output_is_stdout() => open_stdout()?,
+        None => open_with_timeout(&output_options, &config.output_file, config.open_timeout)?,
+    };
+
+    if config.direct_io() {
+        check_direct_alignment(config, &input_file, &output_file)?;
+    }
+    if raw_input.is_some() {
+        check_sector_alignment(config, &input_file, &config.input_file, config.skip_bytes)?;
+    }
+    if let Some(device) = raw_output {
+        check_sector_alignment(config, &output_file, &config.output_file, config.seek_bytes)?;
+        let volumes = rawdevice::lock_for_writing(&output_file, device).with_path(&config.output_file)?;
+        if !volumes.is_empty() {
+            let letters: Vec<_> = volumes.iter().map(|letter| format!("{}:", letter)).collect();
+            config
+                .reporter
+                .message(&format!("Locked and dismounted {} for writing.", letters.join(", ")));
+        }
+    }
+
+    // A device too small for the copy fails it now rather than at its end
+    // (see capacity.rs).
+    check_capacity(config, &input_file, &output_file, &filters)?;
 
-    // --- Handle seek/skip options ---
-    // Move the cursor in the input file if `skip` is specified.
-    if config.skip > 0 {
-        let skip_bytes = config.skip * config.block_size as u64;
-        input_file.seek(SeekFrom::Start(skip_bytes))?;
+    // Appended writes land at the end whatever the position says. Start
+    // there, so the regions recorded for --verify are where the data went.
+    if config.append && !config.output_is_stream() {
+        (&output_file).seek(SeekFrom::End(0)).with_path(&config.output_file)?;
     }
 
-    // Move the cursor in the output file if `seek` is specified.
-    if config.seek > 0 {
-        let seek_bytes = config.seek * config.block_size as u64;
-        output_file.seek(SeekFrom::Start(seek_bytes))?;
+    // --- Apply page cache hints from the target profile ---
+    if config.fadvise.sequential_input {
+        advise_sequential(&input_file);
     }
 
-    // --- Main Copy Loop ---
-    // Create a buffer with the specified block size.
-    // Using `vec!` is fine, but `with_capacity` followed by `set_len` can be
-    // slightly more performant for very large block sizes, though it requires `unsafe`.
-    // For clarity and safety, `vec!` is preferred here.
-    let mut buffer = vec![0; config.block_size];
-    let mut blocks_copied = 0u64;
+    Ok((input_file, output_file, filters))
+}
+
+/// Makes sure direct I/O can work with these files: the platform must
+/// support it, and every block and chunk must be a whole number of sectors.
+fn check_direct_alignment(config: &CopyConfig, input: &File, output: &File) -> RddResult<()> {
+    direct::after_open(input).with_path(&config.input_file)?;
+    direct::after_open(output).with_path(&config.output_file)?;
 
-    loop {
-        // Check if the `count` limit has been reached.
-        if config.count > 0 && blocks_copied >= config.count {
-            break;
+    let files = [
+        (input, &config.input_file, config.skip_bytes),
+        (output, &config.output_file, config.seek_bytes),
+    ];
+    for (file, path, offset) in files {
+        let sector = direct::logical_sector_size(file).with_path(path)?;
+        let chunk_size = config.block_size.min(config.max_io);
+        if sector > 0 && (!config.block_size.is_multiple_of(sector) || !chunk_size.is_multiple_of(sector)) {
+            return Err(RddError::Config(format!(
+                "--direct needs a block size (and --max-io) that is a multiple of {} bytes, the logical sector size of '{}'.",
+                sector, path
+            )));
+        }
+        if sector > 0 && !offset.is_multiple_of(sector as u64) {
+            return Err(RddError::Config(format!(
+                "--direct needs the copy to start at a multiple of {} bytes in '{}', its logical sector size, not at byte {}.",
+                sector, path, offset
+            )));
         }
+    }
+    Ok(())
+}
+
+/// Makes sure every block and chunk is a whole number of sectors of the raw
+/// device `file`, which can't be read or written in any other sizes.
+fn check_sector_alignment(config: &CopyConfig, file: &File, path: &str, offset: u64) -> RddResult<()> {
+    let sector = rawdevice::sector_size(file).with_path(path)?;
+    let chunk_size = config.block_size.min(config.max_io);
+    if sector > 0 && (!config.block_size.is_multiple_of(sector) || !chunk_size.is_multiple_of(sector)) {
+        return Err(RddError::Config(format!(
+            "'{}' is a raw device: the block size (and --max-io) must be a multiple of its sector size, {} bytes.",
+            path, sector
+        )));
+    }
+    if sector > 0 && !offset.is_multiple_of(sector as u64) {
+        return Err(RddError::Config(format!(
+            "'{}' is a raw device: the copy must start at a multiple of its sector size, {} bytes, not at byte {}.",
+            path, sector, offset
+        )));
+    }
+    Ok(())
+}
+
+/// The sector size writes to `output` must be aligned to, or `None` when
+/// direct I/O is off.
+pub(crate) fn direct_alignment(config: &CopyConfig, output: &File) -> RddResult<Option<usize>> {
+    if !config.direct_io() {
+        return Ok(None);
+    }
+    let sector = direct::logical_sector_size(output).with_path(&config.output_file)?;
+    Ok(Some(sector.max(1)))
+}
+
+/// Drops direct I/O on `output` before a transfer of `len` bytes that isn't
+/// a whole number of sectors, usually the partial block at the end of a copy.
+pub(crate) fn relax_direct_for(
+    config: &CopyConfig,
+    output: &File,
+    align: Option<usize>,
+    len: usize,
+) -> RddResult<()> {
+    if let Some(sector) = align
+        && !len.is_multiple_of(sector)
+    {
+        direct::relax(output).with_path(&config.output_file)?;
+    }
+    Ok(())
+}
+
+/// Moves the input to `skip_bytes`. Pipes and terminals can't seek, so
+/// there the skipped data is read and thrown away instead, like dd does.
+pub(crate) fn skip_input(config: &CopyConfig, input: &mut File) -> RddResult<()> {
+    let skip_bytes = config.skip_bytes;
+    if skip_bytes == 0 || input.seek(SeekFrom::Start(skip_bytes)).is_ok() {
+        return Ok(());
+    }
+
+    let skipped = io::copy(&mut Read::by_ref(input).take(skip_bytes), &mut io::sink())
+        .with_path(&config.input_file)?;
+    if skipped < skip_bytes {
+        return Err(RddError::Config(format!(
+            "Cannot skip {} bytes: the input ended after {} bytes.",
+            skip_bytes, skipped
+        )));
+    }
+    Ok(())
+}
+
+/// With --noerror: stands in for a read of `buf.len()` bytes at `pos` that
+/// failed. Logs the error, fills `buf` with the --error-fill byte, moves the
+/// input past the unreadable data and records it in `stats`. Returns how
+/// many bytes of `buf` to use, which is only smaller near the end of the
+/// input (and 0 past it). Fails once more reads have failed than
+/// --max-errors allows, after saving the map of the copy.
+pub(crate) fn skip_unreadable(
+    config: &CopyConfig,
+    input: &mut File,
+    buf: &mut [u8],
+    pos: u64,
+    error: io::Error,
+    progress: &ProgressBar,
+    stats: &mut CopyStats,
+) -> RddResult<usize> {
+    // Don't make the output longer than the input when the error is at
+    // the very end of a file.
+    let mut len = buf.len() as u64;
+    if let Ok(metadata) = input.metadata()
+        && metadata.is_file()
+    {
+        len = len.min(metadata.len().saturating_sub(pos));
+    }
+    if len == 0 {
+        return Ok(0);
+    }
+
+    input
+        .seek(SeekFrom::Current(len as i64))
+        .at_offset(&config.input_file, pos)?;
+    buf[..len as usize].fill(config.error_fill);
 
-        // Read a block from the input file into the buffer.
-        let bytes_read = input_file.read(&mut buffer)?;
+    let warning = Diagnostic::warning(format!(
+        "cannot read {} bytes of '{}' at offset {} ({}): {}; filled with 0x{:02X} and continuing.",
+        len,
+        config.input_file,
+        pos,
+        format_offset(pos),
+        error,
+        config.error_fill
+    ))
+    .with_flag("--noerror");
+    progress.suspend(|| config.reporter.diagnostic(&warning));
 
-        // If `read` returns 0, we've reached the end of the file.
-        if bytes_read == 0 {
-            break;
+    // Neighbouring bad chunks are one bad region.
+    match stats.bad_regions.last_mut() {
+        Some(last) if last.end() == pos => last.length += len,
+        _ => stats.bad_regions.push(ByteRange { offset: pos, length: len }),
+    }
+    stats.failed_reads += 1;
+    if let Some(max) = config.max_errors
+        && stats.failed_reads > max
+    {
+        let map = progress.suspend(|| save_error_map(config, input, pos + len, &stats.bad_regions));
+        return Err(RddError::TooManyErrors {
+            failed: stats.failed_reads,
+            max,
+            map,
+        });
+    }
+    Ok(len as usize)
+}
+
+/// With --max-errors: saves the map of a copy that gives up with the input
+/// read up to `reached`, to --error-map or the output's file name plus
+/// ".map". Returns where it went, or None (after a warning) if it couldn't
+/// be written.
+fn save_error_map(config: &CopyConfig, input: &File, reached: u64, bad_regions: &[ByteRange]) -> Option<String> {
+    let spans = match &config.ranges {
+        Some(ranges) => ranges.clone(),
+        // Without a known length, the untried rest goes as far as a map can.
+        None => vec![ByteRange {
+            offset: config.skip_bytes,
+            length: expected_bytes(config, input).unwrap_or(u64::MAX - config.skip_bytes),
+        }],
+    };
+    let path = config.error_map.clone().unwrap_or_else(|| {
+        match Path::new(&config.output_file).file_name() {
+            Some(name) if !config.output_is_stdout() && !config.output_is_network() => format!("{}.map", name.to_string_lossy()),
+            _ => "rdd.map".to_string(),
+        }
+    });
+    let command_line = std::env::args().collect::<Vec<_>>().join(" ");
+    match MapFile::of_copy(&spans, reached, bad_regions).write(&path, &command_line) {
+        Ok(()) => Some(path),
+        Err(e) => {
+            config
+                .reporter
+                .diagnostic(&Diagnostic::warning(format!("the map of the copy can't be saved: {}", e)).with_flag("--error-map"));
+            None
         }
+    }
+}
 
-        // Write the portion of the buffer that was filled to the output file.
-        // It's crucial to use `&buffer[..bytes_read]` because the last block
-        // may not be a full block.
-        output_file.write_all(&buffer[..bytes_read])?;
+/// The most bad regions listed one by one in the summary.
+const MAX_LISTED_BAD_REGIONS: usize = 10;
 
-        blocks_copied += 1;
+/// Lists the regions --noerror filled in, for the summary.
+fn report_bad_regions(config: &CopyConfig, regions: &[ByteRange]) {
+    let total: u64 = regions.iter().map(|r| r.length).sum();
+    report(config, format!(
+        "{} bytes in {} region(s) of the input could not be read and were filled with 0x{:02X}:",
+        total,
+        regions.len(),
+        config.error_fill
+    ));
+    for region in regions.iter().take(MAX_LISTED_BAD_REGIONS) {
+        report(config, format!(
+            "  offset {} ({}), {} bytes",
+            region.offset,
+            format_offset(region.offset),
+            region.length
+        ));
     }
+    if regions.len() > MAX_LISTED_BAD_REGIONS {
+        report(config, format!("  ... and {} more.", regions.len() - MAX_LISTED_BAD_REGIONS));
+    }
+}
 
+/// Lists the reads and writes that were retried, for the summary.
+fn report_retries(config: &CopyConfig, retried: &[RetriedIo]) {
+    let recovered = retried.iter().filter(|r| r.recovered).count();
+    report(config, format!(
+        "{} I/O operation(s) failed and were retried; {} succeeded on a retry:",
+        retried.len(),
+        recovered
+    ));
+    for retry in retried.iter().take(MAX_LISTED_BAD_REGIONS) {
+        report(config, retry.summary());
+    }
+    if retried.len() > MAX_LISTED_BAD_REGIONS {
+        report(config, format!("  ... and {} more.", retried.len() - MAX_LISTED_BAD_REGIONS));
+    }
+}
+
+/// Reports how the blocks read compare with a --compare-manifest file.
+fn report_block_compare(config: &CopyConfig, path: &str, compare: &BlockCompareStats) {
+    let mut line = if compare.differing_blocks == 0 {
+        format!("All {} blocks compared match the block hashes in '{}'", compare.compared, path)
+    } else {
+        format!(
+            "{} of the {} blocks compared differ from the block hashes in '{}'",
+            compare.differing_blocks, compare.compared, path
+        )
+    };
+    if compare.unrecorded > 0 {
+        line.push_str(&format!("; {} blocks had no recorded hash", compare.unrecorded));
+    }
+    report(config, format!("{}{}", line, if compare.differing.is_empty() { "." } else { ":" }));
+    for region in compare.differing.iter().take(MAX_LISTED_BAD_REGIONS) {
+        report(config, format!(
+            "  offset {} ({}), {} bytes",
+            region.offset,
+            format_offset(region.offset),
+            region.length
+        ));
+    }
+    if compare.differing.len() > MAX_LISTED_BAD_REGIONS {
+        report(config, format!("  ... and {} more.", compare.differing.len() - MAX_LISTED_BAD_REGIONS));
+    }
+}
+
+/// Everything that happens once the data has been transferred: the final
+/// sync, the input and boot checks, and the summary. Shared by the
+/// single-threaded and the parallel copy.
+pub(crate) fn finish_copy(
+    config: &CopyConfig,
+    output_file: &File,
+    progress: &ProgressBar,
+    input_snapshot: Option<&InputSnapshot>,
+    verifier: Option<CopyVerifier>,
+    mut stats: CopyStats,
+    timer: CopyTimer,
+) -> RddResult<CopyStats> {
     // Ensure all buffered data is written to the disk before exiting.
     // This is equivalent to dd's `conv=fsync`.
-    output_file.sync_all()?;
+    sync_output(config, output_file)?;
+    if config.sync_dir
+        && config.sync_mode != SyncMode::None
+        && !config.output_is_stdout()
+        && output_file.metadata()?.is_file()
+    {
+        sync_parent_dir(&config.output_file)?;
+    }
 
-    println!(
-        "{} blocks copied successfully.",
-        blocks_copied
-    );
+    progress.finish_and_clear();
+
+    // Read the output back and compare it with what we wrote. Without
+    // --verify, the data was only hashed for a manifest or a hash file.
+    if let Some(mut verifier) = verifier {
+        stats.hash_windows = verifier.take_windows();
+        if config.verification_algo.is_some() {
+            let mut reports = verifier.verify(config)?.into_iter();
+            stats.verification = reports.next();
+            stats.also_verified = reports.collect();
+            stats.digests = stats
+                .verification
+                .iter()
+                .chain(&stats.also_verified)
+                .map(|report| DataDigest {
+                    algorithm: report.algorithm,
+                    hex: report.source_digest.clone(),
+                })
+                .collect();
+        } else {
+            stats.digests = verifier.digests();
+        }
+        stats.data_digest = stats.digests.first().cloned();
+    }
+
+    // A copy replayed from a manifest must have read the same data.
+    if let (Some(expected), Some(actual)) = (&config.expected_digest, &stats.data_digest)
+        && expected != actual
+    {
+        return Err(RddError::ManifestMismatch {
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        });
+    }
+
+    // Make sure the input didn't change underneath us.
+    if let (Some(policy), Some(before)) = (config.input_guard, input_snapshot) {
+        check_input_stable(config, before, policy)?;
+    }
+
+    // Look at what we wrote and judge whether it can boot.
+    if config.check_bootable {
+        stats.boot = Some(bootcheck::inspect(&config.output_file)?);
+    }
+    // Read the FAT filesystems back from the card and check them.
+    if config.check_fat {
+        stats.filesystems = Some(fatcheck::check_output(config)?);
+    }
+
+    timer.stop(&mut stats);
+
+    if let Some(path) = &config.manifest_out {
+        manifest::write(path, config, &stats)?;
+    }
+    if let (Some(path), Some(hashes)) = (&config.block_hashes, &stats.block_hashes) {
+        hashes.write(path, &config.input_file)?;
+    }
+    if let (Some(path), Some(windows)) = (&config.hash_log, &stats.hash_windows) {
+        windows.write(path, &config.input_file)?;
+    }
+    let hash_files = match &config.hash_output {
+        Some(path) => write_hash_files(path, &config.output_file, &stats.digests)?,
+        None => Vec::new(),
+    };
+
+    if config.write_if_changed {
+        report(config, format!(
+            "{} blocks copied successfully ({} written, {} unchanged and skipped).",
+            stats.blocks_copied, stats.blocks_written, stats.blocks_unchanged
+        ));
+    } else {
+        report(config, format!(
+            "{} blocks copied successfully.",
+            stats.blocks_copied
+        ));
+    }
+    if !stats.bad_regions.is_empty() {
+        report_bad_regions(config, &stats.bad_regions);
+    }
+    if !stats.retried.is_empty() {
+        report_retries(config, &stats.retried);
+    }
+    if stats.bytes_resumed > 0 {
+        report(config, format!(
+            "Resumed an interrupted copy: the first {} bytes were copied by an earlier run.",
+            stats.bytes_resumed
+        ));
+    }
+    if stats.bytes_sparse > 0 {
+        report(config, format!(
+            "{} bytes of zeros were skipped over and left as holes in the output.",
+            stats.bytes_sparse
+        ));
+    }
+    if stats.bytes_discarded > 0 {
+        report(config, format!(
+            "{} bytes of zeros were discarded in the output instead of written.",
+            stats.bytes_discarded
+        ));
+    }
+    if stats.bytes_excluded > 0 {
+        report(config, format!(
+            "{} bytes in excluded ranges were not read and are zeroed in the output.",
+            stats.bytes_excluded
+        ));
+    }
+    if config.sync_mode == SyncMode::None && !config.output_is_stdout() && !config.output_is_network() {
+        report(config, "The output wasn't synced (--sync-mode none): part of it may still be in the system's write cache, and a crash now would lose it.".to_string());
+    }
+    if let Some(probe) = &stats.flush_probe {
+        report(config, format!("{}.", probe.summary()));
+    }
+    if let Some(follow) = &stats.follow {
+        report(config, format!("{}.", follow.summary()));
+    }
+    if let Some(tuning) = &stats.block_size_tuning {
+        report(config, format!("{}.", tuning.summary()));
+    }
+    if let Some(health) = &stats.health {
+        report(config, format!("{}.", health.summary()));
+    }
+    if let Some(power) = stats.power.as_ref().and_then(PowerStats::summary) {
+        report(config, format!("{}.", power));
+    }
+    if let Some(simulation) = &stats.simulation {
+        report(config, format!("{}.", simulation.summary()));
+    }
+    if let Some(coalesced) = &stats.coalesced {
+        report(config, format!("{}.", coalesced.summary()));
+    }
+    if let Some(backpressure) = stats.backpressure.as_ref().and_then(BackpressureStats::summary) {
+        report(config, format!("{}.", backpressure));
+    }
+    for verification in stats.verification.iter().chain(&stats.also_verified) {
+        report(config, format!("{}.", verification.summary()));
+    }
+    if let Some(boot) = &stats.boot {
+        report(config, format!("{}.", boot.summary()));
+    }
+    for check in stats.filesystems.iter().flatten() {
+        report(config, format!("{}.", check.summary()));
+    }
+    if let (Some(expected), Some(_)) = (&config.expected_digest, &stats.data_digest) {
+        report(config, format!("The data read matches the manifest ({}).", expected));
+    }
+    if let Some(path) = &config.manifest_out {
+        report(config, format!("Reproducibility manifest written to '{}'.", path));
+    }
+    if !hash_files.is_empty() {
+        report(config, format!("Digest of the data written to '{}'.", hash_files.join("', '")));
+    }
+    if let (Some(path), Some(hashes)) = (&config.block_hashes, &stats.block_hashes) {
+        report(config, format!("Hashes of {} blocks written to '{}'.", hashes.blocks.len(), path));
+    }
+    if let (Some(path), Some(windows)) = (&config.hash_log, &stats.hash_windows) {
+        report(config, format!(
+            "Hashes of {} windows of {} bytes written to '{}'.",
+            windows.blocks.len(),
+            windows.block_size,
+            path
+        ));
+    }
+    if let (Some(path), Some(compare)) = (&config.compare_manifest, &stats.block_compare) {
+        report_block_compare(config, path, compare);
+    }
+    print_summary(config, stats.bytes_copied, stats.elapsed, stats.cpu.as_ref());
+    if config.stats_format == StatsFormat::Json {
+        statsjson::emit(config, &stats)?;
+    }
+
+    // The output is complete; blocks that no longer match their recorded
+    // hashes still fail the copy, so scripts notice.
+    if let (Some(path), Some(compare)) = (&config.compare_manifest, &stats.block_compare)
+        && compare.differing_blocks > 0
+    {
+        return Err(RddError::BlockHashMismatch {
+            path: path.clone(),
+            blocks: compare.differing_blocks,
+        });
+    }
+
+    Ok(stats)
+}
+
+/// Flushes the output to the device, unless --sync-mode none says not to.
+/// Pipes and terminals can't be synced, and have nothing to sync anyway.
+pub(crate) fn sync_output(config: &CopyConfig, output_file: &File) -> RddResult<()> {
+    let is_stream = config.output_is_stream() && !output_file.metadata()?.is_file();
+    if !is_stream && config.sync_mode != SyncMode::None {
+        output_file.sync_all().with_path(&config.output_file)?;
+    }
+    Ok(())
+}
 
+/// Syncs the directory that contains `path`, so the directory entry of a
+/// newly created file is on disk too.
+#[cfg(unix)]
+fn sync_parent_dir(path: &str) -> RddResult<()> {
+    let dir = match std::path::Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => std::path::Path::new("."),
+    };
+    let dir_path = dir.to_string_lossy();
+    File::open(dir).with_path(&dir_path)?.sync_all().with_path(&dir_path)
+}
+
+/// Windows can't open a directory as a plain file, and NTFS journals new
+/// directory entries on its own.
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &str) -> RddResult<()> {
     Ok(())
 }
+
+/// Measures the wall-clock and CPU time of a copy.
+pub(crate) struct CopyTimer {
+    started: Instant,
+    cpu_start: Option<CpuUsage>,
+}
+
+impl CopyTimer {
+    pub(crate) fn start() -> Self {
+        Self {
+            started: Instant::now(),
+            cpu_start: CpuUsage::now(),
+        }
+    }
+
+    /// Records the time taken so far in `stats`.
+    fn stop(self, stats: &mut CopyStats) {
+        stats.elapsed = self.started.elapsed();
+        stats.cpu = match (CpuUsage::now(), self.cpu_start) {
+            (Some(end), Some(start)) => Some(end.since(&start)),
+            _ => None,
+        };
+    }
+}
+
+/// The result of a finished copy.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CopyStats {
+    /// Blocks read from the input (including partial blocks).
+    pub blocks_copied: u64,
+    /// Blocks that ended short of the block size, at the end of the input
+    /// or after a short read.
+    pub partial_blocks: u64,
+    /// Blocks actually written to the output.
+    pub blocks_written: u64,
+    /// Blocks left alone because the output already matched (write-if-changed).
+    pub blocks_unchanged: u64,
+    pub bytes_copied: u64,
+    /// Bytes written to the output. Less than `bytes_copied` when
+    /// write-if-changed or --sparse left some of them out.
+    pub bytes_written: u64,
+    /// Bytes in excluded ranges that were not read.
+    pub bytes_excluded: u64,
+    /// Regions of the input that couldn't be read and were filled (--noerror).
+    pub bad_regions: Vec<ByteRange>,
+    /// Reads that failed and were filled (--noerror), counted against
+    /// --max-errors. Neighbouring ones make up a single bad region.
+    pub failed_reads: u64,
+    /// Reads and writes that failed and were tried again (--retries).
+    pub retried: Vec<RetriedIo>,
+    /// Bytes an interrupted earlier run had already copied (--resume).
+    pub bytes_resumed: u64,
+    /// Bytes of all-zero chunks left as holes instead of written (--sparse).
+    pub bytes_sparse: u64,
+    /// Bytes of all-zero chunks deallocated in the output instead of
+    /// written (--discard-zeros).
+    pub bytes_discarded: u64,
+    pub elapsed: Duration,
+    /// CPU time consumed by the copy, where the platform reports it.
+    pub cpu: Option<CpuUsage>,
+    /// Apparent vs. stable write rates, if flush probes were enabled.
+    pub flush_probe: Option<FlushProbeStats>,
+    /// How the growing input was followed, with --follow.
+    pub follow: Option<FollowStats>,
+    /// Results of the bootability checks, if they were requested.
+    pub boot: Option<BootReport>,
+    /// The filesystems found in the output and what their check found, with
+    /// --check-fat.
+    pub filesystems: Option<Vec<FilesystemCheck>>,
+    /// Source and output digests, if --verify was given.
+    pub verification: Option<VerificationReport>,
+    /// Digest of the data read, with --verify or a manifest.
+    pub data_digest: Option<DataDigest>,
+    /// The data's digest in every algorithm it was hashed with, data_digest
+    /// first (--verify given more than once).
+    pub digests: Vec<DataDigest>,
+    /// The verifications with the further --verify algorithms.
+    pub also_verified: Vec<VerificationReport>,
+    /// Drive temperatures and errors, if --monitor-health was given.
+    pub health: Option<HealthStats>,
+    /// Waits for AC power and system suspends during the copy.
+    pub power: Option<PowerStats>,
+    /// What the simulated input device did, with --simulate-device.
+    pub simulation: Option<SimulationStats>,
+    /// How the writes were gathered, with --coalesce.
+    pub coalesced: Option<CoalesceStats>,
+    /// The block size --bs auto chose, and what it measured.
+    pub block_size_tuning: Option<TuningStats>,
+    /// How long the reader waited for slower writers (multithreaded copy).
+    pub backpressure: Option<BackpressureStats>,
+    /// The hash of every block read, with --block-hashes.
+    #[serde(skip)]
+    pub block_hashes: Option<BlockHashes>,
+    /// The hash of every window, with --hash-window.
+    #[serde(skip)]
+    pub hash_windows: Option<BlockHashes>,
+    /// How the blocks read compare with --compare-manifest.
+    pub block_compare: Option<BlockCompareStats>,
+}
+
+/// The block-by-block read/write loop, along with its buffers and counters.
+///
+/// Keeping this state in one place lets the same loop copy one contiguous
+/// span (the normal dd case) or many separate ranges.
+struct BlockCopier<'a> {
+    config: &'a CopyConfig,
+    progress: ProgressBar,
+    // Create a buffer with the specified block size (or `max_io`, if smaller).
+    // The buffer is page-aligned, as direct I/O requires (see utils/direct.rs).
+    buffer: AlignedBuffer,
+    // Bookkeeping for write-if-changed mode. The second buffer holds the
+    // current contents of the output block we are about to overwrite.
+    existing: AlignedBuffer,
+    // With --direct: the output's sector size, which transfers must be
+    // multiples of while it's bypassing the page cache.
+    direct_align: Option<usize>,
+    // Whether excluded regions may be skipped over as holes instead of
+    // being explicitly zeroed.
+    holes_allowed: bool,
+    // With --sparse: whether all-zero chunks are skipped over as holes.
+    sparse: bool,
+    // Deallocates all-zero chunks in the output (--discard-zeros).
+    discarder: Option<Discarder>,
+    // Periodic flushes that reveal the target's real write rate (--flush-probe).
+    flush_probe: Option<FlushProbe>,
+    // Hashes everything we write, for --verify.
+    verifier: Option<CopyVerifier>,
+    // Hashes every block on its own (--block-hashes, --compare-manifest).
+    block_hasher: Option<BlockHasher>,
+    // Commits the copy position to the state file (--resume).
+    checkpoint: Option<Checkpointer>,
+    // Gathers small writes into larger ones (--coalesce).
+    coalescer: Option<Coalescer>,
+    // Says when to sync the output along the way (--sync-mode).
+    syncer: Syncer,
+    // Waits at the end of an input that is still growing (--follow).
+    follower: Option<Follower>,
+    // The model the input is read through (--simulate-device).
+    device: Option<SimulatedDevice>,
+    // Watches the drives' temperature and errors (--monitor-health).
+    health: Option<HealthMonitor>,
+    // Watches for battery power and system sleep.
+    power: PowerMonitor,
+    // Keeps the copy under --rate-limit.
+    throttle: Option<Throttle>,
+    // Converts the data with --conv.
+    transform: Option<Transform>,
+    // Counts what's read and written for the bandwidth log.
+    meter: Arc<Meter>,
+    // The size of the logical blocks: config.block_size, unless --bs auto
+    // changes it as the copy goes.
+    block_size: usize,
+    // Picks the block size with --bs auto.
+    tuner: Option<BlockSizeTuner>,
+    // Index of the first excluded range that may still lie ahead of us.
+    next_exclusion: usize,
+    stats: CopyStats,
+}
+
+impl<'a> BlockCopier<'a> {
+    fn new(
+        config: &'a CopyConfig,
+        progress: ProgressBar,
+        holes_allowed: bool,
+        direct_align: Option<usize>,
+        checkpoint: Option<Checkpointer>,
+        discarder: Option<Discarder>,
+        meter: Arc<Meter>,
+    ) -> Self {
+        // Blocks larger than `max_io` are moved in several chunks, so the
+        // buffers never need to be bigger than one chunk. With --bs auto,
+        // they're big enough for the largest block size it may pick.
+        let chunk_size = match config.auto_block_size {
+            true => largest_block_size(config).min(config.max_io),
+            false => config.block_size.min(config.max_io),
+        };
+
+        let existing = if config.write_if_changed {
+            AlignedBuffer::new(chunk_size)
+        } else {
+            AlignedBuffer::new(0)
+        };
+
+        let power = PowerMonitor::start(config, &progress);
+
+        Self {
+            config,
+            progress,
+            buffer: AlignedBuffer::new(chunk_size),
+            existing,
+            holes_allowed,
+            sparse: config.sparse && holes_allowed,
+            discarder,
+            direct_align,
+            next_exclusion: 0,
+            flush_probe: config.flush_probe.map(FlushProbe::new),
+            verifier: CopyVerifier::for_copy(config),
+            block_hasher: (config.block_hashes.is_some() || config.expected_blocks.is_some()).then(|| {
+                BlockHasher::new(
+                    config.block_hashes.is_some(),
+                    config.expected_blocks.as_ref(),
+                    config.verification_algo.unwrap_or(HashAlgorithm::Sha256),
+                    config.block_size,
+                )
+            }),
+            checkpoint,
+            // Chunks that are already as big need no gathering.
+            coalescer: config.coalesce.filter(|&size| size > chunk_size).map(Coalescer::new),
+            syncer: Syncer::for_copy(config),
+            follower: config.follow.map(Follower::new),
+            device: config.device_model.clone().map(SimulatedDevice::new),
+            health: config.monitor_health.then(|| HealthMonitor::new(config)),
+            power,
+            throttle: config.rate_limit.map(Throttle::new),
+            transform: Transform::for_copy(config),
+            meter,
+            block_size: config.block_size,
+            tuner: config.auto_block_size.then(|| BlockSizeTuner::new(config)),
+            stats: CopyStats::default(),
+        }
+    }
+
+    /// Copies blocks from the current position of `input` to the current
+    /// position of `output` until end-of-file, or until `max_blocks` blocks
+    /// or `max_bytes` bytes have been copied, whichever comes first.
+    ///
+    /// Each logical block is transferred in chunks of at most `max_io` bytes,
+    /// so even a multi-gigabyte block size only needs a chunk-sized buffer.
+    fn copy_span(
+        &mut self,
+        input: &mut File,
+        output: &mut File,
+        max_blocks: Option<u64>,
+        max_bytes: Option<u64>,
+    ) -> RddResult<()> {
+        let config = self.config;
+        let mut blocks = 0u64;
+        let mut remaining = max_bytes;
+
+        // Progress through the current logical block. A block ends when it's
+        // full, or early when a read comes back short (like dd's partial blocks).
+        let mut block_filled = 0usize;
+        // In write-if-changed mode: whether any chunk of this block differed.
+        let mut block_changed = false;
+
+        // We track the input position ourselves to find excluded ranges.
+        // For regular files we also know where the input ends, so an excluded
+        // range at the very end doesn't make the output longer than the input.
+        // Pipes can't report a position, but there the bytes read so far are
+        // exactly the offset into the stream.
+        let mut in_pos = input.stream_position().unwrap_or(0);
+        let input_len = Some(input.metadata()?)
+            .filter(|m| m.is_file())
+            .map(|m| m.len());
+
+        // With --verify, remember where this span starts in the output, so
+        // it can be read back later. A verifier that only makes a digest
+        // (for a manifest) doesn't need it, and streams have no position.
+        let span_start = match (&self.verifier, config.verification_algo) {
+            (Some(_), Some(_)) => Some(output.stream_position().with_path(&config.output_file)?),
+            _ => None,
+        };
+
+        loop {
+            // Check if the `count` limit has been reached.
+            if max_blocks.is_some_and(|max| blocks >= max) || remaining == Some(0) {
+                break;
+            }
+
+            // Following the input, Ctrl-C ends it like the end of a file.
+            if let Some(follower) = self.follower.as_mut()
+                && follower.stop_requested()
+            {
+                if block_filled > 0 {
+                    self.end_block(block_filled, block_changed);
+                }
+                break;
+            }
+
+            // Print the status on SIGUSR1; on Ctrl-C, keep what we have and stop.
+            if let Err(e) = signals::check(&*self.config.reporter, &self.progress) {
+                self.flush_coalesced(output)?;
+                self.save_interrupted(output, block_filled)?;
+                return Err(e);
+            }
+
+            // Read at most one chunk, never past the end of the current block
+            // or of a byte-limited span.
+            let mut want = self.buffer.len().min(self.block_size - block_filled);
+            if let Some(left) = remaining {
+                want = want.min(left.min(usize::MAX as u64) as usize);
+            }
+
+            // --- Excluded ranges ---
+            match self.exclusion_at(in_pos) {
+                // We're inside an excluded range: don't read it at all, and
+                // zero (or leave a hole in) the matching part of the output.
+                Some(Exclusion::Inside(len)) => {
+                    let mut len = remaining.map_or(len, |left| left.min(len));
+                    if let Some(end) = input_len {
+                        len = len.min(end.saturating_sub(in_pos));
+                    }
+                    if len == 0 {
+                        if block_filled > 0 {
+                            self.end_block(block_filled, block_changed);
+                        }
+                        break;
+                    }
+
+                    // An excluded range ends the block we were in the middle of.
+                    if block_filled > 0 {
+                        self.end_block(block_filled, block_changed);
+                        blocks += 1;
+                        block_filled = 0;
+                        block_changed = false;
+                    }
+
+                    input.seek(SeekFrom::Current(len as i64))?;
+                    self.flush_coalesced(output)?;
+                    self.zero_output(output, len)?;
+
+                    in_pos += len;
+                    if let Some(left) = remaining.as_mut() {
+                        *left -= len;
+                    }
+                    self.stats.bytes_excluded += len;
+                    if let Some(verifier) = self.verifier.as_mut() {
+                        verifier.update_zeros(len);
+                    }
+                    self.progress.inc(len);
+                    continue;
+                }
+                // Stop this read where the next excluded range begins.
+                Some(Exclusion::Ahead(distance)) => {
+                    want = want.min(distance.min(usize::MAX as u64) as usize);
+                }
+                None => {}
+            }
+
+            // Read a chunk from the input file into the buffer. If the system
+            // just woke up from sleep, the device may need a moment first.
+            let mut result = read_input(&mut self.device, input, in_pos, &mut self.buffer[..want]);
+            if result.is_err() && self.power.settle_after_wake(&self.progress) {
+                result = read_input(&mut self.device, input, in_pos, &mut self.buffer[..want]);
+            }
+            // With --retries, give a flaky device a few more chances.
+            if let Err(e) = result {
+                let (device, buffer) = (&mut self.device, &mut self.buffer[..want]);
+                result = retry::again(config, IoOperation::Read, in_pos, e, &self.progress, &mut self.stats.retried, || {
+                    read_input(device, input, in_pos, buffer)
+                });
+            }
+            // Ctrl-C while waiting to read again stops the copy like
+            // anywhere else, at the top of the loop.
+            if result.is_err() && signals::stopping() {
+                continue;
+            }
+            let bytes_read = match result {
+                Err(e) if config.noerror => skip_unreadable(
+                    config,
+                    input,
+                    &mut self.buffer[..want],
+                    in_pos,
+                    e,
+                    &self.progress,
+                    &mut self.stats,
+                )?,
+                result => result.at_offset(&config.input_file, in_pos)?,
+            };
+
+            // If `read` returns 0, we've reached the end of the file.
+            // Anything already read of the current block is a partial block.
+            if bytes_read == 0 {
+                // Unless it's still growing: then wait for more, with what
+                // we have written out in the meantime.
+                if self.follower.is_some() {
+                    self.flush_coalesced(output)?;
+                    if let Some(follower) = self.follower.as_mut()
+                        && follower.wait_for_more(config, input, in_pos, &self.progress)?
+                    {
+                        continue;
+                    }
+                }
+                if block_filled > 0 {
+                    self.end_block(block_filled, block_changed);
+                }
+                break;
+            }
+
+            self.meter.read(bytes_read as u64);
+            if let Some(throttle) = self.throttle.as_mut() {
+                throttle.wait(bytes_read as u64);
+            }
+
+            relax_direct_for(config, output, self.direct_align, bytes_read)?;
+            if let Some(transform) = &self.transform {
+                transform.apply(&mut self.buffer[..bytes_read]);
+            }
+            let data = &self.buffer[..bytes_read];
+            if let Some(verifier) = self.verifier.as_mut() {
+                verifier.update(data);
+            }
+            if let Some(block_hasher) = self.block_hasher.as_mut() {
+                block_hasher.update(in_pos, data);
+            }
+            // How long the write call took, if we wrote anything.
+            let mut write_took = None;
+            // Whether the chunk was handed to the coalescer instead.
+            let mut coalesced = false;
+            if config.write_if_changed {
+                // Compare with what's really in the output.
+                if let Some(coalescer) = self.coalescer.as_mut() {
+                    coalescer.flush(config, output, self.direct_align, &self.progress, &mut self.stats.retried)?;
+                }
+                // Read what is currently stored at this position in the output.
+                let existing_len = read_full(output, &mut self.existing[..bytes_read])?;
+
+                if existing_len != bytes_read || self.existing[..bytes_read] != *data {
+                    // Step back over what we just read and overwrite it.
+                    output.seek(SeekFrom::Current(-(existing_len as i64)))?;
+                    let write_started = Instant::now();
+                    retry::write_all(config, output, data, &self.progress, &mut self.stats.retried)
+                        .with_path(&config.output_file)?;
+                    write_took = Some(write_started.elapsed());
+                    block_changed = true;
+                }
+                // Otherwise the destination already holds this data; leave it alone.
+            } else if self.sparse && is_all_zeros(data) {
+                if let Some(coalescer) = self.coalescer.as_mut() {
+                    coalescer.flush(config, output, self.direct_align, &self.progress, &mut self.stats.retried)?;
+                }
+                // Seek over the zeros instead of writing them, leaving a hole.
+                output
+                    .seek(SeekFrom::Current(bytes_read as i64))
+                    .with_path(&config.output_file)?;
+                self.stats.bytes_sparse += bytes_read as u64;
+            } else if let Some(discarder) = self.discarder.as_mut()
+                && is_all_zeros(data)
+            {
+                if let Some(coalescer) = self.coalescer.as_mut() {
+                    coalescer.flush(config, output, self.direct_align, &self.progress, &mut self.stats.retried)?;
+                }
+                let write_started = Instant::now();
+                let discarded = discarder.zeros(config, output, data)?;
+                write_took = Some(write_started.elapsed());
+                self.stats.bytes_discarded += discarded;
+            } else if let Some(coalescer) = self.coalescer.as_mut() {
+                coalescer.write(config, output, data, self.direct_align, &self.progress, &mut self.stats.retried)?;
+                coalesced = true;
+            } else {
+                // Write the portion of the buffer that was filled to the output file.
+                // It's crucial to use `&buffer[..bytes_read]` because the last block
+                // may not be a full block.
+                let write_started = Instant::now();
+                retry::write_all(config, output, data, &self.progress, &mut self.stats.retried)
+                    .with_path(&config.output_file)?;
+                write_took = Some(write_started.elapsed());
+            }
+
+            if write_took.is_some() || coalesced {
+                self.stats.bytes_written += bytes_read as u64;
+                self.meter.wrote(bytes_read as u64);
+                // A sync only covers what has reached the output.
+                if self.syncer.wrote(bytes_read as u64) {
+                    self.flush_coalesced(output)?;
+                    syncmode::sync(output, &config.output_file)?;
+                }
+            }
+            if let (Some(probe), Some(took)) = (self.flush_probe.as_mut(), write_took) {
+                probe.record_write(output, config, bytes_read as u64, took)?;
+            }
+
+            // The coalescer drops the cache of what it writes itself.
+            if config.fadvise.drop_output_cache
+                && !coalesced
+                && let Ok(chunk_end) = output.stream_position()
+            {
+                advise_dont_need(output, chunk_end - bytes_read as u64, bytes_read as u64);
+            }
+
+            in_pos += bytes_read as u64;
+            if let Some(left) = remaining.as_mut() {
+                *left -= bytes_read as u64;
+            }
+            self.stats.bytes_copied += bytes_read as u64;
+            self.progress.inc(bytes_read as u64);
+
+            // A full block, or a short read, completes the logical block, and
+            // so does the end of a byte-limited span. Following a growing
+            // input, a short read only means we caught up with its writer.
+            block_filled += bytes_read;
+            let short = bytes_read < want && self.follower.is_none();
+            if block_filled == self.block_size || short || remaining == Some(0) {
+                self.end_block(block_filled, block_changed);
+                blocks += 1;
+                block_filled = 0;
+                block_changed = false;
+
+                // A checkpoint may only count what has reached the output.
+                if self.checkpoint.as_ref().is_some_and(Checkpointer::due) {
+                    self.flush_coalesced(output)?;
+                }
+                if let Some(checkpoint) = self.checkpoint.as_mut() {
+                    checkpoint.maybe_commit(config, output, self.stats.bytes_copied, self.stats.blocks_copied)?;
+                }
+                if let Some(health) = self.health.as_mut() {
+                    health.poll(&self.progress);
+                }
+                self.power.poll(&self.progress);
+            }
+        }
+
+        self.flush_coalesced(output)?;
+        if let (Some(verifier), Some(start)) = (self.verifier.as_mut(), span_start) {
+            let end = output.stream_position().with_path(&config.output_file)?;
+            verifier.add_region(start, end);
+        }
+
+        Ok(())
+    }
+
+    /// Copies like copy_span with `max_blocks`, but lets the kernel move the
+    /// data. Returns false, having copied nothing, if it can't between these
+    /// two files.
+    fn copy_span_fast(
+        &mut self,
+        mut fast: FastPath,
+        input: &mut File,
+        output: &mut File,
+        max_blocks: Option<u64>,
+        max_bytes: Option<u64>,
+    ) -> RddResult<bool> {
+        let config = self.config;
+        if max_blocks.is_none() && max_bytes.is_none() && self.clone_whole(&fast, input, output)? {
+            return Ok(true);
+        }
+
+        let mut blocks = 0u64;
+        let mut block_filled = 0usize;
+        let mut remaining = max_bytes;
+        let mut out_pos = output.stream_position().with_path(&config.output_file)?;
+        loop {
+            if max_blocks.is_some_and(|max| blocks >= max) {
+                break;
+            }
+            if remaining == Some(0) {
+                if block_filled > 0 {
+                    self.end_block(block_filled, false);
+                }
+                break;
+            }
+
+            if let Err(e) = signals::check(&*self.config.reporter, &self.progress) {
+                self.save_interrupted(output, block_filled)?;
+                return Err(e);
+            }
+
+            // A failed transfer may be the input's fault or the output's; the
+            // output, where a full disk shows up, is the likelier one.
+            let mut want = (config.block_size - block_filled).min(fastpath::CHUNK_SIZE);
+            if let Some(left) = remaining {
+                want = want.min(left.min(usize::MAX as u64) as usize);
+            }
+            let moved = match fast.transfer(input, output, want).at_offset(&config.output_file, out_pos)? {
+                Some(moved) => moved,
+                None => return Ok(false),
+            };
+            if self.stats.bytes_copied == 0 {
+                config.reporter.message_over(
+                    &self.progress,
+                    &format!("Copying in the kernel with {}.", fast.method().name()),
+                );
+            }
+
+            if moved == 0 {
+                if block_filled > 0 {
+                    self.end_block(block_filled, false);
+                }
+                break;
+            }
+
+            if let Some(throttle) = self.throttle.as_mut() {
+                throttle.wait(moved as u64);
+            }
+            if config.fadvise.drop_output_cache {
+                advise_dont_need(output, out_pos, moved as u64);
+            }
+
+            self.syncer.after_write(output, &config.output_file, moved as u64)?;
+            self.meter.read(moved as u64);
+            self.meter.wrote(moved as u64);
+
+            out_pos += moved as u64;
+            self.stats.bytes_copied += moved as u64;
+            self.stats.bytes_written += moved as u64;
+            self.progress.inc(moved as u64);
+            if let Some(left) = remaining.as_mut() {
+                *left -= moved as u64;
+            }
+
+            // The kernel may move less than asked; the block goes on until
+            // it's full or the input ends.
+            block_filled += moved;
+            if block_filled == config.block_size {
+                self.end_block(block_filled, false);
+                blocks += 1;
+                block_filled = 0;
+
+                if let Some(checkpoint) = self.checkpoint.as_mut() {
+                    checkpoint.maybe_commit(config, output, self.stats.bytes_copied, self.stats.blocks_copied)?;
+                }
+                if let Some(health) = self.health.as_mut() {
+                    health.poll(&self.progress);
+                }
+                self.power.poll(&self.progress);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Clones all of the input into the output, if both are at their start,
+    /// the output is empty and the filesystem can share data between them.
+    fn clone_whole(&mut self, fast: &FastPath, input: &mut File, output: &mut File) -> RddResult<bool> {
+        let config = self.config;
+        let at_start = input.stream_position().with_path(&config.input_file)? == 0
+            && output.stream_position().with_path(&config.output_file)? == 0;
+        if !at_start || output.metadata().with_path(&config.output_file)?.len() != 0 {
+            return Ok(false);
+        }
+        if !fast.clone_file(input, output) {
+            return Ok(false);
+        }
+
+        let len = input.seek(SeekFrom::End(0)).with_path(&config.input_file)?;
+        output.seek(SeekFrom::End(0)).with_path(&config.output_file)?;
+        let bs = config.block_size as u64;
+        self.stats.blocks_copied = len.div_ceil(bs);
+        self.stats.blocks_written = self.stats.blocks_copied;
+        self.stats.partial_blocks = u64::from(len % bs != 0);
+        self.stats.bytes_copied = len;
+        self.stats.bytes_written = len;
+        self.meter.read(len);
+        self.meter.wrote(len);
+        self.progress.inc(len);
+        config.reporter.message_over(
+            &self.progress,
+            "Cloned the input (reflink): the output shares its data until either is changed.",
+        );
+        Ok(true)
+    }
+
+    /// Makes what was written so far durable before an interrupted copy
+    /// returns, and with --resume records how far it got. The checkpoint is
+    /// the start of the block that was cut off, which is copied again.
+    fn save_interrupted(&mut self, output: &File, block_filled: usize) -> RddResult<()> {
+        let config = self.config;
+        sync_output(config, output)?;
+        if let Some(checkpoint) = self.checkpoint.as_mut() {
+            let offset = self.stats.bytes_copied - block_filled as u64;
+            checkpoint.commit(config, output, offset, self.stats.blocks_copied)?;
+            self.config.reporter.message_over(
+                &self.progress,
+                &format!("Progress saved to '{}'; run the same command again to continue.", checkpoint.path()),
+            );
+        }
+        Ok(())
+    }
+
+    /// With --resume, moves the input past what an interrupted run already
+    /// copied, and returns how many bytes and blocks that was. A hash can't
+    /// be saved halfway, so with --verify that part is read and hashed again.
+    fn skip_resumed(&mut self, input: &mut File) -> RddResult<(u64, u64)> {
+        let config = self.config;
+        let Some(resumed) = self.checkpoint.as_ref().map(|c| c.resumed().clone()) else {
+            return Ok((0, 0));
+        };
+        if resumed.offset == 0 {
+            return Ok((0, 0));
+        }
+
+        match self.verifier.as_mut() {
+            Some(verifier) => {
+                self.progress.set_message("re-hashing the copied part");
+                let in_start = config.skip_bytes;
+                let mut pos = 0;
+                while pos < resumed.offset {
+                    let want = (resumed.offset - pos).min(self.buffer.len() as u64) as usize;
+                    let n = input
+                        .read(&mut self.buffer[..want])
+                        .at_offset(&config.input_file, in_start + pos)?;
+                    if n == 0 {
+                        return Err(RddError::Config(format!(
+                            "Cannot resume: the input ended after {} of the {} bytes that were already copied.",
+                            pos, resumed.offset
+                        )));
+                    }
+                    if let Some(transform) = &self.transform {
+                        transform.apply(&mut self.buffer[..n]);
+                    }
+                    verifier.update(&self.buffer[..n]);
+                    pos += n as u64;
+                    self.progress.inc(n as u64);
+                }
+                self.progress.set_message("");
+
+                let out_start = config.seek_bytes;
+                verifier.add_region(out_start, out_start + resumed.offset);
+            }
+            None => {
+                input
+                    .seek(SeekFrom::Current(resumed.offset as i64))
+                    .with_path(&config.input_file)?;
+                self.progress.inc(resumed.offset);
+            }
+        }
+
+        self.stats.bytes_resumed = resumed.offset;
+        Ok((resumed.offset, resumed.blocks))
+    }
+
+    /// Updates the block counters once a logical block of `filled` bytes is
+    /// complete.
+    fn end_block(&mut self, filled: usize, changed: bool) {
+        self.stats.blocks_copied += 1;
+        if let Some(block_hasher) = self.block_hasher.as_mut() {
+            block_hasher.end_block();
+        }
+        if filled < self.block_size {
+            self.stats.partial_blocks += 1;
+        }
+        // With --bs auto, the next block may get another size.
+        if let Some(tuner) = self.tuner.as_mut()
+            && let Some(size) = tuner.block_done(filled as u64)
+        {
+            self.block_size = size;
+        }
+
+        if !self.config.write_if_changed {
+            self.stats.blocks_written += 1;
+            return;
+        }
+
+        if changed {
+            self.stats.blocks_written += 1;
+        } else {
+            self.stats.blocks_unchanged += 1;
+        }
+        self.progress.set_message(format!(
+            "written: {}, unchanged: {}",
+            self.stats.blocks_written, self.stats.blocks_unchanged
+        ));
+    }
+
+    /// Looks up how the input position `pos` relates to the excluded ranges.
+    fn exclusion_at(&mut self, pos: u64) -> Option<Exclusion> {
+        let excluded = &self.config.excluded;
+
+        // Ranges are sorted and we only ever move forward, so ranges that
+        // end before `pos` can be dropped from consideration for good.
+        while self.next_exclusion < excluded.len() && excluded[self.next_exclusion].end() <= pos {
+            self.next_exclusion += 1;
+        }
+
+        let range = excluded.get(self.next_exclusion)?;
+        if range.offset <= pos {
+            Some(Exclusion::Inside(range.end() - pos))
+        } else {
+            Some(Exclusion::Ahead(range.offset - pos))
+        }
+    }
+
+    /// Writes out what --coalesce has gathered, before anything that looks
+    /// at or moves the output position.
+    fn flush_coalesced(&mut self, output: &mut File) -> RddResult<()> {
+        match self.coalescer.as_mut() {
+            Some(coalescer) => {
+                coalescer.flush(self.config, output, self.direct_align, &self.progress, &mut self.stats.retried)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Moves the output forward by `len` bytes, leaving zeros behind.
+    fn zero_output(&mut self, output: &mut File, len: u64) -> RddResult<()> {
+        if self.holes_allowed {
+            // The output was truncated, so skipping ahead leaves a hole
+            // that reads back as zeros without using any space.
+            output.seek(SeekFrom::Current(len as i64))?;
+            return Ok(());
+        }
+
+        self.buffer.fill(0);
+        let mut left = len;
+        while left > 0 {
+            let chunk = left.min(self.buffer.len() as u64) as usize;
+            output
+                .write_all(&self.buffer[..chunk])
+                .with_path(&self.config.output_file)?;
+            left -= chunk as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Reads from the input at `pos`, through the simulated device if there is
+/// one (--simulate-device).
+pub(crate) fn read_input(
+    device: &mut Option<SimulatedDevice>,
+    input: &mut File,
+    pos: u64,
+    buf: &mut [u8],
+) -> io::Result<usize> {
+    match device {
+        Some(device) => device.read(input, pos, buf),
+        None => input.read(buf),
+    }
+}
+
+/// Where an excluded range lies relative to the current input position.
+enum Exclusion {
+    /// The position is inside an excluded range with this many bytes left.
+    Inside(u64),
+    /// The next excluded range starts this many bytes ahead.
+    Ahead(u64),
+}
+
+/// Prints the throughput and CPU efficiency of a finished copy.
+fn print_summary(config: &CopyConfig, bytes: u64, elapsed: Duration, cpu: Option<&CpuUsage>) {
+    let secs = elapsed.as_secs_f64();
+    let rate = if secs > 0.0 { (bytes as f64 / secs) as u64 } else { bytes };
+    report(config, format!(
+        "{} bytes ({}) copied in {:.2}s ({}/s).",
+        bytes,
+        BinaryBytes(bytes),
+        secs,
+        BinaryBytes(rate)
+    ));
+
+    if let Some(cpu) = cpu {
+        let mut line = format!(
+            "CPU time: {:.2}s user, {:.2}s system",
+            cpu.user.as_secs_f64(),
+            cpu.system.as_secs_f64()
+        );
+        if let Some(switches) = cpu.context_switches {
+            line.push_str(&format!(", {} context switches", switches));
+        }
+        if let Some(efficiency) = cpu.bytes_per_cpu_second(bytes) {
+            line.push_str(&format!(", {}/CPU-second", BinaryBytes(efficiency as u64)));
+        }
+        report(config, format!("{}.", line));
+    }
+}
+
+/// Reports one line of the copy summary. With --stats-format json the JSON
+/// object replaces these lines.
+fn report(config: &CopyConfig, line: String) {
+    if config.stats_format == StatsFormat::Json {
+        return;
+    }
+    config.reporter.message(&line);
+}
+
+/// Works out how many bytes the copy is expected to transfer, if that can be
+/// known up front. Only regular files and devices report a meaningful length.
+pub(crate) fn expected_bytes(config: &CopyConfig, input_file: &File) -> Option<u64> {
+    if let Some(ranges) = &config.ranges {
+        return Some(ranges.iter().map(|r| r.length).sum());
+    }
+
+    // A followed input's final size is only known with --until-size.
+    let len = match config.follow {
+        Some(follow) => follow.until_size?,
+        None => input_size(input_file, &config.input_file)?,
+    };
+    expected_bytes_of(config, len)
+}
+
+/// How many bytes the copy is expected to transfer from an input of `len`
+/// bytes, after --skip and up to --count.
+pub(crate) fn expected_bytes_of(config: &CopyConfig, len: u64) -> Option<u64> {
+    let remaining = len.saturating_sub(config.skip_bytes);
+
+    match config.count_bytes {
+        Some(bytes) => Some(remaining.min(bytes)),
+        None if config.count > 0 => Some(remaining.min(config.count * config.block_size as u64)),
+        None => Some(remaining),
+    }
+}
+
+/// Whether every byte of `data` is zero.
+fn is_all_zeros(data: &[u8]) -> bool {
+    data.iter().all(|&b| b == 0)
+}
+
+/// Reads from `reader` until `buf` is full or end-of-file is reached.
+///
+/// Returns the number of bytes actually read, which is only smaller than
+/// `buf.len()` at end-of-file.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> RddResult<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CopyConfigBuilder;
+    use crate::core::reporter::EventReporter;
+    use std::fs;
+
+    #[test]
+    fn a_range_that_ends_mid_block_counts_its_partial_block() {
+        let dir = std::env::temp_dir().join(format!("rdd-copy-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (input, output) = (dir.join("input"), dir.join("output"));
+        fs::write(&input, vec![7u8; 16384]).unwrap();
+
+        let config = CopyConfig::builder(input.to_string_lossy(), output.to_string_lossy())
+            .block_size(4096)
+            .ranges(vec![ByteRange { offset: 0, length: 6000 }])
+            .progress(false)
+            .fast_path(false)
+            .reporter(Arc::new(EventReporter::new(|_| {})))
+            .build()
+            .unwrap();
+        let stats = run_copy(&config).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(stats.bytes_copied, 6000);
+        assert_eq!(stats.blocks_copied, 2);
+        assert_eq!(stats.blocks_written, 2);
+    }
+
+    #[test]
+    fn resuming_keeps_an_output_that_isnt_truncated() {
+        let dir = std::env::temp_dir().join(format!("rdd-copy-resume-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (input, output) = (dir.join("input"), dir.join("output"));
+        fs::write(&input, vec![7u8; 16384]).unwrap();
+        let copy = |builder: CopyConfigBuilder| {
+            fs::write(&output, vec![1u8; 16384]).unwrap();
+            let config = builder
+                .block_size(4096)
+                .resume(dir.join("state").to_string_lossy())
+                .progress(false)
+                .fast_path(false)
+                .reporter(Arc::new(EventReporter::new(|_| {})))
+                .build()
+                .unwrap();
+            run_copy(&config).unwrap();
+            fs::read(&output).unwrap()
+        };
+        let builder = || CopyConfig::builder(input.to_string_lossy(), output.to_string_lossy());
+        let sought = copy(builder().seek_bytes(8192).count_bytes(4096));
+        let kept = copy(builder().truncate(false).count_bytes(4096));
+        let truncated = copy(builder().count_bytes(4096));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(sought.len(), 16384);
+        assert!(sought[..8192].iter().all(|&b| b == 1));
+        assert!(sought[8192..12288].iter().all(|&b| b == 7));
+        assert!(sought[12288..].iter().all(|&b| b == 1));
+        assert_eq!(kept.len(), 16384);
+        assert!(kept[..4096].iter().all(|&b| b == 7));
+        assert!(kept[4096..].iter().all(|&b| b == 1));
+        assert_eq!(truncated, vec![7u8; 4096]);
+    }
+}
@@ -0,0 +1,238 @@
+// This file implements --custody-log. In forensic work, an image is only worth something as evidence if it can be shown when it was made, by whom, from what, and that nobody touched the record since. With --custody-log FILE, every copy appends time-stamped entries to a log that can be added to but not quietly edited.
+
+// Explanation of this file:
+// Entries : One JSON object per line (see utils/json.rs). A copy adds a "start" entry before it opens anything (time, user, host, rdd version, input, output and the main settings) and a "finish" entry with the results and the digest of the data, or a "failed" entry with the error. The copy hashes its data for that digest even without --verify.
+// Hash chain : Every entry carries the hash of the entry before it ("prev", all zeros for the first), and its own "hash": the SHA-256 of the line as it would read without the hash field. Changing, removing or reordering an entry breaks the chain at that point.
+// Checking : Before appending, the whole log is read and the chain is checked. A broken chain fails the copy with RddError::CustodyLog instead of adding to a log that can no longer be trusted.
+// Limits : Anyone who can write the log can also rewrite it and compute a new chain. The chain makes tampering evident once the hash of the latest entry has been kept elsewhere, so it's printed at the end of every copy; write it into the case notes.
+// Appending : The log is opened in append mode and synced after every entry, so an entry is on disk before the copy goes on.
+
+// src/core/custody.rs
+
+use crate::cli::HashAlgorithm;
+use crate::config::CopyConfig;
+use crate::core::copy::CopyStats;
+use crate::core::hash::{algorithm_flag, StreamHasher};
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::diagnostics::Diagnostic;
+use crate::utils::json::Json;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The "prev" hash of the first entry in a log.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Runs `copy` with --custody-log entries before and after it, if the
+/// config asks for them.
+pub fn logged(config: &CopyConfig, copy: impl FnOnce() -> RddResult<CopyStats>) -> RddResult<CopyStats> {
+    let Some(path) = &config.custody_log else {
+        return copy();
+    };
+    let mut log = CustodyLog::open(path)?;
+    log.append("start", start_details(config))?;
+
+    match copy() {
+        Ok(stats) => {
+            let hash = log.append("finish", finish_details(&stats))?;
+            config
+                .reporter
+                .message(&format!("Custody log: entry {} appended to '{}' (hash {}).", log.seq, path, hash));
+            Ok(stats)
+        }
+        Err(e) => {
+            // The copy's error is what the user needs to see; a log that
+            // can't be written to on top of it only gets a warning.
+            if let Err(log_error) = log.append("failed", vec![("error", Json::str(e.to_string()))]) {
+                config.reporter.diagnostic(
+                    &Diagnostic::warning(format!("could not record the failure in the custody log: {}", log_error))
+                        .with_flag("--custody-log"),
+                );
+            }
+            Err(e)
+        }
+    }
+}
+
+/// An open custody log, positioned after its last entry.
+pub struct CustodyLog {
+    path: String,
+    /// Number of the last entry (0 for an empty log).
+    seq: u64,
+    /// Hash of the last entry.
+    head: String,
+}
+
+impl CustodyLog {
+    /// Opens the log at `path`, creating it if needed, after checking that
+    /// its hash chain is intact.
+    pub fn open(path: &str) -> RddResult<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_path(path),
+        };
+        let (seq, head) = check_chain(path, &contents)?;
+        Ok(Self {
+            path: path.to_string(),
+            seq,
+            head,
+        })
+    }
+
+    /// Appends an entry for `event` and returns its hash.
+    pub fn append(&mut self, event: &str, details: Vec<(&str, Json)>) -> RddResult<String> {
+        let seq = self.seq + 1;
+        let mut fields = vec![
+            ("seq", Json::Int(seq)),
+            ("prev", Json::str(&self.head)),
+            ("time", Json::str(utc_timestamp(SystemTime::now()))),
+            ("event", Json::str(event)),
+        ];
+        fields.extend(details);
+        let body = Json::object(fields).to_string();
+        let hash = sha256(&body);
+        let line = format!("{},\"hash\":\"{}\"}}\n", &body[..body.len() - 1], hash);
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_path(&self.path)?;
+        file.write_all(line.as_bytes()).with_path(&self.path)?;
+        file.sync_all().with_path(&self.path)?;
+
+        self.seq = seq;
+        self.head = hash.clone();
+        Ok(hash)
+    }
+}
+
+/// Checks the hash chain of a log's contents and returns the number and
+/// hash of its last entry.
+fn check_chain(path: &str, contents: &str) -> RddResult<(u64, String)> {
+    let broken = |line: usize, reason: &str| RddError::CustodyLog {
+        path: path.to_string(),
+        reason: format!("line {}: {}", line, reason),
+    };
+
+    let (mut seq, mut head) = (0, GENESIS.to_string());
+    for (number, line) in contents.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let number = number + 1;
+        let Some((body, hash)) = split_entry(line) else {
+            return Err(broken(number, "not a custody log entry"));
+        };
+        if sha256(&body) != hash {
+            return Err(broken(number, "the entry was changed after it was written"));
+        }
+        if field(line, "prev") != Some(head.as_str()) {
+            return Err(broken(number, "the entry doesn't follow the one before it; entries were removed or reordered"));
+        }
+        seq += 1;
+        if field(line, "seq").and_then(|s| s.parse().ok()) != Some(seq) {
+            return Err(broken(number, "the entry is out of sequence"));
+        }
+        head = hash.to_string();
+    }
+    Ok((seq, head))
+}
+
+/// Splits a log line into the entry as it was hashed (without its hash
+/// field) and the hash.
+fn split_entry(line: &str) -> Option<(String, &str)> {
+    let (rest, hash) = line.trim_end().strip_suffix("\"}")?.rsplit_once(",\"hash\":\"")?;
+    (hash.len() == GENESIS.len()).then(|| (format!("{}}}", rest), hash))
+}
+
+/// The value of one of the fields written before any free text in an
+/// entry ("seq" or "prev"), as it appears in the line.
+fn field<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let start = line.find(&format!("\"{}\":", name))? + name.len() + 3;
+    let value = &line[start..];
+    match value.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next(),
+        None => value.split([',', '}']).next(),
+    }
+}
+
+fn sha256(text: &str) -> String {
+    let mut hasher = StreamHasher::new(HashAlgorithm::Sha256);
+    hasher.update(text.as_bytes());
+    hasher.finalize()
+}
+
+/// Who runs the copy, where, and what it copies.
+fn start_details(config: &CopyConfig) -> Vec<(&'static str, Json)> {
+    let user = env::var("USER").or_else(|_| env::var("USERNAME")).ok();
+    let input_size = fs::metadata(&config.input_file).ok().filter(|m| m.is_file()).map(|m| m.len());
+    vec![
+        ("rdd_version", Json::str(env!("CARGO_PKG_VERSION"))),
+        ("user", Json::or_null(user, Json::str)),
+        ("host", Json::or_null(host_name(), Json::str)),
+        ("input", Json::str(&config.input_file)),
+        ("input_size", Json::or_null(input_size, Json::Int)),
+        ("output", Json::str(&config.output_file)),
+        ("block_size", Json::Int(config.block_size as u64)),
+        ("skip", Json::Int(config.skip)),
+        ("seek", Json::Int(config.seek)),
+        ("count", Json::Int(config.count)),
+        ("skip_bytes", Json::Int(config.skip_bytes)),
+        ("seek_bytes", Json::Int(config.seek_bytes)),
+        ("count_bytes", Json::or_null(config.count_bytes, Json::Int)),
+        ("threads", Json::Int(config.threads.into())),
+        ("verify", Json::or_null(config.verification_algo, |algo| Json::str(algorithm_flag(algo)))),
+    ]
+}
+
+/// What the copy did, and the digest of what it read.
+fn finish_details(stats: &CopyStats) -> Vec<(&'static str, Json)> {
+    vec![
+        ("bytes_read", Json::Int(stats.bytes_copied)),
+        ("bytes_written", Json::Int(stats.bytes_written)),
+        ("bad_blocks", Json::Int(stats.bad_regions.len() as u64)),
+        ("elapsed_seconds", Json::Float(stats.elapsed.as_secs_f64())),
+        ("data_digest", Json::or_null(stats.data_digest.as_ref(), |d| Json::str(d.to_string()))),
+        (
+            "output_digest",
+            Json::or_null(stats.verification.as_ref(), |v| Json::str(&v.output_digest)),
+        ),
+    ]
+}
+
+/// The machine's name, where the platform tells us.
+fn host_name() -> Option<String> {
+    env::var("HOSTNAME")
+        .or_else(|_| env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Formats `time` as an RFC 3339 timestamp in UTC, e.g. "2024-05-01T13:45:07Z".
+fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rest) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3_600,
+        rest % 3_600 / 60,
+        rest % 60
+    )
+}
@@ -0,0 +1,819 @@
+// This file implements the full-screen dashboards: 'rdd copy --tui' for one copy, and 'rdd top' for every copy running on the machine. An operator flashing a batch of USB sticks runs several copies side by side, and a handful of progress bars in different terminals don't tell at a glance which station is slow, hot or stuck. The dashboards show each copy with its progress, a graph of its throughput, the drive temperatures and its latest messages.
+
+// Explanation of this file:
+// Job files : Every 'rdd copy' started from the command line publishes its state in a small text file in the job directory, named after its process id: the paths, the bytes copied, the total, the progress bar's message (which carries the drive temperatures with --monitor-health, see health.rs) and its last few messages. The file is rewritten every PUBLISH_INTERVAL and removed when the copy ends. Files older than STALE_AFTER belong to copies that were killed, and are ignored. Library users don't publish anything; job_reporter is only used by the binary.
+// Job directory : Job files name the files being copied, and copies of devices usually run as root, so the directory is private to its user: rdd-jobs in $XDG_RUNTIME_DIR when that's the user's own, or rdd-jobs-UID in the temp directory. It's created with mode 0700, and one that's a symlink, belongs to someone else or can be written by others isn't used. The files themselves are created anew, never through a symlink, and renamed into place. 'rdd top' therefore shows the copies of the user it runs as; use sudo for copies started with sudo.
+// DashboardReporter : Wraps the terminal reporter of a copy. It keeps the copy's messages for the job file, and with --tui draws the dashboard instead of the progress bar, on the alternate screen (see utils/terminal.rs). Messages and warnings that come while it's drawn are held back and printed once the screen is left, so the summary and any errors end up in the terminal as usual. The screen is left as soon as the copy's progress bar is finished or gone, or when the reporter itself is dropped, so an error that ends the copy is never printed on a screen that's about to vanish.
+// Throughput graph : The rate is measured once per RATE_SAMPLE from the bytes copied, and the latest ones are drawn as a sparkline, as wide as the window.
+// rdd top : Reads the job files twice a second and draws every job, until Ctrl-C. --once prints the jobs a single time instead, without the full screen, for scripts and remote shells.
+// Drawing : The full screen is drawn with ratatui (draw_frame): each job in a box titled with its paths, holding a gauge of its progress, the throughput graph, its status and its latest messages. There's no color, so the dashboard reads the same in every terminal and over a serial console. 'rdd top --once' prints the same as plain lines of text (render).
+// tui feature : ratatui only comes with the tui cargo feature (see Cargo.toml). Without it, --tui is refused (see config.rs) and so is 'rdd top' without --once, while copies still publish their job files for 'rdd top --once'.
+
+// src/core/dashboard.rs
+
+use crate::config::{CopyConfig, TopConfig};
+use crate::core::reporter::{Reporter, TerminalReporter};
+use crate::error::{RddError, RddResult};
+use crate::utils::diagnostics::Diagnostic;
+#[cfg(feature = "tui")]
+use crate::utils::signals;
+#[cfg(feature = "tui")]
+use crate::utils::terminal::Screen;
+use indicatif::{BinaryBytes, ProgressBar, WeakProgressBar};
+#[cfg(unix)]
+use nix::unistd::geteuid;
+#[cfg(feature = "tui")]
+use ratatui::Frame;
+#[cfg(feature = "tui")]
+use ratatui::layout::{Constraint, Layout, Rect};
+#[cfg(feature = "tui")]
+use ratatui::widgets::{Block, Gauge, Paragraph, Sparkline};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+#[cfg(feature = "tui")]
+use std::io::IsTerminal;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "tui")]
+use std::sync::Weak;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often a copy rewrites its job file.
+const PUBLISH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often the dashboards are redrawn.
+#[cfg(feature = "tui")]
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often the throughput is measured for the graph.
+const RATE_SAMPLE: Duration = Duration::from_secs(1);
+
+/// Job files that haven't been rewritten for this long are left over from
+/// copies that were killed.
+const STALE_AFTER: Duration = Duration::from_secs(10);
+
+/// Messages of a copy kept for its job file and the --tui log.
+const KEPT_MESSAGES: usize = 100;
+
+/// The title of the --tui dashboard.
+#[cfg(feature = "tui")]
+const TUI_TITLE: &str = "rdd copy  (Ctrl-C stops the copy)";
+
+/// Messages of each job shown by 'rdd top'.
+const TOP_MESSAGES: usize = 3;
+
+/// The directory the job files are published in, for this user.
+#[cfg(unix)]
+pub fn job_dir() -> PathBuf {
+    let uid = geteuid().as_raw();
+    match std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+        Some(runtime) if fs::symlink_metadata(&runtime).is_ok_and(|metadata| private(&metadata)) => runtime.join("rdd-jobs"),
+        _ => std::env::temp_dir().join(format!("rdd-jobs-{}", uid)),
+    }
+}
+
+/// The directory the job files are published in, for this user.
+#[cfg(not(unix))]
+pub fn job_dir() -> PathBuf {
+    std::env::temp_dir().join("rdd-jobs")
+}
+
+/// Whether `metadata` is of a directory only this user can get into.
+#[cfg(unix)]
+fn private(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    metadata.is_dir() && metadata.uid() == geteuid().as_raw() && metadata.mode() & 0o077 == 0
+}
+
+/// The job directory, created if it's missing. Fails if it isn't private
+/// to this user.
+fn open_job_dir() -> io::Result<PathBuf> {
+    let dir = job_dir();
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    match builder.create(&dir) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+        _ => {}
+    }
+    #[cfg(unix)]
+    if !private(&fs::symlink_metadata(&dir)?) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("'{}' isn't a directory only this user can write to", dir.display()),
+        ));
+    }
+    Ok(dir)
+}
+
+/// Creates `path` for writing, failing if anything (even a symlink) is
+/// already there.
+fn create_new(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600).custom_flags(nix::libc::O_NOFOLLOW);
+    }
+    options.open(path)
+}
+
+/// What a running copy publishes about itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JobStatus {
+    pub pid: u32,
+    pub input: String,
+    pub output: String,
+    /// Bytes copied so far.
+    pub bytes: u64,
+    /// Bytes to copy, if known.
+    pub total: Option<u64>,
+    pub elapsed: Duration,
+    /// The progress bar's message, e.g. the drive temperatures.
+    pub status: String,
+    /// The latest messages, oldest first.
+    pub messages: Vec<String>,
+}
+
+impl JobStatus {
+    /// The contents of a job file: one "key value" line per field, and one
+    /// "message" line per message.
+    fn to_text(&self) -> String {
+        let mut text = format!(
+            "pid {}\ninput {}\noutput {}\nbytes {}\nelapsed_ms {}\nstatus {}\n",
+            self.pid,
+            self.input,
+            self.output,
+            self.bytes,
+            self.elapsed.as_millis(),
+            self.status
+        );
+        if let Some(total) = self.total {
+            text.push_str(&format!("total {}\n", total));
+        }
+        for message in &self.messages {
+            text.push_str(&format!("message {}\n", message));
+        }
+        text
+    }
+
+    /// Reads the contents of a job file back.
+    fn parse(text: &str) -> Option<Self> {
+        let mut status = JobStatus::default();
+        for line in text.lines() {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "pid" => status.pid = value.parse().ok()?,
+                "input" => status.input = value.to_string(),
+                "output" => status.output = value.to_string(),
+                "bytes" => status.bytes = value.parse().ok()?,
+                "total" => status.total = Some(value.parse().ok()?),
+                "elapsed_ms" => status.elapsed = Duration::from_millis(value.parse().ok()?),
+                "status" => status.status = value.to_string(),
+                "message" => status.messages.push(value.to_string()),
+                _ => {}
+            }
+        }
+        (status.pid != 0).then_some(status)
+    }
+}
+
+/// A job as the dashboard shows it, with the rates measured so far.
+#[derive(Debug, Default)]
+struct JobView {
+    status: JobStatus,
+    /// Bytes per second, one per RATE_SAMPLE, oldest first.
+    rates: VecDeque<f64>,
+    /// The bytes copied at the last sample, and when it was taken.
+    sampled: Option<(u64, Instant)>,
+}
+
+impl JobView {
+    fn update(&mut self, status: JobStatus) {
+        let now = Instant::now();
+        match self.sampled {
+            Some((bytes, at)) if now - at >= RATE_SAMPLE => {
+                let rate = status.bytes.saturating_sub(bytes) as f64 / (now - at).as_secs_f64();
+                self.rates.push_back(rate);
+                // More than any window is wide.
+                if self.rates.len() > 1000 {
+                    self.rates.pop_front();
+                }
+                self.sampled = Some((status.bytes, now));
+            }
+            Some(_) => {}
+            None => self.sampled = Some((status.bytes, now)),
+        }
+        self.status = status;
+    }
+
+    /// The current rate, or the average one before the first sample.
+    fn rate(&self) -> f64 {
+        match self.rates.back() {
+            Some(&rate) => rate,
+            None if !self.status.elapsed.is_zero() => self.status.bytes as f64 / self.status.elapsed.as_secs_f64(),
+            None => 0.0,
+        }
+    }
+
+    /// "input -> output   (pid 1234, 0:01:02)".
+    fn heading(&self) -> String {
+        let job = &self.status;
+        format!("{} -> {}   (pid {}, {})", job.input, job.output, job.pid, clock(job.elapsed))
+    }
+
+    /// How much is done, and the line of details that goes with it:
+    /// percentage, bytes, speed and ETA. Without a total there's no
+    /// fraction, just the bytes copied and the speed.
+    fn progress(&self) -> (Option<f64>, String) {
+        let job = &self.status;
+        let rate = self.rate();
+        let speed = format!("{}/s", BinaryBytes(rate as u64));
+        match job.total {
+            Some(total) if total > 0 => {
+                let done = (job.bytes as f64 / total as f64).min(1.0);
+                let eta = match rate > 0.0 {
+                    true => clock(Duration::from_secs_f64(total.saturating_sub(job.bytes) as f64 / rate)),
+                    false => "-".to_string(),
+                };
+                let details = format!(
+                    "{:5.1}%  {} / {}  {}  ETA {}",
+                    done * 100.0,
+                    BinaryBytes(job.bytes),
+                    BinaryBytes(total),
+                    speed,
+                    eta
+                );
+                (Some(done), details)
+            }
+            _ => (None, format!("{} copied  {}", BinaryBytes(job.bytes), speed)),
+        }
+    }
+
+    /// What goes next to the throughput graph.
+    fn legend(&self) -> String {
+        match self.rates.is_empty() {
+            true => "(measuring the throughput)".to_string(),
+            false => {
+                let peak = self.rates.iter().copied().fold(0.0, f64::max);
+                format!("peak {}/s", BinaryBytes(peak as u64))
+            }
+        }
+    }
+
+    /// The latest `count` messages at most.
+    fn latest(&self, count: usize) -> &[String] {
+        let messages = &self.status.messages;
+        &messages[messages.len().saturating_sub(count)..]
+    }
+}
+
+/// "1:02:03" for a duration.
+fn clock(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// `line`, cut to `width` characters.
+fn fit(line: &str, width: usize) -> String {
+    line.chars().take(width).collect()
+}
+
+/// The latest `rates` as a sparkline of `width` characters at most.
+fn sparkline(rates: &VecDeque<f64>, width: usize) -> String {
+    const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let shown: Vec<f64> = rates.iter().skip(rates.len().saturating_sub(width)).copied().collect();
+    let peak = shown.iter().copied().fold(0.0, f64::max);
+    shown
+        .iter()
+        .map(|&rate| match peak > 0.0 {
+            true => LEVELS[((rate / peak) * (LEVELS.len() - 1) as f64).round() as usize],
+            false => LEVELS[0],
+        })
+        .collect()
+}
+
+/// What the dashboards show when no copy is running.
+fn nothing_running() -> String {
+    format!("No copies running (they publish their progress in {}).", job_dir().display())
+}
+
+/// The lines of text showing one job, with at most `messages` of its
+/// messages.
+fn render_job(view: &JobView, width: usize, messages: usize) -> Vec<String> {
+    let mut lines = vec![view.heading()];
+    let (done, details) = view.progress();
+    lines.push(match done {
+        Some(done) => {
+            let bar_width = width.saturating_sub(details.chars().count() + 3).clamp(10, 50);
+            let filled = (done * bar_width as f64) as usize;
+            format!("[{}{}] {}", "█".repeat(filled), "░".repeat(bar_width - filled), details)
+        }
+        None => details,
+    });
+
+    let legend = view.legend();
+    let graph = sparkline(&view.rates, width.saturating_sub(legend.len() + 2).max(10));
+    lines.push(match graph.is_empty() {
+        true => legend,
+        false => format!("{}  {}", graph, legend),
+    });
+
+    if !view.status.status.is_empty() {
+        lines.push(view.status.status.clone());
+    }
+    lines.extend(view.latest(messages).iter().map(|message| format!("  {}", message)));
+    lines.iter().map(|line| fit(line, width)).collect()
+}
+
+/// The jobs as text, for 'rdd top --once': `title`, then every job.
+fn render(title: &str, views: &[&JobView], messages: usize) -> Vec<String> {
+    let mut lines = vec![title.to_string(), "-".repeat(title.chars().count().min(80))];
+    if views.is_empty() {
+        lines.push(nothing_running());
+    }
+    for view in views {
+        lines.extend(render_job(view, usize::MAX, messages));
+        lines.push(String::new());
+    }
+    lines
+}
+
+/// Draws a whole dashboard on `frame`: `title`, then a box for each job,
+/// with at most `messages` of its messages. Jobs that don't fit the
+/// window are left out.
+#[cfg(feature = "tui")]
+fn draw_frame(frame: &mut Frame, title: &str, views: &[&JobView], messages: usize) {
+    let [header, body] = Layout::vertical([Constraint::Length(2), Constraint::Fill(1)]).areas(frame.area());
+    frame.render_widget(Paragraph::new(title), header);
+    if views.is_empty() {
+        frame.render_widget(Paragraph::new(nothing_running()), body);
+        return;
+    }
+
+    let mut free = body;
+    for view in views {
+        // The borders, gauge, graph and legend, then the status and messages.
+        let status = usize::from(!view.status.status.is_empty());
+        let wanted = 6 + status + view.latest(messages).len();
+        let height = (wanted.min(u16::MAX as usize) as u16).min(free.height);
+        if height < 6 {
+            break;
+        }
+        let area = Rect { height, ..free };
+        draw_job(frame, view, area);
+        free.y += height;
+        free.height -= height;
+    }
+}
+
+/// Draws one job in `area`, with as many of its latest messages as fit.
+#[cfg(feature = "tui")]
+fn draw_job(frame: &mut Frame, view: &JobView, area: Rect) {
+    let block = Block::bordered().title(format!(" {} ", view.heading()));
+    let inside = block.inner(area);
+    frame.render_widget(block, area);
+    let [progress, graph, legend, rest] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Length(2),
+        Constraint::Length(1),
+        Constraint::Fill(1),
+    ])
+    .areas(inside);
+
+    match view.progress() {
+        (Some(done), details) => frame.render_widget(Gauge::default().ratio(done).label(details), progress),
+        (None, details) => frame.render_widget(Paragraph::new(details), progress),
+    }
+    let width = usize::from(graph.width);
+    let rates = view.rates.iter().skip(view.rates.len().saturating_sub(width)).map(|&rate| rate as u64);
+    frame.render_widget(Sparkline::default().data(rates), graph);
+    frame.render_widget(Paragraph::new(view.legend()), legend);
+
+    let mut lines: Vec<String> = Vec::new();
+    if !view.status.status.is_empty() {
+        lines.push(view.status.status.clone());
+    }
+    let room = usize::from(rest.height).saturating_sub(lines.len());
+    lines.extend(view.latest(room).iter().map(|message| format!("  {}", message)));
+    frame.render_widget(Paragraph::new(lines.join("\n")), rest);
+}
+
+/// Without the tui feature there's never a dashboard on the screen.
+#[cfg(not(feature = "tui"))]
+enum Screen {}
+
+/// What a copy said while the dashboard was on the screen, to be printed
+/// once it's gone.
+enum Held {
+    Message(String),
+    Diagnostic(Diagnostic),
+}
+
+/// The state a DashboardReporter shares with its threads.
+struct Shared {
+    inner: TerminalReporter,
+    /// The job file, if the job directory could be created.
+    path: Option<PathBuf>,
+    started: Instant,
+    state: Mutex<State>,
+}
+
+struct State {
+    input: String,
+    output: String,
+    /// The bar of the copy's current phase (copying, verifying).
+    bar: Option<WeakProgressBar>,
+    /// Counts the phases, so a dashboard knows when its phase is over.
+    phase: u64,
+    messages: VecDeque<String>,
+    /// The dashboard, while --tui draws it.
+    screen: Option<Screen>,
+    held: Vec<Held>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The job as it stands.
+    fn status(&self) -> JobStatus {
+        let state = self.lock();
+        let bar = state.bar.as_ref().and_then(WeakProgressBar::upgrade);
+        JobStatus {
+            pid: std::process::id(),
+            input: state.input.clone(),
+            output: state.output.clone(),
+            bytes: bar.as_ref().map_or(0, ProgressBar::position),
+            total: bar.as_ref().and_then(ProgressBar::length),
+            elapsed: self.started.elapsed(),
+            status: bar.as_ref().map(ProgressBar::message).unwrap_or_default(),
+            messages: state.messages.iter().cloned().collect(),
+        }
+    }
+
+    /// Keeps `line` for the job file and the log.
+    fn remember(&self, state: &mut State, line: &str) {
+        for line in line.lines().filter(|line| !line.trim().is_empty()) {
+            if state.messages.len() == KEPT_MESSAGES {
+                state.messages.pop_front();
+            }
+            state.messages.push_back(line.to_string());
+        }
+    }
+
+    /// Leaves the dashboard once its bar is finished or gone, or when
+    /// `force`d, and prints what was held back meanwhile.
+    fn settle(&self, state: &mut State, force: bool) {
+        if state.screen.is_none() {
+            return;
+        }
+        let live = state.bar.as_ref().and_then(WeakProgressBar::upgrade).is_some_and(|bar| !bar.is_finished());
+        if live && !force {
+            return;
+        }
+        state.screen = None;
+        for held in state.held.drain(..) {
+            match held {
+                Held::Message(line) => self.inner.message(&line),
+                Held::Diagnostic(diagnostic) => self.inner.diagnostic(&diagnostic),
+            }
+        }
+    }
+
+    /// Writes the job file, through a temporary file so 'rdd top' never
+    /// reads half of one.
+    fn publish(&self, path: &Path) {
+        let temporary = path.with_extension("tmp");
+        // Left behind by a killed copy that had our process id.
+        let _ = fs::remove_file(&temporary);
+        let written = create_new(&temporary).and_then(|mut file| file.write_all(self.status().to_text().as_bytes()));
+        if written.is_ok() {
+            let _ = fs::rename(&temporary, path);
+        }
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(|e| e.into_inner());
+        state.screen = None;
+        for held in state.held.drain(..) {
+            match held {
+                Held::Message(line) => self.inner.message(&line),
+                Held::Diagnostic(diagnostic) => self.inner.diagnostic(&diagnostic),
+            }
+        }
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// The reporter of a copy started from the command line: the terminal
+/// reporter, which also publishes the job for 'rdd top' and with --tui
+/// draws the dashboard.
+pub struct DashboardReporter {
+    shared: Arc<Shared>,
+    #[cfg(feature = "tui")]
+    tui: bool,
+}
+
+/// The reporter for a copy from the command line; see DashboardReporter.
+pub fn job_reporter(config: &CopyConfig, tui: bool) -> Arc<dyn Reporter> {
+    let path = open_job_dir()
+        .ok()
+        .map(|dir| dir.join(format!("{}.job", std::process::id())));
+    let shared = Arc::new(Shared {
+        inner: TerminalReporter,
+        path,
+        started: Instant::now(),
+        state: Mutex::new(State {
+            input: config.input_file.clone(),
+            output: config.output_file.clone(),
+            bar: None,
+            phase: 0,
+            messages: VecDeque::new(),
+            screen: None,
+            held: Vec::new(),
+        }),
+    });
+
+    if let Some(path) = shared.path.clone() {
+        let weak = Arc::downgrade(&shared);
+        // Without threads (see utils/threads.rs) the job isn't published.
+        let _ = thread::Builder::new().name("rdd-publish".to_string()).spawn(move || {
+            while let Some(shared) = weak.upgrade() {
+                shared.publish(&path);
+                drop(shared);
+                thread::sleep(PUBLISH_INTERVAL);
+            }
+        });
+    }
+    // The dashboard needs a terminal to draw on.
+    #[cfg(feature = "tui")]
+    let tui = tui && io::stderr().is_terminal();
+    #[cfg(not(feature = "tui"))]
+    let _ = tui;
+    Arc::new(DashboardReporter {
+        shared,
+        #[cfg(feature = "tui")]
+        tui,
+    })
+}
+
+/// Draws the dashboard of the copy's phase `phase` until its bar is
+/// finished or gone.
+#[cfg(feature = "tui")]
+fn draw(shared: Weak<Shared>, bar: WeakProgressBar, phase: u64) {
+    let mut view = JobView::default();
+    loop {
+        thread::sleep(REFRESH_INTERVAL);
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        if bar.upgrade().is_none_or(|bar| bar.is_finished()) {
+            let mut state = shared.lock();
+            // A later phase has a dashboard of its own.
+            if state.phase == phase {
+                shared.settle(&mut state, true);
+            }
+            return;
+        }
+        view.update(shared.status());
+
+        let mut state = shared.lock();
+        if state.phase != phase {
+            return;
+        }
+        match &mut state.screen {
+            // The one job gets the whole window for its messages.
+            Some(screen) => screen.draw(|frame| draw_frame(frame, TUI_TITLE, &[&view], usize::MAX)),
+            None => return,
+        }
+    }
+}
+
+impl Reporter for DashboardReporter {
+    fn message(&self, line: &str) {
+        let mut state = self.shared.lock();
+        self.shared.settle(&mut state, false);
+        self.shared.remember(&mut state, line);
+        match state.screen {
+            Some(_) => state.held.push(Held::Message(line.to_string())),
+            None => self.shared.inner.message(line),
+        }
+    }
+
+    fn diagnostic(&self, diagnostic: &Diagnostic) {
+        let mut state = self.shared.lock();
+        self.shared.settle(&mut state, false);
+        self.shared.remember(&mut state, &diagnostic.render(false));
+        match state.screen {
+            Some(_) => state.held.push(Held::Diagnostic(diagnostic.clone())),
+            None => self.shared.inner.diagnostic(diagnostic),
+        }
+    }
+
+    fn data(&self, text: &str) -> io::Result<()> {
+        let mut state = self.shared.lock();
+        self.shared.settle(&mut state, false);
+        self.shared.inner.data(text)
+    }
+
+    fn progress_bar(&self, total: Option<u64>) -> ProgressBar {
+        let mut state = self.shared.lock();
+        self.shared.settle(&mut state, true);
+        state.phase += 1;
+        #[cfg(feature = "tui")]
+        if self.tui {
+            return self.dashboard(&mut state, total);
+        }
+        let bar = self.shared.inner.progress_bar(total);
+        state.bar = Some(bar.downgrade());
+        bar
+    }
+
+    fn message_over(&self, _progress: &ProgressBar, line: &str) {
+        self.message(line);
+    }
+}
+
+impl DashboardReporter {
+    /// The hidden bar of a phase drawn on the dashboard, which a thread
+    /// draws until the phase is over.
+    #[cfg(feature = "tui")]
+    fn dashboard(&self, state: &mut State, total: Option<u64>) -> ProgressBar {
+        let bar = ProgressBar::hidden();
+        if let Some(total) = total {
+            bar.set_length(total);
+        }
+        state.bar = Some(bar.downgrade());
+        state.screen = Screen::enter();
+        if state.screen.is_some() {
+            let (shared, weak_bar, phase) = (Arc::downgrade(&self.shared), bar.downgrade(), state.phase);
+            if thread::Builder::new()
+                .name("rdd-dashboard".to_string())
+                .spawn(move || draw(shared, weak_bar, phase))
+                .is_err()
+            {
+                state.screen = None;
+            }
+        }
+        bar
+    }
+}
+
+/// The jobs in the job directory that are still running, by process id.
+fn read_jobs() -> BTreeMap<u32, JobStatus> {
+    let mut jobs = BTreeMap::new();
+    let Ok(entries) = open_job_dir().and_then(fs::read_dir) else {
+        return jobs;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|extension| extension != "job") {
+            continue;
+        }
+        let fresh = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| SystemTime::now().duration_since(modified).unwrap_or_default() < STALE_AFTER);
+        if let Some(job) = fs::read_to_string(&path).ok().filter(|_| fresh).and_then(|text| JobStatus::parse(&text)) {
+            jobs.insert(job.pid, job);
+        }
+    }
+    jobs
+}
+
+/// "1 copy running", "2 copies running".
+fn running(jobs: usize) -> String {
+    format!("{} {} running", jobs, if jobs == 1 { "copy" } else { "copies" })
+}
+
+/// Runs the 'top' command: shows every running copy until Ctrl-C, or once
+/// with --once.
+pub fn run_top(config: &TopConfig) -> RddResult<()> {
+    let mut views: BTreeMap<u32, JobView> = BTreeMap::new();
+    if config.once {
+        refresh(&mut views);
+        let shown: Vec<_> = views.values().collect();
+        let title = format!("rdd top: {}", running(shown.len()));
+        let lines = render(&title, &shown, TOP_MESSAGES);
+        return Ok(config.reporter.data(&format!("{}\n", lines.join("\n")))?);
+    }
+    watch(config, views)
+}
+
+/// Brings `views` up to date with the running jobs.
+fn refresh(views: &mut BTreeMap<u32, JobView>) {
+    let mut jobs = read_jobs();
+    views.retain(|pid, _| jobs.contains_key(pid));
+    for (pid, job) in jobs.iter_mut() {
+        views.entry(*pid).or_default().update(std::mem::take(job));
+    }
+}
+
+/// Draws every running copy until Ctrl-C.
+#[cfg(feature = "tui")]
+fn watch(config: &TopConfig, mut views: BTreeMap<u32, JobView>) -> RddResult<()> {
+    let mut screen = Screen::enter().ok_or_else(|| {
+        RddError::Config("'rdd top' needs a terminal to draw on; use --once to print the copies instead.".to_string())
+    })?;
+    let idle = ProgressBar::hidden();
+    loop {
+        refresh(&mut views);
+        let shown: Vec<_> = views.values().collect();
+        let title = format!("rdd top: {}  (Ctrl-C quits)", running(shown.len()));
+        screen.draw(|frame| draw_frame(frame, &title, &shown, TOP_MESSAGES));
+        thread::sleep(REFRESH_INTERVAL * 2);
+        if signals::check(config.reporter.as_ref(), &idle).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Without the tui feature there's nothing to draw with.
+#[cfg(not(feature = "tui"))]
+fn watch(_config: &TopConfig, _views: BTreeMap<u32, JobView>) -> RddResult<()> {
+    Err(RddError::FeatureDisabled {
+        what: "'rdd top' without --once".to_string(),
+        feature: "tui".to_string(),
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    #[test]
+    fn only_a_private_directory_holds_job_files() {
+        let dir = std::env::temp_dir().join(format!("rdd-dashboard-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (private_dir, shared_dir, link) = (dir.join("private"), dir.join("shared"), dir.join("link"));
+        fs::create_dir(&private_dir).unwrap();
+        fs::set_permissions(&private_dir, fs::Permissions::from_mode(0o700)).unwrap();
+        fs::create_dir(&shared_dir).unwrap();
+        fs::set_permissions(&shared_dir, fs::Permissions::from_mode(0o777)).unwrap();
+        symlink(&private_dir, &link).unwrap();
+
+        let is_private = |path: &Path| private(&fs::symlink_metadata(path).unwrap());
+        let checked = [is_private(&private_dir), is_private(&shared_dir), is_private(&link)];
+
+        // A job file is never written through a planted symlink.
+        let target = dir.join("target");
+        fs::write(&target, b"keep").unwrap();
+        symlink(&target, dir.join("1.tmp")).unwrap();
+        let planted = create_new(&dir.join("1.tmp")).is_err();
+        let kept = fs::read(&target).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(checked, [true, false, false]);
+        assert!(planted);
+        assert_eq!(kept, b"keep");
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn a_job_is_drawn_in_its_box() {
+        use ratatui::Terminal;
+        use ratatui::backend::TestBackend;
+
+        let view = JobView {
+            status: JobStatus {
+                pid: 42,
+                input: "disk.img".to_string(),
+                output: "/dev/sdb".to_string(),
+                bytes: 512 << 20,
+                total: Some(1 << 30),
+                elapsed: Duration::from_secs(62),
+                status: "sdb 41°C".to_string(),
+                messages: vec!["first".to_string(), "second".to_string()],
+            },
+            rates: VecDeque::from([10e6, 20e6]),
+            sampled: None,
+        };
+        let mut terminal = Terminal::new(TestBackend::new(80, 16)).unwrap();
+        terminal.draw(|frame| draw_frame(frame, "rdd top", &[&view], 1)).unwrap();
+        let screen: Vec<String> = (0..16)
+            .map(|y| (0..80).map(|x| terminal.backend().buffer()[(x, y)].symbol()).collect())
+            .collect();
+
+        assert_eq!(screen[0].trim_end(), "rdd top");
+        assert!(screen[2].contains("disk.img -> /dev/sdb   (pid 42, 0:01:02)"));
+        assert!(screen[3].contains(" 50.0%  512.00 MiB / 1.00 GiB  19.07 MiB/s"));
+        assert!(screen[6].contains("peak 19.07 MiB/s"));
+        assert!(screen[7].contains("sdb 41°C"));
+        // Only the latest message was asked for.
+        assert!(screen[8].contains("second"));
+        assert!(!screen.iter().any(|line| line.contains("first")));
+    }
+}
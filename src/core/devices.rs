@@ -0,0 +1,599 @@
+// This file implements the 'list' command, which shows the drives attached to the computer. Picking the output of a copy onto a USB stick by guessing between /dev/sdb and /dev/sdc is how system disks get overwritten. 'rdd list' shows every drive with its size and model, whether it's removable, whether the running system lives on it and where its partitions are mounted, so the right one is easy to tell apart.
+
+// Explanation of this file:
+// Device struct: One whole drive. Partitions aren't listed on their own; their mount points are shown with the drive they belong to.
+// Platform backends:
+    // Linux : Reads /sys/block. Virtual devices (loop, ram, zram, device mapper, md) have no hardware behind them and are left out. A drive counts as removable if the kernel says so or if it hangs off a USB bus, since USB hard disks report themselves as fixed. Mount points come from /proc/self/mountinfo, traced through the device mapper (LVM, LUKS) down to the drives underneath. The serial number is the drive's own (SD cards, NVMe) or, on USB, the USB device's.
+    // macOS : Lists /dev/diskN and asks 'diskutil info' about each one. APFS containers are synthesized disks and are left out; their physical store is the drive.
+    // Windows : Opens \\.\PhysicalDrive0 and up without reading them, and asks the drive for its size and its vendor, model and bus (IOCTL_STORAGE_QUERY_PROPERTY). Volumes with drive letters are matched to the drives they lie on (see also utils/rawdevice.rs).
+    // Anything else : Listing drives isn't supported, and the command fails with a clear error.
+// System drives : A drive holds the running system if /, /boot, /boot/efi or /usr is mounted from it (the system drive, usually C:, on Windows; the drive that / lives on on macOS). They're flagged in the list, so nobody picks one as the output of a copy.
+// find_device : The drive a device path lies on, for a partition (/dev/sdb1), a raw node (/dev/rdisk2) or a volume (\\.\E:) as well as for the drive itself. The write guard uses it (see safety.rs).
+// The report : A table on stdout, or a JSON array with --report-format json. Both go through config.reporter.data, since the list is the result of the command.
+
+// src/core/devices.rs
+
+use crate::cli::StatsFormat;
+use crate::config::ListConfig;
+use crate::error::RddResult;
+use crate::utils::json::Json;
+use indicatif::BinaryBytes;
+use serde::{Deserialize, Serialize};
+
+/// A drive attached to the computer.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Device {
+    /// The path to copy from or to, e.g. /dev/sdb or \\.\PhysicalDrive1.
+    pub path: String,
+    /// Size in bytes.
+    pub size: u64,
+    /// Vendor and model, if the drive reports them.
+    pub model: Option<String>,
+    /// The drive's serial number, if it reports one. A USB card reader
+    /// reports its own, whatever card is in it.
+    #[serde(default)]
+    pub serial: Option<String>,
+    pub removable: bool,
+    /// Whether the running system lives on the drive.
+    pub system: bool,
+    /// Where the drive's partitions are mounted.
+    pub mountpoints: Vec<String>,
+}
+
+impl Device {
+    /// The flags shown in the table, e.g. "removable,mounted".
+    pub fn flags(&self) -> String {
+        let mut flags = Vec::new();
+        if self.removable {
+            flags.push("removable");
+        }
+        if self.system {
+            flags.push("system");
+        }
+        if !self.mountpoints.is_empty() {
+            flags.push("mounted");
+        }
+        flags.join(",")
+    }
+}
+
+/// The drives attached to the computer.
+pub fn list_devices() -> RddResult<Vec<Device>> {
+    platform::devices()
+}
+
+/// The drive that `path`, a drive or a partition or volume on one, lies on.
+/// None if `path` is a file, or a device that isn't in the list.
+pub fn find_device(path: &str) -> Option<Device> {
+    let drive = platform::drive_of(path)?;
+    list_devices()
+        .ok()?
+        .into_iter()
+        .find(|device| device.path == drive || device.mountpoints.contains(&drive))
+}
+
+/// Runs the 'list' command and returns the drives it listed.
+pub fn run_list(config: &ListConfig) -> RddResult<Vec<Device>> {
+    let mut devices = list_devices()?;
+    if config.removable_only {
+        devices.retain(|device| device.removable);
+    }
+
+    match config.report_format {
+        StatsFormat::Text => config.reporter.data(&table(&devices))?,
+        StatsFormat::Json => config.reporter.data(&format!("{:#}\n", list_json(&devices)))?,
+    }
+    Ok(devices)
+}
+
+/// The list as a table, one drive per line.
+fn table(devices: &[Device]) -> String {
+    if devices.is_empty() {
+        return "No drives found.\n".to_string();
+    }
+    let rows: Vec<[String; 5]> = devices
+        .iter()
+        .map(|device| {
+            [
+                device.path.clone(),
+                BinaryBytes(device.size).to_string(),
+                device.model.clone().unwrap_or_else(|| "-".to_string()),
+                device.flags(),
+                device.mountpoints.join(" "),
+            ]
+        })
+        .collect();
+    let header = ["DEVICE", "SIZE", "MODEL", "FLAGS", "MOUNTED AT"].map(str::to_string);
+    let mut widths = header.clone().map(|title| title.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// The list as JSON.
+fn list_json(devices: &[Device]) -> Json {
+    Json::Array(
+        devices
+            .iter()
+            .map(|device| {
+                Json::object([
+                    ("path", Json::str(&device.path)),
+                    ("size", Json::Int(device.size)),
+                    ("model", Json::or_null(device.model.as_ref(), Json::str)),
+                    ("serial", Json::or_null(device.serial.as_ref(), Json::str)),
+                    ("removable", Json::Bool(device.removable)),
+                    ("system", Json::Bool(device.system)),
+                    ("mountpoints", Json::Array(device.mountpoints.iter().map(Json::str).collect())),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// Mount points that only the drive of the running system has.
+#[cfg(target_os = "linux")]
+const SYSTEM_MOUNTS: &[&str] = &["/", "/boot", "/boot/efi", "/efi", "/usr"];
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{Device, SYSTEM_MOUNTS};
+    use crate::error::{IoResultExt, RddResult};
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::Path;
+
+    const SYS_BLOCK: &str = "/sys/block";
+    const MOUNTINFO: &str = "/proc/self/mountinfo";
+
+    /// A sysfs attribute of the drive, trimmed, or None if it's missing or
+    /// empty.
+    fn attribute(dir: &Path, name: &str) -> Option<String> {
+        let value = fs::read_to_string(dir.join(name)).ok()?;
+        Some(value.trim().to_string()).filter(|value| !value.is_empty())
+    }
+
+    /// The drives a block device in /sys/dev/block lies on: the whole disk
+    /// of a partition, or the drives underneath a device mapper or md device.
+    fn drives_of(dev: &str, drives: &mut Vec<String>) {
+        let Ok(dir) = fs::canonicalize(format!("/sys/dev/block/{}", dev)) else {
+            return;
+        };
+        let disk = match dir.join("partition").exists() {
+            true => dir.parent().map(Path::to_path_buf).unwrap_or(dir),
+            false => dir,
+        };
+        let slaves = fs::read_dir(disk.join("slaves")).into_iter().flatten().flatten();
+        let mut stacked = false;
+        for slave in slaves {
+            if let Some(dev) = attribute(&slave.path(), "dev") {
+                stacked = true;
+                drives_of(&dev, drives);
+            }
+        }
+        if !stacked && let Some(name) = disk.file_name() {
+            drives.push(name.to_string_lossy().into_owned());
+        }
+    }
+
+    /// Undoes the octal escapes of /proc/self/mountinfo (e.g. \040 for a space).
+    fn unescape(field: &str) -> String {
+        let mut out = String::new();
+        let mut rest = field;
+        while let Some(at) = rest.find('\\') {
+            out.push_str(&rest[..at]);
+            match rest.get(at + 1..at + 4).and_then(|code| u8::from_str_radix(code, 8).ok()) {
+                Some(byte) => {
+                    out.push(byte as char);
+                    rest = &rest[at + 4..];
+                }
+                None => {
+                    out.push('\\');
+                    rest = &rest[at + 1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// The mount points of every drive, by the drive's name.
+    fn mountpoints() -> BTreeMap<String, Vec<String>> {
+        let mut by_drive: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let Ok(mountinfo) = fs::read_to_string(MOUNTINFO) else {
+            return by_drive;
+        };
+        for line in mountinfo.lines() {
+            let fields: Vec<_> = line.split(' ').collect();
+            let (Some(dev), Some(mountpoint)) = (fields.get(2), fields.get(4)) else {
+                continue;
+            };
+            let mut drives = Vec::new();
+            drives_of(dev, &mut drives);
+            for drive in drives {
+                let mounts = by_drive.entry(drive).or_default();
+                let mountpoint = unescape(mountpoint);
+                if !mounts.contains(&mountpoint) {
+                    mounts.push(mountpoint);
+                }
+            }
+        }
+        by_drive
+    }
+
+    pub fn drive_of(path: &str) -> Option<String> {
+        let dir = crate::utils::sysfs::whole_disk(path).ok()??;
+        Some(format!("/dev/{}", dir.file_name()?.to_string_lossy()))
+    }
+
+    pub fn devices() -> RddResult<Vec<Device>> {
+        let mut names = fs::read_dir(SYS_BLOCK)
+            .with_path(SYS_BLOCK)?
+            .map(|entry| entry.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<Vec<_>, _>>()
+            .with_path(SYS_BLOCK)?;
+        names.sort();
+        let mut mounts = mountpoints();
+
+        let mut devices = Vec::new();
+        for name in names {
+            let dir = Path::new(SYS_BLOCK).join(&name);
+            let real = fs::canonicalize(&dir).with_path(&dir.to_string_lossy())?;
+            let real = real.to_string_lossy();
+            if real.contains("/virtual/") {
+                continue;
+            }
+            let sectors: u64 = attribute(&dir, "size").and_then(|size| size.parse().ok()).unwrap_or(0);
+            // virtio and NVMe drives report a PCI vendor number, not a name.
+            let vendor = attribute(&dir, "device/vendor").filter(|vendor| !vendor.starts_with("0x"));
+            let model = [vendor, attribute(&dir, "device/model"), attribute(&dir, "device/name")]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(" ");
+            // A USB drive's serial number belongs to the USB device, further up.
+            let serial = attribute(&dir, "device/serial").or_else(|| {
+                let usb = Path::new(real.as_ref()).ancestors().find(|dir| dir.join("idVendor").exists())?;
+                attribute(usb, "serial")
+            });
+            let mountpoints = mounts.remove(&name).unwrap_or_default();
+            devices.push(Device {
+                path: format!("/dev/{}", name),
+                // sysfs counts in 512-byte sectors, whatever the drive's own.
+                size: sectors * 512,
+                model: Some(model).filter(|model| !model.is_empty()),
+                serial,
+                removable: attribute(&dir, "removable").as_deref() == Some("1") || real.contains("/usb"),
+                system: mountpoints.iter().any(|mount| SYSTEM_MOUNTS.contains(&mount.as_str())),
+                mountpoints,
+            });
+        }
+        Ok(devices)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::Device;
+    use crate::error::{IoResultExt, RddError, RddResult};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::process::Command;
+
+    /// What 'diskutil info' says about a disk or volume, by key.
+    fn info(target: &str) -> RddResult<HashMap<String, String>> {
+        let output = Command::new("diskutil").args(["info", target]).output().with_path("diskutil")?;
+        if !output.status.success() {
+            return Err(RddError::Config(format!("'diskutil info {}' failed.", target)));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .collect())
+    }
+
+    /// The whole disk a disk identifier like disk3s1 belongs to.
+    fn whole_disk(identifier: &str) -> String {
+        let number = identifier.trim_start_matches("/dev/").trim_start_matches("disk");
+        format!("disk{}", number.chars().take_while(char::is_ascii_digit).collect::<String>())
+    }
+
+    /// The physical disk the running system lives on.
+    fn system_disk() -> Option<String> {
+        let root = info("/").ok()?;
+        let disk = whole_disk(root.get("Part of Whole")?);
+        // An APFS volume lies on a synthesized disk; the drive is its store.
+        let container = info(&disk).ok()?;
+        Some(match container.get("APFS Physical Store") {
+            Some(store) => whole_disk(store),
+            None => disk,
+        })
+    }
+
+    pub fn drive_of(path: &str) -> Option<String> {
+        // /dev/rdisk2 is the raw (unbuffered) node of /dev/disk2.
+        let name = path.strip_prefix("/dev/")?;
+        let name = name.strip_prefix('r').unwrap_or(name);
+        name.starts_with("disk").then(|| format!("/dev/{}", whole_disk(name)))
+    }
+
+    pub fn devices() -> RddResult<Vec<Device>> {
+        let mut disks: Vec<(u32, String)> = fs::read_dir("/dev")
+            .with_path("/dev")?
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let number = name.strip_prefix("disk")?.parse().ok()?;
+                Some((number, name))
+            })
+            .collect();
+        disks.sort();
+        let system = system_disk();
+
+        let mut devices = Vec::new();
+        for (_, name) in disks {
+            let path = format!("/dev/{}", name);
+            let Ok(disk) = info(&path) else {
+                continue;
+            };
+            if disk.get("Virtual").is_some_and(|virtual_disk| virtual_disk.starts_with("Yes")) {
+                continue;
+            }
+            // "Disk Size: 16.0 GB (16008609792 Bytes) (exactly ...)"
+            let size = disk
+                .get("Disk Size")
+                .and_then(|size| size.split_once('(')?.1.split_whitespace().next()?.parse().ok())
+                .unwrap_or(0);
+            devices.push(Device {
+                size,
+                model: disk.get("Device / Media Name").cloned().filter(|model| !model.is_empty()),
+                // 'diskutil info' doesn't show serial numbers.
+                serial: None,
+                removable: disk.get("Removable Media").is_some_and(|media| media != "Fixed")
+                    || disk.get("Device Location").is_some_and(|location| location == "External"),
+                system: system.as_deref() == Some(name.as_str()),
+                mountpoints: Vec::new(),
+                path,
+            });
+        }
+        Ok(devices)
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::Device;
+    use crate::utils::rawdevice::RawDevice;
+    use crate::error::RddResult;
+    use std::collections::BTreeMap;
+    use std::ffi::c_void;
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::mem;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr;
+
+    const FILE_SHARE_READ: u32 = 0x1;
+    const FILE_SHARE_WRITE: u32 = 0x2;
+    const IOCTL_DISK_GET_LENGTH_INFO: u32 = 0x0007_405c;
+    const IOCTL_STORAGE_QUERY_PROPERTY: u32 = 0x002d_1400;
+    const IOCTL_VOLUME_GET_VOLUME_DISK_EXTENTS: u32 = 0x0056_0000;
+
+    /// Drives are numbered from 0, with gaps where one was removed.
+    const MAX_DRIVES: u32 = 64;
+
+    /// STORAGE_BUS_TYPE values of buses that removable drives sit on.
+    const REMOVABLE_BUSES: &[u32] = &[7 /* USB */, 0xc /* SD */, 0xd /* MMC */];
+
+    /// How many extents of a volume we look at.
+    const MAX_EXTENTS: usize = 8;
+
+    #[repr(C)]
+    struct StoragePropertyQuery {
+        property_id: u32,
+        query_type: u32,
+        additional: [u8; 4],
+    }
+
+    /// The fixed part of STORAGE_DEVICE_DESCRIPTOR. The strings it points to
+    /// follow it in the same buffer.
+    #[repr(C)]
+    struct StorageDeviceDescriptor {
+        version: u32,
+        size: u32,
+        device_type: u8,
+        device_type_modifier: u8,
+        removable_media: u8,
+        command_queueing: u8,
+        vendor_id_offset: u32,
+        product_id_offset: u32,
+        product_revision_offset: u32,
+        serial_number_offset: u32,
+        bus_type: u32,
+    }
+
+    #[repr(C)]
+    #[derive(Default, Clone, Copy)]
+    struct DiskExtent {
+        disk_number: u32,
+        starting_offset: i64,
+        extent_length: i64,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct VolumeDiskExtents {
+        count: u32,
+        extents: [DiskExtent; MAX_EXTENTS],
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn DeviceIoControl(
+            device: *mut c_void,
+            code: u32,
+            input: *mut c_void,
+            input_size: u32,
+            output: *mut c_void,
+            output_size: u32,
+            returned: *mut u32,
+            overlapped: *mut c_void,
+        ) -> i32;
+        fn GetLogicalDrives() -> u32;
+    }
+
+    /// Sends `code` with `input` to the device, and fills `output`.
+    fn control<I, T>(file: &File, code: u32, input: Option<&mut I>, output: &mut T) -> io::Result<()> {
+        let (input, input_size) = match input {
+            Some(input) => (input as *mut I as *mut c_void, mem::size_of::<I>() as u32),
+            None => (ptr::null_mut(), 0),
+        };
+        let mut returned = 0;
+        // SAFETY: the handle is open for as long as `file` lives, and both
+        // buffers are live and as large as we say.
+        let ok = unsafe {
+            DeviceIoControl(
+                file.as_raw_handle(),
+                code,
+                input,
+                input_size,
+                output as *mut T as *mut c_void,
+                mem::size_of_val(output) as u32,
+                &mut returned,
+                ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Opens a drive or volume without reading or writing it, which needs
+    /// no administrator rights.
+    fn open_device(path: &str) -> io::Result<File> {
+        OpenOptions::new()
+            .access_mode(0)
+            .share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE)
+            .open(path)
+    }
+
+    /// The drive letters of every drive, by drive number.
+    fn volumes() -> BTreeMap<u32, Vec<String>> {
+        let mut by_drive: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+        // SAFETY: GetLogicalDrives takes no arguments.
+        let letters = unsafe { GetLogicalDrives() };
+        for letter in ('A'..='Z').filter(|&letter| letters & (1 << (letter as u32 - 'A' as u32)) != 0) {
+            let Ok(volume) = open_device(&format!(r"\\.\{}:", letter)) else {
+                continue;
+            };
+            let mut extents = VolumeDiskExtents::default();
+            if control::<(), _>(&volume, IOCTL_VOLUME_GET_VOLUME_DISK_EXTENTS, None, &mut extents).is_err() {
+                continue;
+            }
+            for extent in &extents.extents[..(extents.count as usize).min(MAX_EXTENTS)] {
+                by_drive.entry(extent.disk_number).or_default().push(format!(r"{}:\", letter));
+            }
+        }
+        by_drive
+    }
+
+    /// The NUL-terminated string at `offset` in the descriptor buffer.
+    fn descriptor_string(buffer: &[u8], offset: u32) -> Option<String> {
+        let bytes = buffer.get(offset as usize..).filter(|_| offset != 0)?;
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        Some(String::from_utf8_lossy(&bytes[..end]).trim().to_string()).filter(|s| !s.is_empty())
+    }
+
+    pub fn drive_of(path: &str) -> Option<String> {
+        // A volume is found by its drive letter among the mount points.
+        match crate::utils::rawdevice::raw_device(path)? {
+            RawDevice::Disk(number) => Some(format!(r"\\.\PhysicalDrive{}", number)),
+            RawDevice::Volume(letter) => Some(format!(r"{}:\", letter)),
+        }
+    }
+
+    pub fn devices() -> RddResult<Vec<Device>> {
+        let mut volumes = volumes();
+        let system_drive = std::env::var("SystemDrive").unwrap_or_else(|_| "C:".to_string());
+        let system_mount = format!(r"{}\", system_drive.to_ascii_uppercase());
+
+        let mut devices = Vec::new();
+        for number in 0..MAX_DRIVES {
+            let path = format!(r"\\.\PhysicalDrive{}", number);
+            let Ok(drive) = open_device(&path) else {
+                continue;
+            };
+            let mut size = 0i64;
+            if control::<(), _>(&drive, IOCTL_DISK_GET_LENGTH_INFO, None, &mut size).is_err() {
+                continue;
+            }
+
+            let mut query = StoragePropertyQuery {
+                property_id: 0, // StorageDeviceProperty
+                query_type: 0,  // PropertyStandardQuery
+                additional: [0; 4],
+            };
+            // u64s, so the descriptor at its start is aligned.
+            let mut buffer = [0u64; 128];
+            let (mut model, mut serial, mut removable) = (None, None, false);
+            if control(&drive, IOCTL_STORAGE_QUERY_PROPERTY, Some(&mut query), &mut buffer).is_ok() {
+                // SAFETY: the buffer is aligned and larger than the
+                // descriptor, and was filled in by the drive.
+                let descriptor = unsafe { &*(buffer.as_ptr() as *const StorageDeviceDescriptor) };
+                // SAFETY: any bytes are valid u8s.
+                let bytes = unsafe { std::slice::from_raw_parts(buffer.as_ptr() as *const u8, mem::size_of_val(&buffer)) };
+                let names: Vec<_> = [descriptor.vendor_id_offset, descriptor.product_id_offset]
+                    .into_iter()
+                    .filter_map(|offset| descriptor_string(bytes, offset))
+                    .collect();
+                model = Some(names.join(" ")).filter(|model| !model.is_empty());
+                serial = descriptor_string(bytes, descriptor.serial_number_offset);
+                removable = descriptor.removable_media != 0 || REMOVABLE_BUSES.contains(&descriptor.bus_type);
+            }
+
+            let mountpoints = volumes.remove(&number).unwrap_or_default();
+            devices.push(Device {
+                path,
+                size: size.max(0) as u64,
+                model,
+                serial,
+                removable,
+                system: mountpoints.contains(&system_mount),
+                mountpoints,
+            });
+        }
+        Ok(devices)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    use super::Device;
+    use crate::error::{RddError, RddResult};
+
+    pub fn drive_of(_path: &str) -> Option<String> {
+        None
+    }
+
+    pub fn devices() -> RddResult<Vec<Device>> {
+        Err(RddError::Config(
+            "'rdd list' isn't supported on this platform; list the drives with the system's own tools.".to_string(),
+        ))
+    }
+}
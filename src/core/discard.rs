@@ -0,0 +1,126 @@
+// This file implements --discard-zeros, which tells the output to deallocate the all-zero regions of the data instead of writing zeros over them. Disk images are mostly empty space: restoring one to an SSD or a thin-provisioned volume by writing every zero wears the flash and fills the volume's pool with blocks that hold nothing. Discarding them is also much faster than writing them.
+
+// Explanation of this file:
+// Punching : A zero chunk is punched out of the output with fallocate(FALLOC_FL_PUNCH_HOLE), which works on both regular files (leaving a hole, like --sparse) and block devices. On a device, the kernel only does it where the device guarantees the region reads back as zeros afterwards, by unmapping it or with a WRITE ZEROES command; a plain BLKDISCARD makes no such promise, so it isn't used.
+// Alignment : Only whole units of the output's discard granularity can be deallocated: the device's discard_granularity from sysfs (at least its logical sector size), or the file's block size. The aligned middle of a zero chunk is punched and its unaligned edges are written as zeros. Partitions are normally aligned to 1 MiB, so their offsets don't get in the way.
+// Falling back : An output that can't punch holes (EOPNOTSUPP, e.g. a USB stick, or a filesystem without hole support) gets the zeros written instead, with one warning. The copy is right either way; only the saving is lost.
+// Platform : fallocate is Linux-only, so config.rs rejects --discard-zeros elsewhere.
+
+// src/core/discard.rs
+
+use crate::config::CopyConfig;
+use crate::error::{IoResultExt, RddResult};
+use crate::utils::diagnostics::Diagnostic;
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+
+/// Deallocates the zero chunks of a copy in its output (--discard-zeros).
+#[derive(Debug)]
+pub struct Discarder {
+    /// The unit the output deallocates in.
+    granularity: u64,
+    /// False once the output turned out not to support it.
+    supported: bool,
+}
+
+impl Discarder {
+    /// A discarder for `output`, the output of `config`.
+    pub fn new(config: &CopyConfig, output: &File) -> Self {
+        let granularity = platform::granularity(output, &config.output_file).unwrap_or(4096).max(1);
+        Discarder {
+            granularity,
+            supported: true,
+        }
+    }
+
+    /// Puts the zeros of `zeros` at the current position of `output`,
+    /// discarding what can be and writing the rest, and moves past them.
+    /// Returns the number of bytes discarded.
+    pub fn zeros(&mut self, config: &CopyConfig, output: &mut File, zeros: &[u8]) -> RddResult<u64> {
+        let path = config.output_file.as_str();
+        let start = output.stream_position().with_path(path)?;
+        let end = start + zeros.len() as u64;
+        let first = start.next_multiple_of(self.granularity);
+        let last = end / self.granularity * self.granularity;
+        if !self.supported || first >= last {
+            output.write_all(zeros).at_offset(path, start)?;
+            return Ok(0);
+        }
+
+        match platform::punch_hole(output, first, last - first) {
+            Ok(()) => {}
+            Err(e) if matches!(e.raw_os_error(), Some(code) if code == platform::EOPNOTSUPP) => {
+                self.supported = false;
+                config.reporter.diagnostic(
+                    &Diagnostic::warning(format!(
+                        "'{}' can't deallocate zeros without risking old data reading back; writing them instead.",
+                        path
+                    ))
+                    .with_flag("--discard-zeros"),
+                );
+                output.write_all(zeros).at_offset(path, start)?;
+                return Ok(0);
+            }
+            Err(e) => return Err(e).at_offset(path, first),
+        }
+        // The unaligned edges are written like any other data.
+        output.write_all(&zeros[..(first - start) as usize]).at_offset(path, start)?;
+        output.seek(SeekFrom::Start(last)).with_path(path)?;
+        output.write_all(&zeros[(last - start) as usize..]).at_offset(path, last)?;
+        // A hole punched past the end of a file doesn't make it longer.
+        let metadata = output.metadata().with_path(path)?;
+        if metadata.is_file() && metadata.len() < end {
+            output.set_len(end).with_path(path)?;
+        }
+        Ok(last - first)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use crate::utils::direct::logical_sector_size;
+    use crate::utils::sysfs;
+    use nix::fcntl::{fallocate, FallocateFlags};
+    use nix::libc;
+    use std::fs::{self, File};
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::MetadataExt;
+
+    pub const EOPNOTSUPP: i32 = libc::EOPNOTSUPP;
+
+    /// The unit `output` deallocates in: the device's discard granularity,
+    /// or the file's block size.
+    pub fn granularity(output: &File, path: &str) -> Option<u64> {
+        let Some(disk) = sysfs::whole_disk(path).ok()? else {
+            return Some(output.metadata().ok()?.blksize());
+        };
+        let sector = logical_sector_size(output).ok()? as u64;
+        let discard = fs::read_to_string(disk.join("queue/discard_granularity"))
+            .ok()
+            .and_then(|text| text.trim().parse::<u64>().ok())
+            .unwrap_or(0);
+        Some(discard.max(sector))
+    }
+
+    pub fn punch_hole(output: &File, offset: u64, len: u64) -> io::Result<()> {
+        let mode = FallocateFlags::FALLOC_FL_PUNCH_HOLE | FallocateFlags::FALLOC_FL_KEEP_SIZE;
+        fallocate(output.as_raw_fd(), mode, offset as libc::off_t, len as libc::off_t).map_err(io::Error::from)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use std::fs::File;
+    use std::io;
+
+    pub const EOPNOTSUPP: i32 = 95;
+
+    pub fn granularity(_output: &File, _path: &str) -> Option<u64> {
+        None
+    }
+
+    pub fn punch_hole(_output: &File, _offset: u64, _len: u64) -> io::Result<()> {
+        Err(io::Error::from_raw_os_error(EOPNOTSUPP))
+    }
+}
@@ -0,0 +1,721 @@
+// This file implements the io_uring engine (--engine io-uring), a Linux-only alternative to the read/write loop of copy.rs. The standard engine makes one system call at a time and waits for each one, so a fast NVMe drive spends much of the copy idle, waiting for rdd to ask for the next block. io_uring lets a program hand the kernel a queue of reads and writes and collect them as they finish, which keeps both drives busy.
+
+// Explanation of this file:
+// Ring : The kernel interface is a pair of ring buffers shared with the kernel through mmap: we put requests (submission queue entries) in one, and the kernel puts their results (completions) in the other. Ring sets them up with io_uring_setup and drives them with io_uring_enter. There's no io_uring crate among our dependencies, so the few structures we need are declared here, following linux/io_uring.h.
+// Slots : The copy has QUEUE_DEPTH buffers (LOW_MEMORY_QUEUE_DEPTH with --low-memory), each of at most --max-io bytes, and only as many as fit in --max-in-flight. A slot is read into, written out from, and then reused. Reads are issued as far ahead as there are free slots, and a slot's write is issued once every read before it has finished, so the data is hashed (--verify, manifests) in input order.
+// Offsets : Every read and write says where in the file it goes, so this engine needs files and devices it can read and write at offsets. build() rejects stdin, stdout, compression and splitting with it, and an input that turns out to be a pipe when it's opened (a decompressed input, a chunk set) fails the copy.
+// Short transfers : A read or write that moved fewer bytes than asked is resubmitted for the rest. A read that returns nothing is the end of the input. Blocks are counted from the bytes copied, which is what dd counts for files and devices.
+// Errors and stopping : The kernel may use a buffer until the completion of its request arrives. So on an error or Ctrl-C we stop issuing requests and wait for everything in flight before we return. Like the other engines, a copy stopped by Ctrl-C syncs what it wrote.
+// Fallback : A kernel without io_uring (older than 5.6, or with it turned off by a sysctl or seccomp) fails io_uring_setup. The copy then says so and runs in the standard engine. On other systems, and in builds without the io-uring cargo feature, build() rejects --engine io-uring.
+// Syncing : A write that completes is counted by the Syncer of --sync-mode (see syncmode.rs). A sync due then covers every write completed so far, not the ones still in flight.
+// Conversions : With --conv, a read is converted (see transform.rs) when it is handed on to its write.
+// After the copy: Drive health and power are polled like in the other engines, and the final sync, verification and summary are shared with them (finish_copy in copy.rs).
+
+// src/core/engine.rs
+
+use crate::config::CopyConfig;
+use crate::core::copy::CopyStats;
+use crate::error::RddResult;
+
+/// How many buffers the io_uring engine keeps in flight.
+const QUEUE_DEPTH: usize = 16;
+
+/// How many with --low-memory.
+const LOW_MEMORY_QUEUE_DEPTH: usize = 2;
+
+/// How many slots the io_uring engine has. Every slot holds a chunk, and
+/// together they may not hold more than --max-in-flight.
+pub(crate) fn queue_depth(config: &CopyConfig) -> usize {
+    let depth = match config.low_memory {
+        true => LOW_MEMORY_QUEUE_DEPTH,
+        false => QUEUE_DEPTH,
+    };
+    let chunk_size = config.block_size.min(config.max_io);
+    depth.min(config.max_in_flight / chunk_size).max(1)
+}
+
+/// Runs the copy with the io_uring engine, or with the standard one where
+/// the kernel doesn't offer io_uring.
+pub fn run_io_uring_copy(config: &CopyConfig) -> RddResult<CopyStats> {
+    platform::run(config)
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod platform {
+    use super::queue_depth;
+    use super::ring::{Op, Ring};
+    use crate::config::CopyConfig;
+    use crate::core::bandwidth::{BandwidthLog, Meter};
+    use crate::core::copy::{
+        direct_alignment, finish_copy, open_files, relax_direct_for, run_singlethreaded_copy,
+        skip_input, sync_output, take_input_snapshot, CopyStats, CopyTimer,
+    };
+    use crate::core::custody;
+    use crate::core::hash::CopyVerifier;
+    use crate::core::health::HealthMonitor;
+    use crate::core::power::PowerMonitor;
+    use crate::core::reporter::progress_bar;
+    use crate::core::syncmode::Syncer;
+    use crate::core::throttle::Throttle;
+    use crate::core::transform::Transform;
+    use crate::core::writecache::WriteCacheGuard;
+    use crate::error::{IoResultExt, RddError, RddResult};
+    use crate::utils::direct::AlignedBuffer;
+    use crate::utils::fadvise::advise_dont_need;
+    use crate::utils::signals;
+    use indicatif::ProgressBar;
+    use std::collections::VecDeque;
+    use std::fs::File;
+    use std::io::{self, Seek};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::FileTypeExt;
+    use std::sync::Arc;
+
+    pub fn run(config: &CopyConfig) -> RddResult<CopyStats> {
+        let depth = queue_depth(config);
+        match Ring::new(depth as u32) {
+            Ok(ring) => custody::logged(config, || copy_io_uring(config, ring, depth)),
+            Err(e) => {
+                config.reporter.message(&format!(
+                    "io_uring is not available ({}); copying with the standard engine instead.",
+                    e
+                ));
+                run_singlethreaded_copy(config)
+            }
+        }
+    }
+
+    /// Where a slot is in its cycle.
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum State {
+        Free,
+        /// Being read into; `done` bytes have arrived so far.
+        Reading { done: usize },
+        /// Read completely (or up to the end of the input).
+        Read,
+        /// Being written out from; `done` bytes are written so far.
+        Writing { done: usize },
+    }
+
+    /// A buffer and the part of the copy it's carrying.
+    struct Slot {
+        buffer: AlignedBuffer,
+        state: State,
+        /// Where the data comes from in the input and goes to in the output.
+        in_offset: u64,
+        out_offset: u64,
+        /// How many bytes the slot carries: asked for while reading, what
+        /// arrived once it's read.
+        len: usize,
+    }
+
+    fn copy_io_uring(config: &CopyConfig, mut ring: Ring, depth: usize) -> RddResult<CopyStats> {
+        let input_snapshot = take_input_snapshot(config)?;
+
+        let (mut input_file, output_file, filters) = open_files(config)?;
+        for (file, path) in [(&input_file, &config.input_file), (&output_file, &config.output_file)] {
+            let file_type = file.metadata().with_path(path)?.file_type();
+            if !file_type.is_file() && !file_type.is_block_device() {
+                return Err(RddError::Config(format!(
+                    "--engine io-uring reads and writes at offsets, which '{}' doesn't allow (it's a pipe or a character device); copy it with the standard engine.",
+                    path
+                )));
+            }
+        }
+
+        // Keep the target's write cache off until we return, synced or not.
+        let _write_cache = match config.disable_write_cache {
+            true => Some(WriteCacheGuard::disable(config)?),
+            false => None,
+        };
+
+        let timer = CopyTimer::start();
+        let progress = progress_bar(&*config.reporter, filters.expected_bytes(config, &input_file), config.show_progress);
+        let direct_align = direct_alignment(config, &output_file)?;
+
+        skip_input(config, &mut input_file)?;
+        let in_start = input_file.stream_position().with_path(&config.input_file)?;
+        let out_start = config.seek_bytes;
+        let bandwidth = BandwidthLog::start(config)?;
+
+        let chunk_size = config.block_size.min(config.max_io);
+        let slots = (0..depth)
+            .map(|_| Slot {
+                buffer: AlignedBuffer::new(chunk_size),
+                state: State::Free,
+                in_offset: 0,
+                out_offset: 0,
+                len: 0,
+            })
+            .collect();
+        let mut transfer = Transfer {
+            config,
+            ring: &mut ring,
+            input: &input_file,
+            output: &output_file,
+            progress: &progress,
+            direct_align,
+            slots,
+            order: VecDeque::new(),
+            in_flight: 0,
+            next_in: in_start,
+            next_out: out_start,
+            remaining: config
+                .count_bytes
+                .or((config.count > 0).then(|| config.count * config.block_size as u64)),
+            eof: false,
+            stats: CopyStats::default(),
+            verifier: CopyVerifier::for_copy(config),
+            syncer: Syncer::for_copy(config),
+            meter: bandwidth.meter(),
+        };
+        let result = transfer.run();
+        bandwidth.finish();
+        let (mut stats, mut verifier) = (transfer.stats, transfer.verifier);
+
+        // On Ctrl-C, make what was written durable before giving up.
+        if let Err(e @ RddError::Interrupted { .. }) = result {
+            sync_output(config, &output_file)?;
+            return Err(e);
+        }
+        let (health, power) = result?;
+
+        let block_size = config.block_size as u64;
+        stats.blocks_copied = stats.bytes_copied.div_ceil(block_size);
+        stats.partial_blocks = u64::from(!stats.bytes_copied.is_multiple_of(block_size));
+        stats.blocks_written = stats.blocks_copied;
+        stats.bytes_written = stats.bytes_copied;
+        stats.health = health.map(|health| health.totals);
+        stats.power = Some(power.totals);
+        if let Some(verifier) = verifier.as_mut() {
+            verifier.add_region(out_start, out_start + stats.bytes_copied);
+        }
+
+        let output_file = filters.finish(input_file, output_file)?;
+        finish_copy(
+            config,
+            &output_file,
+            &progress,
+            input_snapshot.as_ref(),
+            verifier,
+            stats,
+            timer,
+        )
+    }
+
+    /// The state of the transfer between the slots and the ring.
+    struct Transfer<'a> {
+        config: &'a CopyConfig,
+        ring: &'a mut Ring,
+        input: &'a File,
+        output: &'a File,
+        progress: &'a ProgressBar,
+        direct_align: Option<usize>,
+        slots: Vec<Slot>,
+        /// The slots being read, in input order.
+        order: VecDeque<usize>,
+        /// How many requests the kernel has that haven't completed.
+        in_flight: usize,
+        next_in: u64,
+        next_out: u64,
+        /// How many bytes may still be read, with --count.
+        remaining: Option<u64>,
+        eof: bool,
+        stats: CopyStats,
+        verifier: Option<CopyVerifier>,
+        /// Says when to sync the output along the way (--sync-mode).
+        syncer: Syncer,
+        /// Counts what's read and written for the bandwidth log.
+        meter: Arc<Meter>,
+    }
+
+    impl Transfer<'_> {
+        /// Moves the data until the input ends (or --count is reached).
+        /// Returns the health and power monitors for the summary.
+        fn run(&mut self) -> RddResult<(Option<HealthMonitor>, PowerMonitor)> {
+            let config = self.config;
+            let mut health = config.monitor_health.then(|| HealthMonitor::new(config));
+            let mut power = PowerMonitor::start(config, self.progress);
+            let mut throttle = config.rate_limit.map(Throttle::new);
+            let transform = Transform::for_copy(config);
+
+            let result = loop {
+                if let Err(e) = signals::check(&*config.reporter, self.progress) {
+                    break Err(e);
+                }
+                if let Err(e) = self.issue_reads() {
+                    break Err(e);
+                }
+
+                // Hand the reads that are done on to the writes, in order.
+                let mut failed = None;
+                while let Some(&index) = self.order.front()
+                    && self.slots[index].state == State::Read
+                {
+                    self.order.pop_front();
+                    let len = self.slots[index].len;
+                    if len == 0 {
+                        self.slots[index].state = State::Free;
+                        continue;
+                    }
+                    if let Some(throttle) = throttle.as_mut() {
+                        throttle.wait(len as u64);
+                    }
+                    if let Some(transform) = &transform {
+                        transform.apply(&mut self.slots[index].buffer[..len]);
+                    }
+                    if let Some(verifier) = self.verifier.as_mut() {
+                        verifier.update(&self.slots[index].buffer[..len]);
+                    }
+                    self.stats.bytes_copied += len as u64;
+                    if let Err(e) = relax_direct_for(config, self.output, self.direct_align, len) {
+                        failed = Some(e);
+                        break;
+                    }
+                    self.slots[index].state = State::Writing { done: 0 };
+                    if let Err(e) = self.submit(index) {
+                        failed = Some(e);
+                        break;
+                    }
+                }
+                if let Some(e) = failed {
+                    break Err(e);
+                }
+
+                if self.in_flight == 0 {
+                    break Ok(());
+                }
+                if let Err(e) = self.complete() {
+                    break Err(e);
+                }
+
+                if let Some(health) = health.as_mut() {
+                    health.poll(self.progress);
+                }
+                power.poll(self.progress);
+            };
+
+            // The kernel may still be using some buffers; let it finish.
+            if result.is_err() {
+                self.drain();
+            }
+            result.map(|_| (health, power))
+        }
+
+        /// Starts reading into every free slot, unless the input is done.
+        fn issue_reads(&mut self) -> RddResult<()> {
+            let chunk_size = self.slots[0].buffer.len() as u64;
+            for index in 0..self.slots.len() {
+                if self.eof || self.remaining == Some(0) {
+                    break;
+                }
+                if self.slots[index].state != State::Free {
+                    continue;
+                }
+                let len = self.remaining.map_or(chunk_size, |left| left.min(chunk_size));
+                let slot = &mut self.slots[index];
+                slot.state = State::Reading { done: 0 };
+                slot.in_offset = self.next_in;
+                slot.out_offset = self.next_out;
+                slot.len = len as usize;
+                self.next_in += len;
+                self.next_out += len;
+                if let Some(left) = self.remaining.as_mut() {
+                    *left -= len;
+                }
+                self.order.push_back(index);
+                self.submit(index)?;
+            }
+            Ok(())
+        }
+
+        /// Queues the request for the rest of what slot `index` is reading
+        /// or writing, and passes it to the kernel.
+        fn submit(&mut self, index: usize) -> RddResult<()> {
+            let slot = &mut self.slots[index];
+            let (op, fd, done, offset, path) = match slot.state {
+                State::Reading { done } => (Op::Read, self.input.as_raw_fd(), done, slot.in_offset, &self.config.input_file),
+                State::Writing { done } => {
+                    (Op::Write, self.output.as_raw_fd(), done, slot.out_offset, &self.config.output_file)
+                }
+                State::Free | State::Read => unreachable!("only reads and writes are submitted"),
+            };
+            let offset = offset + done as u64;
+            let rest = &mut slot.buffer[done..slot.len];
+            // SAFETY: the buffer lives in `self.slots` until the transfer is
+            // over, and run() waits for every request before it returns.
+            unsafe { self.ring.push(op, fd, rest.as_mut_ptr(), rest.len() as u32, offset, index as u64) };
+            self.in_flight += 1;
+            self.ring.submit(0).at_offset(path, offset)
+        }
+
+        /// Waits for at least one request to complete and handles every
+        /// completion that has arrived.
+        fn complete(&mut self) -> RddResult<()> {
+            self.ring.submit(1).with_path(&self.config.input_file)?;
+            while let Some((index, result)) = self.ring.pop() {
+                self.in_flight -= 1;
+                let index = index as usize;
+                let slot = &mut self.slots[index];
+                match slot.state {
+                    State::Reading { done } => {
+                        let offset = slot.in_offset + done as u64;
+                        let read = transferred(result).at_offset(&self.config.input_file, offset)?;
+                        self.meter.read(read as u64);
+                        let done = done + read;
+                        if read == 0 || done == slot.len {
+                            // Nothing more: the input ended inside this slot.
+                            self.eof |= read == 0;
+                            slot.len = done;
+                            slot.state = State::Read;
+                        } else {
+                            slot.state = State::Reading { done };
+                            self.submit(index)?;
+                        }
+                    }
+                    State::Writing { done } => {
+                        let offset = slot.out_offset + done as u64;
+                        let written = transferred(result).at_offset(&self.config.output_file, offset)?;
+                        if written == 0 {
+                            return Err(io::Error::from(io::ErrorKind::WriteZero))
+                                .at_offset(&self.config.output_file, offset);
+                        }
+                        self.meter.wrote(written as u64);
+                        let done = done + written;
+                        if done < slot.len {
+                            slot.state = State::Writing { done };
+                            self.submit(index)?;
+                            continue;
+                        }
+                        // The other writes in flight may or may not be covered.
+                        self.syncer.after_write(self.output, &self.config.output_file, slot.len as u64)?;
+                        if self.config.fadvise.drop_output_cache {
+                            advise_dont_need(self.output, slot.out_offset, slot.len as u64);
+                        }
+                        self.progress.inc(slot.len as u64);
+                        slot.state = State::Free;
+                    }
+                    State::Free | State::Read => unreachable!("only reads and writes complete"),
+                }
+            }
+            Ok(())
+        }
+
+        /// Waits for every request still in flight, ignoring their results.
+        fn drain(&mut self) {
+            while self.in_flight > 0 {
+                if self.ring.submit(1).is_err() {
+                    // Nothing else we can do; dropping the ring cancels them.
+                    return;
+                }
+                while self.ring.pop().is_some() {
+                    self.in_flight -= 1;
+                }
+            }
+        }
+    }
+
+    /// The bytes a completed request moved, or its error.
+    fn transferred(result: i32) -> io::Result<usize> {
+        match result {
+            n if n < 0 => Err(io::Error::from_raw_os_error(-n)),
+            n => Ok(n as usize),
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod ring {
+    use nix::libc;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::ptr;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    const IORING_OP_READ: u8 = 22;
+    const IORING_OP_WRITE: u8 = 23;
+    const IORING_ENTER_GETEVENTS: u32 = 1;
+    /// Set by kernels with IORING_OP_READ and IORING_OP_WRITE (5.6 and later).
+    const IORING_FEAT_RW_CUR_POS: u32 = 1 << 3;
+    const IORING_OFF_SQ_RING: i64 = 0;
+    const IORING_OFF_CQ_RING: i64 = 0x800_0000;
+    const IORING_OFF_SQES: i64 = 0x1000_0000;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct SqRingOffsets {
+        head: u32,
+        tail: u32,
+        ring_mask: u32,
+        ring_entries: u32,
+        flags: u32,
+        dropped: u32,
+        array: u32,
+        resv1: u32,
+        user_addr: u64,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct CqRingOffsets {
+        head: u32,
+        tail: u32,
+        ring_mask: u32,
+        ring_entries: u32,
+        overflow: u32,
+        cqes: u32,
+        flags: u32,
+        resv1: u32,
+        user_addr: u64,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Params {
+        sq_entries: u32,
+        cq_entries: u32,
+        flags: u32,
+        sq_thread_cpu: u32,
+        sq_thread_idle: u32,
+        features: u32,
+        wq_fd: u32,
+        resv: [u32; 3],
+        sq_off: SqRingOffsets,
+        cq_off: CqRingOffsets,
+    }
+
+    /// A submission queue entry.
+    #[repr(C)]
+    struct Sqe {
+        opcode: u8,
+        flags: u8,
+        ioprio: u16,
+        fd: i32,
+        off: u64,
+        addr: u64,
+        len: u32,
+        rw_flags: u32,
+        user_data: u64,
+        buf_index: u16,
+        personality: u16,
+        splice_fd_in: i32,
+        addr3: u64,
+        pad: u64,
+    }
+
+    /// A completion queue entry.
+    #[repr(C)]
+    struct Cqe {
+        user_data: u64,
+        res: i32,
+        flags: u32,
+    }
+
+    /// What a request does.
+    #[derive(Clone, Copy)]
+    pub enum Op {
+        Read,
+        Write,
+    }
+
+    /// A memory mapping of one of the rings, unmapped on drop.
+    struct Mapping {
+        ptr: *mut u8,
+        len: usize,
+    }
+
+    impl Mapping {
+        fn new(fd: RawFd, len: usize, offset: i64) -> io::Result<Self> {
+            // SAFETY: a fresh shared mapping of the ring, at no fixed address.
+            let ptr = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED | libc::MAP_POPULATE,
+                    fd,
+                    offset,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { ptr: ptr.cast(), len })
+        }
+
+        /// The field at byte `offset` of the mapping.
+        fn at<T>(&self, offset: u32) -> *mut T {
+            // SAFETY: the kernel's offsets lie within the mapping.
+            unsafe { self.ptr.add(offset as usize).cast() }
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            // SAFETY: mapped in new() with this length.
+            unsafe { libc::munmap(self.ptr.cast(), self.len) };
+        }
+    }
+
+    /// An io_uring instance with its submission and completion queues.
+    pub struct Ring {
+        // The mappings are dropped before the file descriptor.
+        sq: Mapping,
+        cq: Mapping,
+        sqes: Mapping,
+        sq_off: SqRingOffsets,
+        cq_off: CqRingOffsets,
+        sq_mask: u32,
+        cq_mask: u32,
+        /// Entries queued but not yet passed to the kernel.
+        unsubmitted: u32,
+        fd: OwnedFd,
+    }
+
+    impl Ring {
+        /// Sets up a ring with room for `entries` requests.
+        pub fn new(entries: u32) -> io::Result<Self> {
+            let mut params = Params::default();
+            // SAFETY: params is a valid io_uring_params for the kernel to fill.
+            let fd = unsafe { libc::syscall(libc::SYS_io_uring_setup, entries, &mut params as *mut Params) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: io_uring_setup returned a new file descriptor we own.
+            let fd = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+            if params.features & IORING_FEAT_RW_CUR_POS == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "the kernel's io_uring is too old (Linux 5.6 or later is needed)",
+                ));
+            }
+
+            let raw = fd.as_raw_fd();
+            let sq_len = params.sq_off.array as usize + params.sq_entries as usize * size_of::<u32>();
+            let cq_len = params.cq_off.cqes as usize + params.cq_entries as usize * size_of::<Cqe>();
+            let sq = Mapping::new(raw, sq_len, IORING_OFF_SQ_RING)?;
+            let cq = Mapping::new(raw, cq_len, IORING_OFF_CQ_RING)?;
+            let sqes = Mapping::new(raw, params.sq_entries as usize * size_of::<Sqe>(), IORING_OFF_SQES)?;
+            // SAFETY: the masks are plain u32 fields of the mapped rings.
+            let (sq_mask, cq_mask) = unsafe {
+                (
+                    *sq.at::<u32>(params.sq_off.ring_mask),
+                    *cq.at::<u32>(params.cq_off.ring_mask),
+                )
+            };
+            Ok(Self {
+                sq,
+                cq,
+                sqes,
+                sq_off: params.sq_off,
+                cq_off: params.cq_off,
+                sq_mask,
+                cq_mask,
+                unsubmitted: 0,
+                fd,
+            })
+        }
+
+        /// Queues a request to read or write `len` bytes at `addr`, at
+        /// `offset` in `fd`. Its completion carries `user_data`.
+        ///
+        /// # Safety
+        ///
+        /// The memory at `addr` must stay valid, and unused by anything
+        /// else, until the request's completion has been popped.
+        pub unsafe fn push(&mut self, op: Op, fd: RawFd, addr: *mut u8, len: u32, offset: u64, user_data: u64) {
+            let tail = self.sq.at::<AtomicU32>(self.sq_off.tail);
+            // SAFETY: the tail is only written by us, the entry and the array
+            // slot at the tail are ours until the tail moves past them. There
+            // is one request per slot of the copy, at most as many as the ring
+            // has entries, so the queue never overflows.
+            unsafe {
+                let tail_value = (*tail).load(Ordering::Relaxed);
+                let index = tail_value & self.sq_mask;
+                let sqe = self.sqes.at::<Sqe>(index * size_of::<Sqe>() as u32);
+                sqe.write(Sqe {
+                    opcode: match op {
+                        Op::Read => IORING_OP_READ,
+                        Op::Write => IORING_OP_WRITE,
+                    },
+                    flags: 0,
+                    ioprio: 0,
+                    fd,
+                    off: offset,
+                    addr: addr as u64,
+                    len,
+                    rw_flags: 0,
+                    user_data,
+                    buf_index: 0,
+                    personality: 0,
+                    splice_fd_in: 0,
+                    addr3: 0,
+                    pad: 0,
+                });
+                *self.sq.at::<u32>(self.sq_off.array).add(index as usize) = index;
+                (*tail).store(tail_value.wrapping_add(1), Ordering::Release);
+            }
+            self.unsubmitted += 1;
+        }
+
+        /// Passes the queued requests to the kernel and waits until at least
+        /// `wait` completions are there.
+        pub fn submit(&mut self, wait: u32) -> io::Result<()> {
+            let flags = if wait > 0 { IORING_ENTER_GETEVENTS } else { 0 };
+            loop {
+                // SAFETY: a plain io_uring_enter on our ring, without a signal mask.
+                let submitted = unsafe {
+                    libc::syscall(
+                        libc::SYS_io_uring_enter,
+                        self.fd.as_raw_fd(),
+                        self.unsubmitted,
+                        wait,
+                        flags,
+                        ptr::null::<libc::sigset_t>(),
+                        0usize,
+                    )
+                };
+                if submitted >= 0 {
+                    self.unsubmitted -= submitted as u32;
+                    return Ok(());
+                }
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+        }
+
+        /// Takes the next completion, if there is one: its user data and
+        /// result (bytes moved, or a negated errno).
+        pub fn pop(&mut self) -> Option<(u64, i32)> {
+            let head = self.cq.at::<AtomicU32>(self.cq_off.head);
+            let tail = self.cq.at::<AtomicU32>(self.cq_off.tail);
+            // SAFETY: the head is only written by us, and the entry at the
+            // head is the kernel's finished work until we move past it.
+            unsafe {
+                let head_value = (*head).load(Ordering::Relaxed);
+                if head_value == (*tail).load(Ordering::Acquire) {
+                    return None;
+                }
+                let index = head_value & self.cq_mask;
+                let cqe = self.cq.at::<Cqe>(self.cq_off.cqes).add(index as usize).read();
+                (*head).store(head_value.wrapping_add(1), Ordering::Release);
+                Some((cqe.user_data, cqe.res))
+            }
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+mod platform {
+    use crate::config::CopyConfig;
+    use crate::core::copy::CopyStats;
+    use crate::error::{RddError, RddResult};
+
+    pub fn run(_config: &CopyConfig) -> RddResult<CopyStats> {
+        Err(RddError::Config(
+            "--engine io-uring is only available on Linux, in builds with the io-uring feature.".to_string(),
+        ))
+    }
+}
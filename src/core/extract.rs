@@ -0,0 +1,173 @@
+// This file implements the 'extract' command, which writes part or all of the image in a seekable zstd or qcow2 archive to a file or drive. Restoring one partition from a full-disk backup, or pulling a few megabytes out of it to look at, used to mean decompressing the whole archive first; extract reads only the frames or clusters the request covers (see archive.rs). Without --range or --partition it restores the whole image, so it's also how such an archive goes back onto a drive.
+
+// Explanation of this file:
+// What's written : --range OFFSET:LENGTH picks bytes of the image, and --partition N the Nth partition of its partition table. They're written to the start of the output, so a partition comes out as an image of its own (or onto a partition device, e.g. /dev/sdb2); without either, the whole image is written.
+// The output : Checked like the output of a copy (see safety.rs), and main.rs asks before writing to a drive. A regular file is truncated; a device must hold what's extracted (see capacity.rs), and is synced at the end.
+// Signals : Like a copy, extract checks for SIGUSR1 and Ctrl-C between reads (see utils/signals.rs).
+
+// src/core/extract.rs
+
+use crate::config::{ExtractConfig, ExtractWhat, STDIO_PATH};
+use crate::core::archive::Archive;
+use crate::core::capacity::device_size;
+use crate::core::open::open_stdout;
+use crate::core::ranges::ByteRange;
+use crate::core::reporter::progress_bar;
+use crate::core::safety::check_target;
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::rawdevice;
+use crate::utils::signals;
+use indicatif::BinaryBytes;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+/// How much is read from the archive at once, at most.
+const BUFFER_SIZE: usize = 1 << 20;
+
+/// What a finished extraction wrote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractStats {
+    /// The bytes of the image that were written.
+    pub range: ByteRange,
+    /// The frames or clusters read for them, and how many the image has.
+    pub units_read: u64,
+    pub units: u64,
+}
+
+/// Writes what `config` asks for from its archive to its output.
+pub fn run_extract(config: &ExtractConfig) -> RddResult<ExtractStats> {
+    let path = config.archive.as_str();
+    let Some(mut archive) = Archive::open(path)? else {
+        return Err(RddError::Config(format!(
+            "'{}' is neither seekable zstd (--compress zstd --seekable) nor a qcow2 image. A plain image can be copied in part with 'rdd copy --skip-bytes --count-bytes'.",
+            path
+        )));
+    };
+
+    let range = match config.what {
+        ExtractWhat::All => ByteRange {
+            offset: 0,
+            length: archive.len(),
+        },
+        ExtractWhat::Range(range) => {
+            if range.end() > archive.len() {
+                return Err(RddError::Config(format!(
+                    "--range ends at {}, past the end of the image in '{}' ({} bytes).",
+                    range.end(),
+                    path,
+                    archive.len()
+                )));
+            }
+            range
+        }
+        ExtractWhat::Partition(number) => {
+            let partitions = archive.partitions()?;
+            match partitions.get(number.wrapping_sub(1)).copied().flatten() {
+                Some(range) if range.end() <= archive.len() => range,
+                Some(_) => {
+                    return Err(RddError::Config(format!(
+                        "Partition {} of the image in '{}' ends past the end of the image.",
+                        number, path
+                    )));
+                }
+                None if partitions.is_empty() => {
+                    return Err(RddError::Config(format!(
+                        "The image in '{}' has no MBR or GPT partition table; use --range instead.",
+                        path
+                    )));
+                }
+                None => {
+                    let numbers: Vec<_> = (1..=partitions.len())
+                        .filter(|&n| partitions[n - 1].is_some())
+                        .map(|n| n.to_string())
+                        .collect();
+                    return Err(RddError::Config(format!(
+                        "The image in '{}' has no partition {}; it has {}.",
+                        path,
+                        number,
+                        numbers.join(", ")
+                    )));
+                }
+            }
+        }
+    };
+
+    let output_path = config.output.as_str();
+    let mut output = match output_path == STDIO_PATH {
+        true => open_stdout()?,
+        false => {
+            check_target(output_path, config.force, config.reporter.as_ref())?;
+            let mut options = OpenOptions::new();
+            options.write(true).create(true);
+            // A device keeps whatever comes after what's written.
+            let existing = Path::new(output_path);
+            let raw = rawdevice::raw_device(output_path);
+            match raw {
+                Some(_) => {
+                    rawdevice::share(&mut options);
+                    options.create(false);
+                }
+                None => {
+                    options.truncate(existing.is_file() || !existing.exists());
+                }
+            }
+            let file = options.open(output_path).with_path(output_path)?;
+            if let Some(device) = raw {
+                rawdevice::lock_for_writing(&file, device).with_path(output_path)?;
+            }
+            if let Some(capacity) = device_size(&file, output_path)
+                && range.length > capacity
+            {
+                return Err(RddError::TargetTooSmall {
+                    path: output_path.to_string(),
+                    needed: range.length,
+                    capacity,
+                });
+            }
+            file
+        }
+    };
+
+    config.reporter.message(&format!(
+        "Extracting {} at offset {} of the {} image in '{}' to '{}'.",
+        BinaryBytes(range.length),
+        range.offset,
+        archive.format(),
+        path,
+        output_path
+    ));
+    let progress = progress_bar(&*config.reporter, Some(range.length), config.show_progress);
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut done = 0;
+    while done < range.length {
+        signals::check(&*config.reporter, &progress)?;
+        let want = (range.length - done).min(BUFFER_SIZE as u64) as usize;
+        let n = archive.read_at(range.offset + done, &mut buffer[..want])?;
+        if n == 0 {
+            break;
+        }
+        output.write_all(&buffer[..n]).at_offset(output_path, done)?;
+        done += n as u64;
+        progress.inc(n as u64);
+    }
+    progress.finish_and_clear();
+    if output_path != STDIO_PATH {
+        output.sync_all().with_path(output_path)?;
+    }
+
+    let stats = ExtractStats {
+        range,
+        units_read: archive.units_read(),
+        units: archive.units(),
+    };
+    config.reporter.message(&format!(
+        "Extracted {} ({} bytes), reading {} of the image's {} {}.",
+        BinaryBytes(done),
+        done,
+        stats.units_read,
+        stats.units,
+        archive.unit_name()
+    ));
+    Ok(stats)
+}
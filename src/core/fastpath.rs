@@ -0,0 +1,182 @@
+// This file implements the fast paths of the single-threaded copy. When the input and the output are both regular files, the kernel can move the data between them itself, without rdd reading every block into a buffer and writing it out again. On a filesystem that shares data between files (btrfs, XFS, bcachefs), a whole file can even be cloned (a reflink) without copying any data at all.
+
+// Explanation of this file:
+// Eligibility : The data never reaches rdd on a fast path, so it's only used when nothing needs to see it: no --verify or manifest digest, no block hashes, no --conv, no write-if-changed, --sparse, --noerror, --retries, ranges, exclusions, flush probes, --follow or --append, no --direct or --simulate-device, and no compressed, decompressed or split streams. --no-fast-path turns it off altogether, e.g. to compare speeds or to work around a filesystem that gets it wrong.
+// Reflink : A copy of a whole file into an empty output (no skip, seek or count) is first tried with the FICLONE ioctl. It fails right away on filesystems that can't share data, and the copy goes on with copy_file_range.
+// copy_file_range and sendfile : Otherwise the data is moved with copy_file_range, which NFS and SMB can turn into a server-side copy and other filesystems into a reflink of the range, in chunks of at most CHUNK_SIZE, so progress, Ctrl-C, --rate-limit and --resume checkpoints work as in the normal loop (see BlockCopier::copy_span_fast in copy.rs). Kernels before 5.3 refuse copy_file_range between filesystems, and newer ones between different kinds of filesystem; sendfile copies between any two files and takes over then.
+// Fallback : If neither call works for the two files (ENOSYS, EXDEV, EINVAL, EOPNOTSUPP before anything was moved), the copy goes on with the standard read/write loop. An error after data was moved is a real I/O error and fails the copy.
+// Platforms : The fast paths are Linux only. Elsewhere FastPath::new returns None and the normal loop runs. The multithreaded and io_uring engines don't use them; they exist for devices, where there's no fast path.
+
+// src/core/fastpath.rs
+
+use crate::config::CopyConfig;
+use std::fs::File;
+use std::io;
+
+/// The most one fast-path call moves at once.
+pub const CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Whether the copy's options allow the kernel to move the data without rdd
+/// seeing it. The files themselves are checked by FastPath::new.
+pub fn eligible(config: &CopyConfig) -> bool {
+    config.fast_path
+        && config.digest_algorithm().is_none()
+        && config.block_hashes.is_none()
+        && config.expected_blocks.is_none()
+        && config.ranges.is_none()
+        && config.conversions.is_empty()
+        && config.records.is_none()
+        && config.excluded.is_empty()
+        && !config.write_if_changed
+        && !config.sparse
+        && !config.discard_zeros
+        && !config.noerror
+        && config.retries == 0
+        && config.flush_probe.is_none()
+        && config.follow.is_none()
+        && !config.append
+        && config.device_model.is_none()
+        && !config.direct_io()
+        && !config.output_is_stream()
+}
+
+/// The system call a fast path moves the data with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    CopyFileRange,
+    Sendfile,
+}
+
+impl Method {
+    /// The name of the system call, for messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            Method::CopyFileRange => "copy_file_range",
+            Method::Sendfile => "sendfile",
+        }
+    }
+}
+
+/// Moves data between two regular files in the kernel.
+pub struct FastPath {
+    method: Method,
+    // Whether any data was moved yet; after that, errors are real ones.
+    moved: bool,
+}
+
+impl FastPath {
+    /// A fast path between `input` and `output`, if both are regular files on
+    /// a platform that has one.
+    pub fn new(input: &File, output: &File) -> Option<Self> {
+        let regular = |file: &File| file.metadata().is_ok_and(|m| m.is_file());
+        (cfg!(target_os = "linux") && regular(input) && regular(output)).then_some(Self {
+            method: Method::CopyFileRange,
+            moved: false,
+        })
+    }
+
+    /// The system call the data is moved with.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// Clones all of `input` into `output` (a reflink). Returns false if the
+    /// filesystem can't, which leaves both files as they were.
+    pub fn clone_file(&self, input: &File, output: &File) -> bool {
+        platform::clone_file(input, output)
+    }
+
+    /// Moves up to `len` bytes from the position of `input` to the position
+    /// of `output`, advancing both. Returns how many were moved (0 at the end
+    /// of the input), or None if no fast path works between these files and
+    /// nothing was moved, so the normal loop should take over.
+    pub fn transfer(&mut self, input: &File, output: &File, len: usize) -> io::Result<Option<usize>> {
+        loop {
+            match platform::transfer(self.method, input, output, len) {
+                Ok(moved) if moved > 0 => {
+                    self.moved = true;
+                    return Ok(Some(moved));
+                }
+                // Some pseudo-filesystems look like regular files but report
+                // nothing to copy_file_range; the normal loop finds out
+                // whether the input is really empty.
+                Ok(_) if !self.moved => return Ok(None),
+                Ok(_) => return Ok(Some(0)),
+                Err(e) if !self.moved && platform::unsupported(&e) => match self.method {
+                    Method::CopyFileRange => self.method = Method::Sendfile,
+                    Method::Sendfile => return Ok(None),
+                },
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::Method;
+    use nix::libc;
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::ptr;
+
+    pub fn clone_file(input: &File, output: &File) -> bool {
+        // SAFETY: FICLONE only reads the file descriptor passed as its argument.
+        unsafe { libc::ioctl(output.as_raw_fd(), libc::FICLONE, input.as_raw_fd()) == 0 }
+    }
+
+    pub fn transfer(method: Method, input: &File, output: &File, len: usize) -> io::Result<usize> {
+        let (in_fd, out_fd) = (input.as_raw_fd(), output.as_raw_fd());
+        // Null offsets make both calls use and advance the file positions.
+        let moved = match method {
+            // Called through syscall(), since older C libraries don't have a
+            // wrapper for it.
+            // SAFETY: the null offset pointers are allowed and nothing else is a pointer.
+            Method::CopyFileRange => unsafe {
+                libc::syscall(
+                    libc::SYS_copy_file_range,
+                    in_fd,
+                    ptr::null_mut::<libc::loff_t>(),
+                    out_fd,
+                    ptr::null_mut::<libc::loff_t>(),
+                    len,
+                    0u32,
+                ) as isize
+            },
+            // SAFETY: as above, the null offset pointer is allowed.
+            Method::Sendfile => unsafe { libc::sendfile(out_fd, in_fd, ptr::null_mut(), len) },
+        };
+        match moved {
+            -1 => Err(io::Error::last_os_error()),
+            moved => Ok(moved as usize),
+        }
+    }
+
+    /// Whether an error means the call can't be used for these files, rather
+    /// than that the I/O failed.
+    pub fn unsupported(e: &io::Error) -> bool {
+        use libc::{EINVAL, ENOSYS, EOPNOTSUPP, EXDEV};
+        matches!(e.raw_os_error(), Some(EINVAL | ENOSYS | EOPNOTSUPP | EXDEV))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::Method;
+    use std::fs::File;
+    use std::io;
+
+    pub fn clone_file(_input: &File, _output: &File) -> bool {
+        false
+    }
+
+    pub fn transfer(_method: Method, _input: &File, _output: &File, _len: usize) -> io::Result<usize> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn unsupported(_e: &io::Error) -> bool {
+        true
+    }
+}
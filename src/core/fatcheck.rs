@@ -0,0 +1,924 @@
+// This file checks the FAT and exFAT filesystems in a freshly written output, for SD cards cloned for embedded products. Comparing hashes proves that the bytes we handed to the card are the bytes we read, but cheap card controllers have been caught reordering or dropping writes they acknowledged, and a hash read back through the page cache never sees that. A filesystem's own structures are full of redundancy (two copies of the FAT, checksums, lengths that must agree with chains), so reading them back from the card catches what a plain comparison misses.
+
+// Explanation of this file:
+// Finding filesystems : The output is read as a disk: the partitions of its MBR or GPT, or the whole output if it starts with a FAT boot sector itself (a "superfloppy", as many cameras format cards). Every one that holds FAT12, FAT16, FAT32 or exFAT is checked; anything else is skipped.
+// FAT checks : The copies of the FAT must be identical (unless FAT32 mirroring is turned off), FAT[0] must carry the media byte, FAT32's backup boot sector must match the boot sector, and its FSINFO sector must have its signatures and a free cluster count that agrees with the FAT.
+// exFAT checks : The main and backup boot regions must match and carry the right checksum, every directory entry set must match its checksum, and the clusters in use must be the ones the allocation bitmap marks.
+// Chains : The directory tree is walked from the root. Every file's and directory's chain must stay inside the volume, end properly, never reach a free or bad cluster, and never reach a cluster another chain already used (cross-linked). A file's chain must have as many clusters as its size needs. Clusters the FAT (or the bitmap) marks as used that no file reaches are reported as lost.
+// Page cache : The output is read with its cached pages dropped first (see utils/fadvise.rs), so the card has to answer, not the cache.
+// Problems : Each problem is a sentence that names the file or structure. A read that fails is a problem too. The first MAX_PROBLEMS of every filesystem are kept.
+// Damaged metadata : Every number comes from a card that may be damaged, so nothing is trusted to fit: offsets are computed with overflow checks (a partition or cluster whose offset overflows is skipped, or reported), no read goes past the end of the output, and exFAT's sector and cluster sizes must be within the specification, and its cluster count within what its FAT has room for, before anything is allocated for them.
+// check_output : What the copy runs with --check-fat (or --target-profile sd-clone). A problem the input has as well was copied faithfully and is only a warning; the others fail the copy with RddError::FilesystemDamaged.
+
+// src/core/fatcheck.rs
+
+use crate::config::CopyConfig;
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::fadvise::advise_dont_need;
+use indicatif::BinaryBytes;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Problems kept for each filesystem.
+const MAX_PROBLEMS: usize = 20;
+
+/// The most clusters FAT32 can have. A boot sector that claims more is
+/// damaged, and we don't read a FAT bigger than that.
+const MAX_CLUSTERS: u64 = 0x0FFF_FFF5;
+
+/// The sector size partition tables are read with. SD cards all use 512.
+const SECTOR: u64 = 512;
+
+/// The GPT entries we look at; SD card images have a handful.
+const MAX_GPT_ENTRIES: u64 = 128;
+
+/// The kind of a FAT filesystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FatKind {
+    Fat12,
+    Fat16,
+    Fat32,
+    ExFat,
+}
+
+impl fmt::Display for FatKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FatKind::Fat12 => "FAT12",
+            FatKind::Fat16 => "FAT16",
+            FatKind::Fat32 => "FAT32",
+            FatKind::ExFat => "exFAT",
+        })
+    }
+}
+
+/// What the check found in one filesystem.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilesystemCheck {
+    /// Where the filesystem starts in the output, in bytes.
+    pub offset: u64,
+    pub kind: FatKind,
+    pub files: u64,
+    pub directories: u64,
+    /// Bytes in the clusters the files and directories use.
+    pub bytes_used: u64,
+    /// Everything that's inconsistent, up to MAX_PROBLEMS.
+    pub problems: Vec<String>,
+}
+
+impl FilesystemCheck {
+    /// A one-line summary for the end of a copy.
+    pub fn summary(&self) -> String {
+        let verdict = match self.problems.len() {
+            0 => "consistent".to_string(),
+            n => format!("{} problem(s)", n),
+        };
+        format!(
+            "FAT check: {} at offset {}: {} file(s) and {} folder(s), {} in use; {}",
+            self.kind,
+            self.offset,
+            self.files,
+            self.directories,
+            BinaryBytes(self.bytes_used),
+            verdict
+        )
+    }
+}
+
+/// Reads the output at byte offsets.
+struct Disk {
+    file: File,
+    /// The size of the output, which no read goes past.
+    len: u64,
+}
+
+impl Disk {
+    fn open(file: File) -> io::Result<Self> {
+        // Devices have no length in their metadata, but seek to their end.
+        let mut file = file;
+        let len = file.seek(SeekFrom::End(0))?;
+        Ok(Disk { file, len })
+    }
+
+    /// Reads `len` bytes at `offset`. A read past the end of the output
+    /// fails before anything is allocated for it, so a damaged length
+    /// can't ask for gigabytes.
+    fn read(&mut self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        if offset.checked_add(len as u64).is_none_or(|end| end > self.len) {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} bytes at offset {} are past the end of the disk", len, offset),
+            ));
+        }
+        let mut buffer = vec![0; len];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// One FAT or exFAT filesystem being checked.
+struct Volume {
+    offset: u64,
+    kind: FatKind,
+    cluster_bytes: u64,
+    /// Byte offset of cluster 2, from the start of the volume.
+    heap_offset: u64,
+    cluster_count: u32,
+    /// The FAT, one entry per cluster, clusters 0 and 1 included.
+    fat: Vec<u32>,
+    /// FAT entries at and above this end a chain.
+    end_of_chain: u32,
+    bad: u32,
+    /// Clusters a chain has reached.
+    used: Vec<bool>,
+    files: u64,
+    directories: u64,
+    problems: Vec<String>,
+}
+
+impl Volume {
+    fn problem(&mut self, text: String) {
+        if self.problems.len() < MAX_PROBLEMS {
+            self.problems.push(text);
+        }
+    }
+
+    fn in_range(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster - 2 < self.cluster_count
+    }
+
+    /// Where `cluster` is on the disk. None if it's past what an offset
+    /// can hold, which only a damaged boot sector leads to.
+    fn cluster_offset(&self, cluster: u32) -> Option<u64> {
+        (cluster as u64 - 2)
+            .checked_mul(self.cluster_bytes)?
+            .checked_add(self.heap_offset)?
+            .checked_add(self.offset)
+    }
+
+    /// Marks `cluster` as used by `what`. Returns false if it can't be.
+    fn take(&mut self, cluster: u32, what: &str) -> bool {
+        if !self.in_range(cluster) {
+            self.problem(format!("{} points to cluster {}, outside the volume", what, cluster));
+            return false;
+        }
+        if self.used[cluster as usize] {
+            self.problem(format!(
+                "{} reaches cluster {}, which another chain already uses (cross-linked or looped)",
+                what, cluster
+            ));
+            return false;
+        }
+        self.used[cluster as usize] = true;
+        true
+    }
+
+    /// Follows the chain of `what` from `first`. Returns its clusters, or
+    /// None if the chain is broken.
+    fn chain(&mut self, first: u32, what: &str) -> Option<Vec<u32>> {
+        let mut clusters = Vec::new();
+        let mut cluster = first;
+        loop {
+            if !self.take(cluster, what) {
+                return None;
+            }
+            clusters.push(cluster);
+            let next = self.fat[cluster as usize];
+            if next >= self.end_of_chain {
+                return Some(clusters);
+            }
+            if next == 0 {
+                self.problem(format!("{} runs into cluster {}, which the FAT marks as free", what, cluster));
+                return None;
+            }
+            if next == self.bad {
+                self.problem(format!("{} runs into cluster {}, which the FAT marks as bad", what, cluster));
+                return None;
+            }
+            cluster = next;
+        }
+    }
+
+    /// The `count` clusters from `first` on, for exFAT files stored without
+    /// a FAT chain.
+    fn contiguous(&mut self, first: u32, count: u64, what: &str) -> Option<Vec<u32>> {
+        let mut clusters = Vec::new();
+        for i in 0..count {
+            let cluster = u32::try_from(first as u64 + i).unwrap_or(u32::MAX);
+            if !self.take(cluster, what) {
+                return None;
+            }
+            clusters.push(cluster);
+        }
+        Some(clusters)
+    }
+
+    /// Checks that `clusters`, the chain of a file of `size` bytes, are as
+    /// many as its size needs.
+    fn check_length(&mut self, clusters: &[u32], size: u64, what: &str) {
+        let needed = size.div_ceil(self.cluster_bytes);
+        if clusters.len() as u64 != needed {
+            self.problem(format!(
+                "{} is {} bytes long, which takes {} cluster(s), but its chain has {}",
+                what,
+                size,
+                needed,
+                clusters.len()
+            ));
+        }
+    }
+
+    /// Reads the contents of `clusters`, the chain of `what`. A cluster
+    /// that's out of range is a problem, and ends what's read.
+    fn read_clusters(&mut self, disk: &mut Disk, clusters: &[u32], what: &str) -> io::Result<Vec<u8>> {
+        // Grown as the reads succeed: a long damaged chain can claim more
+        // than the disk holds.
+        let mut data = Vec::new();
+        for &cluster in clusters {
+            let Some(at) = self.cluster_offset(cluster) else {
+                self.problem(format!("{} has cluster {}, which lies beyond any offset of the disk", what, cluster));
+                break;
+            };
+            data.extend(disk.read(at, self.cluster_bytes as usize)?);
+        }
+        Ok(data)
+    }
+
+    /// Clusters the FAT marks as used that no chain reached.
+    fn lost(&self) -> u64 {
+        (2..self.cluster_count as usize + 2)
+            .filter(|&cluster| !self.used[cluster] && self.fat[cluster] != 0 && self.fat[cluster] != self.bad)
+            .count() as u64
+    }
+
+    fn into_check(self) -> FilesystemCheck {
+        let used = self.used.iter().filter(|&&used| used).count() as u64;
+        FilesystemCheck {
+            offset: self.offset,
+            kind: self.kind,
+            files: self.files,
+            directories: self.directories,
+            bytes_used: used * self.cluster_bytes,
+            problems: self.problems,
+        }
+    }
+}
+
+/// Where the partitions of the disk start, in bytes.
+fn partitions(disk: &mut Disk) -> io::Result<Vec<u64>> {
+    let mbr = disk.read(0, SECTOR as usize)?;
+    if u16_at(&mbr, 510) != 0xAA55 {
+        return Ok(Vec::new());
+    }
+    let mut starts = Vec::new();
+    let mut gpt = false;
+    for entry in mbr[446..510].chunks_exact(16) {
+        let start = u32_at(entry, 8) as u64;
+        match entry[4] {
+            0x00 => {}
+            0xEE => gpt = true,
+            // Extended partitions hold more partition tables, not filesystems.
+            0x05 | 0x0F | 0x85 => {}
+            _ if start > 0 => starts.push(start * SECTOR),
+            _ => {}
+        }
+    }
+    if gpt {
+        let header = disk.read(SECTOR, SECTOR as usize)?;
+        if &header[0..8] == b"EFI PART" {
+            let entries = u64_at(&header, 72);
+            let count = (u32_at(&header, 80) as u64).min(MAX_GPT_ENTRIES);
+            let size = u32_at(&header, 84) as u64;
+            // The LBAs come from a card that may be damaged: a table or an
+            // entry whose offset overflows is skipped.
+            if let Some(at) = entries.checked_mul(SECTOR).filter(|_| (48..=4096).contains(&size)) {
+                let table = disk.read(at, (count * size) as usize)?;
+                for entry in table.chunks_exact(size as usize) {
+                    let first_lba = u64_at(entry, 32);
+                    if entry[..16].iter().any(|&b| b != 0) && first_lba > 0 {
+                        starts.extend(first_lba.checked_mul(SECTOR));
+                    }
+                }
+            }
+        }
+    }
+    Ok(starts)
+}
+
+/// Whether `boot` is the boot sector of a FAT12/16/32 filesystem.
+fn is_fat(boot: &[u8]) -> bool {
+    let bytes_per_sector = u16_at(boot, 11);
+    let sectors_per_cluster = boot[13];
+    matches!(boot[0], 0xEB | 0xE9)
+        && u16_at(boot, 510) == 0xAA55
+        && matches!(bytes_per_sector, 512 | 1024 | 2048 | 4096)
+        && sectors_per_cluster.is_power_of_two()
+        && u16_at(boot, 14) > 0
+        && boot[16] > 0
+}
+
+fn is_exfat(boot: &[u8]) -> bool {
+    &boot[3..11] == b"EXFAT   "
+}
+
+/// The checksum of an exFAT boot region (sectors 0 to 10), which skips the
+/// fields that change while it's mounted.
+fn boot_checksum(region: &[u8]) -> u32 {
+    region.iter().enumerate().fold(0u32, |sum, (i, &byte)| match i {
+        106 | 107 | 112 => sum,
+        _ => sum.rotate_right(1).wrapping_add(byte as u32),
+    })
+}
+
+/// The checksum of an exFAT directory entry set, which skips its own field.
+fn set_checksum(set: &[u8]) -> u16 {
+    set.iter().enumerate().fold(0u16, |sum, (i, &byte)| match i {
+        2 | 3 => sum,
+        _ => sum.rotate_right(1).wrapping_add(byte as u16),
+    })
+}
+
+/// A FAT directory entry's 8.3 name, e.g. "README.TXT".
+fn short_name(entry: &[u8]) -> String {
+    let base = String::from_utf8_lossy(&entry[0..8]).trim_end().to_string();
+    let extension = String::from_utf8_lossy(&entry[8..11]).trim_end().to_string();
+    match extension.is_empty() {
+        true => base,
+        false => format!("{}.{}", base, extension),
+    }
+}
+
+/// Reads the FAT that starts at `offset` into one entry per cluster.
+fn read_fat(disk: &mut Disk, offset: u64, kind: FatKind, entries: usize) -> io::Result<Vec<u32>> {
+    let bytes = match kind {
+        FatKind::Fat12 => entries * 3 / 2 + 2,
+        FatKind::Fat16 => entries * 2,
+        FatKind::Fat32 | FatKind::ExFat => entries * 4,
+    };
+    let raw = disk.read(offset, bytes)?;
+    Ok((0..entries)
+        .map(|i| match kind {
+            FatKind::Fat12 => {
+                let pair = u16_at(&raw, i * 3 / 2) as u32;
+                if i % 2 == 0 { pair & 0xFFF } else { pair >> 4 }
+            }
+            FatKind::Fat16 => u16_at(&raw, i * 2) as u32,
+            FatKind::Fat32 => u32_at(&raw, i * 4) & 0x0FFF_FFFF,
+            FatKind::ExFat => u32_at(&raw, i * 4),
+        })
+        .collect())
+}
+
+/// Checks the FAT12/16/32 filesystem at `offset`.
+fn check_fat(disk: &mut Disk, offset: u64, boot: &[u8]) -> io::Result<FilesystemCheck> {
+    let bytes_per_sector = u16_at(boot, 11) as u64;
+    let sectors_per_cluster = boot[13] as u64;
+    let reserved = u16_at(boot, 14) as u64;
+    let fats = boot[16] as u64;
+    let root_entries = u16_at(boot, 17) as u64;
+    let total = match u16_at(boot, 19) {
+        0 => u32_at(boot, 32) as u64,
+        total => total as u64,
+    };
+    let media = boot[21];
+    let fat_sectors = match u16_at(boot, 22) {
+        0 => u32_at(boot, 36) as u64,
+        sectors => sectors as u64,
+    };
+    let root_sectors = (root_entries * 32).div_ceil(bytes_per_sector);
+    let data_start = reserved + fats * fat_sectors + root_sectors;
+    let cluster_count = (total.saturating_sub(data_start) / sectors_per_cluster).min(MAX_CLUSTERS) as u32;
+    // The FAT type follows from the cluster count alone.
+    let kind = match cluster_count {
+        ..4085 => FatKind::Fat12,
+        4085..65525 => FatKind::Fat16,
+        _ => FatKind::Fat32,
+    };
+    let (end_of_chain, bad) = match kind {
+        FatKind::Fat12 => (0xFF8, 0xFF7),
+        FatKind::Fat16 => (0xFFF8, 0xFFF7),
+        _ => (0x0FFF_FFF8, 0x0FFF_FFF7),
+    };
+    let entries = cluster_count as usize + 2;
+    let fat_offset = |n: u64| offset + (reserved + n * fat_sectors) * bytes_per_sector;
+    let mut volume = Volume {
+        offset,
+        kind,
+        cluster_bytes: sectors_per_cluster * bytes_per_sector,
+        heap_offset: data_start * bytes_per_sector,
+        cluster_count,
+        fat: read_fat(disk, fat_offset(0), kind, entries)?,
+        end_of_chain,
+        bad,
+        used: vec![false; entries],
+        files: 0,
+        directories: 0,
+        problems: Vec::new(),
+    };
+
+    // --- The FAT itself ---
+    if volume.fat[0] & 0xFF != media as u32 {
+        volume.problem(format!(
+            "FAT[0] is {:#x}, which doesn't carry the media byte {:#x} of the boot sector",
+            volume.fat[0], media
+        ));
+    }
+    // With FAT32 mirroring off, only the active FAT is kept up to date.
+    let mirrored = kind != FatKind::Fat32 || u16_at(boot, 40) & 0x80 == 0;
+    if mirrored {
+        for n in 1..fats {
+            let copy = read_fat(disk, fat_offset(n), kind, entries)?;
+            if let Some(cluster) = (0..entries).find(|&i| copy[i] != volume.fat[i]) {
+                let differing = (0..entries).filter(|&i| copy[i] != volume.fat[i]).count();
+                volume.problem(format!(
+                    "FAT copy {} differs from the first at {} cluster(s), starting with cluster {}",
+                    n + 1,
+                    differing,
+                    cluster
+                ));
+            }
+        }
+    }
+
+    // --- FAT32's backup boot sector and FSINFO ---
+    if kind == FatKind::Fat32 {
+        let backup = u16_at(boot, 50) as u64;
+        if backup != 0 && backup != 0xFFFF {
+            let copy = disk.read(offset + backup * bytes_per_sector, 512)?;
+            // The BIOS parameter block, which has to match exactly.
+            if copy[..90] != boot[..90] {
+                volume.problem(format!("the backup boot sector (sector {}) doesn't match the boot sector", backup));
+            }
+        }
+        let fsinfo_sector = u16_at(boot, 48) as u64;
+        if fsinfo_sector != 0 && fsinfo_sector != 0xFFFF {
+            let fsinfo = disk.read(offset + fsinfo_sector * bytes_per_sector, 512)?;
+            if u32_at(&fsinfo, 0) != 0x4161_5252 || u32_at(&fsinfo, 484) != 0x6141_7272 || u32_at(&fsinfo, 508) != 0xAA55_0000 {
+                volume.problem("the FSINFO sector doesn't have its signatures".to_string());
+            } else {
+                let free = (2..entries).filter(|&i| volume.fat[i] == 0).count() as u32;
+                let recorded = u32_at(&fsinfo, 488);
+                if recorded != 0xFFFF_FFFF && recorded != free {
+                    volume.problem(format!(
+                        "FSINFO says {} cluster(s) are free, but the FAT has {} free",
+                        recorded, free
+                    ));
+                }
+                let next = u32_at(&fsinfo, 492);
+                if next != 0xFFFF_FFFF && !volume.in_range(next) {
+                    volume.problem(format!("FSINFO's next free cluster ({}) is outside the volume", next));
+                }
+            }
+        }
+    }
+
+    // --- The directory tree ---
+    let root = match kind {
+        FatKind::Fat32 => {
+            let root_cluster = u32_at(boot, 44);
+            match volume.chain(root_cluster, "the root directory") {
+                Some(clusters) => volume.read_clusters(disk, &clusters, "the root directory")?,
+                None => Vec::new(),
+            }
+        }
+        _ => disk.read(
+            offset + (reserved + fats * fat_sectors) * bytes_per_sector,
+            (root_entries * 32) as usize,
+        )?,
+    };
+    let mut pending = vec![(String::new(), root)];
+    while let Some((dir, data)) = pending.pop() {
+        for entry in data.chunks_exact(32) {
+            match entry[0] {
+                0x00 => break,
+                0xE5 => continue,
+                _ => {}
+            }
+            let attributes = entry[11];
+            // Long name entries and the volume label.
+            if attributes & 0x0F == 0x0F || attributes & 0x08 != 0 {
+                continue;
+            }
+            let name = short_name(entry);
+            if name == "." || name == ".." {
+                continue;
+            }
+            let path = format!("{}/{}", dir, name);
+            let high = if kind == FatKind::Fat32 { (u16_at(entry, 20) as u32) << 16 } else { 0 };
+            let first = high | u16_at(entry, 26) as u32;
+            let size = u32_at(entry, 28) as u64;
+            if attributes & 0x10 != 0 {
+                volume.directories += 1;
+                if first == 0 {
+                    volume.problem(format!("directory {} has no clusters", path));
+                } else if let Some(clusters) = volume.chain(first, &path) {
+                    let data = volume.read_clusters(disk, &clusters, &path)?;
+                    pending.push((path, data));
+                }
+            } else {
+                volume.files += 1;
+                if first == 0 {
+                    if size > 0 {
+                        volume.problem(format!("{} is {} bytes long but has no clusters", path, size));
+                    }
+                } else if let Some(clusters) = volume.chain(first, &path) {
+                    volume.check_length(&clusters, size, &path);
+                }
+            }
+        }
+    }
+
+    let lost = volume.lost();
+    if lost > 0 {
+        volume.problem(format!("{} cluster(s) are marked as used in the FAT but belong to no file", lost));
+    }
+    Ok(volume.into_check())
+}
+
+/// Checks the exFAT filesystem at `offset`.
+fn check_exfat(disk: &mut Disk, offset: u64, boot: &[u8]) -> io::Result<FilesystemCheck> {
+    // Sectors of 512 bytes to 4 KiB, clusters of at most 32 MiB.
+    let (sector_shift, cluster_shift) = (boot[108], boot[109]);
+    if !(9..=12).contains(&sector_shift) || cluster_shift > 25 - sector_shift {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the boot sector's sector and cluster shifts ({} and {}) are out of range",
+                sector_shift, cluster_shift
+            ),
+        ));
+    }
+    let sector = 1u64 << sector_shift;
+    let cluster_bytes = sector << cluster_shift;
+    let claimed = u32_at(boot, 92) as u64;
+    // The FAT has an entry for every cluster, and clusters 0 and 1.
+    let fat_entries = (u32_at(boot, 84) as u64 * sector / 4).saturating_sub(2);
+    let cluster_count = claimed.min(fat_entries).min(MAX_CLUSTERS) as u32;
+    let entries = cluster_count as usize + 2;
+    let mut volume = Volume {
+        offset,
+        kind: FatKind::ExFat,
+        cluster_bytes,
+        heap_offset: u32_at(boot, 88) as u64 * sector,
+        cluster_count,
+        fat: read_fat(disk, offset + u32_at(boot, 80) as u64 * sector, FatKind::ExFat, entries)?,
+        end_of_chain: 0xFFFF_FFF8,
+        bad: 0xFFFF_FFF7,
+        used: vec![false; entries],
+        files: 0,
+        directories: 0,
+        problems: Vec::new(),
+    };
+
+    if claimed > fat_entries {
+        volume.problem(format!(
+            "the boot sector claims {} clusters, but the FAT only has room for {}",
+            claimed, fat_entries
+        ));
+    }
+
+    // --- Boot regions: sectors 0-11, and their backup in 12-23 ---
+    let main = disk.read(offset, 12 * sector as usize)?;
+    let checksum = boot_checksum(&main[..11 * sector as usize]);
+    if main[11 * sector as usize..].chunks_exact(4).any(|word| u32_at(word, 0) != checksum) {
+        volume.problem("the boot region's checksum doesn't match its contents".to_string());
+    }
+    let backup = disk.read(offset + 12 * sector, 12 * sector as usize)?;
+    // The backup has the same contents, apart from the fields the checksum
+    // skips.
+    if boot_checksum(&backup[..11 * sector as usize]) != checksum || backup[11 * sector as usize..] != main[11 * sector as usize..] {
+        volume.problem("the backup boot region doesn't match the main boot region".to_string());
+    }
+    if boot[110] == 2 {
+        let copy = read_fat(disk, offset + (u32_at(boot, 80) as u64 + u32_at(boot, 84) as u64) * sector, FatKind::ExFat, entries)?;
+        if copy != volume.fat {
+            volume.problem("the second FAT differs from the first".to_string());
+        }
+    }
+
+    // --- The directory tree ---
+    let root = match volume.chain(u32_at(boot, 96), "the root directory") {
+        Some(clusters) => volume.read_clusters(disk, &clusters, "the root directory")?,
+        None => Vec::new(),
+    };
+    let mut bitmap = None;
+    let mut pending = vec![(String::new(), root)];
+    while let Some((dir, data)) = pending.pop() {
+        let entries: Vec<&[u8]> = data.chunks_exact(32).collect();
+        let mut i = 0;
+        while i < entries.len() {
+            let entry = entries[i];
+            i += 1;
+            match entry[0] {
+                0x00 => break,
+                // The allocation bitmap and the up-case table, in the root.
+                0x81 | 0x82 => {
+                    let what = if entry[0] == 0x81 { "the allocation bitmap" } else { "the up-case table" };
+                    let (first, length) = (u32_at(entry, 20), u64_at(entry, 24));
+                    let clusters = match volume.fat.get(first as usize) {
+                        // Usually contiguous without a FAT chain.
+                        Some(0) => volume.contiguous(first, length.div_ceil(cluster_bytes), what),
+                        _ => volume.chain(first, what),
+                    };
+                    if let (0x81, None, Some(clusters)) = (entry[0], &bitmap, clusters) {
+                        let mut data = volume.read_clusters(disk, &clusters, what)?;
+                        data.truncate(length as usize);
+                        bitmap = Some(data);
+                    }
+                }
+                0x85 => {
+                    let secondary = entry[1] as usize;
+                    let Some(set) = entries.get(i - 1..i + secondary) else {
+                        volume.problem(format!("an entry set in {}/ runs past the end of the directory", dir));
+                        break;
+                    };
+                    i += secondary;
+                    let name: String = set
+                        .iter()
+                        .filter(|entry| entry[0] == 0xC1)
+                        .flat_map(|entry| entry[2..32].chunks_exact(2).map(|c| u16_at(c, 0)))
+                        .take_while(|&c| c != 0)
+                        .map(|c| char::from_u32(c as u32).unwrap_or('?'))
+                        .collect();
+                    let path = format!("{}/{}", dir, name);
+                    if set_checksum(&set.concat()) != u16_at(entry, 2) {
+                        volume.problem(format!("the directory entries of {} don't match their checksum", path));
+                        continue;
+                    }
+                    let Some(stream) = set.get(1).filter(|stream| stream[0] == 0xC0) else {
+                        volume.problem(format!("{} has no stream extension entry", path));
+                        continue;
+                    };
+                    let directory = u16_at(entry, 4) & 0x10 != 0;
+                    let (flags, first, length) = (stream[1], u32_at(stream, 20), u64_at(stream, 24));
+                    match directory {
+                        true => volume.directories += 1,
+                        false => volume.files += 1,
+                    }
+                    if first == 0 || flags & 0x01 == 0 {
+                        if length > 0 {
+                            volume.problem(format!("{} is {} bytes long but has no clusters", path, length));
+                        }
+                        continue;
+                    }
+                    let clusters = match flags & 0x02 != 0 {
+                        true => volume.contiguous(first, length.div_ceil(cluster_bytes), &path),
+                        false => volume.chain(first, &path),
+                    };
+                    let Some(clusters) = clusters else { continue };
+                    volume.check_length(&clusters, length, &path);
+                    if directory {
+                        let mut data = volume.read_clusters(disk, &clusters, &path)?;
+                        data.truncate(length as usize);
+                        pending.push((path, data));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // --- The allocation bitmap against what the files use ---
+    match bitmap {
+        None => volume.problem("the root directory has no allocation bitmap".to_string()),
+        Some(bitmap) => {
+            let marked = |cluster: usize| bitmap.get((cluster - 2) / 8).is_some_and(|byte| byte >> ((cluster - 2) % 8) & 1 == 1);
+            let unmarked = (2..entries).filter(|&c| volume.used[c] && !marked(c)).count();
+            if unmarked > 0 {
+                volume.problem(format!("{} cluster(s) in use are marked as free in the allocation bitmap", unmarked));
+            }
+            let lost = (2..entries).filter(|&c| !volume.used[c] && marked(c)).count();
+            if lost > 0 {
+                volume.problem(format!("{} cluster(s) are marked as used in the allocation bitmap but belong to no file", lost));
+            }
+        }
+    }
+    Ok(volume.into_check())
+}
+
+/// Finds the FAT and exFAT filesystems in `path` and checks them.
+pub fn inspect(path: &str) -> RddResult<Vec<FilesystemCheck>> {
+    let file = File::open(path).with_path(path)?;
+    // Make the device answer, not the page cache.
+    advise_dont_need(&file, 0, 0);
+    let mut disk = Disk::open(file).with_path(path)?;
+
+    let mut starts = vec![0];
+    // A disk too short for a partition table has no filesystem either.
+    starts.extend(partitions(&mut disk).unwrap_or_default());
+    let mut checks = Vec::new();
+    for start in starts {
+        let Ok(boot) = disk.read(start, 512) else { continue };
+        let check = match (is_exfat(&boot), is_fat(&boot)) {
+            (true, _) => check_exfat(&mut disk, start, &boot),
+            (false, true) => check_fat(&mut disk, start, &boot),
+            (false, false) => continue,
+        };
+        checks.push(check.unwrap_or_else(|e| FilesystemCheck {
+            offset: start,
+            kind: if is_exfat(&boot) { FatKind::ExFat } else { FatKind::Fat32 },
+            files: 0,
+            directories: 0,
+            bytes_used: 0,
+            problems: vec![format!("its structures can't be read ({})", e)],
+        }));
+        // A superfloppy has no partitions; its first sector isn't an MBR.
+        if start == 0 {
+            break;
+        }
+    }
+    Ok(checks)
+}
+
+/// Checks the filesystems in the output of a copy that's done, for
+/// --check-fat. Problems the input has too are reported as warnings;
+/// others fail the copy.
+pub fn check_output(config: &CopyConfig) -> RddResult<Vec<FilesystemCheck>> {
+    let checks = inspect(&config.output_file)?;
+    if checks.is_empty() {
+        config
+            .reporter
+            .message("FAT check: the output holds no FAT or exFAT filesystem to check.");
+        return Ok(checks);
+    }
+    let problems: Vec<(u64, &String)> = checks
+        .iter()
+        .flat_map(|check| check.problems.iter().map(move |problem| (check.offset, problem)))
+        .collect();
+    if problems.is_empty() {
+        return Ok(checks);
+    }
+
+    // What the input has too was copied faithfully. Its filesystems are only
+    // where the output's are if the copy started at the start of both.
+    let comparable = config.skip_bytes == 0 && config.seek_bytes == 0 && !config.input_is_stdin() && !config.input_is_network();
+    let inherited: BTreeSet<(u64, String)> = match comparable {
+        true => inspect(&config.input_file)
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|check| check.problems.into_iter().map(move |problem| (check.offset, problem)))
+            .collect(),
+        false => BTreeSet::new(),
+    };
+    let mut new = Vec::new();
+    for (offset, problem) in problems {
+        if inherited.contains(&(offset, problem.clone())) {
+            config.reporter.message(&format!(
+                "Warning: FAT check at offset {}: {} (the input has this too).",
+                offset, problem
+            ));
+        } else {
+            config.reporter.message(&format!("FAT check at offset {}: {}.", offset, problem));
+            new.push(problem.clone());
+        }
+    }
+    if !new.is_empty() {
+        return Err(RddError::FilesystemDamaged {
+            path: config.output_file.clone(),
+            problems: new,
+        });
+    }
+    Ok(checks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `image` to a temporary file named after `name`, inspects it
+    /// and removes it again.
+    fn inspect_image(name: &str, image: &[u8]) -> RddResult<Vec<FilesystemCheck>> {
+        let path = std::env::temp_dir().join(format!("rdd-fatcheck-test-{}-{}", std::process::id(), name));
+        std::fs::write(&path, image).unwrap();
+        let checks = inspect(&path.to_string_lossy());
+        std::fs::remove_file(&path).unwrap();
+        checks
+    }
+
+    /// A FAT16 image of 5000 one-sector clusters with a file A.TXT of 1024
+    /// bytes in clusters 2 and 3, where the FAT entry of cluster 3 is
+    /// `after_3`.
+    fn fat16(after_3: u16) -> Vec<u8> {
+        let (reserved, fat_sectors, root_sectors, clusters) = (1usize, 20usize, 32usize, 5000usize);
+        let mut image = vec![0u8; (reserved + 2 * fat_sectors + root_sectors + clusters) * 512];
+        image[0] = 0xEB;
+        image[11..13].copy_from_slice(&512u16.to_le_bytes());
+        image[13] = 1;
+        image[14..16].copy_from_slice(&(reserved as u16).to_le_bytes());
+        image[16] = 2;
+        image[17..19].copy_from_slice(&512u16.to_le_bytes());
+        let sectors = (image.len() / 512) as u16;
+        image[19..21].copy_from_slice(&sectors.to_le_bytes());
+        image[21] = 0xF8;
+        image[22..24].copy_from_slice(&(fat_sectors as u16).to_le_bytes());
+        image[510..512].copy_from_slice(&[0x55, 0xAA]);
+        for fat in 0..2 {
+            let at = (reserved + fat * fat_sectors) * 512;
+            for (i, entry) in [0xFFF8u16, 0xFFFF, 3, after_3].into_iter().enumerate() {
+                image[at + i * 2..at + i * 2 + 2].copy_from_slice(&entry.to_le_bytes());
+            }
+        }
+        let root = (reserved + 2 * fat_sectors) * 512;
+        image[root..root + 11].copy_from_slice(b"A       TXT");
+        image[root + 26..root + 28].copy_from_slice(&2u16.to_le_bytes());
+        image[root + 28..root + 32].copy_from_slice(&1024u32.to_le_bytes());
+        image
+    }
+
+    /// An exFAT boot sector with the given shifts, cluster count and FAT
+    /// length, on a disk of 64 sectors of 512 bytes.
+    fn exfat(sector_shift: u8, cluster_shift: u8, clusters: u32, fat_sectors: u32) -> Vec<u8> {
+        let mut image = vec![0u8; 64 * 512];
+        image[0] = 0xEB;
+        image[3..11].copy_from_slice(b"EXFAT   ");
+        image[80..84].copy_from_slice(&24u32.to_le_bytes());
+        image[84..88].copy_from_slice(&fat_sectors.to_le_bytes());
+        image[88..92].copy_from_slice(&32u32.to_le_bytes());
+        image[92..96].copy_from_slice(&clusters.to_le_bytes());
+        image[96..100].copy_from_slice(&2u32.to_le_bytes());
+        image[108] = sector_shift;
+        image[109] = cluster_shift;
+        image[110] = 1;
+        image[510..512].copy_from_slice(&[0x55, 0xAA]);
+        image
+    }
+
+    #[test]
+    fn a_consistent_fat16_volume_has_no_problems_and_a_broken_chain_is_one() {
+        let good = inspect_image("fat16-good", &fat16(0xFFFF)).unwrap();
+        let broken = inspect_image("fat16-broken", &fat16(0)).unwrap();
+
+        assert_eq!(good.len(), 1);
+        assert_eq!(good[0].kind, FatKind::Fat16);
+        assert_eq!((good[0].files, good[0].bytes_used), (1, 1024));
+        assert_eq!(good[0].problems, Vec::<String>::new());
+        assert_eq!(
+            broken[0].problems,
+            ["/A.TXT runs into cluster 3, which the FAT marks as free"]
+        );
+    }
+
+    #[test]
+    fn gpt_headers_that_are_cut_short_or_overflow_are_no_partitions() {
+        let mut image = vec![0u8; 4096];
+        image[446 + 4] = 0xEE;
+        image[510..512].copy_from_slice(&[0x55, 0xAA]);
+        image[512..520].copy_from_slice(b"EFI PART");
+        image[512 + 72..512 + 80].copy_from_slice(&2u64.to_le_bytes());
+        image[512 + 80..512 + 84].copy_from_slice(&2u32.to_le_bytes());
+        image[512 + 84..512 + 88].copy_from_slice(&128u32.to_le_bytes());
+        for (entry, first_lba) in [(1024, u64::MAX / 256), (1152, 4)] {
+            image[entry] = 1;
+            image[entry + 32..entry + 40].copy_from_slice(&first_lba.to_le_bytes());
+        }
+        let partitions_of = |image: Vec<u8>| {
+            let path = std::env::temp_dir().join(format!("rdd-fatcheck-test-{}-gpt", std::process::id()));
+            std::fs::write(&path, image).unwrap();
+            let mut disk = Disk::open(File::open(&path).unwrap()).unwrap();
+            let partitions = partitions(&mut disk);
+            std::fs::remove_file(&path).unwrap();
+            partitions
+        };
+        let skipped = partitions_of(image.clone());
+        let mut far = image.clone();
+        far[512 + 72..512 + 80].copy_from_slice(&(u64::MAX / 256).to_le_bytes());
+        let far = partitions_of(far);
+        let cut_short = partitions_of(image[..600].to_vec());
+
+        assert_eq!(skipped.unwrap(), [4 * SECTOR]);
+        assert_eq!(far.unwrap(), Vec::<u64>::new());
+        assert!(cut_short.is_err());
+        // inspect() takes a disk too short for its GPT as one without any.
+        assert_eq!(inspect_image("gpt-short", &image[..600]).unwrap(), []);
+    }
+
+    #[test]
+    fn exfat_boot_sectors_with_extreme_fields_are_problems() {
+        let shifts = inspect_image("exfat-shifts", &exfat(12, 25, 16, 1)).unwrap();
+        let sectors = inspect_image("exfat-sectors", &exfat(200, 0, 16, 1)).unwrap();
+        let count = inspect_image("exfat-count", &exfat(9, 0, u32::MAX, 1)).unwrap();
+        let fat = inspect_image("exfat-fat", &exfat(9, 0, u32::MAX, u32::MAX)).unwrap();
+
+        assert!(shifts[0].problems[0].contains("shifts (12 and 25) are out of range"));
+        assert!(sectors[0].problems[0].contains("out of range"));
+        assert_eq!(count[0].problems[0], "the boot sector claims 4294967295 clusters, but the FAT only has room for 126");
+        // A FAT longer than the disk is never read, let alone allocated.
+        assert!(fat[0].problems[0].contains("past the end of the disk"));
+    }
+}
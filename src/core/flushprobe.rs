@@ -0,0 +1,126 @@
+// This file detects targets that report writes as done long before the data is actually stored. Some USB bridges and cheap flash controllers accept writes into a small internal cache and acknowledge them at full speed, then stall. The progress bar races ahead, and then the final sync "hangs" for minutes. Periodic flushes reveal the device's real, stable write rate.
+
+// Explanation of this file:
+// FlushProbe struct: Counts bytes written and the time spent inside write calls. Every `interval` bytes it forces the data to stable storage with sync_data() and times that too.
+// Apparent vs. stable rate: Over each probe window, apparent = bytes / time spent in writes (what the progress bar sees), stable = bytes / (time in writes + time in the flush). On an honest device the flush is quick and both rates are close. On a caching device the flush takes most of the time.
+// The warning: If the apparent rate is more than SUSPICIOUS_RATIO times the stable rate, we warn once, so the user knows the progress bar is optimistic.
+// FlushProbeStats : The totals over the whole copy, reported in the summary and in CopyStats.
+
+// src/core/flushprobe.rs
+
+use crate::config::CopyConfig;
+use crate::core::reporter::Reporter;
+use crate::error::{IoResultExt, RddResult};
+use crate::utils::diagnostics::Diagnostic;
+use indicatif::BinaryBytes;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::time::{Duration, Instant};
+
+/// Apparent throughput this many times above the stable rate is suspicious.
+const SUSPICIOUS_RATIO: f64 = 4.0;
+
+/// Totals collected by the flush probes over a whole copy.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlushProbeStats {
+    pub probes: u64,
+    pub bytes: u64,
+    pub write_time: Duration,
+    pub flush_time: Duration,
+}
+
+impl FlushProbeStats {
+    /// Throughput as seen by the write calls alone.
+    pub fn apparent_rate(&self) -> Option<f64> {
+        rate(self.bytes, self.write_time)
+    }
+
+    /// Throughput including the time needed to make the data stable.
+    pub fn stable_rate(&self) -> Option<f64> {
+        rate(self.bytes, self.write_time + self.flush_time)
+    }
+
+    /// A one-line summary for the end of a copy.
+    pub fn summary(&self) -> String {
+        let fmt = |r: Option<f64>| r.map_or("n/a".to_string(), |r| format!("{}/s", BinaryBytes(r as u64)));
+        format!(
+            "Flush probes: {}, stable write rate {} (apparent {})",
+            self.probes,
+            fmt(self.stable_rate()),
+            fmt(self.apparent_rate())
+        )
+    }
+}
+
+fn rate(bytes: u64, time: Duration) -> Option<f64> {
+    let secs = time.as_secs_f64();
+    (secs > 0.0).then(|| bytes as f64 / secs)
+}
+
+/// Periodically flushes the output and compares apparent and stable rates.
+pub struct FlushProbe {
+    interval: u64,
+    /// The current probe window.
+    window: FlushProbeStats,
+    /// Everything from completed windows.
+    pub totals: FlushProbeStats,
+    warned: bool,
+}
+
+impl FlushProbe {
+    /// Creates a probe that flushes after every `interval` written bytes.
+    pub fn new(interval: u64) -> Self {
+        Self {
+            interval,
+            window: FlushProbeStats::default(),
+            totals: FlushProbeStats::default(),
+            warned: false,
+        }
+    }
+
+    /// Records a completed write of `bytes` that took `took`, and runs a
+    /// flush probe on `output` if the interval has been reached.
+    pub fn record_write(&mut self, output: &File, config: &CopyConfig, bytes: u64, took: Duration) -> RddResult<()> {
+        self.window.bytes += bytes;
+        self.window.write_time += took;
+
+        if self.window.bytes < self.interval {
+            return Ok(());
+        }
+
+        let started = Instant::now();
+        output.sync_data().with_path(&config.output_file)?;
+        self.window.flush_time = started.elapsed();
+        self.window.probes = 1;
+
+        self.check_window(&*config.reporter);
+
+        self.totals.probes += 1;
+        self.totals.bytes += self.window.bytes;
+        self.totals.write_time += self.window.write_time;
+        self.totals.flush_time += self.window.flush_time;
+        self.window = FlushProbeStats::default();
+        Ok(())
+    }
+
+    /// Warns (once) if the last window shows writes being acknowledged much
+    /// faster than they are stored.
+    fn check_window(&mut self, reporter: &dyn Reporter) {
+        if self.warned {
+            return;
+        }
+        if let (Some(apparent), Some(stable)) = (self.window.apparent_rate(), self.window.stable_rate())
+            && apparent > stable * SUSPICIOUS_RATIO
+        {
+            self.warned = true;
+            reporter.diagnostic(
+                &Diagnostic::warning(format!(
+                    "writes are acknowledged at {}/s but only reach stable storage at about {}/s; the device (or the OS page cache) is buffering writes, so progress may look faster than it is.",
+                    BinaryBytes(apparent as u64),
+                    BinaryBytes(stable as u64)
+                ))
+                .with_flag("--flush-probe"),
+            );
+        }
+    }
+}
@@ -0,0 +1,118 @@
+// This file implements --follow, which copies an input that is still being written, like `tail -f` does. A packet capture, a log or a recording grows while it's made, and copying it to other media as it grows means it's there in full soon after the capture stops, instead of only starting then. A plain copy stops at the end of the file; a followed one waits there for more.
+
+// Explanation of this file:
+// Follow : The settings: --until-size stops once the input has reached that size and all of it is copied, and --until-idle stops once the input hasn't grown for that long. Without either, the copy follows until Ctrl-C.
+// Follower : The single-threaded copy (copy_span in copy.rs) asks it what to do at the end of the input. wait_for_more polls the input's size every POLL_INTERVAL until it's larger than what was read, and says whether to carry on. The status signal is still answered while it waits.
+// Stopping : Ctrl-C or SIGTERM ends a followed copy like the end of the input would, and the copy finishes normally: the last partial block is written, and the output synced, verified and summarized. stop_requested takes the signal before signals::check sees it. A second Ctrl-C still ends rdd at once. Library users don't get the handlers (see utils/signals.rs), so they need --until-size or --until-idle.
+// Blocks : Following, a short read only means we caught up with the writer, so it doesn't end the block (see copy.rs); blocks are full except the last one.
+// Shrinking : An input that gets shorter than what we read was truncated or replaced (log rotation), and following it makes no sense anymore. We warn and stop.
+// FollowStats : How long the copy waited for the input, and why it stopped, for the summary and CopyStats.
+
+// src/core/follow.rs
+
+use crate::config::CopyConfig;
+use crate::error::{IoResultExt, RddResult};
+use crate::utils::signals;
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the size of the input is checked while waiting for it to grow.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// When to stop following the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Follow {
+    /// Stop once the input has reached this many bytes.
+    pub until_size: Option<u64>,
+    /// Stop once the input hasn't grown for this long.
+    pub until_idle: Option<Duration>,
+}
+
+/// What following the input came to.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FollowStats {
+    /// Time spent waiting for the input to grow.
+    pub waited: Duration,
+    /// Why following stopped, e.g. "on SIGINT". None if the copy reached
+    /// --until-size or --count.
+    pub stopped: Option<String>,
+}
+
+impl FollowStats {
+    /// A one-line summary for the end of a copy.
+    pub fn summary(&self) -> String {
+        format!(
+            "Followed the input, waiting {:.1}s in all for it to grow; stopped {}",
+            self.waited.as_secs_f64(),
+            self.stopped.as_deref().unwrap_or("at the size limit")
+        )
+    }
+}
+
+/// Waits at the end of a growing input.
+pub struct Follower {
+    follow: Follow,
+    pub totals: FollowStats,
+}
+
+impl Follower {
+    pub fn new(follow: Follow) -> Self {
+        Self {
+            follow,
+            totals: FollowStats::default(),
+        }
+    }
+
+    /// Whether Ctrl-C or SIGTERM asked to stop following. The signal is
+    /// taken, so it ends the input rather than interrupting the copy.
+    pub fn stop_requested(&mut self) -> bool {
+        match signals::take_stop() {
+            Some(signal) => {
+                self.totals.stopped = Some(format!("on {}", signal));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Called at the end of `input`, after reading up to `pos`: waits until
+    /// it has grown. Returns false, without waiting for more, once following
+    /// should stop.
+    pub fn wait_for_more(&mut self, config: &CopyConfig, input: &File, pos: u64, progress: &ProgressBar) -> RddResult<bool> {
+        let started = Instant::now();
+        let grown = loop {
+            if self.stop_requested() {
+                break false;
+            }
+            signals::check(&*config.reporter, progress)?;
+
+            let len = input.metadata().with_path(&config.input_file)?.len();
+            if len > pos {
+                break true;
+            }
+            if len < pos {
+                config.reporter.message_over(
+                    progress,
+                    &format!(
+                        "Warning: '{}' shrank from {} to {} bytes while it was followed; it was truncated or replaced, so the copy stops here.",
+                        config.input_file, pos, len
+                    ),
+                );
+                self.totals.stopped = Some("because the input shrank".to_string());
+                break false;
+            }
+            if let Some(idle) = self.follow.until_idle
+                && started.elapsed() >= idle
+            {
+                self.totals.stopped = Some(format!("after the input didn't grow for {}s", idle.as_secs()));
+                break false;
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+        self.totals.waited += started.elapsed();
+        Ok(grown)
+    }
+}
@@ -0,0 +1,291 @@
+// This file implements --verify. The data is hashed while it's being copied, so checking the source costs no extra read. After the final sync, the written region of the output is read back and hashed again. If the two digests differ, the copy is not a faithful one, and we fail with RddError::VerificationFailure.
+
+// Explanation of this file:
+// StreamHasher enum: One running hash, SHA-256 or BLAKE3, behind a single update/finalize interface, so the copy loops don't care which algorithm was chosen. BLAKE3 is left out of builds without the blake3 feature; --verify blake3 is then rejected by the parser, and a manifest or block hash file recorded with it can't be read.
+// CopyVerifier struct: What the copy loop feeds. It hashes every byte that goes to the output, and remembers which regions of the output were written. Excluded ranges are hashed as zeros, since that's what they read back as.
+// verify : Runs once the output has been synced. It asks the kernel to drop the output's cached pages first, so that (where supported) the read-back really comes from the device and not from memory. Then it hashes the written regions in order and compares the digests. A second progress bar shows how the read-back is going.
+// VerificationReport struct: The algorithm and both digests, which end up in CopyStats and in the summary.
+// Several algorithms : --verify given more than once (--verify sha256 --verify blake3) hashes the data with each, in the same pass, and the read-back is hashed with each too. The first algorithm is the main one, which manifests, the custody log and block hashes use.
+// Hash windows : With --hash-window, CopyVerifier also hashes the data in windows of that size with the main algorithm (WindowHasher in blockhash.rs). Being fed here, the windows cover the same data as the digest in every engine, including the part of the input a resumed copy reads again.
+// Hash files : write_hash_files writes the digests for --hash-output in the `<digest>  <name>` format of sha256sum and b3sum, so the output can be checked later without rdd.
+// DataDigest struct: The digest of the data a copy read, tagged with its algorithm ("sha256:…"). It's what a reproducibility manifest records (see manifest.rs), and what a re-run from the manifest is compared against. Without --verify, the copy only hashes the data and doesn't read the output back.
+
+// src/core/hash.rs
+
+use crate::cli::HashAlgorithm;
+use crate::config::CopyConfig;
+use crate::core::blockhash::{BlockHashes, WindowHasher};
+use crate::core::ranges::ByteRange;
+use crate::core::reporter::progress_bar;
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::fadvise::advise_dont_need;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+
+/// Size of the buffer used to read the output back.
+const VERIFY_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// A running hash in one of the supported algorithms.
+pub enum StreamHasher {
+    Sha256(Sha256),
+    #[cfg(feature = "blake3")]
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl StreamHasher {
+    pub fn new(algo: HashAlgorithm) -> Self {
+        match algo {
+            HashAlgorithm::Sha256 => StreamHasher::Sha256(Sha256::new()),
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => StreamHasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            StreamHasher::Sha256(h) => h.update(data),
+            #[cfg(feature = "blake3")]
+            StreamHasher::Blake3(h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    /// Finishes the hash and returns it as lower-case hex.
+    pub fn finalize(self) -> String {
+        match self {
+            StreamHasher::Sha256(h) => format!("{:x}", h.finalize()),
+            #[cfg(feature = "blake3")]
+            StreamHasher::Blake3(h) => h.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+/// The outcome of a successful verification.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerificationReport {
+    pub algorithm: HashAlgorithm,
+    pub source_digest: String,
+    pub output_digest: String,
+}
+
+impl VerificationReport {
+    /// A short summary for the end of a copy.
+    pub fn summary(&self) -> String {
+        format!(
+            "Verified ({}): source {}, output {}",
+            algorithm_name(self.algorithm),
+            self.source_digest,
+            self.output_digest
+        )
+    }
+}
+
+/// The digest of the data a copy read from its input.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataDigest {
+    pub algorithm: HashAlgorithm,
+    /// Lower-case hex.
+    pub hex: String,
+}
+
+impl DataDigest {
+    /// Parses "ALGORITHM:HEX", as written by the `Display` impl.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (name, hex) = s.split_once(':')?;
+        let algorithm = HashAlgorithm::from_str(name, true).ok()?;
+        let valid = !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit());
+        valid.then(|| DataDigest {
+            algorithm,
+            hex: hex.to_ascii_lowercase(),
+        })
+    }
+}
+
+impl fmt::Display for DataDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", algorithm_flag(self.algorithm), self.hex)
+    }
+}
+
+/// Hashes the data of a copy as it goes by, and checks the output afterwards.
+pub struct CopyVerifier {
+    /// One running hash per algorithm; the first is the main one.
+    hashers: Vec<(HashAlgorithm, StreamHasher)>,
+    /// The regions of the output that were written, in the order they were hashed.
+    regions: Vec<ByteRange>,
+    /// The hashes of every --hash-window.
+    windows: Option<WindowHasher>,
+}
+
+impl CopyVerifier {
+    /// A verifier that hashes with each of `algorithms`, the first being the
+    /// main one.
+    pub fn new(algorithms: &[HashAlgorithm]) -> Self {
+        Self {
+            hashers: algorithms.iter().map(|&algo| (algo, StreamHasher::new(algo))).collect(),
+            regions: Vec::new(),
+            windows: None,
+        }
+    }
+
+    /// The verifier for a copy, if anything needs the digest of its data.
+    /// With --hash-window it also hashes every window, with the main
+    /// algorithm.
+    pub fn for_copy(config: &CopyConfig) -> Option<Self> {
+        let algorithms = config.digest_algorithms();
+        let &main = algorithms.first()?;
+        let mut verifier = Self::new(&algorithms);
+        if let Some(size) = config.hash_window {
+            let start = config.skip_bytes;
+            verifier.windows = Some(WindowHasher::new(main, size, start));
+        }
+        Some(verifier)
+    }
+
+    /// Hashes data that was written to the output.
+    pub fn update(&mut self, data: &[u8]) {
+        for (_, hasher) in &mut self.hashers {
+            hasher.update(data);
+        }
+        if let Some(windows) = self.windows.as_mut() {
+            windows.update(data);
+        }
+    }
+
+    /// The hashes of every --hash-window, taken out before the digests are
+    /// finished.
+    pub fn take_windows(&mut self) -> Option<BlockHashes> {
+        self.windows.take().map(WindowHasher::finish)
+    }
+
+    /// Hashes `len` zero bytes, for excluded ranges.
+    pub fn update_zeros(&mut self, len: u64) {
+        let zeros = [0u8; 4096];
+        let mut left = len;
+        while left > 0 {
+            let n = left.min(zeros.len() as u64) as usize;
+            self.update(&zeros[..n]);
+            left -= n as u64;
+        }
+    }
+
+    /// Records that the output was written from `start` up to `end`.
+    pub fn add_region(&mut self, start: u64, end: u64) {
+        if end > start {
+            self.regions.push(ByteRange {
+                offset: start,
+                length: end - start,
+            });
+        }
+    }
+
+    /// Finishes the hashes of the copied data, without reading the output
+    /// back. The main one comes first.
+    pub fn digests(self) -> Vec<DataDigest> {
+        self.hashers
+            .into_iter()
+            .map(|(algorithm, hasher)| DataDigest {
+                algorithm,
+                hex: hasher.finalize(),
+            })
+            .collect()
+    }
+
+    /// Reads the written regions of the output back once, hashes them with
+    /// every algorithm and compares each hash with the one computed during
+    /// the copy. The main algorithm's report comes first.
+    pub fn verify(self, config: &CopyConfig) -> RddResult<Vec<VerificationReport>> {
+        let algorithms: Vec<_> = self.hashers.iter().map(|(algo, _)| *algo).collect();
+        let mut readback = CopyVerifier::new(&algorithms);
+
+        let path = config.output_file.as_str();
+        let mut output = File::open(path).with_path(path)?;
+        let total = self.regions.iter().map(|r| r.length).sum();
+        let progress = progress_bar(&*config.reporter, Some(total), config.show_progress);
+        progress.set_message("verifying");
+
+        let mut buffer = vec![0; VERIFY_BUFFER_SIZE.min(config.max_io)];
+        for region in &self.regions {
+            // Don't let the page cache answer for the device.
+            advise_dont_need(&output, region.offset, region.length);
+
+            output
+                .seek(SeekFrom::Start(region.offset))
+                .at_offset(path, region.offset)?;
+            let mut pos = region.offset;
+            while pos < region.end() {
+                let want = (region.end() - pos).min(buffer.len() as u64) as usize;
+                let n = output.read(&mut buffer[..want]).at_offset(path, pos)?;
+                // The output is shorter than what we wrote: stop here and let
+                // the digests tell the story.
+                if n == 0 {
+                    break;
+                }
+                readback.update(&buffer[..n]);
+                pos += n as u64;
+                progress.inc(n as u64);
+            }
+        }
+        progress.finish_and_clear();
+
+        let mut reports = Vec::new();
+        for (source, output) in self.digests().into_iter().zip(readback.digests()) {
+            if output.hex != source.hex {
+                return Err(RddError::VerificationFailure {
+                    expected: source.hex,
+                    actual: output.hex,
+                });
+            }
+            reports.push(VerificationReport {
+                algorithm: source.algorithm,
+                source_digest: source.hex,
+                output_digest: output.hex,
+            });
+        }
+        Ok(reports)
+    }
+}
+
+/// Writes `digests` as `<digest>  <name>` lines, the format sha256sum and
+/// b3sum write and check. A single digest goes to `path`; several go to one
+/// file each, named `path` plus the algorithm ("sums.sha256"), since the
+/// tools can't tell their lines apart. Returns the files written.
+pub fn write_hash_files(path: &str, name: &str, digests: &[DataDigest]) -> RddResult<Vec<String>> {
+    // Like the tools themselves, escape a name that would break the line
+    // and flag the line with a leading backslash.
+    let line = |digest: &DataDigest| match name.contains(['\\', '\n']) {
+        true => format!("\\{}  {}\n", digest.hex, name.replace('\\', "\\\\").replace('\n', "\\n")),
+        false => format!("{}  {}\n", digest.hex, name),
+    };
+
+    let mut written = Vec::new();
+    for digest in digests {
+        let file = match digests.len() {
+            1 => path.to_string(),
+            _ => format!("{}.{}", path, algorithm_flag(digest.algorithm)),
+        };
+        fs::write(&file, line(digest)).with_path(&file)?;
+        written.push(file);
+    }
+    Ok(written)
+}
+
+pub fn algorithm_name(algo: HashAlgorithm) -> &'static str {
+    match algo {
+        HashAlgorithm::Sha256 => "SHA-256",
+        #[cfg(feature = "blake3")]
+        HashAlgorithm::Blake3 => "BLAKE3",
+    }
+}
+
+/// The algorithm's name as the command line spells it ("sha256").
+pub fn algorithm_flag(algo: HashAlgorithm) -> String {
+    algo.to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,298 @@
+// This file keeps an eye on the drives during long copies. A copy that runs for hours keeps a drive busy the whole time, and a drive in a poorly cooled USB enclosure can overheat, which is exactly when old or failing drives start throwing errors. With --monitor-health, rdd shows the temperature and error count of the input and output drives in the progress bar. With --max-temp, it also pauses the copy while a drive is too hot.
+
+// Explanation of this file:
+// Sensors: We read what the kernel already exposes in sysfs (see utils/sysfs.rs), so no extra tools or SMART libraries are needed. The temperature comes from the drive's hwmon sensor, in millidegrees Celsius: NVMe drives always have one, and SATA drives have one when the drivetemp module is loaded. The error counter is the SCSI layer's ioerr_cnt, the number of commands the drive failed, which covers SATA, SAS and USB drives.
+// Polling: poll() is called at the end of every block, but only reads the sensors every POLL_INTERVAL, since reading a drive's temperature makes the kernel send it a command.
+// Pausing: When a drive goes above --max-temp, we stop issuing I/O and poll until every drive has cooled to COOLDOWN_MARGIN degrees below the limit, so the copy doesn't flap between running and paused around the threshold. Ctrl-C ends the pause at once (see utils/signals.rs). Both the pause and the resume are printed, and the time spent paused is added to the summary.
+// New errors: If a drive's error counter goes up during the copy, we warn once per poll. The copy carries on, since reading errors are dealt with by the copy itself, but the user learns that the drive is struggling.
+// Platform support: This only works on Linux. Elsewhere, or for files and drives without sensors, there's nothing to read; we warn once at the start and the copy runs as usual.
+// HealthStats struct: The hottest temperature seen, the number of new errors and the time spent paused, which end up in CopyStats and in the summary.
+
+// src/core/health.rs
+
+use crate::config::CopyConfig;
+use crate::core::reporter::Reporter;
+use crate::utils::diagnostics::Diagnostic;
+use crate::utils::signals;
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often the sensors are read.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How far below --max-temp a drive must cool before the copy resumes.
+const COOLDOWN_MARGIN: f64 = 5.0;
+
+/// One reading of a drive's sensors.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DriveHealth {
+    /// Temperature in degrees Celsius, if the drive has a sensor.
+    pub temperature: Option<f64>,
+    /// Commands the drive has failed since it was attached, if known.
+    pub io_errors: Option<u64>,
+}
+
+/// What the health monitor saw over a whole copy.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HealthStats {
+    /// The highest temperature any drive reached, in degrees Celsius.
+    pub peak_temperature: Option<f64>,
+    /// I/O errors the drives reported during the copy.
+    pub new_io_errors: u64,
+    /// Time spent waiting for an overheated drive to cool down.
+    pub paused: Duration,
+}
+
+impl HealthStats {
+    /// A short summary for the end of a copy.
+    pub fn summary(&self) -> String {
+        let mut line = match self.peak_temperature {
+            Some(peak) => format!("Drive health: peak temperature {:.0}°C", peak),
+            None => "Drive health: no temperature readings".to_string(),
+        };
+        line.push_str(&format!(", {} new I/O errors", self.new_io_errors));
+        if !self.paused.is_zero() {
+            line.push_str(&format!(", paused {:.0}s to cool down", self.paused.as_secs_f64()));
+        }
+        line
+    }
+}
+
+/// A drive we're monitoring.
+struct Drive {
+    /// Short name for messages, e.g. "sdb".
+    name: String,
+    sensors: platform::Sensors,
+    /// The error count when monitoring started.
+    errors_at_start: Option<u64>,
+    /// The error count at the last poll.
+    errors_seen: Option<u64>,
+}
+
+/// Reads the sensors of the input and output drives during a copy.
+pub struct HealthMonitor {
+    drives: Vec<Drive>,
+    max_temp: Option<f64>,
+    last_poll: Option<Instant>,
+    reporter: Arc<dyn Reporter>,
+    pub totals: HealthStats,
+}
+
+impl HealthMonitor {
+    /// Finds the drives behind the input and output of `config`. Files and
+    /// streams are left out; if no drive has sensors, we say so once.
+    pub fn new(config: &CopyConfig) -> Self {
+        let mut drives = Vec::new();
+        for (path, is_stdio) in [
+            (&config.input_file, config.input_is_stdin() || config.input_is_network()),
+            (&config.output_file, config.output_is_stdout() || config.output_is_network()),
+        ] {
+            if is_stdio {
+                continue;
+            }
+            if let Some((name, sensors)) = platform::Sensors::find(path)
+                && !drives.iter().any(|d: &Drive| d.name == name)
+            {
+                let errors = sensors.read().io_errors;
+                drives.push(Drive {
+                    name,
+                    sensors,
+                    errors_at_start: errors,
+                    errors_seen: errors,
+                });
+            }
+        }
+
+        if drives.is_empty() {
+            config.reporter.diagnostic(
+                &Diagnostic::warning(format!(
+                    "no drive sensors found for '{}' or '{}' (only block devices on Linux have them); the copy runs unmonitored.",
+                    config.input_file, config.output_file
+                ))
+                .with_flag(if config.max_temp.is_some() { "--max-temp" } else { "--monitor-health" }),
+            );
+        }
+
+        Self {
+            drives,
+            max_temp: config.max_temp.map(f64::from),
+            last_poll: None,
+            reporter: config.reporter.clone(),
+            totals: HealthStats::default(),
+        }
+    }
+
+    /// Reads the sensors if POLL_INTERVAL has passed, shows the readings in
+    /// the progress bar, and waits while a drive is hotter than --max-temp.
+    pub fn poll(&mut self, progress: &ProgressBar) {
+        if self.drives.is_empty() || self.last_poll.is_some_and(|t| t.elapsed() < POLL_INTERVAL) {
+            return;
+        }
+
+        let mut readings = self.read_all(progress);
+        progress.set_message(describe(&self.drives, &readings));
+
+        let Some(limit) = self.max_temp else {
+            return;
+        };
+        let Some(peak) = hottest(&readings).filter(|t| *t > limit) else {
+            return;
+        };
+
+        let resume_at = limit - COOLDOWN_MARGIN;
+        let warning = Diagnostic::warning(format!(
+            "a drive reached {:.0}°C, above the limit of {:.0}°C; pausing until it cools down to {:.0}°C.",
+            peak, limit, resume_at
+        ))
+        .with_flag("--max-temp");
+        progress.suspend(|| self.reporter.diagnostic(&warning));
+        let paused_at = Instant::now();
+        while hottest(&readings).is_some_and(|t| t > resume_at) {
+            progress.set_message(format!("paused to cool down: {}", describe(&self.drives, &readings)));
+            // Ctrl-C stops the copy at once, rather than after the cool-down.
+            if !signals::sleep(POLL_INTERVAL, &*self.reporter, progress) {
+                self.totals.paused += paused_at.elapsed();
+                return;
+            }
+            readings = self.read_all(progress);
+        }
+        let paused = paused_at.elapsed();
+        self.totals.paused += paused;
+        self.reporter.message_over(
+            progress,
+            &format!("Drives cooled down after {:.0}s; resuming the copy.", paused.as_secs_f64()),
+        );
+        progress.set_message(describe(&self.drives, &readings));
+    }
+
+    /// Reads every drive once, updating the totals and warning about new errors.
+    fn read_all(&mut self, progress: &ProgressBar) -> Vec<DriveHealth> {
+        self.last_poll = Some(Instant::now());
+        let mut readings = Vec::with_capacity(self.drives.len());
+        for drive in &mut self.drives {
+            let reading = drive.sensors.read();
+            if let Some(temp) = reading.temperature {
+                self.totals.peak_temperature = Some(self.totals.peak_temperature.map_or(temp, |p| p.max(temp)));
+            }
+            if let (Some(now), Some(seen)) = (reading.io_errors, drive.errors_seen)
+                && now > seen
+            {
+                let added = now - seen;
+                let warning = Diagnostic::warning(format!(
+                    "{} reported {} new I/O error(s) during the copy; the drive may be failing.",
+                    drive.name, added
+                ));
+                progress.suspend(|| self.reporter.diagnostic(&warning));
+            }
+            drive.errors_seen = reading.io_errors.or(drive.errors_seen);
+            readings.push(reading);
+        }
+        self.totals.new_io_errors = self
+            .drives
+            .iter()
+            .filter_map(|d| Some(d.errors_seen? - d.errors_at_start?))
+            .sum();
+        readings
+    }
+}
+
+/// The highest temperature among `readings`.
+fn hottest(readings: &[DriveHealth]) -> Option<f64> {
+    readings.iter().filter_map(|r| r.temperature).reduce(f64::max)
+}
+
+/// "sda 41°C, 0 errors | sdb 38°C", for the progress bar.
+fn describe(drives: &[Drive], readings: &[DriveHealth]) -> String {
+    drives
+        .iter()
+        .zip(readings)
+        .map(|(drive, reading)| {
+            let mut part = drive.name.clone();
+            if let Some(temp) = reading.temperature {
+                part.push_str(&format!(" {:.0}°C", temp));
+            }
+            if let Some(errors) = reading.io_errors {
+                part.push_str(&format!(", {} errors", errors));
+            }
+            part
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::DriveHealth;
+    use crate::core::mapfile::parse_offset;
+    use crate::utils::sysfs;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// The sysfs files we read for one drive.
+    pub struct Sensors {
+        temperature: Option<PathBuf>,
+        io_errors: Option<PathBuf>,
+    }
+
+    impl Sensors {
+        /// Finds the sensors of the drive behind `path`, with its name.
+        pub fn find(path: &str) -> Option<(String, Self)> {
+            let disk = sysfs::whole_disk(path).ok()??;
+            let name = disk.file_name()?.to_string_lossy().into_owned();
+            let io_errors = Some(disk.join("device/ioerr_cnt")).filter(|p| p.exists());
+            let sensors = Sensors {
+                temperature: find_temperature(&disk),
+                io_errors,
+            };
+            (sensors.temperature.is_some() || sensors.io_errors.is_some()).then_some((name, sensors))
+        }
+
+        pub fn read(&self) -> DriveHealth {
+            let read = |path: &Option<PathBuf>| {
+                path.as_ref()
+                    .and_then(|p| fs::read_to_string(p).ok())
+                    .map(|s| s.trim().to_string())
+            };
+            DriveHealth {
+                // hwmon reports millidegrees.
+                temperature: read(&self.temperature)
+                    .and_then(|s| s.parse::<i64>().ok())
+                    .map(|milli| milli as f64 / 1000.0),
+                // ioerr_cnt is printed in hex ("0x3").
+                io_errors: read(&self.io_errors).and_then(|s| parse_offset(&s)),
+            }
+        }
+    }
+
+    /// The drive's hwmon temperature input. drivetemp (SATA) registers it
+    /// under device/hwmon/, NVMe directly under device/.
+    fn find_temperature(disk: &Path) -> Option<PathBuf> {
+        [disk.join("device/hwmon"), disk.join("device")]
+            .iter()
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("hwmon"))
+            .map(|entry| entry.path().join("temp1_input"))
+            .find(|path| path.exists())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::DriveHealth;
+
+    pub struct Sensors;
+
+    impl Sensors {
+        pub fn find(_path: &str) -> Option<(String, Self)> {
+            None
+        }
+
+        pub fn read(&self) -> DriveHealth {
+            DriveHealth::default()
+        }
+    }
+}
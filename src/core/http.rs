@@ -0,0 +1,335 @@
+// This file implements http:// and https:// inputs, so an image can be flashed straight from where it's published: `rdd copy -i https://example.org/os.img.xz -o /dev/sdb`, without downloading it to a temporary file first, or `curl | xz -d | dd`, which can't tell a dropped download from the end of the image.
+
+// Explanation of this file:
+// The curl program : Like ssh for ssh:// (see remote.rs), rdd runs the curl client that's installed, so TLS, proxies (http_proxy and friends), redirects and ~/.curlrc work as they do on the command line. CodecMissing says to install it when it isn't.
+// Probing : Before the download starts, a HEAD request asks for the size (Content-Length) and for what identifies this version of the file (ETag or Last-Modified). A server that only answers GET is simply downloaded, without a size. Either way, start() waits for the first bytes of the body, so a URL that can't be downloaded fails the copy before the output is opened. The size gives the progress bar its length when the body is copied as it is.
+// Pipes as files : The body arrives through a pipe from a thread that runs curl, like the connections of network.rs, so an http(s):// input counts as a network input in config.rs and comes with the restrictions of stdin. A compressed image is decompressed on the way (see compress.rs): the thread passes on a copy of the first bytes it gets, so the format is told without taking them out of the pipe.
+// Resuming : A download that breaks off (the connection drops or stalls, the server goes away) is picked up where it stopped with a Range request, a few times with a growing pause in between. The request carries If-Range with the version from the probe, so a file that changed on the server in the meantime fails the copy instead of splicing two versions together.
+// Errors : curl says what went wrong on its stderr (e.g. "The requested URL returned error: 404"), and a download that failed, or couldn't be resumed, fails the copy in finish, with curl's exit status. A download stopped early by --count is stopped on purpose.
+
+// src/core/http.rs
+
+use crate::config::CopyConfig;
+use crate::core::compress::failed;
+use crate::core::open::pipe_file;
+use crate::core::reporter::Reporter;
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::diagnostics::Diagnostic;
+use crossbeam_channel::{bounded, Sender};
+use indicatif::BinaryBytes;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How http inputs are written; https:// is the same, over TLS.
+pub const HTTP_SCHEMES: &[&str] = &["http://", "https://"];
+/// The program that downloads.
+const CURL: &str = "curl";
+/// How much the downloading thread moves at once.
+const BUFFER_SIZE: usize = 1 << 20;
+/// How often a broken download is picked up again before the copy fails.
+const RESUMES: u32 = 5;
+/// The pause before the first resume; it doubles for each one after.
+const RESUME_DELAY: Duration = Duration::from_secs(1);
+/// A download that moves nothing for this many seconds counts as broken.
+const STALL_SECS: u64 = 30;
+/// curl's exit codes for a transfer that broke off, rather than one that was
+/// refused: couldn't connect (7), partial file (18), timed out or stalled
+/// (28), empty reply (52), send or receive failure (55, 56) and an HTTP/2
+/// stream error (92).
+const INTERRUPTED: &[i32] = &[7, 18, 28, 52, 55, 56, 92];
+/// curl's exit code for an HTTP error status (with --fail).
+const REFUSED: i32 = 22;
+/// curl's exit code for a server that sent the whole file when asked for
+/// the rest.
+const CANNOT_RESUME: i32 = 33;
+
+/// Whether `path` is an http:// or https:// URL.
+pub fn is_http(path: &str) -> bool {
+    HTTP_SCHEMES.iter().any(|scheme| path.starts_with(scheme))
+}
+
+/// What the probe learned about the file behind a URL.
+#[derive(Debug, Default, Clone)]
+struct Probed {
+    /// The size of the body, if the server said.
+    size: Option<u64>,
+    /// What tells this version of the file from another, for If-Range.
+    version: Option<String>,
+}
+
+/// A download a copy reads its input from.
+pub struct Download {
+    thread: JoinHandle<RddResult<Downloaded>>,
+    /// A copy of the first bytes of the body.
+    first: Vec<u8>,
+    url: String,
+    size: Option<u64>,
+    started: Instant,
+    reporter: Arc<dyn Reporter>,
+}
+
+/// What the downloading thread did.
+#[derive(Debug, Default, Clone, Copy)]
+struct Downloaded {
+    bytes: u64,
+    resumes: u32,
+    /// Whether the copy stopped reading before the end.
+    stopped: bool,
+}
+
+impl Download {
+    /// Starts downloading the input of `config`. Returns the download with
+    /// the pipe its body arrives on.
+    pub fn start(config: &CopyConfig) -> RddResult<(Self, File)> {
+        let url = config.input_file.clone();
+        let probed = probe(config, &url)?;
+        if let Some(size) = probed.size {
+            config
+                .reporter
+                .message(&format!("Downloading {} ({} bytes) from {}.", BinaryBytes(size), size, url));
+        }
+        let (reader, writer) = io::pipe().with_path(&url)?;
+        let pipe = pipe_file(reader).with_path(&url)?;
+        let (thread_url, thread_probed, reporter) = (url.clone(), probed.clone(), config.reporter.clone());
+        let connect_timeout = config.open_timeout;
+        let (first_sender, first_receiver) = bounded(1);
+        let thread = thread::Builder::new()
+            .name("rdd-download".to_string())
+            .spawn(move || {
+                download(writer, &thread_url, &thread_probed, connect_timeout, first_sender, &*reporter)
+            })
+            .with_path(&url)?;
+        // Nothing arrives from a download that failed, or an empty one; the
+        // thread is done then.
+        let Ok(first) = first_receiver.recv() else {
+            thread
+                .join()
+                .map_err(|_| RddError::Channel("the download thread panicked".to_string()))??;
+            return Err(RddError::Config(format!("'{}' is empty; there's nothing to copy.", url)));
+        };
+        Ok((
+            Download {
+                thread,
+                first,
+                url,
+                size: probed.size,
+                started: Instant::now(),
+                reporter: config.reporter.clone(),
+            },
+            pipe,
+        ))
+    }
+
+    /// The size of the body, if the server said.
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Copies the first bytes of the body into `header`, without taking them
+    /// out of the pipe. Returns how many there were, which is fewer for a
+    /// short body.
+    pub fn peek(&self, header: &mut [u8]) -> usize {
+        let n = self.first.len().min(header.len());
+        header[..n].copy_from_slice(&self.first[..n]);
+        n
+    }
+
+    /// Waits for the download once the copy's end of the pipe is closed.
+    pub fn finish(self) -> RddResult<()> {
+        let downloaded = self
+            .thread
+            .join()
+            .map_err(|_| RddError::Channel("the download thread panicked".to_string()))??;
+        if downloaded.stopped {
+            return Ok(());
+        }
+        let secs = self.started.elapsed().as_secs_f64();
+        let rate = if secs > 0.0 { (downloaded.bytes as f64 / secs) as u64 } else { downloaded.bytes };
+        self.reporter.message(&format!(
+            "Downloaded {} ({} bytes) from {} in {:.2}s ({}/s){}.",
+            BinaryBytes(downloaded.bytes),
+            downloaded.bytes,
+            self.url,
+            secs,
+            BinaryBytes(rate),
+            match downloaded.resumes {
+                0 => String::new(),
+                n => format!(", resumed {} time(s)", n),
+            }
+        ));
+        Ok(())
+    }
+}
+
+/// The curl command, failing on HTTP errors and following redirects, with
+/// curl's own messages for errors only. The URL goes last.
+fn curl(connect_timeout: Option<Duration>) -> Command {
+    let mut curl = Command::new(CURL);
+    curl.args(["--silent", "--show-error", "--fail", "--location"]);
+    if let Some(timeout) = connect_timeout {
+        curl.arg("--connect-timeout").arg(timeout.as_secs().max(1).to_string());
+    }
+    curl
+}
+
+/// Asks the server about `url` without downloading it. A server that
+/// refuses the question (some only answer GET, e.g. presigned URLs) gets
+/// the download itself; only then is the size unknown.
+fn probe(config: &CopyConfig, url: &str) -> RddResult<Probed> {
+    let mut command = curl(config.open_timeout);
+    command.arg("--head").arg(url).stdin(Stdio::null()).stderr(Stdio::piped());
+    let output = command.output().map_err(spawn_error)?;
+    match output.status.code() {
+        _ if output.status.success() => Ok(parse_headers(&String::from_utf8_lossy(&output.stdout))),
+        Some(REFUSED) => Ok(Probed::default()),
+        _ => {
+            config.reporter.message(String::from_utf8_lossy(&output.stderr).trim_end());
+            Err(failed(CURL, "downloading", output.status))
+        }
+    }
+}
+
+/// Reads the headers of the last response in `text`, which holds one per
+/// redirect followed.
+fn parse_headers(text: &str) -> Probed {
+    let mut probed = Probed::default();
+    let mut modified = None;
+    for line in text.lines() {
+        if line.starts_with("HTTP/") {
+            (probed, modified) = (Probed::default(), None);
+            continue;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => probed.size = value.parse().ok(),
+            // A weak ETag can't be used to resume.
+            "etag" if !value.starts_with("W/") => probed.version = Some(value.to_string()),
+            "last-modified" => modified = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    probed.version = probed.version.or(modified);
+    probed
+}
+
+/// The downloading thread: runs curl and copies the body into `pipe`,
+/// resuming after a break, until the body ends or the copy stops reading.
+fn download(
+    mut pipe: io::PipeWriter,
+    url: &str,
+    probed: &Probed,
+    connect_timeout: Option<Duration>,
+    first: Sender<Vec<u8>>,
+    reporter: &dyn Reporter,
+) -> RddResult<Downloaded> {
+    let mut first = Some(first);
+    let mut downloaded = Downloaded::default();
+    let mut delay = RESUME_DELAY;
+    loop {
+        let mut command = curl(connect_timeout);
+        command.arg("--speed-limit").arg("1").arg("--speed-time").arg(STALL_SECS.to_string());
+        if downloaded.bytes > 0 {
+            command.arg("--continue-at").arg(downloaded.bytes.to_string());
+            if let Some(version) = &probed.version {
+                command.arg("--header").arg(format!("If-Range: {}", version));
+            }
+        }
+        // curl's messages are only passed on for a download that failed,
+        // not one the copy stopped.
+        let mut child = command
+            .arg(url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(spawn_error)?;
+        if !pass_on(&mut child, &mut pipe, url, &mut downloaded, &mut first)? {
+            // The copy has what it wants; curl isn't needed anymore.
+            let _ = child.kill();
+            let _ = child.wait();
+            downloaded.stopped = true;
+            return Ok(downloaded);
+        }
+        let mut said = String::new();
+        if let Some(mut stderr) = child.stderr.take() {
+            let _ = stderr.read_to_string(&mut said);
+        }
+        let status = child.wait().with_path(CURL)?;
+        if status.success() {
+            return Ok(downloaded);
+        }
+        let said = said.trim_end();
+        let interrupted = status.code().is_some_and(|code| INTERRUPTED.contains(&code));
+        if !interrupted || downloaded.resumes == RESUMES {
+            reporter.message(said);
+            if status.code() == Some(CANNOT_RESUME) {
+                reporter.diagnostic(&Diagnostic::warning(
+                    "The server sent the file from the start instead of the rest: it changed since the download started, or the server can't resume downloads.",
+                ));
+            }
+            return Err(failed(CURL, "downloading", status));
+        }
+        downloaded.resumes += 1;
+        reporter.diagnostic(&Diagnostic::warning(format!(
+            "The download broke off after {} ({} bytes): {}. Resuming in {}s (attempt {} of {}).",
+            BinaryBytes(downloaded.bytes),
+            downloaded.bytes,
+            said,
+            delay.as_secs(),
+            downloaded.resumes,
+            RESUMES
+        )));
+        thread::sleep(delay);
+        delay *= 2;
+    }
+}
+
+/// Copies what curl writes into `pipe`, and a copy of the first bytes to
+/// `first`. Returns false if the copy stopped reading the pipe.
+fn pass_on(
+    child: &mut Child,
+    pipe: &mut io::PipeWriter,
+    url: &str,
+    downloaded: &mut Downloaded,
+    first: &mut Option<Sender<Vec<u8>>>,
+) -> RddResult<bool> {
+    let Some(mut body) = child.stdout.take() else {
+        return Err(RddError::Channel("curl has no output pipe".to_string()));
+    };
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    loop {
+        let n = match body.read(&mut buffer) {
+            Ok(0) => return Ok(true),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e).at_offset(url, downloaded.bytes),
+        };
+        if let Some(first) = first.take() {
+            let _ = first.send(buffer[..n].to_vec());
+        }
+        match pipe.write_all(&buffer[..n]) {
+            Ok(()) => downloaded.bytes += n as u64,
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(false),
+            Err(e) => return Err(e).at_offset(url, downloaded.bytes),
+        }
+    }
+}
+
+/// The error for curl that couldn't be started.
+fn spawn_error(e: io::Error) -> RddError {
+    match e.kind() {
+        io::ErrorKind::NotFound => RddError::CodecMissing { tool: CURL.to_string() },
+        _ => RddError::IoAt {
+            path: CURL.to_string(),
+            offset: None,
+            source: e,
+        },
+    }
+}
@@ -0,0 +1,238 @@
+// This file checks, before a copy starts, that the system lets it have what it's going to use: open files, threads and memory for its buffers. A copy that runs into a limit halfway doesn't fail cleanly: opening a file fails with EMFILE, spawning a thread panics, and an allocation that doesn't fit gets the process killed, hours into a copy and with the output half-written. Checking up front turns that into a clear message before anything is written, or into an adjustment the copy can live with.
+
+// Explanation of this file:
+// Needs : What a copy may use at once, worked out from its configuration: the files it opens (input and output, the pipes and chunks of --compress, --split-size and their reverse, the io_uring ring, the logs and records it writes), its threads (the writers of --threads, the helper threads of the pipes, the bandwidth log) and the memory of its buffers (see buffer_count in parallel.rs and queue_depth in engine.rs). They're upper bounds; a copy may well use less.
+// Open files : Checked against RLIMIT_NOFILE (ulimit -n). A soft limit that's too low is raised up to the hard limit, which any process may do; if even the hard limit is too low, the copy fails.
+// Threads : On Linux, threads count against RLIMIT_NPROC (ulimit -u) like processes do, except for root. A limit lower than the copy's own threads fails the copy; the user's other processes count too, but we can't know how many there will be.
+// Memory : On Linux, the buffers are checked against MemAvailable in /proc/meminfo. Buffers that wouldn't fit lower --max-in-flight, with a warning, to half of what's available (the parallel and io_uring engines then keep fewer chunks in flight). A copy that doesn't fit even with a single chunk fails, and asks for a smaller --bs or --max-io.
+// Other systems : Where a limit can't be read (memory outside Linux, everything on Windows), it isn't checked.
+
+// src/core/limits.rs
+
+use crate::cli::{Decompression, Engine};
+use crate::config::CopyConfig;
+use crate::core::autotune::largest_block_size;
+use crate::core::engine::queue_depth;
+use crate::core::parallel::{buffer_count, writer_count};
+use crate::core::split::chunk_set;
+use crate::error::{RddError, RddResult};
+use crate::utils::diagnostics::Diagnostic;
+use indicatif::BinaryBytes;
+use std::borrow::Cow;
+
+/// Files every copy may have open: stdin, stdout and stderr, the input and
+/// the output, and a few short-lived ones (the state file of --resume,
+/// sysfs reads, the output read back by --verify).
+const BASE_FILES: u64 = 8;
+
+/// Files a pipe to or from a helper takes: the pipe's two ends and the
+/// file or chunk on the other side.
+const PIPE_FILES: u64 = 3;
+
+/// Threads every copy may have: the copy itself, the progress bar's ticker,
+/// the job file of 'rdd top' and the thread of --open-timeout.
+const BASE_THREADS: u64 = 4;
+
+/// What a copy may use at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Needs {
+    pub files: u64,
+    pub threads: u64,
+    /// Bytes of buffers.
+    pub memory: u64,
+    /// Bytes of buffers with --max-in-flight as low as it goes.
+    pub min_memory: u64,
+}
+
+impl Needs {
+    /// Works out what `config` needs.
+    pub fn of(config: &CopyConfig) -> Self {
+        let mut files = BASE_FILES;
+        let mut threads = BASE_THREADS;
+        let pipes = [
+            config.compress.is_some(),
+            config.split_size.is_some(),
+            config.decompress != Decompression::Never && !config.input_is_stdin(),
+            chunk_set(&config.input_file).is_some(),
+            config.input_is_network(),
+            config.output_is_network(),
+            config.records.is_some(),
+        ];
+        for _ in pipes.iter().filter(|&&pipe| pipe) {
+            files += PIPE_FILES;
+            threads += 1;
+        }
+        let records = [
+            &config.bandwidth_log,
+            &config.custody_log,
+            &config.hash_log,
+            &config.block_hashes,
+            &config.stats_file,
+            &config.manifest_out,
+            &config.hash_output,
+        ];
+        files += records.iter().filter(|record| record.is_some()).count() as u64;
+        if config.bandwidth_log.is_some() {
+            threads += 1;
+        }
+
+        let chunk = config.block_size.min(config.max_io) as u64;
+        let (memory, min_memory) = match config.engine {
+            Engine::IoUring => {
+                files += 1;
+                (queue_depth(config) as u64 * chunk, chunk)
+            }
+            Engine::Standard if config.threads > 1 => {
+                threads += 1 + writer_count(config) as u64;
+                (buffer_count(config) as u64 * chunk, chunk)
+            }
+            Engine::Standard => {
+                // --bs auto allocates for the largest block size it may pick.
+                let chunk = match config.auto_block_size {
+                    true => largest_block_size(config).min(config.max_io) as u64,
+                    false => chunk,
+                };
+                let existing = if config.write_if_changed { chunk } else { 0 };
+                let memory = chunk + existing + config.coalesce.unwrap_or(0) as u64;
+                (memory, memory)
+            }
+        };
+        Needs {
+            files,
+            threads,
+            memory,
+            min_memory,
+        }
+    }
+}
+
+/// Checks what `config` needs against the system's limits. Raises the
+/// open-files limit if it can, and lowers --max-in-flight if the buffers
+/// wouldn't fit in memory; fails when neither is enough.
+pub fn fit(config: &CopyConfig) -> RddResult<Cow<'_, CopyConfig>> {
+    let needs = Needs::of(config);
+
+    if let Some((soft, hard)) = platform::open_files_limit()
+        && needs.files > soft
+    {
+        if needs.files > hard || !platform::raise_open_files(needs.files) {
+            return Err(RddError::Config(format!(
+                "This copy may need {} open files at once, but the limit is {} (see ulimit -n). Raise it, or leave out some of the logs and pipes.",
+                needs.files, soft
+            )));
+        }
+        config.reporter.message(&format!(
+            "Raised the open-files limit from {} to {}, which this copy may need.",
+            soft, needs.files
+        ));
+    }
+
+    if let Some(limit) = platform::threads_limit()
+        && needs.threads > limit
+    {
+        return Err(RddError::Config(format!(
+            "This copy needs {} threads, but the limit is {} processes and threads (see ulimit -u). Raise it, or use fewer --threads.",
+            needs.threads, limit
+        )));
+    }
+
+    let Some(available) = platform::available_memory() else {
+        return Ok(Cow::Borrowed(config));
+    };
+    if needs.memory <= available {
+        return Ok(Cow::Borrowed(config));
+    }
+    if needs.min_memory > available {
+        return Err(RddError::Config(format!(
+            "The copy's buffers need {} of memory, but only {} is available. Use a smaller --bs or --max-io.",
+            BinaryBytes(needs.min_memory),
+            BinaryBytes(available)
+        )));
+    }
+    let mut fitted = config.clone();
+    fitted.max_in_flight = ((available / 2) as usize).max(config.block_size.min(config.max_io));
+    config.reporter.diagnostic(
+        &Diagnostic::warning(format!(
+            "the copy's buffers would need {}, but only {} of memory is available; keeping at most {} in flight instead.",
+            BinaryBytes(needs.memory),
+            BinaryBytes(available),
+            BinaryBytes(fitted.max_in_flight as u64)
+        ))
+        .with_flag("--max-in-flight"),
+    );
+    Ok(Cow::Owned(fitted))
+}
+
+#[cfg(unix)]
+mod platform {
+    use nix::sys::resource::{getrlimit, setrlimit, Resource};
+
+    /// The soft and hard limits of open files.
+    pub fn open_files_limit() -> Option<(u64, u64)> {
+        let (soft, hard) = getrlimit(Resource::RLIMIT_NOFILE).ok()?;
+        Some((to_u64(soft), to_u64(hard)))
+    }
+
+    /// A limit as u64, which rlim_t isn't everywhere. Unlimited (or a
+    /// negative value, where it's signed) is as much as there can be.
+    fn to_u64(limit: impl TryInto<u64>) -> u64 {
+        limit.try_into().unwrap_or(u64::MAX)
+    }
+
+    /// Raises the soft limit of open files to `soft`, keeping the hard one.
+    /// Returns whether it worked.
+    pub fn raise_open_files(soft: u64) -> bool {
+        getrlimit(Resource::RLIMIT_NOFILE)
+            .and_then(|(_, hard)| setrlimit(Resource::RLIMIT_NOFILE, soft as _, hard))
+            .is_ok()
+    }
+
+    /// The soft limit of processes and threads, which threads count against.
+    /// Root isn't held to it.
+    #[cfg(target_os = "linux")]
+    pub fn threads_limit() -> Option<u64> {
+        // SAFETY: geteuid has no preconditions and can't fail.
+        if unsafe { nix::libc::geteuid() } == 0 {
+            return None;
+        }
+        getrlimit(Resource::RLIMIT_NPROC).ok().map(|(soft, _)| to_u64(soft))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn threads_limit() -> Option<u64> {
+        None
+    }
+
+    /// The memory that can be allocated without swapping.
+    #[cfg(target_os = "linux")]
+    pub fn available_memory() -> Option<u64> {
+        let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+        let line = meminfo.lines().find(|line| line.starts_with("MemAvailable:"))?;
+        let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+        Some(kib * 1024)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn available_memory() -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    pub fn open_files_limit() -> Option<(u64, u64)> {
+        None
+    }
+
+    pub fn raise_open_files(_soft: u64) -> bool {
+        false
+    }
+
+    pub fn threads_limit() -> Option<u64> {
+        None
+    }
+
+    pub fn available_memory() -> Option<u64> {
+        None
+    }
+}
@@ -0,0 +1,655 @@
+// This file implements --manifest-out and --from-manifest. An acquisition that has to hold up later (forensics, audits, or just "how did I make this image?") needs more than the image: it needs a record of exactly how it was made. With --manifest-out, a finished copy writes a reproducibility manifest next to its output. With --from-manifest, the same copy can be run again from it, with identical settings, and is checked against the recorded digest.
+
+// Explanation of this file:
+// The format : A small subset of TOML, so it can be read by people and by any TOML library: [sections], `key = value` lines, and "#" comments. Values are strings, integers, booleans, or lists of [offset, length] pairs for the ranges. We write and read it ourselves, since that subset is all we need.
+// What's recorded : [rdd] has the rdd version, OS, architecture and copy engine. [copy] has every effective setting of the copy, after the target profile was applied, so a replay doesn't depend on what a profile means in another version. [result] has the size of the copy and the digest of the data read (see DataDigest in hash.rs).
+// What isn't : Settings that only change how the copy is shown (the progress bar) and the manifest path itself are left out.
+// Replaying : load() turns a manifest back into a CopyConfigBuilder, so the settings are validated exactly like typed-in ones. Missing keys fall back to the builder's defaults, and unknown keys are ignored, so manifests from older and newer versions still load. A manifest from another rdd version gets a warning, since the copy may not behave exactly the same.
+// Checking : The recorded digest becomes the digest the replayed copy must produce. If the input changed since, the copy fails with RddError::ManifestMismatch once it's done.
+// Scrubbing : load_record reads a manifest for `rdd scrub` (see scrub.rs), which checks the image a copy wrote against the recorded digest long after the copy. is_manifest lets it pick manifests out of a directory by their first line, whatever they're named.
+
+// src/core/manifest.rs
+
+use crate::cli::{Compression, Conversion, Decompression, Engine, HashAlgorithm, InputGuard};
+use crate::config::{CopyConfig, CopyConfigBuilder};
+use crate::core::copy::CopyStats;
+use crate::core::hash::DataDigest;
+use crate::core::priority::PriorityRanges;
+use crate::core::ranges::ByteRange;
+use crate::core::reporter::Reporter;
+use crate::core::syncmode::SyncMode;
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::profile::FadviseHints;
+use crate::utils::diagnostics::Diagnostic;
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The line every manifest starts with.
+const HEADER: &str = "# rdd reproducibility manifest.";
+
+/// A value in a manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Str(String),
+    Int(u64),
+    Bool(bool),
+    Ranges(Vec<ByteRange>),
+}
+
+impl Value {
+    fn name(&self) -> &'static str {
+        match self {
+            Value::Str(_) => "string",
+            Value::Int(_) => "integer",
+            Value::Bool(_) => "boolean",
+            Value::Ranges(_) => "list of [offset, length] pairs",
+        }
+    }
+
+    /// Parses the right-hand side of a `key = value` line, including any
+    /// comment after the value.
+    fn parse(s: &str) -> Option<Self> {
+        if let Some(rest) = s.strip_prefix('"') {
+            let (value, rest) = parse_string(rest)?;
+            return is_blank(rest).then_some(Value::Str(value));
+        }
+        let s = s.split('#').next().unwrap_or_default().trim();
+        match s {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            _ if s.starts_with('[') => parse_ranges(s).map(Value::Ranges),
+            _ => s.parse().ok().map(Value::Int),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => {
+                f.write_str("\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        c if c.is_control() => write!(f, "\\u{:04X}", c as u32)?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                f.write_str("\"")
+            }
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Ranges(ranges) => {
+                let pairs: Vec<_> = ranges
+                    .iter()
+                    .map(|r| format!("[{}, {}]", r.offset, r.length))
+                    .collect();
+                write!(f, "[{}]", pairs.join(", "))
+            }
+        }
+    }
+}
+
+/// Parses a basic string after its opening quote. Returns the string and
+/// whatever follows the closing quote.
+fn parse_string(s: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &s[i + 1..])),
+            '\\' => {
+                let escaped = match chars.next()?.1 {
+                    '"' => '"',
+                    '\\' => '\\',
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    'u' => {
+                        let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                        char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                    }
+                    _ => return None,
+                };
+                value.push(escaped);
+            }
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// Parses "[[offset, length], ...]".
+fn parse_ranges(s: &str) -> Option<Vec<ByteRange>> {
+    let mut rest = s.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let mut ranges = Vec::new();
+    while !rest.is_empty() {
+        let (pair, after) = rest.strip_prefix('[')?.split_once(']')?;
+        let (offset, length) = pair.split_once(',')?;
+        ranges.push(ByteRange {
+            offset: offset.trim().parse().ok()?,
+            length: length.trim().parse().ok()?,
+        });
+        let after = after.trim_start();
+        rest = after.strip_prefix(',').unwrap_or(after).trim_start();
+    }
+    Some(ranges)
+}
+
+/// Whether `s` is empty apart from whitespace and a comment.
+fn is_blank(s: &str) -> bool {
+    let s = s.trim_start();
+    s.is_empty() || s.starts_with('#')
+}
+
+/// Describes the engine that runs a copy with `config`.
+fn engine(config: &CopyConfig) -> String {
+    match config.threads {
+        _ if config.engine == Engine::IoUring => "io_uring".to_string(),
+        1 => "single-threaded".to_string(),
+        n => format!("parallel, {} threads (1 reader, {} writers)", n, n - 1),
+    }
+}
+
+/// Renders the manifest of a finished copy.
+pub fn render(config: &CopyConfig, stats: &CopyStats) -> String {
+    let mut out = String::new();
+    let mut section = |name: &str, entries: Vec<(&str, Option<Value>)>| {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!("[{}]\n", name));
+        for (key, value) in entries {
+            if let Some(value) = value {
+                out.push_str(&format!("{} = {}\n", key, value));
+            }
+        }
+    };
+    let text = |s: &str| Some(Value::Str(s.to_string()));
+    let int = |n: u64| Some(Value::Int(n));
+    let flag = |b: bool| Some(Value::Bool(b));
+
+    section(
+        "rdd",
+        vec![
+            ("version", text(env!("CARGO_PKG_VERSION"))),
+            ("os", text(std::env::consts::OS)),
+            ("arch", text(std::env::consts::ARCH)),
+            ("engine", text(&engine(config))),
+        ],
+    );
+    section(
+        "copy",
+        vec![
+            ("input", text(&config.input_file)),
+            ("output", text(&config.output_file)),
+            ("block_size", int(config.block_size as u64)),
+            ("auto_block_size", flag(config.auto_block_size)),
+            ("max_io", int(config.max_io as u64)),
+            ("coalesce", config.coalesce.and_then(|size| int(size as u64))),
+            ("max_in_flight", int(config.max_in_flight as u64)),
+            ("rate_limit", config.rate_limit.and_then(int)),
+            ("count", int(config.count)),
+            ("skip", int(config.skip)),
+            ("seek", int(config.seek)),
+            ("count_bytes", config.count_bytes.and_then(int)),
+            ("skip_bytes", int(config.skip_bytes)),
+            ("seek_bytes", int(config.seek_bytes)),
+            ("records", flag(config.records.is_some())),
+            ("record_skip", config.records.and_then(|records| int(records.skip))),
+            ("record_count", config.records.and_then(|records| records.count).and_then(int)),
+            ("truncate", flag(config.truncate)),
+            ("append", flag(config.append)),
+            ("threads", int(config.threads.into())),
+            ("engine", Some(Value::Str(flag_name(config.engine)))),
+            ("low_memory", flag(config.low_memory)),
+            ("fast_path", flag(config.fast_path)),
+            ("direct", flag(config.direct_io())),
+            ("sequential_input", flag(config.fadvise.sequential_input)),
+            ("drop_output_cache", flag(config.fadvise.drop_output_cache)),
+            ("write_if_changed", flag(config.write_if_changed)),
+            ("sparse", flag(config.sparse)),
+            ("discard_zeros", flag(config.discard_zeros)),
+            ("conv", (!config.conversions.is_empty()).then(|| {
+                Value::Str(config.conversions.iter().map(|&conversion| flag_name(conversion)).collect::<Vec<_>>().join(","))
+            })),
+            ("noerror", flag(config.noerror)),
+            ("error_fill", int(config.error_fill.into())),
+            ("max_errors", config.max_errors.and_then(int)),
+            ("retries", int(config.retries.into())),
+            ("retry_delay_ms", int(config.retry_delay.as_millis() as u64)),
+            ("retry_writes", flag(config.retry_writes)),
+            // The ranges list of --priority-ranges is made when the copy starts.
+            ("ranges", config.ranges.clone().filter(|_| config.priority_ranges.is_none()).map(Value::Ranges)),
+            ("priority_auto", flag(config.priority_ranges == Some(PriorityRanges::Auto))),
+            ("priority_ranges", match &config.priority_ranges {
+                Some(PriorityRanges::List(ranges)) => Some(Value::Ranges(ranges.clone())),
+                _ => None,
+            }),
+            ("excluded", Some(Value::Ranges(config.excluded.clone()))),
+            ("check_input_stable", config.input_guard.map(|policy| Value::Str(flag_name(policy)))),
+            ("flush_probe", config.flush_probe.and_then(int)),
+            ("open_timeout", config.open_timeout.and_then(|t| int(t.as_secs()))),
+            ("tcp_framing", flag(config.tcp_framing)),
+            ("check_bootable", flag(config.check_bootable)),
+            ("check_fat", flag(config.check_fat)),
+            ("disable_write_cache", flag(config.disable_write_cache)),
+            ("sync_dir", flag(config.sync_dir)),
+            ("sync_mode", Some(Value::Str(config.sync_mode.to_string()))),
+            ("resume", config.resume.as_deref().and_then(text)),
+            ("monitor_health", flag(config.monitor_health)),
+            ("max_temp", config.max_temp.and_then(|t| int(t.into()))),
+            ("pause_on_battery", flag(config.pause_on_battery)),
+            ("verify", config.verification_algo.map(|algo| Value::Str(flag_name(algo)))),
+            ("verify_also", (!config.verify_also.is_empty()).then(|| {
+                Value::Str(config.verify_also.iter().map(|&algo| flag_name(algo)).collect::<Vec<_>>().join(","))
+            })),
+            ("compress", config.compress.map(|format| Value::Str(flag_name(format)))),
+            ("compress_level", config.compress_level.and_then(|level| int(level.into()))),
+            ("seekable_frame_size", config.seekable_frame_size.and_then(int)),
+            ("decompress", Some(Value::Str(flag_name(config.decompress)))),
+            ("split_size", config.split_size.and_then(int)),
+        ],
+    );
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    section(
+        "result",
+        vec![
+            ("created", int(created)),
+            ("bytes_copied", int(stats.bytes_copied)),
+            ("blocks_copied", int(stats.blocks_copied)),
+            ("data_digest", stats.data_digest.as_ref().map(|d| Value::Str(d.to_string()))),
+        ],
+    );
+
+    format!(
+        "{} Run the same copy again with:\n#   rdd copy --from-manifest <this file>\n\n{}",
+        HEADER, out
+    )
+}
+
+/// Writes the manifest of a finished copy to `path`.
+pub fn write(path: &str, config: &CopyConfig, stats: &CopyStats) -> RddResult<()> {
+    fs::write(path, render(config, stats)).with_path(path)
+}
+
+/// Reads the manifest at `path` and returns a builder with its settings,
+/// expecting the recorded digest. A manifest from another rdd version is
+/// reported to `reporter`.
+pub fn load(path: &str, reporter: &dyn Reporter) -> RddResult<CopyConfigBuilder> {
+    let contents = fs::read_to_string(path).with_path(path)?;
+    let manifest = Manifest::parse(path, &contents)?;
+
+    if let Some(version) = manifest.string("rdd.version")?
+        && version != env!("CARGO_PKG_VERSION")
+    {
+        reporter.diagnostic(
+            &Diagnostic::warning(format!(
+                "the manifest '{}' was written by rdd {}, this is rdd {}; the copy may not behave exactly the same.",
+                path,
+                version,
+                env!("CARGO_PKG_VERSION")
+            ))
+            .with_flag("--from-manifest"),
+        );
+    }
+    manifest.builder()
+}
+
+/// A manifest as `rdd scrub` reads it: the copy it records, and how much
+/// data that copy wrote.
+#[derive(Debug, Clone)]
+pub struct Record {
+    /// The recorded settings, expecting the recorded digest.
+    pub config: CopyConfig,
+    pub bytes_copied: Option<u64>,
+}
+
+/// Reads the manifest at `path` without replaying it.
+pub fn load_record(path: &str) -> RddResult<Record> {
+    let contents = fs::read_to_string(path).with_path(path)?;
+    let manifest = Manifest::parse(path, &contents)?;
+    Ok(Record {
+        config: manifest.builder()?.build()?,
+        bytes_copied: manifest.int("result.bytes_copied")?,
+    })
+}
+
+/// Whether the file at `path` looks like a manifest, judging by its first
+/// line.
+pub fn is_manifest(path: &Path) -> bool {
+    let mut start = [0u8; HEADER.len()];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut start))
+        .is_ok_and(|()| start == HEADER.as_bytes())
+}
+
+/// A parsed manifest: the values by "section.key".
+struct Manifest<'a> {
+    path: &'a str,
+    values: HashMap<String, Value>,
+}
+
+impl<'a> Manifest<'a> {
+    fn parse(path: &'a str, contents: &str) -> RddResult<Self> {
+        let mut values = HashMap::new();
+        let mut section = String::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if is_blank(line) {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim().to_string();
+                continue;
+            }
+            let parsed = line
+                .split_once('=')
+                .and_then(|(key, value)| Some((key.trim(), Value::parse(value.trim())?)));
+            let Some((key, value)) = parsed else {
+                return Err(RddError::Config(format!(
+                    "{}, line {}: expected 'key = value', found '{}'.",
+                    path,
+                    number + 1,
+                    line
+                )));
+            };
+            values.insert(format!("{}.{}", section, key), value);
+        }
+        Ok(Self { path, values })
+    }
+
+    /// Looks up `key`, converting it with `convert`. Fails if the value has
+    /// the wrong type, or if `convert` returns `None`.
+    fn get<T>(&self, key: &str, what: &str, convert: impl Fn(&Value) -> Option<T>) -> RddResult<Option<T>> {
+        let Some(value) = self.values.get(key) else {
+            return Ok(None);
+        };
+        convert(value).map(Some).ok_or_else(|| {
+            RddError::Config(format!(
+                "{}: '{}' should be {}, found a {} ({}).",
+                self.path,
+                key,
+                what,
+                value.name(),
+                value
+            ))
+        })
+    }
+
+    fn string(&self, key: &str) -> RddResult<Option<String>> {
+        self.get(key, "a string", |v| match v {
+            Value::Str(s) => Some(s.clone()),
+            _ => None,
+        })
+    }
+
+    fn int<T: TryFrom<u64>>(&self, key: &str) -> RddResult<Option<T>> {
+        self.get(key, "a (small enough) integer", |v| match v {
+            Value::Int(n) => T::try_from(*n).ok(),
+            _ => None,
+        })
+    }
+
+    fn flag(&self, key: &str) -> RddResult<Option<bool>> {
+        self.get(key, "true or false", |v| match v {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        })
+    }
+
+    fn ranges(&self, key: &str) -> RddResult<Option<Vec<ByteRange>>> {
+        self.get(key, "a list of [offset, length] pairs", |v| match v {
+            Value::Ranges(ranges) => Some(ranges.clone()),
+            _ => None,
+        })
+    }
+
+    /// A string naming one of the values of a command-line enum.
+    fn choice<T: ValueEnum>(&self, key: &str) -> RddResult<Option<T>> {
+        self.get(key, "one of the values the matching option accepts", |v| match v {
+            Value::Str(s) => T::from_str(s, true).ok(),
+            _ => None,
+        })
+    }
+
+    fn required<T>(&self, key: &str, value: Option<T>) -> RddResult<T> {
+        value.ok_or_else(|| RddError::Config(format!("{}: '{}' is missing.", self.path, key)))
+    }
+
+    /// Builds a copy with the recorded settings.
+    fn builder(&self) -> RddResult<CopyConfigBuilder> {
+        let input = self.required("copy.input", self.string("copy.input")?)?;
+        let output = self.required("copy.output", self.string("copy.output")?)?;
+        let block_size = self.required("copy.block_size", self.int("copy.block_size")?)?;
+
+        let mut builder = CopyConfig::builder(input, output).block_size(block_size);
+        if let Some(enabled) = self.flag("copy.auto_block_size")? {
+            builder = builder.auto_block_size(enabled);
+        }
+
+        let defaults = FadviseHints::default();
+        builder = builder.fadvise(FadviseHints {
+            sequential_input: self.flag("copy.sequential_input")?.unwrap_or(defaults.sequential_input),
+            drop_output_cache: self.flag("copy.drop_output_cache")?.unwrap_or(defaults.drop_output_cache),
+        });
+
+        if let Some(max_io) = self.int("copy.max_io")? {
+            builder = builder.max_io(max_io);
+        }
+        if let Some(size) = self.int("copy.coalesce")? {
+            builder = builder.coalesce(size);
+        }
+        if let Some(max_in_flight) = self.int("copy.max_in_flight")? {
+            builder = builder.max_in_flight(max_in_flight);
+        }
+        if let Some(rate) = self.int("copy.rate_limit")? {
+            builder = builder.rate_limit(rate);
+        }
+        if let Some(count) = self.int("copy.count")? {
+            builder = builder.count(count);
+        }
+        if let Some(skip) = self.int("copy.skip")? {
+            builder = builder.skip(skip);
+        }
+        if let Some(seek) = self.int("copy.seek")? {
+            builder = builder.seek(seek);
+        }
+        if let Some(bytes) = self.int("copy.count_bytes")? {
+            builder = builder.count_bytes(bytes);
+        }
+        if let Some(bytes) = self.int("copy.skip_bytes")? {
+            builder = builder.skip_bytes(bytes);
+        }
+        if let Some(bytes) = self.int("copy.seek_bytes")? {
+            builder = builder.seek_bytes(bytes);
+        }
+        // The records of --records were saved apart from the blocks.
+        if self.flag("copy.records")? == Some(true) {
+            builder = builder
+                .records(true)
+                .skip(self.int("copy.record_skip")?.unwrap_or(0))
+                .count(self.int("copy.record_count")?.unwrap_or(0));
+        }
+        if let Some(enabled) = self.flag("copy.truncate")? {
+            builder = builder.truncate(enabled);
+        }
+        if let Some(enabled) = self.flag("copy.append")? {
+            builder = builder.append(enabled);
+        }
+        if let Some(threads) = self.int("copy.threads")? {
+            builder = builder.threads(threads);
+        }
+        if let Some(engine) = self.choice::<Engine>("copy.engine")? {
+            builder = builder.engine(engine);
+        }
+        if let Some(enabled) = self.flag("copy.low_memory")? {
+            builder = builder.low_memory(enabled);
+        }
+        if let Some(enabled) = self.flag("copy.fast_path")? {
+            builder = builder.fast_path(enabled);
+        }
+        if let Some(direct) = self.flag("copy.direct")? {
+            #[cfg(unix)]
+            {
+                builder = builder.direct(direct);
+            }
+            #[cfg(not(unix))]
+            if direct {
+                return Err(RddError::Config(format!(
+                    "{}: the copy used --direct, which isn't available on this platform.",
+                    self.path
+                )));
+            }
+        }
+        if let Some(enabled) = self.flag("copy.write_if_changed")? {
+            builder = builder.write_if_changed(enabled);
+        }
+        if let Some(enabled) = self.flag("copy.sparse")? {
+            builder = builder.sparse(enabled);
+        }
+        if let Some(enabled) = self.flag("copy.discard_zeros")? {
+            builder = builder.discard_zeros(enabled);
+        }
+        if let Some(names) = self.string("copy.conv")? {
+            let conversions = names
+                .split(',')
+                .map(|name| {
+                    Conversion::from_str(name.trim(), true).map_err(|_| {
+                        RddError::Config(format!(
+                            "{}: 'copy.conv' lists \"{}\", which isn't a conversion this build of rdd knows.",
+                            self.path, name
+                        ))
+                    })
+                })
+                .collect::<RddResult<Vec<_>>>()?;
+            builder = builder.conversions(conversions);
+        }
+        if let Some(enabled) = self.flag("copy.noerror")? {
+            builder = builder.noerror(enabled);
+        }
+        if let Some(byte) = self.int("copy.error_fill")? {
+            builder = builder.error_fill(byte);
+        }
+        if let Some(errors) = self.int("copy.max_errors")? {
+            builder = builder.max_errors(errors);
+        }
+        if let Some(retries) = self.int("copy.retries")? {
+            builder = builder.retries(retries);
+        }
+        if let Some(ms) = self.int("copy.retry_delay_ms")? {
+            builder = builder.retry_delay(Duration::from_millis(ms));
+        }
+        if let Some(enabled) = self.flag("copy.retry_writes")? {
+            builder = builder.retry_writes(enabled);
+        }
+        if let Some(ranges) = self.ranges("copy.ranges")? {
+            builder = builder.ranges(ranges);
+        }
+        if let Some(excluded) = self.ranges("copy.excluded")? {
+            builder = builder.exclude_ranges(excluded);
+        }
+        if self.flag("copy.priority_auto")? == Some(true) {
+            builder = builder.priority_ranges(PriorityRanges::Auto);
+        } else if let Some(ranges) = self.ranges("copy.priority_ranges")? {
+            builder = builder.priority_ranges(PriorityRanges::List(ranges));
+        }
+        if let Some(policy) = self.choice::<InputGuard>("copy.check_input_stable")? {
+            builder = builder.input_guard(policy);
+        }
+        if let Some(interval) = self.int("copy.flush_probe")? {
+            builder = builder.flush_probe(interval);
+        }
+        if let Some(secs) = self.int("copy.open_timeout")? {
+            builder = builder.open_timeout(Duration::from_secs(secs));
+        }
+        if let Some(enabled) = self.flag("copy.tcp_framing")? {
+            builder = builder.tcp_framing(enabled);
+        }
+        if let Some(enabled) = self.flag("copy.check_bootable")? {
+            builder = builder.check_bootable(enabled);
+        }
+        if let Some(enabled) = self.flag("copy.check_fat")? {
+            builder = builder.check_fat(enabled);
+        }
+        if let Some(enabled) = self.flag("copy.disable_write_cache")? {
+            builder = builder.disable_write_cache(enabled);
+        }
+        if let Some(enabled) = self.flag("copy.sync_dir")? {
+            builder = builder.sync_dir(enabled);
+        }
+        let sync_mode = self.get("copy.sync_mode", "none, end, interval:BYTES or per-block", |v| match v {
+            Value::Str(s) => SyncMode::parse(s).ok(),
+            _ => None,
+        })?;
+        if let Some(mode) = sync_mode {
+            builder = builder.sync_mode(mode);
+        }
+        if let Some(state_file) = self.string("copy.resume")? {
+            builder = builder.resume(state_file);
+        }
+        if let Some(enabled) = self.flag("copy.monitor_health")? {
+            builder = builder.monitor_health(enabled);
+        }
+        if let Some(celsius) = self.int("copy.max_temp")? {
+            builder = builder.max_temp(celsius);
+        }
+        if let Some(enabled) = self.flag("copy.pause_on_battery")? {
+            builder = builder.pause_on_battery(enabled);
+        }
+        if let Some(algo) = self.choice::<HashAlgorithm>("copy.verify")? {
+            builder = builder.verify(algo);
+        }
+        if let Some(names) = self.string("copy.verify_also")? {
+            for name in names.split(',') {
+                let algo = HashAlgorithm::from_str(name.trim(), true).map_err(|_| {
+                    RddError::Config(format!(
+                        "{}: 'copy.verify_also' lists \"{}\", which isn't a hash algorithm this build of rdd knows.",
+                        self.path, name
+                    ))
+                })?;
+                builder = builder.verify(algo);
+            }
+        }
+        if let Some(format) = self.choice::<Compression>("copy.compress")? {
+            builder = builder.compress(format, self.int("copy.compress_level")?);
+        }
+        if let Some(frame_size) = self.int("copy.seekable_frame_size")? {
+            builder = builder.seekable(frame_size);
+        }
+        if let Some(mode) = self.choice::<Decompression>("copy.decompress")? {
+            builder = builder.decompress(mode);
+        }
+        if let Some(size) = self.int("copy.split_size")? {
+            builder = builder.split_size(size);
+        }
+
+        if let Some(digest) = self.string("result.data_digest")? {
+            let digest = DataDigest::parse(&digest).ok_or_else(|| {
+                RddError::Config(format!(
+                    "{}: 'result.data_digest' should look like \"sha256:<hex digest>\", found \"{}\".",
+                    self.path, digest
+                ))
+            })?;
+            builder = builder.expect_digest(digest);
+        }
+        Ok(builder)
+    }
+}
+
+/// The command-line spelling of an enum value ("warn", "sha256").
+fn flag_name<T: ValueEnum>(value: T) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
+}
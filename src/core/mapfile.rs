@@ -0,0 +1,404 @@
+// This file reads and writes map files in the format of GNU ddrescue. A map file records which parts of a device were read successfully and which still need work. Because we use exactly the same format, users can switch between ddrescue, ddrescueview and rdd in the middle of a recovery: a map written by one tool can be read by the others.
+
+// Explanation of this file:
+// The format: Comment lines start with '#'. The first data line is the status line, "current_pos current_status current_pass". It says where the tool stopped and in which phase. Every other data line is a block, "pos size status". The blocks are sorted and don't overlap.
+// BlockStatus enum: The status characters of a block: '?' non-tried, '*' non-trimmed, '/' non-scraped, '-' bad sector, '+' finished.
+// Phase enum: The current_status character of the status line, which names the phase the tool was in: '?' copying, '*' trimming, '/' scraping, '-' retrying, 'F' filling, 'G' generating, '+' finished.
+// Numbers: Like ddrescue, we read numbers in decimal, hexadecimal (0x prefix) or octal (leading 0). Maps written by ddrescue 1.20 and older have no current_pass field; it then defaults to 1.
+// of_copy : The map of a copy that gave up partway (see --max-errors in copy.rs): read, failed and untried blocks over the spans the copy meant to read, ready to be passed back as --ranges-file or to ddrescue. The spans are taken in the order they were copied, which --priority-ranges changes, and the blocks sorted afterwards.
+// Writing: render() produces the same layout as ddrescue, including its column-header comments and column spacing. Positions and sizes are written with format_offset as "0x" plus at least 8 upper-case hex digits, so every data line is byte-for-byte what ddrescue would write. Only the first header comments differ, since they name rdd instead of ddrescue.
+// format_offset : rdd uses this one helper for every byte offset it shows (e.g. in I/O error messages). An offset from an error can then be found in a map file with a simple text search.
+
+// src/core/mapfile.rs
+
+use crate::core::ranges::ByteRange;
+use crate::error::{IoResultExt, RddError, RddResult};
+use std::cmp::Ordering;
+use std::fs;
+
+/// The status of one block in a map file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockStatus {
+    NonTried,
+    NonTrimmed,
+    NonScraped,
+    BadSector,
+    Finished,
+}
+
+impl BlockStatus {
+    pub fn as_char(self) -> char {
+        match self {
+            BlockStatus::NonTried => '?',
+            BlockStatus::NonTrimmed => '*',
+            BlockStatus::NonScraped => '/',
+            BlockStatus::BadSector => '-',
+            BlockStatus::Finished => '+',
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            '?' => Some(BlockStatus::NonTried),
+            '*' => Some(BlockStatus::NonTrimmed),
+            '/' => Some(BlockStatus::NonScraped),
+            '-' => Some(BlockStatus::BadSector),
+            '+' => Some(BlockStatus::Finished),
+            _ => None,
+        }
+    }
+}
+
+/// The phase recorded in the status line of a map file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Copying,
+    Trimming,
+    Scraping,
+    Retrying,
+    Filling,
+    Generating,
+    Finished,
+}
+
+impl Phase {
+    pub fn as_char(self) -> char {
+        match self {
+            Phase::Copying => '?',
+            Phase::Trimming => '*',
+            Phase::Scraping => '/',
+            Phase::Retrying => '-',
+            Phase::Filling => 'F',
+            Phase::Generating => 'G',
+            Phase::Finished => '+',
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            '?' => Some(Phase::Copying),
+            '*' => Some(Phase::Trimming),
+            '/' => Some(Phase::Scraping),
+            '-' => Some(Phase::Retrying),
+            'F' => Some(Phase::Filling),
+            'G' => Some(Phase::Generating),
+            '+' => Some(Phase::Finished),
+            _ => None,
+        }
+    }
+}
+
+/// One "pos size status" line of a map file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapBlock {
+    pub pos: u64,
+    pub size: u64,
+    pub status: BlockStatus,
+}
+
+impl MapBlock {
+    pub fn range(&self) -> ByteRange {
+        ByteRange {
+            offset: self.pos,
+            length: self.size,
+        }
+    }
+}
+
+/// The contents of a ddrescue map file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapFile {
+    pub current_pos: u64,
+    pub current_status: Phase,
+    pub current_pass: u32,
+    pub blocks: Vec<MapBlock>,
+}
+
+impl MapFile {
+    /// Reads and parses the map file at `path`.
+    pub fn load(path: &str) -> RddResult<Self> {
+        let contents = fs::read_to_string(path).with_path(path)?;
+        Self::parse(path, &contents)
+    }
+
+    /// Parses the contents of a map file. `path` is only used in error messages.
+    pub fn parse(path: &str, contents: &str) -> RddResult<Self> {
+        let mut lines = contents
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.split_whitespace().collect::<Vec<_>>()))
+            .filter(|(_, fields)| !fields.is_empty() && !fields[0].starts_with('#'));
+
+        let Some((line_no, fields)) = lines.next() else {
+            return Err(RddError::Config(format!("{}: map file has no status line", path)));
+        };
+        let (current_pos, current_status, current_pass) = match fields.as_slice() {
+            [pos, status] => (*pos, *status, None),
+            [pos, status, pass] => (*pos, *status, Some(*pass)),
+            _ => {
+                return Err(invalid_line(
+                    path,
+                    line_no,
+                    "expected 'current_pos current_status current_pass'",
+                ));
+            }
+        };
+        let current_pass = match current_pass {
+            Some(pass) => u32::try_from(parse_field(path, line_no, pass)?)
+                .ok()
+                .filter(|&p| p >= 1)
+                .ok_or_else(|| invalid_line(path, line_no, "invalid current_pass"))?,
+            None => 1,
+        };
+        let mut map = MapFile {
+            current_pos: parse_field(path, line_no, current_pos)?,
+            current_status: single_char(current_status)
+                .and_then(Phase::from_char)
+                .ok_or_else(|| invalid_line(path, line_no, "unknown current_status"))?,
+            current_pass,
+            blocks: Vec::new(),
+        };
+
+        for (line_no, fields) in lines {
+            let [pos, size, status] = fields.as_slice() else {
+                return Err(invalid_line(path, line_no, "expected 'pos size status'"));
+            };
+            let block = MapBlock {
+                pos: parse_field(path, line_no, pos)?,
+                size: parse_field(path, line_no, size)?,
+                status: single_char(status)
+                    .and_then(BlockStatus::from_char)
+                    .ok_or_else(|| invalid_line(path, line_no, "unknown block status"))?,
+            };
+            // ddrescue rejects maps with unsorted or overlapping blocks, and
+            // so do we.
+            if let Some(prev) = map.blocks.last()
+                && block.pos < prev.pos + prev.size
+            {
+                return Err(invalid_line(path, line_no, "block overlaps the previous one"));
+            }
+            if block.pos.checked_add(block.size).is_none() {
+                return Err(invalid_line(path, line_no, "block extends past the largest offset"));
+            }
+            map.blocks.push(block);
+        }
+        Ok(map)
+    }
+
+    /// The map of a copy of `spans`, in the order they were copied, that
+    /// stopped at `reached` in the first span holding it. What comes before
+    /// it was read, except for the chunks in `bad`, which failed as a whole,
+    /// like ddrescue's non-trimmed blocks. The rest wasn't tried. The gaps
+    /// between spans weren't part of the copy and count as finished, so a
+    /// run with this map as its --ranges-file reads what's left and nothing
+    /// else.
+    pub fn of_copy(spans: &[ByteRange], reached: u64, bad: &[ByteRange]) -> Self {
+        let mut map = MapFile {
+            current_pos: reached,
+            current_status: Phase::Copying,
+            current_pass: 1,
+            blocks: Vec::new(),
+        };
+        let current = spans
+            .iter()
+            .position(|span| (span.offset..=span.end()).contains(&reached))
+            .or_else(|| spans.iter().position(|span| reached < span.offset))
+            .unwrap_or(spans.len());
+        let mut spans: Vec<_> = spans
+            .iter()
+            .enumerate()
+            .map(|(i, span)| match i.cmp(&current) {
+                Ordering::Less => (*span, span.end()),
+                Ordering::Equal => (*span, reached.clamp(span.offset, span.end())),
+                Ordering::Greater => (*span, span.offset),
+            })
+            .collect();
+        spans.sort_by_key(|(span, _)| span.offset);
+        let mut bad = bad.to_vec();
+        bad.sort_by_key(|region| region.offset);
+
+        let mut at = spans.first().map_or(0, |(span, _)| span.offset);
+        for (span, done) in spans {
+            map.add(at, span.offset, BlockStatus::Finished);
+            let mut pos = span.offset;
+            for region in &bad {
+                let (start, end) = (region.offset.max(pos), region.end().min(done));
+                if start >= end {
+                    continue;
+                }
+                map.add(pos, start, BlockStatus::Finished);
+                map.add(start, end, BlockStatus::NonTrimmed);
+                pos = end;
+            }
+            map.add(pos, done, BlockStatus::Finished);
+            map.add(done, span.end(), BlockStatus::NonTried);
+            at = span.end();
+        }
+        map
+    }
+
+    /// Adds the block [pos, end) with `status`, merging it into the last
+    /// block if that one ends there with the same status.
+    fn add(&mut self, pos: u64, end: u64, status: BlockStatus) {
+        if end <= pos {
+            return;
+        }
+        match self.blocks.last_mut() {
+            Some(last) if last.status == status && last.pos + last.size == pos => last.size += end - pos,
+            _ => self.blocks.push(MapBlock {
+                pos,
+                size: end - pos,
+                status,
+            }),
+        }
+    }
+
+    /// The ranges of all blocks that are not finished yet.
+    pub fn unfinished(&self) -> impl Iterator<Item = ByteRange> + '_ {
+        self.blocks
+            .iter()
+            .filter(|b| b.status != BlockStatus::Finished)
+            .map(MapBlock::range)
+    }
+
+    /// Renders the map in ddrescue's layout. `command_line` is recorded in
+    /// the header comment, as ddrescue does.
+    pub fn render(&self, command_line: &str) -> String {
+        let mut out = format!(
+            "# Mapfile. Created by rdd version {}\n# Command line: {}\n",
+            env!("CARGO_PKG_VERSION"),
+            command_line
+        );
+        out.push_str("# current_pos  current_status  current_pass\n");
+        out.push_str(&format!(
+            "{}     {}               {}\n",
+            format_offset(self.current_pos),
+            self.current_status.as_char(),
+            self.current_pass
+        ));
+        out.push_str("#      pos        size  status\n");
+        for block in &self.blocks {
+            out.push_str(&format!(
+                "{}  {}  {}\n",
+                format_offset(block.pos),
+                format_offset(block.size),
+                block.status.as_char()
+            ));
+        }
+        out
+    }
+
+    /// Writes the map to `path`. The new contents go to a temporary file
+    /// first, which is then renamed over `path`, so an interrupted write
+    /// never leaves a truncated map behind.
+    pub fn write(&self, path: &str, command_line: &str) -> RddResult<()> {
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, self.render(command_line)).with_path(&tmp)?;
+        fs::rename(&tmp, path).with_path(path)
+    }
+}
+
+/// Formats a byte offset or size the way ddrescue does: "0x" followed by at
+/// least 8 upper-case hex digits.
+pub fn format_offset(value: u64) -> String {
+    format!("0x{:08X}", value)
+}
+
+/// Parses a number the way ddrescue does: decimal, `0x` hexadecimal or
+/// octal with a leading zero.
+pub fn parse_offset(s: &str) -> Option<u64> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else if s.len() > 1
+        && let Some(octal) = s.strip_prefix('0')
+    {
+        u64::from_str_radix(octal, 8).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    let c = chars.next()?;
+    chars.next().is_none().then_some(c)
+}
+
+fn parse_field(path: &str, line_no: usize, s: &str) -> RddResult<u64> {
+    parse_offset(s).ok_or_else(|| invalid_line(path, line_no, &format!("invalid number '{}'", s)))
+}
+
+fn invalid_line(path: &str, line_no: usize, reason: &str) -> RddError {
+    RddError::Config(format!("{}:{}: {}", path, line_no, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A map as GNU ddrescue 1.27 writes it.
+    const DDRESCUE_MAP: &str = "\
+# Mapfile. Created by GNU ddrescue version 1.27
+# Command line: ddrescue -d /dev/sdb disk.img disk.map
+# Start time:   2024-03-05 10:11:12
+# Current time: 2024-03-05 10:20:40
+# Scraping failed blocks... (forwards)
+# current_pos  current_status  current_pass
+0x0A230000     /               1
+#      pos        size  status
+0x00000000  0x0A200000  +
+0x0A200000  0x00010000  *
+0x0A210000  0x00020000  /
+0x0A230000  0x00000200  -
+0x0A230200  0x1F5CFE00  ?
+0x29800000  0x1A5E00000  +
+";
+
+    /// The map from its column headers on: what render() writes exactly
+    /// like ddrescue, after the comments naming the tool.
+    fn body(map: &str) -> &str {
+        &map[map.find("# current_pos").unwrap()..]
+    }
+
+    #[test]
+    fn a_ddrescue_map_round_trips() {
+        let map = MapFile::parse("disk.map", DDRESCUE_MAP).unwrap();
+        assert_eq!(map.current_pos, 0x0A23_0000);
+        assert_eq!(map.current_status, Phase::Scraping);
+        assert_eq!(map.current_pass, 1);
+        let statuses: String = map.blocks.iter().map(|b| b.status.as_char()).collect();
+        assert_eq!(statuses, "+*/-?+");
+        assert_eq!(map.blocks[5].size, 0x1_A5E0_0000);
+
+        let written = map.render("rdd copy /dev/sdb disk.img");
+        assert_eq!(body(&written), body(DDRESCUE_MAP));
+        assert_eq!(MapFile::parse("disk.map", &written).unwrap(), map);
+    }
+
+    #[test]
+    fn reads_old_maps_and_every_number_base() {
+        let map = MapFile::parse("old.map", "# old\n1024 +\n0 0x200 +\n512 01000 -\n").unwrap();
+        assert_eq!(map.current_pass, 1);
+        assert_eq!(map.current_status, Phase::Finished);
+        assert_eq!(map.blocks[1], MapBlock { pos: 512, size: 512, status: BlockStatus::BadSector });
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for contents in [
+            "# only comments\n",
+            "0x0 +\n0x0 0x200\n",
+            "0x0 x\n",
+            "0x0 + 0\n",
+            "0x0 +\n0x0 0x200 x\n",
+            "0x0 +\n0x0 0x200 ++\n",
+            "0x0 +\n0xZZ 0x200 +\n",
+            "0x0 +\n0x0 0x200 + extra\n",
+            "0x0 +\n0x100 0x200 +\n0x200 0x200 -\n",
+            "0x0 +\n0x200 0x0 +\n0x100 0x10 +\n",
+            "0x0 +\n0xFFFFFFFFFFFFFF00 0x200 +\n",
+        ] {
+            assert!(MapFile::parse("bad.map", contents).is_err(), "accepted {:?}", contents);
+        }
+    }
+}
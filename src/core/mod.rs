@@ -1,3 +1,54 @@
 // src/core/mod.rs
+pub mod archive;
+pub mod autotune;
+pub mod bandwidth;
+pub mod benchmark;
+pub mod blockhash;
+pub mod bootcheck;
+pub mod capacity;
+pub mod checkpoint;
+pub mod coalesce;
+pub mod compress;
 pub mod copy;
-pub mod hasher;
+pub mod custody;
+pub mod dashboard;
+pub mod devices;
+pub mod discard;
+pub mod engine;
+pub mod extract;
+pub mod fatcheck;
+pub mod fastpath;
+pub mod flushprobe;
+pub mod follow;
+pub mod guard;
+pub mod hash;
+pub mod limits;
+pub mod health;
+pub mod http;
+pub mod manifest;
+pub mod mapfile;
+pub mod network;
+pub mod open;
+pub mod parallel;
+pub mod power;
+pub mod priority;
+pub mod prng;
+pub mod ranges;
+pub mod records;
+pub mod remote;
+pub mod reporter;
+pub mod retry;
+pub mod safety;
+pub mod scheduler;
+pub mod scrub;
+pub mod seekable;
+pub mod simulate;
+pub mod split;
+pub mod statsjson;
+pub mod syncmode;
+pub mod throttle;
+pub mod transform;
+pub mod verify;
+pub mod wear;
+pub mod wipe;
+pub mod writecache;
@@ -0,0 +1,351 @@
+// This file implements tcp:// inputs and outputs, so a machine can be imaged over the network without `dd | nc` on one end and `nc | dd` on the other. One rdd sends (its output is tcp://...), the other receives (its input is tcp://...), and each end gets rdd's progress, throughput and summary; the data can be compressed on the way with --compress and --decompress, like a pipe.
+
+// Explanation of this file:
+// Addresses : tcp://HOST:PORT connects to HOST, and tcp://:PORT listens on every interface for the other end to connect (tcp://ADDRESS:PORT?listen on one). Either end may listen; usually it's the receiver, started first. Listening waits until the other end connects, or --open-timeout runs out, and Ctrl-C stops the wait.
+// Pipes as files : Like the compressor (see compress.rs), the connection is a pipe to or from a thread: the copy engines read and write a File, and the thread moves the data between the pipe and the socket. So a tcp:// end is a stream, with the restrictions of stdin and stdout (see config.rs).
+// Framing : By default the bytes go over the connection as they are, so either end can be netcat. With --tcp-framing (on both ends), they go in frames of a 4-byte big-endian length and the data, and an empty frame marks the end. A receiver then tells a complete transfer from a connection that dropped halfway, which plain TCP can't: without framing, a dropped connection looks like the end of the data.
+// Throughput : The copy's own summary counts the data the engines moved; the connection reports what went over the wire, which is less with --compress, and how fast.
+// Closing : When the data is sent, the sender closes its half of the connection and waits for the receiver to close too, so the copy is only reported done once the other end has everything. A receiver that stops reading early (e.g. --count) just closes the connection.
+// Errors : A sender's copy only sees a broken pipe when the connection fails, so the sending thread reports what went wrong itself. A receiver's failure just ends the pipe, and finish() returns the error, which fails the copy.
+
+// src/core/network.rs
+
+use crate::config::CopyConfig;
+use crate::core::open::pipe_file;
+use crate::core::reporter::Reporter;
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::diagnostics::Diagnostic;
+use crate::utils::signals;
+use indicatif::{BinaryBytes, ProgressBar};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How network inputs and outputs are written.
+pub const TCP_SCHEME: &str = "tcp://";
+/// How much is moved between the pipe and the socket at once.
+const BUFFER_SIZE: usize = 1 << 20;
+/// How often a listener checks for Ctrl-C while it waits.
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+
+/// Whether `path` is a tcp:// address.
+pub fn is_tcp(path: &str) -> bool {
+    path.starts_with(TCP_SCHEME)
+}
+
+/// Which end of the connection a tcp:// address makes us.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// Wait for the other end on this address.
+    Listen(String),
+    /// Connect to the other end at this address.
+    Connect(String),
+}
+
+impl Endpoint {
+    pub fn parse(uri: &str) -> RddResult<Self> {
+        let invalid = || {
+            RddError::Config(format!(
+                "'{}' isn't a network address rdd understands; use tcp://HOST:PORT to connect, or tcp://:PORT to listen.",
+                uri
+            ))
+        };
+        let rest = uri.strip_prefix(TCP_SCHEME).ok_or_else(invalid)?;
+        let (address, listen) = match rest.strip_suffix("?listen") {
+            Some(address) => (address, true),
+            None => (rest, false),
+        };
+        let (host, port) = address.rsplit_once(':').ok_or_else(invalid)?;
+        if !port.parse::<u16>().is_ok_and(|port| port > 0) {
+            return Err(invalid());
+        }
+        Ok(match (host.is_empty(), listen) {
+            (true, _) => Endpoint::Listen(format!("0.0.0.0:{}", port)),
+            (false, true) => Endpoint::Listen(address.to_string()),
+            (false, false) => Endpoint::Connect(address.to_string()),
+        })
+    }
+}
+
+/// A connection a copy reads from or writes to, through a pipe.
+pub struct Connection {
+    thread: JoinHandle<RddResult<(u64, Option<io::PipeReader>)>>,
+    socket: TcpStream,
+    peer: String,
+    /// A receiver's flag that the copy stopped reading; None for a sender.
+    closed: Option<Arc<AtomicBool>>,
+    connected: Instant,
+    reporter: Arc<dyn Reporter>,
+}
+
+impl Connection {
+    /// Connects the input of `config`. Returns the connection with the
+    /// pipe to read the data from.
+    pub fn receive(config: &CopyConfig) -> RddResult<(Self, File)> {
+        let uri = config.input_file.as_str();
+        let (socket, peer) = open(config, uri)?;
+        let (reader, writer) = io::pipe().with_path(uri)?;
+        let pipe = pipe_file(reader).with_path(uri)?;
+        let (from, framed, thread_uri) = (socket.try_clone().with_path(uri)?, config.tcp_framing, uri.to_string());
+        let closed = Arc::new(AtomicBool::new(false));
+        let thread_closed = closed.clone();
+        // A failed transfer ends the pipe early, so the copy gets to finish()
+        // and its error.
+        let thread = thread::Builder::new()
+            .name("rdd-receive".to_string())
+            .spawn(move || receive(from, writer, framed, &thread_uri, &thread_closed).map(|received| (received, None)))
+            .with_path(uri)?;
+        Ok((Self::new(config, thread, socket, peer, Some(closed)), pipe))
+    }
+
+    /// Connects the output of `config`. Returns the connection with the
+    /// pipe to write the data into.
+    pub fn send(config: &CopyConfig) -> RddResult<(Self, File)> {
+        let uri = config.output_file.as_str();
+        let (socket, peer) = open(config, uri)?;
+        let (reader, writer) = io::pipe().with_path(uri)?;
+        let pipe = pipe_file(writer).with_path(uri)?;
+        let (to, framed, thread_uri) = (socket.try_clone().with_path(uri)?, config.tcp_framing, uri.to_string());
+        let reporter = config.reporter.clone();
+        let thread = thread::Builder::new()
+            .name("rdd-send".to_string())
+            .spawn(move || {
+                let mut reader = reader;
+                match send(&mut reader, to, framed, &thread_uri) {
+                    Ok(sent) => Ok((sent, Some(reader))),
+                    // The copy only sees a broken pipe once the pipe is
+                    // closed, so say what really happened first.
+                    Err(e) => {
+                        reporter.diagnostic(&Diagnostic::error(e.to_string()));
+                        Err(e)
+                    }
+                }
+            })
+            .with_path(uri)?;
+        Ok((Self::new(config, thread, socket, peer, None), pipe))
+    }
+
+    fn new(
+        config: &CopyConfig,
+        thread: JoinHandle<RddResult<(u64, Option<io::PipeReader>)>>,
+        socket: TcpStream,
+        peer: String,
+        closed: Option<Arc<AtomicBool>>,
+    ) -> Self {
+        Connection {
+            thread,
+            socket,
+            peer,
+            closed,
+            connected: Instant::now(),
+            reporter: config.reporter.clone(),
+        }
+    }
+
+    /// Waits for the thread once the copy's end of the pipe is closed. A
+    /// sender returns the end of the pipe it read, which is what the copy
+    /// then treats as its output.
+    pub fn finish(self) -> RddResult<Option<File>> {
+        // A receiver may still wait for data the copy doesn't want (e.g.
+        // with --count); closing the connection ends the wait.
+        if let Some(closed) = &self.closed {
+            closed.store(true, Ordering::Relaxed);
+            let _ = self.socket.shutdown(Shutdown::Both);
+        }
+        let sending = self.closed.is_none();
+        let result = self
+            .thread
+            .join()
+            .map_err(|_| RddError::Channel("the network thread panicked".to_string()))?;
+        let (bytes, reader) = match result {
+            // The copy stopped reading while the thread wrote to the pipe.
+            Err(RddError::IoAt { source, .. }) if source.kind() == io::ErrorKind::BrokenPipe && !sending => {
+                return Ok(None);
+            }
+            result => result?,
+        };
+        let secs = self.connected.elapsed().as_secs_f64();
+        let rate = if secs > 0.0 { (bytes as f64 / secs) as u64 } else { bytes };
+        self.reporter.message(&format!(
+            "{} {} ({} bytes) {} {} in {:.2}s ({}/s).",
+            if sending { "Sent" } else { "Received" },
+            BinaryBytes(bytes),
+            bytes,
+            if sending { "to" } else { "from" },
+            self.peer,
+            secs,
+            BinaryBytes(rate)
+        ));
+        reader.map(pipe_file).transpose().with_path(&self.peer)
+    }
+}
+
+/// Connects to the other end for `uri`, or waits for it to connect.
+/// Returns the connection and the other end's address.
+fn open(config: &CopyConfig, uri: &str) -> RddResult<(TcpStream, String)> {
+    let socket = match Endpoint::parse(uri)? {
+        Endpoint::Connect(address) => {
+            let connected = match config.open_timeout {
+                Some(timeout) => address
+                    .to_socket_addrs()
+                    .and_then(|addrs| {
+                        let mut last = io::Error::new(io::ErrorKind::NotFound, "the host has no address");
+                        for addr in addrs {
+                            match TcpStream::connect_timeout(&addr, timeout) {
+                                Ok(socket) => return Ok(socket),
+                                Err(e) => last = e,
+                            }
+                        }
+                        Err(last)
+                    }),
+                None => TcpStream::connect(&address),
+            };
+            connected.map_err(|e| match e.kind() {
+                io::ErrorKind::ConnectionRefused => RddError::Config(format!(
+                    "Nothing is listening at '{}'. Start the other end first, e.g. 'rdd copy -i tcp://:PORT -o ...'.",
+                    uri
+                )),
+                _ => RddError::IoAt {
+                    path: uri.to_string(),
+                    offset: None,
+                    source: e,
+                },
+            })?
+        }
+        Endpoint::Listen(address) => accept(config, uri, &address)?,
+    };
+    let peer = socket.peer_addr().map_or_else(|_| uri.to_string(), |addr| addr.to_string());
+    Ok((socket, peer))
+}
+
+/// Listens on `address` until the other end connects.
+fn accept(config: &CopyConfig, uri: &str, address: &str) -> RddResult<TcpStream> {
+    let listener = TcpListener::bind(address).with_path(uri)?;
+    listener.set_nonblocking(true).with_path(uri)?;
+    let local = listener.local_addr().map_or_else(|_| address.to_string(), |addr| addr.to_string());
+    config.reporter.message(&format!("Waiting for the other end to connect on {}.", local));
+    let started = Instant::now();
+    let progress = ProgressBar::hidden();
+    loop {
+        signals::check(&*config.reporter, &progress)?;
+        match listener.accept() {
+            Ok((socket, peer)) => {
+                socket.set_nonblocking(false).with_path(uri)?;
+                config.reporter.message(&format!("Connected to {}.", peer));
+                return Ok(socket);
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e).with_path(uri),
+        }
+        if let Some(timeout) = config.open_timeout
+            && started.elapsed() >= timeout
+        {
+            return Err(RddError::Config(format!(
+                "Nobody connected to {} within {} seconds (--open-timeout).",
+                local,
+                timeout.as_secs()
+            )));
+        }
+        thread::sleep(ACCEPT_POLL);
+    }
+}
+
+/// The receiving thread: copies what arrives on `socket` into `pipe`, until
+/// the data ends or `closed` says the copy stopped reading. Returns the
+/// number of bytes received.
+fn receive(mut socket: TcpStream, mut pipe: io::PipeWriter, framed: bool, uri: &str, closed: &AtomicBool) -> RddResult<u64> {
+    let mut received = 0u64;
+    match receive_into(&mut socket, &mut pipe, framed, uri, &mut received) {
+        // Closing the connection under the thread fails its read.
+        Err(_) if closed.load(Ordering::Relaxed) => Ok(received),
+        result => result.map(|()| received),
+    }
+}
+
+fn receive_into(
+    socket: &mut TcpStream,
+    pipe: &mut io::PipeWriter,
+    framed: bool,
+    uri: &str,
+    received: &mut u64,
+) -> RddResult<()> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    loop {
+        if !framed {
+            let n = read_some(socket, &mut buffer).with_path(uri)?;
+            if n == 0 {
+                return Ok(());
+            }
+            pipe.write_all(&buffer[..n]).at_offset(uri, *received)?;
+            *received += n as u64;
+            continue;
+        }
+        let mut header = [0u8; 4];
+        socket.read_exact(&mut header).map_err(|e| incomplete(e, uri, *received))?;
+        let mut left = u32::from_be_bytes(header) as usize;
+        if left == 0 {
+            return Ok(());
+        }
+        while left > 0 {
+            let want = left.min(buffer.len());
+            let n = match read_some(socket, &mut buffer[..want]) {
+                Ok(0) => return Err(incomplete(io::ErrorKind::UnexpectedEof.into(), uri, *received)),
+                result => result.map_err(|e| incomplete(e, uri, *received))?,
+            };
+            pipe.write_all(&buffer[..n]).at_offset(uri, *received)?;
+            *received += n as u64;
+            left -= n;
+        }
+    }
+}
+
+/// The error for a framed connection that ended before its end marker.
+fn incomplete(e: io::Error, uri: &str, received: u64) -> RddError {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => RddError::ConnectionLost {
+            path: uri.to_string(),
+            received,
+        },
+        _ => RddError::IoAt {
+            path: uri.to_string(),
+            offset: Some(received),
+            source: e,
+        },
+    }
+}
+
+/// The sending thread: copies what comes out of `pipe` to `socket`, then
+/// waits for the other end to close. Returns the number of bytes sent.
+fn send(pipe: &mut io::PipeReader, mut socket: TcpStream, framed: bool, uri: &str) -> RddResult<u64> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut sent = 0u64;
+    loop {
+        let n = read_some(pipe, &mut buffer).with_path(uri)?;
+        if framed {
+            socket.write_all(&(n as u32).to_be_bytes()).at_offset(uri, sent)?;
+        }
+        if n == 0 {
+            break;
+        }
+        socket.write_all(&buffer[..n]).at_offset(uri, sent)?;
+        sent += n as u64;
+    }
+    socket.shutdown(Shutdown::Write).with_path(uri)?;
+    // The other end closes once it has everything.
+    while read_some(&mut socket, &mut buffer).with_path(uri)? > 0 {}
+    Ok(sent)
+}
+
+/// Reads into `buf`, retrying when a signal interrupts the read.
+fn read_some(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    loop {
+        match reader.read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    }
+}
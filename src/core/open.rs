@@ -0,0 +1,114 @@
+// This file opens files and devices with an optional timeout. Opening a device node that belongs to a dying disk can block inside the kernel for minutes, or forever. Without a timeout rdd would just sit there, looking frozen. With one, the user gets a clear error and advice.
+
+// Explanation of this file:
+// open_with_timeout : Without a timeout, this is a plain OpenOptions::open. With one, the open runs on a helper thread and we wait for its result on a channel with recv_timeout.
+// What happens on timeout: We return RddError::Timeout straight away. The helper thread is left behind; it can't be cancelled while it's stuck in the kernel. If the open finally succeeds later, the thread simply drops the file again.
+// open_stdin / open_stdout : An input or output of "-" means stdin or stdout. We duplicate the descriptor (or handle, on Windows) and wrap it in a File. The copy code can then read, write, stat and sync it like any other file.
+// pipe_file : The same for one end of a pipe, e.g. to a compressor (see compress.rs) or the splitter (see split.rs).
+// Why a thread and not a non-blocking open: O_NONBLOCK doesn't make opening a block device non-blocking, and isn't available on every platform. A thread works everywhere.
+
+// src/core/open.rs
+
+use crate::error::{IoResultExt, RddError, RddResult};
+use crossbeam_channel::{RecvTimeoutError, bounded};
+use std::fs::{File, OpenOptions};
+use std::thread;
+use std::time::Duration;
+
+/// Returns a `File` for rdd's stdin. It's a duplicate of the descriptor, so
+/// the copy reads the raw stream and the rest of rdd can treat it like any
+/// other input file.
+pub fn open_stdin() -> RddResult<File> {
+    platform::duplicate(&std::io::stdin()).with_path("<stdin>")
+}
+
+/// Returns a `File` for rdd's stdout. Writes through it go straight to the
+/// descriptor, without Rust's line buffering in between.
+pub fn open_stdout() -> RddResult<File> {
+    platform::duplicate(&std::io::stdout()).with_path("<stdout>")
+}
+
+pub use platform::pipe_file;
+
+/// Opens `path` with `options`, giving up after `timeout` if one is set.
+pub fn open_with_timeout(options: &OpenOptions, path: &str, timeout: Option<Duration>) -> RddResult<File> {
+    let Some(timeout) = timeout else {
+        return options.open(path).with_path(path);
+    };
+
+    let (sender, receiver) = bounded(1);
+    let thread_options = options.clone();
+    let thread_path = path.to_string();
+    thread::Builder::new()
+        .name("rdd-open".to_string())
+        .spawn(move || {
+            // If we've already given up, nobody is listening anymore and the
+            // file is simply closed again.
+            let _ = sender.send(thread_options.open(&thread_path));
+        })
+        .with_path(path)?;
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result.with_path(path),
+        Err(RecvTimeoutError::Timeout) => Err(RddError::Timeout {
+            operation: "opening".to_string(),
+            path: path.to_string(),
+            after: timeout,
+        }),
+        Err(RecvTimeoutError::Disconnected) => Err(RddError::Channel(
+            "open helper thread exited without a result".to_string(),
+        )),
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::{AsFd, OwnedFd};
+
+    pub fn duplicate(stream: &impl AsFd) -> io::Result<File> {
+        Ok(File::from(stream.as_fd().try_clone_to_owned()?))
+    }
+
+    /// A `File` for one end of a pipe.
+    pub fn pipe_file(pipe: impl Into<OwnedFd>) -> io::Result<File> {
+        Ok(File::from(pipe.into()))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::fs::File;
+    use std::io;
+    use std::os::windows::io::{AsHandle, OwnedHandle};
+
+    pub fn duplicate(stream: &impl AsHandle) -> io::Result<File> {
+        Ok(File::from(stream.as_handle().try_clone_to_owned()?))
+    }
+
+    /// A `File` for one end of a pipe.
+    pub fn pipe_file(pipe: impl Into<OwnedHandle>) -> io::Result<File> {
+        Ok(File::from(pipe.into()))
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use std::fs::File;
+    use std::io;
+
+    pub fn duplicate<T>(_stream: &T) -> io::Result<File> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "stdin and stdout can't be used for copies on this platform",
+        ))
+    }
+
+    pub fn pipe_file<T>(_pipe: T) -> io::Result<File> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "pipes can't be used for copies on this platform",
+        ))
+    }
+}
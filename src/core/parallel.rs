@@ -0,0 +1,488 @@
+// This file implements the multithreaded copy used when --threads is 2 or more. In the single-threaded copy, reading and writing take turns: the disk we read from sits idle while we write, and the other way round. Here one thread reads while the others write, so both devices can stay busy at the same time.
+
+// Explanation of this file:
+// The pipeline: A reader thread fills buffers from the input and sends them over a bounded channel to the writer threads. Writers put each buffer at the right output offset, then hand it back to the reader over a second channel. Buffers are recycled in this way, so memory use is fixed: at most BUFFERS_PER_WRITER buffers per writer thread (just one with --low-memory), and no more than fit in --max-in-flight. The reader only allocates another buffer when none has come back yet, so a copy whose writers keep up uses just a few.
+// Back-pressure : The reader reserves room for every chunk with the scheduler before sending it, and the writers release it once the chunk is written (see scheduler.rs). When the output is slower than the input, the reader paces its reads to the writers' rate instead of racing ahead.
+// Threads: --threads N uses one reader and N - 1 writers. One writer is enough to overlap reads and writes. More writers help on devices that handle several requests at once, like NVMe drives.
+// Chunks: Like the single-threaded copy, each logical block is moved in chunks of at most --max-io bytes. The reader follows dd's rules: a block ends when it's full, or early after a short read. It also applies skip, seek and count.
+// Positional writes: Every chunk carries its output offset. A single writer receives chunks in order and writes them one after another, so the output can even be a pipe. That's why stdout always gets a single writer. Several writers write at explicit offsets (pwrite on Unix, seek_write on Windows) and may finish out of order.
+// Errors and stopping: If a writer fails, it raises the shared `failed` flag, and the reader stops at its next chunk. If the reader fails, its channel closes and the writers exit once the queue is drained. The first error is reported.
+// Not supported: The options that need to look at the output before writing (write-if-changed), or that move back and forth in the files (ranges, excluded ranges, flush probes), use the single-threaded copy. build() rejects them together with --threads.
+// Drive health : With --monitor-health, the reader polls the drive sensors at the end of each block. While it waits for a hot drive to cool down, it sends nothing, so the writers go idle as well.
+// Simulation : With --simulate-device, the reader reads through the device model, like the single-threaded copy (see simulate.rs).
+// Read errors : With --noerror, the reader fills a chunk it can't read and moves on, just like the single-threaded copy (skip_unreadable in copy.rs). The writers never know the difference.
+// Retries : The reader retries failed reads with --retries, and the writers failed writes with --retry-writes (see retry.rs). Each writer keeps its own list of retried writes, which are added to the reader's retried reads at the end.
+// Rate limit : The reader applies --rate-limit (see throttle.rs). Writers only get what the reader sends, so this limits the whole copy.
+// Conversions : The reader also converts every chunk with --conv (see transform.rs), before it's hashed and sent.
+// Syncing : With --sync-mode interval or per-block, the writers share one Syncer (see syncmode.rs), which counts what all of them wrote and tells the writer that crosses the interval to sync.
+// Signals : The reader checks for SIGUSR1 and Ctrl-C before every chunk (see utils/signals.rs). When it stops, the writers finish the chunks already sent, and the output is synced before the error is returned.
+// Power : The reader also watches for battery power and system sleep (see power.rs), and retries a read that fails right after the system woke up.
+// Verification : With --verify, the reader hashes each chunk before sending it, so the digest follows the input order no matter which writer handles the chunk.
+// After the copy: The final sync, verification, input check, boot check and summary are shared with the single-threaded copy (finish_copy in copy.rs), and so is the custody log around the whole copy.
+
+// src/core/parallel.rs
+
+use crate::config::CopyConfig;
+use crate::core::bandwidth::{BandwidthLog, Meter};
+use crate::core::copy::{
+    direct_alignment, finish_copy, open_files, read_input, relax_direct_for, skip_input,
+    skip_unreadable, sync_output, take_input_snapshot, CopyStats, CopyTimer,
+};
+use crate::core::custody;
+use crate::core::hash::CopyVerifier;
+use crate::core::health::HealthMonitor;
+use crate::core::power::PowerMonitor;
+use crate::core::reporter::progress_bar;
+use crate::core::retry::{self, IoOperation, RetriedIo};
+use crate::core::scheduler::Scheduler;
+use crate::core::simulate::SimulatedDevice;
+use crate::core::syncmode::Syncer;
+use crate::core::throttle::Throttle;
+use crate::core::transform::Transform;
+use crate::core::writecache::WriteCacheGuard;
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::direct::AlignedBuffer;
+use crate::utils::fadvise::advise_dont_need;
+use crate::utils::signals;
+use crossbeam_channel::{bounded, Receiver, Sender, TryRecvError};
+use indicatif::ProgressBar;
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+/// How many buffers are in flight per writer thread.
+const BUFFERS_PER_WRITER: usize = 4;
+
+/// How many with --low-memory.
+const LOW_MEMORY_BUFFERS_PER_WRITER: usize = 1;
+
+/// A chunk of input data on its way to the output.
+struct Chunk {
+    /// Where in the output this chunk belongs.
+    offset: u64,
+    buffer: AlignedBuffer,
+    len: usize,
+}
+
+/// The buffers of the copy, allocated as the reader needs them.
+struct BufferPool {
+    /// Buffers the writers are done with.
+    free: Receiver<AlignedBuffer>,
+    allocated: usize,
+    limit: usize,
+    buffer_size: usize,
+}
+
+impl BufferPool {
+    /// A buffer to read into: one that came back, a new one while fewer than
+    /// `limit` exist, or else the next one to come back, which counts as
+    /// waiting for the writers. None once all writers are gone.
+    fn take(&mut self, scheduler: &Scheduler) -> Option<AlignedBuffer> {
+        match self.free.try_recv() {
+            Ok(buffer) => Some(buffer),
+            Err(TryRecvError::Empty) if self.allocated < self.limit => {
+                self.allocated += 1;
+                Some(AlignedBuffer::new(self.buffer_size))
+            }
+            Err(_) => scheduler.wait_for(|| self.free.recv().ok()),
+        }
+    }
+}
+
+/// What the reader reads from: the input, and the meter it counts the
+/// reads in.
+struct Source<'a> {
+    file: &'a mut File,
+    meter: &'a Meter,
+}
+
+/// What the writers write to: the output, its sector size while it
+/// bypasses the page cache (--direct), and the syncer and meter they share.
+struct Target<'a> {
+    file: &'a File,
+    direct_align: Option<usize>,
+    syncer: Syncer,
+    meter: &'a Meter,
+}
+
+/// How a writer hands a written chunk back.
+struct Recycler<'a> {
+    free: Sender<AlignedBuffer>,
+    scheduler: &'a Scheduler,
+}
+
+impl Recycler<'_> {
+    /// Releases the chunk's bytes and returns its buffer to the pool.
+    fn recycle(&self, chunk: Chunk) {
+        self.scheduler.release(chunk.len as u64);
+        // The reader may already be done and gone; that's fine.
+        let _ = self.free.send(chunk.buffer);
+    }
+}
+
+/// How many writer threads the copy has. Writing to stdout or a compressor
+/// means writing to a stream, which only one writer can do, in order.
+pub(crate) fn writer_count(config: &CopyConfig) -> usize {
+    match config.output_is_stream() {
+        true => 1,
+        false => usize::from(config.threads.saturating_sub(1)).max(1),
+    }
+}
+
+/// How many chunk buffers the copy may allocate: BUFFERS_PER_WRITER per
+/// writer, but no more than fit in --max-in-flight.
+pub(crate) fn buffer_count(config: &CopyConfig) -> usize {
+    let per_writer = match config.low_memory {
+        true => LOW_MEMORY_BUFFERS_PER_WRITER,
+        false => BUFFERS_PER_WRITER,
+    };
+    let chunk_size = config.block_size.min(config.max_io);
+    (writer_count(config) * per_writer).min(config.max_in_flight / chunk_size).max(1)
+}
+
+/// Runs the copy with one reader thread and `config.threads - 1` writer threads.
+pub fn run_parallel_copy(config: &CopyConfig) -> RddResult<CopyStats> {
+    custody::logged(config, || copy_parallel(config))
+}
+
+fn copy_parallel(config: &CopyConfig) -> RddResult<CopyStats> {
+    let input_snapshot = take_input_snapshot(config)?;
+
+    let (mut input_file, output_file, filters) = open_files(config)?;
+
+    // Keep the target's write cache off until we return, synced or not.
+    let _write_cache = match config.disable_write_cache {
+        true => Some(WriteCacheGuard::disable(config)?),
+        false => None,
+    };
+
+    let timer = CopyTimer::start();
+
+    let progress = progress_bar(&*config.reporter, filters.expected_bytes(config, &input_file), config.show_progress);
+
+    let writers = writer_count(config);
+    let chunk_size = config.block_size.min(config.max_io);
+    let direct_align = direct_alignment(config, &output_file)?;
+
+    // Filled chunks go from the reader to the writers; empty buffers come back.
+    let buffers = buffer_count(config);
+    let (filled_tx, filled_rx) = bounded::<Chunk>(buffers);
+    let (free_tx, free_rx) = bounded::<AlignedBuffer>(buffers);
+    let pool = BufferPool {
+        free: free_rx,
+        allocated: 0,
+        limit: buffers,
+        buffer_size: chunk_size,
+    };
+
+    let failed = AtomicBool::new(false);
+    // The buffers may hold less than --max-in-flight, and then they're the
+    // limit the reader is paced against.
+    let scheduler = Scheduler::new(config.max_in_flight.min(buffers * chunk_size) as u64);
+    let bandwidth = BandwidthLog::start(config)?;
+    let meter = bandwidth.meter();
+    // One count for all writers, so --sync-mode interval syncs as often
+    // however many there are.
+    let target = Target {
+        file: &output_file,
+        direct_align,
+        syncer: Syncer::for_copy(config),
+        meter: &meter,
+    };
+
+    let (read_result, write_results) = thread::scope(|scope| {
+        let handles: Vec<_> = (0..writers)
+            .map(|_| {
+                let filled_rx = filled_rx.clone();
+                let recycler = Recycler {
+                    free: free_tx.clone(),
+                    scheduler: &scheduler,
+                };
+                let (target, progress, failed, scheduler) = (&target, &progress, &failed, &scheduler);
+                scope.spawn(move || {
+                    let result = write_chunks(config, target, writers == 1, filled_rx, recycler, progress);
+                    if result.is_err() {
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                    // Don't leave the reader waiting for room this writer
+                    // won't make anymore; it only stops early when it failed.
+                    scheduler.close();
+                    result
+                })
+            })
+            .collect();
+
+        // Only the reader and the writers may hold channel ends, so that each
+        // side notices when the other is gone.
+        drop(filled_rx);
+        drop(free_tx);
+
+        let source = Source {
+            file: &mut input_file,
+            meter: &meter,
+        };
+        let reader = scope.spawn(|| read_chunks(config, source, filled_tx, pool, &scheduler, &failed, &progress));
+
+        let read_result = reader.join().unwrap_or_else(|_| Err(thread_panicked("reader")));
+        let write_results: Vec<_> = handles
+            .into_iter()
+            .map(|h| h.join().unwrap_or_else(|_| Err(thread_panicked("writer"))))
+            .collect();
+        (read_result, write_results)
+    });
+    bandwidth.finish();
+
+    // A writer error is the root cause when both sides failed: the reader
+    // only stops because the writers did.
+    let mut retried_writes = Vec::new();
+    for result in write_results {
+        retried_writes.extend(result?);
+    }
+    // On Ctrl-C the reader stops and the writers drain what it had sent;
+    // make that durable before giving up.
+    if let Err(e @ RddError::Interrupted { .. }) = read_result {
+        sync_output(config, &output_file)?;
+        return Err(e);
+    }
+    let (mut stats, verifier) = read_result?;
+    stats.retried.extend(retried_writes);
+    stats.backpressure = Some(scheduler.totals());
+    let output_file = filters.finish(input_file, output_file)?;
+
+    finish_copy(
+        config,
+        &output_file,
+        &progress,
+        input_snapshot.as_ref(),
+        verifier,
+        stats,
+        timer,
+    )
+}
+
+/// The reader thread: applies skip, seek and count, splits the input into
+/// chunks and sends them to the writers. Returns the copy's counters, and
+/// with --verify the hash of everything it sent.
+fn read_chunks(
+    config: &CopyConfig,
+    source: Source,
+    filled: Sender<Chunk>,
+    mut pool: BufferPool,
+    scheduler: &Scheduler,
+    failed: &AtomicBool,
+    progress: &ProgressBar,
+) -> RddResult<(CopyStats, Option<CopyVerifier>)> {
+    let input = source.file;
+    let mut stats = CopyStats::default();
+    let mut verifier = CopyVerifier::for_copy(config);
+    let mut health = config.monitor_health.then(|| HealthMonitor::new(config));
+    let mut power = PowerMonitor::start(config, progress);
+    let mut throttle = config.rate_limit.map(Throttle::new);
+    let transform = Transform::for_copy(config);
+    let mut device = config.device_model.clone().map(SimulatedDevice::new);
+
+    skip_input(config, input)?;
+    let mut in_pos = config.skip_bytes;
+    let out_start = config.seek_bytes;
+    let mut out_pos = out_start;
+
+    let max_blocks = (config.count > 0).then_some(config.count);
+    let mut remaining = config.count_bytes;
+    let mut block_filled = 0usize;
+
+    while max_blocks.is_none_or(|max| stats.blocks_copied < max) && remaining != Some(0) {
+        // The writers have given up; their error is reported instead.
+        if failed.load(Ordering::Relaxed) {
+            break;
+        }
+        signals::check(&*config.reporter, progress)?;
+        // All writers are gone, so nobody will return buffers anymore.
+        let Some(mut buffer) = pool.take(scheduler) else {
+            break;
+        };
+
+        let mut want = buffer.len().min(config.block_size - block_filled);
+        if let Some(left) = remaining {
+            want = want.min(left.min(usize::MAX as u64) as usize);
+        }
+        let mut result = read_input(&mut device, input, in_pos, &mut buffer[..want]);
+        if result.is_err() && power.settle_after_wake(progress) {
+            result = read_input(&mut device, input, in_pos, &mut buffer[..want]);
+        }
+        if let Err(e) = result {
+            result = retry::again(config, IoOperation::Read, in_pos, e, progress, &mut stats.retried, || {
+                read_input(&mut device, input, in_pos, &mut buffer[..want])
+            });
+        }
+        // Ctrl-C while waiting to read again stops the copy at the top of
+        // the loop.
+        if result.is_err() && signals::stopping() {
+            continue;
+        }
+        let len = match result {
+            Err(e) if config.noerror => skip_unreadable(
+                config,
+                input,
+                &mut buffer[..want],
+                in_pos,
+                e,
+                progress,
+                &mut stats,
+            )?,
+            result => result.at_offset(&config.input_file, in_pos)?,
+        };
+
+        // End of file. Anything already read is a partial block.
+        if len == 0 {
+            if block_filled > 0 {
+                stats.blocks_copied += 1;
+                stats.partial_blocks += 1;
+            }
+            break;
+        }
+
+        source.meter.read(len as u64);
+        if let Some(throttle) = throttle.as_mut() {
+            throttle.wait(len as u64);
+        }
+        if let Some(transform) = &transform {
+            transform.apply(&mut buffer[..len]);
+        }
+        if let Some(verifier) = verifier.as_mut() {
+            verifier.update(&buffer[..len]);
+        }
+
+        // The writers have failed while we waited for them.
+        if !scheduler.reserve(len as u64) {
+            break;
+        }
+        if filled
+            .send(Chunk {
+                offset: out_pos,
+                buffer,
+                len,
+            })
+            .is_err()
+        {
+            break;
+        }
+
+        in_pos += len as u64;
+        out_pos += len as u64;
+        stats.bytes_copied += len as u64;
+        if let Some(left) = remaining.as_mut() {
+            *left -= len as u64;
+        }
+
+        // A full block, a short read or the end of --count-bytes completes
+        // the logical block.
+        block_filled += len;
+        if block_filled == config.block_size || len < want || remaining == Some(0) {
+            stats.blocks_copied += 1;
+            if block_filled < config.block_size {
+                stats.partial_blocks += 1;
+            }
+            block_filled = 0;
+
+            if let Some(health) = health.as_mut() {
+                health.poll(progress);
+            }
+            power.poll(progress);
+        }
+    }
+
+    stats.blocks_written = stats.blocks_copied;
+    stats.bytes_written = stats.bytes_copied;
+    stats.health = health.map(|health| health.totals);
+    stats.power = Some(power.totals);
+    stats.simulation = device.as_ref().map(SimulatedDevice::stats);
+    if let Some(verifier) = verifier.as_mut() {
+        verifier.add_region(out_start, out_pos);
+    }
+    Ok((stats, verifier))
+}
+
+/// A writer thread: writes chunks until the reader is done, returning each
+/// buffer to the pool. With `in_order` set, this is the only writer and the
+/// chunks are written sequentially instead of at their offsets.
+fn write_chunks(
+    config: &CopyConfig,
+    target: &Target,
+    in_order: bool,
+    filled: Receiver<Chunk>,
+    recycler: Recycler,
+    progress: &ProgressBar,
+) -> RddResult<Vec<RetriedIo>> {
+    let output = target.file;
+    let mut retried = Vec::new();
+    let mut sequential = output;
+    if in_order && config.seek_bytes > 0 {
+        let seek_bytes = config.seek_bytes;
+        sequential
+            .seek(SeekFrom::Start(seek_bytes))
+            .at_offset(&config.output_file, seek_bytes)?;
+    }
+
+    for chunk in filled {
+        relax_direct_for(config, output, target.direct_align, chunk.len)?;
+        let data = &chunk.buffer[..chunk.len];
+        if in_order {
+            retry::write_all(config, &mut sequential, data, progress, &mut retried)
+        } else {
+            // Writing the whole chunk at its offset again is harmless, so a
+            // write cut short is simply repeated.
+            write_all_at(output, data, chunk.offset).or_else(|e| match config.retry_writes {
+                true => retry::again(config, IoOperation::Write, chunk.offset, e, progress, &mut retried, || {
+                    write_all_at(output, data, chunk.offset)
+                }),
+                false => Err(e),
+            })
+        }
+        .at_offset(&config.output_file, chunk.offset)?;
+        target.syncer.after_write(output, &config.output_file, chunk.len as u64)?;
+        target.meter.wrote(chunk.len as u64);
+
+        if config.fadvise.drop_output_cache {
+            advise_dont_need(output, chunk.offset, chunk.len as u64);
+        }
+        progress.inc(chunk.len as u64);
+        recycler.recycle(chunk);
+    }
+    Ok(retried)
+}
+
+/// Writes all of `buf` at `offset` without moving the file cursor, so several
+/// threads can write to the same file at once.
+#[cfg(unix)]
+fn write_all_at(file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.write_all_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn write_all_at(file: &File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_write(buf, offset)? {
+            0 => return Err(io::ErrorKind::WriteZero.into()),
+            n => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn write_all_at(_file: &File, _buf: &[u8], _offset: u64) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "positional writes are not supported on this platform; use a single writer thread (--threads 2)",
+    ))
+}
+
+fn thread_panicked(role: &str) -> RddError {
+    RddError::Channel(format!("the {} thread panicked", role))
+}
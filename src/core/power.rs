@@ -0,0 +1,213 @@
+// This file makes rdd aware of the laptop it may be running on. A copy onto a USB drive that dies halfway because the battery ran out leaves a half-written, unusable target. A laptop that goes to sleep in the middle of a copy often wakes up with its USB devices still settling, and the first read after wake-up fails with an error that has nothing to do with the drive itself.
+
+// Explanation of this file:
+// Battery: On Linux, /sys/class/power_supply lists the power sources. We're on battery when a battery is discharging and no mains adapter is online. Before the copy starts, we warn about it. With --pause-on-battery, we wait for the charger instead, and during the copy we pause whenever it's unplugged. Ctrl-C ends the wait (see utils/signals.rs). Other platforms don't tell us, and nothing happens there.
+// Sleep: CLOCK_MONOTONIC stops while the system is suspended, CLOCK_BOOTTIME doesn't. When the gap between the two grows, the system slept. We check that every POWER_CHECK_INTERVAL at the end of a block, and after a failed read. The wall-clock throughput isn't affected either way, since Instant uses the monotonic clock.
+// Resuming after sleep: When a read fails and we find that the system slept since the last check, we give the devices SETTLE_TIME to come back and retry the read once. A failed read doesn't move the file position, so the retry reads the same data. Writes aren't retried, since a failed write may have been partly done.
+// PowerStats struct: How long we waited for the charger and how often the system slept, which end up in CopyStats and in the summary.
+
+// src/core/power.rs
+
+use crate::config::CopyConfig;
+use crate::core::reporter::Reporter;
+use crate::utils::diagnostics::Diagnostic;
+use crate::utils::signals;
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often the power source and the sleep clock are checked during a copy.
+const POWER_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long we wait for devices after the system woke up.
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+
+/// A sleep shorter than this is clock noise, not a suspend.
+const MIN_SLEEP: Duration = Duration::from_secs(1);
+
+/// What the power monitor saw over a whole copy.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PowerStats {
+    /// Time spent waiting for the charger (--pause-on-battery).
+    pub paused_on_battery: Duration,
+    /// How often the system was suspended during the copy.
+    pub suspends: u32,
+    /// Total time the system was suspended.
+    pub suspended: Duration,
+}
+
+impl PowerStats {
+    /// A short summary for the end of a copy, if anything happened.
+    pub fn summary(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if !self.paused_on_battery.is_zero() {
+            parts.push(format!(
+                "paused {:.0}s waiting for AC power",
+                self.paused_on_battery.as_secs_f64()
+            ));
+        }
+        if self.suspends > 0 {
+            parts.push(format!(
+                "system suspended {} time(s) for {:.0}s in total",
+                self.suspends,
+                self.suspended.as_secs_f64()
+            ));
+        }
+        (!parts.is_empty()).then(|| format!("Power: {}", parts.join(", ")))
+    }
+}
+
+/// Watches the power source and system sleep during a copy.
+pub struct PowerMonitor {
+    pause_on_battery: bool,
+    /// CLOCK_BOOTTIME minus CLOCK_MONOTONIC at the last check.
+    slept_before: Option<Duration>,
+    last_check: Instant,
+    reporter: Arc<dyn Reporter>,
+    pub totals: PowerStats,
+}
+
+impl PowerMonitor {
+    /// Checks the power source before the copy starts: warns when we're on
+    /// battery, or with --pause-on-battery waits for the charger.
+    pub fn start(config: &CopyConfig, progress: &ProgressBar) -> Self {
+        let mut monitor = Self {
+            pause_on_battery: config.pause_on_battery,
+            slept_before: platform::time_asleep(),
+            last_check: Instant::now(),
+            reporter: config.reporter.clone(),
+            totals: PowerStats::default(),
+        };
+
+        if platform::on_battery() == Some(true) {
+            if monitor.pause_on_battery {
+                monitor.wait_for_charger(progress);
+            } else {
+                monitor.reporter.diagnostic(&Diagnostic::warning(
+                    "running on battery power; if it runs out during the copy, the output is left incomplete. Plug in the charger, or use --pause-on-battery to wait for it.",
+                ));
+            }
+        }
+        monitor
+    }
+
+    /// Every POWER_CHECK_INTERVAL: notes a system sleep, and with
+    /// --pause-on-battery waits while the charger is unplugged.
+    pub fn poll(&mut self, progress: &ProgressBar) {
+        if self.last_check.elapsed() < POWER_CHECK_INTERVAL {
+            return;
+        }
+        self.last_check = Instant::now();
+
+        if let Some(slept) = self.check_sleep() {
+            self.reporter.message_over(
+                progress,
+                &format!("The system was suspended for {:.0}s; continuing the copy.", slept.as_secs_f64()),
+            );
+        }
+        if self.pause_on_battery && platform::on_battery() == Some(true) {
+            self.wait_for_charger(progress);
+        }
+    }
+
+    /// Called after a failed read. If the system slept since the last
+    /// check, waits for the devices to settle and returns true, so the
+    /// caller can retry the read once.
+    pub fn settle_after_wake(&mut self, progress: &ProgressBar) -> bool {
+        let Some(slept) = self.check_sleep() else {
+            return false;
+        };
+        self.reporter.message_over(
+            progress,
+            &format!(
+                "A read failed right after the system was suspended for {:.0}s; retrying in {}s.",
+                slept.as_secs_f64(),
+                SETTLE_TIME.as_secs()
+            ),
+        );
+        signals::sleep(SETTLE_TIME, &*self.reporter, progress)
+    }
+
+    /// Returns how long the system slept since the last call, if it did.
+    fn check_sleep(&mut self) -> Option<Duration> {
+        let now = platform::time_asleep()?;
+        let slept = now.saturating_sub(self.slept_before?);
+        self.slept_before = Some(now);
+        if slept < MIN_SLEEP {
+            return None;
+        }
+        self.totals.suspends += 1;
+        self.totals.suspended += slept;
+        Some(slept)
+    }
+
+    fn wait_for_charger(&mut self, progress: &ProgressBar) {
+        let warning = Diagnostic::warning("running on battery power; waiting for the charger before writing on.")
+            .with_flag("--pause-on-battery");
+        progress.suspend(|| self.reporter.diagnostic(&warning));
+        let waiting_since = Instant::now();
+        let message = progress.message();
+        progress.set_message("paused: waiting for AC power");
+        while platform::on_battery() == Some(true) {
+            // Ctrl-C stops the copy without waiting for the charger.
+            if !signals::sleep(Duration::from_secs(1), &*self.reporter, progress) {
+                self.totals.paused_on_battery += waiting_since.elapsed();
+                progress.set_message(message);
+                return;
+            }
+            progress.tick();
+        }
+        self.totals.paused_on_battery += waiting_since.elapsed();
+        progress.set_message(message);
+        self.reporter.message_over(progress, "AC power is back; continuing.");
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use nix::libc;
+    use std::fs;
+    use std::time::Duration;
+
+    /// Whether the system runs on battery, or `None` if it can't tell.
+    pub fn on_battery() -> Option<bool> {
+        let (mut discharging, mut mains_online) = (false, false);
+        for entry in fs::read_dir("/sys/class/power_supply").ok()?.flatten() {
+            let read = |name: &str| fs::read_to_string(entry.path().join(name)).unwrap_or_default();
+            match read("type").trim() {
+                "Battery" => discharging |= read("status").trim() == "Discharging",
+                "Mains" | "USB" => mains_online |= read("online").trim() == "1",
+                _ => {}
+            }
+        }
+        Some(discharging && !mains_online)
+    }
+
+    /// Time the system has spent suspended since boot.
+    pub fn time_asleep() -> Option<Duration> {
+        Some(clock(libc::CLOCK_BOOTTIME)?.saturating_sub(clock(libc::CLOCK_MONOTONIC)?))
+    }
+
+    fn clock(id: libc::clockid_t) -> Option<Duration> {
+        let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        // SAFETY: clock_gettime only writes to the timespec we pass.
+        if unsafe { libc::clock_gettime(id, &mut ts) } != 0 {
+            return None;
+        }
+        Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use std::time::Duration;
+
+    pub fn on_battery() -> Option<bool> {
+        None
+    }
+
+    pub fn time_asleep() -> Option<Duration> {
+        None
+    }
+}
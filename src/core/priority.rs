@@ -0,0 +1,343 @@
+// This file implements --priority-ranges, which copies the regions of the input that matter most before everything else. A failing drive may only survive one pass, and the order of that pass decides what's saved: a partition table and filesystem metadata are a tiny part of the disk, but without them the rest of the image is a heap of unnamed blocks. Read first, they're in the image even if the drive dies a minute into the copy.
+
+// Explanation of this file:
+// The plan : plan() turns the priority ranges into a ranges list for the copy (see ranges mode in copy.rs): the priority ranges, clipped to the input and merged, followed by the gaps between them, so the whole input is still copied exactly once, to the same offsets. The output isn't truncated, like with --ranges-file. The input's size must be known to list the gaps.
+// Auto : --priority-ranges auto finds the regions by looking at the input: its first MiB (boot code, MBR, the GPT and its entries), the backup GPT at its end, and for every partition (or a filesystem without a partition table) its first MiB, which holds the superblocks of most filesystems, plus the metadata that's easy to locate: the reserved sectors, FATs and root directory of FAT, the boot region and FAT of exFAT; the superblock, group descriptors and first inode table of ext2/3/4; the start of the MFT and its mirror on NTFS.
+// Read errors : Detection reads a few sectors of a drive that may be failing. If one can't be read, the ranges found so far are kept, with a warning, and the copy goes on; it'll meet the bad sector again, with --noerror and --max-errors to deal with it. A damaged drive can also hold nonsense: an entry whose offset doesn't fit in 64 bits is skipped, and the count of those is reported with a warning too.
+// From a file : --priority-ranges FILE takes the ranges from a file in the formats of --ranges-file instead.
+// The error map : The plan isn't in input order, so a copy that gives up under --max-errors saves a map that reflects it (see of_copy in mapfile.rs).
+
+// src/core/priority.rs
+
+use crate::config::CopyConfig;
+use crate::core::capacity::input_size;
+use crate::core::ranges::{normalize, ByteRange};
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::diagnostics::Diagnostic;
+use crate::utils::rawdevice;
+use indicatif::BinaryBytes;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// What's read first around every place a structure starts: the
+/// partition table, or a filesystem's boot sector and superblocks.
+const HEAD: u64 = 1 << 20;
+/// How much of an NTFS MFT is read first.
+const MFT_PRIORITY: u64 = 64 << 20;
+const SECTOR: u64 = 512;
+/// The most GPT entries we read.
+const MAX_GPT_ENTRIES: u64 = 1024;
+
+/// The regions a copy reads first (see --priority-ranges).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PriorityRanges {
+    /// The partition table and filesystem metadata found in the input.
+    Auto,
+    /// These ranges of the input.
+    List(Vec<ByteRange>),
+}
+
+/// What auto-detection found.
+#[derive(Debug, Default)]
+struct Found {
+    ranges: Vec<ByteRange>,
+    partition_table: Option<&'static str>,
+    filesystems: Vec<&'static str>,
+    /// Entries skipped because their offsets overflow.
+    skipped: usize,
+}
+
+impl Found {
+    fn add(&mut self, offset: u64, length: u64) {
+        self.ranges.push(ByteRange { offset, length });
+    }
+}
+
+/// With --priority-ranges, `config` with the ranges list the copy follows:
+/// the priority ranges first, then the rest of the input.
+pub fn plan(config: &CopyConfig) -> RddResult<Cow<'_, CopyConfig>> {
+    let Some(priority) = &config.priority_ranges else {
+        return Ok(Cow::Borrowed(config));
+    };
+    let path = config.input_file.as_str();
+    let mut options = OpenOptions::new();
+    options.read(true);
+    if rawdevice::raw_device(path).is_some() {
+        rawdevice::share(&mut options);
+    }
+    let mut input = options.open(path).with_path(path)?;
+    let Some(size) = input_size(&input, path) else {
+        return Err(RddError::Config(format!(
+            "--priority-ranges needs an input whose size is known, to copy the rest after them; '{}' has none.",
+            path
+        )));
+    };
+
+    let wanted = match priority {
+        PriorityRanges::List(ranges) => ranges.clone(),
+        PriorityRanges::Auto => {
+            let mut found = Found::default();
+            if let Err(e) = detect(&mut input, size, &mut found) {
+                config.reporter.diagnostic(
+                    &Diagnostic::warning(format!(
+                        "'{}' couldn't be read while looking for its metadata ({}); only what was found until then is copied first.",
+                        path, e
+                    ))
+                    .with_flag("--priority-ranges"),
+                );
+            }
+            if found.skipped > 0 {
+                config.reporter.diagnostic(
+                    &Diagnostic::warning(format!(
+                        "Skipped {} partition table or filesystem entries in '{}' whose offsets are out of range; its metadata may be damaged.",
+                        found.skipped, path
+                    ))
+                    .with_flag("--priority-ranges"),
+                );
+            }
+            let mut what: Vec<String> = found.partition_table.map(|t| format!("{} partition table", t)).into_iter().collect();
+            if !found.filesystems.is_empty() {
+                what.push(format!("{} filesystem(s) ({})", found.filesystems.len(), found.filesystems.join(", ")));
+            }
+            if !what.is_empty() {
+                config.reporter.message(&format!("Found {} in '{}'.", what.join(" and "), path));
+            }
+            found.ranges
+        }
+    };
+    let first = normalize(
+        wanted
+            .into_iter()
+            .filter(|r| r.offset < size)
+            .map(|r| ByteRange {
+                offset: r.offset,
+                length: r.length.min(size - r.offset),
+            })
+            .collect(),
+    );
+
+    let mut rest = Vec::new();
+    let mut at = 0;
+    for range in first.iter().chain([&ByteRange { offset: size, length: 0 }]) {
+        if range.offset > at {
+            rest.push(ByteRange {
+                offset: at,
+                length: range.offset - at,
+            });
+        }
+        at = range.end();
+    }
+    config.reporter.message(&format!(
+        "Copying {} priority range(s), {} in all, before the rest of the input.",
+        first.len(),
+        BinaryBytes(first.iter().map(|r| r.length).sum())
+    ));
+
+    let mut planned = config.clone();
+    planned.ranges = Some(first.into_iter().chain(rest).collect());
+    Ok(Cow::Owned(planned))
+}
+
+/// Adds the partition table and filesystem metadata of the disk or
+/// filesystem in `input` to `found`.
+fn detect(input: &mut File, size: u64, found: &mut Found) -> io::Result<()> {
+    found.add(0, HEAD);
+    // A filesystem without a partition table has a boot signature too.
+    if filesystem(input, 0, found)? {
+        return Ok(());
+    }
+    let mbr = read_at(input, 0, SECTOR as usize)?;
+    if mbr.len() < SECTOR as usize || u16_at(&mbr, 510) != 0xAA55 {
+        return Ok(());
+    }
+    found.partition_table = Some("an MBR");
+
+    let mut starts = Vec::new();
+    let mut gpt = false;
+    for entry in mbr[446..510].chunks_exact(16) {
+        let start = u64::from(u32_at(entry, 8));
+        match entry[4] {
+            0x00 => {}
+            0xEE => gpt = true,
+            // Extended partitions hold more partition tables, not filesystems.
+            0x05 | 0x0F | 0x85 => starts.push((start * SECTOR, false)),
+            _ if start > 0 => starts.push((start * SECTOR, true)),
+            _ => {}
+        }
+    }
+    if gpt {
+        let header = read_at(input, SECTOR, SECTOR as usize)?;
+        if header.len() >= 92 && &header[0..8] == b"EFI PART" {
+            found.partition_table = Some("a GPT");
+            found.add(size.saturating_sub(HEAD), HEAD);
+            let entries = u64_at(&header, 72);
+            let count = u64::from(u32_at(&header, 80)).min(MAX_GPT_ENTRIES);
+            let entry_size = u64::from(u32_at(&header, 84));
+            if (48..=4096).contains(&entry_size) {
+                let table_len = count.saturating_mul(entry_size);
+                match entries.checked_mul(SECTOR) {
+                    Some(at) => {
+                        found.add(at, table_len);
+                        let table = read_at(input, at, table_len as usize)?;
+                        for entry in table.chunks_exact(entry_size as usize) {
+                            let first_lba = u64_at(entry, 32);
+                            if entry[..16].iter().any(|&b| b != 0) && first_lba > 0 {
+                                match first_lba.checked_mul(SECTOR) {
+                                    Some(start) => starts.push((start, true)),
+                                    None => found.skipped += 1,
+                                }
+                            }
+                        }
+                    }
+                    None => found.skipped += 1,
+                }
+            }
+        }
+    }
+    for (start, holds_filesystem) in starts {
+        match holds_filesystem {
+            true => {
+                filesystem(input, start, found)?;
+            }
+            // The extended partition's first table.
+            false => found.add(start, SECTOR),
+        }
+    }
+    Ok(())
+}
+
+/// Adds the metadata of the filesystem starting at `start` to `found`, and
+/// its first MiB whatever it is. Returns whether it was recognized.
+fn filesystem(input: &mut File, start: u64, found: &mut Found) -> io::Result<bool> {
+    if start > 0 {
+        found.add(start, HEAD);
+    }
+    let boot = read_at(input, start, SECTOR as usize)?;
+    if boot.len() < SECTOR as usize {
+        return Ok(false);
+    }
+    if &boot[3..11] == b"NTFS    " {
+        let cluster = u64::from(u16_at(&boot, 11)) * u64::from(boot[13]);
+        found.add(start.saturating_add(u64_at(&boot, 48).saturating_mul(cluster)), MFT_PRIORITY);
+        found.add(start.saturating_add(u64_at(&boot, 56).saturating_mul(cluster)), cluster.max(4096));
+        found.filesystems.push("NTFS");
+        return Ok(true);
+    }
+    if &boot[3..11] == b"EXFAT   " && boot[108] <= 12 {
+        // Everything up to the cluster heap: boot region, FAT and bitmap
+        // aside, which live in the heap.
+        found.add(start, u64::from(u32_at(&boot, 88)) << boot[108]);
+        found.filesystems.push("exFAT");
+        return Ok(true);
+    }
+    if is_fat(&boot) {
+        let fat_sectors = match u16_at(&boot, 22) {
+            0 => u64::from(u32_at(&boot, 36)),
+            sectors => u64::from(sectors),
+        };
+        let sectors = u64::from(u16_at(&boot, 14)) + u64::from(boot[16]) * fat_sectors;
+        let root = u64::from(u16_at(&boot, 17)) * 32;
+        found.add(start, sectors * u64::from(u16_at(&boot, 11)) + root);
+        found.filesystems.push("FAT");
+        return Ok(true);
+    }
+
+    let Some(superblock) = start.checked_add(1024) else {
+        found.skipped += 1;
+        return Ok(false);
+    };
+    let sb = read_at(input, superblock, 1024)?;
+    if sb.len() < 1024 || u16_at(&sb, 56) != 0xEF53 || u32_at(&sb, 24) > 6 || u32_at(&sb, 32) == 0 {
+        return Ok(false);
+    }
+    let block = 1024u64 << u32_at(&sb, 24);
+    let is_64bit = u32_at(&sb, 96) & 0x80 != 0;
+    let mut blocks = u64::from(u32_at(&sb, 4));
+    let mut desc_size = 32;
+    if is_64bit {
+        blocks |= u64::from(u32_at(&sb, 0x150)) << 32;
+        desc_size = u64::from(u16_at(&sb, 254)).max(32);
+    }
+    let groups = blocks.div_ceil(u64::from(u32_at(&sb, 32)));
+    // The group descriptors follow the block with the superblock.
+    let descriptors = (u64::from(u32_at(&sb, 20)) + 1) * block;
+    found.add(start, descriptors.saturating_add(groups.saturating_mul(desc_size)));
+    // The first group's inode table holds the root directory.
+    match start.checked_add(descriptors) {
+        Some(at) => {
+            let desc = read_at(input, at, 32)?;
+            if desc.len() == 32 {
+                let table = u64::from(u32_at(&desc, 8)) * block;
+                let inodes = u64::from(u32_at(&sb, 40)) * u64::from(u16_at(&sb, 88));
+                found.add(start.saturating_add(table), inodes);
+            }
+        }
+        None => found.skipped += 1,
+    }
+    found.filesystems.push("ext");
+    Ok(true)
+}
+
+/// Whether `boot` is the boot sector of a FAT12/16/32 filesystem.
+fn is_fat(boot: &[u8]) -> bool {
+    matches!(boot[0], 0xEB | 0xE9)
+        && u16_at(boot, 510) == 0xAA55
+        && matches!(u16_at(boot, 11), 512 | 1024 | 2048 | 4096)
+        && boot[13].is_power_of_two()
+        && u16_at(boot, 14) > 0
+        && boot[16] > 0
+}
+
+/// Reads up to `len` bytes at `offset`, fewer at the end of the input.
+fn read_at(input: &mut File, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(len);
+    input.seek(SeekFrom::Start(offset))?;
+    input.by_ref().take(len as u64).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gpt_entries_past_any_offset_are_skipped() {
+        let path = std::env::temp_dir().join(format!("rdd-priority-test-{}", std::process::id()));
+        let mut disk = vec![0u8; 8192];
+        disk[446 + 4] = 0xEE;
+        disk[510..512].copy_from_slice(&[0x55, 0xAA]);
+        disk[512..520].copy_from_slice(b"EFI PART");
+        disk[512 + 72..512 + 80].copy_from_slice(&2u64.to_le_bytes());
+        disk[512 + 80..512 + 84].copy_from_slice(&2u32.to_le_bytes());
+        disk[512 + 84..512 + 88].copy_from_slice(&128u32.to_le_bytes());
+        for (entry, first_lba) in [(1024, u64::MAX / 256), (1152, 8)] {
+            disk[entry] = 1;
+            disk[entry + 32..entry + 40].copy_from_slice(&first_lba.to_le_bytes());
+        }
+        std::fs::write(&path, &disk).unwrap();
+
+        let mut found = Found::default();
+        let detected = detect(&mut File::open(&path).unwrap(), disk.len() as u64, &mut found);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(detected.is_ok());
+        assert_eq!(found.partition_table, Some("a GPT"));
+        assert_eq!(found.skipped, 1);
+        // The good entry's partition is still looked at.
+        assert!(found.ranges.contains(&ByteRange { offset: 8 * SECTOR, length: HEAD }));
+    }
+}
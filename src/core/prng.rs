@@ -0,0 +1,71 @@
+// This file is the random number generator behind `rdd wipe --passes random`. A random pass has to produce as many random bytes as the device is large, and a generator slower than the disk would set the pace of the whole wipe. xoshiro256++ produces 8 bytes in a handful of instructions, well beyond the speed of any drive.
+
+// Explanation of this file:
+// Not for secrets : xoshiro256++ is fast and statistically good, but not cryptographically secure: its output can be predicted from a few earlier values. That doesn't matter for overwriting a disk, where the point is that the old data is gone, not that the new data is unpredictable.
+// Seeds : Each generator is started from a 64-bit seed, spread over the 256-bit state with SplitMix64 as the xoshiro authors recommend. The same seed always gives the same stream, which is how a wipe checks a random pass when it reads the target back. entropy_seed picks a seed from the clock, the process ID and a stack address.
+// fill : Fills a buffer 8 bytes at a time, with one call per block of the wipe loop.
+
+// src/core/prng.rs
+
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A xoshiro256++ generator.
+#[derive(Debug, Clone)]
+pub struct Prng {
+    state: [u64; 4],
+}
+
+impl Prng {
+    /// A generator whose stream is fixed by `seed`.
+    pub fn from_seed(seed: u64) -> Self {
+        let mut x = seed;
+        let mut splitmix = || {
+            x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        Self {
+            state: [splitmix(), splitmix(), splitmix(), splitmix()],
+        }
+    }
+
+    /// A seed that differs from run to run.
+    pub fn entropy_seed() -> u64 {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        let local = 0u8;
+        let address = &local as *const u8 as u64;
+        nanos ^ (u64::from(process::id()) << 32) ^ address.rotate_left(17)
+    }
+
+    /// The next 64 random bits.
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[0].wrapping_add(s[3]).rotate_left(23).wrapping_add(s[0]);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    /// Fills `buf` with random bytes.
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        let mut chunks = buf.chunks_exact_mut(8);
+        for chunk in &mut chunks {
+            chunk.copy_from_slice(&self.next_u64().to_le_bytes());
+        }
+        let tail = chunks.into_remainder();
+        if !tail.is_empty() {
+            let bytes = self.next_u64().to_le_bytes();
+            tail.copy_from_slice(&bytes[..tail.len()]);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+// This file loads lists of byte ranges, so a copy can be limited to just the regions of the input that matter. The typical use is going back to a failing drive and re-reading only the regions that could not be read the first time.
+
+// Explanation of this file:
+// ByteRange struct: A half-open region [offset, offset + length) of the input. The same offsets are used in the output, so a range file can patch holes in an existing image in place.
+// Two file formats are accepted, and detected automatically:
+    // Plain : One "offset length" pair per line. Numbers may be decimal or hexadecimal with a 0x prefix. Lines starting with '#' and blank lines are ignored.
+    // ddrescue map : The map file written by GNU ddrescue, parsed by mapfile.rs. Every block whose status is not '+' (finished) is selected, since those are the regions that still need to be read.
+// Detection: We look at the first data line. If its second field is a number, it's a plain file; otherwise it must be the ddrescue status line.
+// normalize : Sorts the ranges and merges overlapping or touching ones, so the copy never reads the same byte twice and always moves forward through the input.
+
+// src/core/ranges.rs
+
+use crate::core::mapfile::MapFile;
+use crate::error::{RddError, RddResult};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// A region of `length` bytes starting at `offset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl ByteRange {
+    /// The first offset after the end of this range.
+    pub fn end(&self) -> u64 {
+        self.offset + self.length
+    }
+}
+
+/// Reads a ranges file (plain "offset length" lines or a ddrescue map file)
+/// and returns its ranges sorted and merged.
+pub fn load_ranges_file(path: &str) -> RddResult<Vec<ByteRange>> {
+    let contents = fs::read_to_string(path)?;
+
+    let lines: Vec<(usize, Vec<&str>)> = contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split_whitespace().collect::<Vec<_>>()))
+        .filter(|(_, fields)| !fields.is_empty() && !fields[0].starts_with('#'))
+        .collect();
+
+    let is_plain = lines
+        .first()
+        .is_none_or(|(_, fields)| fields.len() == 2 && parse_number(fields[1]).is_some());
+
+    let ranges = if is_plain {
+        parse_plain(path, &lines)?
+    } else {
+        MapFile::parse(path, &contents)?.unfinished().collect()
+    };
+
+    Ok(normalize(ranges))
+}
+
+fn parse_plain(path: &str, lines: &[(usize, Vec<&str>)]) -> RddResult<Vec<ByteRange>> {
+    lines
+        .iter()
+        .map(|(line_no, fields)| match fields.as_slice() {
+            [offset, length] => Ok(ByteRange {
+                offset: parse_field(path, *line_no, offset)?,
+                length: parse_field(path, *line_no, length)?,
+            }),
+            _ => Err(invalid_line(path, *line_no, "expected 'offset length'")),
+        })
+        .collect()
+}
+
+/// Sorts ranges by offset and merges any that overlap or touch.
+/// Empty ranges are dropped.
+pub fn normalize(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.retain(|r| r.length > 0);
+    ranges.sort_by_key(|r| r.offset);
+
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.offset <= last.end() => {
+                let end = last.end().max(range.end());
+                last.length = end - last.offset;
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal number.
+fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn parse_field(path: &str, line_no: usize, s: &str) -> RddResult<u64> {
+    parse_number(s).ok_or_else(|| invalid_line(path, line_no, &format!("invalid number '{}'", s)))
+}
+
+fn invalid_line(path: &str, line_no: usize, reason: &str) -> RddError {
+    RddError::Config(format!("{}:{}: {}", path, line_no, reason))
+}
@@ -0,0 +1,165 @@
+// This file implements --records, which makes --skip and --count count lines instead of blocks, so a slice of a huge log or CSV file can be taken with the same tool as a slice of a disk: `rdd copy -i app.log -o part.log --records --skip 1000000 --count 500`. Lines have no fixed size, so where a record starts can only be found by reading everything before it.
+
+// Explanation of this file:
+// Records : A record is everything up to and including a newline (\n). A last line without one is a record too, as in `wc -l` plus one; \r\n line endings come along as they are. The data isn't changed, only cut.
+// The slicer : Like the joiner of split.rs, the slicer is a pipe with a thread on the other end. The thread reads the input (the file, stdin, or what a decompressor or a connection delivers), drops the first --skip records, and writes the next --count records (or all the rest) into the pipe, which the copy reads as its input. Once the count is reached, it stops reading, so the copy ends without reading the rest of the file.
+// Streams : The copy reads a pipe, so --records comes with the restrictions of stdin (see config.rs). Blocks still decide how the output is written; --seek still counts blocks of the output.
+// Summary : finish() reports how many records were skipped and copied, and says so when the input ran out before the records asked for.
+
+// src/core/records.rs
+
+use crate::core::open::pipe_file;
+use crate::core::reporter::Reporter;
+use crate::error::{IoResultExt, RddError, RddResult};
+use indicatif::BinaryBytes;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// How much the slicer reads at once.
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Which records of the input a copy takes (see --records).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Records {
+    /// Records to leave out at the start.
+    pub skip: u64,
+    /// Records to copy after them, or None for all the rest.
+    pub count: Option<u64>,
+}
+
+/// What the slicer did.
+#[derive(Debug, Default, Clone, Copy)]
+struct Sliced {
+    skipped: u64,
+    copied: u64,
+    bytes: u64,
+}
+
+/// Cuts the records a copy takes out of its input.
+pub struct RecordSlicer {
+    thread: JoinHandle<RddResult<Sliced>>,
+    records: Records,
+    reporter: Arc<dyn Reporter>,
+}
+
+impl RecordSlicer {
+    /// Starts cutting `records` out of `input`, which is read from `path`.
+    /// Returns the slicer with the pipe to read them from.
+    pub fn start(input: File, path: &str, records: Records, reporter: Arc<dyn Reporter>) -> RddResult<(Self, File)> {
+        let (reader, writer) = io::pipe().with_path(path)?;
+        let pipe = pipe_file(reader).with_path(path)?;
+        let thread_path = path.to_string();
+        let thread = thread::Builder::new()
+            .name("rdd-records".to_string())
+            .spawn(move || slice(input, writer, &thread_path, records))
+            .with_path(path)?;
+        Ok((
+            Self {
+                thread,
+                records,
+                reporter,
+            },
+            pipe,
+        ))
+    }
+
+    /// Waits for the slicer once the pipe is closed. An error reading the
+    /// input fails the copy here, rather than looking like its end.
+    pub fn finish(self) -> RddResult<()> {
+        let result = self
+            .thread
+            .join()
+            .map_err(|_| RddError::Channel("the record slicer thread panicked".to_string()))?;
+        let sliced = match result {
+            // The copy stopped reading early.
+            Err(RddError::IoAt { source, .. }) if source.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            result => result?,
+        };
+        self.reporter.message(&format!(
+            "Skipped {} record(s) and copied {} record(s), {} ({} bytes).",
+            sliced.skipped,
+            sliced.copied,
+            BinaryBytes(sliced.bytes),
+            sliced.bytes
+        ));
+        if sliced.skipped < self.records.skip {
+            self.reporter.message(&format!(
+                "The input has only {} record(s), fewer than the {} to skip; nothing was copied.",
+                sliced.skipped, self.records.skip
+            ));
+        } else if let Some(count) = self.records.count
+            && sliced.copied < count
+        {
+            self.reporter.message(&format!(
+                "The input ran out after {} of the {} record(s) to copy.",
+                sliced.copied, count
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The slicer thread: writes the records of `input` that `records` asks for
+/// into `pipe`.
+fn slice(mut input: File, mut pipe: io::PipeWriter, path: &str, records: Records) -> RddResult<Sliced> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut sliced = Sliced::default();
+    let mut offset = 0u64;
+    // Whether the last byte seen left a record unfinished.
+    let mut partial = false;
+    if records.count == Some(0) {
+        return Ok(sliced);
+    }
+    loop {
+        let n = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e).at_offset(path, offset),
+        };
+        offset += n as u64;
+        let mut data = &buffer[..n];
+        partial = data[n - 1] != b'\n';
+
+        while sliced.skipped < records.skip && !data.is_empty() {
+            match data.iter().position(|&b| b == b'\n') {
+                Some(i) => {
+                    sliced.skipped += 1;
+                    data = &data[i + 1..];
+                }
+                None => data = &[],
+            }
+        }
+        if data.is_empty() {
+            continue;
+        }
+
+        // Up to the newline that ends the last record to copy.
+        let mut end = data.len();
+        let mut lines = 0;
+        for (i, _) in data.iter().enumerate().filter(|&(_, &b)| b == b'\n') {
+            lines += 1;
+            if records.count == Some(sliced.copied + lines) {
+                end = i + 1;
+                break;
+            }
+        }
+        pipe.write_all(&data[..end]).at_offset(path, sliced.bytes)?;
+        sliced.copied += lines;
+        sliced.bytes += end as u64;
+        if records.count == Some(sliced.copied) {
+            return Ok(sliced);
+        }
+    }
+    // A last line without a newline is a record too.
+    if partial {
+        match sliced.skipped < records.skip {
+            true => sliced.skipped += 1,
+            false => sliced.copied += 1,
+        }
+    }
+    Ok(sliced)
+}
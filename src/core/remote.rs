@@ -0,0 +1,222 @@
+// This file implements ssh:// inputs and outputs, so an image can be pulled from (or pushed to) another machine in one command, with rdd's progress, hashing and verification, instead of a pipeline like `ssh server 'dd if=/dev/sda' | dd of=disk.img` that hides failures on the far end.
+
+// Explanation of this file:
+// Addresses : ssh://[USER@]HOST[:PORT]/PATH names PATH on HOST. The path is absolute; ssh://HOST/~/PATH is relative to the remote home directory.
+// The ssh program : rdd runs the ssh client that's installed, so ~/.ssh/config, keys, agents and known hosts work as they do on the command line, and ssh asks for a password or a host key confirmation on the terminal itself. With --passphrase-file or RDD_PASSPHRASE, ssh gets its password (or key passphrase) from there instead, through rdd as its askpass program (see utils/passphrase.rs), so a script can copy from a host that only takes passwords. On the far end, `cat` reads or writes the file or device, so nothing but a shell is needed there.
+// Pipes as files : Like the decompressor (see compress.rs), a remote input is ssh's output, read as a stream. A remote output is a pipe to a thread that feeds ssh, like the connections of network.rs, so both count as network streams in config.rs and come with the restrictions of stdin and stdout.
+// Errors : ssh passes on what `cat` says on its stderr (e.g. "No such file or directory"), and a failed ssh fails the copy in finish, with its exit status: a copy isn't complete unless the far end says so. An input ssh stopped early by --count dies of SIGPIPE, which is expected.
+
+// src/core/remote.rs
+
+use crate::config::CopyConfig;
+use crate::core::compress::{failed, stopped_by_us};
+use crate::core::open::pipe_file;
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::diagnostics::Diagnostic;
+use crate::utils::passphrase::{self, PassphraseArgs};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+
+/// How remote inputs and outputs are written.
+pub const SSH_SCHEME: &str = "ssh://";
+/// The program that reaches the far end.
+const SSH: &str = "ssh";
+/// How much the feeding thread moves at once.
+const BUFFER_SIZE: usize = 1 << 20;
+
+/// Whether `path` is an ssh:// address.
+pub fn is_ssh(path: &str) -> bool {
+    path.starts_with(SSH_SCHEME)
+}
+
+/// Where an ssh:// address points.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    /// [USER@]HOST, as ssh takes it.
+    pub destination: String,
+    pub port: Option<u16>,
+    /// The path on the far end.
+    pub path: String,
+}
+
+impl Remote {
+    pub fn parse(uri: &str) -> RddResult<Self> {
+        let invalid = || {
+            RddError::Config(format!(
+                "'{}' isn't a remote path rdd understands; use ssh://[USER@]HOST[:PORT]/PATH.",
+                uri
+            ))
+        };
+        let rest = uri.strip_prefix(SSH_SCHEME).ok_or_else(invalid)?;
+        let (authority, path) = rest.split_once('/').ok_or_else(invalid)?;
+        let (destination, port) = match authority.rsplit_once(':') {
+            Some((destination, port)) => (destination, Some(port.parse::<u16>().map_err(|_| invalid())?)),
+            None => (authority, None),
+        };
+        let host = destination.rsplit_once('@').map_or(destination, |(_, host)| host);
+        if host.is_empty() || path.is_empty() || destination.starts_with('-') {
+            return Err(invalid());
+        }
+        let path = match path.strip_prefix("~/") {
+            Some(relative) => relative.to_string(),
+            None => format!("/{}", path),
+        };
+        Ok(Remote {
+            destination: destination.to_string(),
+            port,
+            path,
+        })
+    }
+
+    /// The ssh command that runs `command` (a shell command line) on the
+    /// far end.
+    fn command(&self, command: &str) -> Command {
+        let mut ssh = Command::new(SSH);
+        if let Some(port) = self.port {
+            ssh.arg("-p").arg(port.to_string());
+        }
+        ssh.arg("--").arg(&self.destination).arg(command);
+        ssh
+    }
+}
+
+/// An ssh reading or writing a remote file for a copy.
+pub enum RemoteFile {
+    /// ssh reading a remote input, which the copy reads from.
+    Reading { child: Child },
+    /// The thread feeding the copy's output to ssh, which waits for ssh too.
+    Writing {
+        feeder: JoinHandle<RddResult<io::PipeReader>>,
+    },
+}
+
+impl RemoteFile {
+    /// Starts reading the input of `config` from the far end. Returns the
+    /// stream it arrives on.
+    pub fn read(config: &CopyConfig) -> RddResult<(Self, File)> {
+        let uri = config.input_file.as_str();
+        let remote = Remote::parse(uri)?;
+        let mut command = remote.command(&format!("cat -- {}", quote(&remote.path)));
+        give_password(&mut command, config, uri)?;
+        let mut child = spawn(command.stdin(Stdio::null()).stdout(Stdio::piped()))?;
+        let stream = child.stdout.take().map(pipe_file).transpose().with_path(uri)?;
+        let stream = stream.ok_or_else(|| RddError::Channel("ssh has no output pipe".to_string()))?;
+        Ok((RemoteFile::Reading { child }, stream))
+    }
+
+    /// Starts writing the output of `config` on the far end. Returns the
+    /// pipe to write into.
+    pub fn write(config: &CopyConfig) -> RddResult<(Self, File)> {
+        let uri = config.output_file.as_str();
+        let remote = Remote::parse(uri)?;
+        let mut command = remote.command(&format!("cat > {}", quote(&remote.path)));
+        give_password(&mut command, config, uri)?;
+        let mut child = spawn(command.stdin(Stdio::piped()).stdout(Stdio::null()))?;
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| RddError::Channel("ssh has no input pipe".to_string()))?;
+        let (reader, writer) = io::pipe().with_path(uri)?;
+        let pipe = pipe_file(writer).with_path(uri)?;
+        let (thread_uri, reporter) = (uri.to_string(), config.reporter.clone());
+        let feeder = thread::Builder::new()
+            .name("rdd-ssh".to_string())
+            .spawn(move || {
+                let mut reader = reader;
+                let fed = feed(&mut reader, stdin, &thread_uri);
+                // Once its input is closed, ssh finishes; a failed ssh is
+                // what stopped the feeding, whatever the write said.
+                let result = match child.wait().with_path(SSH) {
+                    Ok(status) if !status.success() => Err(failed(SSH, "sending", status)),
+                    Ok(_) => fed.map(|()| reader),
+                    Err(e) => Err(e),
+                };
+                // The copy only sees a broken pipe once the pipe is closed,
+                // so say what really happened first.
+                if let Err(e) = &result {
+                    reporter.diagnostic(&Diagnostic::error(e.to_string()));
+                }
+                result
+            })
+            .with_path(uri)?;
+        Ok((RemoteFile::Writing { feeder }, pipe))
+    }
+
+    /// Waits for ssh once the copy's end of the pipe is closed. A remote
+    /// output returns the end of the pipe the feeder read, which stands for
+    /// the output from here on.
+    pub fn finish(self) -> RddResult<Option<File>> {
+        match self {
+            RemoteFile::Reading { mut child } => {
+                let status = child.wait().with_path(SSH)?;
+                if !status.success() && !stopped_reading(status) {
+                    return Err(failed(SSH, "receiving", status));
+                }
+                Ok(None)
+            }
+            RemoteFile::Writing { feeder } => {
+                let reader = feeder
+                    .join()
+                    .map_err(|_| RddError::Channel("the ssh feeder thread panicked".to_string()))??;
+                pipe_file(reader).map(Some).with_path(SSH)
+            }
+        }
+    }
+}
+
+/// Whether ssh, or the `cat` it ran, was stopped by SIGPIPE because the copy
+/// stopped reading early (e.g. with --count). ssh passes on the exit code
+/// of the remote shell, which is 128 + 13 for a command killed by SIGPIPE.
+fn stopped_reading(status: ExitStatus) -> bool {
+    stopped_by_us(status) || status.code() == Some(128 + 13)
+}
+
+/// Has the ssh `command` for `uri` take its password from --passphrase-file
+/// or RDD_PASSPHRASE, when one is given. Without either, ssh asks on the
+/// terminal as usual.
+fn give_password(command: &mut Command, config: &CopyConfig, uri: &str) -> RddResult<()> {
+    let args = PassphraseArgs {
+        passphrase_file: config.passphrase_file.clone(),
+    };
+    let what = format!("'{}'", uri);
+    if let Some(password) = passphrase::given(&args, &what, config.reporter.as_ref())? {
+        passphrase::askpass(command, &password, &what)?;
+    }
+    Ok(())
+}
+
+/// Starts ssh, or says it needs installing.
+fn spawn(command: &mut Command) -> RddResult<Child> {
+    command.spawn().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => RddError::CodecMissing { tool: SSH.to_string() },
+        _ => RddError::IoAt {
+            path: SSH.to_string(),
+            offset: None,
+            source: e,
+        },
+    })
+}
+
+/// The feeding thread: copies what comes out of `pipe` into ssh, until the
+/// pipe is empty and closed. Closes ssh's input.
+fn feed(pipe: &mut io::PipeReader, mut stdin: ChildStdin, uri: &str) -> RddResult<()> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut sent = 0u64;
+    loop {
+        let n = match pipe.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e).with_path(uri),
+        };
+        stdin.write_all(&buffer[..n]).at_offset(uri, sent)?;
+        sent += n as u64;
+    }
+}
+
+/// `text` quoted for a POSIX shell.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
@@ -0,0 +1,182 @@
+// This file defines how the core tells the outside world what is going on. The copy engines never print anything themselves: every note along the way, every warning, the summary at the end and the JSON results go to a Reporter. The rdd binary uses TerminalReporter, which prints like rdd always has; a program embedding rdd (a GUI, a service, a test) passes its own and gets the same information as calls instead of text on its terminal.
+
+// Explanation of this file:
+// Reporter trait : message is a line for a person (the banner-like notes, the summary lines), diagnostic a warning or hint that didn't stop the copy, data a result meant for programs (the JSON stats, the digest lines of `rdd verify`), and progress_bar the bar shown while bytes move. Implementations must be Send + Sync, since the parallel copy reports from its reader thread.
+// Where it comes from : The reporter travels in CopyConfig (CopyConfigBuilder::reporter) and VerifyConfig, since the config already reaches every part of the core. It isn't serialized; a deserialized config reports to the terminal.
+// TerminalReporter : Messages and diagnostics go to stderr and data to stdout, following utils/output.rs. When the copied data itself goes to stdout, the engines send the JSON results as a message instead, so they don't corrupt it.
+// progress_bar : Returns an indicatif ProgressBar, which the engines drive with inc and set_message. A reporter that draws its own progress can return a hidden bar and read position() from it.
+// EventReporter : For programs that would rather not implement the trait. Everything reported becomes an Event, handed to a callback or sent down a channel. Progress is read from a hidden bar a few times a second by a small thread, which ends once the bar is finished or dropped.
+
+// src/core/reporter.rs
+
+use crate::utils::diagnostics::Diagnostic;
+use crate::utils::output;
+use crate::utils::progress::new_copy_progress;
+use crossbeam_channel::{unbounded, Receiver};
+use indicatif::ProgressBar;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Receives everything the core has to say while it works.
+pub trait Reporter: Send + Sync {
+    /// A line for the person running rdd.
+    fn message(&self, line: &str);
+
+    /// A warning or hint about something that didn't stop the operation.
+    fn diagnostic(&self, diagnostic: &Diagnostic);
+
+    /// Results meant for programs to read.
+    fn data(&self, text: &str) -> io::Result<()>;
+
+    /// The progress bar for an operation of `total` bytes, if known.
+    fn progress_bar(&self, total: Option<u64>) -> ProgressBar {
+        new_copy_progress(total, true)
+    }
+
+    /// A message while `progress` may be drawn.
+    fn message_over(&self, progress: &ProgressBar, line: &str) {
+        progress.suspend(|| self.message(line));
+    }
+}
+
+impl fmt::Debug for dyn Reporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Reporter")
+    }
+}
+
+/// Prints to the terminal: messages and diagnostics to stderr, data to
+/// stdout.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalReporter;
+
+impl Reporter for TerminalReporter {
+    fn message(&self, line: &str) {
+        output::message(line);
+    }
+
+    fn diagnostic(&self, diagnostic: &Diagnostic) {
+        diagnostic.emit();
+    }
+
+    fn data(&self, text: &str) -> io::Result<()> {
+        output::data(text)
+    }
+}
+
+/// Something the core reported, as delivered by EventReporter.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A line for the person running rdd (Reporter::message).
+    Message(String),
+    /// A warning or hint (Reporter::diagnostic).
+    Diagnostic(Diagnostic),
+    /// Results meant for programs to read (Reporter::data).
+    Data(String),
+    /// How far the operation has got: `bytes` of `total`, if known.
+    Progress { bytes: u64, total: Option<u64> },
+}
+
+/// How often EventReporter reports progress unless told otherwise.
+const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Hands everything the core reports to a callback as an Event.
+///
+/// Progress events only come while progress is enabled (e.g.
+/// CopyConfigBuilder::progress).
+#[derive(Clone)]
+pub struct EventReporter {
+    callback: Arc<dyn Fn(Event) + Send + Sync>,
+    interval: Duration,
+}
+
+impl EventReporter {
+    /// Calls `callback` with every event. It's called from the threads the
+    /// core runs on, so it should return quickly.
+    pub fn new(callback: impl Fn(Event) + Send + Sync + 'static) -> Self {
+        Self {
+            callback: Arc::new(callback),
+            interval: DEFAULT_PROGRESS_INTERVAL,
+        }
+    }
+
+    /// Sends every event down a channel instead, and returns its receiving
+    /// end. Events sent after the receiver is dropped are discarded.
+    pub fn channel() -> (Self, Receiver<Event>) {
+        let (sender, receiver) = unbounded();
+        let reporter = Self::new(move |event| {
+            let _ = sender.send(event);
+        });
+        (reporter, receiver)
+    }
+
+    /// How often progress is reported. Defaults to four times a second.
+    pub fn progress_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+impl Reporter for EventReporter {
+    fn message(&self, line: &str) {
+        (self.callback)(Event::Message(line.to_string()));
+    }
+
+    fn diagnostic(&self, diagnostic: &Diagnostic) {
+        (self.callback)(Event::Diagnostic(diagnostic.clone()));
+    }
+
+    fn data(&self, text: &str) -> io::Result<()> {
+        (self.callback)(Event::Data(text.to_string()));
+        Ok(())
+    }
+
+    fn progress_bar(&self, total: Option<u64>) -> ProgressBar {
+        let bar = ProgressBar::hidden();
+        if let Some(total) = total {
+            bar.set_length(total);
+        }
+        // Only a weak handle, so the thread doesn't keep the bar alive once
+        // the engine is done with it.
+        let weak = bar.downgrade();
+        let (callback, interval) = (self.callback.clone(), self.interval);
+        // Without threads (see utils/threads.rs) there are no progress events.
+        let _ = thread::Builder::new().name("rdd-events".to_string()).spawn(move || {
+            loop {
+                thread::sleep(interval);
+                let Some(bar) = weak.upgrade() else {
+                    break;
+                };
+                callback(Event::Progress {
+                    bytes: bar.position(),
+                    total: bar.length(),
+                });
+                if bar.is_finished() {
+                    break;
+                }
+            }
+        });
+        bar
+    }
+
+    fn message_over(&self, _progress: &ProgressBar, line: &str) {
+        self.message(line);
+    }
+}
+
+/// The reporter used when none is given.
+pub fn default_reporter() -> Arc<dyn Reporter> {
+    Arc::new(TerminalReporter)
+}
+
+/// The progress bar for an operation, or a hidden one when progress is off.
+pub(crate) fn progress_bar(reporter: &dyn Reporter, total: Option<u64>, enabled: bool) -> ProgressBar {
+    if enabled {
+        reporter.progress_bar(total)
+    } else {
+        ProgressBar::hidden()
+    }
+}
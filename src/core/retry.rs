@@ -0,0 +1,183 @@
+// This file implements --retries. Flaky USB card readers and worn cables sometimes fail a read with an I/O error that succeeds when it's simply tried again a moment later. Without retries such a read fails the copy, or with --noerror turns into a filled bad region although the data was perfectly readable.
+
+// Explanation of this file:
+// Backoff : A failed read is tried again up to --retries times. Before the first retry we wait --retry-delay, and the wait doubles before every further one (up to MAX_DELAY), which gives a device that's resetting itself time to come back. Only when the last retry fails too is the error handed on, to --noerror (skip_unreadable in copy.rs) or to the caller, which fails the copy.
+// Writes : With --retry-writes, failed writes are retried the same way. A write that was cut short is continued where it stopped, so no byte is written twice out of place. Streams can't have their writes retried (see config.rs), since a pipe that failed is gone for good.
+// Stopping : Ctrl-C cuts the wait short and ends the retries with the last error. A read then stops the copy cleanly, like Ctrl-C between chunks; a write fails, since its data never reached the output.
+// Errors : Errors that can't go away by waiting (a full disk, a read-only filesystem, permissions, invalid arguments such as a misaligned direct read, a broken pipe) are not retried.
+// Report : Every operation that needed a retry is recorded with its offset and how many retries it took, in CopyStats::retried. The summary lists them (see copy.rs), and so do the JSON stats.
+
+// src/core/retry.rs
+
+use crate::config::CopyConfig;
+use crate::core::mapfile::format_offset;
+use crate::utils::diagnostics::Diagnostic;
+use crate::utils::signals;
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Seek, Write};
+use std::time::Duration;
+
+/// The longest wait between two retries.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// The kind of I/O that was retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IoOperation {
+    Read,
+    Write,
+}
+
+impl IoOperation {
+    /// How it is named in messages and the JSON stats.
+    pub fn name(self) -> &'static str {
+        match self {
+            IoOperation::Read => "read",
+            IoOperation::Write => "write",
+        }
+    }
+}
+
+/// A read or write that failed and was tried again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetriedIo {
+    pub operation: IoOperation,
+    /// Where it was: an offset in the input for reads, in the output for writes.
+    pub offset: u64,
+    /// How many times it was tried again.
+    pub retries: u32,
+    /// Whether one of the retries succeeded.
+    pub recovered: bool,
+}
+
+impl RetriedIo {
+    /// One line of the summary.
+    pub fn summary(&self) -> String {
+        let outcome = match self.recovered {
+            true => "succeeded after",
+            false => "still failed after",
+        };
+        format!(
+            "  {} at offset {} ({}): {} {} retr{}",
+            self.operation.name(),
+            self.offset,
+            format_offset(self.offset),
+            outcome,
+            self.retries,
+            if self.retries == 1 { "y" } else { "ies" }
+        )
+    }
+}
+
+/// Tries an operation that failed with `error` again, up to --retries times
+/// with a growing wait in between, and records how it went in `log`.
+/// Returns the result of the last try.
+pub fn again<T>(
+    config: &CopyConfig,
+    operation: IoOperation,
+    offset: u64,
+    error: io::Error,
+    progress: &ProgressBar,
+    log: &mut Vec<RetriedIo>,
+    mut attempt: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    if config.retries == 0 || !transient(&error) {
+        return Err(error);
+    }
+
+    let path = match operation {
+        IoOperation::Read => &config.input_file,
+        IoOperation::Write => &config.output_file,
+    };
+    let mut error = error;
+    let mut delay = config.retry_delay;
+    for retry in 1..=config.retries {
+        let warning = Diagnostic::warning(format!(
+            "{} of '{}' failed at offset {} ({}): {}; retrying in {:?} ({} of {}).",
+            operation.name(),
+            path,
+            offset,
+            format_offset(offset),
+            error,
+            delay,
+            retry,
+            config.retries
+        ))
+        .with_flag("--retries");
+        progress.suspend(|| config.reporter.diagnostic(&warning));
+        // Ctrl-C ends the retries with the error we have.
+        if !signals::sleep(delay, &*config.reporter, progress) {
+            return Err(error);
+        }
+        delay = (delay * 2).min(MAX_DELAY);
+
+        let result = loop {
+            match attempt() {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        };
+        match result {
+            Ok(value) => {
+                log.push(RetriedIo {
+                    operation,
+                    offset,
+                    retries: retry,
+                    recovered: true,
+                });
+                return Ok(value);
+            }
+            Err(e) if transient(&e) && retry < config.retries => error = e,
+            Err(e) => {
+                log.push(RetriedIo {
+                    operation,
+                    offset,
+                    retries: retry,
+                    recovered: false,
+                });
+                return Err(e);
+            }
+        }
+    }
+    unreachable!("the last retry returns")
+}
+
+/// Writes all of `data` at the position of `output`, retrying failed writes
+/// with --retry-writes. A write cut short carries on where it stopped.
+pub fn write_all(
+    config: &CopyConfig,
+    output: &mut (impl Write + Seek),
+    mut data: &[u8],
+    progress: &ProgressBar,
+    log: &mut Vec<RetriedIo>,
+) -> io::Result<()> {
+    if !config.retry_writes {
+        return output.write_all(data);
+    }
+    while !data.is_empty() {
+        let written = match output.write(data) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                // A failed write leaves the position where it was.
+                let offset = output.stream_position()?;
+                again(config, IoOperation::Write, offset, e, progress, log, || output.write(data))?
+            }
+            Ok(written) => written,
+        };
+        if written == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        data = &data[written..];
+    }
+    Ok(())
+}
+
+/// Whether an error might go away when the operation is tried again.
+fn transient(e: &io::Error) -> bool {
+    use io::ErrorKind::*;
+    !matches!(
+        e.kind(),
+        StorageFull | ReadOnlyFilesystem | PermissionDenied | InvalidInput | Unsupported | BrokenPipe | NotFound
+    )
+}
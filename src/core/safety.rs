@@ -0,0 +1,57 @@
+// This file keeps rdd from overwriting the drive the computer runs from. dd writes wherever it's told, and one wrong letter in of= (sda for sdb) destroys the system disk, or a disk with mounted filesystems that the kernel keeps writing to behind our back, which corrupts it even if it was the right one. Before anything is written, copy and wipe check where the output lies and refuse such drives unless --force is given.
+
+// Explanation of this file:
+// check_target : Finds the drive the output lies on (see find_device in devices.rs); files, and devices that aren't drives, pass without a check. A drive that holds the running system, or one with mounted filesystems, is refused with RddError::UnsafeTarget. A partition counts as its drive, so /dev/sda3 on the system disk is refused too. With --force it's written anyway, after a warning.
+// Windows : Volumes on a raw device there are locked and dismounted before writing (see utils/rawdevice.rs), so only the system drive is refused.
+// describe : How a drive is named in messages and in the confirmation prompt (see wizard.rs): its path, model and size.
+
+// src/core/safety.rs
+
+use crate::core::devices::{find_device, Device};
+use crate::core::reporter::Reporter;
+use crate::error::{RddError, RddResult};
+use crate::utils::rawdevice;
+use indicatif::BinaryBytes;
+
+/// The drive, e.g. "/dev/sdb (SanDisk Ultra, 14.32 GiB)".
+pub fn describe(device: &Device) -> String {
+    match &device.model {
+        Some(model) => format!("{} ({}, {})", device.path, model, BinaryBytes(device.size)),
+        None => format!("{} ({})", device.path, BinaryBytes(device.size)),
+    }
+}
+
+/// Why writing to `path` on `device` is unsafe, if it is.
+pub(crate) fn danger(path: &str, device: &Device) -> Option<String> {
+    if device.system {
+        return Some(format!("{} holds the running system", describe(device)));
+    }
+    // Windows dismounts the volumes for us.
+    if !device.mountpoints.is_empty() && rawdevice::raw_device(path).is_none() {
+        return Some(format!(
+            "{} has mounted filesystems ({})",
+            describe(device),
+            device.mountpoints.join(", ")
+        ));
+    }
+    None
+}
+
+/// Refuses to write to `path` if it lies on a drive that holds the running
+/// system or has mounted filesystems, unless `force` is set. Returns the
+/// drive, if `path` lies on one.
+pub fn check_target(path: &str, force: bool, reporter: &dyn Reporter) -> RddResult<Option<Device>> {
+    let Some(device) = find_device(path) else {
+        return Ok(None);
+    };
+    if let Some(reason) = danger(path, &device) {
+        if !force {
+            return Err(RddError::UnsafeTarget {
+                path: path.to_string(),
+                reason,
+            });
+        }
+        reporter.message(&format!("Warning: writing to '{}' although {} (--force).", path, reason));
+    }
+    Ok(Some(device))
+}
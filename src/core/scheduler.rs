@@ -0,0 +1,170 @@
+// This file implements the back-pressure between the reader and the writers of the multithreaded copy. When the output is much slower than the input (a fast NVMe drive copied onto a USB stick or a network share), the reader would otherwise race ahead and keep every buffer of the copy filled with data the writers can't take yet. That's memory spent for nothing, and the input is read in bursts: flat out until the queue is full, then not at all until a buffer comes back.
+
+// Explanation of this file:
+// Budget : The data in flight, read but not yet written, is counted in bytes, not in buffers, and capped at --max-in-flight. A short read from a pipe takes up only what it holds, and a --bs 64M copy doesn't get 64M per queue slot for free. The buffers themselves are allocated only when the reader needs another one, and never more than fit in the budget (see parallel.rs), so a copy onto a fast output never allocates the whole budget.
+// Writer rate : Every write the writers finish is counted, and once per RATE_WINDOW the rate they sustained is folded into a moving average. Single slow writes (a drive flushing its cache, a network hiccup) don't swing it much.
+// Pacing : While the queue is less than half full, the reader reads as fast as the input allows. Beyond that the output is the bottleneck, and the reader paces itself to the writers' rate, sleeping after each chunk for as long as the writers need to write one. The queue then stays about half full: enough to keep the writers busy, without filling up and stalling the reader. Only if the writers slow down further does the queue reach the budget, and the reader then waits until they've written enough.
+// Stopping : A writer that fails, or is done, closes the scheduler, so a reader waiting for room wakes up and stops (see parallel.rs).
+// Summary : The time the reader spent waiting for the writers is reported once the copy is done, together with their rate, which tells whether the output was the bottleneck.
+
+// src/core/scheduler.rs
+
+use indicatif::BinaryBytes;
+use serde::{Deserialize, Serialize};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the writers' rate is measured.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// How much a new measurement of the writers' rate counts in the average.
+const RATE_WEIGHT: f64 = 0.3;
+
+/// How the reader and the writers kept pace over a whole copy.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackpressureStats {
+    /// Time the reader spent waiting for the writers.
+    pub waited: Duration,
+    /// The writers' average rate in bytes per second, if the copy lasted long
+    /// enough to measure it.
+    pub writer_rate: Option<f64>,
+}
+
+impl BackpressureStats {
+    /// A short summary for the end of a copy, if the reader had to wait.
+    pub fn summary(&self) -> Option<String> {
+        if self.waited.as_secs_f64() < 1.0 {
+            return None;
+        }
+        let rate = self
+            .writer_rate
+            .map_or(String::new(), |rate| format!(", which wrote about {}/s", BinaryBytes(rate as u64)));
+        Some(format!(
+            "The output was the bottleneck: reads waited {:.0}s for the writers{}",
+            self.waited.as_secs_f64(),
+            rate
+        ))
+    }
+}
+
+/// Caps the bytes in flight between a reader and its writers, and paces the
+/// reader to the writers' rate when they fall behind.
+#[derive(Debug)]
+pub struct Scheduler {
+    /// Most bytes in flight at once.
+    budget: u64,
+    state: Mutex<State>,
+    /// Signalled whenever bytes are written or the scheduler is closed.
+    room: Condvar,
+}
+
+#[derive(Debug)]
+struct State {
+    in_flight: u64,
+    /// Bytes written since `window_start`.
+    window_bytes: u64,
+    window_start: Instant,
+    /// The writers' average rate in bytes per second, once measured.
+    rate: Option<f64>,
+    /// How long the reader slept or waited for the writers.
+    waited: Duration,
+    closed: bool,
+}
+
+impl Scheduler {
+    /// A scheduler that lets at most `budget` bytes be in flight. A single
+    /// chunk is let through even if it's bigger.
+    pub fn new(budget: u64) -> Self {
+        Self {
+            budget: budget.max(1),
+            state: Mutex::new(State {
+                in_flight: 0,
+                window_bytes: 0,
+                window_start: Instant::now(),
+                rate: None,
+                waited: Duration::ZERO,
+                closed: false,
+            }),
+            room: Condvar::new(),
+        }
+    }
+
+    /// Called by the reader before it hands `len` bytes to the writers. Waits
+    /// until they fit in the budget, and when the writers are behind, for
+    /// as long as they need to write them. Returns false if the scheduler was
+    /// closed meanwhile, so nothing should be sent.
+    pub fn reserve(&self, len: u64) -> bool {
+        let started = Instant::now();
+        let mut state = self.lock();
+        while !state.closed && state.in_flight > 0 && state.in_flight + len > self.budget {
+            state = self.room.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        if state.closed {
+            return false;
+        }
+        state.in_flight += len;
+
+        let pace = match state.rate {
+            Some(rate) if state.in_flight > self.budget / 2 => Duration::from_secs_f64(len as f64 / rate),
+            _ => Duration::ZERO,
+        };
+        drop(state);
+        if !pace.is_zero() {
+            thread::sleep(pace);
+        }
+
+        self.lock().waited += started.elapsed();
+        true
+    }
+
+    /// Runs `wait`, which waits for the writers in some other way, and counts
+    /// the time it takes as waiting.
+    pub fn wait_for<T>(&self, wait: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = wait();
+        self.lock().waited += started.elapsed();
+        result
+    }
+
+    /// Called by a writer once it has written `len` bytes.
+    pub fn release(&self, len: u64) {
+        let mut state = self.lock();
+        state.in_flight = state.in_flight.saturating_sub(len);
+        state.window_bytes += len;
+
+        let elapsed = state.window_start.elapsed();
+        if elapsed >= RATE_WINDOW {
+            let measured = state.window_bytes as f64 / elapsed.as_secs_f64();
+            state.rate = Some(match state.rate {
+                Some(rate) => rate + RATE_WEIGHT * (measured - rate),
+                None => measured,
+            });
+            state.window_bytes = 0;
+            state.window_start = Instant::now();
+        }
+        drop(state);
+        self.room.notify_all();
+    }
+
+    /// Wakes the reader and makes every further reserve() fail, because a
+    /// writer failed or is done.
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.room.notify_all();
+    }
+
+    /// How long the reader waited for the writers, and how fast they wrote.
+    pub fn totals(&self) -> BackpressureStats {
+        let state = self.lock();
+        BackpressureStats {
+            waited: state.waited,
+            writer_rate: state.rate,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // A panicking writer leaves the counters intact.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,361 @@
+// This file implements the 'scrub' command, which re-checks an archive of images made with rdd. An image that sits on a backup drive for years can decay, get truncated by a bad move, or disappear, and nobody notices until the day it's needed. Every copy made with --manifest-out recorded the digest of its data; a scrub finds those manifests, reads the images they describe again and checks them against that digest, so problems turn up while there's still another copy to restore from.
+
+// Explanation of this file:
+// Finding artifacts : Every path given is either a manifest or a directory, which is searched recursively for files that start like a manifest (see manifest::is_manifest), whatever they're named. Symbolic links aren't followed, so a link to the archive itself can't make the walk go round in circles.
+// Finding the image : The image is the output recorded in the manifest. If it isn't there any more, a file of the same name next to the manifest is used, since manifests and images are usually moved together.
+// Checking : The image is hashed with the algorithm of the recorded digest, up to the number of bytes the copy wrote, through a decompressor if it was written with --compress (see compress.rs). --rate-limit applies to all images together, like it does to a copy (see throttle.rs).
+// What can't be checked : Manifests without a digest, copies to stdout or devices, and copies that didn't start at offset 0 of their output (--seek, ranges lists) are skipped and reported as such, rather than counted as failures.
+// The report : One line per image (or one JSON object with --report-format json), then a summary. Corrupted, missing and unreadable images don't stop the scrub; once every image was checked, they fail it with RddError::ScrubFailed.
+// Signals : Like a copy, a scrub checks for SIGUSR1 and Ctrl-C between blocks (see utils/signals.rs).
+
+// src/core/scrub.rs
+
+use crate::cli::StatsFormat;
+use crate::config::ScrubConfig;
+use crate::core::compress::Filters;
+use crate::core::hash::{DataDigest, StreamHasher};
+use crate::core::manifest::{self, Record};
+use crate::core::reporter::progress_bar;
+use crate::core::split::chunk_name;
+use crate::core::throttle::Throttle;
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::json::Json;
+use crate::utils::signals;
+use indicatif::BinaryBytes;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// What a scrub found out about one image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Health {
+    /// The image has the recorded digest.
+    Healthy,
+    /// The image has another digest than the recorded one.
+    Corrupted { actual: DataDigest },
+    /// Neither the recorded image nor one next to the manifest exists.
+    Missing,
+    /// The manifest or the image couldn't be read.
+    Unreadable(String),
+    /// There's nothing the image could be checked against; says why.
+    Skipped(String),
+}
+
+impl Health {
+    /// The word the report uses for it.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Health::Healthy => "healthy",
+            Health::Corrupted { .. } => "corrupted",
+            Health::Missing => "missing",
+            Health::Unreadable(_) => "unreadable",
+            Health::Skipped(_) => "skipped",
+        }
+    }
+
+    /// Whether this fails the scrub.
+    pub fn is_failure(&self) -> bool {
+        matches!(self, Health::Corrupted { .. } | Health::Missing | Health::Unreadable(_))
+    }
+}
+
+/// One image and its manifest.
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    pub manifest: String,
+    /// The image that was checked, or the recorded one if none was found.
+    pub image: Option<String>,
+    pub expected: Option<DataDigest>,
+    /// Bytes of the image that were read.
+    pub bytes: u64,
+    pub health: Health,
+}
+
+/// What a finished scrub found.
+#[derive(Debug, Clone)]
+pub struct ScrubReport {
+    pub artifacts: Vec<Artifact>,
+    pub elapsed: Duration,
+}
+
+impl ScrubReport {
+    /// How many artifacts have the health `label`.
+    pub fn count(&self, label: &str) -> u64 {
+        self.artifacts.iter().filter(|a| a.health.label() == label).count() as u64
+    }
+
+    /// How many artifacts fail the scrub.
+    pub fn failures(&self) -> u64 {
+        self.artifacts.iter().filter(|a| a.health.is_failure()).count() as u64
+    }
+}
+
+/// Checks every image described by a manifest in `config.paths`, reports
+/// what it found, and fails if any image is corrupted, missing or
+/// unreadable.
+pub fn run_scrub(config: &ScrubConfig) -> RddResult<ScrubReport> {
+    let mut manifests = Vec::new();
+    for path in &config.paths {
+        find_manifests(Path::new(path), &mut manifests)?;
+    }
+    if manifests.is_empty() {
+        return Err(RddError::Config(format!(
+            "No rdd manifests found in {}. Scrub checks the images of copies made with --manifest-out.",
+            config.paths.join(", ")
+        )));
+    }
+
+    let started = Instant::now();
+    let mut throttle = config.rate_limit.map(Throttle::new);
+    let mut artifacts = Vec::new();
+    for path in manifests {
+        let artifact = check(config, &path.to_string_lossy(), throttle.as_mut())?;
+        if config.report_format == StatsFormat::Text {
+            config.reporter.message(&describe(&artifact));
+        }
+        artifacts.push(artifact);
+    }
+    let report = ScrubReport {
+        artifacts,
+        elapsed: started.elapsed(),
+    };
+
+    match config.report_format {
+        StatsFormat::Text => config.reporter.message(&summary(&report)),
+        StatsFormat::Json => config.reporter.data(&format!("{:#}\n", report_json(&report)))?,
+    }
+    match report.failures() {
+        0 => Ok(report),
+        failed => Err(RddError::ScrubFailed {
+            failed,
+            checked: report.artifacts.len() as u64,
+        }),
+    }
+}
+
+/// Adds `path` to `manifests` if it's a manifest file, or every manifest
+/// below it if it's a directory, in name order.
+fn find_manifests(path: &Path, manifests: &mut Vec<PathBuf>) -> RddResult<()> {
+    let display = path.to_string_lossy();
+    let metadata = fs::symlink_metadata(path).with_path(&display)?;
+    if metadata.is_file() || (metadata.is_symlink() && path.is_file()) {
+        if manifest::is_manifest(path) {
+            manifests.push(path.to_path_buf());
+        }
+        return Ok(());
+    }
+    if !metadata.is_dir() {
+        return Ok(());
+    }
+
+    let mut entries = fs::read_dir(path)
+        .with_path(&display)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .with_path(&display)?;
+    entries.sort();
+    for entry in entries {
+        // Don't follow links below the paths we were given.
+        if !fs::symlink_metadata(&entry).with_path(&entry.to_string_lossy())?.is_symlink() {
+            find_manifests(&entry, manifests)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks the image of the manifest at `path`.
+fn check(config: &ScrubConfig, path: &str, throttle: Option<&mut Throttle>) -> RddResult<Artifact> {
+    let mut artifact = Artifact {
+        manifest: path.to_string(),
+        image: None,
+        expected: None,
+        bytes: 0,
+        health: Health::Healthy,
+    };
+    let record = match manifest::load_record(path) {
+        Ok(record) => record,
+        Err(e) => {
+            artifact.health = Health::Unreadable(e.to_string());
+            return Ok(artifact);
+        }
+    };
+    let copy = &record.config;
+    // A split image is read as a whole through its first chunk.
+    let recorded = match copy.split_size {
+        Some(_) => chunk_name(&copy.output_file, 0),
+        None => copy.output_file.clone(),
+    };
+    artifact.image = Some(recorded.clone());
+    artifact.expected = copy.expected_digest.clone();
+
+    if let Some(reason) = unchecked_reason(&record) {
+        artifact.health = Health::Skipped(reason.to_string());
+        return Ok(artifact);
+    }
+    let Some(image) = locate_image(path, &recorded) else {
+        artifact.health = Health::Missing;
+        return Ok(artifact);
+    };
+    artifact.image = Some(image.clone());
+    if !Path::new(&image).is_file() {
+        artifact.health = Health::Skipped("the copy was written to a device, not an image file".to_string());
+        return Ok(artifact);
+    }
+
+    let Some(expected) = artifact.expected.clone() else {
+        return Ok(artifact);
+    };
+    match hash_image(config, &image, &expected, record.bytes_copied, throttle) {
+        Ok((actual, bytes)) => {
+            artifact.bytes = bytes;
+            if actual != expected {
+                artifact.health = Health::Corrupted { actual };
+            }
+        }
+        Err(e @ RddError::Interrupted { .. }) => return Err(e),
+        Err(e) => artifact.health = Health::Unreadable(e.to_string()),
+    }
+    Ok(artifact)
+}
+
+/// Why the image of `record` can't be checked against its digest, if it
+/// can't.
+fn unchecked_reason(record: &Record) -> Option<&'static str> {
+    let copy = &record.config;
+    if copy.expected_digest.is_none() {
+        Some("the manifest has no digest of the data")
+    } else if copy.output_is_stdout() {
+        Some("the copy was written to stdout")
+    } else if copy.output_is_network() {
+        Some("the copy was sent over the network")
+    } else if copy.seek > 0 || copy.ranges.is_some() {
+        Some("the copy didn't start at the beginning of its output (--seek or a ranges list)")
+    } else {
+        None
+    }
+}
+
+/// Where the image recorded as `output` in the manifest at `manifest` is
+/// now: where it was written, or next to the manifest.
+fn locate_image(manifest: &str, output: &str) -> Option<String> {
+    if Path::new(output).exists() {
+        return Some(output.to_string());
+    }
+    let beside = Path::new(manifest).with_file_name(Path::new(output).file_name()?);
+    beside.exists().then(|| beside.to_string_lossy().into_owned())
+}
+
+/// Hashes `image` like the copy that wrote it hashed its data, up to
+/// `limit` bytes. Returns the digest and how many bytes were read.
+fn hash_image(
+    config: &ScrubConfig,
+    image: &str,
+    expected: &DataDigest,
+    limit: Option<u64>,
+    mut throttle: Option<&mut Throttle>,
+) -> RddResult<(DataDigest, u64)> {
+    let mut filters = Filters::default();
+    let mut file = filters.open_decompressed(image)?;
+    let total = limit.or_else(|| file.metadata().ok().map(|m| m.len()));
+    let progress = progress_bar(&*config.reporter, total, config.show_progress);
+    progress.set_message(image.to_string());
+
+    let mut hasher = StreamHasher::new(expected.algorithm);
+    let mut buffer = vec![0u8; config.block_size];
+    let mut pos = 0u64;
+    loop {
+        if let Err(e) = signals::check(&*config.reporter, &progress) {
+            progress.finish_and_clear();
+            return Err(e);
+        }
+        let want = match limit {
+            Some(limit) => (limit - pos).min(buffer.len() as u64) as usize,
+            None => buffer.len(),
+        };
+        if want == 0 {
+            break;
+        }
+        let n = file.read(&mut buffer[..want]).at_offset(image, pos)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+        if let Some(throttle) = throttle.as_deref_mut() {
+            throttle.wait(n as u64);
+        }
+        pos += n as u64;
+        progress.inc(n as u64);
+    }
+    progress.finish_and_clear();
+    filters.finish_input(file)?;
+
+    let digest = DataDigest {
+        algorithm: expected.algorithm,
+        hex: hasher.finalize(),
+    };
+    Ok((digest, pos))
+}
+
+/// The report line for `artifact`.
+fn describe(artifact: &Artifact) -> String {
+    let image = artifact.image.as_deref().unwrap_or(&artifact.manifest);
+    let expected = artifact.expected.as_ref().map_or_else(String::new, |d| d.to_string());
+    let detail = match &artifact.health {
+        Health::Healthy => format!("{} match {}", BinaryBytes(artifact.bytes), expected),
+        Health::Corrupted { actual } => format!("expected {}, got {} over {} bytes", expected, actual, artifact.bytes),
+        Health::Missing => format!("recorded in '{}'", artifact.manifest),
+        Health::Unreadable(reason) | Health::Skipped(reason) => reason.clone(),
+    };
+    format!("{:<10} {}: {}", artifact.health.label(), image, detail)
+}
+
+/// The summary line after the report.
+fn summary(report: &ScrubReport) -> String {
+    let bytes: u64 = report.artifacts.iter().map(|a| a.bytes).sum();
+    format!(
+        "Scrubbed {} image(s), {} read in {:.2}s: {} healthy, {} corrupted, {} missing, {} unreadable, {} skipped.",
+        report.artifacts.len(),
+        BinaryBytes(bytes),
+        report.elapsed.as_secs_f64(),
+        report.count("healthy"),
+        report.count("corrupted"),
+        report.count("missing"),
+        report.count("unreadable"),
+        report.count("skipped")
+    )
+}
+
+/// The report as JSON.
+fn report_json(report: &ScrubReport) -> Json {
+    let artifacts = report
+        .artifacts
+        .iter()
+        .map(|artifact| {
+            let (actual, reason) = match &artifact.health {
+                Health::Corrupted { actual } => (Some(actual.to_string()), None),
+                Health::Unreadable(reason) | Health::Skipped(reason) => (None, Some(reason.clone())),
+                _ => (None, None),
+            };
+            Json::object([
+                ("manifest", Json::str(&artifact.manifest)),
+                ("image", Json::or_null(artifact.image.as_ref(), Json::str)),
+                ("status", Json::str(artifact.health.label())),
+                ("expected_digest", Json::or_null(artifact.expected.as_ref(), |d| Json::str(d.to_string()))),
+                ("actual_digest", Json::or_null(actual, Json::str)),
+                ("bytes", Json::Int(artifact.bytes)),
+                ("reason", Json::or_null(reason, Json::str)),
+            ])
+        })
+        .collect();
+    let count = |label| Json::Int(report.count(label));
+    Json::object([
+        ("artifacts", Json::Array(artifacts)),
+        ("healthy", count("healthy")),
+        ("corrupted", count("corrupted")),
+        ("missing", count("missing")),
+        ("unreadable", count("unreadable")),
+        ("skipped", count("skipped")),
+        ("elapsed_seconds", Json::Float(report.elapsed.as_secs_f64())),
+    ])
+}
@@ -0,0 +1,298 @@
+// This file implements --seekable, which writes --compress zstd output in the zstd seekable format. A plain .zst image is one long frame: to get at a partition in the middle of it, everything before has to be decompressed first. The seekable format cuts the data into frames that are compressed independently and ends the file with a seek table saying where each frame starts, so a reader can jump straight to the frames it needs and decompress only those. Any zstd decompresses the file as usual, front to back.
+
+// Explanation of this file:
+// Frames : Every FRAME_SIZE bytes of the input (4 MiB unless --seekable=SIZE says otherwise) become one zstd frame. Each is compressed by its own run of the zstd program, the way --compress runs it for a whole stream (see compress.rs); a run per frame is what guarantees the frames don't depend on each other.
+// In parallel : Frames are compressed by up to one zstd per core at the same time (one with --low-memory), and written out in order as they're done. Each holds its frame's data until then, so memory use is about two frames per core.
+// The seek table : After the last frame comes a zstd skippable frame (which decompressors pass over) holding the compressed and decompressed size of every frame and a footer ending in SEEKABLE_MAGIC, as the format's specification in the zstd sources lays out. The sizes are 32-bit, so frames are at most MAX_FRAME_SIZE. Frames carry zstd's own checksum, so the table's optional per-frame checksums are left out.
+// Reading : SeekTable::read finds the table from the footer and checks that the frame sizes add up to the file, and decompress_frame decompresses one frame. core/archive.rs uses both for 'rdd extract'.
+// SeekableWriter : Works like the splitter of split.rs: the copy writes into a pipe, and a thread on the other end cuts what comes out into frames and writes them to the output (or into the splitter, with --split-size). A frame that fails to compress is reported by the thread, since the copy only sees a broken pipe.
+
+// src/core/seekable.rs
+
+use crate::cli::Compression;
+use crate::core::compress::{failed, spawn, tool};
+use crate::core::open::pipe_file;
+use crate::core::reporter::Reporter;
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::diagnostics::Diagnostic;
+use indicatif::BinaryBytes;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::num::NonZeroUsize;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// How much of the input goes into one frame, unless --seekable says.
+pub const FRAME_SIZE: u64 = 4 << 20;
+
+/// The largest frame size --seekable accepts. Frame sizes are stored in 32
+/// bits, and a frame of data that doesn't compress comes out a little
+/// larger than it went in.
+pub const MAX_FRAME_SIZE: u64 = 1 << 30;
+
+/// The magic number of the skippable frame that holds the seek table.
+pub const SKIPPABLE_MAGIC: u32 = 0x184D_2A5E;
+
+/// The magic number at the very end of a seekable file.
+pub const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+
+/// The length of the seek table's footer: the number of frames, the
+/// descriptor and SEEKABLE_MAGIC.
+pub const FOOTER_LEN: usize = 9;
+
+/// One frame of a seekable file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub compressed: u32,
+    pub decompressed: u32,
+}
+
+/// The seek table written at the end of a seekable file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeekTable {
+    pub frames: Vec<Frame>,
+}
+
+impl SeekTable {
+    /// The table as the skippable frame that ends the file.
+    pub fn to_bytes(&self) -> RddResult<Vec<u8>> {
+        let count = u32::try_from(self.frames.len())
+            .map_err(|_| RddError::Config("Too many frames for a zstd seek table; use a larger --seekable frame size.".to_string()))?;
+        let content = self.frames.len() * 8 + FOOTER_LEN;
+        let mut out = Vec::with_capacity(8 + content);
+        out.extend_from_slice(&SKIPPABLE_MAGIC.to_le_bytes());
+        out.extend_from_slice(&(content as u32).to_le_bytes());
+        for frame in &self.frames {
+            out.extend_from_slice(&frame.compressed.to_le_bytes());
+            out.extend_from_slice(&frame.decompressed.to_le_bytes());
+        }
+        out.extend_from_slice(&count.to_le_bytes());
+        // No checksums; the reserved bits stay zero.
+        out.push(0);
+        out.extend_from_slice(&SEEKABLE_MAGIC.to_le_bytes());
+        Ok(out)
+    }
+
+    /// Reads the seek table at the end of `file`, opened from `path`. None
+    /// if the file doesn't end in one.
+    pub fn read(file: &mut File, path: &str) -> RddResult<Option<Self>> {
+        let len = file.metadata().with_path(path)?.len();
+        if len < (8 + FOOTER_LEN) as u64 {
+            return Ok(None);
+        }
+        let mut footer = [0u8; FOOTER_LEN];
+        file.seek(SeekFrom::Start(len - FOOTER_LEN as u64)).with_path(path)?;
+        file.read_exact(&mut footer).with_path(path)?;
+        if footer[5..9] != SEEKABLE_MAGIC.to_le_bytes() {
+            return Ok(None);
+        }
+        let count = u64::from(u32::from_le_bytes(footer[0..4].try_into().unwrap()));
+        let entry_len = match footer[4] & 0x80 {
+            0 => 8,
+            _ => 12,
+        };
+        let table_len = 8 + count * entry_len + FOOTER_LEN as u64;
+        let damaged = |why: &str| RddError::Config(format!("'{}' has a damaged zstd seek table: {}.", path, why));
+        if table_len > len {
+            return Err(damaged("it lists more frames than the file can hold"));
+        }
+        let mut table = vec![0u8; table_len as usize];
+        file.seek(SeekFrom::Start(len - table_len)).with_path(path)?;
+        file.read_exact(&mut table).with_path(path)?;
+        let u32_at = |off: usize| u32::from_le_bytes(table[off..off + 4].try_into().unwrap());
+        if u32_at(0) != SKIPPABLE_MAGIC || u64::from(u32_at(4)) != table_len - 8 {
+            return Err(damaged("its skippable frame header doesn't match its footer"));
+        }
+        let frames: Vec<_> = (0..count as usize)
+            .map(|i| {
+                let off = 8 + i * entry_len as usize;
+                Frame {
+                    compressed: u32_at(off),
+                    decompressed: u32_at(off + 4),
+                }
+            })
+            .collect();
+        let table = SeekTable { frames };
+        if table.compressed_len() + table_len != len {
+            return Err(damaged("the frame sizes don't add up to the file's length"));
+        }
+        Ok(Some(table))
+    }
+
+    /// The number of bytes the frames hold once decompressed.
+    pub fn decompressed_len(&self) -> u64 {
+        self.frames.iter().map(|frame| u64::from(frame.decompressed)).sum()
+    }
+
+    /// The number of bytes the frames take up in the file.
+    pub fn compressed_len(&self) -> u64 {
+        self.frames.iter().map(|frame| u64::from(frame.compressed)).sum()
+    }
+}
+
+/// Writes what comes out of a pipe to a file as seekable zstd.
+pub struct SeekableWriter {
+    thread: JoinHandle<RddResult<(File, SeekTable)>>,
+    reporter: Arc<dyn Reporter>,
+}
+
+impl SeekableWriter {
+    /// Starts compressing into `output` (opened from `path`) in frames of
+    /// `frame_size` bytes, at `level` or zstd's default. Returns the writer
+    /// with the pipe that feeds it.
+    pub fn start(
+        output: File,
+        path: &str,
+        frame_size: u64,
+        level: Option<u32>,
+        low_memory: bool,
+        reporter: Arc<dyn Reporter>,
+    ) -> RddResult<(Self, File)> {
+        let (reader, writer) = io::pipe().with_path(path)?;
+        let pipe = pipe_file(writer).with_path(path)?;
+        let reader = pipe_file(reader).with_path(path)?;
+        let workers = match low_memory {
+            true => 1,
+            false => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        };
+
+        let (thread_path, thread_reporter) = (path.to_string(), reporter.clone());
+        let thread = thread::Builder::new()
+            .name("rdd-seekable".to_string())
+            .spawn(move || {
+                let result = write_frames(reader, output, &thread_path, frame_size as usize, level, workers);
+                // The copy only sees a broken pipe; say what really happened.
+                if let Err(e) = &result {
+                    thread_reporter.diagnostic(&Diagnostic::error(e.to_string()).with_flag("--seekable"));
+                }
+                result
+            })
+            .with_path(path)?;
+        Ok((Self { thread, reporter }, pipe))
+    }
+
+    /// Waits for the last frames and writes the seek table, once the pipe
+    /// is closed. Returns the output file.
+    pub fn finish(self) -> RddResult<File> {
+        let (output, table) = self
+            .thread
+            .join()
+            .map_err(|_| RddError::Channel("the seekable zstd thread panicked".to_string()))??;
+        self.reporter.message(&format!(
+            "Wrote {} in {} seekable zstd frame(s), {} compressed.",
+            BinaryBytes(table.decompressed_len()),
+            table.frames.len(),
+            BinaryBytes(table.compressed_len())
+        ));
+        Ok(output)
+    }
+}
+
+/// The writer thread: cuts `reader` into frames of `frame_size`, has up to
+/// `workers` of them compressed at once, and writes them to `output` in
+/// order, followed by the seek table.
+fn write_frames(
+    mut reader: File,
+    mut output: File,
+    path: &str,
+    frame_size: usize,
+    level: Option<u32>,
+    workers: usize,
+) -> RddResult<(File, SeekTable)> {
+    let mut table = SeekTable::default();
+    let mut pending: VecDeque<(usize, JoinHandle<RddResult<Vec<u8>>>)> = VecDeque::new();
+    let mut written = 0u64;
+    let mut write_next = |pending: &mut VecDeque<(usize, JoinHandle<RddResult<Vec<u8>>>)>, table: &mut SeekTable| {
+        let Some((len, job)) = pending.pop_front() else {
+            return Ok(());
+        };
+        let compressed = job
+            .join()
+            .map_err(|_| RddError::Channel("a zstd frame thread panicked".to_string()))??;
+        output.write_all(&compressed).at_offset(path, written)?;
+        written += compressed.len() as u64;
+        table.frames.push(Frame {
+            compressed: compressed.len() as u32,
+            decompressed: len as u32,
+        });
+        Ok::<_, RddError>(())
+    };
+
+    loop {
+        let mut data = vec![0u8; frame_size];
+        let len = fill(&mut reader, &mut data).with_path(path)?;
+        if len == 0 {
+            break;
+        }
+        data.truncate(len);
+        if pending.len() >= workers {
+            write_next(&mut pending, &mut table)?;
+        }
+        let job = thread::Builder::new()
+            .name("rdd-zstd-frame".to_string())
+            .spawn(move || compress_frame(data, level))
+            .with_path(path)?;
+        pending.push_back((len, job));
+        if len < frame_size {
+            break;
+        }
+    }
+    while !pending.is_empty() {
+        write_next(&mut pending, &mut table)?;
+    }
+    output.write_all(&table.to_bytes()?).at_offset(path, written)?;
+    Ok((output, table))
+}
+
+/// Compresses `data` into a frame of its own with the zstd program.
+fn compress_frame(data: Vec<u8>, level: Option<u32>) -> RddResult<Vec<u8>> {
+    let mut args = vec!["-c".to_string(), "-q".to_string()];
+    if let Some(level) = level {
+        args.push(format!("-{}", level));
+    }
+    run_zstd(&args, data, "compressing")
+}
+
+/// Decompresses one frame of a seekable file with the zstd program.
+pub(crate) fn decompress_frame(data: Vec<u8>) -> RddResult<Vec<u8>> {
+    run_zstd(&["-d", "-c", "-q"], data, "decompressing")
+}
+
+/// Runs the zstd program with `args` on `data`, and returns what it wrote.
+fn run_zstd(args: &[impl AsRef<std::ffi::OsStr>], data: Vec<u8>, what: &str) -> RddResult<Vec<u8>> {
+    let format = Compression::Zstd;
+    let mut command = Command::new(tool(format));
+    command.args(args);
+    let mut child = spawn(command.stdin(Stdio::piped()).stdout(Stdio::piped()), format)?;
+    let mut stdin = child.stdin.take();
+    // zstd writes while it reads, so the frame is fed from another thread.
+    let feeder = thread::spawn(move || stdin.as_mut().map(|pipe| pipe.write_all(&data)));
+    let mut out = Vec::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        stdout.read_to_end(&mut out).with_path(tool(format))?;
+    }
+    // Write errors only mean zstd is gone, which its status reports.
+    let _ = feeder.join();
+    let status = child.wait().with_path(tool(format))?;
+    if !status.success() {
+        return Err(failed(tool(format), what, status));
+    }
+    Ok(out)
+}
+
+/// Reads until `buf` is full or the pipe is closed, retrying when a signal
+/// interrupts the read.
+fn fill(reader: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
@@ -0,0 +1,215 @@
+// This file implements --simulate-device. Tuning a rescue (--noerror, --retries, the block size, --rate-limit, the thread count) against a real failing drive wears out the drive, and a failing drive is rarely at hand when writing a CI job. With --simulate-device MODEL, the input is read as if it came from a device described in the MODEL file: every read takes the time the model says, and reads of its bad regions fail. The data itself still comes from the input, typically an image file, so the output can be compared with it afterwards.
+
+// Explanation of this file:
+// The model : A text file in the style of our ranges files (see ranges.rs). '#' starts a comment. "latency 2ms" is the time every read takes before any data moves, "bandwidth 40M" the bytes per second the device transfers, "slow OFFSET LENGTH 50ms" adds latency to reads in a region (weak sectors that the drive retries internally), "bad OFFSET LENGTH" is a region that can never be read, and "flaky OFFSET LENGTH N" one whose reads fail the first N times and then succeed. Offsets and lengths are in bytes, decimal or 0x hexadecimal, and sizes take the suffixes of --bs. "time virtual" makes the model keep its time on paper instead of sleeping it, for quick CI runs.
+// Failing reads : Like a real drive, a read that touches a bad region fails as a whole, with an I/O error, even if only one byte of it is bad. So a smaller --bs or --max-io loses less data to --noerror, just as it would on the drive. A flaky region counts every read that touches it, so whether --retries rescues it doesn't depend on timing.
+// Determinism : Which reads fail only depends on the model and the order of the reads, never on the clock, so the same command gives the same bad regions on every run. With "time virtual" the summary reports the simulated time too.
+// Where it's used : The single-threaded copy and the reader of the multithreaded copy read through SimulatedDevice. The io_uring engine reads in the kernel and the fast paths don't read at all, so build() rejects the io_uring engine with --simulate-device, and the fast paths aren't used. Only the input is simulated.
+// Summary : The reads, failed reads and simulated time are reported once the copy is done, and in the JSON stats.
+
+// src/core/simulate.rs
+
+use crate::config::parse_size;
+use crate::core::mapfile::{format_offset, parse_offset};
+use crate::core::ranges::ByteRange;
+use crate::error::{IoResultExt, RddError, RddResult};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::thread;
+use std::time::Duration;
+
+/// What a region of the simulated device does to reads that touch it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegionKind {
+    /// Every read takes this much longer.
+    Slow(Duration),
+    /// Every read fails.
+    Bad,
+    /// Reads fail this many times, then succeed.
+    Flaky(u32),
+}
+
+/// A region of the simulated device.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub range: ByteRange,
+    pub kind: RegionKind,
+}
+
+/// The contents of a device model file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceModel {
+    /// Time every read takes before any data moves.
+    pub latency: Duration,
+    /// Bytes per second the device transfers; unlimited if None.
+    pub bandwidth: Option<u64>,
+    pub regions: Vec<Region>,
+    /// Whether the simulated time is only counted, not slept.
+    pub virtual_time: bool,
+}
+
+impl DeviceModel {
+    /// Reads and parses the model file at `path`.
+    pub fn load(path: &str) -> RddResult<Self> {
+        let contents = fs::read_to_string(path).with_path(path)?;
+        Self::parse(path, &contents)
+    }
+
+    /// Parses the contents of a model file. `path` is only used in error
+    /// messages.
+    pub fn parse(path: &str, contents: &str) -> RddResult<Self> {
+        let invalid =
+            |line: usize, reason: &str| RddError::Config(format!("{}:{}: invalid device model line: {}", path, line, reason));
+        let offset = |line: usize, s: &str| parse_offset(s).ok_or_else(|| invalid(line, "invalid offset or length"));
+        let duration = |line: usize, s: &str| parse_duration(s).ok_or_else(|| invalid(line, "invalid time (e.g. 5ms, 2s)"));
+
+        let mut model = DeviceModel::default();
+        for (i, line) in contents.lines().enumerate() {
+            let line_no = i + 1;
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let region = |start: &str, len: &str, kind| -> RddResult<Region> {
+                Ok(Region {
+                    range: ByteRange {
+                        offset: offset(line_no, start)?,
+                        length: offset(line_no, len)?,
+                    },
+                    kind,
+                })
+            };
+            match fields.as_slice() {
+                [] => {}
+                [first, ..] if first.starts_with('#') => {}
+                ["latency", time] => model.latency = duration(line_no, time)?,
+                ["bandwidth", size] => {
+                    let bytes = parse_size(size).map_err(|_| invalid(line_no, "invalid bandwidth"))?;
+                    if bytes == 0 {
+                        return Err(invalid(line_no, "the bandwidth cannot be zero"));
+                    }
+                    model.bandwidth = Some(bytes as u64);
+                }
+                ["time", "virtual"] => model.virtual_time = true,
+                ["time", "real"] => model.virtual_time = false,
+                ["slow", start, len, time] => {
+                    let kind = RegionKind::Slow(duration(line_no, time)?);
+                    model.regions.push(region(start, len, kind)?);
+                }
+                ["bad", start, len] => model.regions.push(region(start, len, RegionKind::Bad)?),
+                ["flaky", start, len, times] => {
+                    let times = times.parse().map_err(|_| invalid(line_no, "invalid number of failures"))?;
+                    model.regions.push(region(start, len, RegionKind::Flaky(times))?);
+                }
+                _ => {
+                    return Err(invalid(
+                        line_no,
+                        "expected 'latency', 'bandwidth', 'time', 'slow', 'bad' or 'flaky'",
+                    ));
+                }
+            }
+        }
+        Ok(model)
+    }
+}
+
+/// What the simulated device did over a whole copy.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SimulationStats {
+    pub reads: u64,
+    /// Reads that failed because they touched a bad or flaky region.
+    pub failed_reads: u64,
+    /// The time the reads took on the simulated device.
+    pub simulated: Duration,
+}
+
+impl SimulationStats {
+    /// A one-line summary for the end of a copy.
+    pub fn summary(&self) -> String {
+        format!(
+            "Simulated device: {} reads, {} failed, {:.2}s of device time",
+            self.reads,
+            self.failed_reads,
+            self.simulated.as_secs_f64()
+        )
+    }
+}
+
+/// The input device of a copy, as described by its model.
+pub struct SimulatedDevice {
+    model: DeviceModel,
+    /// How often each region was read, for flaky regions.
+    reads_of: Vec<u32>,
+    stats: SimulationStats,
+}
+
+impl SimulatedDevice {
+    pub fn new(model: DeviceModel) -> Self {
+        Self {
+            reads_of: vec![0; model.regions.len()],
+            model,
+            stats: SimulationStats::default(),
+        }
+    }
+
+    /// Reads from `input` into `buf`, as a read at offset `pos` of the
+    /// simulated device: it takes the model's time, and fails with an I/O
+    /// error if it touches a region that can't be read (yet).
+    pub fn read(&mut self, input: &mut File, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let end = pos + buf.len() as u64;
+        let mut time = self.model.latency;
+        let mut failure = None;
+        for (region, reads) in self.model.regions.iter().zip(&mut self.reads_of) {
+            if region.range.offset >= end || region.range.end() <= pos {
+                continue;
+            }
+            *reads += 1;
+            match region.kind {
+                RegionKind::Slow(extra) => time += extra,
+                RegionKind::Bad => failure = failure.or(Some(region.range.offset.max(pos))),
+                RegionKind::Flaky(times) if *reads <= times => failure = failure.or(Some(region.range.offset.max(pos))),
+                RegionKind::Flaky(_) => {}
+            }
+        }
+
+        self.stats.reads += 1;
+        let result = match failure {
+            Some(at) => {
+                self.stats.failed_reads += 1;
+                Err(io::Error::other(format!(
+                    "simulated media error at offset {} ({})",
+                    at,
+                    format_offset(at)
+                )))
+            }
+            None => input.read(buf),
+        };
+        if let (Ok(n), Some(bandwidth)) = (&result, self.model.bandwidth) {
+            time += Duration::from_secs_f64(*n as f64 / bandwidth as f64);
+        }
+
+        self.stats.simulated += time;
+        if !self.model.virtual_time {
+            thread::sleep(time);
+        }
+        result
+    }
+
+    /// What the device did so far.
+    pub fn stats(&self) -> SimulationStats {
+        self.stats.clone()
+    }
+}
+
+/// Parses a time like "5ms", "250us" or "2s".
+fn parse_duration(s: &str) -> Option<Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let seconds = match unit {
+        "us" => number / 1_000_000.0,
+        "ms" => number / 1000.0,
+        "s" => number,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
@@ -0,0 +1,243 @@
+// This file implements --split-size and the reading of chunk sets. FAT32 can't hold a file of 4 GiB or more, and many upload services limit the size of a single file, so a disk image sometimes has to be stored in pieces. With --split-size, the output "image.img" is written as image.img.000, image.img.001, ..., each at most that size. An input named like the first chunk of such a set is read as the whole set, one chunk after the other.
+
+// Explanation of this file:
+// Naming : Chunks are numbered from 000 with at least three digits, like the output of `split -d -a 3`, so `cat image.img.*` also joins them. A set is found by looking for image.img.001, .002, ... until one is missing. Without a .001 it's an ordinary file that happens to end in .000, and is read as it is: a set of one chunk holds the same bytes anyway.
+// Splitter : The copy engines write one File. With --split-size, that File is a pipe, and a thread on the other end writes what comes out of it to the chunks, syncing each one before it moves on to the next (and along the way, or not at all, as --sync-mode says; see syncmode.rs). Chunks left over from an earlier, longer copy to the same name are removed at the end, so the set is never mistaken for a longer one.
+// Joiner : Reading a chunk set works the other way round: a thread writes the chunks into a pipe one after another, and the copy reads the pipe. A chunk that can't be read fails the copy once the transfer is done (see Filters::finish_input in compress.rs), rather than looking like the end of the data.
+// Streams : Both pipes are streams, so split outputs and chunk-set inputs come with the restrictions of stdout and stdin (see config.rs). Compression and splitting combine: the compressor writes into the splitter, and a chunk set is joined before it's decompressed.
+
+// src/core/split.rs
+
+use crate::core::open::pipe_file;
+use crate::core::reporter::Reporter;
+use crate::core::syncmode::{SyncMode, Syncer};
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::diagnostics::Diagnostic;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// How much the splitter moves at once, at most.
+pub const BUFFER_SIZE: usize = 1024 * 1024;
+
+/// The name of chunk `index` of `path`, e.g. "image.img.000".
+pub fn chunk_name(path: &str, index: usize) -> String {
+    format!("{}.{:03}", path, index)
+}
+
+/// If `path` names the first chunk of a set ("image.img.000", with an
+/// "image.img.001" next to it), the paths of all chunks of the set, in
+/// order.
+pub fn chunk_set(path: &str) -> Option<Vec<String>> {
+    let base = path.strip_suffix(".000")?;
+    if base.is_empty() || !Path::new(path).is_file() || !Path::new(&chunk_name(base, 1)).is_file() {
+        return None;
+    }
+    let chunks = (0..)
+        .map(|index| chunk_name(base, index))
+        .take_while(|chunk| Path::new(chunk).is_file())
+        .collect();
+    Some(chunks)
+}
+
+/// Writes what comes out of a pipe to chunks of at most `size` bytes.
+pub struct Splitter {
+    thread: JoinHandle<RddResult<(File, usize)>>,
+    path: String,
+    reporter: Arc<dyn Reporter>,
+}
+
+impl Splitter {
+    /// Creates the first chunk of `path` with `options` and starts the
+    /// splitter, which moves up to `buffer_size` bytes at once and syncs the
+    /// chunks as `sync_mode` says. Returns it with the pipe that feeds it.
+    pub fn start(
+        path: &str,
+        size: u64,
+        buffer_size: usize,
+        options: &OpenOptions,
+        sync_mode: SyncMode,
+        reporter: Arc<dyn Reporter>,
+    ) -> RddResult<(Self, File)> {
+        let first = chunk_name(path, 0);
+        let chunk = options.open(&first).with_path(&first)?;
+        let (reader, writer) = io::pipe().with_path(path)?;
+        let pipe = pipe_file(writer).with_path(path)?;
+
+        let (thread_path, thread_options, thread_reporter) = (path.to_string(), options.clone(), reporter.clone());
+        let thread = thread::Builder::new()
+            .name("rdd-split".to_string())
+            .spawn(move || {
+                let result = split(reader, chunk, &thread_path, size, buffer_size, &thread_options, sync_mode);
+                // The copy only sees a broken pipe; say what really happened.
+                if let Err(e) = &result {
+                    thread_reporter.diagnostic(&Diagnostic::error(e.to_string()).with_flag("--split-size"));
+                }
+                result
+            })
+            .with_path(path)?;
+        Ok((
+            Self {
+                thread,
+                path: path.to_string(),
+                reporter,
+            },
+            pipe,
+        ))
+    }
+
+    /// Waits for the splitter to write the rest, once the pipe is closed.
+    /// Returns the last chunk, which is synced already (unless --sync-mode
+    /// none).
+    pub fn finish(self) -> RddResult<File> {
+        let (last, count) = self
+            .thread
+            .join()
+            .map_err(|_| RddError::Channel("the splitter thread panicked".to_string()))??;
+
+        let mut stale = 0;
+        loop {
+            let chunk = chunk_name(&self.path, count + stale);
+            if !Path::new(&chunk).is_file() {
+                break;
+            }
+            fs::remove_file(&chunk).with_path(&chunk)?;
+            stale += 1;
+        }
+
+        self.reporter.message(&format!(
+            "Output split into {} chunk(s): {} to {}.",
+            count,
+            chunk_name(&self.path, 0),
+            chunk_name(&self.path, count - 1)
+        ));
+        if stale > 0 {
+            self.reporter.message(&format!(
+                "Removed {} chunk(s) after {} left from an earlier copy.",
+                stale,
+                chunk_name(&self.path, count - 1)
+            ));
+        }
+        Ok(last)
+    }
+}
+
+/// The splitter thread: copies `reader` into `chunk` and the chunks after
+/// it. Returns the last chunk and how many there are.
+fn split(
+    mut reader: io::PipeReader,
+    mut chunk: File,
+    path: &str,
+    size: u64,
+    buffer_size: usize,
+    options: &OpenOptions,
+    sync_mode: SyncMode,
+) -> RddResult<(File, usize)> {
+    let mut buffer = vec![0u8; buffer_size];
+    let syncer = Syncer::new(sync_mode);
+    let finish_chunk = |chunk: &File, index| match sync_mode {
+        SyncMode::None => Ok(()),
+        _ => chunk.sync_all().with_path(&chunk_name(path, index)),
+    };
+    let (mut index, mut written) = (0, 0u64);
+    loop {
+        // Only start the next chunk once there's data for it, so the set
+        // doesn't end in an empty one.
+        if written == size {
+            let mut next = [0u8; 1];
+            if read_some(&mut reader, &mut next).with_path(path)? == 0 {
+                break;
+            }
+            finish_chunk(&chunk, index)?;
+            index += 1;
+            let name = chunk_name(path, index);
+            chunk = options.open(&name).with_path(&name)?;
+            chunk.write_all(&next).at_offset(&name, 0)?;
+            written = 1;
+            continue;
+        }
+
+        let want = (size - written).min(buffer.len() as u64) as usize;
+        let n = read_some(&mut reader, &mut buffer[..want]).with_path(path)?;
+        if n == 0 {
+            break;
+        }
+        chunk.write_all(&buffer[..n]).at_offset(&chunk_name(path, index), written)?;
+        syncer.after_write(&chunk, &chunk_name(path, index), n as u64)?;
+        written += n as u64;
+    }
+    finish_chunk(&chunk, index)?;
+    Ok((chunk, index + 1))
+}
+
+/// Reads into `buf`, retrying when a signal interrupts the read.
+fn read_some(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    loop {
+        match reader.read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    }
+}
+
+/// Reads the chunks of a set as one stream.
+pub struct Joiner {
+    thread: JoinHandle<RddResult<()>>,
+}
+
+impl Joiner {
+    /// Starts writing `chunks` into a pipe, one after another. Returns the
+    /// joiner with the pipe to read them from.
+    pub fn start(chunks: Vec<String>) -> RddResult<(Self, File)> {
+        let first = chunks.first().cloned().unwrap_or_default();
+        let (reader, mut writer) = io::pipe().with_path(&first)?;
+        let pipe = pipe_file(reader).with_path(&first)?;
+        let thread = thread::Builder::new()
+            .name("rdd-join".to_string())
+            .spawn(move || {
+                for chunk in &chunks {
+                    let mut file = File::open(chunk).with_path(chunk)?;
+                    io::copy(&mut file, &mut writer).with_path(chunk)?;
+                }
+                Ok(())
+            })
+            .with_path(&first)?;
+        Ok((Self { thread }, pipe))
+    }
+
+    /// Waits for the joiner once the pipe is closed. A reader that stopped
+    /// early (e.g. with --count) leaves it with a broken pipe, which is fine.
+    pub fn finish(self) -> RddResult<()> {
+        let result = self
+            .thread
+            .join()
+            .map_err(|_| RddError::Channel("the joiner thread panicked".to_string()))?;
+        match result {
+            Err(RddError::IoAt { source, .. }) if source.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_first_chunk_with_a_second_is_a_set() {
+        let dir = std::env::temp_dir().join(format!("rdd-split-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("image.img").to_string_lossy().into_owned();
+        fs::write(chunk_name(&base, 0), b"first").unwrap();
+        let alone = chunk_set(&chunk_name(&base, 0));
+
+        fs::write(chunk_name(&base, 1), b"second").unwrap();
+        let set = chunk_set(&chunk_name(&base, 0));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(alone, None);
+        assert_eq!(set, Some(vec![chunk_name(&base, 0), chunk_name(&base, 1)]));
+    }
+}
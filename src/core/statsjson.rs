@@ -0,0 +1,255 @@
+// This file implements --stats-format json. Scripts that run rdd want the results of a copy as data: how much was read and written, how long it took, which digests came out, and where the bad blocks are. With --stats-format json, the text summary is replaced by a single JSON object (see utils/json.rs), printed once the copy has succeeded.
+
+// Explanation of this file:
+// stats_json : Turns CopyStats into JSON. Sizes are in bytes and times in seconds, and every key is always present: what didn't apply to this copy (no --verify, no health monitoring, ...) is null, so scripts don't have to check whether a key exists.
+// Where it goes : To the file given by --stats-file, or else to stdout. When the copied data itself goes to stdout, the JSON is reported as a message instead, which TerminalReporter prints on stderr (see reporter.rs).
+
+// src/core/statsjson.rs
+
+use crate::config::CopyConfig;
+use crate::core::copy::CopyStats;
+use crate::core::hash::algorithm_flag;
+use crate::error::{IoResultExt, RddResult};
+use crate::utils::json::Json;
+use std::fs;
+
+/// Builds the JSON object for a finished copy.
+pub fn stats_json(config: &CopyConfig, stats: &CopyStats) -> Json {
+    let secs = stats.elapsed.as_secs_f64();
+    let seconds = |d: std::time::Duration| Json::Float(d.as_secs_f64());
+
+    Json::object([
+        ("rdd_version", Json::str(env!("CARGO_PKG_VERSION"))),
+        ("input", Json::str(&config.input_file)),
+        ("output", Json::str(&config.output_file)),
+        ("block_size", Json::Int(config.block_size as u64)),
+        ("tuned_block_size", Json::or_null(stats.block_size_tuning.as_ref(), |tuning| Json::Int(tuning.block_size as u64))),
+        ("bytes_read", Json::Int(stats.bytes_copied)),
+        ("bytes_written", Json::Int(stats.bytes_written)),
+        ("bytes_resumed", Json::Int(stats.bytes_resumed)),
+        ("bytes_sparse", Json::Int(stats.bytes_sparse)),
+        ("bytes_discarded", Json::Int(stats.bytes_discarded)),
+        ("bytes_excluded", Json::Int(stats.bytes_excluded)),
+        (
+            "blocks",
+            Json::object([
+                ("total", Json::Int(stats.blocks_copied)),
+                ("full", Json::Int(stats.blocks_copied - stats.partial_blocks)),
+                ("partial", Json::Int(stats.partial_blocks)),
+                ("written", Json::Int(stats.blocks_written)),
+                ("unchanged", Json::Int(stats.blocks_unchanged)),
+            ]),
+        ),
+        ("elapsed_seconds", Json::Float(secs)),
+        (
+            "throughput_bytes_per_second",
+            Json::or_null((secs > 0.0).then_some(stats.bytes_copied as f64 / secs), Json::Float),
+        ),
+        (
+            "cpu",
+            Json::or_null(stats.cpu.as_ref(), |cpu| {
+                Json::object([
+                    ("user_seconds", seconds(cpu.user)),
+                    ("system_seconds", seconds(cpu.system)),
+                    ("context_switches", Json::or_null(cpu.context_switches, Json::Int)),
+                ])
+            }),
+        ),
+        (
+            "data_digest",
+            Json::or_null(stats.data_digest.as_ref(), |digest| Json::str(digest.to_string())),
+        ),
+        (
+            "digests",
+            Json::object(stats.digests.iter().map(|digest| (algorithm_flag(digest.algorithm), Json::str(&digest.hex)))),
+        ),
+        (
+            "verification",
+            Json::or_null(stats.verification.as_ref(), |report| {
+                Json::object([
+                    ("algorithm", Json::str(algorithm_flag(report.algorithm))),
+                    ("source_digest", Json::str(&report.source_digest)),
+                    ("output_digest", Json::str(&report.output_digest)),
+                ])
+            }),
+        ),
+        (
+            "bad_blocks",
+            Json::Array(
+                stats
+                    .bad_regions
+                    .iter()
+                    .map(|region| {
+                        Json::object([
+                            ("offset", Json::Int(region.offset)),
+                            ("length", Json::Int(region.length)),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ),
+        (
+            "retries",
+            Json::Array(
+                stats
+                    .retried
+                    .iter()
+                    .map(|retry| {
+                        Json::object([
+                            ("operation", Json::str(retry.operation.name())),
+                            ("offset", Json::Int(retry.offset)),
+                            ("retries", Json::Int(retry.retries.into())),
+                            ("recovered", Json::Bool(retry.recovered)),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ),
+        (
+            "flush_probe",
+            Json::or_null(stats.flush_probe.as_ref(), |probe| {
+                Json::object([
+                    ("probes", Json::Int(probe.probes)),
+                    ("stable_bytes_per_second", Json::or_null(probe.stable_rate(), Json::Float)),
+                    ("apparent_bytes_per_second", Json::or_null(probe.apparent_rate(), Json::Float)),
+                ])
+            }),
+        ),
+        (
+            "follow",
+            Json::or_null(stats.follow.as_ref(), |follow| {
+                Json::object([
+                    ("waited_seconds", seconds(follow.waited)),
+                    ("stopped", Json::or_null(follow.stopped.as_deref(), Json::str)),
+                ])
+            }),
+        ),
+        (
+            "health",
+            Json::or_null(stats.health.as_ref(), |health| {
+                Json::object([
+                    ("peak_temperature_celsius", Json::or_null(health.peak_temperature, Json::Float)),
+                    ("new_io_errors", Json::Int(health.new_io_errors)),
+                    ("paused_seconds", seconds(health.paused)),
+                ])
+            }),
+        ),
+        (
+            "power",
+            Json::or_null(stats.power.as_ref(), |power| {
+                Json::object([
+                    ("paused_on_battery_seconds", seconds(power.paused_on_battery)),
+                    ("suspends", Json::Int(power.suspends.into())),
+                    ("suspended_seconds", seconds(power.suspended)),
+                ])
+            }),
+        ),
+        (
+            "simulation",
+            Json::or_null(stats.simulation.as_ref(), |simulation| {
+                Json::object([
+                    ("reads", Json::Int(simulation.reads)),
+                    ("failed_reads", Json::Int(simulation.failed_reads)),
+                    ("simulated_seconds", seconds(simulation.simulated)),
+                ])
+            }),
+        ),
+        (
+            "coalesced",
+            Json::or_null(stats.coalesced.as_ref(), |coalesced| {
+                Json::object([
+                    ("writes", Json::Int(coalesced.writes)),
+                    ("physical_writes", Json::Int(coalesced.physical_writes)),
+                    ("size", Json::Int(coalesced.size as u64)),
+                ])
+            }),
+        ),
+        (
+            "backpressure",
+            Json::or_null(stats.backpressure.as_ref(), |backpressure| {
+                Json::object([
+                    ("waited_seconds", seconds(backpressure.waited)),
+                    ("writer_bytes_per_second", Json::or_null(backpressure.writer_rate, Json::Float)),
+                ])
+            }),
+        ),
+        (
+            "boot",
+            Json::or_null(stats.boot.as_ref(), |boot| {
+                Json::object([
+                    ("mbr_signature", Json::Bool(boot.mbr_signature)),
+                    ("gpt", Json::Bool(boot.gpt)),
+                    ("efi_system_partition", Json::Bool(boot.efi_system_partition)),
+                    ("iso9660", Json::Bool(boot.iso9660)),
+                    ("el_torito", Json::Bool(boot.el_torito)),
+                    ("isohybrid", Json::Bool(boot.isohybrid())),
+                    ("likely_bootable", Json::Bool(boot.likely_bootable())),
+                ])
+            }),
+        ),
+        (
+            "filesystems",
+            Json::or_null(stats.filesystems.as_ref(), |checks| {
+                Json::Array(
+                    checks
+                        .iter()
+                        .map(|check| {
+                            Json::object([
+                                ("offset", Json::Int(check.offset)),
+                                ("kind", Json::str(check.kind.to_string())),
+                                ("files", Json::Int(check.files)),
+                                ("directories", Json::Int(check.directories)),
+                                ("bytes_used", Json::Int(check.bytes_used)),
+                                ("problems", Json::Array(check.problems.iter().map(Json::str).collect())),
+                            ])
+                        })
+                        .collect(),
+                )
+            }),
+        ),
+        ("manifest", Json::or_null(config.manifest_out.as_ref(), Json::str)),
+        ("block_hashes", Json::or_null(config.block_hashes.as_ref(), Json::str)),
+        ("hash_log", Json::or_null(config.hash_log.as_ref(), Json::str)),
+        (
+            "block_compare",
+            Json::or_null(stats.block_compare.as_ref(), |compare| {
+                Json::object([
+                    ("manifest", Json::or_null(config.compare_manifest.as_ref(), Json::str)),
+                    ("compared", Json::Int(compare.compared)),
+                    ("differing_blocks", Json::Int(compare.differing_blocks)),
+                    ("unrecorded", Json::Int(compare.unrecorded)),
+                    (
+                        "differing",
+                        Json::Array(
+                            compare
+                                .differing
+                                .iter()
+                                .map(|region| {
+                                    Json::object([
+                                        ("offset", Json::Int(region.offset)),
+                                        ("length", Json::Int(region.length)),
+                                    ])
+                                })
+                                .collect(),
+                        ),
+                    ),
+                ])
+            }),
+        ),
+    ])
+}
+
+/// Reports the JSON results of a finished copy, or writes them to the stats
+/// file.
+pub fn emit(config: &CopyConfig, stats: &CopyStats) -> RddResult<()> {
+    let json = format!("{:#}\n", stats_json(config, stats));
+    match &config.stats_file {
+        Some(path) => fs::write(path, json).with_path(path),
+        // Stdout carries the copied data.
+        None if config.output_is_stdout() => {
+            config.reporter.message(json.trim_end());
+            Ok(())
+        }
+        None => Ok(config.reporter.data(&json)?),
+    }
+}
@@ -0,0 +1,128 @@
+// This file decides when the output is synced (--sync-mode). By default rdd writes through the page cache and syncs once, at the end, which is fastest and what dd's conv=fsync does. But then a power cut or a pulled USB stick in the middle of a long copy loses everything the cache held, and the final sync can take minutes on a slow device. Syncing along the way bounds how much is at stake, at the cost of speed; not syncing at all suits scratch copies that don't need to survive a crash.
+
+// Explanation of this file:
+// SyncMode : none (never sync, not even at the end), end (the default: once, after the transfer), interval:SIZE (whenever SIZE bytes were written since the last sync, and at the end) or per-block (after every write, and at the end). A write is one block, or one --max-io chunk of a larger block.
+// Syncer : Every engine's writer owns one (the parallel writers share one) and tells it about each write with wrote(), which says when a sync is due. It only counts, so the writer can first push out what it still holds (the coalescer of copy.rs) and then call sync(). after_write does both for writers that hold nothing back. With --split-size, the engines only write into a pipe (for_copy leaves them at end), and the splitter thread on the other end syncs the chunk it's writing instead (see split.rs).
+// Syncing : The syncs along the way use sync_data, which skips metadata that doesn't matter for reading the data back, like the modification time. The final sync (sync_output in copy.rs) is a full sync_all, followed by the directory sync.
+// Streams : stdout and the compressor's output are written by someone else or can't be synced, so build() rejects interval and per-block with them.
+
+// src/core/syncmode.rs
+
+use crate::config::{parse_size, CopyConfig};
+use crate::error::{IoResultExt, RddError, RddResult};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// When the output is synced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SyncMode {
+    /// Never, not even at the end.
+    None,
+    /// Once, after the transfer.
+    #[default]
+    End,
+    /// Whenever this many bytes were written since the last sync, and at the end.
+    Interval(u64),
+    /// After every write, and at the end.
+    PerBlock,
+}
+
+impl SyncMode {
+    /// Parses "none", "end", "interval:SIZE" (e.g. interval:64M) or "per-block".
+    pub fn parse(text: &str) -> RddResult<Self> {
+        let text = text.trim();
+        let mode = match text {
+            "none" => SyncMode::None,
+            "end" => SyncMode::End,
+            "per-block" => SyncMode::PerBlock,
+            _ => match text.strip_prefix("interval:") {
+                Some(size) => SyncMode::Interval(parse_size(size)? as u64),
+                None => {
+                    return Err(RddError::Config(format!(
+                        "Invalid sync mode '{}': expected none, end, interval:SIZE (e.g. interval:64M) or per-block.",
+                        text
+                    )));
+                }
+            },
+        };
+        if mode == SyncMode::Interval(0) {
+            return Err(RddError::Config("Sync interval cannot be zero.".to_string()));
+        }
+        Ok(mode)
+    }
+
+    /// Whether the output is synced during the transfer, not just at the end.
+    pub fn periodic(self) -> bool {
+        matches!(self, SyncMode::Interval(_) | SyncMode::PerBlock)
+    }
+}
+
+impl fmt::Display for SyncMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncMode::None => write!(f, "none"),
+            SyncMode::End => write!(f, "end"),
+            SyncMode::Interval(bytes) => write!(f, "interval:{}", bytes),
+            SyncMode::PerBlock => write!(f, "per-block"),
+        }
+    }
+}
+
+/// Counts what's written to the output and says when to sync it. Writer
+/// threads may share one.
+pub struct Syncer {
+    mode: SyncMode,
+    /// Bytes written since the last sync.
+    unsynced: AtomicU64,
+}
+
+impl Syncer {
+    pub fn new(mode: SyncMode) -> Self {
+        Self {
+            mode,
+            unsynced: AtomicU64::new(0),
+        }
+    }
+
+    /// The Syncer of a copy engine. An engine that writes into a pipe
+    /// (to the splitter) leaves the syncing to whoever is on the other end.
+    pub fn for_copy(config: &CopyConfig) -> Self {
+        match config.output_is_stream() {
+            true => Self::new(SyncMode::End),
+            false => Self::new(config.sync_mode),
+        }
+    }
+
+    /// Records a write of `bytes`, and returns whether the output should be
+    /// synced now. Of several writers that cross the interval together,
+    /// only one is told to.
+    pub fn wrote(&self, bytes: u64) -> bool {
+        match self.mode {
+            SyncMode::None | SyncMode::End => false,
+            SyncMode::PerBlock => true,
+            SyncMode::Interval(interval) => {
+                let unsynced = self.unsynced.fetch_add(bytes, Ordering::Relaxed) + bytes;
+                unsynced >= interval
+                    && self
+                        .unsynced
+                        .compare_exchange(unsynced, 0, Ordering::Relaxed, Ordering::Relaxed)
+                        .is_ok()
+            }
+        }
+    }
+
+    /// Records a write of `bytes` to `output`, and syncs it if that's due.
+    pub fn after_write(&self, output: &File, path: &str, bytes: u64) -> RddResult<()> {
+        if self.wrote(bytes) {
+            sync(output, path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Syncs the data written to `output` so far.
+pub fn sync(output: &File, path: &str) -> RddResult<()> {
+    output.sync_data().with_path(path)
+}
@@ -0,0 +1,51 @@
+// This file implements --rate-limit. Copying onto a shared NAS, or off a disk that other programs also use, can saturate the link and make everything else crawl. Throttling the copy to a fixed number of bytes per second leaves room for everyone else.
+
+// Explanation of this file:
+// Token bucket: The bucket fills up with `rate` tokens (bytes) per second, up to one second's worth. Every chunk we read takes its size in tokens out of it. If there aren't enough, we sleep until the bucket has refilled. Over any longer stretch, the copy can't go faster than the rate. The bucket starts empty, but after a pause a short burst of up to one second's worth is allowed, which keeps the copy from stuttering.
+// Large chunks: A chunk may be bigger than the whole bucket (e.g. --bs 64M at 10M/s). The bucket is then allowed to go negative, and we sleep until it's back at zero, so the average rate still holds.
+// Stopping : The sleep is cut short by Ctrl-C (see utils/signals.rs), since at low rates it can last as long as a whole block takes.
+// Where it's applied: The single-threaded copy throttles right after each read. The multithreaded copy throttles in its reader thread; the writers can only write what the reader passes on, so the whole pipeline runs at the limit.
+
+// src/core/throttle.rs
+
+use crate::utils::signals;
+use std::time::{Duration, Instant};
+
+/// Limits the rate at which bytes pass through it.
+#[derive(Debug)]
+pub struct Throttle {
+    /// Bytes per second.
+    rate: f64,
+    /// Tokens in the bucket; negative after a chunk bigger than the bucket.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Throttle {
+    /// A throttle that lets `bytes_per_sec` bytes through per second.
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let rate = bytes_per_sec.max(1) as f64;
+        // Start empty, so even a short copy stays under the limit.
+        Self {
+            rate,
+            tokens: 0.0,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes `bytes` out of the bucket, sleeping first if the copy is ahead
+    /// of the rate limit.
+    pub fn wait(&mut self, bytes: u64) {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last_refill).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.rate);
+        self.last_refill = now;
+
+        self.tokens -= bytes as f64;
+        if self.tokens < 0.0 {
+            // The tokens we're short of come back at `rate` per second;
+            // the refill after the sleep accounts for them.
+            signals::sleep_quietly(Duration::from_secs_f64(-self.tokens / self.rate));
+        }
+    }
+}
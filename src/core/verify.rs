@@ -0,0 +1,294 @@
+// This file implements the 'verify' command, which checks an existing copy against its source without copying again. It's the same check --verify does at the end of a copy, but it can be run at any time, for example on a USB stick that was written yesterday.
+
+// Explanation of this file:
+// Two reader threads: Each file gets its own thread that reads it block by block and hashes what it read. The two files usually live on different devices, so reading and hashing both at once takes about as long as doing just the slower one.
+// Comparing: The main thread receives the blocks of both files side by side and compares them byte by byte. That's how we can report the first offset at which they differ, which a digest alone can't tell. Both readers fill every block completely (except at the end of the file), so the blocks always line up.
+// Without threads : On platforms that have none (see utils/threads.rs), a block of each file is read, hashed and compared in turn instead. It's slower, but gives the same report.
+// Buffers: As in the parallel copy, a fixed number of buffers per file travel from the reader to the main thread and back again, so memory use doesn't grow with the file size.
+// VerifyReport struct: Both digests, the number of bytes compared and the first difference, if any. A mismatch, in content or in length, fails the command with RddError::VerificationFailure.
+
+// src/core/verify.rs
+
+use crate::config::VerifyConfig;
+use crate::core::hash::{algorithm_name, StreamHasher};
+use crate::core::mapfile::format_offset;
+use crate::core::reporter::progress_bar;
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::threads;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::thread;
+
+/// How many buffers each reader thread can have in flight.
+const BUFFERS_PER_READER: usize = 4;
+
+/// The outcome of comparing two files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyReport {
+    pub source_digest: String,
+    pub target_digest: String,
+    /// How many bytes were read from the source and from the target.
+    pub source_bytes: u64,
+    pub target_bytes: u64,
+    /// The first offset at which the two differ, if they differ.
+    pub first_difference: Option<u64>,
+}
+
+impl VerifyReport {
+    pub fn matches(&self) -> bool {
+        self.first_difference.is_none() && self.source_digest == self.target_digest
+    }
+}
+
+/// One block read by a reader thread.
+struct Block {
+    buffer: Vec<u8>,
+    len: usize,
+}
+
+/// Hashes `config.source` and `config.target` side by side, compares them,
+/// and prints the result.
+pub fn run_verify(config: &VerifyConfig) -> RddResult<VerifyReport> {
+    let mut source = File::open(&config.source).with_path(&config.source)?;
+    let mut target = File::open(&config.target).with_path(&config.target)?;
+
+    let total = match config.limit {
+        Some(limit) => Some(limit),
+        None => source.metadata().ok().filter(|m| m.is_file()).map(|m| m.len()),
+    };
+    let progress = progress_bar(&*config.reporter, total, config.show_progress);
+    progress.set_message("verifying");
+
+    // Without threads, the two files are read in turn.
+    let (comparison, source_digest, target_digest) = match threads::AVAILABLE {
+        true => compare_side_by_side(config, &mut source, &mut target, &progress)?,
+        false => compare_in_turn(config, &mut source, &mut target, &progress)?,
+    };
+    progress.finish_and_clear();
+
+    let report = VerifyReport {
+        source_digest,
+        target_digest,
+        source_bytes: comparison.source_bytes,
+        target_bytes: comparison.target_bytes,
+        first_difference: comparison.first_difference,
+    };
+
+    print_report(config, &report)?;
+
+    if !report.matches() {
+        return Err(RddError::VerificationFailure {
+            expected: report.source_digest,
+            actual: report.target_digest,
+        });
+    }
+    Ok(report)
+}
+
+/// Reads and hashes both files in their own threads, and compares them in
+/// this one. Returns the comparison and the digests of both files.
+fn compare_side_by_side(
+    config: &VerifyConfig,
+    source: &mut File,
+    target: &mut File,
+    progress: &ProgressBar,
+) -> RddResult<(Comparison, String, String)> {
+    let (source_tx, source_rx) = bounded::<Block>(BUFFERS_PER_READER);
+    let (target_tx, target_rx) = bounded::<Block>(BUFFERS_PER_READER);
+    let (source_free_tx, source_free_rx) = bounded::<Vec<u8>>(BUFFERS_PER_READER);
+    let (target_free_tx, target_free_rx) = bounded::<Vec<u8>>(BUFFERS_PER_READER);
+    for _ in 0..BUFFERS_PER_READER {
+        for free in [&source_free_tx, &target_free_tx] {
+            free.send(vec![0; config.block_size])
+                .map_err(|_| RddError::Channel("buffer pool closed".to_string()))?;
+        }
+    }
+
+    let (comparison, source_digest, target_digest) = thread::scope(|scope| {
+        let source_reader = scope.spawn(|| read_blocks(config, source, &config.source, source_tx, source_free_rx));
+        let target_reader = scope.spawn(|| read_blocks(config, target, &config.target, target_tx, target_free_rx));
+
+        let comparison = compare_blocks(source_rx, target_rx, source_free_tx, target_free_tx, progress);
+
+        let source_digest = source_reader.join().unwrap_or_else(|_| Err(thread_panicked()));
+        let target_digest = target_reader.join().unwrap_or_else(|_| Err(thread_panicked()));
+        (comparison, source_digest, target_digest)
+    });
+
+    // A read error ends that file's stream early, which looks like a
+    // mismatch. Report the error instead.
+    Ok((comparison, source_digest?, target_digest?))
+}
+
+/// Reads, hashes and compares a block of each file in turn, for platforms
+/// without threads.
+fn compare_in_turn(
+    config: &VerifyConfig,
+    source: &mut File,
+    target: &mut File,
+    progress: &ProgressBar,
+) -> RddResult<(Comparison, String, String)> {
+    let mut hashers = (StreamHasher::new(config.algorithm), StreamHasher::new(config.algorithm));
+    let mut buffers = (vec![0; config.block_size], vec![0; config.block_size]);
+    let mut comparison = Comparison::default();
+    let mut remaining = config.limit;
+
+    while remaining != Some(0) {
+        let want = remaining.map_or(config.block_size, |left| left.min(config.block_size as u64) as usize);
+        let source_len = read_full(source, &mut buffers.0[..want]).at_offset(&config.source, comparison.source_bytes)?;
+        let target_len = read_full(target, &mut buffers.1[..want]).at_offset(&config.target, comparison.target_bytes)?;
+        if source_len == 0 && target_len == 0 {
+            break;
+        }
+
+        let (source_data, target_data) = (&buffers.0[..source_len], &buffers.1[..target_len]);
+        hashers.0.update(source_data);
+        hashers.1.update(target_data);
+        comparison.add(source_data, target_data, progress);
+        if let Some(left) = remaining.as_mut() {
+            *left -= source_len.max(target_len) as u64;
+        }
+    }
+    Ok((comparison, hashers.0.finalize(), hashers.1.finalize()))
+}
+
+/// A reader thread: reads `file` in blocks of `config.block_size`, up to
+/// `config.limit` bytes, hashes them and sends them to the main thread.
+/// Returns the digest of everything it read.
+fn read_blocks(
+    config: &VerifyConfig,
+    file: &mut File,
+    path: &str,
+    blocks: Sender<Block>,
+    free: Receiver<Vec<u8>>,
+) -> RddResult<String> {
+    let mut hasher = StreamHasher::new(config.algorithm);
+    let mut pos = 0u64;
+    let mut remaining = config.limit;
+
+    while remaining != Some(0) {
+        // The main thread has stopped taking blocks.
+        let Ok(mut buffer) = free.recv() else {
+            break;
+        };
+        let want = remaining.map_or(buffer.len(), |left| left.min(buffer.len() as u64) as usize);
+        let len = read_full(file, &mut buffer[..want]).at_offset(path, pos)?;
+        if len == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..len]);
+        pos += len as u64;
+        if let Some(left) = remaining.as_mut() {
+            *left -= len as u64;
+        }
+        if blocks.send(Block { buffer, len }).is_err() {
+            break;
+        }
+    }
+    Ok(hasher.finalize())
+}
+
+/// How the two files compare so far.
+#[derive(Default)]
+struct Comparison {
+    /// How many bytes were seen from each file.
+    source_bytes: u64,
+    target_bytes: u64,
+    /// The first offset at which they differ.
+    first_difference: Option<u64>,
+}
+
+impl Comparison {
+    /// Compares the next block of each file. A file that has ended gives an
+    /// empty one.
+    fn add(&mut self, source_data: &[u8], target_data: &[u8], progress: &ProgressBar) {
+        if self.first_difference.is_none() {
+            let common = source_data.len().min(target_data.len());
+            self.first_difference = source_data[..common]
+                .iter()
+                .zip(&target_data[..common])
+                .position(|(a, b)| a != b)
+                // One file ends inside this block.
+                .or((source_data.len() != target_data.len()).then_some(common))
+                .map(|i| self.source_bytes + i as u64);
+        }
+
+        self.source_bytes += source_data.len() as u64;
+        self.target_bytes += target_data.len() as u64;
+        progress.inc(source_data.len().max(target_data.len()) as u64);
+    }
+}
+
+/// Takes the blocks of both files in step and compares them.
+fn compare_blocks(
+    source: Receiver<Block>,
+    target: Receiver<Block>,
+    source_free: Sender<Vec<u8>>,
+    target_free: Sender<Vec<u8>>,
+    progress: &ProgressBar,
+) -> Comparison {
+    let mut comparison = Comparison::default();
+
+    loop {
+        // A closed channel means that file has ended (or failed to read).
+        let source_block = source.recv().ok();
+        let target_block = target.recv().ok();
+        if source_block.is_none() && target_block.is_none() {
+            break;
+        }
+
+        let source_data = source_block.as_ref().map_or(&[][..], |b| &b.buffer[..b.len]);
+        let target_data = target_block.as_ref().map_or(&[][..], |b| &b.buffer[..b.len]);
+        comparison.add(source_data, target_data, progress);
+
+        // The reader may already be done and gone; that's fine.
+        if let Some(block) = source_block {
+            let _ = source_free.send(block.buffer);
+        }
+        if let Some(block) = target_block {
+            let _ = target_free.send(block.buffer);
+        }
+    }
+    comparison
+}
+
+/// The digest lines are data for scripts; the verdict is for the person
+/// reading it (see reporter.rs).
+fn print_report(config: &VerifyConfig, report: &VerifyReport) -> RddResult<()> {
+    let name = algorithm_name(config.algorithm);
+    let digests = format!(
+        "{} {}  {} ({} bytes)\n{} {}  {} ({} bytes)\n",
+        name, report.source_digest, config.source, report.source_bytes,
+        name, report.target_digest, config.target, report.target_bytes
+    );
+    config.reporter.data(&digests)?;
+    match report.first_difference {
+        None => config.reporter.message(&format!("Match: '{}' is identical to '{}'.", config.target, config.source)),
+        Some(offset) => config.reporter.message(&format!(
+            "Mismatch: the files first differ at offset {} ({}).",
+            offset,
+            format_offset(offset)
+        )),
+    }
+    Ok(())
+}
+
+/// Reads until `buf` is full or end-of-file is reached.
+pub(crate) fn read_full(reader: &mut File, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+fn thread_panicked() -> RddError {
+    RddError::Channel("a reader thread panicked".to_string())
+}
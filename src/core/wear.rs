@@ -0,0 +1,207 @@
+// This file keeps track of how much rdd has written to each SD card and USB stick, so a lab that writes images onto the same cards again and again knows when to retire one. Flash memory wears out with every erase, and a worn card doesn't fail loudly: it starts returning corrupted blocks, and the images written to it go bad. rdd can't ask a card how worn it is, but it can count what it has written to it.
+
+// Explanation of this file:
+// The history : An append-only text file with one line per copy or wipe of a removable drive: the time, the drive and the bytes written, separated by tabs. Appending a short line is atomic, so copies running side by side don't lose each other's records, and the total of a drive is the sum of its lines. It lives in the user's state directory ($XDG_STATE_HOME/rdd, ~/.local/state/rdd, or %LOCALAPPDATA%\rdd on Windows), or wherever --wear-history says, e.g. on a share for a whole lab.
+// Drive identity : Model and serial number (see devices.rs). Drives without a serial number can't be told apart and aren't tracked, and neither are fixed drives, which report their own wear (SMART). A USB card reader reports its own serial number, so all cards that go through one reader add up to one total; use a built-in reader to track cards one by one.
+// WearLimit : How much a drive may absorb before we warn, as bytes (e.g. 10T) or as full writes of the drive (e.g. 1000x, the default). Cheap cards are rated for a few hundred to a few thousand erase cycles.
+// WearTracker : Created before the copy starts. It reads the drive's total so far and warns right away if the drive is past its limit. After the copy, record() adds what was written, and reports the new total and how much of the limit it is. A copy that fails isn't recorded.
+// Errors : Keeping the history is never worth failing a copy over; if the history can't be read or written, we warn and carry on.
+
+// src/core/wear.rs
+
+use crate::core::devices::{find_device, Device};
+use crate::core::reporter::Reporter;
+use crate::core::safety::describe;
+use indicatif::BinaryBytes;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The first line of a new history file.
+const HEADER: &str = "# rdd write history: unix time, drive (model/serial), bytes written\n";
+
+/// How much a drive may absorb before rdd warns that it's worn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WearLimit {
+    Bytes(u64),
+    /// This many writes of the whole drive.
+    Writes(u64),
+}
+
+impl WearLimit {
+    /// The limit in bytes, for a drive of `size` bytes.
+    pub fn bytes(self, size: u64) -> u64 {
+        match self {
+            WearLimit::Bytes(bytes) => bytes,
+            WearLimit::Writes(writes) => writes.saturating_mul(size),
+        }
+    }
+}
+
+impl fmt::Display for WearLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WearLimit::Bytes(bytes) => write!(f, "{}", BinaryBytes(*bytes)),
+            WearLimit::Writes(writes) => write!(f, "{} full writes", writes),
+        }
+    }
+}
+
+/// Where the write history is kept, and when to warn.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WearTracking {
+    pub history: PathBuf,
+    /// None to keep the history without warning.
+    pub limit: Option<WearLimit>,
+}
+
+impl WearTracking {
+    /// The default limit: a thousand writes of the whole drive.
+    pub const DEFAULT_LIMIT: WearLimit = WearLimit::Writes(1000);
+}
+
+/// The history file used when --wear-history isn't given.
+pub fn default_history() -> PathBuf {
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let dir = if cfg!(windows) {
+        var("LOCALAPPDATA")
+    } else {
+        var("XDG_STATE_HOME").or_else(|| var("HOME").map(|home| home.join(".local").join("state")))
+    };
+    match dir {
+        Some(dir) => dir.join("rdd").join("write-history"),
+        None => std::env::temp_dir().join("rdd-write-history"),
+    }
+}
+
+/// How a drive is named in the history: model and serial number, without
+/// the tabs and newlines the format uses.
+fn identity(device: &Device) -> Option<String> {
+    let serial = device.serial.as_deref()?;
+    let name = format!("{}/{}", device.model.as_deref().unwrap_or("-"), serial);
+    Some(name.replace(['\t', '\n', '\r'], " "))
+}
+
+/// The bytes the history records for `identity`. A missing history is an
+/// empty one.
+fn total_written(history: &Path, identity: &str) -> io::Result<u64> {
+    let text = match fs::read_to_string(history) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    Ok(text
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let (_time, drive, bytes) = (fields.next()?, fields.next()?, fields.next()?);
+            (drive == identity).then(|| bytes.parse::<u64>().ok()).flatten()
+        })
+        .fold(0, u64::saturating_add))
+}
+
+/// Appends a line for `bytes` written to `identity`.
+fn append(history: &Path, identity: &str, bytes: u64) -> io::Result<()> {
+    if let Some(dir) = history.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(history)?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    // Written in one go, so lines of concurrent copies don't interleave.
+    let mut line = String::new();
+    if file.metadata()?.len() == 0 {
+        line.push_str(HEADER);
+    }
+    line.push_str(&format!("{}\t{}\t{}\n", time, identity, bytes));
+    file.write_all(line.as_bytes())
+}
+
+/// The wear of one drive, from before a copy to after it.
+pub struct WearTracker<'a> {
+    tracking: &'a WearTracking,
+    reporter: &'a dyn Reporter,
+    device: Device,
+    identity: String,
+    /// Bytes written to the drive before this copy.
+    before: u64,
+}
+
+impl<'a> WearTracker<'a> {
+    /// Starts tracking the drive `path` lies on, if it's a removable drive
+    /// with a serial number. Warns if it's already past its limit.
+    pub fn start(tracking: Option<&'a WearTracking>, path: &str, reporter: &'a dyn Reporter) -> Option<Self> {
+        let tracking = tracking?;
+        let device = find_device(path).filter(|device| device.removable)?;
+        let identity = identity(&device)?;
+        let before = match total_written(&tracking.history, &identity) {
+            Ok(before) => before,
+            Err(e) => {
+                reporter.message(&format!(
+                    "Warning: can't read the write history '{}' ({}); {} isn't tracked this time.",
+                    tracking.history.display(),
+                    e,
+                    device.path
+                ));
+                return None;
+            }
+        };
+        let tracker = WearTracker {
+            tracking,
+            reporter,
+            device,
+            identity,
+            before,
+        };
+        if tracker.limit().is_some_and(|limit| before >= limit) {
+            tracker.warn_worn(before);
+        }
+        Some(tracker)
+    }
+
+    /// The limit in bytes, if there is one.
+    fn limit(&self) -> Option<u64> {
+        self.tracking.limit.map(|limit| limit.bytes(self.device.size))
+    }
+
+    fn warn_worn(&self, total: u64) {
+        self.reporter.message(&format!(
+            "Warning: {} has had {} written to it by rdd, past its wear limit of {}. Worn flash starts to corrupt what's written to it; consider retiring it.",
+            describe(&self.device),
+            BinaryBytes(total),
+            self.tracking.limit.map_or(String::new(), |limit| limit.to_string())
+        ));
+    }
+
+    /// Adds `bytes` written by the copy to the history, and reports the
+    /// drive's new total.
+    pub fn record(self, bytes: u64) {
+        if let Err(e) = append(&self.tracking.history, &self.identity, bytes) {
+            self.reporter.message(&format!(
+                "Warning: can't add this copy to the write history '{}' ({}).",
+                self.tracking.history.display(),
+                e
+            ));
+            return;
+        }
+        let total = self.before.saturating_add(bytes);
+        let writes = total as f64 / self.device.size.max(1) as f64;
+        let mut line = format!(
+            "Wear: {} has had {} written to it by rdd ({:.1} full writes)",
+            self.device.path,
+            BinaryBytes(total),
+            writes
+        );
+        if let Some(limit) = self.limit().filter(|&limit| limit > 0) {
+            line.push_str(&format!(", {:.0}% of its wear limit", total as f64 * 100.0 / limit as f64));
+        }
+        line.push('.');
+        self.reporter.message(&line);
+        if self.limit().is_some_and(|limit| self.before < limit && total >= limit) {
+            self.warn_worn(total);
+        }
+    }
+}
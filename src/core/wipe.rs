@@ -0,0 +1,232 @@
+// This file implements the 'wipe' command, which overwrites a file or device so its old contents can't be read back, e.g. before a drive is given away. It writes one or more passes over the whole target (zeros, ones or random data, in the order given by --passes), and with --verify reads the target back to check that the last pass really landed.
+
+// Explanation of this file:
+// Size : A regular file is wiped up to its current length; a device is measured by seeking to its end. --bytes wipes only the start of the target.
+// Passes : Each pass writes the target from start to end in blocks of --bs, then syncs it, so the next pass (and the report) never runs ahead of what is on the medium. Every pass gets its own progress bar and a summary line.
+// Random data : Random passes are filled by the xoshiro256++ generator in prng.rs, seeded afresh for every pass. The seed is kept so --verify can regenerate the same stream instead of storing it.
+// Verification : With --verify, the target is read back after the last pass, with the page cache dropped first so the device has to answer, and compared with the pattern block by block. A difference fails the wipe with RddError::WipeMismatch and its offset.
+// Limits : On SSDs, flash drives and SMR disks, the device may put a write somewhere other than where the old data sits, and spare or remapped sectors are never reached by an overwrite at all. For those, the drive's own secure erase is the only thorough wipe; a pass of zeros still makes the data unreadable through the normal interface.
+// System drives : Like the output of a copy, the target is checked first (see safety.rs); the system drive, or a drive with mounted filesystems, is only wiped with --force.
+// Wear : Every pass counts towards a removable drive's write history (see wear.rs).
+// Signals : Like the copy, the wipe checks for SIGUSR1 and Ctrl-C between blocks (see utils/signals.rs). An interrupted wipe syncs what it wrote and stops.
+
+// src/core/wipe.rs
+
+use crate::cli::WipePattern;
+use crate::config::WipeConfig;
+use crate::core::prng::Prng;
+use crate::core::reporter::progress_bar;
+use crate::core::safety::check_target;
+use crate::core::wear::WearTracker;
+use crate::core::verify::read_full;
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::fadvise::advise_dont_need;
+use crate::utils::signals;
+use clap::ValueEnum;
+use indicatif::BinaryBytes;
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::time::{Duration, Instant};
+
+/// What a finished wipe did.
+#[derive(Debug, Clone)]
+pub struct WipeReport {
+    /// Bytes overwritten by each pass.
+    pub bytes: u64,
+    pub passes: Vec<WipePattern>,
+    pub elapsed: Duration,
+    /// Whether the target was read back after the last pass.
+    pub verified: bool,
+}
+
+/// Overwrites `config.target` with every pass in `config.passes`, and
+/// reads it back with --verify.
+pub fn run_wipe(config: &WipeConfig) -> RddResult<WipeReport> {
+    let path = config.target.as_str();
+    check_target(path, config.force, config.reporter.as_ref())?;
+    let wear = WearTracker::start(config.wear.as_ref(), path, config.reporter.as_ref());
+    let mut target = OpenOptions::new().write(true).open(path).with_path(path)?;
+    let size = match config.limit {
+        Some(limit) => limit,
+        None => target_size(&mut target).with_path(path)?,
+    };
+
+    let started = Instant::now();
+    let mut last_seed = 0;
+    for (number, &pattern) in config.passes.iter().enumerate() {
+        let seed = Prng::entropy_seed();
+        let pass_started = Instant::now();
+        write_pass(config, &mut target, size, pattern, seed, number + 1)?;
+        let took = pass_started.elapsed();
+        config.reporter.message(&format!(
+            "Pass {}/{} ({}) done: {} bytes in {:.2}s ({}/s).",
+            number + 1,
+            config.passes.len(),
+            pattern_name(pattern),
+            size,
+            took.as_secs_f64(),
+            BinaryBytes(rate(size, took))
+        ));
+        last_seed = seed;
+    }
+    if let Some(wear) = wear {
+        wear.record(size.saturating_mul(config.passes.len() as u64));
+    }
+
+    let last = config.passes.last().copied();
+    if let (true, Some(pattern)) = (config.verify, last) {
+        verify_pass(config, size, pattern, last_seed)?;
+        config.reporter.message(&format!(
+            "Verified: '{}' reads back as the last pass ({}) wrote it.",
+            path,
+            pattern_name(pattern)
+        ));
+    }
+
+    let elapsed = started.elapsed();
+    config.reporter.message(&format!(
+        "Wiped '{}': {} pass(es) over {} bytes ({}) in {:.2}s.",
+        path,
+        config.passes.len(),
+        size,
+        BinaryBytes(size),
+        elapsed.as_secs_f64()
+    ));
+    Ok(WipeReport {
+        bytes: size,
+        passes: config.passes.clone(),
+        elapsed,
+        verified: config.verify && last.is_some(),
+    })
+}
+
+/// The length of a regular file, or the size of a device, found by seeking
+/// to its end.
+fn target_size(target: &mut File) -> std::io::Result<u64> {
+    let metadata = target.metadata()?;
+    if metadata.is_file() {
+        return Ok(metadata.len());
+    }
+    let size = target.seek(SeekFrom::End(0))?;
+    target.seek(SeekFrom::Start(0))?;
+    Ok(size)
+}
+
+/// Fills blocks with what `pattern` writes.
+enum Filler {
+    Constant,
+    Random(Prng),
+}
+
+impl Filler {
+    /// Prepares `buffer` for `pattern`; constant patterns are filled once.
+    fn new(pattern: WipePattern, seed: u64, buffer: &mut [u8]) -> Self {
+        match pattern {
+            WipePattern::Zeros => {
+                buffer.fill(0x00);
+                Filler::Constant
+            }
+            WipePattern::Ones => {
+                buffer.fill(0xFF);
+                Filler::Constant
+            }
+            WipePattern::Random => Filler::Random(Prng::from_seed(seed)),
+        }
+    }
+
+    /// Fills `block` with the next bytes of the pattern.
+    fn next(&mut self, block: &mut [u8]) {
+        if let Filler::Random(prng) = self {
+            prng.fill(block);
+        }
+    }
+}
+
+/// Writes one pass of `pattern` over the first `size` bytes of the target.
+fn write_pass(
+    config: &WipeConfig,
+    target: &mut File,
+    size: u64,
+    pattern: WipePattern,
+    seed: u64,
+    number: usize,
+) -> RddResult<()> {
+    let path = config.target.as_str();
+    let progress = progress_bar(&*config.reporter, Some(size), config.show_progress);
+    progress.set_message(format!("pass {}/{} ({})", number, config.passes.len(), pattern_name(pattern)));
+
+    let mut buffer = vec![0u8; config.block_size];
+    let mut filler = Filler::new(pattern, seed, &mut buffer);
+    target.seek(SeekFrom::Start(0)).with_path(path)?;
+
+    let mut pos = 0u64;
+    while pos < size {
+        if let Err(e) = signals::check(&*config.reporter, &progress) {
+            progress.finish_and_clear();
+            target.sync_all().with_path(path)?;
+            return Err(e);
+        }
+        let len = (size - pos).min(buffer.len() as u64) as usize;
+        filler.next(&mut buffer[..len]);
+        target.write_all(&buffer[..len]).at_offset(path, pos)?;
+        pos += len as u64;
+        progress.inc(len as u64);
+    }
+    target.sync_all().with_path(path)?;
+    progress.finish_and_clear();
+    Ok(())
+}
+
+/// Reads the first `size` bytes of the target back and compares them with
+/// what the pass of `pattern` (started from `seed`) wrote.
+fn verify_pass(config: &WipeConfig, size: u64, pattern: WipePattern, seed: u64) -> RddResult<()> {
+    let path = config.target.as_str();
+    let mut target = File::open(path).with_path(path)?;
+    // Don't let the page cache answer for the device.
+    advise_dont_need(&target, 0, size);
+
+    let progress = progress_bar(&*config.reporter, Some(size), config.show_progress);
+    progress.set_message("verifying");
+
+    let mut expected = vec![0u8; config.block_size];
+    let mut actual = vec![0u8; config.block_size];
+    let mut filler = Filler::new(pattern, seed, &mut expected);
+
+    let mut pos = 0u64;
+    while pos < size {
+        signals::check(&*config.reporter, &progress)?;
+        let len = (size - pos).min(expected.len() as u64) as usize;
+        filler.next(&mut expected[..len]);
+        let got = read_full(&mut target, &mut actual[..len]).at_offset(path, pos)?;
+        // A target that ends early differs where it ends.
+        let difference = expected[..got]
+            .iter()
+            .zip(&actual[..got])
+            .position(|(e, a)| e != a)
+            .or((got < len).then_some(got));
+        if let Some(i) = difference {
+            progress.finish_and_clear();
+            return Err(RddError::WipeMismatch {
+                path: path.to_string(),
+                pattern: pattern_name(pattern),
+                offset: pos + i as u64,
+            });
+        }
+        pos += len as u64;
+        progress.inc(len as u64);
+    }
+    progress.finish_and_clear();
+    Ok(())
+}
+
+/// The pattern as it's written on the command line, e.g. "zeros".
+fn pattern_name(pattern: WipePattern) -> String {
+    pattern
+        .to_possible_value()
+        .map_or_else(|| format!("{:?}", pattern), |v| v.get_name().to_string())
+}
+
+fn rate(bytes: u64, took: Duration) -> u64 {
+    let secs = took.as_secs_f64();
+    if secs > 0.0 { (bytes as f64 / secs) as u64 } else { bytes }
+}
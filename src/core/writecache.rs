@@ -0,0 +1,213 @@
+// This file turns off the volatile write cache of the target drive for the duration of a copy, and turns it back on afterwards. With the cache on, a drive may acknowledge a flush before the data is really on the medium, and a power cut can still lose it. For strict durability runs it's safer to let every write go straight to the medium, at the cost of speed.
+
+// Explanation of this file:
+// WriteCacheGuard struct: Disables the cache when it's created and restores the original setting when it's dropped, so the drive is put back the way we found it even when the copy fails halfway. Every state change is reported (see reporter.rs), so there's a record of what we did to the drive.
+// Finding the drive: The output must be a block device. utils/sysfs.rs finds the sysfs directory of its whole disk, since the cache belongs to the drive, not to a partition.
+// SATA/SCSI drives: The kernel exposes the drive's cache mode in .../device/scsi_disk/*/cache_type. Writing "write through" there makes the kernel send the drive a MODE SELECT with the write cache bit cleared. For SATA drives, libata turns this into the matching ATA SET FEATURES command.
+// NVMe drives: We send Get/Set Features admin commands for the Volatile Write Cache feature (0x06) through the NVME_IOCTL_ADMIN_CMD ioctl.
+// Platform support: This only works on Linux, and needs root. Elsewhere, and for outputs that aren't block devices, disable() fails with an "unsupported" error rather than silently carrying on, since the user asked for a guarantee we can't give.
+
+// src/core/writecache.rs
+
+use crate::config::CopyConfig;
+use crate::core::reporter::Reporter;
+use crate::error::{IoResultExt, RddResult};
+use crate::utils::diagnostics::Diagnostic;
+use std::sync::Arc;
+
+/// Keeps the target's write cache disabled while it's alive.
+pub struct WriteCacheGuard {
+    path: String,
+    control: platform::CacheControl,
+    reporter: Arc<dyn Reporter>,
+}
+
+impl WriteCacheGuard {
+    /// Disables the volatile write cache of the drive behind the output.
+    pub fn disable(config: &CopyConfig) -> RddResult<Self> {
+        let path = config.output_file.as_str();
+        let control = platform::CacheControl::open(path).with_path(path)?;
+        let guard = Self {
+            path: path.to_string(),
+            control,
+            reporter: config.reporter.clone(),
+        };
+        if guard.control.was_enabled() {
+            guard.control.set_enabled(false).with_path(path)?;
+            guard.reporter.message(&format!(
+                "Write cache of '{}' disabled for the copy ({}).",
+                path,
+                guard.control.describe()
+            ));
+        } else {
+            guard.reporter.message(&format!("Write cache of '{}' is already disabled.", path));
+        }
+        Ok(guard)
+    }
+}
+
+impl Drop for WriteCacheGuard {
+    fn drop(&mut self) {
+        if !self.control.was_enabled() {
+            return;
+        }
+        match self.control.set_enabled(true) {
+            Ok(()) => self.reporter.message(&format!("Write cache of '{}' restored.", self.path)),
+            Err(e) => self.reporter.diagnostic(
+                &Diagnostic::warning(format!(
+                    "could not re-enable the write cache of '{}': {}; it stays disabled until the drive is reset or re-enabled by hand.",
+                    self.path, e
+                ))
+                .with_flag("--disable-write-cache"),
+            ),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use crate::utils::sysfs;
+    use std::fs::{self, File};
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::path::{Path, PathBuf};
+
+    /// NVMe admin opcodes and the Volatile Write Cache feature.
+    const NVME_ADMIN_SET_FEATURES: u8 = 0x09;
+    const NVME_ADMIN_GET_FEATURES: u8 = 0x0a;
+    const NVME_FEAT_VOLATILE_WC: u32 = 0x06;
+
+    /// The kernel's `struct nvme_passthru_cmd`.
+    #[repr(C)]
+    #[derive(Default)]
+    pub struct NvmeAdminCmd {
+        opcode: u8,
+        flags: u8,
+        rsvd1: u16,
+        nsid: u32,
+        cdw2: u32,
+        cdw3: u32,
+        metadata: u64,
+        addr: u64,
+        metadata_len: u32,
+        data_len: u32,
+        cdw10: u32,
+        cdw11: u32,
+        cdw12: u32,
+        cdw13: u32,
+        cdw14: u32,
+        cdw15: u32,
+        timeout_ms: u32,
+        result: u32,
+    }
+
+    nix::ioctl_readwrite!(nvme_admin_cmd, b'N', 0x41, NvmeAdminCmd);
+
+    pub enum CacheControl {
+        Scsi { cache_type: PathBuf, original: String },
+        Nvme { device: File, enabled: bool },
+    }
+
+    impl CacheControl {
+        pub fn open(path: &str) -> io::Result<Self> {
+            let disk = sysfs::whole_disk(path)?.ok_or_else(|| unsupported("the output is not a block device"))?;
+            let name = disk.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+
+            if name.starts_with("nvme") {
+                let device = File::open(Path::new("/dev").join(name))?;
+                let mut cmd = NvmeAdminCmd {
+                    opcode: NVME_ADMIN_GET_FEATURES,
+                    cdw10: NVME_FEAT_VOLATILE_WC,
+                    ..Default::default()
+                };
+                // SAFETY: `cmd` is a properly laid out nvme_passthru_cmd that
+                // transfers no data buffer.
+                unsafe { nvme_admin_cmd(device.as_raw_fd(), &mut cmd) }.map_err(io::Error::from)?;
+                return Ok(CacheControl::Nvme {
+                    device,
+                    enabled: cmd.result & 1 == 1,
+                });
+            }
+
+            let scsi_disk = fs::read_dir(disk.join("device/scsi_disk"))
+                .ok()
+                .and_then(|mut entries| entries.next())
+                .and_then(|entry| entry.ok())
+                .ok_or_else(|| unsupported("the drive has no controllable write cache (not a SATA/SCSI or NVMe disk)"))?;
+            let cache_type = scsi_disk.path().join("cache_type");
+            let original = fs::read_to_string(&cache_type)?.trim().to_string();
+            Ok(CacheControl::Scsi { cache_type, original })
+        }
+
+        pub fn was_enabled(&self) -> bool {
+            match self {
+                CacheControl::Scsi { original, .. } => original.starts_with("write back"),
+                CacheControl::Nvme { enabled, .. } => *enabled,
+            }
+        }
+
+        pub fn set_enabled(&self, enable: bool) -> io::Result<()> {
+            match self {
+                CacheControl::Scsi { cache_type, original } => {
+                    let value = match (enable, original.contains("no read")) {
+                        (true, _) => original.as_str(),
+                        // "write back, no read (daft)" keeps its read cache off.
+                        (false, true) => "none",
+                        (false, false) => "write through",
+                    };
+                    fs::write(cache_type, value)
+                }
+                CacheControl::Nvme { device, .. } => {
+                    let mut cmd = NvmeAdminCmd {
+                        opcode: NVME_ADMIN_SET_FEATURES,
+                        cdw10: NVME_FEAT_VOLATILE_WC,
+                        cdw11: u32::from(enable),
+                        ..Default::default()
+                    };
+                    // SAFETY: as above, no data buffer is involved.
+                    unsafe { nvme_admin_cmd(device.as_raw_fd(), &mut cmd) }.map_err(io::Error::from)?;
+                    Ok(())
+                }
+            }
+        }
+
+        pub fn describe(&self) -> String {
+            match self {
+                CacheControl::Scsi { original, .. } => format!("was '{}'", original),
+                CacheControl::Nvme { .. } => "NVMe volatile write cache".to_string(),
+            }
+        }
+    }
+
+    fn unsupported(msg: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::Unsupported, format!("cannot control the write cache: {}", msg))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use std::io;
+
+    pub struct CacheControl;
+
+    impl CacheControl {
+        pub fn open(_path: &str) -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "controlling a drive's write cache is only supported on Linux",
+            ))
+        }
+
+        pub fn was_enabled(&self) -> bool {
+            false
+        }
+
+        pub fn set_enabled(&self, _enable: bool) -> io::Result<()> {
+            Ok(())
+        }
+
+        pub fn describe(&self) -> String {
+            String::new()
+        }
+    }
+}
@@ -4,19 +4,26 @@
 // standard std::error::Error trait for our RddError enum, saving us a lot of boilerplate code. Debug allows the error to be printed for debugging purposes.
 // #[error("...")] -> This attribute provides the user-facing error message for each variant of our enum.
 // #[from] io::Error -> This is a powerful helper. It tells Rust how to automatically convert a standard std::io::Error into our RddError::Io variant. This means if we have a function that does file I/O and returns a std::io::Result, we can use the ? operator on it, and the error will be seamlessly converted into our application's error type.
+// RddErrorKind : A small, stable enum describing the category of a failure (configuration, I/O, device gone, ...). Library users can match on err.kind() instead of parsing error messages. Both enums are #[non_exhaustive], so we can add new variants later without breaking anyone's match statements.
+// IoAt variant and IoResultExt : Plain io::Error values don't say which file failed or where. The with_path/at_offset helpers wrap an io::Result with that context, so the message (and err.path()/err.offset()) can tell the user exactly what went wrong.
+// Hint registry : HINTS is a table of (matcher, hint text) pairs for failures users commonly hit on a first run, like permission denied on /dev nodes or a busy (mounted) target. err.hint() returns the first matching hint, which main prints under the error. Adding a hint is one new entry in the table.
 // RddResult<T> : This is a command Rust idiom. We create a type alias for REsult<T, RddError>. This makes function signatures much cleaner throughout our project.
 
 // This file defines a single. comprehensive error type for our entire program
 
 // src/error.rs
 
+use crate::core::mapfile::format_offset;
+use indicatif::BinaryBytes;
 use std::io;
+use std::time::Duration;
 use thiserror::Error;
 
 /// The unified error type for all fallible operations in 'rdd'.
 /// This enum is designed to provide clear, user-friendly error messagesfor every potential failure point in the application, from IO problems to configuration mistakes.
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum RddError {
 
     /// Error originating from standard I/O operations (e.g., reading a file, writing to a device).
@@ -24,6 +31,15 @@ pub enum RddError {
     #[error("I/O Error: {0}")]
     Io(#[from] io::Error),
 
+    /// An I/O error with the file and (where known) the byte offset it happened at.
+    #[error("I/O Error on '{path}'{}: {source}", fmt_offset(.offset))]
+    IoAt {
+        path: String,
+        offset: Option<u64>,
+        #[source]
+        source: io::Error,
+    },
+
     /// Error during the parsing of command-line arguments or configuration values, such as an invalid block size string.
     #[error("Configuration Error: {0}")]
     Config(String),
@@ -32,6 +48,74 @@ pub enum RddError {
     #[error("Verification failed: Hashes do not match. Expected: {expected}, Got: {actual}")]
     VerificationFailure { expected: String, actual: String },
 
+    /// Error when a copy replayed from a manifest read different data than the copy that wrote the manifest.
+    #[error("The data read doesn't match the manifest. Expected: {expected}, Got: {actual}")]
+    ManifestMismatch { expected: String, actual: String },
+
+    /// Error when a custody log's hash chain is broken, so it can't be trusted or added to.
+    #[error("The custody log '{path}' has been tampered with ({reason})")]
+    CustodyLog { path: String, reason: String },
+
+    /// Error when blocks of the input no longer match the hashes recorded for them by an earlier run.
+    #[error("{blocks} block(s) of the input differ from the block hashes in '{path}'")]
+    BlockHashMismatch { path: String, blocks: u64 },
+
+    /// Error when a scrub found archived images that are corrupted, missing or unreadable.
+    #[error("{failed} of {checked} archived image(s) failed the scrub")]
+    ScrubFailed { failed: u64, checked: u64 },
+
+    /// Error when an option needs a part of rdd that this build was made without (a cargo feature).
+    #[error("{what} isn't available: this build of rdd was made without the '{feature}' feature")]
+    FeatureDisabled { what: String, feature: String },
+
+    /// Error when a wiped target doesn't read back as the last pass wrote it.
+    #[error("'{path}' doesn't read back as the last pass ({pattern}) wrote it; the first difference is at offset {offset} ({})", format_offset(*.offset))]
+    WipeMismatch { path: String, pattern: String, offset: u64 },
+
+    /// Error when the program that compresses or decompresses a copy can't be started because it isn't installed.
+    #[error("The '{tool}' program is needed for this copy, but it isn't installed (or not on the PATH)")]
+    CodecMissing { tool: String },
+
+    /// Error when the compressor or decompressor of a copy failed, so the data that went through it is incomplete.
+    #[error("'{tool}' failed ({status}); the data it was {what} is incomplete")]
+    CodecFailed { tool: String, what: String, status: String },
+
+    /// Error when the input was modified while it was being copied, so the output may not match any consistent version of it.
+    #[error("Input '{path}' changed during the copy ({what}); the output may be inconsistent")]
+    InputChanged { path: String, what: String },
+
+    /// Error when the FAT or exFAT filesystem read back from the output is inconsistent, in ways the input's isn't.
+    #[error("The filesystem read back from '{path}' is inconsistent: {}{}", .problems[0], match .problems.len() { 1 => String::new(), n => format!(" (and {} more problem(s))", n - 1) })]
+    FilesystemDamaged { path: String, problems: Vec<String> },
+
+    /// Error when the output is a drive that holds the running system or has mounted filesystems, and --force wasn't given.
+    #[error("Refusing to write to '{path}': {reason}")]
+    UnsafeTarget { path: String, reason: String },
+
+    /// Error when the output is a device too small for what the copy would write to it.
+    #[error("'{path}' is too small for this copy: it holds {} ({capacity} bytes), but the copy writes up to {} ({needed} bytes)", BinaryBytes(*.capacity), BinaryBytes(*.needed))]
+    TargetTooSmall { path: String, needed: u64, capacity: u64 },
+
+    /// Error when an operation on a file or device didn't complete in time, e.g. opening a device node of a failing disk.
+    #[error("Timed out after {}s while {operation} '{path}'", .after.as_secs())]
+    Timeout {
+        operation: String,
+        path: String,
+        after: Duration,
+    },
+
+    /// Error when a framed network transfer ended before the sender marked the end of the data (see --tcp-framing).
+    #[error("The connection on '{path}' ended after {received} bytes, before the sender marked the end of the data; the transfer is incomplete")]
+    ConnectionLost { path: String, received: u64 },
+
+    /// Error when more reads failed than --max-errors allows, so the copy gave up. The map of what was read has been saved, if it could be.
+    #[error("Gave up after {failed} failed reads, more than the {max} allowed; {}", match .map { Some(map) => format!("the map of what was read is in '{}'", map), None => "the map of what was read couldn't be saved".to_string() })]
+    TooManyErrors { failed: u64, max: u64, map: Option<String> },
+
+    /// Error when the copy was stopped by a signal (Ctrl-C). What was written up to then has been synced.
+    #[error("Interrupted by {signal}; the output was synced up to that point")]
+    Interrupted { signal: String },
+
     /// Error when a multithreading channel operation fails, indicating a breakdown in communication between the reader and writer threads.
     #[error("Threading channel error: {0}")]
     Channel(String), 
@@ -41,6 +125,270 @@ pub enum RddError {
     NotImplemented(String),
 }
 
+/// The category of an [`RddError`], for callers that want to react to
+/// failures without inspecting error messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RddErrorKind {
+    /// Invalid arguments or configuration values.
+    Config,
+    /// A read, write, seek or open failed.
+    Io,
+    /// The copied data did not match the source.
+    Verification,
+    /// The operation was cancelled before it finished.
+    Cancelled,
+    /// An operation did not complete in time.
+    Timeout,
+    /// The requested feature or operation is not supported here.
+    Unsupported,
+    /// The device disappeared while it was in use (e.g., unplugged).
+    DeviceGone,
+    /// An internal failure, such as a worker thread going away.
+    Internal,
+}
+
+impl RddError {
+    /// Returns the category of this error.
+    pub fn kind(&self) -> RddErrorKind {
+        match self {
+            RddError::Io(e) | RddError::IoAt { source: e, .. } => io_kind(e),
+            RddError::Config(_) | RddError::UnsafeTarget { .. } | RddError::TargetTooSmall { .. } => {
+                RddErrorKind::Config
+            }
+            RddError::VerificationFailure { .. }
+            | RddError::ManifestMismatch { .. }
+            | RddError::CustodyLog { .. }
+            | RddError::WipeMismatch { .. }
+            | RddError::BlockHashMismatch { .. }
+            | RddError::ScrubFailed { .. }
+            | RddError::InputChanged { .. }
+            | RddError::FilesystemDamaged { .. } => {
+                RddErrorKind::Verification
+            }
+            RddError::Timeout { .. } => RddErrorKind::Timeout,
+            RddError::CodecMissing { .. } | RddError::FeatureDisabled { .. } => RddErrorKind::Unsupported,
+            RddError::Interrupted { .. } => RddErrorKind::Cancelled,
+            RddError::Channel(_) => RddErrorKind::Internal,
+            RddError::NotImplemented(_) => RddErrorKind::Unsupported,
+            RddError::CodecFailed { .. } | RddError::TooManyErrors { .. } | RddError::ConnectionLost { .. } => {
+                RddErrorKind::Io
+            }
+        }
+    }
+
+    /// The file or device the error relates to, if known.
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            RddError::IoAt { path, .. }
+            | RddError::InputChanged { path, .. }
+            | RddError::UnsafeTarget { path, .. }
+            | RddError::TargetTooSmall { path, .. }
+            | RddError::FilesystemDamaged { path, .. }
+            | RddError::CustodyLog { path, .. }
+            | RddError::WipeMismatch { path, .. }
+            | RddError::BlockHashMismatch { path, .. }
+            | RddError::ConnectionLost { path, .. }
+            | RddError::Timeout { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Actionable advice for this error, if it matches a known failure.
+    pub fn hint(&self) -> Option<&'static str> {
+        HINTS.iter().find(|h| (h.matches)(self)).map(|h| h.text)
+    }
+
+    /// The command-line flag whose behavior produced this error, if any.
+    pub fn flag(&self) -> Option<&'static str> {
+        match self {
+            RddError::InputChanged { .. } => Some("--check-input-stable"),
+            RddError::Timeout { .. } => Some("--open-timeout"),
+            RddError::VerificationFailure { .. } => Some("--verify"),
+            RddError::ManifestMismatch { .. } => Some("--from-manifest"),
+            RddError::CustodyLog { .. } => Some("--custody-log"),
+            RddError::CodecMissing { tool } if tool != "ssh" && tool != "curl" => Some("--compress"),
+            RddError::WipeMismatch { .. } => Some("--verify"),
+            RddError::BlockHashMismatch { .. } => Some("--compare-manifest"),
+            RddError::FilesystemDamaged { .. } => Some("--check-fat"),
+            RddError::TooManyErrors { .. } => Some("--max-errors"),
+            RddError::ConnectionLost { .. } => Some("--tcp-framing"),
+            _ => None,
+        }
+    }
+
+    /// The underlying I/O error, if this is an I/O failure.
+    fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            RddError::Io(e) | RddError::IoAt { source: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+
+    /// The byte offset the error happened at, if known.
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            RddError::IoAt { offset, .. } => *offset,
+            RddError::WipeMismatch { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+}
+
+/// Maps an I/O error onto the error categories we expose.
+fn io_kind(e: &io::Error) -> RddErrorKind {
+    // ENODEV and ENXIO are what Linux and the BSDs report once a device node
+    // has lost its backing hardware.
+    #[cfg(unix)]
+    if let Some(code) = e.raw_os_error()
+        && (code == nix::libc::ENODEV || code == nix::libc::ENXIO)
+    {
+        return RddErrorKind::DeviceGone;
+    }
+
+    // An EINTR is a call that can simply be made again, not the user
+    // cancelling; only RddError::Interrupted is that.
+    match e.kind() {
+        io::ErrorKind::TimedOut => RddErrorKind::Timeout,
+        io::ErrorKind::Unsupported => RddErrorKind::Unsupported,
+        _ => RddErrorKind::Io,
+    }
+}
+
+/// A known failure signature and the advice we give for it.
+struct Hint {
+    matches: fn(&RddError) -> bool,
+    text: &'static str,
+}
+
+/// The hint registry. Entries are checked in order; the first match wins.
+const HINTS: &[Hint] = &[
+    Hint {
+        matches: |e| {
+            has_io_kind(e, io::ErrorKind::PermissionDenied)
+                && e.path().is_some_and(|p| p.starts_with("/dev/"))
+        },
+        text: "Device nodes usually need root access. Try again with sudo, or add your user to the group that owns the device (often 'disk').",
+    },
+    Hint {
+        matches: |e| matches!(e, RddError::FeatureDisabled { .. }),
+        text: "Rebuild rdd with that feature turned on (e.g., cargo build --features <name>); the default build has every feature.",
+    },
+    Hint {
+        matches: |e| matches!(e, RddError::Timeout { .. }),
+        text: "The device did not respond. It may be failing or half-disconnected; check the kernel log (e.g., 'dmesg') for I/O errors, and try another port or cable.",
+    },
+    Hint {
+        matches: |e| matches!(e, RddError::ManifestMismatch { .. }),
+        text: "The input has changed since the manifest was written, or it isn't the input the manifest was made from. The output now holds the data as it is today.",
+    },
+    Hint {
+        matches: |e| matches!(e, RddError::BlockHashMismatch { .. }),
+        text: "The listed blocks were changed, or have decayed, since their hashes were recorded. The output holds them as they read today; restore them from another copy if you have one.",
+    },
+    Hint {
+        matches: |e| matches!(e, RddError::ScrubFailed { .. }),
+        text: "Restore the images listed above from another copy while you still have one. Images that fail again after a restore point to a failing archive drive.",
+    },
+    Hint {
+        matches: |e| matches!(e, RddError::WipeMismatch { .. }),
+        text: "The device accepted the writes but returns other data, which points to failing hardware or a device that remaps writes (flash translation, SMR). Overwriting can't reach remapped sectors; use the drive's own secure erase (e.g., 'hdparm --security-erase' or 'nvme format -s1') instead.",
+    },
+    Hint {
+        matches: |e| matches!(e, RddError::CodecMissing { tool } if tool == "curl"),
+        text: "Install it with your package manager (the 'curl' package), or download the image first and copy the file.",
+    },
+    Hint {
+        matches: |e| matches!(e, RddError::CodecMissing { .. }),
+        text: "Install it with your package manager (the 'gzip', 'zstd' or 'xz-utils' package), or copy a compressed input as it is with --no-decompress.",
+    },
+    Hint {
+        matches: |e| matches!(e, RddError::CodecFailed { .. }),
+        text: "Its own message, printed above, says why. A damaged or truncated compressed input fails like this; so does an output that ran out of space.",
+    },
+    Hint {
+        matches: |e| matches!(e, RddError::FilesystemDamaged { .. }),
+        text: "If the input's filesystem is sound, the card didn't store what was written to it: cheap controllers reorder or drop writes. Write it again, with --direct if you can, and retire the card if it happens again.",
+    },
+    Hint {
+        matches: |e| matches!(e, RddError::UnsafeTarget { .. }),
+        text: "Check with 'rdd list' that this is the drive you mean. If it is, unmount its filesystems first (e.g., 'umount /dev/sdb1'); add --force only if you really mean to overwrite it as it is.",
+    },
+    Hint {
+        matches: |e| matches!(e, RddError::TargetTooSmall { .. }),
+        text: "Nothing was written. Use a larger target, or copy less of the input with --count or --count-bytes if you know its end is unused (e.g., an image whose partitions end well before it does).",
+    },
+    Hint {
+        matches: |e| matches!(e, RddError::TooManyErrors { .. }),
+        text: "The input is failing faster than a straight copy can cope with. Copy only what's missing with the map as --ranges-file (e.g., with a smaller --bs and --retries), or hand the map to GNU ddrescue, which reads the same format.",
+    },
+    Hint {
+        matches: |e| matches!(e, RddError::Interrupted { .. }),
+        text: "A copy started with --resume continues where it stopped when you run the same command again; any other copy has to start over.",
+    },
+    Hint {
+        matches: |e| has_io_kind(e, io::ErrorKind::ResourceBusy),
+        text: "The device is in use, most often because one of its partitions is mounted. Unmount it (e.g., 'umount /dev/sdb1') and try again.",
+    },
+    Hint {
+        matches: |e| has_io_kind(e, io::ErrorKind::StorageFull),
+        text: "The output ran out of space. Free up space on the target, or check that the target device is large enough for the input.",
+    },
+    Hint {
+        matches: |e| has_io_kind(e, io::ErrorKind::InvalidInput),
+        text: "The OS rejected the request as invalid: a size, offset or setting this file or device doesn't accept. Devices often need sizes and offsets that are multiples of their logical sector size (usually 512 or 4096 bytes); check that --bs and any skip or seek are.",
+    },
+];
+
+fn has_io_kind(e: &RddError, kind: io::ErrorKind) -> bool {
+    e.io_error().is_some_and(|io| io.kind() == kind)
+}
+
+fn fmt_offset(offset: &Option<u64>) -> String {
+    offset
+        .map(|o| format!(" at offset {} ({})", o, format_offset(o)))
+        .unwrap_or_default()
+}
+
+/// Adds file and offset context to `std::io::Result` values.
+pub trait IoResultExt<T> {
+    /// Attaches the path of the file the operation was performed on.
+    fn with_path(self, path: &str) -> RddResult<T>;
+    /// Attaches the path and the byte offset the operation was performed at.
+    fn at_offset(self, path: &str, offset: u64) -> RddResult<T>;
+}
+
+impl<T> IoResultExt<T> for io::Result<T> {
+    fn with_path(self, path: &str) -> RddResult<T> {
+        self.map_err(|source| RddError::IoAt {
+            path: path.to_string(),
+            offset: None,
+            source,
+        })
+    }
+
+    fn at_offset(self, path: &str, offset: u64) -> RddResult<T> {
+        self.map_err(|source| RddError::IoAt {
+            path: path.to_string(),
+            offset: Some(offset),
+            source,
+        })
+    }
+}
+
 /// A specialized 'Result' type for 'rdd operations. Using this alias simplifies function signatures throughout the crate, making the code cleaners and more readable. Instead of 'Result<T, RddError', we can just write RddResult<T>'.
 pub type RddResult<T> = Result<T, RddError>;
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_interrupted_call_is_an_io_error_not_a_cancellation() {
+        let e = RddError::Io(io::Error::from(io::ErrorKind::Interrupted));
+        assert_eq!(e.kind(), RddErrorKind::Io);
+        let e = RddError::Interrupted { signal: "SIGINT".to_string() };
+        assert_eq!(e.kind(), RddErrorKind::Cancelled);
+    }
+}
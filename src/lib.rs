@@ -0,0 +1,24 @@
+// This file is the root of rdd's library crate. Everything that does real work lives here, so other Rust programs can embed rdd's copy engine. The rdd binary (main.rs) is just a thin command-line wrapper on top of it.
+
+// Explanation of this file:
+// pub mod ...; : Each line declares one of our modules and makes it part of the public library API.
+// Library vs binary: Cargo builds src/lib.rs as the `rdd` library and src/main.rs as the `rdd` binary. main.rs reaches the modules through `rdd::...` paths, exactly like any other user of the library would.
+// Entry points for embedders: CopyConfig::builder (config.rs) to describe a copy, and run_copy to run it; run_verify compares two files. The copy prints nothing itself; pass a Reporter (core/reporter.rs) to the builder to receive its messages and results, or an EventReporter to get them as events through a callback or a channel.
+// pub use ...; : The types most programs need are re-exported here, so `use rdd::{CopyConfig, run_copy};` is enough. Everything else is still reachable through its module.
+// Signals : The library never installs signal handlers; that's up to the program (the binary calls utils::signals::install). Without them, Ctrl-C simply ends the process as usual.
+
+// src/lib.rs
+
+pub mod cli;
+pub mod config;
+pub mod core;
+pub mod error;
+pub mod profile;
+pub mod utils;
+pub mod wizard;
+
+pub use config::{CopyConfig, CopyConfigBuilder, VerifyConfig};
+pub use core::copy::{run_copy, CopyStats};
+pub use core::reporter::{Event, EventReporter, Reporter, TerminalReporter};
+pub use core::verify::{run_verify, VerifyReport};
+pub use error::{RddError, RddErrorKind, RddResult};
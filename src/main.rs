@@ -3,8 +3,9 @@
 
 
 // Explanation of this file : 
-// mod ...; : these lines at the top declare the existence of our other modules, making their code accessible withing main.rs.
+// use rdd::...; : our modules live in the library crate (src/lib.rs). These lines bring the pieces main.rs needs into scope.
 // main() -> Exitcode: This is the modern , idiomatic way to write a main function in Rust. Instead of calling std::process::exit(), we return an Exitcode. This ensures that all resources are properly cleaned up(a process known as "stack unwinding") before the program exits. ExitCode::SUCCESS corresponds to exit code 0, and ExitCode::FAILURE corresponds to 1.
+// Diagnostics: Errors and their hints are printed through utils::diagnostics, which adds colored severity tags when stderr is a terminal (and --no-color isn't set).
 // Seperation of main and run: We delegate all the fallible logic to run function that returns our RddResult<()>. This allows us to use the ? operator freely inside run. The main function;s only job is to call run and translate it Ok or Err result into the appropriate Exitcode, printing any errorrs to stderr. This is a very common and robust pattern in Rust applications.
 // Orchestration: The run function clearly shows the intended flow: 
     //1. Parse arguments(Cli::parse()).
@@ -12,26 +13,36 @@
     //3. Execute the core logic(the Err(RddError::NotImplemented(...))) part, which will replace next).
 // src/main.rs
 
-// Declare all the modules we have created or will create. This makes their contents available to the 'main' functions.
-
 // src/main.rs
 
-// Declare all the modules we have created or will create.
-// This makes their contents available to the `main` function.
-mod cli;
-mod config;
-mod core;
-mod error;
-mod utils;
-
-use crate::cli::{Cli, Command};
-use crate::config::CopyConfig;
-use crate::core::copy::run_singlethreaded_copy;
-use crate::error::{RddError,RddResult};
+// All of our modules live in the library crate (src/lib.rs).
+// The binary only needs to pull in what it uses from there.
+use rdd::cli::{Cli, Command};
+use rdd::config::{BenchmarkConfig, CopyConfig, ExtractConfig, ListConfig, ScrubConfig, TopConfig, VerifyConfig, WipeConfig, STDIO_PATH};
+use rdd::core::benchmark::run_benchmark;
+use rdd::core::copy::run_copy;
+use rdd::core::dashboard::{job_reporter, run_top};
+use rdd::core::devices::run_list;
+use rdd::core::extract::run_extract;
+use rdd::core::scrub::run_scrub;
+use rdd::core::verify::run_verify;
+use rdd::core::wipe::run_wipe;
+use rdd::error::{RddErrorKind, RddResult};
+use rdd::utils::diagnostics::{self, Diagnostic};
+use rdd::utils::{passphrase, signals};
+use rdd::wizard::{confirm_overwrite, run_wizard};
 use clap::Parser;
 use std::process::ExitCode;
 
+/// The exit code after Ctrl-C: 128 + SIGINT, what shells report for a
+/// process that Ctrl-C killed, so scripts can tell it from a failure.
+const INTERRUPTED_EXIT_CODE: u8 = 130;
+
 fn main() -> ExitCode {
+    // Started by ssh to give it a password (see utils/passphrase.rs).
+    if passphrase::answer_askpass() {
+        return ExitCode::SUCCESS;
+    }
     // The `run` function contains the application's primary logic.
     // By putting it in a separate function, we can use the `?` operator
     // for cleaner error handling. The result of `run` is then handled
@@ -40,8 +51,20 @@ fn main() -> ExitCode {
         Ok(_) => ExitCode::SUCCESS,
         Err(e) => {
             // Print the user-friendly error message to stderr.
-            eprintln!("Error: {}", e);
-            ExitCode::FAILURE
+            let mut diagnostic = Diagnostic::error(e.to_string());
+            if let Some(flag) = e.flag() {
+                diagnostic = diagnostic.with_flag(flag);
+            }
+            diagnostic.emit();
+            // If this is a failure we know how to explain, say how to fix it.
+            if let Some(hint) = e.hint() {
+                Diagnostic::hint(hint).emit();
+            }
+            if e.kind() == RddErrorKind::Cancelled {
+                ExitCode::from(INTERRUPTED_EXIT_CODE)
+            } else {
+                ExitCode::FAILURE
+            }
         }
     }
 }
@@ -52,20 +75,93 @@ fn run() -> RddResult<()> {
     // `clap` will handle invalid arguments and printing help messages.
     let cli = Cli::parse();
 
+    // Decide once whether errors and warnings are printed in color.
+    diagnostics::init(cli.no_color);
+
     // Match on the subcommand to dispatch to the correct logic.
     // This structure makes it easy to add new commands in the future.
     match cli.command {
         Command::Copy(args) => {
             // 1. Create a validated configuration from the raw arguments.
             //    The `?` operator will propagate any configuration errors.
-            let config = CopyConfig::from_args(args)?;
+            //    The copy shows up in 'rdd top', and with --tui draws its own dashboard.
+            let (tui, yes) = (args.tui, args.yes);
+            let mut config = CopyConfig::from_args(*args)?;
+            // Overwriting a drive is confirmed first, in a terminal (see wizard.rs).
+            if !yes && !config.output_is_stdout() && !config.output_is_network() && !confirm_overwrite(&config.output_file, config.force)? {
+                return Ok(());
+            }
+            config.reporter = job_reporter(&config, tui);
 
-            // 2. Print a confirmation of the configuration for debugging.
-            //    This will be replaced by the actual copy logic.
-            println!("Starting copy from '{}' to '{}' with block size {} bytes.", config.input_file, config.output_file, config.block_size);
+            // SIGUSR1 prints the status, Ctrl-C stops the copy cleanly.
+            signals::install();
 
-            // 3. Call the core copy function. The '?' operator will handle any I/O errors that occur.
-            run_singlethreaded_copy(&config)?;
+            // 2. Call the core copy function. The '?' operator will handle any I/O errors that occur.
+            //    It announces the copy, and with --threads 2 or more, reads and writes run on separate threads.
+            run_copy(&config)?;
+        }
+        Command::Verify(args) => {
+            // Nothing is copied here: both files are read, hashed and compared.
+            let config = VerifyConfig::from_args(args)?;
+            run_verify(&config)?;
+        }
+        Command::Wipe(args) => {
+            // Every pass overwrites the whole target; Ctrl-C stops between blocks.
+            let yes = args.yes;
+            let config = WipeConfig::from_args(args)?;
+            if !yes && !confirm_overwrite(&config.target, config.force)? {
+                return Ok(());
+            }
+            signals::install();
+            run_wipe(&config)?;
+        }
+        Command::Scrub(args) => {
+            // Reads every archived image again; Ctrl-C stops between blocks.
+            let config = ScrubConfig::from_args(args)?;
+            signals::install();
+            run_scrub(&config)?;
+        }
+        Command::List(args) => {
+            // Only looks at the drives; nothing is opened for reading or writing.
+            let config = ListConfig::from_args(args);
+            run_list(&config)?;
+        }
+        Command::Benchmark(args) => {
+            // The write test overwrites the start of the target, so a drive is
+            // confirmed first, like for a copy. Ctrl-C stops between blocks.
+            let yes = args.yes;
+            let config = BenchmarkConfig::from_args(args)?;
+            if !yes && !config.read_only && !confirm_overwrite(&config.target, config.force)? {
+                return Ok(());
+            }
+            signals::install();
+            run_benchmark(&config)?;
+        }
+        Command::Extract(args) => {
+            // Writes to the output like a copy, so a drive is confirmed
+            // first. Ctrl-C stops between reads.
+            let yes = args.yes;
+            let config = ExtractConfig::from_args(args)?;
+            if !yes && config.output != STDIO_PATH && !confirm_overwrite(&config.output, config.force)? {
+                return Ok(());
+            }
+            signals::install();
+            run_extract(&config)?;
+        }
+        Command::Top(args) => {
+            // Ctrl-C leaves the dashboard.
+            let config = TopConfig::from_args(args);
+            signals::install();
+            run_top(&config)?;
+        }
+        Command::Wizard => {
+            // The wizard only asks; the copy it sets up runs like 'rdd copy'.
+            if let Some(args) = run_wizard()? {
+                let mut config = CopyConfig::from_args(*args)?;
+                config.reporter = job_reporter(&config, false);
+                signals::install();
+                run_copy(&config)?;
+            }
         }
     }
     Ok(())
@@ -0,0 +1,117 @@
+// This file renders the messages rdd prints to stderr about problems: errors, warnings and hints. Giving them one consistent look (a colored severity tag, then the message, then optional context) makes them easy to spot in the middle of progress output, and keeps every call site down to a single line.
+
+// Explanation of this file:
+// Severity enum: How serious a message is. Each severity has its own tag ("error:", "warning:", "hint:") and color.
+// Diagnostic struct: One message, plus optional context naming the command-line flag that caused it. Built with Diagnostic::error/warning/hint and with_flag, then printed with emit().
+// Color handling: Colors are only used when stderr is a terminal, and never when the user passes --no-color or sets the NO_COLOR environment variable (see https://no-color.org). The decision is made once in init() and stored in a global, since diagnostics can be emitted from anywhere in the program.
+// ANSI escapes: We write the standard escape codes directly instead of pulling in a color crate; we only need three colors and bold.
+
+// src/utils/diagnostics.rs
+
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether diagnostics are rendered with ANSI colors.
+static COLOR_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Decides whether diagnostics should be colored. Call once at startup.
+///
+/// Colors are enabled only if stderr is a terminal, `no_color` is false and
+/// the `NO_COLOR` environment variable is not set.
+pub fn init(no_color: bool) {
+    let env_disabled = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let enabled = !no_color && !env_disabled && std::io::stderr().is_terminal();
+    COLOR_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// How serious a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Hint,
+}
+
+impl Severity {
+    fn tag(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Hint => "hint",
+        }
+    }
+
+    /// ANSI color code for the tag: red, yellow or cyan.
+    fn color(&self) -> &'static str {
+        match self {
+            Severity::Error => "31",
+            Severity::Warning => "33",
+            Severity::Hint => "36",
+        }
+    }
+}
+
+/// A message for the user, printed to stderr.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// The command-line flag that led to this message, if any.
+    pub flag: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message.into())
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message.into())
+    }
+
+    pub fn hint(message: impl Into<String>) -> Self {
+        Self::new(Severity::Hint, message.into())
+    }
+
+    fn new(severity: Severity, message: String) -> Self {
+        Self {
+            severity,
+            message,
+            flag: None,
+        }
+    }
+
+    /// Names the command-line flag that caused this diagnostic.
+    pub fn with_flag(mut self, flag: impl Into<String>) -> Self {
+        self.flag = Some(flag.into());
+        self
+    }
+
+    /// Renders the diagnostic, with or without colors.
+    pub fn render(&self, color: bool) -> String {
+        let mut out = if color {
+            format!(
+                "\x1b[1;{}m{}:\x1b[0m {}",
+                self.severity.color(),
+                self.severity.tag(),
+                self.message
+            )
+        } else {
+            format!("{}: {}", self.severity.tag(), self.message)
+        };
+
+        if let Some(flag) = &self.flag {
+            if color {
+                out.push_str(&format!("\n  \x1b[1m=\x1b[0m caused by: {}", flag));
+            } else {
+                out.push_str(&format!("\n  = caused by: {}", flag));
+            }
+        }
+        out
+    }
+
+    /// Prints the diagnostic to stderr.
+    pub fn emit(&self) {
+        eprintln!("{}", self.render(COLOR_ENABLED.load(Ordering::Relaxed)));
+    }
+}
@@ -0,0 +1,191 @@
+// This file contains the pieces needed for --direct, which bypasses the OS page cache. With direct I/O the kernel moves data straight between the device and our buffers, so it insists on alignment: the buffer address, the file offset and the transfer size must all be multiples of the device's logical sector size.
+
+// Explanation of this file:
+// AlignedBuffer struct: A heap buffer aligned to BUFFER_ALIGN (4 KiB, the page size on most systems), which satisfies every common sector size. The copy always uses these buffers, with or without --direct, so there's only one code path.
+// enable : Adds the flag for direct I/O to OpenOptions. On Linux, Android and the BSDs that's O_DIRECT.
+// after_open : macOS has no O_DIRECT; instead we switch caching off on the open file with fcntl(F_NOCACHE).
+// relax : Turns direct I/O off again on an open file. The last block of a copy is usually shorter than a sector multiple, and a direct write of it would fail with EINVAL, so it goes through the page cache instead.
+// logical_sector_size : The alignment the file needs. For block devices we ask the kernel (the BLKSSZGET ioctl on Linux). For regular files we use the filesystem's preferred block size, which is always a multiple of the sector size.
+
+// src/utils/direct.rs
+
+use std::alloc::{self, Layout};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+/// Alignment of every I/O buffer: a page, which covers all common sector sizes.
+pub const BUFFER_ALIGN: usize = 4096;
+
+/// A zero-initialized heap buffer whose start is aligned to `BUFFER_ALIGN`.
+pub struct AlignedBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// SAFETY: AlignedBuffer owns its allocation exclusively, just like a Vec<u8>.
+unsafe impl Send for AlignedBuffer {}
+unsafe impl Sync for AlignedBuffer {}
+
+impl AlignedBuffer {
+    pub fn new(len: usize) -> Self {
+        let layout = Self::layout(len);
+        // SAFETY: the layout has a non-zero size.
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        Self { ptr, len }
+    }
+
+    fn layout(len: usize) -> Layout {
+        Layout::from_size_align(len.max(1), BUFFER_ALIGN).expect("buffer size fits in isize")
+    }
+}
+
+impl Deref for AlignedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: ptr points to `len` initialized bytes that we own.
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: as above, and we hold the only reference.
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        // SAFETY: allocated in new() with this same layout.
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), Self::layout(self.len)) }
+    }
+}
+
+/// Asks for direct I/O when the file is opened, where the platform does it
+/// with an open flag.
+pub fn enable(options: &mut OpenOptions) {
+    platform::enable(options);
+}
+
+/// Finishes turning on direct I/O for an open file, where the platform does
+/// it after opening.
+pub fn after_open(file: &File) -> io::Result<()> {
+    platform::after_open(file)
+}
+
+/// Turns direct I/O off again for `file`.
+pub fn relax(file: &File) -> io::Result<()> {
+    platform::relax(file)
+}
+
+/// The alignment direct I/O on `file` needs, in bytes.
+pub fn logical_sector_size(file: &File) -> io::Result<usize> {
+    platform::logical_sector_size(file)
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+))]
+mod platform {
+    use nix::fcntl::{fcntl, FcntlArg, OFlag};
+    use nix::libc;
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+
+    pub fn enable(options: &mut OpenOptions) {
+        options.custom_flags(libc::O_DIRECT);
+    }
+
+    pub fn after_open(_file: &File) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn relax(file: &File) -> io::Result<()> {
+        let flags = OFlag::from_bits_truncate(fcntl(file.as_raw_fd(), FcntlArg::F_GETFL)?);
+        fcntl(file.as_raw_fd(), FcntlArg::F_SETFL(flags - OFlag::O_DIRECT))?;
+        Ok(())
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    nix::ioctl_read_bad!(blksszget, 0x1268, libc::c_int);
+
+    pub fn logical_sector_size(file: &File) -> io::Result<usize> {
+        let metadata = file.metadata()?;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            use std::os::unix::fs::FileTypeExt;
+            if metadata.file_type().is_block_device() {
+                let mut size: libc::c_int = 0;
+                // SAFETY: BLKSSZGET writes a single int.
+                unsafe { blksszget(file.as_raw_fd(), &mut size) }.map_err(io::Error::from)?;
+                return Ok(size as usize);
+            }
+        }
+        Ok(metadata.blksize() as usize)
+    }
+}
+
+#[cfg(target_vendor = "apple")]
+mod platform {
+    use nix::fcntl::{fcntl, FcntlArg};
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::MetadataExt;
+
+    pub fn enable(_options: &mut OpenOptions) {}
+
+    pub fn after_open(file: &File) -> io::Result<()> {
+        fcntl(file.as_raw_fd(), FcntlArg::F_NOCACHE(true))?;
+        Ok(())
+    }
+
+    pub fn relax(file: &File) -> io::Result<()> {
+        fcntl(file.as_raw_fd(), FcntlArg::F_NOCACHE(false))?;
+        Ok(())
+    }
+
+    pub fn logical_sector_size(file: &File) -> io::Result<usize> {
+        Ok(file.metadata()?.blksize() as usize)
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "dragonfly",
+    target_vendor = "apple"
+)))]
+mod platform {
+    use std::fs::{File, OpenOptions};
+    use std::io;
+
+    pub fn enable(_options: &mut OpenOptions) {}
+
+    pub fn after_open(_file: &File) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "direct I/O is not supported on this platform",
+        ))
+    }
+
+    pub fn relax(_file: &File) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn logical_sector_size(_file: &File) -> io::Result<usize> {
+        Ok(super::BUFFER_ALIGN)
+    }
+}
@@ -0,0 +1,54 @@
+// This file wraps posix_fadvise, which lets us give the kernel hints about how we are going to use a file's pages in the page cache.
+
+// Explanation of this file:
+// posix_fadvise is only available on some platforms (Linux, Android, FreeBSD). On every other platform these functions compile to no-ops, so callers never need their own #[cfg] checks.
+// Hints are advisory: If the kernel rejects a hint (e.g., because the file is a pipe), we ignore the error. A failed hint must never fail a copy.
+
+// src/utils/fadvise.rs
+
+use std::fs::File;
+
+/// Advises the kernel that `file` will be read sequentially.
+pub fn advise_sequential(file: &File) {
+    platform::advise_sequential(file);
+}
+
+/// Advises the kernel that the given range of `file` will not be needed
+/// again, so its pages can be dropped from the cache.
+pub fn advise_dont_need(file: &File, offset: u64, len: u64) {
+    platform::advise_dont_need(file, offset, len);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+mod platform {
+    use nix::fcntl::{posix_fadvise, PosixFadviseAdvice};
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+
+    pub fn advise_sequential(file: &File) {
+        let _ = posix_fadvise(
+            file.as_raw_fd(),
+            0,
+            0,
+            PosixFadviseAdvice::POSIX_FADV_SEQUENTIAL,
+        );
+    }
+
+    pub fn advise_dont_need(file: &File, offset: u64, len: u64) {
+        let _ = posix_fadvise(
+            file.as_raw_fd(),
+            offset as nix::libc::off_t,
+            len as nix::libc::off_t,
+            PosixFadviseAdvice::POSIX_FADV_DONTNEED,
+        );
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+mod platform {
+    use std::fs::File;
+
+    pub fn advise_sequential(_file: &File) {}
+
+    pub fn advise_dont_need(_file: &File, _offset: u64, _len: u64) {}
+}
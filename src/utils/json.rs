@@ -0,0 +1,106 @@
+// This file writes JSON, for --stats-format json and the custody log. Scripts that drive rdd want the results of a copy as data rather than as sentences to pick apart. We only ever write JSON, never read it, and only small documents, so a few lines here do the job of a full JSON library.
+
+// Explanation of this file:
+// Json enum: One JSON value. Objects keep their keys in the order they were added, so the output reads in a stable, sensible order.
+// Display : Prints the value as compact JSON on one line, or with `{:#}` pretty-printed with two-space indentation (the same convention as serde_json). Strings are escaped as the JSON spec requires, and numbers that JSON can't represent (NaN, infinity) become null.
+
+// src/utils/json.rs
+
+use std::fmt::{self, Write};
+
+/// A JSON value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(u64),
+    Float(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// An object with `entries`, in order.
+    pub fn object<K: Into<String>>(entries: impl IntoIterator<Item = (K, Json)>) -> Self {
+        Json::Object(entries.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    pub fn str(s: impl Into<String>) -> Self {
+        Json::Str(s.into())
+    }
+
+    /// `value` converted with `f`, or null.
+    pub fn or_null<T>(value: Option<T>, f: impl FnOnce(T) -> Json) -> Self {
+        value.map_or(Json::Null, f)
+    }
+
+    /// Writes the value; `indent` is `None` for compact output.
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: Option<usize>) -> fmt::Result {
+        let inner = indent.map(|i| i + 1);
+        let (open_sep, item_sep, close_sep) = match indent {
+            Some(_) => ("\n", ",\n", "\n"),
+            None => ("", ",", ""),
+        };
+        let pad = |f: &mut fmt::Formatter<'_>, level: Option<usize>| match level {
+            Some(level) => write!(f, "{:1$}", "", level * 2),
+            None => Ok(()),
+        };
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Int(n) => write!(f, "{}", n),
+            Json::Float(x) if x.is_finite() => write!(f, "{}", x),
+            Json::Float(_) => f.write_str("null"),
+            Json::Str(s) => write_string(f, s),
+            Json::Array(items) if items.is_empty() => f.write_str("[]"),
+            Json::Array(items) => {
+                f.write_str("[")?;
+                f.write_str(open_sep)?;
+                for (i, item) in items.iter().enumerate() {
+                    pad(f, inner)?;
+                    item.write(f, inner)?;
+                    f.write_str(if i + 1 < items.len() { item_sep } else { close_sep })?;
+                }
+                pad(f, indent)?;
+                f.write_str("]")
+            }
+            Json::Object(entries) if entries.is_empty() => f.write_str("{}"),
+            Json::Object(entries) => {
+                f.write_str("{")?;
+                f.write_str(open_sep)?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    pad(f, inner)?;
+                    write_string(f, key)?;
+                    f.write_str(if indent.is_some() { ": " } else { ":" })?;
+                    value.write(f, inner)?;
+                    f.write_str(if i + 1 < entries.len() { item_sep } else { close_sep })?;
+                }
+                pad(f, indent)?;
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, f.alternate().then_some(0))
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
@@ -1,2 +1,16 @@
 // src/utils/mod.rs
+pub mod cpu;
+pub mod diagnostics;
+pub mod direct;
+pub mod fadvise;
+pub mod json;
+pub mod output;
+pub mod passphrase;
 pub mod progress;
+pub mod rawdevice;
+pub mod signals;
+#[cfg(feature = "tui")]
+pub mod terminal;
+pub mod threads;
+#[cfg(target_os = "linux")]
+pub mod sysfs;
@@ -0,0 +1,30 @@
+// This file decides where rdd's output goes on a terminal. rdd is often one stage of a pipeline (`rdd copy -i disk.img -o - | gzip`), and a script reading stdout must only ever find what it asked for there: the copied data, the JSON stats or digest lines. Everything written for a person reading the terminal goes to stderr. The core reports through core/reporter.rs; TerminalReporter prints through the functions here, so that rule lives in one place.
+
+// Explanation of this file:
+// message : A line for the person running rdd: the banner, the copy summary, notes like "Write cache restored". Always stderr, like the progress bar and the diagnostics (utils/diagnostics.rs).
+// prompt : A question that's answered on the same line, e.g. by 'rdd wizard'. On stderr, like messages, and flushed, since there's no newline to do it.
+// data : Output meant for scripts, e.g. --stats-format json or the digest lines of `rdd verify`, on stdout. When the copied data itself goes to stdout, the copy engines send their results as a message instead rather than corrupt the data stream.
+
+// src/utils/output.rs
+
+use std::fmt::Display;
+use std::io::{self, Write};
+
+/// Prints a line meant for a person, on stderr.
+pub fn message(line: impl Display) {
+    eprintln!("{}", line);
+}
+
+/// Asks a question on stderr, leaving the cursor after it for the answer.
+pub fn prompt(text: impl Display) -> io::Result<()> {
+    let mut stderr = io::stderr().lock();
+    write!(stderr, "{}", text)?;
+    stderr.flush()
+}
+
+/// Writes `text` for scripts to read, on stdout.
+pub fn data(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(text.as_bytes())?;
+    stdout.flush()
+}
@@ -0,0 +1,289 @@
+// This file is the one place rdd gets passphrases and passwords from, for whatever needs one (today the ssh of ssh:// inputs and outputs; encrypted outputs or signed manifests would ask here too), so every feature asks the same way and none of them prints a secret, keeps it around, or takes it on the command line where `ps` and the shell history would show it.
+
+// Explanation of this file:
+// Sources : A command that needs a passphrase takes it, in this order, from --passphrase-file (the flattened PassphraseArgs, see 'rdd copy'), from the RDD_PASSPHRASE environment variable, or by asking on the terminal. A script sets one of the first two; a person at the keyboard gets the prompt. given() stops before the prompt, for a program like ssh that asks by itself.
+// ssh : ssh doesn't take a password on its command line or stdin, only from the terminal or from its SSH_ASKPASS program (with SSH_ASKPASS_REQUIRE=force, OpenSSH 8.4 and later). askpass() makes rdd itself that program: ssh starts rdd again with RDD_ASKPASS set, and answer_askpass() prints the passphrase, which ssh was given in RDD_PASSPHRASE, for its password or key passphrase question. A yes/no question, about a host key it doesn't know, is answered no, so an unknown host fails the copy instead of being trusted unseen.
+// The file : Only its first line is the passphrase, without the line ending, so a file written by `echo` works. On Unix, a file that other users can read gets a warning, since the passphrase isn't a secret anymore.
+// Prompting : The prompt goes to the terminal itself (/dev/tty on Unix, the console on Windows), not to stdin and stderr, so it works while stdin or stdout carry data. Echo is switched off while the passphrase is typed and always restored, also when reading fails. Without a terminal, the error says how else to give one.
+// Confirming : A passphrase that something new is protected with (e.g. an encrypted output) is asked for twice, since a typo there can't be undone. A passphrase from a file or the environment is taken as it is.
+// Passphrase struct: Holds the secret, prints as "<redacted>" with {:?}, and overwrites its bytes when it's dropped.
+
+// src/utils/passphrase.rs
+
+use crate::core::reporter::Reporter;
+use crate::error::{IoResultExt, RddError, RddResult};
+use crate::utils::diagnostics::Diagnostic;
+use crate::utils::output;
+use clap::Args;
+use std::fmt;
+use std::fs;
+use std::process::Command;
+
+/// The environment variable a passphrase may be given in.
+pub const PASSPHRASE_ENV: &str = "RDD_PASSPHRASE";
+
+/// The environment variable that has rdd answer as ssh's askpass program.
+pub const ASKPASS_ENV: &str = "RDD_ASKPASS";
+
+/// The options of a command that needs a passphrase.
+#[derive(Args, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PassphraseArgs {
+    /// Read the password or passphrase that's needed (e.g. by ssh, for an ssh:// input or output) from the first line of FILE, instead of from the RDD_PASSPHRASE environment variable or the terminal.
+    #[arg(long, value_name = "FILE")]
+    pub passphrase_file: Option<String>,
+}
+
+/// A passphrase, wiped from memory when it's dropped.
+pub struct Passphrase(Vec<u8>);
+
+impl Passphrase {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Passphrase(<redacted>)")
+    }
+}
+
+impl Drop for Passphrase {
+    fn drop(&mut self) {
+        for b in self.0.iter_mut() {
+            // SAFETY: b is a valid, exclusive reference into the vector; the
+            // volatile write keeps the compiler from leaving it out.
+            unsafe { std::ptr::write_volatile(b, 0) };
+        }
+    }
+}
+
+/// Gets the passphrase for `what` (e.g. "'backup.img'") from `args`, the
+/// environment or the terminal, asking twice if `confirm`.
+pub fn read(args: &PassphraseArgs, what: &str, confirm: bool, reporter: &dyn Reporter) -> RddResult<Passphrase> {
+    if let Some(passphrase) = given(args, what, reporter)? {
+        return Ok(passphrase);
+    }
+    let first = prompt(&format!("Enter the passphrase for {}: ", what), what)?;
+    if confirm && prompt("Enter it again: ", what)?.as_bytes() != first.as_bytes() {
+        return Err(RddError::Config("The passphrases don't match.".to_string()));
+    }
+    non_empty(first, what)
+}
+
+/// The passphrase for `what` from `args` or the environment. None if
+/// neither has one, which leaves the asking to the caller.
+pub fn given(args: &PassphraseArgs, what: &str, reporter: &dyn Reporter) -> RddResult<Option<Passphrase>> {
+    let passphrase = match (&args.passphrase_file, std::env::var_os(PASSPHRASE_ENV)) {
+        (Some(path), _) => from_file(path, reporter)?,
+        (None, Some(value)) => Passphrase(value.into_encoded_bytes()),
+        (None, None) => return Ok(None),
+    };
+    non_empty(passphrase, what).map(Some)
+}
+
+/// `passphrase`, unless it's empty.
+fn non_empty(passphrase: Passphrase, what: &str) -> RddResult<Passphrase> {
+    if passphrase.as_bytes().is_empty() {
+        return Err(RddError::Config(format!("The passphrase for {} is empty.", what)));
+    }
+    Ok(passphrase)
+}
+
+/// Has the ssh `command` take its password or key passphrase from
+/// `passphrase`, by running rdd as its askpass program, instead of asking
+/// on the terminal.
+pub fn askpass(command: &mut Command, passphrase: &Passphrase, what: &str) -> RddResult<()> {
+    let program = std::env::current_exe().with_path("rdd")?;
+    // The environment only carries text on every platform.
+    let secret = std::str::from_utf8(passphrase.as_bytes())
+        .map_err(|_| RddError::Config(format!("The password for {} isn't valid UTF-8 text.", what)))?;
+    command
+        .env("SSH_ASKPASS", program)
+        .env("SSH_ASKPASS_REQUIRE", "force")
+        .env(ASKPASS_ENV, "1")
+        .env(PASSPHRASE_ENV, secret);
+    Ok(())
+}
+
+/// If rdd was started as ssh's askpass program (see askpass), answers the
+/// question ssh asked and returns true.
+pub fn answer_askpass() -> bool {
+    if std::env::var_os(ASKPASS_ENV).is_none() {
+        return false;
+    }
+    // ssh passes its question as the only argument.
+    let question = std::env::args().nth(1).unwrap_or_default();
+    let answer = match question.contains("(yes/no") {
+        true => "no".to_string(),
+        false => std::env::var(PASSPHRASE_ENV).unwrap_or_default(),
+    };
+    let _ = output::data(&format!("{}\n", answer));
+    true
+}
+
+/// The first line of the file at `path`.
+fn from_file(path: &str, reporter: &dyn Reporter) -> RddResult<Passphrase> {
+    if platform::readable_by_others(path) {
+        reporter.diagnostic(
+            &Diagnostic::warning(format!("other users can read the passphrase in '{}'.", path))
+                .with_flag("--passphrase-file"),
+        );
+    }
+    let contents = Passphrase(fs::read(path).with_path(path)?);
+    let line = contents.as_bytes().split(|&b| b == b'\n').next().unwrap_or_default();
+    Ok(Passphrase(line.strip_suffix(b"\r").unwrap_or(line).to_vec()))
+}
+
+/// Asks for the passphrase for `what` on the terminal with `text`, without
+/// echoing what's typed.
+fn prompt(text: &str, what: &str) -> RddResult<Passphrase> {
+    let line = platform::read_hidden(text).map_err(|e| {
+        RddError::Config(format!(
+            "The passphrase for {} is needed, but there is no terminal to ask for it on ({}). Give it with --passphrase-file or the {} environment variable.",
+            what, e, PASSPHRASE_ENV
+        ))
+    })?;
+    let line = Passphrase(line);
+    let text = line.as_bytes();
+    let text = text.strip_suffix(b"\n").unwrap_or(text);
+    Ok(Passphrase(text.strip_suffix(b"\r").unwrap_or(text).to_vec()))
+}
+
+#[cfg(unix)]
+mod platform {
+    use nix::libc;
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::MetadataExt;
+
+    pub fn readable_by_others(path: &str) -> bool {
+        std::fs::metadata(path).is_ok_and(|m| m.mode() & 0o044 != 0)
+    }
+
+    /// Turns echo back on when dropped.
+    struct EchoOff<'a> {
+        tty: &'a File,
+        saved: libc::termios,
+    }
+
+    impl Drop for EchoOff<'_> {
+        fn drop(&mut self) {
+            // SAFETY: saved is the state tcgetattr returned for this tty.
+            unsafe { libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSAFLUSH, &self.saved) };
+        }
+    }
+
+    pub fn read_hidden(prompt: &str) -> io::Result<Vec<u8>> {
+        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        (&tty).write_all(prompt.as_bytes())?;
+        // SAFETY: termios is plain old data, filled in by tcgetattr.
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: tty is an open file, and saved a writable termios.
+        if unsafe { libc::tcgetattr(tty.as_raw_fd(), &mut saved) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut hidden = saved;
+        hidden.c_lflag &= !libc::ECHO;
+        hidden.c_lflag |= libc::ECHONL;
+        // SAFETY: as above.
+        if unsafe { libc::tcsetattr(tty.as_raw_fd(), libc::TCSAFLUSH, &hidden) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let _restore = EchoOff { tty: &tty, saved };
+        let mut line = Vec::new();
+        BufReader::new(&tty).read_until(b'\n', &mut line)?;
+        Ok(line)
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::c_void;
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::os::windows::io::AsRawHandle;
+
+    const ENABLE_ECHO_INPUT: u32 = 0x4;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+    }
+
+    pub fn readable_by_others(_path: &str) -> bool {
+        false
+    }
+
+    /// Turns echo back on when dropped.
+    struct EchoOff<'a> {
+        console: &'a File,
+        saved: u32,
+    }
+
+    impl Drop for EchoOff<'_> {
+        fn drop(&mut self) {
+            // SAFETY: the handle belongs to the open console input.
+            unsafe { SetConsoleMode(self.console.as_raw_handle(), self.saved) };
+        }
+    }
+
+    pub fn read_hidden(prompt: &str) -> io::Result<Vec<u8>> {
+        let input = OpenOptions::new().read(true).write(true).open("CONIN$")?;
+        let mut output = OpenOptions::new().write(true).open("CONOUT$")?;
+        output.write_all(prompt.as_bytes())?;
+        let mut saved = 0;
+        // SAFETY: the handle belongs to the open console input, and saved
+        // is a writable u32.
+        if unsafe { GetConsoleMode(input.as_raw_handle(), &mut saved) } == 0
+            || unsafe { SetConsoleMode(input.as_raw_handle(), saved & !ENABLE_ECHO_INPUT) } == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        let restore = EchoOff { console: &input, saved };
+        let mut line = Vec::new();
+        let read = BufReader::new(&input).read_until(b'\n', &mut line);
+        drop(restore);
+        output.write_all(b"\r\n")?;
+        read.map(|_| line)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use std::io;
+
+    pub fn readable_by_others(_path: &str) -> bool {
+        false
+    }
+
+    pub fn read_hidden(_prompt: &str) -> io::Result<Vec<u8>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "this platform has no terminal"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::reporter::TerminalReporter;
+
+    #[test]
+    fn a_passphrase_file_gives_its_first_line() {
+        let path = std::env::temp_dir().join(format!("rdd-passphrase-test-{}", std::process::id()));
+        let path_str = path.to_string_lossy().into_owned();
+        let args = PassphraseArgs {
+            passphrase_file: Some(path_str.clone()),
+        };
+        fs::write(&path, b"s3cret pass\r\nsecond line\n").unwrap();
+        let given_one = given(&args, "'test'", &TerminalReporter).unwrap();
+        fs::write(&path, b"\nsecond line\n").unwrap();
+        let empty = given(&args, "'test'", &TerminalReporter);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(given_one.unwrap().as_bytes(), b"s3cret pass");
+        assert!(matches!(empty, Err(RddError::Config(_))));
+        assert_eq!(format!("{:?}", Passphrase(b"s3cret".to_vec())), "Passphrase(<redacted>)");
+    }
+}
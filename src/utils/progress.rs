@@ -0,0 +1,44 @@
+// This file contains the helpers for drawing progress bars with the indicatif crate. Keeping them here means the core copy logic only has to call `inc` and `set_message`, and never has to know about templates or styles.
+
+// Explanation of this file:
+// new_copy_progress : Creates the progress bar used by the copy operation. If we know the total number of bytes up front (e.g., a regular file), we draw a real bar with an ETA. If we don't (e.g., a block device or a pipe), we fall back to a spinner that still shows bytes and throughput.
+// ProgressBar::hidden() : When the user disables progress, we return a hidden bar. This lets the copy loop call `inc` unconditionally, without sprinkling `if show_progress` checks everywhere.
+// Draw target : indicatif draws to stderr by default, so the bar never mixes with data or summary lines written to stdout.
+
+// src/utils/progress.rs
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Creates the progress bar for a copy operation.
+///
+/// `total_bytes` is the expected number of bytes to copy, if known. When it
+/// is `None`, a spinner is shown instead of a bar.
+pub fn new_copy_progress(total_bytes: Option<u64>, enabled: bool) -> ProgressBar {
+    if !enabled {
+        return ProgressBar::hidden();
+    }
+
+    match total_bytes {
+        Some(total) => {
+            let bar = ProgressBar::new(total);
+            bar.set_style(
+                ProgressStyle::with_template(
+                    "[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({binary_bytes_per_sec}, ETA {eta}) {msg}",
+                )
+                .expect("progress template is valid")
+                .progress_chars("=>-"),
+            );
+            bar
+        }
+        None => {
+            let spinner = ProgressBar::new_spinner();
+            spinner.set_style(
+                ProgressStyle::with_template(
+                    "[{elapsed_precise}] {spinner} {bytes} ({binary_bytes_per_sec}) {msg}",
+                )
+                .expect("progress template is valid"),
+            );
+            spinner
+        }
+    }
+}
@@ -0,0 +1,250 @@
+// This file handles raw devices on Windows: whole disks (\\.\PhysicalDrive1) and volumes (\\.\E:). Windows treats them differently from files in three ways, and without handling them rdd can't image or write a USB stick there. They must be opened with sharing allowed, since the system itself keeps them open. A mounted volume refuses writes to its sectors while its filesystem is mounted, so it has to be locked and dismounted first. And every read and write on them must start at a sector boundary and be a whole number of sectors long.
+
+// Explanation of this file:
+// raw_device : Recognizes the paths of whole disks and volumes (case doesn't matter, and "//./" works as well as "\\.\"). On other platforms these are just odd file names, so it always returns None there.
+// share : Opens the device with FILE_SHARE_READ and FILE_SHARE_WRITE. Without them the open fails with a sharing violation.
+// lock_for_writing : Before writing, every volume on the device is locked (FSCTL_LOCK_VOLUME) and dismounted (FSCTL_DISMOUNT_VOLUME), so Windows neither writes to it behind our back nor serves stale files from it afterwards. For a volume that's the output itself. For a whole disk it's every volume with a drive letter whose extents (IOCTL_VOLUME_GET_VOLUME_DISK_EXTENTS) lie on that disk. Their handles are kept open until rdd exits, since closing one releases its lock. A volume that can't be locked is in use, and the copy stops before writing anything.
+// sector_size : The sector size of the device, from IOCTL_DISK_GET_DRIVE_GEOMETRY. open_files (see copy.rs) uses it to reject block sizes that aren't a multiple of it. Our buffers are aligned to BUFFER_ALIGN anyway (see direct.rs).
+// device_length : The size of the device in bytes, from IOCTL_DISK_GET_LENGTH_INFO. Seeking to the end of a raw device doesn't tell it on Windows, so this is how capacity.rs learns it.
+
+// src/utils/rawdevice.rs
+
+use std::fs::{File, OpenOptions};
+use std::io;
+
+/// A raw device path on Windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawDevice {
+    /// \\.\PhysicalDriveN, a whole disk.
+    Disk(u32),
+    /// \\.\X:, a volume.
+    Volume(char),
+}
+
+/// Returns which raw device `path` names, if it names one and rdd runs on
+/// Windows.
+pub fn raw_device(path: &str) -> Option<RawDevice> {
+    if !cfg!(windows) {
+        return None;
+    }
+    let name = path.strip_prefix(r"\\.\").or_else(|| path.strip_prefix("//./"))?;
+    if let Some(prefix) = name.get(..13).filter(|prefix| prefix.eq_ignore_ascii_case("PhysicalDrive")) {
+        return name[prefix.len()..].parse().ok().map(RawDevice::Disk);
+    }
+    match name.as_bytes() {
+        [letter, b':'] if letter.is_ascii_alphabetic() => Some(RawDevice::Volume(letter.to_ascii_uppercase() as char)),
+        _ => None,
+    }
+}
+
+/// Lets the device be opened while others have it open too.
+pub fn share(options: &mut OpenOptions) {
+    platform::share(options);
+}
+
+/// Locks and dismounts the volumes on `device`, which `output` was opened
+/// on, so it can be written. Returns the drive letters of the volumes.
+pub fn lock_for_writing(output: &File, device: RawDevice) -> io::Result<Vec<char>> {
+    platform::lock_for_writing(output, device)
+}
+
+/// The sector size of the device `file` was opened on, in bytes.
+pub fn sector_size(file: &File) -> io::Result<usize> {
+    platform::sector_size(file)
+}
+
+/// The size of the device `file` was opened on, in bytes.
+pub fn device_length(file: &File) -> io::Result<u64> {
+    platform::device_length(file)
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::RawDevice;
+    use std::ffi::c_void;
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::mem;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr;
+    use std::sync::Mutex;
+
+    const FILE_SHARE_READ: u32 = 0x1;
+    const FILE_SHARE_WRITE: u32 = 0x2;
+    const FSCTL_LOCK_VOLUME: u32 = 0x0009_0018;
+    const FSCTL_DISMOUNT_VOLUME: u32 = 0x0009_0020;
+    const IOCTL_DISK_GET_DRIVE_GEOMETRY: u32 = 0x0007_0000;
+    const IOCTL_DISK_GET_LENGTH_INFO: u32 = 0x0007_405C;
+    const IOCTL_VOLUME_GET_VOLUME_DISK_EXTENTS: u32 = 0x0056_0000;
+
+    /// How many extents of a volume we look at. Volumes spanning more disks
+    /// than this are not on a USB stick.
+    const MAX_EXTENTS: usize = 8;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct DiskGeometry {
+        cylinders: i64,
+        media_type: u32,
+        tracks_per_cylinder: u32,
+        sectors_per_track: u32,
+        bytes_per_sector: u32,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct LengthInformation {
+        length: i64,
+    }
+
+    #[repr(C)]
+    #[derive(Default, Clone, Copy)]
+    struct DiskExtent {
+        disk_number: u32,
+        starting_offset: i64,
+        extent_length: i64,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct VolumeDiskExtents {
+        count: u32,
+        extents: [DiskExtent; MAX_EXTENTS],
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn DeviceIoControl(
+            device: *mut c_void,
+            code: u32,
+            input: *mut c_void,
+            input_size: u32,
+            output: *mut c_void,
+            output_size: u32,
+            returned: *mut u32,
+            overlapped: *mut c_void,
+        ) -> i32;
+        fn GetLogicalDrives() -> u32;
+    }
+
+    /// Volumes locked for a whole disk. Closing a handle releases its lock,
+    /// so they stay open until rdd exits.
+    static LOCKED: Mutex<Vec<File>> = Mutex::new(Vec::new());
+
+    /// Sends `code` to the device, with `output` as the output buffer.
+    fn control<T>(file: &File, code: u32, output: Option<&mut T>) -> io::Result<()> {
+        let (buffer, size) = match output {
+            Some(output) => (output as *mut T as *mut c_void, mem::size_of::<T>() as u32),
+            None => (ptr::null_mut(), 0),
+        };
+        let mut returned = 0;
+        // SAFETY: the handle is open for as long as `file` lives, and the
+        // output buffer, if any, is a live, writable T of `size` bytes.
+        let ok = unsafe {
+            DeviceIoControl(
+                file.as_raw_handle(),
+                code,
+                ptr::null_mut(),
+                0,
+                buffer,
+                size,
+                &mut returned,
+                ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn lock(volume: &File, letter: char) -> io::Result<()> {
+        control::<()>(volume, FSCTL_LOCK_VOLUME, None).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "cannot lock volume {}: ({}); close the programs and Explorer windows that use it",
+                    letter, e
+                ),
+            )
+        })?;
+        control::<()>(volume, FSCTL_DISMOUNT_VOLUME, None)
+    }
+
+    /// Whether the volume lies, at least partly, on disk `number`.
+    fn on_disk(volume: &File, number: u32) -> bool {
+        let mut extents = VolumeDiskExtents::default();
+        control(volume, IOCTL_VOLUME_GET_VOLUME_DISK_EXTENTS, Some(&mut extents)).is_ok()
+            && extents.extents[..(extents.count as usize).min(MAX_EXTENTS)]
+                .iter()
+                .any(|extent| extent.disk_number == number)
+    }
+
+    pub fn share(options: &mut OpenOptions) {
+        options.share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE);
+    }
+
+    pub fn lock_for_writing(output: &File, device: RawDevice) -> io::Result<Vec<char>> {
+        let number = match device {
+            RawDevice::Volume(letter) => {
+                lock(output, letter)?;
+                return Ok(vec![letter]);
+            }
+            RawDevice::Disk(number) => number,
+        };
+
+        // SAFETY: GetLogicalDrives takes no arguments.
+        let drives = unsafe { GetLogicalDrives() };
+        let mut locked = Vec::new();
+        for letter in ('A'..='Z').filter(|&letter| drives & (1 << (letter as u32 - 'A' as u32)) != 0) {
+            let mut options = OpenOptions::new();
+            options.read(true).write(true);
+            share(&mut options);
+            // Drives that can't be opened (an empty card reader, a network
+            // drive) aren't on this disk either.
+            let Ok(volume) = options.open(format!(r"\\.\{}:", letter)) else {
+                continue;
+            };
+            if on_disk(&volume, number) {
+                lock(&volume, letter)?;
+                LOCKED.lock().unwrap_or_else(|e| e.into_inner()).push(volume);
+                locked.push(letter);
+            }
+        }
+        Ok(locked)
+    }
+
+    pub fn sector_size(file: &File) -> io::Result<usize> {
+        let mut geometry = DiskGeometry::default();
+        control(file, IOCTL_DISK_GET_DRIVE_GEOMETRY, Some(&mut geometry))?;
+        Ok(geometry.bytes_per_sector as usize)
+    }
+
+    pub fn device_length(file: &File) -> io::Result<u64> {
+        let mut info = LengthInformation::default();
+        control(file, IOCTL_DISK_GET_LENGTH_INFO, Some(&mut info))?;
+        Ok(info.length as u64)
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use super::RawDevice;
+    use std::fs::{File, OpenOptions};
+    use std::io;
+
+    pub fn share(_options: &mut OpenOptions) {}
+
+    pub fn lock_for_writing(_output: &File, _device: RawDevice) -> io::Result<Vec<char>> {
+        Ok(Vec::new())
+    }
+
+    pub fn sector_size(_file: &File) -> io::Result<usize> {
+        Ok(crate::utils::direct::BUFFER_ALIGN)
+    }
+
+    pub fn device_length(_file: &File) -> io::Result<u64> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
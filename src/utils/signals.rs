@@ -0,0 +1,206 @@
+// This file handles the signals a long copy can receive. dd users are used to `kill -USR1` printing how far a copy has got, and everyone expects Ctrl-C to stop a copy without leaving a mess behind. Signal handlers can't safely do real work, so ours only set a flag; the copy loops look at the flags between chunks (check) and do the work there.
+
+// Explanation of this file:
+// Status : SIGUSR1, and SIGINFO (Ctrl-T) on macOS and the BSDs, report the bytes copied so far, the elapsed time and the throughput, like dd does (on stderr, with the terminal reporter). The copy carries on. On Windows, Ctrl-Break does the same.
+// Stopping : SIGINT (Ctrl-C) and SIGTERM make check() return RddError::Interrupted. The copy loop then syncs what it has written, saves its --resume checkpoint if it has one, and returns the error, which main turns into exit code 130. The handlers are reset after the first signal, so a second Ctrl-C ends rdd at once, for when the output device hangs.
+// Following : A copy that follows a growing input (--follow) takes the stop signal with take_stop instead, and ends there as if the input had ended.
+// install : Only the rdd binary installs the handlers. A library user keeps their own signal handling, and check() never fires for them.
+// SA_RESTART : The handlers are installed with SA_RESTART, so a status request doesn't make the read or write that's in progress fail with EINTR.
+// Waiting : The long waits of a copy (a drive cooling down, the charger, retry back-off, the rate limit) sleep through sleep or sleep_quietly, in slices of SLICE. A stop signal cuts them short, and stays pending, so the copy loop stops at its next check as it would between chunks.
+
+// src/utils/signals.rs
+
+use crate::core::reporter::Reporter;
+use crate::error::{RddError, RddResult};
+use indicatif::{BinaryBytes, ProgressBar};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the waits sleep between looks at the signals.
+const SLICE: Duration = Duration::from_millis(100);
+
+/// Set by the status signal, cleared when the status has been printed.
+static STATUS_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// The signal that asked the copy to stop, or 0.
+static STOP_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// Installs the handlers for the status and stop signals.
+pub fn install() {
+    platform::install();
+}
+
+/// Reports the status if it was asked for, and fails with
+/// RddError::Interrupted once a stop signal has arrived. The copy loops call
+/// this between chunks.
+pub fn check(reporter: &dyn Reporter, progress: &ProgressBar) -> RddResult<()> {
+    if STATUS_REQUESTED.swap(false, Ordering::Relaxed) {
+        report_status(reporter, progress);
+    }
+    match STOP_SIGNAL.load(Ordering::Relaxed) {
+        0 => Ok(()),
+        signal => Err(RddError::Interrupted {
+            signal: platform::signal_name(signal),
+        }),
+    }
+}
+
+/// Whether a stop signal has arrived (and wasn't taken by take_stop).
+pub fn stopping() -> bool {
+    STOP_SIGNAL.load(Ordering::Relaxed) != 0
+}
+
+/// Sleeps for `duration`, reporting the status in between if it's asked
+/// for. Returns false as soon as a stop signal arrives, leaving the signal
+/// for the copy loop's check.
+pub fn sleep(duration: Duration, reporter: &dyn Reporter, progress: &ProgressBar) -> bool {
+    sleep_while(duration, || check(reporter, progress).is_ok())
+}
+
+/// Like sleep, for short waits inside the copy loop, which reports the
+/// status itself once the wait is over.
+pub fn sleep_quietly(duration: Duration) -> bool {
+    sleep_while(duration, || !stopping())
+}
+
+fn sleep_while(duration: Duration, mut carry_on: impl FnMut() -> bool) -> bool {
+    let until = Instant::now() + duration;
+    loop {
+        if !carry_on() {
+            return false;
+        }
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        thread::sleep(left.min(SLICE));
+    }
+}
+
+/// Takes a stop signal that has arrived, so it ends a followed input
+/// (--follow, see core/follow.rs) instead of interrupting the copy. Returns
+/// the signal's name.
+pub fn take_stop() -> Option<String> {
+    match STOP_SIGNAL.swap(0, Ordering::Relaxed) {
+        0 => None,
+        signal => Some(platform::signal_name(signal)),
+    }
+}
+
+/// "123456 bytes (120.56 KiB) copied, 1.2s, 100.47 KiB/s", like dd's status line.
+fn report_status(reporter: &dyn Reporter, progress: &ProgressBar) {
+    let bytes = progress.position();
+    let secs = progress.elapsed().as_secs_f64();
+    let rate = if secs > 0.0 { (bytes as f64 / secs) as u64 } else { bytes };
+    reporter.message_over(
+        progress,
+        &format!("{} bytes ({}) copied, {:.1}s, {}/s", bytes, BinaryBytes(bytes), secs, BinaryBytes(rate)),
+    );
+}
+
+#[cfg(unix)]
+mod platform {
+    use super::{STATUS_REQUESTED, STOP_SIGNAL};
+    use crate::utils::diagnostics::Diagnostic;
+    use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+    use std::sync::atomic::Ordering;
+
+    extern "C" fn on_status(_: nix::libc::c_int) {
+        STATUS_REQUESTED.store(true, Ordering::Relaxed);
+    }
+
+    extern "C" fn on_stop(signal: nix::libc::c_int) {
+        STOP_SIGNAL.store(signal, Ordering::Relaxed);
+    }
+
+    pub fn install() {
+        let status = SigAction::new(SigHandler::Handler(on_status), SaFlags::SA_RESTART, SigSet::empty());
+        let stop = SigAction::new(
+            SigHandler::Handler(on_stop),
+            SaFlags::SA_RESTART | SaFlags::SA_RESETHAND,
+            SigSet::empty(),
+        );
+
+        let handlers = [
+            (Signal::SIGUSR1, &status),
+            (Signal::SIGINT, &stop),
+            (Signal::SIGTERM, &stop),
+        ];
+        #[cfg(any(
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd",
+            target_os = "dragonfly",
+            target_os = "openbsd",
+            target_os = "netbsd"
+        ))]
+        let handlers = handlers.into_iter().chain([(Signal::SIGINFO, &status)]);
+
+        for (signal, action) in handlers {
+            // SAFETY: the handlers only store to atomics, which is
+            // async-signal-safe.
+            if let Err(e) = unsafe { sigaction(signal, action) } {
+                Diagnostic::warning(format!("could not install a handler for {}: {}", signal, e)).emit();
+            }
+        }
+    }
+
+    pub fn signal_name(signal: i32) -> String {
+        Signal::try_from(signal)
+            .map(|s| s.as_str().to_string())
+            .unwrap_or_else(|_| format!("signal {}", signal))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::{STATUS_REQUESTED, STOP_SIGNAL};
+    use crate::utils::diagnostics::Diagnostic;
+    use std::sync::atomic::Ordering;
+
+    const CTRL_C_EVENT: u32 = 0;
+    const CTRL_BREAK_EVENT: u32 = 1;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn SetConsoleCtrlHandler(handler: Option<unsafe extern "system" fn(u32) -> i32>, add: i32) -> i32;
+    }
+
+    /// Returning 0 passes the event on to the default handler, which ends
+    /// the process: that's what a second Ctrl-C does.
+    unsafe extern "system" fn on_ctrl(event: u32) -> i32 {
+        match event {
+            CTRL_BREAK_EVENT => {
+                STATUS_REQUESTED.store(true, Ordering::Relaxed);
+                1
+            }
+            CTRL_C_EVENT if STOP_SIGNAL.load(Ordering::Relaxed) == 0 => {
+                STOP_SIGNAL.store(1, Ordering::Relaxed);
+                1
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn install() {
+        // SAFETY: the handler only touches atomics.
+        if unsafe { SetConsoleCtrlHandler(Some(on_ctrl), 1) } == 0 {
+            Diagnostic::warning("could not install a Ctrl-C handler; Ctrl-C ends rdd without syncing the output.")
+                .emit();
+        }
+    }
+
+    pub fn signal_name(_signal: i32) -> String {
+        "Ctrl-C".to_string()
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    pub fn install() {}
+
+    pub fn signal_name(signal: i32) -> String {
+        format!("signal {}", signal)
+    }
+}
@@ -0,0 +1,31 @@
+// This file finds the sysfs directory of the drive behind a device node. Several features talk to the drive itself rather than to the data on it: the write cache switch (writecache.rs) and the temperature and error counters (health.rs). All of that lives under /sys/block/<disk>, so they all need the same lookup.
+
+// Explanation of this file:
+// whole_disk : Goes from the device number of a block device to /sys/dev/block/MAJOR:MINOR, which links to the device's sysfs directory. For a partition, we step up to the whole disk, since caches, sensors and counters belong to the drive. Returns None for anything that isn't a block device (regular files, pipes), so callers can decide whether that's an error.
+
+// src/utils/sysfs.rs
+
+use nix::libc;
+use std::fs;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+/// Finds the sysfs directory of the whole disk that `path` belongs to, or
+/// `None` if `path` isn't a block device.
+pub fn whole_disk(path: &str) -> io::Result<Option<PathBuf>> {
+    let metadata = fs::metadata(path)?;
+    if !metadata.file_type().is_block_device() {
+        return Ok(None);
+    }
+    let rdev = metadata.rdev();
+    let dir = fs::canonicalize(format!(
+        "/sys/dev/block/{}:{}",
+        libc::major(rdev),
+        libc::minor(rdev)
+    ))?;
+    match dir.join("partition").exists() {
+        true => Ok(dir.parent().map(Path::to_path_buf)),
+        false => Ok(Some(dir)),
+    }
+}
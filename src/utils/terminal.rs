@@ -0,0 +1,54 @@
+// This file holds the terminal the full-screen dashboards draw on (see core/dashboard.rs). The drawing itself is done with ratatui, on its crossterm backend, which takes care of the escape sequences, of the window's size and of Windows consoles. It's only built with the tui cargo feature.
+
+// Explanation of this file:
+// Screen struct: Switches stderr to the alternate screen when created and back when dropped, like 'top' and 'less' do, so the dashboard doesn't scroll the terminal's history away, and whatever was on the screen before comes back afterwards. draw() hands a ratatui frame, as big as the window, to the caller, and puts on the screen only what changed since the last one.
+// Raw mode : Not used. The dashboards read no keys, and in the terminal's normal mode Ctrl-C still stops the copy through the signal handler (see utils/signals.rs), which takes care of leaving the screen on the way out.
+
+// src/utils/terminal.rs
+
+use ratatui::Frame;
+use ratatui::Terminal;
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::crossterm::{cursor, execute};
+use std::io::{self, IsTerminal, Stderr};
+
+/// The alternate screen of the terminal on stderr, for as long as it lives.
+pub struct Screen {
+    terminal: Terminal<CrosstermBackend<Stderr>>,
+}
+
+impl Screen {
+    /// Switches to the alternate screen. Returns None if stderr isn't a
+    /// terminal.
+    pub fn enter() -> Option<Self> {
+        if !io::stderr().is_terminal() {
+            return None;
+        }
+        execute!(io::stderr(), EnterAlternateScreen, cursor::Hide).ok()?;
+        let Ok(terminal) = Terminal::new(CrosstermBackend::new(io::stderr())) else {
+            leave();
+            return None;
+        };
+        // From here on, dropping the Screen leaves the alternate screen.
+        let mut screen = Screen { terminal };
+        screen.terminal.clear().ok()?;
+        Some(screen)
+    }
+
+    /// Replaces what's on the screen with the frame `render` draws.
+    pub fn draw(&mut self, render: impl FnOnce(&mut Frame)) {
+        let _ = self.terminal.draw(render);
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        leave();
+    }
+}
+
+/// Shows the cursor again and goes back to the normal screen.
+fn leave() {
+    let _ = execute!(io::stderr(), cursor::Show, LeaveAlternateScreen);
+}
@@ -0,0 +1,9 @@
+// This file says whether the platform can run threads. rdd uses threads for the parallel copy, the 'verify' command, --open-timeout, the splitter and the joiner, the compressor pipes, and the bandwidth log. WebAssembly without the threads proposal (e.g. wasm32-wasip1, where rdd can run in a sandbox on preopened files) has none: spawning one fails, and std's scoped threads panic.
+
+// Explanation of this file:
+// AVAILABLE : Decided when rdd is compiled. Without threads, build() rejects the options that need them (see config.rs), 'verify' reads the two files in turn instead of side by side (see core/verify.rs), and EventReporter sends no progress events. The streaming copy itself, the hashes and --verify run in a single thread and work everywhere.
+
+// src/utils/threads.rs
+
+/// Whether threads can be spawned on this platform.
+pub const AVAILABLE: bool = !cfg!(all(target_family = "wasm", not(target_feature = "atomics")));
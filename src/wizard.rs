@@ -0,0 +1,261 @@
+// This file implements 'rdd wizard', a guided way to set up a copy for people who'd rather not type a dd command line. dd is known as "disk destroyer" for good reason: one wrong letter in the output and the wrong drive is gone. The wizard asks for the source, the destination, verification and compression one step at a time, picks the destination from the list of attached drives, and shows the exact 'rdd copy' command line before anything happens.
+
+// Explanation of this file:
+// Questions : Every question is a numbered menu or a path, asked on stderr (through utils/output.rs, like every other message) and answered on stdin. An empty answer takes the default shown in brackets, and end of input (Ctrl-D) cancels the wizard.
+// Destination : The attached drives come from the 'list' command (see core/devices.rs). Drives that hold the running system are shown but can't be picked, not even by typing their path. A file path is accepted as well.
+// Compression : Only offered when the destination is a file, in builds with the compress feature; a drive gets the raw data. A compressed output can't be read back for --verify, so the wizard only asks about verification when there's no compression.
+// Review : The answers are turned into the arguments of 'rdd copy', and the command line is shown, quoted for the shell, so it can be run again later or put in a script. Overwriting a drive has to be confirmed by typing "yes".
+// confirm_overwrite : The same "yes" is asked for by 'rdd copy' and 'rdd wipe' when their output is a drive and they run in a terminal, with the drive's model and size, so the wrong drive is noticed before it's gone. --yes skips it, and scripts are never asked.
+// Running it : The wizard parses that same command line with clap and hands the copy arguments back to main, which runs them like any other 'rdd copy'. So the copy is exactly what the command line says, with the same validation.
+
+// src/wizard.rs
+
+use crate::cli::{Cli, Command, CopyArgs};
+use crate::core::devices::{find_device, list_devices, Device};
+use crate::core::safety;
+use crate::error::{RddError, RddResult};
+use crate::utils::output;
+use clap::Parser;
+use indicatif::BinaryBytes;
+use std::io::{self, BufRead, IsTerminal};
+use std::path::Path;
+
+/// A compressed format the wizard offers, with its file extension.
+const COMPRESSIONS: &[(&str, &str)] = &[("zstd", ".zst"), ("gzip", ".gz"), ("xz", ".xz")];
+
+/// The verification algorithms the wizard offers.
+const ALGORITHMS: &[&str] = &[
+    "sha256",
+    #[cfg(feature = "blake3")]
+    "blake3",
+];
+
+/// Asks the questions on stderr and reads the answers from stdin.
+struct Prompter<R> {
+    input: R,
+}
+
+impl<R: BufRead> Prompter<R> {
+    /// Asks `question` and returns the trimmed answer, or `default` for an
+    /// empty one. End of input cancels the wizard.
+    fn ask(&mut self, question: &str, default: Option<&str>) -> RddResult<String> {
+        match default {
+            Some(default) => output::prompt(format!("{} [{}]: ", question, default))?,
+            None => output::prompt(format!("{}: ", question))?,
+        }
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            output::message("");
+            return Err(RddError::Config("Cancelled; nothing was written.".to_string()));
+        }
+        let answer = line.trim();
+        Ok(match (answer, default) {
+            ("", Some(default)) => default.to_string(),
+            _ => answer.to_string(),
+        })
+    }
+
+    /// Offers `choices` as a numbered menu and returns the index of the
+    /// one picked, asking again until the answer is one of them.
+    fn choose(&mut self, question: &str, choices: &[String], default: usize) -> RddResult<usize> {
+        output::message(question);
+        for (i, choice) in choices.iter().enumerate() {
+            output::message(format!("  {}) {}", i + 1, choice));
+        }
+        loop {
+            let answer = self.ask("Choice", Some(&(default + 1).to_string()))?;
+            match answer.parse::<usize>() {
+                Ok(n) if (1..=choices.len()).contains(&n) => return Ok(n - 1),
+                _ => output::message(format!("Please answer with a number from 1 to {}.", choices.len())),
+            }
+        }
+    }
+
+    /// Asks a yes/no question; only `expected` (e.g. "y" or "yes") counts as yes.
+    fn confirm(&mut self, question: &str, expected: &str) -> RddResult<bool> {
+        Ok(self.ask(question, None)?.eq_ignore_ascii_case(expected))
+    }
+}
+
+/// A line describing a drive in the menus.
+fn describe(device: &Device) -> String {
+    let mut line = format!("{}  {}", device.path, BinaryBytes(device.size));
+    if let Some(model) = &device.model {
+        line.push_str(&format!("  {}", model));
+    }
+    let flags = device.flags();
+    if !flags.is_empty() {
+        line.push_str(&format!("  ({})", flags));
+    }
+    line
+}
+
+/// Quotes `arg` for the shell, if it needs it.
+fn shell_quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        return arg.to_string();
+    }
+    if cfg!(windows) {
+        format!("\"{}\"", arg)
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Asks before `path` is overwritten, if it lies on a drive and rdd runs in
+/// a terminal. Returns whether to go ahead. A drive the write guard refuses
+/// isn't asked about; the copy fails with the reason instead.
+pub fn confirm_overwrite(path: &str, force: bool) -> RddResult<bool> {
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        return Ok(true);
+    }
+    let Some(device) = find_device(path) else {
+        return Ok(true);
+    };
+    if !force && safety::danger(path, &device).is_some() {
+        return Ok(true);
+    }
+    output::message(format!("Everything on {} will be overwritten.", safety::describe(&device)));
+    if device.path != path {
+        output::message(format!("('{}' lies on that drive.)", path));
+    }
+    let stdin = io::stdin();
+    let mut prompter = Prompter { input: stdin.lock() };
+    let confirmed = prompter.confirm("Type 'yes' to continue", "yes")?;
+    if !confirmed {
+        output::message("Nothing was written.");
+    }
+    Ok(confirmed)
+}
+
+/// Runs the wizard. Returns the arguments of the copy it set up, or None if
+/// the user decided not to run it.
+pub fn run_wizard() -> RddResult<Option<Box<CopyArgs>>> {
+    let stdin = io::stdin();
+    let mut prompter = Prompter { input: stdin.lock() };
+    // Without a drive list, the wizard still works with paths.
+    let devices = list_devices().unwrap_or_default();
+
+    output::message("This wizard sets up a copy with 'rdd copy'. Nothing is written until you confirm it at the end.\n");
+
+    // --- Source ---
+    let source = loop {
+        if !devices.is_empty() {
+            output::message("Attached drives:");
+            for device in &devices {
+                output::message(format!("  {}", describe(device)));
+            }
+        }
+        let path = prompter.ask("Source: the image file or drive to copy from", None)?;
+        match Path::new(&path).exists() {
+            true => break path,
+            false if path.is_empty() => {}
+            false => output::message(format!("'{}' doesn't exist.", path)),
+        }
+    };
+    output::message("");
+
+    // --- Destination ---
+    let (mut destination, drive) = {
+        let (offered, system): (Vec<&Device>, Vec<&Device>) =
+            devices.iter().filter(|device| device.path != source).partition(|device| !device.system);
+        for device in &system {
+            output::message(format!("Not offered, it holds the running system: {}", describe(device)));
+        }
+        let mut choices: Vec<String> = offered.iter().map(|device| describe(device)).collect();
+        choices.push("A file (e.g. an image to create)".to_string());
+        let picked = prompter.choose("Destination: where should the data go?", &choices, choices.len() - 1)?;
+        match offered.get(picked) {
+            Some(device) => (device.path.clone(), Some(*device)),
+            None => loop {
+                let path = prompter.ask("Destination file", None)?;
+                if let Some(device) = devices.iter().find(|device| device.path == path) {
+                    if device.system {
+                        output::message(format!("'{}' holds the running system; rdd won't overwrite it from here.", path));
+                        continue;
+                    }
+                    break (path, Some(device));
+                }
+                if path == source {
+                    output::message("The destination can't be the source.");
+                } else if !path.is_empty() {
+                    break (path, None);
+                }
+            },
+        }
+    };
+    output::message("");
+
+    // --- Compression ---
+    let mut compression = None;
+    if drive.is_none() && cfg!(feature = "compress") {
+        let mut choices = vec!["No, write the data as it is".to_string()];
+        choices.extend(COMPRESSIONS.iter().map(|(name, extension)| format!("{} ({})", name, extension)));
+        let picked = prompter.choose("Compress the output?", &choices, 0)?;
+        if let Some(&(name, extension)) = picked.checked_sub(1).and_then(|i| COMPRESSIONS.get(i)) {
+            if !destination.ends_with(extension) {
+                destination.push_str(extension);
+                output::message(format!("The output will be '{}'.", destination));
+            }
+            compression = Some(name);
+        }
+        output::message("");
+    }
+
+    // --- Verification ---
+    let mut algorithm = None;
+    if compression.is_none() {
+        let mut choices: Vec<String> = ALGORITHMS
+            .iter()
+            .map(|name| format!("Yes, read the output back and compare its {} hash", name))
+            .collect();
+        choices.push("No".to_string());
+        let picked = prompter.choose("Verify the copy?", &choices, 0)?;
+        algorithm = ALGORITHMS.get(picked).copied();
+        output::message("");
+    }
+
+    // --- Review ---
+    let mut args = vec!["rdd", "copy", "-i", &source, "-o", &destination];
+    if let Some(name) = compression {
+        args.extend(["--compress", name]);
+    }
+    if let Some(name) = algorithm {
+        args.extend(["--verify", name]);
+    }
+    let command_line: Vec<_> = args.iter().map(|arg| shell_quote(arg)).collect();
+    output::message(format!("The copy, as a command line:\n\n    {}\n", command_line.join(" ")));
+
+    let confirmed = match drive {
+        Some(device) => {
+            output::message(format!("Everything on {} will be overwritten.", describe(device)));
+            if !device.mountpoints.is_empty() {
+                output::message(format!(
+                    "It's mounted at {}; unmount it first, or rdd will refuse to write to it.",
+                    device.mountpoints.join(", ")
+                ));
+            }
+            prompter.confirm("Type 'yes' to start the copy", "yes")?
+        }
+        None => {
+            if Path::new(&destination).exists() {
+                output::message(format!("'{}' exists and will be overwritten.", destination));
+            }
+            prompter.confirm("Start the copy? [y/N]", "y")?
+        }
+    };
+    if !confirmed {
+        output::message("Nothing was copied.");
+        return Ok(None);
+    }
+
+    match Cli::try_parse_from(&args) {
+        Ok(Cli {
+            command: Command::Copy(copy),
+            ..
+        }) => Ok(Some(copy)),
+        Ok(_) => unreachable!("the wizard builds a copy command line"),
+        Err(e) => Err(RddError::Config(format!("The wizard built an invalid command line: {}", e))),
+    }
+}